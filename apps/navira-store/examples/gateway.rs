@@ -0,0 +1,250 @@
+//! Minimal HTTP gateway serving a directory of CAR files
+//!
+//! The [gateway handlers](navira_store::gateway) do not embed an HTTP server: this example plugs them into a
+//! bare-bones HTTP/1.1 server over [std::net::TcpListener], one connection at a time. It serves
+//! `GET /ipfs/{cid}?format=raw` (raw blocks) and `GET /ipfs/{cid}?format=car` (DFS-ordered CARs of UnixFS
+//! files, optionally scoped with `entity-bytes=from:to`), or the same with an `Accept` header.
+//!
+//! ```sh
+//! cargo run --features gateway --example gateway -- /srv/cars 127.0.0.1:8080
+//! curl -s 'http://127.0.0.1:8080/ipfs/bafk...?format=raw'
+//! ```
+//!
+//! Its tests double as an end-to-end test of the stack: a file is packed into a CAR file (writer and DAG
+//! builder), indexed by a DataStore, then fetched through the gateway over TCP, the CAR responses being
+//! consumed by a section stream (reader) as an HTTP client would.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+};
+
+use navira_store::{
+    datastore::DataStore,
+    gateway::{GatewayRequest, handle},
+};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(datastore) = args.next().map(PathBuf::from) else {
+        eprintln!("Usage: gateway <datastore directory> [address, default 127.0.0.1:8080]");
+        std::process::exit(2);
+    };
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_owned());
+
+    let mut store = DataStore::new();
+    let indexed = store
+        .scan_directory(&datastore)
+        .and_then(|count| store.index().map(|()| count));
+    match indexed {
+        Ok(count) => eprintln!(
+            "Indexed {} blocks from {} CAR files",
+            store.block_count(),
+            count
+        ),
+        Err(e) => {
+            eprintln!("Error indexing {:?}: {}", datastore, e);
+            std::process::exit(1);
+        }
+    }
+
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Error binding {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Serving on http://{}", address);
+    serve(&mut store, &listener, None);
+}
+
+/// Serve the connections of the listener one at a time, stopping after `max_connections` if given
+fn serve(store: &mut DataStore, listener: &TcpListener, max_connections: Option<usize>) {
+    let connections = listener
+        .incoming()
+        .take(max_connections.unwrap_or(usize::MAX));
+    for stream in connections {
+        if let Err(e) = stream.and_then(|stream| serve_connection(store, stream)) {
+            eprintln!("Connection failed: {}", e);
+        }
+    }
+}
+
+/// Answer a single request, then close the connection
+fn serve_connection(store: &mut DataStore, stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut accept = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("accept")
+        {
+            accept = Some(value.trim().to_owned());
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("/"),
+    );
+    let mut request = GatewayRequest::new(method, target);
+    if let Some(accept) = &accept {
+        request = request.with_accept(accept);
+    }
+    let response = handle(store, &request);
+
+    let mut writer = std::io::BufWriter::new(&stream);
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\n",
+        response.status,
+        reason(response.status)
+    )?;
+    for (name, value) in &response.headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    write!(writer, "Connection: close\r\n\r\n")?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+/// Reason phrase of the status codes returned by the gateway handlers
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        410 => "Gone",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use navira_car::{
+        pack::{RechunkOptions, raw_block_cid, rechunk},
+        stdio::{SectionStreamOptions, spawn_section_stream},
+        verify::{DigestComparison, verify_block},
+        wire::cid::RawCid,
+    };
+    use std::{collections::HashMap, io::Read};
+
+    /// Send a request to the gateway, returning the status, the headers and the unread response
+    fn get(
+        address: std::net::SocketAddr,
+        target: &str,
+    ) -> (u16, Vec<String>, BufReader<TcpStream>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).unwrap();
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            headers.push(line.trim_end().to_owned());
+        }
+        (status, headers, reader)
+    }
+
+    #[test]
+    fn test_gateway_end_to_end() {
+        let dir =
+            std::env::temp_dir().join(format!("navira-gateway-example-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Pack a file into a UnixFS DAG of 1 KiB raw leaves, with at most 4 links per node
+        let content: Vec<u8> = (0..10_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut source = HashMap::new();
+        let source_root = raw_block_cid(&content);
+        source.insert(source_root.clone(), content.clone());
+        let options = RechunkOptions {
+            chunk_size: 1024,
+            max_links: 4,
+        };
+        let mut car = std::io::Cursor::new(Vec::new());
+        let summary = rechunk(&mut source, &source_root, &options, &mut car).unwrap();
+        std::fs::write(dir.join("file.car"), car.into_inner()).unwrap();
+
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        store.index().unwrap();
+        assert_eq!(store.block_count() as u64, summary.sections);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || serve(&mut store, &listener, Some(4)));
+        let root = summary.root.to_cid_string();
+
+        // The root block, verifiable against its CID
+        let (status, _, mut body) = get(address, &format!("/ipfs/{}?format=raw", root));
+        assert_eq!(status, 200);
+        let mut block = Vec::new();
+        body.read_to_end(&mut block).unwrap();
+        verify_block(&summary.root, &block, DigestComparison::Variable).unwrap();
+
+        // The whole file as a DFS CAR, streamed out of the socket: its leaves are the content, in order
+        let (status, headers, body) = get(address, &format!("/ipfs/{}?format=car", root));
+        assert_eq!(status, 200);
+        assert!(headers.iter().any(|h| h.contains("order=dfs")));
+        let mut stream = spawn_section_stream(body, SectionStreamOptions::default()).unwrap();
+        let mut file = Vec::new();
+        let mut sections = 0;
+        while let Some(section) = stream.blocking_recv() {
+            let section = section.unwrap();
+            verify_block(
+                section.cid(),
+                section.block().data(),
+                DigestComparison::Variable,
+            )
+            .unwrap();
+            if section.cid().bytes()[1] == 0x55 {
+                file.extend_from_slice(section.block().data());
+            }
+            sections += 1;
+        }
+        assert_eq!(file, content);
+        assert_eq!(sections, summary.sections);
+        assert_eq!(stream.roots().unwrap()[0].to_raw_cid(), &summary.root);
+
+        // A byte range only needs the blocks on the path to its leaves
+        let target = format!("/ipfs/{}?format=car&entity-bytes=5000:5100", root);
+        let (status, _, body) = get(address, &target);
+        assert_eq!(status, 200);
+        let mut stream = spawn_section_stream(body, SectionStreamOptions::default()).unwrap();
+        let leaves: Vec<RawCid> = std::iter::from_fn(|| stream.blocking_recv())
+            .map(|section| section.unwrap().cid().clone())
+            .filter(|cid| cid.bytes()[1] == 0x55)
+            .collect();
+        assert_eq!(leaves, vec![raw_block_cid(&content[4096..5120])]);
+
+        let (status, _, _) = get(address, "/ipfs/notacid?format=raw");
+        assert_eq!(status, 400);
+        server.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
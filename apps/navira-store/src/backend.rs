@@ -0,0 +1,143 @@
+//! Storage backends of navira-store
+//!
+//! The DataStore indexes and serves CAR archives, but does not assume where they are stored: all its accesses to
+//! the archives go through a [StorageBackend], which enumerates the archives, opens them for (random) reads,
+//! reads the small objects stored along them (e.g. sidecar indexes), and optionally creates new ones.
+//! The index, the caches and the serving layers are unaware of the backend.
+//!
+//! Archives are identified by a path-like key, the canonical path of the file for the [FsBackend] (the default).
+//! Other backends (object storage, raw block devices, embedded key-value stores, …) map these keys to their own
+//! naming, e.g. an object key under a bucket. The keys are also what the tombstones and the reports refer to.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// An archive opened from a [StorageBackend], to be read at arbitrary offsets
+pub trait Archive: Read + Seek + Send {
+    /// Length of the archive, in bytes
+    fn len(&self) -> io::Result<u64>;
+
+    /// Is the archive empty?
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Read up to `buf.len()` bytes at `offset`, returning the number of bytes read (0 at the end of the archive)
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read(buf)
+    }
+
+    /// Read exactly `buf.len()` bytes at `offset`
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buf)
+    }
+
+    /// Read all the bytes from `offset` to the end of the archive
+    fn read_to_end_at(&mut self, offset: u64) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Archive for File {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl<T: AsRef<[u8]> + Send> Archive for io::Cursor<T> {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.get_ref().as_ref().len() as u64)
+    }
+}
+
+/// Where the CAR archives of a DataStore are stored
+///
+/// Only the reads are required: a backend which cannot store new archives keeps the default
+/// [StorageBackend::create_archive], and reports itself as not [writable](StorageBackend::writable).
+pub trait StorageBackend: Send + Sync {
+    /// Enumerate the CAR archives under a location (e.g. a directory, or a prefix)
+    ///
+    /// # Returns
+    /// * `Ok(Vec<PathBuf>)` - Keys of the archives, as resolved by [StorageBackend::resolve]
+    /// * `Err((PathBuf, io::Error))` - The location (or the entry at this path) can not be listed
+    fn list_archives(&self, location: &Path) -> Result<Vec<PathBuf>, (PathBuf, io::Error)>;
+
+    /// Resolve a user-provided path into the key of an archive (e.g. canonicalize it)
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Open an archive for reading
+    fn open(&self, archive: &Path) -> io::Result<Box<dyn Archive>>;
+
+    /// Read a whole object stored along the archives, such as a sidecar index
+    ///
+    /// # Returns
+    /// * `Ok(Some(Vec<u8>))` - The content of the object
+    /// * `Ok(None)` - There is no such object
+    /// * `Err(io::Error)` - The object exists, but can not be read
+    fn read_object(&self, key: &Path) -> io::Result<Option<Vec<u8>>>;
+
+    /// Can new archives be created?
+    fn writable(&self) -> bool {
+        false
+    }
+
+    /// Create a new archive, replacing any archive with the same key
+    ///
+    /// Not supported by default, see [StorageBackend::writable].
+    fn create_archive(&self, archive: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Cannot create {:?}: read-only storage backend", archive),
+        ))
+    }
+}
+
+/// Storage backend of CAR files in local directories (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsBackend;
+
+impl StorageBackend for FsBackend {
+    fn list_archives(&self, location: &Path) -> Result<Vec<PathBuf>, (PathBuf, io::Error)> {
+        let inaccessible = |e| (location.to_path_buf(), e);
+        let mut archives = Vec::new();
+        for entry in std::fs::read_dir(location).map_err(inaccessible)? {
+            let path = entry.map_err(inaccessible)?.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("car") {
+                archives.push(std::fs::canonicalize(&path).map_err(|e| (path, e))?);
+            }
+        }
+        Ok(archives)
+    }
+
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+
+    fn open(&self, archive: &Path) -> io::Result<Box<dyn Archive>> {
+        Ok(Box::new(File::open(archive)?))
+    }
+
+    fn read_object(&self, key: &Path) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(key) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn create_archive(&self, archive: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(io::BufWriter::new(File::create(archive)?)))
+    }
+}
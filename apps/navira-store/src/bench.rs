@@ -0,0 +1,588 @@
+//! Benchmark and regression harness of the DataStore lookups
+//!
+//! The harness builds a synthetic datastore (a directory of CARv1 files filled with raw blocks of random sizes),
+//! then measures the indexing time and the latency of block lookups, both cold (fresh DataStore, no open CAR file
+//! nor cached block) and warm (the same lookups, repeated). The results are emitted as a flat JSON object, so that
+//! they can be archived and compared against a baseline to catch regressions of the index, caches or CAR handles.
+//!
+//! The harness is deterministic for a given seed (same CAR files, same lookup order), but the latencies obviously
+//! depend on the machine and the OS page cache: only compare results from the same host.
+//!
+//! The harness also compares the two ways of indexing a finished CAR file offline: the sequential reader path (one
+//! thread reading, parsing and hashing the sections) and the parallel [Indexer] (see `navira_car::pack`). Both build
+//! the same MultihashIndexSorted index of every synthetic CAR file, with block validation.
+//!
+//! It is only built with the `bench` feature, and driven by the `navira-store-bench` binary.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use navira_car::{
+    CarReader, CarReaderError,
+    pack::{Indexer, raw_block_cid},
+    verify::{DigestComparison, DigestError, verify_block},
+    wire::{
+        cid::RawCid,
+        v1::{Block, CarWriter, Section},
+        v2::Index,
+    },
+};
+
+use crate::{
+    cache::{BlockCache, CacheKeying},
+    datastore::{DataStore, DataStoreError},
+};
+
+/// Distribution of the synthetic block sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeDistribution {
+    /// Every block has the same size
+    Fixed(usize),
+    /// Block sizes are uniformly distributed in `min..=max`
+    Uniform { min: usize, max: usize },
+    /// Block sizes are log-uniformly distributed in `min..=max` (many small blocks, a few large ones)
+    LogUniform { min: usize, max: usize },
+}
+
+impl SizeDistribution {
+    /// Draw a block size
+    fn sample(&self, rng: &mut SplitMix64) -> usize {
+        match *self {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => {
+                min + (rng.next() % (max - min + 1) as u64) as usize
+            }
+            SizeDistribution::LogUniform { min, max } => {
+                let (low, high) = ((min.max(1) as f64).ln(), (max.max(1) as f64).ln());
+                let unit = (rng.next() >> 11) as f64 / (1u64 << 53) as f64;
+                ((low + unit * (high - low)).exp() as usize).clamp(min, max)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SizeDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SizeDistribution::Fixed(size) => write!(f, "fixed:{}", size),
+            SizeDistribution::Uniform { min, max } => write!(f, "uniform:{}-{}", min, max),
+            SizeDistribution::LogUniform { min, max } => write!(f, "log-uniform:{}-{}", min, max),
+        }
+    }
+}
+
+impl FromStr for SizeDistribution {
+    type Err = String;
+
+    /// Parse a distribution: `fixed:<size>`, `uniform:<min>-<max>` or `log-uniform:<min>-<max>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid size distribution {:?} (expected fixed:<size>, uniform:<min>-<max> or log-uniform:<min>-<max>)",
+                s
+            )
+        };
+        let size = |s: &str| s.parse::<usize>().map_err(|_| invalid());
+        let range = |s: &str| {
+            let (min, max) = s.split_once('-').ok_or_else(invalid)?;
+            let (min, max) = (size(min)?, size(max)?);
+            if min > max {
+                return Err(invalid());
+            }
+            Ok((min, max))
+        };
+        match s.split_once(':').ok_or_else(invalid)? {
+            ("fixed", value) => Ok(SizeDistribution::Fixed(size(value)?)),
+            ("uniform", value) => {
+                range(value).map(|(min, max)| SizeDistribution::Uniform { min, max })
+            }
+            ("log-uniform", value) => {
+                range(value).map(|(min, max)| SizeDistribution::LogUniform { min, max })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Configuration of a benchmark run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchConfig {
+    /// Number of CAR files of the synthetic datastore
+    pub car_files: usize,
+    /// Number of blocks of each CAR file
+    pub blocks_per_car: usize,
+    /// Distribution of the block sizes
+    pub sizes: SizeDistribution,
+    /// Number of block lookups of each (cold and warm) pass
+    pub lookups: usize,
+    /// Maximum number of CAR files kept open by the DataStore
+    pub max_open_cars: usize,
+    /// Size of the block cache (disabled if None)
+    pub block_cache_size: Option<usize>,
+    /// Seed of the block contents and of the lookup order
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            car_files: 16,
+            blocks_per_car: 1024,
+            sizes: SizeDistribution::LogUniform {
+                min: 64,
+                max: 256 * 1024,
+            },
+            lookups: 10_000,
+            max_open_cars: 16,
+            block_cache_size: None,
+            seed: 0x6e61_7669_7261,
+        }
+    }
+}
+
+/// Latency statistics of a lookup pass, in microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LatencySummary {
+    /// Number of lookups
+    pub lookups: usize,
+    /// Fastest lookup
+    pub min_us: f64,
+    /// Mean lookup latency
+    pub mean_us: f64,
+    /// Median lookup latency
+    pub p50_us: f64,
+    /// 90th percentile of the lookup latency
+    pub p90_us: f64,
+    /// 99th percentile of the lookup latency
+    pub p99_us: f64,
+    /// Slowest lookup
+    pub max_us: f64,
+}
+
+impl LatencySummary {
+    /// Summarize the latencies of a pass
+    fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let us = |d: Duration| d.as_secs_f64() * 1e6;
+        let percentile = |p: usize| us(samples[(samples.len() - 1) * p / 100]);
+        Self {
+            lookups: samples.len(),
+            min_us: us(samples[0]),
+            mean_us: samples.iter().copied().map(us).sum::<f64>() / samples.len() as f64,
+            p50_us: percentile(50),
+            p90_us: percentile(90),
+            p99_us: percentile(99),
+            max_us: us(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// Results of a benchmark run
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// Configuration of the run
+    pub config: BenchConfig,
+    /// Number of blocks of the synthetic datastore
+    pub blocks: usize,
+    /// Total size of the block data of the synthetic datastore
+    pub block_bytes: u64,
+    /// Time to scan and index the synthetic datastore
+    pub indexing: Duration,
+    /// Time to build the index of every CAR file with the sequential reader
+    pub sequential_indexer: Duration,
+    /// Time to build the index of every CAR file with the parallel [Indexer]
+    pub parallel_indexer: Duration,
+    /// Latencies of the first lookups on a freshly indexed DataStore
+    pub cold: LatencySummary,
+    /// Latencies of the same lookups, repeated
+    pub warm: LatencySummary,
+}
+
+/// JSON keys of the metrics compared against a baseline (lower is better)
+const COMPARED_METRICS: [&str; 7] = [
+    "indexing_ms",
+    "cold_p50_us",
+    "cold_p90_us",
+    "cold_p99_us",
+    "warm_p50_us",
+    "warm_p90_us",
+    "warm_p99_us",
+];
+
+/// A metric which regressed against the baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    /// JSON key of the metric
+    pub metric: &'static str,
+    /// Value of the baseline
+    pub baseline: f64,
+    /// Value of the current run
+    pub current: f64,
+}
+
+impl BenchReport {
+    /// Value of a compared metric
+    fn metric(&self, key: &str) -> f64 {
+        match key {
+            "indexing_ms" => self.indexing.as_secs_f64() * 1e3,
+            "cold_p50_us" => self.cold.p50_us,
+            "cold_p90_us" => self.cold.p90_us,
+            "cold_p99_us" => self.cold.p99_us,
+            "warm_p50_us" => self.warm.p50_us,
+            "warm_p90_us" => self.warm.p90_us,
+            "warm_p99_us" => self.warm.p99_us,
+            _ => unreachable!("unknown metric {}", key),
+        }
+    }
+
+    /// Render the report as a flat JSON object
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        let config = &self.config;
+        let _ = write!(
+            json,
+            "\"car_files\":{},\"blocks_per_car\":{},\"sizes\":\"{}\",\"lookups\":{},\"max_open_cars\":{},\"block_cache_size\":{},\"seed\":{},",
+            config.car_files,
+            config.blocks_per_car,
+            config.sizes,
+            config.lookups,
+            config.max_open_cars,
+            config.block_cache_size.unwrap_or(0),
+            config.seed,
+        );
+        let _ = write!(
+            json,
+            "\"blocks\":{},\"block_bytes\":{},\"indexing_ms\":{:.3},\"sequential_indexer_ms\":{:.3},\"parallel_indexer_ms\":{:.3}",
+            self.blocks,
+            self.block_bytes,
+            self.indexing.as_secs_f64() * 1e3,
+            self.sequential_indexer.as_secs_f64() * 1e3,
+            self.parallel_indexer.as_secs_f64() * 1e3,
+        );
+        for (pass, summary) in [("cold", &self.cold), ("warm", &self.warm)] {
+            let _ = write!(
+                json,
+                ",\"{pass}_lookups\":{},\"{pass}_min_us\":{:.3},\"{pass}_mean_us\":{:.3},\"{pass}_p50_us\":{:.3},\"{pass}_p90_us\":{:.3},\"{pass}_p99_us\":{:.3},\"{pass}_max_us\":{:.3}",
+                summary.lookups,
+                summary.min_us,
+                summary.mean_us,
+                summary.p50_us,
+                summary.p90_us,
+                summary.p99_us,
+                summary.max_us,
+            );
+        }
+        json.push('}');
+        json
+    }
+
+    /// Compare the report against a baseline report (as rendered by [BenchReport::to_json])
+    ///
+    /// # Arguments
+    /// * `baseline` - The JSON baseline
+    /// * `tolerance` - The relative slowdown tolerated (e.g. 0.1 for 10%)
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Regression>)` - The metrics slower than the baseline by more than the tolerance
+    /// * `Err(String)` - A compared metric is missing from the baseline
+    pub fn regressions(&self, baseline: &str, tolerance: f64) -> Result<Vec<Regression>, String> {
+        let mut regressions = Vec::new();
+        for metric in COMPARED_METRICS {
+            let baseline = json_number(baseline, metric)
+                .ok_or_else(|| format!("metric {:?} missing from the baseline", metric))?;
+            let current = self.metric(metric);
+            if current > baseline * (1.0 + tolerance) {
+                regressions.push(Regression {
+                    metric,
+                    baseline,
+                    current,
+                });
+            }
+        }
+        Ok(regressions)
+    }
+}
+
+/// Extract a number from a flat JSON object, as rendered by [BenchReport::to_json]
+fn json_number(json: &str, key: &str) -> Option<f64> {
+    let pattern = format!("\"{}\":", key);
+    let start = json.find(&pattern)? + pattern.len();
+    let value = json[start..].split([',', '}']).next()?;
+    value.trim().parse().ok()
+}
+
+/// A synthetic datastore
+#[derive(Debug, Clone)]
+pub struct SyntheticStore {
+    /// Directory of the CAR files
+    pub dir: PathBuf,
+    /// CIDs of the blocks, in writing order
+    pub cids: Vec<RawCid>,
+    /// Total size of the block data
+    pub block_bytes: u64,
+}
+
+/// Build a synthetic datastore in the given directory (created if missing)
+///
+/// Each CAR file holds `blocks_per_car` raw blocks filled with pseudo-random bytes, its first block being its root.
+pub fn build_synthetic_store<P: AsRef<Path>>(
+    dir: P,
+    config: &BenchConfig,
+) -> std::io::Result<SyntheticStore> {
+    let dir = dir.as_ref().to_path_buf();
+    std::fs::create_dir_all(&dir)?;
+    let mut rng = SplitMix64(config.seed);
+    let mut store = SyntheticStore {
+        dir: dir.clone(),
+        cids: Vec::with_capacity(config.car_files * config.blocks_per_car),
+        block_bytes: 0,
+    };
+    let mut buf = vec![0u8; 64 * 1024];
+    for car in 0..config.car_files {
+        let blocks: Vec<Vec<u8>> = (0..config.blocks_per_car)
+            .map(|_| {
+                let mut data = vec![0u8; config.sizes.sample(&mut rng)];
+                rng.fill(&mut data);
+                data
+            })
+            .collect();
+        let cids: Vec<RawCid> = blocks.iter().map(|data| raw_block_cid(data)).collect();
+        let roots = cids.first().cloned().into_iter().collect();
+        let largest = blocks.iter().map(Vec::len).max().unwrap_or_default();
+        let mut writer = CarWriter::with_buffer_size(roots, (largest + 1024).max(1024 * 1024));
+        let mut sink = BufWriter::new(File::create(dir.join(format!("synthetic-{:04}.car", car)))?);
+        for (cid, data) in cids.iter().zip(blocks) {
+            store.block_bytes += data.len() as u64;
+            let section = Section::new(cid.clone(), Block::new(data));
+            while writer.write_section(&section).is_err() {
+                drain_writer(&mut writer, &mut sink, &mut buf)?;
+            }
+        }
+        drain_writer(&mut writer, &mut sink, &mut buf)?;
+        sink.flush()?;
+        store.cids.extend(cids);
+    }
+    Ok(store)
+}
+
+/// Write the pending bytes of a CarWriter to the sink
+fn drain_writer<W: Write>(
+    writer: &mut CarWriter,
+    sink: &mut W,
+    buf: &mut [u8],
+) -> std::io::Result<()> {
+    while writer.has_data_to_send() {
+        let len = writer.send_data(buf);
+        sink.write_all(&buf[..len])?;
+    }
+    Ok(())
+}
+
+/// Run the benchmark against a synthetic datastore
+///
+/// # Returns
+/// * `Ok(BenchReport)` - The benchmark results
+/// * `Err(DataStoreError)` - Error occurred while indexing or looking up the blocks
+pub fn run(store: &SyntheticStore, config: &BenchConfig) -> Result<BenchReport, DataStoreError> {
+    let (sequential_indexer, parallel_indexer) = compare_indexers(store)?;
+
+    let start = Instant::now();
+    let mut datastore = DataStore::with_limits(config.max_open_cars);
+    datastore.scan_directory(&store.dir)?;
+    datastore.index()?;
+    let indexing = start.elapsed();
+    datastore.set_block_cache(
+        config
+            .block_cache_size
+            .map(|size| BlockCache::shared(size, CacheKeying::PerStore)),
+    );
+
+    let mut rng = SplitMix64(config.seed ^ 0x6c6f_6f6b_7570);
+    let lookups: Vec<&RawCid> = (0..config.lookups)
+        .filter_map(|_| {
+            store
+                .cids
+                .get((rng.next() % store.cids.len().max(1) as u64) as usize)
+        })
+        .collect();
+    let mut pass = || -> Result<LatencySummary, DataStoreError> {
+        let mut samples = Vec::with_capacity(lookups.len());
+        for cid in &lookups {
+            let start = Instant::now();
+            std::hint::black_box(datastore.get_block(cid)?);
+            samples.push(start.elapsed());
+        }
+        Ok(LatencySummary::from_samples(samples))
+    };
+    let cold = pass()?;
+    let warm = pass()?;
+
+    Ok(BenchReport {
+        config: config.clone(),
+        blocks: store.cids.len(),
+        block_bytes: store.block_bytes,
+        indexing,
+        sequential_indexer,
+        parallel_indexer,
+        cold,
+        warm,
+    })
+}
+
+/// Index every CAR file of the synthetic datastore with the sequential reader, then with the parallel [Indexer]
+///
+/// Returns the time taken by each indexer.
+fn compare_indexers(store: &SyntheticStore) -> Result<(Duration, Duration), DataStoreError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&store.dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "car"))
+        .collect();
+    paths.sort();
+    let invalid =
+        |e: String| DataStoreError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+
+    let start = Instant::now();
+    let sequential: Vec<Index> = paths
+        .iter()
+        .map(|path| index_sequentially(path).map_err(|e| invalid(e.to_string())))
+        .collect::<Result<_, _>>()?;
+    let sequential_time = start.elapsed();
+
+    let start = Instant::now();
+    let indexer = Indexer::default();
+    let parallel: Vec<Index> = paths
+        .iter()
+        .map(|path| {
+            let file = File::open(path)?;
+            let summary = indexer.index(file).map_err(|e| invalid(e.to_string()))?;
+            Ok::<_, DataStoreError>(summary.index)
+        })
+        .collect::<Result<_, _>>()?;
+    let parallel_time = start.elapsed();
+
+    if sequential != parallel {
+        return Err(invalid("the indexers built different indexes".into()));
+    }
+    Ok((sequential_time, parallel_time))
+}
+
+/// Build the index of a CARv1 file with the sequential reader, validating its blocks
+fn index_sequentially(path: &Path) -> Result<Index, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let mut reader = CarReader::with_min_read_hint(1024 * 1024);
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut entries = Vec::new();
+    loop {
+        let result = if reader.has_header() {
+            reader.read_section().map(|section| {
+                entries.push((section.cid().clone(), section.location.offset));
+                match verify_block(
+                    section.cid(),
+                    section.block().data(),
+                    DigestComparison::Variable,
+                ) {
+                    Ok(()) | Err(DigestError::UnsupportedHash(_)) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            })
+        } else {
+            reader.read_header().map(Ok)
+        };
+        match result {
+            Ok(verified) => verified?,
+            Err(CarReaderError::InsufficientData(offset, hint)) => {
+                if buf.len() < hint {
+                    buf.resize(hint, 0);
+                }
+                std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(offset as u64))?;
+                let n = std::io::Read::read(&mut file, &mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                reader.receive_data(&buf[..n], offset);
+            }
+            Err(CarReaderError::EndOfSections) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Index::multihash_sorted(
+        entries.iter().map(|(cid, offset)| (cid, *offset)),
+    ))
+}
+
+/// SplitMix64 pseudo-random generator, good enough for synthetic data and reproducible across platforms
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_small_run() {
+        let dir = std::env::temp_dir().join(format!("navira-store-bench-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = BenchConfig {
+            car_files: 3,
+            blocks_per_car: 20,
+            sizes: "uniform:1-4096".parse().unwrap(),
+            lookups: 50,
+            max_open_cars: 2,
+            block_cache_size: Some(64 * 1024),
+            ..BenchConfig::default()
+        };
+        let store = build_synthetic_store(&dir, &config).unwrap();
+        assert_eq!(store.cids.len(), 60);
+        let report = run(&store, &config).unwrap();
+        assert_eq!((report.cold.lookups, report.warm.lookups), (50, 50));
+        assert!(report.parallel_indexer > Duration::ZERO);
+
+        let json = report.to_json();
+        assert!(json.contains("\"sizes\":\"uniform:1-4096\""));
+        assert_eq!(json_number(&json, "blocks"), Some(60.0));
+        // Rendered values are rounded, so the report does not exactly match itself
+        assert!(report.regressions(&json, 0.5).unwrap().is_empty());
+        let regressions = report
+            .regressions(&json.replace("\"warm_p50_us\":", "\"warm_p50_us\":-"), 0.1)
+            .unwrap();
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].metric, "warm_p50_us");
+        assert!(report.regressions("{}", 0.1).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_size_distribution_parsing() {
+        for s in ["fixed:1024", "uniform:10-20", "log-uniform:64-262144"] {
+            assert_eq!(s.parse::<SizeDistribution>().unwrap().to_string(), s);
+        }
+        assert!("uniform:20-10".parse::<SizeDistribution>().is_err());
+        assert!("normal:10".parse::<SizeDistribution>().is_err());
+    }
+}
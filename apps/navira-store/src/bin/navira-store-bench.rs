@@ -0,0 +1,115 @@
+use clap::Parser;
+use navira_store::bench::{BenchConfig, SizeDistribution, build_synthetic_store, run};
+use std::path::PathBuf;
+
+/// `navira-store-bench` measures the DataStore indexing and lookup latencies on a synthetic datastore
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Directory where the synthetic CAR files are written
+    /// Default: a temporary directory, removed after the run
+    #[arg(long, value_name = "PATH")]
+    dir: Option<PathBuf>,
+
+    /// Number of CAR files
+    #[arg(long, default_value_t = 16)]
+    car_files: usize,
+
+    /// Number of blocks per CAR file
+    #[arg(long, default_value_t = 1024)]
+    blocks_per_car: usize,
+
+    /// Distribution of the block sizes: fixed:<size>, uniform:<min>-<max> or log-uniform:<min>-<max>
+    #[arg(long, default_value = "log-uniform:64-262144")]
+    sizes: SizeDistribution,
+
+    /// Number of block lookups of each (cold and warm) pass
+    #[arg(long, default_value_t = 10_000)]
+    lookups: usize,
+
+    /// Maximum number of CAR files kept open by the DataStore
+    #[arg(long, default_value_t = 16)]
+    max_open_cars: usize,
+
+    /// Size (in bytes) of the block cache
+    /// If not provided, the block cache is disabled
+    #[arg(long, value_name = "BYTES")]
+    block_cache_size: Option<usize>,
+
+    /// Seed of the block contents and of the lookup order
+    #[arg(long, default_value_t = BenchConfig::default().seed)]
+    seed: u64,
+
+    /// Previous results (JSON) to compare against, the process fails if a metric regressed
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<PathBuf>,
+
+    /// Relative slowdown tolerated against the baseline
+    #[arg(long, default_value_t = 0.1)]
+    tolerance: f64,
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = BenchConfig {
+        car_files: args.car_files,
+        blocks_per_car: args.blocks_per_car,
+        sizes: args.sizes,
+        lookups: args.lookups,
+        max_open_cars: args.max_open_cars,
+        block_cache_size: args.block_cache_size,
+        seed: args.seed,
+    };
+    let (dir, temporary) = match args.dir {
+        Some(dir) => (dir, false),
+        None => (
+            std::env::temp_dir().join(format!("navira-store-bench-{}", std::process::id())),
+            true,
+        ),
+    };
+
+    let store = match build_synthetic_store(&dir, &config) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!(
+                "Error building the synthetic datastore in {:?}: {:?}",
+                dir, e
+            );
+            std::process::exit(1);
+        }
+    };
+    let result = run(&store, &config);
+    if temporary {
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error running the benchmark: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("{}", report.to_json());
+
+    if let Some(path) = args.baseline {
+        let regressions = std::fs::read_to_string(&path)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|baseline| report.regressions(&baseline, args.tolerance));
+        match regressions {
+            Ok(regressions) if regressions.is_empty() => {}
+            Ok(regressions) => {
+                for regression in regressions {
+                    eprintln!(
+                        "Regression of {}: {:.3} (baseline {:.3})",
+                        regression.metric, regression.current, regression.baseline
+                    );
+                }
+                std::process::exit(2);
+            }
+            Err(e) => {
+                eprintln!("Error comparing against the baseline {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
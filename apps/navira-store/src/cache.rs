@@ -0,0 +1,282 @@
+//! Block cache of navira-store
+//!
+//! Popular blocks are kept in memory, so that repeated lookups do not hit the disk. The cache is bounded by the
+//! total size of the cached blocks, and evicts the least recently used blocks first.
+//!
+//! A [BlockCache] is concurrency-safe and meant to be shared (behind an [Arc](std::sync::Arc)) between several
+//! DataStore instances of the same process, e.g. in a multi-tenant server, so that popular content is only held once.
+//! Depending on the [CacheKeying], blocks are either kept apart per store, or shared between the stores by digest:
+//! - [CacheKeying::PerStore] keys the blocks by (store, digest): no block is ever shared, the stores only share the
+//!   memory budget.
+//! - [CacheKeying::Digest] keys the blocks by digest only. As the DataStore does not re-hash the blocks, a block cached
+//!   by a store is only served to another store after a provenance check: the other store must index a block with the
+//!   same multihash and size itself. A store never serves a block it does not hold.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// Identifier of a DataStore instance, unique in the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StoreId(pub(crate) u64);
+
+/// Keying strategy of a [BlockCache]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheKeying {
+    /// Blocks are keyed by (store, digest), so each store only hits its own blocks
+    #[default]
+    PerStore,
+    /// Blocks are keyed by digest, so the stores hit the blocks cached by the others (after a provenance check)
+    Digest,
+}
+
+/// Key of a cached block
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    /// Store owning the block (None when keyed by digest)
+    store: Option<StoreId>,
+    /// Multihash code of the block CID
+    multihash_code: u64,
+    /// Raw multihash digest of the block CID
+    digest: Vec<u8>,
+}
+
+/// A cached block
+#[derive(Debug)]
+struct CacheEntry {
+    data: Arc<[u8]>,
+    /// Store which inserted the block
+    provenance: StoreId,
+    /// Last use of the block, key in the recency queue
+    tick: u64,
+}
+
+/// Counters of a [BlockCache]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Number of lookups served from the cache
+    pub hits: u64,
+    /// Number of lookups not served from the cache
+    pub misses: u64,
+    /// Number of hits served from a block cached by another store
+    pub shared_hits: u64,
+    /// Number of blocks evicted to make room for new ones
+    pub evictions: u64,
+    /// Number of cached blocks
+    pub blocks: usize,
+    /// Total size of the cached blocks
+    pub bytes: usize,
+}
+
+/// Mutable state of the cache, behind its lock
+#[derive(Debug, Default)]
+struct CacheInner {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Keys by last use, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+    next_tick: u64,
+    stats: CacheStats,
+}
+
+impl CacheInner {
+    /// Mark an entry as just used
+    fn touch(&mut self, key: &CacheKey) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.tick);
+            entry.tick = tick;
+            self.recency.insert(tick, key.clone());
+        }
+    }
+
+    /// Remove an entry
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.tick);
+            self.stats.blocks -= 1;
+            self.stats.bytes -= entry.data.len();
+        }
+    }
+}
+
+/// Size-bounded LRU block cache, shareable between DataStore instances
+#[derive(Debug)]
+pub struct BlockCache {
+    /// Maximum total size of the cached blocks
+    capacity: usize,
+    /// Keying strategy
+    keying: CacheKeying,
+    inner: Mutex<CacheInner>,
+}
+
+impl BlockCache {
+    /// Create a new cache holding at most `capacity` bytes of blocks
+    ///
+    /// Blocks larger than the capacity are never cached.
+    pub fn new(capacity: usize, keying: CacheKeying) -> Self {
+        Self {
+            capacity,
+            keying,
+            inner: Mutex::new(CacheInner::default()),
+        }
+    }
+
+    /// Create a new cache, ready to be shared between DataStore instances
+    pub fn shared(capacity: usize, keying: CacheKeying) -> Arc<Self> {
+        Arc::new(Self::new(capacity, keying))
+    }
+
+    /// Maximum total size of the cached blocks
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Keying strategy of the cache
+    pub fn keying(&self) -> CacheKeying {
+        self.keying
+    }
+
+    /// Snapshot of the cache counters
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Remove every block from the cache
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.entries.clear();
+        inner.recency.clear();
+        inner.stats.blocks = 0;
+        inner.stats.bytes = 0;
+    }
+
+    /// Lock the cache state
+    ///
+    /// The state is consistent after every operation, so a lock poisoned by a panicking thread is still usable.
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Build the key of a block for the given store
+    fn key(&self, store: StoreId, multihash_code: u64, digest: &[u8]) -> CacheKey {
+        CacheKey {
+            store: (self.keying == CacheKeying::PerStore).then_some(store),
+            multihash_code,
+            digest: digest.to_vec(),
+        }
+    }
+
+    /// Get a block from the cache
+    ///
+    /// # Arguments
+    /// * `store` - The store looking up the block
+    /// * `multihash_code` - The multihash code of the block CID
+    /// * `digest` - The multihash digest of the block CID
+    /// * `size` - The size of the block, as indexed by the store (provenance check of the blocks cached by other stores)
+    ///
+    /// # Returns
+    /// * `Some(data)` - The cached block
+    /// * `None` - The block is not cached (or fails the provenance check)
+    pub(crate) fn get(
+        &self,
+        store: StoreId,
+        multihash_code: u64,
+        digest: &[u8],
+        size: usize,
+    ) -> Option<Arc<[u8]>> {
+        let key = self.key(store, multihash_code, digest);
+        let mut inner = self.lock();
+        let hit = inner
+            .entries
+            .get(&key)
+            .filter(|entry| entry.provenance == store || entry.data.len() == size)
+            .map(|entry| (entry.data.clone(), entry.provenance != store));
+        match hit {
+            Some((data, shared)) => {
+                inner.touch(&key);
+                inner.stats.hits += 1;
+                if shared {
+                    inner.stats.shared_hits += 1;
+                }
+                Some(data)
+            }
+            None => {
+                inner.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Insert a block in the cache, evicting the least recently used blocks to make room for it
+    pub(crate) fn insert(&self, store: StoreId, multihash_code: u64, digest: &[u8], data: &[u8]) {
+        if data.len() > self.capacity {
+            return;
+        }
+        let key = self.key(store, multihash_code, digest);
+        let mut inner = self.lock();
+        inner.remove(&key);
+        while inner.stats.bytes + data.len() > self.capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.stats.blocks -= 1;
+                inner.stats.bytes -= entry.data.len();
+                inner.stats.evictions += 1;
+            }
+        }
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.recency.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            CacheEntry {
+                data: data.into(),
+                provenance: store,
+                tick,
+            },
+        );
+        inner.stats.blocks += 1;
+        inner.stats.bytes += data.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_cache_lru() {
+        let cache = BlockCache::new(10, CacheKeying::PerStore);
+        let store = StoreId(1);
+        cache.insert(store, 0x12, b"a", b"aaaa");
+        cache.insert(store, 0x12, b"b", b"bbbb");
+        // Refresh "a", so that "b" is the least recently used
+        assert!(cache.get(store, 0x12, b"a", 4).is_some());
+        cache.insert(store, 0x12, b"c", b"cccc");
+        assert!(cache.get(store, 0x12, b"b", 4).is_none());
+        assert_eq!(&*cache.get(store, 0x12, b"a", 4).unwrap(), b"aaaa");
+        // Too large to be cached
+        cache.insert(store, 0x12, b"d", &[0; 11]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 1, 1));
+        assert_eq!((stats.blocks, stats.bytes), (2, 8));
+
+        // Per-store keying never shares the blocks
+        assert!(cache.get(StoreId(2), 0x12, b"a", 4).is_none());
+    }
+
+    #[test]
+    fn test_block_cache_shared_by_digest() {
+        let cache = BlockCache::new(1024, CacheKeying::Digest);
+        cache.insert(StoreId(1), 0x12, b"a", b"aaaa");
+        // Provenance check: the other store must index a block of the same size
+        assert!(cache.get(StoreId(2), 0x12, b"a", 5).is_none());
+        assert!(cache.get(StoreId(2), 0x13, b"a", 4).is_none());
+        assert!(cache.get(StoreId(2), 0x12, b"a", 4).is_some());
+        assert_eq!(cache.stats().shared_hits, 1);
+    }
+}
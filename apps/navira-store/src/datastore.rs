@@ -10,7 +10,8 @@
 //! file index in memory for fast lookup.
 //!
 //! Additional caches are also implemented (as LRU caches) to speed up repeated access to the same blocks or CAR files.
-//! Therefore a small number of frequently accessed blocks is kept in memory to avoid repeated disk access. Moreover, recently
+//! Therefore a small number of frequently accessed blocks is kept in memory to avoid repeated disk access, in a
+//! [block cache](crate::cache) which can be shared between DataStore instances. Moreover, recently
 //! accessed CAR files are kept open, and their index is cached in memory to avoid re-reading it from disk.
 //!
 //! The main type provided by this module is `DataStore` which exposes methods to lookup blocks by CID and retrieve their data.
@@ -18,13 +19,39 @@
 //! TODO: Example usage of DataStore
 
 use std::{
-    fs::File,
-    io::{Read, Seek},
+    collections::{HashMap, HashSet},
+    io::{Seek, Write},
     path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use navira_car::{CarReader, CarReaderError};
-use tracing::debug;
+use navira_car::{
+    CarReader, CarReaderError, Limits,
+    inspect::{SectionExporter, SectionRecord},
+    ipld::block_links,
+    verify::{
+        DigestComparison, DigestError, IndexCoverage, IndexedVerifier, VerifyReport, verify_block,
+        verify_full_index,
+    },
+    wire::{
+        cid::{CidParsing, RawCid},
+        v1::{self, Block, Section, SectionLocation},
+        v2::{self, CarV2Header, CarWriteV2, Index},
+        varint::UnsignedVarint,
+    },
+};
+use tracing::{debug, debug_span, info, trace, warn};
+
+use crate::backend::{Archive, FsBackend, StorageBackend};
+use crate::cache::{BlockCache, StoreId};
+use crate::discovery::{ContentDiscovery, DiscoveryError};
+use crate::metrics::{LookupOutcome, Metrics};
+use crate::policy::{AdmissionPolicy, AdmitAll, ServingPolicy};
+use crate::tombstone::Tombstones;
 
 pub type Result<T> = std::result::Result<T, DataStoreError>;
 /// Errors related to DataStore operations
@@ -36,16 +63,67 @@ pub enum DataStoreError {
     /// CID not found in the datastore
     #[error("CID not found: {0}")]
     NotFound(String),
+    /// CID is indexed but not servable according to the serving policy
+    #[error("CID not servable: {0}")]
+    Denied(String),
+    /// CID (or its CAR file) is tombstoned
+    #[error("CID tombstoned: {0}")]
+    Tombstoned(String),
+    /// DAG request turned down by the admission policy
+    #[error("DAG request not admitted: {0}")]
+    NotAdmitted(String),
+    /// Request past the maximum batch size
+    #[error("Batch limit of {0} blocks exceeded")]
+    BatchLimit(usize),
+    /// A directory or CAR file can not be accessed (missing, not readable, …)
+    #[error("Cannot access {0:?}: {1} ({hint})", hint = access_hint(.1.kind()))]
+    Inaccessible(PathBuf, std::io::Error),
 }
 
+/// Default maximum number of blocks requested at once with [DataStore::get_blocks]
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 1024;
+
+/// Identifier of the next DataStore instance
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
+
 /// DataStore for navira-store
 pub struct DataStore {
-    // Tracked CAR files
+    // Storage backend of the CAR files
+    backend: Box<dyn StorageBackend>,
+    // Tracked CAR files, by backend key
     tracked_car: Vec<PathBuf>,
     // CAR file handles
     car_handles: Vec<CarHandle>,
+    // Block index: CID to location of the block
+    index: HashMap<RawCid, BlockLocation>,
+    // Multihash digest to indexed CID, to resolve the CIDs which only differ by their codec
+    digests: HashMap<Vec<u8>, RawCid>,
+    // Roots of the indexed CAR files, without duplicates
+    roots: Vec<RawCid>,
+    // Same roots, as a set for the root lookups
+    root_set: HashSet<RawCid>,
+    // Serving policy, consulted before serving any block
+    policy: ServingPolicy,
+    // Admission policy, consulted before attempting any DAG request
+    admission: Box<dyn AdmissionPolicy>,
+    // Tombstoned CIDs and CAR files, consulted before any lookup
+    tombstones: Tombstones,
+    // Block lookup metrics
+    metrics: Metrics,
+    // Latency above which a block lookup is logged as slow (disabled if None)
+    slow_query_threshold: Option<Duration>,
+    // Identifier of the store, keying its blocks in a shared block cache
+    id: StoreId,
+    // Block cache, possibly shared with other DataStore instances (disabled if None)
+    block_cache: Option<Arc<BlockCache>>,
+
+    // Maximum number of blocks requested at once with get_blocks
+    max_batch_size: usize,
+    // CAR files which failed to be indexed, skipped by the index
+    quarantine: Vec<QuarantinedCar>,
+    // Whether the store is forbidden to write anything to the disk
+    read_only: bool,
 
-    // TODO: Block caches
     // TODO: CAR index caches
     max_open_cars: usize,
 }
@@ -59,14 +137,60 @@ impl DataStore {
     /// Create a DataStore with custom limits
     pub fn with_limits(max_open_cars: usize) -> Self {
         Self {
+            backend: Box::new(FsBackend),
             tracked_car: Vec::new(),
             car_handles: Vec::new(),
+            index: HashMap::new(),
+            digests: HashMap::new(),
+            roots: Vec::new(),
+            root_set: HashSet::new(),
+            policy: ServingPolicy::AllowAll,
+            admission: Box::new(AdmitAll),
+            tombstones: Tombstones::new(),
+            metrics: Metrics::new(),
+            slow_query_threshold: None,
+            id: StoreId(NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed)),
+            block_cache: None,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            quarantine: Vec::new(),
+            read_only: false,
             max_open_cars,
         }
     }
 
+    /// Is the DataStore in read-only mode?
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Set the read-only mode of the DataStore
+    ///
+    /// In read-only mode, the DataStore never writes to the disk: the CAR files are only read (as always), and the
+    /// tombstones loaded afterwards (see [DataStore::load_tombstones]) are kept in memory only, their file being
+    /// neither created nor updated. This suits datastores on read-only mounts or owned by another user.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Get the storage backend of the CAR files
+    pub fn backend(&self) -> &dyn StorageBackend {
+        &*self.backend
+    }
+
+    /// Set the storage backend of the CAR files (the local filesystem by default, see [FsBackend])
+    ///
+    /// It must be set before tracking any CAR file, as the tracked files are keys of the backend.
+    /// The open CAR files are closed.
+    pub fn set_backend(&mut self, backend: Box<dyn StorageBackend>) {
+        self.car_handles.clear();
+        self.backend = backend;
+    }
+
     /// Scan a directory for CAR files and track them
     ///
+    /// The directory is listed by the storage backend (see [StorageBackend::list_archives]), so it may also
+    /// be a location of another kind, such as a prefix of an object storage.
+    ///
     /// # Arguments
     ///
     /// * `dir` - Path to the directory to scan
@@ -74,18 +198,14 @@ impl DataStore {
     /// # Returns
     ///
     /// * `Ok(usize)` - Number of CAR files found and tracked
-    /// * `Err(DataStoreError)` - Error occurred during scanning
+    /// * `Err(DataStoreError::Inaccessible)` - The directory (or one of its entries) can not be read
+    /// * `Err(DataStoreError)` - Other error occurred during scanning
     pub fn scan_directory<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize> {
         // Scan the directory for .car files
-        let mut discovered = Vec::new();
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("car") {
-                let abs_path = std::fs::canonicalize(&path)?;
-                discovered.push(abs_path);
-            }
-        }
+        let discovered = self
+            .backend
+            .list_archives(dir.as_ref())
+            .map_err(|(path, e)| DataStoreError::Inaccessible(path, e))?;
 
         // Insert the discovered CAR files into tracked_car if not already present
         let mut count = 0;
@@ -101,117 +221,1078 @@ impl DataStore {
 
     /// Preforms the block indexing of the tracked CAR files
     ///
+    /// Failures are isolated per CAR file: a file which can not be opened, read or parsed is skipped, and
+    /// recorded in the quarantine (see [DataStore::report]) along with the offset and the kind of the error,
+    /// while the other files are still indexed. Files which can not be opened are reported with a hint on how
+    /// to fix their access (e.g. their permissions). The quarantine is rebuilt on each call, so a repaired file
+    /// leaves it once the store is re-indexed.
+    ///
     /// # Returns
-    /// * `Ok(())` - Indexing completed successfully
+    /// * `Ok(())` - Indexing completed, possibly with quarantined files
     /// * `Err(DataStoreError)` - Error occurred during indexing
     pub fn index(&mut self) -> Result<()> {
-        let cnt = self.tracked_car.len();
-        for idx in 0..cnt {
-            let path = self.tracked_car[idx].clone();
-            let handle = self.open_car(idx)?;
-            let mut reader = CarReader::new();
-            let mut buf = [0u8; 16 * 1024];
+        self.quarantine.clear();
+        for idx in 0..self.tracked_car.len() {
+            debug!(
+                "Indexing CAR file {} at path {:?}",
+                idx, self.tracked_car[idx]
+            );
+            match self.scan_car(idx) {
+                Ok((entries, roots)) => {
+                    let count = self.insert_entries(entries, roots);
+                    debug!("Indexed {} sections of CAR file {}", count, idx);
+                }
+                Err((offset, error)) => {
+                    let path = self.tracked_car[idx].clone();
+                    warn!(
+                        "Quarantined CAR file {:?} (error at offset {}): {}",
+                        path, offset, error
+                    );
+                    self.quarantine.push(QuarantinedCar {
+                        path,
+                        offset,
+                        kind: error.kind(),
+                        message: error.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read all the sections of a tracked CAR file, without indexing them
+    ///
+    /// # Returns
+    /// * `Ok((entries, roots))` - Sections and roots of the CAR file
+    /// * `Err((u64, std::io::Error))` - Offset in the file at which the error occurred, and the error
+    fn scan_car(&mut self, idx: usize) -> std::result::Result<ScannedCar, (u64, std::io::Error)> {
+        let path = self.tracked_car[idx].clone();
+        let handle = self.open_car(idx).map_err(|e| {
+            let e = into_io_error(e);
+            let kind = e.kind();
+            (
+                0,
+                std::io::Error::new(kind, format!("{} ({})", e, access_hint(kind))),
+            )
+        })?;
+        let file_len = handle.file.len().map_err(|e| (0, e))?;
+        let mut reader = CarReader::new();
+        // CIDs are opaque keys here, so blocks with CIDs of future versions can still be served
+        reader.set_cid_parsing(CidParsing::Lenient);
+        let mut entries = Vec::new();
+        let mut buf = [0u8; 16 * 1024];
+        let invalid = |offset: usize, context: &str, e: CarReaderError| {
+            (
+                offset as u64,
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Error parsing {}: {:?}", context, e),
+                ),
+            )
+        };
+        // Feed the reader with the data it requested, returns the number of bytes read (0 at the end of the file)
+        let mut feed = |reader: &mut CarReader, file: &mut dyn Archive, offset: usize| {
+            let n = file
+                .read_at(offset as u64, &mut buf)
+                .map_err(|e| (offset as u64, e))?;
+            reader.receive_data(&buf[..n], offset);
+            Ok::<_, (u64, std::io::Error)>(n)
+        };
 
-            debug!("Indexing CAR file {} at path {:?}", idx, path);
+        // Read the CAR header
+        loop {
+            match reader.read_header() {
+                Ok(()) => break,
+                Err(CarReaderError::InsufficientData(offset, _)) => {
+                    // We need more data to parse the header, continue reading
+                    if feed(&mut reader, &mut *handle.file, offset)? == 0 {
+                        return Err((
+                            offset as u64,
+                            std::io::Error::new(
+                                std::io::ErrorKind::UnexpectedEof,
+                                "Unexpected end of file while reading the CAR header",
+                            ),
+                        ));
+                    }
+                }
+                Err(e) => return Err(invalid(0, "CAR header", e)),
+            }
+        }
 
-            // Read the CAR header
-            loop {
-                // Attempt to parse the CAR header
-                match reader.read_header() {
-                    Ok(()) => {
-                        // Header parsed successfully, we can stop reading and move to the next CAR file
+        let Some((v1_header, v2_header)) = reader.header() else {
+            return Err(invalid(0, "CAR header", CarReaderError::PreconditionNotMet));
+        };
+        debug!("CAR file {} has root CIDs: {:?}", idx, v1_header.roots());
+        let roots: Vec<RawCid> = v1_header
+            .roots()
+            .iter()
+            .map(|root| root.to_raw_cid().clone())
+            .collect();
+        let v2_header = v2_header.cloned();
+
+        // Read all the CAR blocks to build the index
+        loop {
+            match reader.seek_first_section() {
+                Ok(()) => break,
+                Err(CarReaderError::InsufficientData(offset, _)) => {
+                    if feed(&mut reader, &mut *handle.file, offset)? == 0 {
                         break;
                     }
-                    Err(CarReaderError::InsufficientData(offset, size)) => {
-                        // We need more data to parse the header, continue reading
-                        let pos = handle.file.seek(std::io::SeekFrom::Start(offset as u64))?;
-                        let n = handle.file.read(&mut buf)?;
-                        if n == 0 {
-                            panic!(
-                                "Unexpected end of file while reading CAR header for file {}",
-                                idx
-                            );
+                }
+                Err(e) => return Err(invalid(0, "CAR blocks", e)),
+            }
+        }
+        debug!("Seeked to first section of CAR file {}", idx);
+
+        // End of the last parsed section, to tell a truncated file from a complete one
+        let mut sections_end = None;
+        loop {
+            match reader.read_section() {
+                Ok(section) => {
+                    debug!(
+                        "Parsed block with {:?} in CAR file {} (start:{}, length:{})",
+                        section.cid(),
+                        idx,
+                        section.location.offset,
+                        section.location.length
+                    );
+                    sections_end = Some(section.location.offset + section.location.length);
+                    entries.push((
+                        section.cid().clone(),
+                        BlockLocation {
+                            car: idx,
+                            location: section.location.clone(),
+                        },
+                    ));
+                }
+                Err(CarReaderError::InsufficientData(offset, size)) => {
+                    debug!(
+                        "Need more data to parse block in CAR file {}, offset: {}, size: {}",
+                        idx, offset, size
+                    );
+                    if feed(&mut reader, &mut *handle.file, offset)? == 0 {
+                        // We reached the end of the file, which must also be the end of the last section
+                        match sections_end {
+                            Some(end) if end < file_len => {
+                                return Err((
+                                    end,
+                                    std::io::Error::new(
+                                        std::io::ErrorKind::UnexpectedEof,
+                                        "CAR file truncated in the middle of a section",
+                                    ),
+                                ));
+                            }
+                            _ => break,
                         }
-                        reader.receive_data(&buf[..n], pos as usize);
                     }
-                    Err(e) => {
-                        // An error occurred while parsing the header, return it
+                }
+                Err(CarReaderError::EndOfSections) => {
+                    debug!("Reached end of sections for CAR file {}", idx);
+                    break;
+                }
+                Err(e) => {
+                    let offset = sections_end.unwrap_or_default() as usize;
+                    return Err(invalid(offset, "CAR block", e));
+                }
+            }
+        }
+
+        debug!("Finished indexing CAR file {}", idx);
+        if let Some(v2_header) = v2_header.filter(|h| h.characteristics.has_full_index()) {
+            check_full_index(&mut *handle.file, &path, &v2_header, &entries)
+                .map_err(|e| (v2_header.index_offset, into_io_error(e)))?;
+        }
+        Ok((entries, roots))
+    }
+
+    /// Files which failed to be indexed during the last [DataStore::index]
+    pub fn quarantine(&self) -> &[QuarantinedCar] {
+        &self.quarantine
+    }
+
+    /// Report of the state of the DataStore, for operators
+    pub fn report(&self) -> StoreReport {
+        StoreReport {
+            tracked_cars: self.tracked_car.len(),
+            blocks: self.index.len(),
+            roots: self.roots.len(),
+            quarantine: self.quarantine.clone(),
+        }
+    }
+
+    /// Verify every block of the tracked CAR files against its CID
+    ///
+    /// CARv2 files with an index are verified with exact reads planned from their index (see
+    /// [IndexedVerifier]): the payload is read sequentially, one section (or a few unindexed ones) at a time
+    /// in a single pre-sized buffer, and the index entries are checked along. The other files are verified
+    /// section by section, following the hints of the reader.
+    ///
+    /// Failures are isolated per CAR file, like with [DataStore::index].
+    pub fn verify(&mut self) -> Vec<CarVerification> {
+        (0..self.tracked_car.len())
+            .map(|idx| {
+                let path = self.tracked_car[idx].clone();
+                let (exact, result) = match self.verify_car(idx) {
+                    Ok((exact, report)) => (exact, Ok(report)),
+                    Err((exact, e)) => (exact, Err(e.to_string())),
+                };
+                match &result {
+                    Ok(report) => debug!(
+                        "Verified {} sections of CAR file {:?} ({} not hashed)",
+                        report.sections, path, report.unhashed
+                    ),
+                    Err(e) => warn!("Verification of CAR file {:?} failed: {}", path, e),
+                }
+                CarVerification {
+                    path,
+                    exact,
+                    result,
+                }
+            })
+            .collect()
+    }
+
+    /// Verify a tracked CAR file, with exact reads if it has an index
+    ///
+    /// The returned flag tells whether the exact reads were used, even on error.
+    fn verify_car(
+        &mut self,
+        idx: usize,
+    ) -> std::result::Result<(bool, VerifyReport), (bool, Box<dyn std::error::Error>)> {
+        let file = &mut *self.open_car(idx).map_err(|e| (false, e.into()))?.file;
+        let mut reader = read_car_header(file).map_err(|e| (false, e))?;
+        let mut buf = vec![0u8; 16 * 1024];
+
+        if let (_, Some(header)) = reader.header().unwrap()
+            && header.index_offset != 0
+        {
+            let header = header.clone();
+            return verify_car_exact(file, &header)
+                .map(|report| (true, report))
+                .map_err(|e| (true, e));
+        }
+
+        let mut report = VerifyReport::default();
+        loop {
+            match reader.read_section() {
+                Ok(section) => {
+                    match verify_block(
+                        section.cid(),
+                        section.block().data(),
+                        DigestComparison::Variable,
+                    ) {
+                        Ok(()) => report.sections += 1,
+                        Err(DigestError::UnsupportedHash(_)) => report.unhashed += 1,
+                        Err(e) => return Err((false, e.into())),
+                    }
+                    report.bytes += section.location.length;
+                }
+                Err(CarReaderError::InsufficientData(offset, _)) => {
+                    let fed = feed_reader(&mut reader, file, offset, &mut buf);
+                    if fed.map_err(|e| (false, e.into()))? == 0 {
+                        return Ok((false, report));
+                    }
+                }
+                Err(CarReaderError::EndOfSections) => return Ok((false, report)),
+                Err(e) => return Err((false, e.into())),
+            }
+        }
+    }
+
+    /// Adopt a new CAR file into the served set, without scanning all its blocks when possible
+    ///
+    /// The locations of the sections are taken, in order of preference, from:
+    /// 1. the given manifest, returned by the writer of the file (see [DataStore::ingest_manifest]);
+    /// 2. the embedded index of a CARv2 file, if the file claims to be fully indexed;
+    /// 3. a sidecar index next to the file (`<file>.idx`, an encoded CARv2 index with offsets relative to the
+    ///    CARv1 payload, covering every non-identity section);
+    /// 4. a full scan of the file, as with [DataStore::index].
+    ///
+    /// With an index (2 and 3), only the section headers (length and CID) are read, at the indexed offsets.
+    /// A random sample of the sections is then read and checked against its CID, and the file is only adopted
+    /// if the whole sample is valid. The entries are merged into the index at once, so the blocks of the file
+    /// are either all servable or none of them.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the CAR file, which must not be tracked yet
+    /// * `manifest` - Manifest returned by the writer of the file, if any
+    ///
+    /// # Returns
+    /// * `Ok(AdoptedCar)` - The file is tracked, and its blocks are servable
+    /// * `Err(DataStoreError)` - The file is already tracked, could not be read, or failed the validation
+    pub fn adopt_car<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        manifest: Option<&v1::WriteManifest>,
+    ) -> Result<AdoptedCar> {
+        let path = self.backend.resolve(path.as_ref())?;
+        if self.tracked_car.contains(&path) {
+            return Err(DataStoreError::Io(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("CAR file {:?} is already tracked", path),
+            )));
+        }
+        let car = self.tracked_car.len();
+        let mut file = self.backend.open(&path)?;
+
+        let located = match manifest {
+            Some(manifest) => {
+                let roots = manifest
+                    .header
+                    .roots()
+                    .iter()
+                    .map(|root| root.to_raw_cid().clone())
+                    .collect();
+                Some((AdoptionSource::Manifest, manifest.sections.clone(), roots))
+            }
+            None => locate_from_index(&mut *file, &path, &*self.backend)?,
+        };
+        let (source, sections, roots) = match located {
+            Some(located) => located,
+            None => {
+                self.tracked_car.push(path.clone());
+                match self.scan_car(car) {
+                    Ok((entries, roots)) => {
+                        let sections = entries
+                            .into_iter()
+                            .map(|(cid, loc)| (cid, loc.location))
+                            .collect();
+                        (AdoptionSource::Scan, sections, roots)
+                    }
+                    Err((offset, e)) => {
+                        self.tracked_car.pop();
+                        self.car_handles.retain(|handle| handle.idx != car);
                         return Err(DataStoreError::Io(std::io::Error::new(
-                            std::io::ErrorKind::InvalidData,
-                            format!("Error parsing CAR header: {:?}", e),
+                            e.kind(),
+                            format!("Error at offset {}: {}", offset, e),
                         )));
                     }
                 }
             }
+        };
 
-            let (v1_header, v2_header): (
-                &navira_car::wire::v1::CarHeader,
-                Option<&navira_car::wire::v2::CarV2Header>,
-            ) = reader.header().unwrap();
-            debug!("CAR file {} has root CIDs: {:?}", idx, v1_header.roots());
+        let sampled = validate_sample(&mut *file, &sections)?;
+        if self.tracked_car.len() == car {
+            self.tracked_car.push(path);
+        }
+        let entries = sections
+            .into_iter()
+            .map(|(cid, location)| (cid, BlockLocation { car, location }))
+            .collect();
+        let blocks = self.insert_entries(entries, roots);
+        info!(
+            "Adopted CAR file {:?} ({:?}): {} blocks, {} sampled",
+            self.tracked_car[car], source, blocks, sampled
+        );
+        Ok(AdoptedCar {
+            source,
+            blocks,
+            sampled,
+        })
+    }
 
-            // Read all the CAR blocks to build the index
-            match reader.seek_first_section() {
-                Ok(()) => debug!("Seeked to first section of CAR file {}", idx),
-                Err(CarReaderError::InsufficientData(offset, size)) => {
-                    // We need more data to parse the blocks, continue reading
-                    handle.file.seek(std::io::SeekFrom::Start(offset as u64))?;
-                    continue;
-                }
-                Err(e) => {
-                    // An error occurred while parsing the blocks, return it
-                    return Err(DataStoreError::Io(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        format!("Error parsing CAR blocks: {:?}", e),
-                    )));
-                }
+    /// Index a CAR file which has just been written, from the manifest returned by its writer
+    ///
+    /// The CAR file is tracked, and its sections are added to the index without scanning the file.
+    /// The manifest must describe the file as it is on disk (see `finish_with_manifest` on the navira-car writers).
+    ///
+    /// # Arguments
+    /// * `path` - Path to the written CAR file
+    /// * `manifest` - Manifest returned by the writer of the file
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of indexed sections (identity CIDs excluded)
+    /// * `Err(DataStoreError)` - The CAR file could not be found
+    pub fn ingest_manifest<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        manifest: &v1::WriteManifest,
+    ) -> Result<usize> {
+        let path = self.backend.resolve(path.as_ref())?;
+        let car = match self.tracked_car.iter().position(|tracked| tracked == &path) {
+            Some(car) => car,
+            None => {
+                self.tracked_car.push(path);
+                self.tracked_car.len() - 1
             }
+        };
+        let entries: Vec<_> = manifest
+            .sections
+            .iter()
+            .map(|(cid, location)| {
+                (
+                    cid.clone(),
+                    BlockLocation {
+                        car,
+                        location: location.clone(),
+                    },
+                )
+            })
+            .collect();
+        let roots = manifest
+            .header
+            .roots()
+            .iter()
+            .map(|root| root.to_raw_cid().clone())
+            .collect();
+        let count = self.insert_entries(entries, roots);
+        debug!(
+            "Ingested {} sections of CAR file {} from its manifest",
+            count, car
+        );
+        Ok(count)
+    }
 
-            loop {
-                // Attempt to read a block
-                match reader.read_section() {
-                    Ok(section) => {
-                        // Block parsed successfully, we can add it to the index
-                        debug!(
-                            "Parsed block with {:?} in CAR file {} (start:{}, length:{})",
-                            section.cid(),
-                            idx,
-                            section.location.offset,
-                            section.location.length
-                        );
+    /// Add the sections and roots of a CAR file to the index
+    ///
+    /// Identity CIDs are not indexed, as they embed their data (see [DataStore::get_block]).
+    /// Returns the number of indexed sections.
+    fn insert_entries(
+        &mut self,
+        mut entries: Vec<(RawCid, BlockLocation)>,
+        roots: Vec<RawCid>,
+    ) -> usize {
+        entries.retain(|(cid, _)| !cid.is_identity());
+        let count = entries.len();
+        for (cid, _) in &entries {
+            if let Some(digest) = cid.digest() {
+                self.digests
+                    .entry(digest.to_vec())
+                    .or_insert_with(|| cid.clone());
+            }
+        }
+        self.index.extend(entries);
+        for root in roots {
+            if self.root_set.insert(root.clone()) {
+                self.roots.push(root);
+            }
+        }
+        count
+    }
+
+    /// Number of blocks indexed in the DataStore
+    pub fn block_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Find an indexed block by its raw multihash digest
+    ///
+    /// This is how the CARv2 indexes key their entries, so CIDs which only differ by their codec
+    /// (or version) resolve to the same block. If several indexed CIDs share the digest, the first indexed one is returned.
+    ///
+    /// # Returns
+    /// * `Some(&RawCid)` - The indexed CID with this digest
+    /// * `None` - No indexed block has this digest
+    pub fn find_by_digest(&self, digest: &[u8]) -> Option<&RawCid> {
+        self.digests.get(digest)
+    }
+
+    /// Resolve a CID to the indexed CID of the same block
+    ///
+    /// The CID itself if it is indexed, otherwise the indexed CID with the same multihash (digest and hash function).
+    fn resolve_cid(&self, cid: &RawCid) -> Option<&RawCid> {
+        if let Some((indexed, _)) = self.index.get_key_value(cid) {
+            return Some(indexed);
+        }
+        self.find_by_digest(cid.digest()?)
+            .filter(|indexed| indexed.multihash_code() == cid.multihash_code())
+    }
+
+    /// Roots of the indexed CAR files, in indexing order and without duplicates
+    pub fn roots(&self) -> &[RawCid] {
+        &self.roots
+    }
+
+    /// Is this CID a root of an indexed CAR file?
+    ///
+    /// This is a set lookup, the CID being compared as is: unlike the blocks, the roots are not resolved by digest.
+    pub fn has_root(&self, cid: &RawCid) -> bool {
+        self.root_set.contains(cid)
+    }
+
+    /// Get the admission policy of the DAG requests
+    pub fn admission_policy(&self) -> &dyn AdmissionPolicy {
+        self.admission.as_ref()
+    }
+
+    /// Set the admission policy of the DAG requests (by default, [AdmitAll])
+    ///
+    /// For instance, [AdmitRoots](crate::policy::AdmitRoots) turns down the DAG requests which are not rooted at
+    /// a root of an indexed CAR file, without reading any block.
+    pub fn set_admission_policy(&mut self, admission: Box<dyn AdmissionPolicy>) {
+        self.admission = admission;
+    }
+
+    /// Check a DAG request rooted at this CID against the admission policy
+    ///
+    /// This is meant to be called by the servers before attempting a DAG request: only the roots set is consulted,
+    /// the tombstones and the serving policy still apply to each block of the DAG afterwards.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The request should be attempted
+    /// * `Err(DataStoreError::NotAdmitted)` - The request is turned down by the admission policy
+    pub fn admit_dag(&self, cid: &RawCid) -> Result<()> {
+        if self.admission.admit(cid, self.has_root(cid)) {
+            Ok(())
+        } else {
+            debug!("DAG request for {:?} not admitted", cid);
+            Err(DataStoreError::NotAdmitted(cid.to_hex()))
+        }
+    }
+
+    /// Roots of the indexed CAR files which are servable, under the tombstones and the serving policy
+    pub fn servable_roots(&self) -> Vec<RawCid> {
+        self.roots
+            .iter()
+            .filter(|root| {
+                self.index
+                    .get(*root)
+                    .is_some_and(|loc| self.is_servable(root, loc))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Announce the servable roots to the content discovery
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of announced roots
+    /// * `Err(DiscoveryError)` - The announcement failed
+    pub fn announce(
+        &self,
+        discovery: &mut dyn ContentDiscovery,
+    ) -> std::result::Result<usize, DiscoveryError> {
+        let roots = self.servable_roots();
+        let count = discovery.announce(&roots)?;
+        debug!("Announced {} of {} servable roots", count, roots.len());
+        Ok(count)
+    }
+
+    /// Export all the indexed blocks into a single CARv2 file, with a full index
+    ///
+    /// Blocks present in several CAR files are only written once, and the roots of all the indexed
+    /// CAR files become the roots of the exported file. The serving policy is not applied.
+    ///
+    /// Blocks are streamed one by one from their CAR file (ordered by CAR file and offset), so the memory
+    /// usage is bounded by the largest block and the index of the exported file. The `sink` must be seekable,
+    /// as the CARv2 header is only written once the payload and the index are.
+    ///
+    /// # Arguments
+    /// * `sink` - Where to write the CARv2 file
+    /// * `progress` - Called after each exported block
+    ///
+    /// # Returns
+    /// * `Ok(ExportProgress)` - The final progress, once the CARv2 file is complete
+    /// * `Err(DataStoreError)` - Error occurred while reading the blocks or writing the file
+    pub fn export_all<W: Write + Seek>(
+        &mut self,
+        sink: &mut W,
+        mut progress: impl FnMut(&ExportProgress),
+    ) -> Result<ExportProgress> {
+        let mut blocks: Vec<_> = self
+            .index
+            .iter()
+            .map(|(cid, loc)| (cid.clone(), loc.car, loc.location.offset))
+            .collect();
+        blocks.sort_by_key(|(_, car, offset)| (*car, *offset));
+
+        let mut writer = v2::CarWriter::with_buffer_size(self.roots.clone(), EXPORT_BUFFER_SIZE);
+        let data_offset = writer.data_offset();
+        let mut buf = vec![0u8; EXPORT_BUFFER_SIZE];
+        let mut entries = Vec::with_capacity(blocks.len());
+        let mut state = ExportProgress {
+            blocks: 0,
+            total_blocks: blocks.len(),
+            bytes: 0,
+        };
+
+        for (cid, _, _) in blocks {
+            let (data, _) = self.read_block(&cid)?;
+            let section = Section::new(cid.clone(), Block::new(data));
+            let location = loop {
+                match writer.write_section(&section) {
+                    Ok(location) => break location,
+                    Err(v2::CarWriterError::BufferFull) => {
+                        state.bytes += flush_writer(&mut writer, sink, &mut buf)?;
                     }
-                    Err(CarReaderError::InsufficientData(offset, size)) => {
-                        debug!(
-                            "Need more data to parse block in CAR file {}, offset: {}, size: {}",
-                            idx, offset, size
-                        );
-                        // We need more data to parse the block, continue reading
-                        let pos = handle.file.seek(std::io::SeekFrom::Start(offset as u64))?;
-                        let n = handle.file.read(&mut buf)?;
-                        if n == 0 {
-                            // We reached the end of the file, we can stop reading and move to the next CAR file
-                            break;
-                        }
-                        reader.receive_data(&buf[..n], pos as usize);
+                    Err(e) => {
+                        return Err(DataStoreError::Io(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("Error exporting block {}: {}", cid.to_hex(), e),
+                        )));
                     }
-                    Err(CarReaderError::EndOfSections) => {
-                        debug!("Reached end of sections for CAR file {}", idx);
-                        // We reached the end of the sections, we can stop reading and move to the next CAR file
-                        break;
+                }
+            };
+            entries.push((cid, location.offset - data_offset));
+            state.blocks += 1;
+            progress(&state);
+        }
+        state.bytes += flush_writer(&mut writer, sink, &mut buf)?;
+
+        let index = Index::multihash_sorted(entries.iter().map(|(cid, offset)| (cid, *offset)));
+        drop(entries);
+        let Ok(mut writer) = writer.finalize_sections() else {
+            unreachable!("the sections have been flushed");
+        };
+        writer.write_index(&index);
+        state.bytes += flush_writer(&mut writer, sink, &mut buf)?;
+        let Ok(mut writer) = writer.finalize_full_index() else {
+            unreachable!("the index has been flushed");
+        };
+        // The header overwrites the reserved bytes at the start of the file
+        flush_writer(&mut writer, sink, &mut buf)?;
+        sink.flush()?;
+
+        info!(
+            "Exported {} blocks ({} bytes) with {} roots",
+            state.blocks,
+            state.bytes,
+            self.roots.len()
+        );
+        Ok(state)
+    }
+
+    /// Export the servable blocks as a CARv1 stream
+    ///
+    /// Unlike [DataStore::export_all], the sink does not need to be seekable (e.g. a socket), and the tombstones
+    /// and the serving policy apply: only the servable blocks (and roots) are exported. Blocks present in several
+    /// CAR files are only written once. Blocks are streamed one by one, ordered by CAR file and offset.
+    ///
+    /// # Arguments
+    /// * `sink` - Where to write the CARv1 stream
+    /// * `progress` - Called after each exported block
+    ///
+    /// # Returns
+    /// * `Ok(ExportProgress)` - The final progress, once the stream is complete
+    /// * `Err(DataStoreError)` - Error occurred while reading the blocks or writing the stream
+    pub fn export_car_v1<W: Write>(
+        &mut self,
+        sink: &mut W,
+        mut progress: impl FnMut(&ExportProgress),
+    ) -> Result<ExportProgress> {
+        let mut blocks: Vec<_> = self
+            .index
+            .iter()
+            .filter(|(cid, loc)| self.is_servable(cid, loc))
+            .map(|(cid, loc)| (cid.clone(), loc.car, loc.location.offset))
+            .collect();
+        blocks.sort_by_key(|(_, car, offset)| (*car, *offset));
+        let roots = self.servable_roots();
+
+        let mut writer = v1::CarWriter::with_buffer_size(roots, EXPORT_BUFFER_SIZE);
+        let mut buf = vec![0u8; EXPORT_BUFFER_SIZE];
+        let mut state = ExportProgress {
+            blocks: 0,
+            total_blocks: blocks.len(),
+            bytes: 0,
+        };
+        for (cid, _, _) in blocks {
+            let (data, _) = self.read_block(&cid)?;
+            let section = Section::new(cid.clone(), Block::new(data));
+            loop {
+                match writer.write_section(&section) {
+                    Ok(_) => break,
+                    Err(v1::CarWriterError::BufferFull) => {
+                        state.bytes += drain_writer(&mut writer, sink, &mut buf)?;
                     }
                     Err(e) => {
-                        // An error occurred while parsing the block, return it
                         return Err(DataStoreError::Io(std::io::Error::new(
                             std::io::ErrorKind::InvalidData,
-                            format!("Error parsing CAR block: {:?}", e),
+                            format!("Error exporting block {}: {}", cid.to_hex(), e),
                         )));
                     }
                 }
             }
+            state.blocks += 1;
+            progress(&state);
+        }
+        state.bytes += drain_writer(&mut writer, sink, &mut buf)?;
+        sink.flush()?;
+        Ok(state)
+    }
+
+    /// Is the indexed block servable, under the tombstones and the serving policy?
+    fn is_servable(&self, cid: &RawCid, location: &BlockLocation) -> bool {
+        !self.tombstones.is_cid_tombstoned(cid)
+            && !self
+                .tombstones
+                .is_car_tombstoned(&self.tracked_car[location.car])
+            && self.policy.is_servable(cid)
+    }
 
-            debug!("Finished indexing CAR file {}", idx);
+    /// Export the listing of all the indexed blocks, ordered by CAR file and offset
+    ///
+    /// The serving policy is not applied, every indexed block is listed along with the path of its CAR file.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of exported blocks
+    /// * `Err(DataStoreError)` - Error occurred while writing the listing
+    pub fn export_index<W: Write>(&self, exporter: &mut SectionExporter<W>) -> Result<usize> {
+        let mut blocks: Vec<_> = self.index.iter().collect();
+        blocks.sort_by_key(|(_, loc)| (loc.car, loc.location.offset));
+        for (cid, loc) in &blocks {
+            let car = self.tracked_car[loc.car].display().to_string();
+            exporter
+                .write_record(&SectionRecord::new((*cid).clone(), &loc.location).with_car(car))?;
         }
-        Ok(())
+        exporter.flush()?;
+        Ok(blocks.len())
+    }
+
+    /// Get the serving policy of the DataStore
+    pub fn policy(&self) -> &ServingPolicy {
+        &self.policy
+    }
+
+    /// Get the tombstones of the DataStore
+    pub fn tombstones(&self) -> &Tombstones {
+        &self.tombstones
+    }
+
+    /// Load the tombstones persisted in the given file, replacing the current ones
+    ///
+    /// Later changes (see [DataStore::tombstone_cid] and [DataStore::tombstone_car]) are persisted to this file.
+    /// See the [tombstone module](crate::tombstone) for its format.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of loaded tombstones
+    /// * `Err(DataStoreError)` - The file could not be read, or is malformed
+    pub fn load_tombstones<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        self.tombstones = Tombstones::load(path)?;
+        if self.read_only {
+            self.tombstones.detach();
+        }
+        Ok(self.tombstones.len())
+    }
+
+    /// Mark a block as not servable, without touching its CAR file
+    ///
+    /// The CID does not need to be indexed, so that content can be tombstoned before it is added to the storage.
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the CID was not tombstoned yet
+    /// * `Err(DataStoreError)` - The tombstones could not be persisted (the change is applied anyway)
+    pub fn tombstone_cid(&mut self, cid: RawCid) -> Result<bool> {
+        info!("Tombstoning CID {}", cid.to_hex());
+        Ok(self.tombstones.insert_cid(cid)?)
+    }
+
+    /// Mark every block of a CAR file as not servable, without deleting the file
+    ///
+    /// Blocks also indexed from another (not tombstoned) CAR file may remain servable.
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the CAR file was not tombstoned yet
+    /// * `Err(DataStoreError)` - The path could not be resolved, or the tombstones could not be persisted
+    pub fn tombstone_car<P: AsRef<Path>>(&mut self, path: P) -> Result<bool> {
+        let path = self.backend.resolve(path.as_ref())?;
+        info!("Tombstoning CAR file {:?}", path);
+        Ok(self.tombstones.insert_car(path)?)
+    }
+
+    /// Lift the tombstone of a block
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the CID was tombstoned
+    /// * `Err(DataStoreError)` - The tombstones could not be persisted (the change is applied anyway)
+    pub fn lift_tombstone_cid(&mut self, cid: &RawCid) -> Result<bool> {
+        info!("Lifting the tombstone of CID {}", cid.to_hex());
+        Ok(self.tombstones.remove_cid(cid)?)
+    }
+
+    /// Lift the tombstone of a CAR file
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the CAR file was tombstoned
+    /// * `Err(DataStoreError)` - The path could not be resolved, or the tombstones could not be persisted
+    pub fn lift_tombstone_car<P: AsRef<Path>>(&mut self, path: P) -> Result<bool> {
+        let path = self.backend.resolve(path.as_ref())?;
+        info!("Lifting the tombstone of CAR file {:?}", path);
+        Ok(self.tombstones.remove_car(&path)?)
+    }
+
+    /// Get the block lookup metrics of the DataStore
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Set the latency above which a block lookup is considered slow
+    ///
+    /// Slow lookups are counted in the metrics and logged (target `navira_store::slow_query`) with
+    /// the CID, the CAR file, whether the CAR file had to be opened and the seek distance in the file.
+    /// Passing `None` disables the slow-query log.
+    pub fn set_slow_query_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_query_threshold = threshold;
+    }
+
+    /// Identifier of the DataStore, unique in the process
+    pub fn id(&self) -> StoreId {
+        self.id
+    }
+
+    /// Get the block cache of the DataStore, if any
+    pub fn block_cache(&self) -> Option<&Arc<BlockCache>> {
+        self.block_cache.as_ref()
+    }
+
+    /// Set the block cache of the DataStore
+    ///
+    /// The same cache can be set on several DataStore instances, so that they share its memory budget
+    /// (and its blocks, depending on its [keying](crate::cache::CacheKeying)). Passing `None` disables the cache.
+    /// The tombstones and the serving policy are always consulted before the cache.
+    pub fn set_block_cache(&mut self, cache: Option<Arc<BlockCache>>) {
+        self.block_cache = cache;
+    }
+
+    /// Restrict the servable blocks to the ones reachable from the given roots
+    ///
+    /// The reachability set is precomputed by walking the DAGs from the allowed roots, following
+    /// the links of dag-pb and dag-cbor blocks. Therefore, this method must be called after [DataStore::index],
+    /// and again whenever new CAR files are indexed.
+    ///
+    /// Roots (and linked blocks) which are not indexed in the DataStore are ignored.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of servable blocks
+    /// * `Err(DataStoreError)` - Error occurred while reading the blocks
+    pub fn set_allowed_roots(&mut self, roots: Vec<RawCid>) -> Result<usize> {
+        let mut reachable = HashSet::new();
+        let mut stack = roots.clone();
+        while let Some(cid) = stack.pop() {
+            if reachable.contains(&cid) || !self.index.contains_key(&cid) {
+                continue;
+            }
+            let (data, _) = self.read_block(&cid)?;
+            stack.extend(block_links(&cid, &data));
+            reachable.insert(cid);
+        }
+
+        let count = reachable.len();
+        info!(
+            "Serving policy restricted to {} roots ({} reachable blocks)",
+            roots.len(),
+            count
+        );
+        self.policy = ServingPolicy::AllowRoots {
+            roots: roots.into_iter().collect(),
+            reachable,
+        };
+        Ok(count)
+    }
+
+    /// Get the data of a block by its CID
+    ///
+    /// CIDs which are not indexed as such, but share their multihash with an indexed block (e.g. a CIDv0 and the
+    /// CIDv1 of the same dag-pb block, or the same bytes under another codec), resolve to that block.
+    /// The tombstones and the serving policy are consulted before touching the disk.
+    /// Every lookup is recorded in the [DataStore::metrics], and logged if it exceeds the slow-query threshold.
+    ///
+    /// Identity CIDs embed their data, which is returned without any disk access (unless the CID is tombstoned).
+    /// The serving policy does not apply to them: the requester already has the data.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The block data
+    /// * `Err(DataStoreError::NotFound)` - The block is not indexed
+    /// * `Err(DataStoreError::Tombstoned)` - The block (or its CAR file) is tombstoned
+    /// * `Err(DataStoreError::Denied)` - The block is indexed but not servable
+    /// * `Err(DataStoreError)` - Error occurred while reading the block
+    pub fn get_block(&mut self, cid: &RawCid) -> Result<Vec<u8>> {
+        let _span = debug_span!("get_block", cid = %cid.to_hex()).entered();
+        let start = Instant::now();
+        match self.resolve_block(cid, start)? {
+            Resolution::Ready(data) => Ok(data),
+            Resolution::Read(indexed, location) => {
+                self.complete_read(cid, &indexed, &location, start)
+            }
+        }
+    }
+
+    /// Maximum number of blocks requested at once with [DataStore::get_blocks]
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    /// Set the maximum number of blocks requested at once with [DataStore::get_blocks]
+    pub fn set_max_batch_size(&mut self, max_batch_size: usize) {
+        self.max_batch_size = max_batch_size;
+    }
+
+    /// Get the data of a batch of blocks (e.g. a wantlist burst)
+    ///
+    /// Each block is checked as with [DataStore::get_block], then the blocks to read from disk are sorted
+    /// by CAR file and offset, so that each CAR file is read sequentially instead of seeking back and forth.
+    ///
+    /// # Returns
+    /// One result per requested CID, in request order, as returned by [DataStore::get_block].
+    /// The requests past [DataStore::max_batch_size] fail with [DataStoreError::BatchLimit].
+    pub fn get_blocks(&mut self, cids: &[RawCid]) -> Vec<Result<Vec<u8>>> {
+        let _span = debug_span!("get_blocks", count = cids.len()).entered();
+        let mut results: Vec<Option<Result<Vec<u8>>>> = (0..cids.len()).map(|_| None).collect();
+        let mut reads = Vec::new();
+        for (i, cid) in cids.iter().enumerate() {
+            if i >= self.max_batch_size {
+                results[i] = Some(Err(DataStoreError::BatchLimit(self.max_batch_size)));
+                continue;
+            }
+            match self.resolve_block(cid, Instant::now()) {
+                Ok(Resolution::Ready(data)) => results[i] = Some(Ok(data)),
+                Ok(Resolution::Read(indexed, location)) => reads.push((i, indexed, location)),
+                Err(e) => results[i] = Some(Err(e)),
+            }
+        }
+
+        reads.sort_by_key(|(_, _, location)| (location.car, location.location.offset));
+        debug!(
+            "Reading {} of {} batched blocks from disk",
+            reads.len(),
+            cids.len()
+        );
+        for (i, indexed, location) in reads {
+            results[i] = Some(self.complete_read(&cids[i], &indexed, &location, Instant::now()));
+        }
+        results
+            .into_iter()
+            .map(|result| result.expect("every request is answered"))
+            .collect()
+    }
+
+    /// Check a block request against the tombstones, the index and the serving policy
+    ///
+    /// The lookups which do not need a disk read (identity CIDs, block cache hits, failures) are recorded
+    /// in the metrics here.
+    fn resolve_block(&mut self, cid: &RawCid, start: Instant) -> Result<Resolution> {
+        if self.tombstones.is_cid_tombstoned(cid) {
+            debug!("Denied request for tombstoned {:?}", cid);
+            self.metrics
+                .record_lookup(LookupOutcome::Tombstoned, start.elapsed());
+            return Err(DataStoreError::Tombstoned(cid.to_hex()));
+        }
+        if let Some(data) = cid.identity_data() {
+            trace!(cid = %cid.to_hex(), "Identity CID served");
+            self.metrics
+                .record_lookup(LookupOutcome::Served, start.elapsed());
+            return Ok(Resolution::Ready(data.to_vec()));
+        }
+        let Some(indexed) = self.resolve_cid(cid).cloned() else {
+            self.metrics
+                .record_lookup(LookupOutcome::NotFound, start.elapsed());
+            return Err(DataStoreError::NotFound(cid.to_hex()));
+        };
+        let block_location = self.index[&indexed].clone();
+        if self.tombstones.is_cid_tombstoned(&indexed)
+            || self
+                .tombstones
+                .is_car_tombstoned(&self.tracked_car[block_location.car])
+        {
+            debug!(
+                "Denied request for {:?} from a tombstoned block or CAR file",
+                cid
+            );
+            self.metrics
+                .record_lookup(LookupOutcome::Tombstoned, start.elapsed());
+            return Err(DataStoreError::Tombstoned(cid.to_hex()));
+        }
+        if !self.policy.is_servable(&indexed) {
+            debug!("Denied request for {:?} by serving policy", cid);
+            self.metrics
+                .record_lookup(LookupOutcome::Denied, start.elapsed());
+            return Err(DataStoreError::Denied(cid.to_hex()));
+        }
+
+        let cached = self.block_cache.as_ref().and_then(|cache| {
+            let size = block_size(&indexed, &block_location.location)?;
+            let data = cache.get(self.id, indexed.multihash_code()?, indexed.digest()?, size);
+            trace!(cid = %indexed.to_hex(), hit = data.is_some(), "Block cache lookup");
+            data
+        });
+        if let Some(data) = cached {
+            self.metrics
+                .record_lookup(LookupOutcome::Served, start.elapsed());
+            return Ok(Resolution::Ready(data.to_vec()));
+        }
+        Ok(Resolution::Read(indexed, block_location))
+    }
+
+    /// Read a resolved block from disk, recording the lookup and caching the block
+    fn complete_read(
+        &mut self,
+        cid: &RawCid,
+        indexed: &RawCid,
+        block_location: &BlockLocation,
+        start: Instant,
+    ) -> Result<Vec<u8>> {
+        let result = self.read_block(indexed);
+        let elapsed = start.elapsed();
+        let (data, stats) = match result {
+            Ok(read) => read,
+            Err(e) => {
+                self.metrics.record_lookup(LookupOutcome::Error, elapsed);
+                return Err(e);
+            }
+        };
+        self.metrics.record_lookup(LookupOutcome::Served, elapsed);
+        if let (Some(cache), Some(code), Some(digest)) = (
+            &self.block_cache,
+            indexed.multihash_code(),
+            indexed.digest(),
+        ) {
+            cache.insert(self.id, code, digest, &data);
+            trace!(cid = %indexed.to_hex(), size = data.len(), "Block cached");
+        }
+        if stats.cache_miss {
+            self.metrics.record_car_cache_miss();
+        }
+        if self.slow_query_threshold.is_some_and(|t| elapsed >= t) {
+            self.metrics.record_slow_lookup();
+            warn!(
+                target: "navira_store::slow_query",
+                cid = %cid.to_hex(),
+                car = ?self.tracked_car[block_location.car],
+                cache_miss = stats.cache_miss,
+                seek_distance = stats.seek_distance,
+                elapsed_us = elapsed.as_micros() as u64,
+                "Slow block lookup"
+            );
+        }
+        Ok(data)
+    }
+
+    /// Read a block from its CAR file, regardless of the serving policy
+    fn read_block(&mut self, cid: &RawCid) -> Result<(Vec<u8>, ReadStats)> {
+        let Some(block_location) = self.index.get(cid).cloned() else {
+            return Err(DataStoreError::NotFound(cid.to_hex()));
+        };
+        let cache_miss = !self.car_handles.iter().any(|h| h.idx == block_location.car);
+        let handle = self.open_car(block_location.car)?;
+        let mut buf = vec![0u8; block_location.location.length as usize];
+        let previous = handle.file.stream_position()?;
+        let stats = ReadStats {
+            cache_miss,
+            seek_distance: previous.abs_diff(block_location.location.offset),
+        };
+        handle
+            .file
+            .read_exact_at(block_location.location.offset, &mut buf)?;
+        let (section, _) =
+            Section::try_read_bytes_with(&buf, &Limits::default(), CidParsing::Lenient).map_err(
+                |e| {
+                    DataStoreError::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Error parsing CAR block: {:?}", e),
+                    ))
+                },
+            )?;
+        if section.cid() != cid {
+            return Err(DataStoreError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Unexpected block {:?} at the location of {:?}",
+                    section.cid(),
+                    cid
+                ),
+            )));
+        }
+        Ok((section.block().data().to_vec(), stats))
     }
 
     /// Carefully shutdown the DataStore, closing any open CAR files
@@ -226,12 +1307,14 @@ impl DataStore {
         if !self.car_handles.iter().any(|h| h.idx == idx) {
             // If we reached the max open CAR files, close the least recently used one
             if self.car_handles.len() >= self.max_open_cars {
-                self.car_handles.remove(0);
+                let evicted = self.car_handles.remove(0);
+                trace!(car = ?self.tracked_car[evicted.idx], "CAR file handle evicted");
             }
 
             // Open the CAR file
             let car_path = &self.tracked_car[idx];
-            let file = File::open(car_path)?;
+            let file = self.backend.open(car_path)?;
+            trace!(car = ?car_path, "CAR file opened");
             let handle = CarHandle { idx, file };
             self.car_handles.push(handle);
         }
@@ -240,8 +1323,1108 @@ impl DataStore {
     }
 }
 
+/// Number of sections read and checked against their CID when adopting a CAR file
+const ADOPTION_SAMPLE_SIZE: usize = 16;
+
+/// Feed the reader with the data it requested, returns the number of bytes read (0 at the end of the file)
+fn feed_reader(
+    reader: &mut CarReader,
+    file: &mut dyn Archive,
+    offset: usize,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    let n = file.read_at(offset as u64, buf)?;
+    reader.receive_data(&buf[..n], offset);
+    Ok(n)
+}
+
+/// Read the header(s) of a CAR file, returning the reader positioned right after them
+fn read_car_header(
+    file: &mut dyn Archive,
+) -> std::result::Result<CarReader, Box<dyn std::error::Error>> {
+    let mut reader = CarReader::new();
+    reader.set_cid_parsing(CidParsing::Lenient);
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        match reader.read_header() {
+            Ok(()) => return Ok(reader),
+            Err(CarReaderError::InsufficientData(offset, _)) => {
+                if feed_reader(&mut reader, file, offset, &mut buf)? == 0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Absolute offset of the first section, after the CARv1 header of the payload at `data_offset`
+fn sections_start(
+    file: &mut dyn Archive,
+    data_offset: u64,
+) -> std::result::Result<u64, Box<dyn std::error::Error>> {
+    let mut prefix = [0u8; 10];
+    let n = file.read_at(data_offset, &mut prefix)?;
+    let (UnsignedVarint(header_length), varint_length) =
+        UnsignedVarint::decode(&prefix[..n]).ok_or("invalid CARv1 header length")?;
+    Ok(data_offset + varint_length as u64 + header_length)
+}
+
+/// Sections and roots of a CAR file, with the source they were located from
+type LocatedSections = (AdoptionSource, Vec<(RawCid, SectionLocation)>, Vec<RawCid>);
+
+/// Locate the sections of a CAR file from its embedded (full) index or its sidecar index, if any
+///
+/// Only the section headers are read, at the indexed offsets. Returns `None` if the file has no usable index.
+fn locate_from_index(
+    file: &mut dyn Archive,
+    path: &Path,
+    backend: &dyn StorageBackend,
+) -> Result<Option<LocatedSections>> {
+    let invalid = |e: Box<dyn std::error::Error>| {
+        DataStoreError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Error reading CAR file {:?}: {}", path, e),
+        ))
+    };
+    let reader = read_car_header(file).map_err(invalid)?;
+    let (v1_header, v2_header) = reader
+        .header()
+        .ok_or_else(|| invalid("missing CAR header".into()))?;
+    let roots: Vec<RawCid> = v1_header
+        .roots()
+        .iter()
+        .map(|root| root.to_raw_cid().clone())
+        .collect();
+    let v2_header = v2_header.cloned();
+
+    let mut sidecar_name = path.file_name().unwrap_or_default().to_os_string();
+    sidecar_name.push(".idx");
+    let sidecar = path.with_file_name(sidecar_name);
+    let (source, index_bytes, data_offset) = match &v2_header {
+        Some(header) if header.characteristics.has_full_index() && header.index_offset != 0 => (
+            AdoptionSource::EmbeddedIndex,
+            file.read_to_end_at(header.index_offset)?,
+            header.data_offset,
+        ),
+        _ => match backend.read_object(&sidecar)? {
+            Some(bytes) => (
+                AdoptionSource::SidecarIndex,
+                bytes,
+                v2_header.as_ref().map_or(0, |header| header.data_offset),
+            ),
+            None => return Ok(None),
+        },
+    };
+    let (index, _) = Index::decode(&index_bytes).map_err(|e| invalid(e.into()))?;
+    drop(index_bytes);
+
+    let first = sections_start(file, data_offset).map_err(invalid)?;
+    let mut offsets: Vec<u64> = index
+        .buckets
+        .iter()
+        .flat_map(|bucket| {
+            bucket
+                .entries
+                .iter()
+                .map(|entry| data_offset + entry.offset)
+        })
+        .collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+
+    let mut sections = Vec::with_capacity(offsets.len());
+    // Length varint and CID (with a digest of up to 64 bytes)
+    let mut buf = [0u8; 96];
+    for offset in offsets {
+        if offset < first {
+            return Err(invalid(
+                format!("index entry at offset {} before the sections", offset).into(),
+            ));
+        }
+        let n = file.read_at(offset, &mut buf)?;
+        let (section, length) = v1::Section::try_read_header_bytes_with(
+            &buf[..n],
+            &Limits::default(),
+            CidParsing::Lenient,
+        )
+        .map_err(|e| invalid(format!("section at offset {}: {}", offset, e).into()))?;
+        let (cid, _) = section.into_parts();
+        let location = SectionLocation {
+            offset,
+            length: length as u64,
+        };
+        sections.push((cid, location));
+    }
+    Ok(Some((source, sections, roots)))
+}
+
+/// Read a random sample of the sections and check them against their CID
+///
+/// Returns the number of sampled sections.
+fn validate_sample(
+    file: &mut dyn Archive,
+    sections: &[(RawCid, SectionLocation)],
+) -> Result<usize> {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut candidates: Vec<usize> = (0..sections.len()).collect();
+    let sample_size = ADOPTION_SAMPLE_SIZE.min(candidates.len());
+    let mut random = std::collections::hash_map::RandomState::new().build_hasher();
+    for i in 0..sample_size {
+        random.write_usize(i);
+        let j = i + (random.finish() as usize) % (candidates.len() - i);
+        candidates.swap(i, j);
+    }
+
+    let mut buf = Vec::new();
+    for &i in &candidates[..sample_size] {
+        let (cid, location) = &sections[i];
+        let invalid = |message: String| {
+            DataStoreError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid section at offset {}: {}", location.offset, message),
+            ))
+        };
+        buf.resize(location.length as usize, 0);
+        file.read_exact_at(location.offset, &mut buf)?;
+        let (section, length) =
+            v1::Section::try_read_bytes_with(&buf, &Limits::default(), CidParsing::Lenient)
+                .map_err(|e| invalid(e.to_string()))?;
+        if section.cid() != cid || length != buf.len() {
+            return Err(invalid(format!("expected {}", cid.to_hex())));
+        }
+        match verify_block(cid, section.block().data(), DigestComparison::Variable) {
+            Ok(()) | Err(DigestError::UnsupportedHash(_)) => {}
+            Err(e) => return Err(invalid(e.to_string())),
+        }
+    }
+    Ok(sample_size)
+}
+
+/// Verify a CARv2 file with exact reads, planned from its index
+fn verify_car_exact(
+    file: &mut dyn Archive,
+    header: &CarV2Header,
+) -> std::result::Result<VerifyReport, Box<dyn std::error::Error>> {
+    let bytes = file.read_to_end_at(header.index_offset)?;
+    let (index, _) = Index::decode(&bytes)?;
+    drop(bytes);
+
+    let sections_start = sections_start(file, header.data_offset)?;
+    let mut verifier = IndexedVerifier::new(header, sections_start, &index)?;
+    let mut buf = vec![0u8; verifier.max_read_length()];
+    while let Some(range) = verifier.next_read() {
+        let buf = &mut buf[..(range.end - range.start) as usize];
+        file.read_exact_at(range.start, buf)?;
+        verifier.verify_read(buf)?;
+    }
+    Ok(*verifier.report())
+}
+
+/// Warn if a CARv2 file claims to be fully indexed while its index does not cover all its sections
+///
+/// The DataStore always scans all the sections, so an untruthful flag does not affect it, but it must be
+/// reported as other consumers of the file may rely on it to skip full scans.
+fn check_full_index(
+    file: &mut dyn Archive,
+    path: &Path,
+    header: &CarV2Header,
+    entries: &[(RawCid, BlockLocation)],
+) -> Result<()> {
+    let mut coverage = IndexCoverage::default();
+    if header.index_offset != 0 {
+        let bytes = file.read_to_end_at(header.index_offset)?;
+        match Index::decode(&bytes) {
+            Ok((index, _)) => {
+                coverage = IndexCoverage::compute(&index, entries.iter().map(|(cid, _)| cid));
+            }
+            Err(e) => {
+                warn!(
+                    "CAR file {:?} claims to be fully indexed, but its index is invalid: {}",
+                    path, e
+                );
+                return Ok(());
+            }
+        }
+    }
+    if let Err(e) = verify_full_index(header, &coverage) {
+        warn!("CAR file {:?} has untruthful characteristics: {}", path, e);
+    }
+    Ok(())
+}
+
+/// Size of the buffers used when exporting the DataStore (and of the largest exportable section)
+const EXPORT_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Write the pending data of a CARv1 writer to a sink
+///
+/// Returns the number of bytes written.
+fn drain_writer<W: Write>(writer: &mut v1::CarWriter, sink: &mut W, buf: &mut [u8]) -> Result<u64> {
+    let mut written = 0;
+    while writer.has_data_to_send() {
+        let len = writer.send_data(buf);
+        sink.write_all(&buf[..len])?;
+        written += len as u64;
+    }
+    Ok(written)
+}
+
+/// Write the pending data of a CARv2 writer to a seekable sink
+///
+/// Returns the number of bytes written.
+fn flush_writer<C: CarWriteV2, W: Write + Seek>(
+    writer: &mut C,
+    sink: &mut W,
+    buf: &mut [u8],
+) -> Result<u64> {
+    let mut written = 0;
+    while writer.has_data_to_send() {
+        let (offset, len) = writer.send_data(buf);
+        sink.seek(std::io::SeekFrom::Start(offset as u64))?;
+        sink.write_all(&buf[..len])?;
+        written += len as u64;
+    }
+    Ok(written)
+}
+
+/// Progress of a DataStore export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportProgress {
+    /// Number of blocks exported so far
+    pub blocks: usize,
+    /// Number of blocks to export
+    pub total_blocks: usize,
+    /// Number of bytes written so far (the CARv2 header excluded)
+    pub bytes: u64,
+}
+
+/// Sections and roots of a scanned CAR file
+type ScannedCar = (Vec<(RawCid, BlockLocation)>, Vec<RawCid>);
+
+/// Convert a DataStore error to an I/O error, to be recorded in the quarantine
+fn into_io_error(error: DataStoreError) -> std::io::Error {
+    match error {
+        DataStoreError::Io(e) => e,
+        e => std::io::Error::other(e.to_string()),
+    }
+}
+
+/// Hint for the operators on how to fix an access error of the given kind
+fn access_hint(kind: std::io::ErrorKind) -> &'static str {
+    match kind {
+        std::io::ErrorKind::PermissionDenied => {
+            "check the permissions and the owner of the path, the store needs read access"
+        }
+        std::io::ErrorKind::NotFound => {
+            "the path does not exist, or was removed since it was discovered"
+        }
+        std::io::ErrorKind::NotADirectory => "the datastore path must be a directory of CAR files",
+        _ => "check that the path is readable by the store",
+    }
+}
+
+/// A CAR file which failed to be indexed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedCar {
+    /// Path of the CAR file
+    pub path: PathBuf,
+    /// Offset in the file at which the error occurred
+    pub offset: u64,
+    /// Kind of the error (`InvalidData` for parsing errors, `UnexpectedEof` for truncated files, …)
+    pub kind: std::io::ErrorKind,
+    /// Description of the error
+    pub message: String,
+}
+
+/// Where the section locations of an adopted CAR file come from (see [DataStore::adopt_car])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdoptionSource {
+    /// The manifest returned by the writer of the file
+    Manifest,
+    /// The embedded index of a fully indexed CARv2 file
+    EmbeddedIndex,
+    /// The sidecar index next to the file
+    SidecarIndex,
+    /// A full scan of the file
+    Scan,
+}
+
+/// Outcome of the adoption of a CAR file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdoptedCar {
+    /// Where the section locations come from
+    pub source: AdoptionSource,
+    /// Number of indexed blocks (identity CIDs excluded)
+    pub blocks: usize,
+    /// Number of sections read and checked against their CID
+    pub sampled: usize,
+}
+
+/// Result of the verification of a CAR file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarVerification {
+    /// Path of the CAR file
+    pub path: PathBuf,
+    /// Whether the file was verified with exact reads planned from its index
+    pub exact: bool,
+    /// Counters of the verification, or the description of the first error
+    pub result: std::result::Result<VerifyReport, String>,
+}
+
+/// Report of the state of a DataStore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreReport {
+    /// Number of tracked CAR files
+    pub tracked_cars: usize,
+    /// Number of indexed blocks
+    pub blocks: usize,
+    /// Number of distinct roots of the indexed CAR files
+    pub roots: usize,
+    /// CAR files which failed to be indexed, and were skipped
+    pub quarantine: Vec<QuarantinedCar>,
+}
+
+impl StoreReport {
+    /// Render the report as plain text, one `key value` per line
+    ///
+    /// Each quarantined file is rendered on a `quarantined` line, with its path, offset, error kind and
+    /// message separated by tabs.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "tracked_cars {}\nblocks {}\nroots {}\n",
+            self.tracked_cars, self.blocks, self.roots
+        );
+        for car in &self.quarantine {
+            out.push_str(&format!(
+                "quarantined {}\t{}\t{:?}\t{}\n",
+                car.path.display(),
+                car.offset,
+                car.kind,
+                car.message.replace('\n', " ")
+            ));
+        }
+        out
+    }
+}
+
+/// Location of a block in the tracked CAR files
+#[derive(Debug, Clone)]
+pub struct BlockLocation {
+    /// Index of the CAR file in the tracked CAR files
+    pub car: usize,
+    /// Location of the section in the CAR file
+    pub location: SectionLocation,
+}
+
+/// Size of the block data of a section, from its location and CID
+///
+/// The section length covers its varint length prefix, so the prefix length is derived first.
+fn block_size(cid: &RawCid, location: &SectionLocation) -> Option<usize> {
+    let varint_len = |n: u64| (64 - n.leading_zeros()).div_ceil(7).max(1) as u64;
+    let prefixed = (1..=10).find_map(|len| {
+        let payload = location.length.checked_sub(len)?;
+        (varint_len(payload) == len).then_some(payload)
+    })?;
+    let data = prefixed.checked_sub(cid.bytes().len() as u64)?;
+    usize::try_from(data).ok()
+}
+
+/// Statistics of a block read, reported in the slow-query log
+#[derive(Debug, Clone, Copy)]
+struct ReadStats {
+    // Whether the CAR file had to be opened
+    cache_miss: bool,
+    // Distance (in bytes) between the previous position in the CAR file and the block
+    seek_distance: u64,
+}
+
+/// Outcome of the checks preceding a block read
+enum Resolution {
+    /// The block data is already available (identity CID, block cache hit)
+    Ready(Vec<u8>),
+    /// The block must be read from its CAR file, as the indexed CID
+    Read(RawCid, BlockLocation),
+}
+
 /// Handle to an open CAR file
 pub struct CarHandle {
     idx: usize,
-    file: File,
+    file: Box<dyn Archive>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use navira_car::testdata;
+
+    /// Create a temporary datastore directory with the navira-car fixtures
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("navira-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("carv1-basic.car"), testdata::CARV1_BASIC).unwrap();
+        std::fs::write(dir.join("carv2-basic.car"), testdata::CARV2_BASIC).unwrap();
+        dir
+    }
+
+    fn indexed_store(name: &str) -> DataStore {
+        let mut store = DataStore::new();
+        assert_eq!(store.scan_directory(fixture_dir(name)).unwrap(), 2);
+        store.index().unwrap();
+        store
+    }
+
+    #[test]
+    fn test_datastore_index_and_get_block() {
+        let mut store = indexed_store("get-block");
+        assert_eq!(store.block_count(), 13);
+        let cid = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        assert_eq!(store.get_block(&cid).unwrap(), b"cccc");
+        let unknown = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        assert!(matches!(
+            store.get_block(&unknown),
+            Err(DataStoreError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_datastore_quarantine() {
+        let dir = fixture_dir("quarantine");
+        let mut truncated = testdata::CARV1_BASIC.to_vec();
+        truncated.truncate(truncated.len() - 2);
+        std::fs::write(dir.join("truncated.car"), &truncated).unwrap();
+        // A 5-byte header which is not valid CBOR
+        std::fs::write(dir.join("garbage.car"), b"\x05\xff\xff\xff\xff\xff garbage").unwrap();
+        let mut store = DataStore::new();
+        assert_eq!(store.scan_directory(&dir).unwrap(), 4);
+        store.index().unwrap();
+
+        // The valid files are still indexed
+        assert_eq!(store.block_count(), 13);
+        let report = store.report();
+        assert_eq!(report.tracked_cars, 4);
+        let mut quarantine = report.quarantine.clone();
+        quarantine.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(quarantine.len(), 2);
+        assert!(quarantine[0].path.ends_with("garbage.car"));
+        assert_eq!(quarantine[0].kind, std::io::ErrorKind::InvalidData);
+        assert!(quarantine[1].path.ends_with("truncated.car"));
+        assert_eq!(quarantine[1].kind, std::io::ErrorKind::UnexpectedEof);
+        let last = testdata::CARV1_BASIC_SECTIONS.last().unwrap().location();
+        assert_eq!(quarantine[1].offset, last.offset);
+        assert_eq!(report.render().matches("quarantined ").count(), 2);
+
+        // Repaired files leave the quarantine
+        std::fs::write(dir.join("truncated.car"), testdata::CARV1_BASIC).unwrap();
+        std::fs::write(dir.join("garbage.car"), testdata::CARV1_BASIC).unwrap();
+        store.index().unwrap();
+        assert!(store.quarantine().is_empty());
+        assert_eq!(store.block_count(), 13);
+    }
+
+    #[test]
+    fn test_datastore_get_block_by_digest() {
+        let mut store = indexed_store("by-digest");
+        // The raw block "cccc" under the dag-pb codec, and as a CIDv0
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        let mut other_codec = cccc.bytes().to_vec();
+        other_codec[1] = 0x70;
+        let other_codec = RawCid::new(other_codec);
+        let v0 = RawCid::new(cccc.bytes()[2..].to_vec());
+        assert_eq!(store.find_by_digest(cccc.digest().unwrap()), Some(&cccc));
+        assert_eq!(store.get_block(&other_codec).unwrap(), b"cccc");
+        assert_eq!(store.get_block(&v0).unwrap(), b"cccc");
+
+        // Same digest, other hash function
+        let mut other_hash = cccc.bytes().to_vec();
+        other_hash[2] = 0x13;
+        assert!(matches!(
+            store.get_block(&RawCid::new(other_hash)),
+            Err(DataStoreError::NotFound(_))
+        ));
+
+        // Tombstoning the indexed CID covers its aliases
+        store.tombstone_cid(cccc).unwrap();
+        assert!(matches!(
+            store.get_block(&v0),
+            Err(DataStoreError::Tombstoned(_))
+        ));
+    }
+
+    #[test]
+    fn test_datastore_export_all() {
+        let dir = fixture_dir("export-all");
+        // The same blocks in another file must be exported once
+        std::fs::write(dir.join("copy.car"), testdata::CARV1_BASIC).unwrap();
+        let mut store = DataStore::new();
+        assert_eq!(store.scan_directory(&dir).unwrap(), 3);
+        store.index().unwrap();
+        assert_eq!(store.block_count(), 13);
+        assert_eq!(store.roots().len(), 3);
+
+        let mut sink = std::io::Cursor::new(Vec::new());
+        let mut calls = 0;
+        let progress = store
+            .export_all(&mut sink, |p| {
+                calls += 1;
+                assert_eq!(p.blocks, calls);
+                assert_eq!(p.total_blocks, 13);
+            })
+            .unwrap();
+        assert_eq!(calls, 13);
+        let bytes = sink.into_inner();
+        assert_eq!(progress.bytes + 51, bytes.len() as u64);
+
+        // The export is a fully indexed CARv2 with all the blocks
+        let mut reader = CarReader::from_bytes(&bytes).unwrap();
+        let (v1_header, v2_header) = reader.header().unwrap();
+        assert_eq!(v1_header.roots().len(), 3);
+        let v2_header = v2_header.unwrap().clone();
+        assert!(v2_header.characteristics.has_full_index());
+        let (index, _) = Index::decode(&bytes[v2_header.index_offset as usize..]).unwrap();
+        assert_eq!(index.len(), 13);
+        let mut count = 0;
+        while let Ok(section) = reader.read_section() {
+            assert_eq!(
+                index.find(section.cid()),
+                Some(section.location.offset - v2_header.data_offset)
+            );
+            assert_eq!(
+                store.get_block(section.cid()).unwrap(),
+                section.block().data()
+            );
+            count += 1;
+        }
+        assert_eq!(count, 13);
+    }
+
+    #[test]
+    fn test_datastore_export_index() {
+        use navira_car::inspect::ExportFormat;
+
+        let store = indexed_store("export-index");
+        let mut exporter = SectionExporter::new(Vec::new(), ExportFormat::Csv);
+        assert_eq!(store.export_index(&mut exporter).unwrap(), 13);
+        let listing = String::from_utf8(exporter.into_inner()).unwrap();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines.len(), 14);
+        assert_eq!(lines[0], "car,cid,offset,length,codec,multihash");
+        // Blocks of the same CAR file are listed by offset
+        let carv1_offsets: Vec<u64> = lines[1..]
+            .iter()
+            .filter(|l| l.contains("carv1-basic.car"))
+            .map(|l| l.split(',').nth(2).unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(carv1_offsets, vec![100, 192, 325, 366, 496, 537, 619, 660]);
+    }
+
+    #[test]
+    fn test_datastore_lookup_metrics() {
+        let mut store = indexed_store("lookup-metrics");
+        store.set_slow_query_threshold(Some(Duration::ZERO));
+        let cccc = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        let unknown = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        store.shutdown().unwrap();
+        assert!(store.get_block(&cccc).is_ok());
+        assert!(store.get_block(&cccc).is_ok());
+        assert!(store.get_block(&unknown).is_err());
+
+        let metrics = store.metrics();
+        assert_eq!(metrics.lookups(LookupOutcome::Served), 2);
+        assert_eq!(metrics.lookups(LookupOutcome::NotFound), 1);
+        assert_eq!(metrics.slow_lookups(), 2);
+        assert_eq!(metrics.car_cache_misses(), 1);
+    }
+
+    #[test]
+    fn test_datastore_allowed_roots() {
+        let mut store = indexed_store("allowed-roots");
+        // Second root of carv1-basic.car: a dag-cbor block with no link
+        let limbo = RawCid::from_hex(
+            "0171122069ea0740f9807a28f4d932c62e7c1c83be055e55072c90266ab3e79df63a365b",
+        )
+        .unwrap();
+        assert_eq!(store.set_allowed_roots(vec![limbo.clone()]).unwrap(), 1);
+        assert!(store.get_block(&limbo).is_ok());
+        let cccc = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        assert!(matches!(
+            store.get_block(&cccc),
+            Err(DataStoreError::Denied(_))
+        ));
+
+        // First root of carv1-basic.car: links (transitively) to all the other blocks of the file
+        let blip = RawCid::from_hex(
+            "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b",
+        )
+        .unwrap();
+        assert_eq!(store.set_allowed_roots(vec![blip]).unwrap(), 7);
+        assert_eq!(store.get_block(&cccc).unwrap(), b"cccc");
+        assert!(matches!(
+            store.get_block(&limbo),
+            Err(DataStoreError::Denied(_))
+        ));
+    }
+
+    #[test]
+    fn test_datastore_admission() {
+        let mut store = indexed_store("admission");
+        let blip = testdata::CARV1_BASIC_SECTIONS[0].cid();
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        assert!(store.has_root(&blip));
+        assert!(!store.has_root(&cccc));
+        assert_eq!(store.roots().len(), 3);
+
+        // Every DAG request is admitted by default
+        assert!(store.admit_dag(&cccc).is_ok());
+        store.set_admission_policy(Box::new(crate::policy::AdmitRoots));
+        assert!(store.admit_dag(&blip).is_ok());
+        assert!(matches!(
+            store.admit_dag(&cccc),
+            Err(DataStoreError::NotAdmitted(_))
+        ));
+        // The blocks themselves are still served
+        assert_eq!(store.get_block(&cccc).unwrap(), b"cccc");
+
+        store.set_admission_policy(Box::new(move |cid: &RawCid, _| *cid == cccc));
+        assert!(store.admit_dag(&blip).is_err());
+        assert!(
+            store
+                .admit_dag(&testdata::CARV1_BASIC_SECTIONS[2].cid())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_datastore_verify() {
+        let dir = fixture_dir("verify");
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        store.index().unwrap();
+        let mut verifications = store.verify();
+        verifications.sort_by(|a, b| a.path.cmp(&b.path));
+        // The CARv1 file is scanned, and its blocks are all valid
+        assert!(!verifications[0].exact);
+        let report = verifications[0].result.as_ref().unwrap();
+        assert_eq!(report.sections + report.unhashed, 8);
+        // The index of the CARv2 fixture lacks its leading bytes
+        assert!(verifications[1].exact);
+        assert!(verifications[1].result.is_err());
+
+        // Once its index is repaired, the CARv2 file is verified with exact reads
+        let index_offset = testdata::CARV2_BASIC_INDEX_OFFSET as usize;
+        let mut repaired = testdata::CARV2_BASIC[..index_offset].to_vec();
+        repaired.extend(testdata::carv2_basic_index());
+        std::fs::write(dir.join("carv2-basic.car"), &repaired).unwrap();
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        store.index().unwrap();
+        let verification = store
+            .verify()
+            .into_iter()
+            .find(|v| v.path.ends_with("carv2-basic.car"))
+            .unwrap();
+        assert!(verification.exact);
+        assert_eq!(verification.result.unwrap().indexed, 5);
+
+        // A corrupted block is reported
+        let last = index_offset - 1;
+        repaired[last] ^= 0xff;
+        std::fs::write(dir.join("carv2-basic.car"), &repaired).unwrap();
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        let verification = store
+            .verify()
+            .into_iter()
+            .find(|v| v.path.ends_with("carv2-basic.car"))
+            .unwrap();
+        assert!(verification.result.is_err());
+    }
+
+    #[test]
+    fn test_datastore_adopt_car() {
+        let dir = fixture_dir("adopt");
+        let mut store = DataStore::new();
+        let adopted = store.adopt_car(dir.join("carv1-basic.car"), None).unwrap();
+        assert_eq!(adopted.source, AdoptionSource::Scan);
+        assert_eq!((adopted.blocks, adopted.sampled), (8, 8));
+        assert!(store.adopt_car(dir.join("carv1-basic.car"), None).is_err());
+
+        // Flagged as fully indexed, the CARv2 fixture is adopted from its index, which lacks its leading bytes
+        let mut flagged = testdata::CARV2_BASIC.to_vec();
+        flagged[11] |= 1;
+        std::fs::write(dir.join("carv2-basic.car"), &flagged).unwrap();
+        assert!(store.adopt_car(dir.join("carv2-basic.car"), None).is_err());
+        assert_eq!(store.block_count(), 8);
+
+        // A corrupted block is caught by the sample
+        let index_offset = testdata::CARV2_BASIC_INDEX_OFFSET as usize;
+        let mut repaired = flagged[..index_offset].to_vec();
+        repaired.extend(testdata::carv2_basic_index());
+        repaired[index_offset - 1] ^= 0xff;
+        std::fs::write(dir.join("carv2-basic.car"), &repaired).unwrap();
+        assert!(store.adopt_car(dir.join("carv2-basic.car"), None).is_err());
+        assert_eq!(store.report().tracked_cars, 1);
+
+        repaired[index_offset - 1] ^= 0xff;
+        std::fs::write(dir.join("carv2-basic.car"), &repaired).unwrap();
+        let adopted = store.adopt_car(dir.join("carv2-basic.car"), None).unwrap();
+        assert_eq!(adopted.source, AdoptionSource::EmbeddedIndex);
+        assert_eq!(adopted.blocks, 5);
+        assert_eq!(store.block_count(), 13);
+        assert_eq!(store.roots().len(), 3);
+
+        // The payload of the CARv2 fixture, with its index as a sidecar
+        let data_offset = 51;
+        let payload = &testdata::CARV2_BASIC[data_offset..index_offset];
+        std::fs::write(dir.join("payload.car"), payload).unwrap();
+        std::fs::write(dir.join("payload.car.idx"), testdata::carv2_basic_index()).unwrap();
+        let mut store = DataStore::new();
+        let adopted = store.adopt_car(dir.join("payload.car"), None).unwrap();
+        assert_eq!(adopted.source, AdoptionSource::SidecarIndex);
+        assert_eq!(adopted.blocks, 5);
+        let last = testdata::CARV2_BASIC_SECTIONS[4].cid();
+        assert_eq!(store.get_block(&last).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn test_datastore_read_only() {
+        let dir = fixture_dir("read-only");
+        let mut store = DataStore::new();
+        store.set_read_only(true);
+        assert!(store.read_only());
+
+        // Missing or invalid directories are reported with their path
+        let missing = dir.join("missing");
+        match store.scan_directory(&missing) {
+            Err(DataStoreError::Inaccessible(path, e)) => {
+                assert_eq!(path, missing);
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let file = dir.join("carv1-basic.car");
+        let error = store.scan_directory(&file).unwrap_err();
+        assert!(error.to_string().contains("directory of CAR files"));
+
+        // A CAR file removed after its discovery is quarantined, the others are indexed
+        std::fs::write(dir.join("removed.car"), testdata::CARV1_BASIC).unwrap();
+        assert_eq!(store.scan_directory(&dir).unwrap(), 3);
+        std::fs::remove_file(dir.join("removed.car")).unwrap();
+        store.index().unwrap();
+        assert_eq!(store.block_count(), 13);
+        let quarantine = store.quarantine();
+        assert_eq!(quarantine.len(), 1);
+        assert_eq!(quarantine[0].kind, std::io::ErrorKind::NotFound);
+        assert!(
+            quarantine[0]
+                .message
+                .contains("removed since it was discovered")
+        );
+
+        // Tombstones are kept in memory only
+        let tombstones = dir.join("tombstones.txt");
+        assert_eq!(store.load_tombstones(&tombstones).unwrap(), 0);
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        assert!(store.tombstone_cid(cccc.clone()).unwrap());
+        assert!(matches!(
+            store.get_block(&cccc),
+            Err(DataStoreError::Tombstoned(_))
+        ));
+        assert!(!tombstones.exists());
+    }
+
+    /// Storage backend holding the archives in memory, keyed by path
+    #[derive(Default)]
+    struct MemoryBackend {
+        objects: HashMap<PathBuf, Vec<u8>>,
+    }
+
+    impl StorageBackend for MemoryBackend {
+        fn list_archives(
+            &self,
+            location: &Path,
+        ) -> std::result::Result<Vec<PathBuf>, (PathBuf, std::io::Error)> {
+            let mut archives: Vec<_> = self
+                .objects
+                .keys()
+                .filter(|key| key.starts_with(location) && key.extension() == Some("car".as_ref()))
+                .cloned()
+                .collect();
+            archives.sort();
+            Ok(archives)
+        }
+
+        fn resolve(&self, path: &Path) -> std::io::Result<PathBuf> {
+            Ok(path.to_path_buf())
+        }
+
+        fn open(&self, archive: &Path) -> std::io::Result<Box<dyn Archive>> {
+            let bytes = self.read_object(archive)?;
+            let bytes = bytes.ok_or(std::io::ErrorKind::NotFound)?;
+            Ok(Box::new(std::io::Cursor::new(bytes)))
+        }
+
+        fn read_object(&self, key: &Path) -> std::io::Result<Option<Vec<u8>>> {
+            Ok(self.objects.get(key).cloned())
+        }
+    }
+
+    #[test]
+    fn test_datastore_storage_backend() {
+        let mut backend = MemoryBackend::default();
+        let objects = &mut backend.objects;
+        objects.insert("bucket/a.car".into(), testdata::CARV1_BASIC.to_vec());
+        objects.insert("bucket/b.car".into(), testdata::CARV2_BASIC.to_vec());
+        objects.insert("other/c.car".into(), testdata::CARV2_BASIC.to_vec());
+        let index_offset = testdata::CARV2_BASIC_INDEX_OFFSET as usize;
+        let payload = testdata::CARV2_BASIC[51..index_offset].to_vec();
+        objects.insert("other/payload.car".into(), payload);
+        objects.insert(
+            "other/payload.car.idx".into(),
+            testdata::carv2_basic_index(),
+        );
+
+        let mut store = DataStore::new();
+        store.set_backend(Box::new(backend));
+        assert!(!store.backend().writable());
+        assert_eq!(store.scan_directory("bucket").unwrap(), 2);
+        store.index().unwrap();
+        assert_eq!(store.block_count(), 13);
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        assert_eq!(store.get_block(&cccc).unwrap(), b"cccc");
+        let report = store.verify();
+        assert_eq!(report[0].path, Path::new("bucket/a.car"));
+        assert_eq!(report[0].result.as_ref().unwrap().sections, 8);
+
+        // Sidecar indexes are read from the backend too
+        let adopted = store.adopt_car("other/payload.car", None).unwrap();
+        assert_eq!(adopted.source, AdoptionSource::SidecarIndex);
+        assert!(store.adopt_car("other/missing.car", None).is_err());
+    }
+
+    /// Content discovery recording the announced CIDs
+    #[derive(Default)]
+    struct MockDiscovery {
+        announced: Vec<RawCid>,
+    }
+
+    impl ContentDiscovery for MockDiscovery {
+        fn announce(&mut self, cids: &[RawCid]) -> std::result::Result<usize, DiscoveryError> {
+            self.announced.extend_from_slice(cids);
+            Ok(cids.len())
+        }
+
+        fn resolve(
+            &mut self,
+            _cid: &RawCid,
+        ) -> std::result::Result<Vec<crate::discovery::Provider>, DiscoveryError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn test_datastore_announce() {
+        let mut store = indexed_store("announce");
+        let mut discovery = MockDiscovery::default();
+        assert_eq!(store.announce(&mut discovery).unwrap(), 3);
+        assert_eq!(discovery.announced, store.roots());
+
+        // Only the servable roots are announced
+        let limbo = RawCid::from_hex(
+            "0171122069ea0740f9807a28f4d932c62e7c1c83be055e55072c90266ab3e79df63a365b",
+        )
+        .unwrap();
+        store.set_allowed_roots(vec![limbo.clone()]).unwrap();
+        let mut discovery = MockDiscovery::default();
+        assert_eq!(store.announce(&mut discovery).unwrap(), 1);
+        assert_eq!(discovery.announced, vec![limbo]);
+    }
+
+    #[test]
+    fn test_datastore_tombstones() {
+        let dir = fixture_dir("tombstones");
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        store.index().unwrap();
+        let tombstones = dir.join("tombstones.txt");
+        assert_eq!(store.load_tombstones(&tombstones).unwrap(), 0);
+
+        let cccc = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        assert!(store.tombstone_cid(cccc.clone()).unwrap());
+        assert!(matches!(
+            store.get_block(&cccc),
+            Err(DataStoreError::Tombstoned(_))
+        ));
+
+        // Tombstoning the CAR file hides all its blocks, the other CAR file is still served
+        let carv2_block = testdata::CARV2_BASIC_SECTIONS[3].cid();
+        assert!(store.tombstone_car(dir.join("carv1-basic.car")).unwrap());
+        let aaaa = testdata::CARV1_BASIC_SECTIONS[6].cid();
+        assert!(matches!(
+            store.get_block(&aaaa),
+            Err(DataStoreError::Tombstoned(_))
+        ));
+        assert!(store.get_block(&carv2_block).is_ok());
+        assert_eq!(store.metrics().lookups(LookupOutcome::Tombstoned), 2);
+
+        // Tombstones are persisted, and can be lifted
+        let mut reloaded = DataStore::new();
+        reloaded.scan_directory(&dir).unwrap();
+        reloaded.index().unwrap();
+        assert_eq!(reloaded.load_tombstones(&tombstones).unwrap(), 2);
+        assert!(
+            reloaded
+                .lift_tombstone_car(dir.join("carv1-basic.car"))
+                .unwrap()
+        );
+        assert_eq!(reloaded.get_block(&aaaa).unwrap(), b"aaaa");
+        assert!(reloaded.lift_tombstone_cid(&cccc).unwrap());
+        assert_eq!(reloaded.get_block(&cccc).unwrap(), b"cccc");
+        assert!(reloaded.tombstones().is_empty());
+    }
+
+    #[test]
+    fn test_datastore_shared_block_cache() {
+        let cache = BlockCache::shared(1024, crate::cache::CacheKeying::Digest);
+        let mut first = indexed_store("cache-first");
+        let mut second = indexed_store("cache-second");
+        first.set_block_cache(Some(cache.clone()));
+        second.set_block_cache(Some(cache.clone()));
+        assert_ne!(first.id(), second.id());
+
+        let aaaa = testdata::CARV1_BASIC_SECTIONS[6].cid();
+        assert_eq!(first.get_block(&aaaa).unwrap(), b"aaaa");
+        assert_eq!(first.get_block(&aaaa).unwrap(), b"aaaa");
+        assert_eq!(second.get_block(&aaaa).unwrap(), b"aaaa");
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.shared_hits, stats.misses), (2, 1, 1));
+        assert_eq!((stats.blocks, stats.bytes), (1, 4));
+
+        // The tombstones are consulted before the cache
+        second.tombstone_cid(aaaa.clone()).unwrap();
+        assert!(matches!(
+            second.get_block(&aaaa),
+            Err(DataStoreError::Tombstoned(_))
+        ));
+        assert_eq!(cache.stats().hits, 2);
+    }
+
+    #[test]
+    fn test_datastore_ingest_manifest() {
+        let dir = fixture_dir("ingest-manifest");
+        let mut reader = CarReader::from_bytes(testdata::CARV1_BASIC).unwrap();
+        let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|locsec| locsec.section)
+            .collect();
+        let mut writer = v1::CarWriter::new(vec![sections[0].cid().clone()]);
+        writer.set_record_manifest(true);
+        let path = dir.join("written.car");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let mut buf = vec![0u8; 64 * 1024];
+        for section in &sections {
+            writer.write_section(section).unwrap();
+        }
+        drain_writer(&mut writer, &mut file, &mut buf).unwrap();
+        let manifest = writer.finish_with_manifest().unwrap();
+
+        let mut store = DataStore::new();
+        assert_eq!(
+            store.ingest_manifest(&path, &manifest).unwrap(),
+            sections.len()
+        );
+        assert_eq!(store.roots(), &[sections[0].cid().clone()]);
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        assert_eq!(store.get_block(&cccc).unwrap(), b"cccc");
+        // Ingesting it again does not track the file twice
+        store.ingest_manifest(&path, &manifest).unwrap();
+        assert_eq!(store.tracked_car.len(), 1);
+    }
+
+    #[test]
+    fn test_datastore_identity_cids() {
+        let hello = RawCid::from_hex("0155000568656c6c6f").unwrap();
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        // A CAR file embedding the identity block, which must not be indexed
+        let dir = fixture_dir("identity");
+        let mut writer = v1::CarWriter::new(vec![cccc.clone()]);
+        writer.set_record_manifest(true);
+        writer
+            .write_section(&Section::new(hello.clone(), Block::new(b"hello".to_vec())))
+            .unwrap();
+        writer
+            .write_section(&Section::new(cccc.clone(), Block::new(b"cccc".to_vec())))
+            .unwrap();
+        let path = dir.join("identity.car");
+        let mut file = std::fs::File::create(&path).unwrap();
+        drain_writer(&mut writer, &mut file, &mut [0u8; 1024]).unwrap();
+        let manifest = writer.finish_with_manifest().unwrap();
+
+        let mut store = DataStore::new();
+        assert_eq!(store.ingest_manifest(&path, &manifest).unwrap(), 1);
+        assert_eq!(store.block_count(), 1);
+        // Served from the CID itself, even if denied by the serving policy
+        store.set_allowed_roots(vec![]).unwrap();
+        assert_eq!(store.get_block(&hello).unwrap(), b"hello");
+        assert!(store.car_handles.is_empty());
+        store.tombstone_cid(hello.clone()).unwrap();
+        assert!(matches!(
+            store.get_block(&hello),
+            Err(DataStoreError::Tombstoned(_))
+        ));
+    }
+
+    #[test]
+    fn test_datastore_get_blocks() {
+        let mut store = indexed_store("get-blocks");
+        let aaaa = testdata::CARV1_BASIC_SECTIONS[6].cid();
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        let v2_block = testdata::CARV2_BASIC_SECTIONS[1].cid();
+        let unknown = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let hello = RawCid::from_hex("0155000568656c6c6f").unwrap();
+        store.tombstone_cid(cccc.clone()).unwrap();
+        store.set_max_batch_size(5);
+
+        let requests = [
+            aaaa.clone(),
+            v2_block.clone(),
+            unknown,
+            hello,
+            cccc,
+            aaaa.clone(),
+        ];
+        let results = store.get_blocks(&requests);
+        assert_eq!(results.len(), requests.len());
+        assert_eq!(results[0].as_ref().unwrap(), b"aaaa");
+        assert_eq!(
+            results[1].as_ref().unwrap(),
+            &store.get_block(&v2_block).unwrap()
+        );
+        assert!(matches!(results[2], Err(DataStoreError::NotFound(_))));
+        assert_eq!(results[3].as_ref().unwrap(), b"hello");
+        assert!(matches!(results[4], Err(DataStoreError::Tombstoned(_))));
+        assert!(matches!(results[5], Err(DataStoreError::BatchLimit(5))));
+    }
 }
@@ -0,0 +1,468 @@
+//! Content discovery for navira-store
+//!
+//! Serving blocks is only useful if peers can find out who provides them. The [ContentDiscovery] trait
+//! abstracts the content routing system: the store announces the CIDs it provides, and any client (e.g. a
+//! future replication client) resolves a CID to its providers, without knowing which system is behind.
+//! Tests inject their own implementation.
+//!
+//! The available implementations, selected with a [DiscoveryConfig] (`--discovery` option):
+//! - `none` ([NoDiscovery]): nothing is announced, and nothing is ever resolved.
+//! - `http://host[:port][/path]` ([DelegatedHttp]): a [Delegated Routing V1 HTTP API](https://specs.ipfs.tech/routing/http-routing-v1/)
+//!   endpoint. Only plain HTTP is supported by the bundled transport, so public (HTTPS) routers should be
+//!   reached through a local proxy, or with another [HttpTransport].
+//! - `dht`: reserved for the Amino DHT, not implemented yet.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    str::FromStr,
+    time::Duration,
+};
+
+use navira_car::wire::cid::RawCid;
+use serde::{Deserialize, Serialize};
+
+/// Errors related to content discovery operations
+#[derive(thiserror::Error, Debug)]
+pub enum DiscoveryError {
+    /// The routing system could not be reached
+    #[error("Transport error: {0}")]
+    Transport(#[from] io::Error),
+    /// The routing system answered with an error status
+    #[error("Unexpected HTTP status: {0}")]
+    Status(u16),
+    /// The routing system answered with a malformed response
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+    /// The discovery system is not implemented (yet)
+    #[error("Unsupported discovery: {0}")]
+    Unsupported(String),
+}
+
+/// A provider of some content, as returned by the routing system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provider {
+    /// Peer ID of the provider
+    pub id: String,
+    /// Multiaddresses of the provider (may be empty, if they have to be resolved separately)
+    pub addrs: Vec<String>,
+}
+
+/// Content routing system, where the provided content is announced and looked up
+pub trait ContentDiscovery {
+    /// Announce that the given CIDs are provided by this node
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of CIDs announced
+    /// * `Err(DiscoveryError)` - The announcement failed
+    fn announce(&mut self, cids: &[RawCid]) -> Result<usize, DiscoveryError>;
+
+    /// Find the providers of the given CID
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Provider>)` - Known providers (possibly none)
+    /// * `Err(DiscoveryError)` - The lookup failed
+    fn resolve(&mut self, cid: &RawCid) -> Result<Vec<Provider>, DiscoveryError>;
+}
+
+/// No content discovery: nothing is announced, and no provider is ever found
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDiscovery;
+
+impl ContentDiscovery for NoDiscovery {
+    fn announce(&mut self, _cids: &[RawCid]) -> Result<usize, DiscoveryError> {
+        Ok(0)
+    }
+
+    fn resolve(&mut self, _cid: &RawCid) -> Result<Vec<Provider>, DiscoveryError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Response to an HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+/// Transport of the HTTP requests of [DelegatedHttp]
+pub trait HttpTransport {
+    /// Send a request with the given method, URL and (JSON) body
+    fn send(&mut self, method: &str, url: &str, body: Option<&[u8]>) -> io::Result<HttpResponse>;
+}
+
+/// Plain HTTP/1.0 transport over a TCP connection per request (`http://` URLs only)
+#[derive(Debug, Clone)]
+pub struct PlainHttpTransport {
+    /// Timeout of the connection, and of each read and write
+    pub timeout: Duration,
+}
+
+impl Default for PlainHttpTransport {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl HttpTransport for PlainHttpTransport {
+    fn send(&mut self, method: &str, url: &str, body: Option<&[u8]>) -> io::Result<HttpResponse> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_owned());
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| invalid("only http:// URLs are supported"))?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let addr = if host.contains(':') {
+            host.to_owned()
+        } else {
+            format!("{}:80", host)
+        };
+        let addr = std::net::ToSocketAddrs::to_socket_addrs(&addr)?
+            .next()
+            .ok_or_else(|| invalid("host did not resolve"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let body = body.unwrap_or_default();
+        let path = if path.is_empty() { "/" } else { path };
+        write!(
+            stream,
+            "{} {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            host,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response");
+        let header_end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(malformed)?;
+        let status_line = std::str::from_utf8(&response[..header_end])
+            .map_err(|_| malformed())?
+            .lines()
+            .next()
+            .unwrap_or_default();
+        let status = status_line
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(malformed)?;
+        Ok(HttpResponse {
+            status,
+            body: response[header_end + 4..].to_vec(),
+        })
+    }
+}
+
+/// Provider records of the Delegated Routing V1 HTTP API
+#[derive(Debug, Serialize, Deserialize)]
+struct ProvidersBody<T> {
+    #[serde(rename = "Providers", default = "Vec::new")]
+    providers: Vec<T>,
+}
+
+/// Peer record, the only schema of provider records kept by [DelegatedHttp::resolve]
+#[derive(Debug, Deserialize)]
+struct PeerRecord {
+    #[serde(rename = "Schema")]
+    schema: String,
+    #[serde(rename = "ID", default)]
+    id: Option<String>,
+    #[serde(rename = "Addrs", default)]
+    addrs: Vec<String>,
+}
+
+/// Announcement record, of the (proposed) write API of the Delegated Routing V1 HTTP API
+#[derive(Debug, Serialize)]
+struct AnnouncementRecord<'a> {
+    #[serde(rename = "Schema")]
+    schema: &'static str,
+    #[serde(rename = "Payload")]
+    payload: AnnouncementPayload<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnnouncementPayload<'a> {
+    #[serde(rename = "CID")]
+    cids: Vec<String>,
+    #[serde(rename = "ID")]
+    id: &'a str,
+    #[serde(rename = "Addrs")]
+    addrs: &'a [String],
+    #[serde(rename = "Protocols")]
+    protocols: [&'static str; 1],
+}
+
+/// Content discovery through a Delegated Routing V1 HTTP API endpoint
+///
+/// Providers are looked up with `GET /routing/v1/providers/{cid}`, keeping the `peer` records only.
+/// Announcements are sent with `PUT /routing/v1/providers`, following the proposed write API: they are
+/// unsigned, so only routers trusting this node (e.g. a private one) accept them. Announcing requires the
+/// peer identity of the node (see [DelegatedHttp::with_identity]).
+#[derive(Debug, Clone)]
+pub struct DelegatedHttp<T: HttpTransport = PlainHttpTransport> {
+    endpoint: String,
+    transport: T,
+    identity: Option<(String, Vec<String>)>,
+}
+
+impl DelegatedHttp {
+    /// Create a delegated routing client for the given endpoint, over plain HTTP
+    pub fn new(endpoint: &str) -> Self {
+        Self::with_transport(endpoint, PlainHttpTransport::default())
+    }
+}
+
+impl<T: HttpTransport> DelegatedHttp<T> {
+    /// Create a delegated routing client for the given endpoint, over the given transport
+    pub fn with_transport(endpoint: &str, transport: T) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            transport,
+            identity: None,
+        }
+    }
+
+    /// Set the peer ID and multiaddresses announced as the provider of the content
+    pub fn with_identity(mut self, id: String, addrs: Vec<String>) -> Self {
+        self.identity = Some((id, addrs));
+        self
+    }
+
+    /// Endpoint of the router, without trailing slash
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+}
+
+impl<T: HttpTransport> ContentDiscovery for DelegatedHttp<T> {
+    fn announce(&mut self, cids: &[RawCid]) -> Result<usize, DiscoveryError> {
+        let Some((id, addrs)) = &self.identity else {
+            return Err(DiscoveryError::Unsupported(
+                "announcing requires a peer identity".to_owned(),
+            ));
+        };
+        if cids.is_empty() {
+            return Ok(0);
+        }
+        let body = ProvidersBody {
+            providers: vec![AnnouncementRecord {
+                schema: "announcement",
+                payload: AnnouncementPayload {
+                    cids: cids.iter().map(RawCid::to_cid_string).collect(),
+                    id,
+                    addrs,
+                    protocols: ["transport-bitswap"],
+                },
+            }],
+        };
+        let body = serde_json::to_vec(&body)
+            .map_err(|e| DiscoveryError::InvalidResponse(e.to_string()))?;
+        let url = format!("{}/routing/v1/providers", self.endpoint);
+        let response = self.transport.send("PUT", &url, Some(&body))?;
+        match response.status {
+            200..=299 => Ok(cids.len()),
+            status => Err(DiscoveryError::Status(status)),
+        }
+    }
+
+    fn resolve(&mut self, cid: &RawCid) -> Result<Vec<Provider>, DiscoveryError> {
+        let url = format!(
+            "{}/routing/v1/providers/{}",
+            self.endpoint,
+            cid.to_cid_string()
+        );
+        let response = self.transport.send("GET", &url, None)?;
+        match response.status {
+            200 => {}
+            404 => return Ok(Vec::new()),
+            status => return Err(DiscoveryError::Status(status)),
+        }
+        let body: ProvidersBody<PeerRecord> = serde_json::from_slice(&response.body)
+            .map_err(|e| DiscoveryError::InvalidResponse(e.to_string()))?;
+        Ok(body
+            .providers
+            .into_iter()
+            .filter(|record| record.schema == "peer")
+            .filter_map(|record| {
+                Some(Provider {
+                    id: record.id?,
+                    addrs: record.addrs,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Content discovery selected by the configuration
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DiscoveryConfig {
+    /// No content discovery ([NoDiscovery])
+    #[default]
+    None,
+    /// Delegated routing, through the given HTTP endpoint ([DelegatedHttp])
+    DelegatedHttp(String),
+    /// Amino DHT (not implemented yet)
+    Dht,
+}
+
+impl FromStr for DiscoveryConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(DiscoveryConfig::None),
+            "dht" => Ok(DiscoveryConfig::Dht),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(DiscoveryConfig::DelegatedHttp(url.to_owned()))
+            }
+            _ => Err(format!(
+                "invalid discovery {:?}, expected none, dht or an http:// endpoint",
+                s
+            )),
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    /// Build the selected content discovery
+    ///
+    /// # Returns
+    /// * `Ok(Box<dyn ContentDiscovery>)` - The content discovery, ready to be used
+    /// * `Err(DiscoveryError::Unsupported)` - The selected discovery is not available in this build
+    pub fn build(&self) -> Result<Box<dyn ContentDiscovery>, DiscoveryError> {
+        match self {
+            DiscoveryConfig::None => Ok(Box::new(NoDiscovery)),
+            DiscoveryConfig::DelegatedHttp(url) if url.starts_with("https://") => Err(
+                DiscoveryError::Unsupported("HTTPS endpoints need a local proxy".to_owned()),
+            ),
+            DiscoveryConfig::DelegatedHttp(url) => Ok(Box::new(DelegatedHttp::new(url))),
+            DiscoveryConfig::Dht => Err(DiscoveryError::Unsupported(
+                "the DHT is not implemented yet".to_owned(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use navira_car::testdata;
+
+    /// Transport answering with canned responses, and recording the requests
+    #[derive(Default)]
+    struct MockTransport {
+        responses: Vec<HttpResponse>,
+        requests: Vec<(String, String, Option<Vec<u8>>)>,
+    }
+
+    impl HttpTransport for MockTransport {
+        fn send(
+            &mut self,
+            method: &str,
+            url: &str,
+            body: Option<&[u8]>,
+        ) -> io::Result<HttpResponse> {
+            self.requests
+                .push((method.to_owned(), url.to_owned(), body.map(<[u8]>::to_vec)));
+            Ok(self.responses.remove(0))
+        }
+    }
+
+    fn response(status: u16, body: &str) -> HttpResponse {
+        HttpResponse {
+            status,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_discovery_config() {
+        assert_eq!("none".parse(), Ok(DiscoveryConfig::None));
+        assert_eq!(
+            "http://127.0.0.1:8190/".parse(),
+            Ok(DiscoveryConfig::DelegatedHttp(
+                "http://127.0.0.1:8190/".to_owned()
+            ))
+        );
+        assert!("ftp://example.com".parse::<DiscoveryConfig>().is_err());
+        assert!(DiscoveryConfig::None.build().is_ok());
+        assert!(matches!(
+            DiscoveryConfig::Dht.build(),
+            Err(DiscoveryError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_delegated_http_resolve() {
+        let cid = &testdata::CARV1_BASIC_SECTIONS[6].cid();
+        let transport = MockTransport {
+            responses: vec![
+                response(
+                    200,
+                    r#"{"Providers":[
+                        {"Schema":"peer","ID":"12D3KooWA","Addrs":["/ip4/192.0.2.1/udp/4001/quic-v1"],"Protocols":["transport-bitswap"]},
+                        {"Schema":"unknown","ID":"12D3KooWB"}
+                    ]}"#,
+                ),
+                response(404, ""),
+                response(500, ""),
+            ],
+            ..Default::default()
+        };
+        let mut discovery = DelegatedHttp::with_transport("http://router/", transport);
+        assert_eq!(
+            discovery.resolve(cid).unwrap(),
+            vec![Provider {
+                id: "12D3KooWA".to_owned(),
+                addrs: vec!["/ip4/192.0.2.1/udp/4001/quic-v1".to_owned()],
+            }]
+        );
+        assert!(discovery.resolve(cid).unwrap().is_empty());
+        assert!(matches!(
+            discovery.resolve(cid),
+            Err(DiscoveryError::Status(500))
+        ));
+        let (method, url, _) = &discovery.transport.requests[0];
+        assert_eq!(method, "GET");
+        assert_eq!(
+            url,
+            &format!("http://router/routing/v1/providers/{}", cid.to_cid_string())
+        );
+    }
+
+    #[test]
+    fn test_delegated_http_announce() {
+        let cids = [testdata::CARV1_BASIC_SECTIONS[6].cid()];
+        let transport = MockTransport {
+            responses: vec![response(200, "")],
+            ..Default::default()
+        };
+        let mut discovery = DelegatedHttp::with_transport("http://router", transport);
+        assert!(matches!(
+            discovery.announce(&cids),
+            Err(DiscoveryError::Unsupported(_))
+        ));
+        let mut discovery = discovery.with_identity("12D3KooWA".to_owned(), vec![]);
+        assert_eq!(discovery.announce(&cids).unwrap(), 1);
+        let (method, url, body) = &discovery.transport.requests[0];
+        assert_eq!(method, "PUT");
+        assert_eq!(url, "http://router/routing/v1/providers");
+        let body: serde_json::Value = serde_json::from_slice(body.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            body["Providers"][0]["Payload"]["CID"][0],
+            cids[0].to_cid_string()
+        );
+    }
+}
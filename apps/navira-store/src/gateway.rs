@@ -0,0 +1,431 @@
+//! Block gateway handlers for navira-store
+//!
+//! Not every deployment speaks libp2p: a DataStore can also act as a block provider behind a standard
+//! HTTP reverse proxy, using the raw block subset of the [trustless gateway specification](https://specs.ipfs.tech/http-gateways/trustless-gateway/):
+//! `GET /ipfs/{cid}?format=raw` (or `Accept: application/vnd.ipld.raw`) returns the verifiable bytes of the block.
+//! Byte ranges of UnixFS files are also served, as CAR responses scoped to the entity:
+//! `GET /ipfs/{cid}?format=car&dag-scope=entity&entity-bytes=from:to` (or `Accept: application/vnd.ipld.car`)
+//! returns the blocks needed to verify and read the range (see [navira_car::ipld::entity]).
+//!
+//! This module does not embed an HTTP server. It implements the gateway semantics as plain functions over
+//! [GatewayRequest] and [GatewayResponse], so that they can be plugged into any HTTP stack (or tested without one).
+//! Blocks are served through [DataStore::get_block], so the tombstones and the serving policy apply.
+//! CAR requests are first checked with [DataStore::admit_dag], so an admission policy can turn them down
+//! before any block is read.
+//!
+//! ## Examples
+//! ```no_run
+//! use navira_store::datastore::DataStore;
+//! use navira_store::gateway::{GatewayRequest, handle};
+//!
+//! let mut store = DataStore::new();
+//! store.scan_directory("/srv/cars").unwrap();
+//! store.index().unwrap();
+//!
+//! let request = GatewayRequest::new("GET", "/ipfs/bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq?format=raw");
+//! let response = handle(&mut store, &request);
+//! assert_eq!(response.status, 200);
+//! ```
+
+use navira_car::ipld::entity::{EntityBytes, EntityBytesError, write_entity_bytes_car};
+use navira_car::wire::{cid::RawCid, multibase};
+
+use crate::datastore::{DataStore, DataStoreError};
+
+/// Media type of raw blocks
+pub const RAW_BLOCK_MEDIA_TYPE: &str = "application/vnd.ipld.raw";
+
+/// Media type of CAR responses, as written by [write_entity_bytes_car]
+pub const CAR_MEDIA_TYPE: &str = "application/vnd.ipld.car";
+
+/// Cache-Control of the served blocks, immutable as they are content-addressed
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=29030400, immutable";
+
+/// A gateway request, as extracted from the HTTP request by the embedding server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayRequest<'a> {
+    /// HTTP method (`GET`, `HEAD`, …)
+    pub method: &'a str,
+    /// Request target: the path, with the query string if any (`/ipfs/{cid}?format=raw`)
+    pub target: &'a str,
+    /// Value of the `Accept` header, if any
+    pub accept: Option<&'a str>,
+}
+
+impl<'a> GatewayRequest<'a> {
+    /// Create a request without `Accept` header
+    pub fn new(method: &'a str, target: &'a str) -> Self {
+        Self {
+            method,
+            target,
+            accept: None,
+        }
+    }
+
+    /// Set the value of the `Accept` header
+    pub fn with_accept(mut self, accept: &'a str) -> Self {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Value of a query parameter, if present
+    fn query_param(&self, name: &str) -> Option<&'a str> {
+        let (_, query) = self.target.split_once('?')?;
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Path of the request, without the query string
+    fn path(&self) -> &'a str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// Response format asked with `?format=` or the `Accept` header, if supported
+    fn response_format(&self) -> Option<ResponseFormat> {
+        match self.query_param("format") {
+            Some("raw") => Some(ResponseFormat::RawBlock),
+            Some("car") => Some(ResponseFormat::Car),
+            Some(_) => None,
+            None => self.accept.and_then(|accept| {
+                accept.split(',').find_map(|media| {
+                    match media.split(';').next().unwrap_or_default().trim() {
+                        RAW_BLOCK_MEDIA_TYPE => Some(ResponseFormat::RawBlock),
+                        CAR_MEDIA_TYPE => Some(ResponseFormat::Car),
+                        _ => None,
+                    }
+                })
+            }),
+        }
+    }
+}
+
+/// Response formats supported by the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    /// The raw bytes of a block
+    RawBlock,
+    /// A CAR stream of the blocks of an entity (a byte range of a UnixFS file)
+    Car,
+}
+
+/// A gateway response, to be written back by the embedding server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response headers, `Content-Length` included
+    pub headers: Vec<(&'static str, String)>,
+    /// Response body (empty for `HEAD` requests)
+    pub body: Vec<u8>,
+}
+
+impl GatewayResponse {
+    /// Build a plain text error response
+    fn error(status: u16, message: &str) -> Self {
+        let body = format!("{}\n", message).into_bytes();
+        Self {
+            status,
+            headers: vec![
+                ("Content-Type", "text/plain; charset=utf-8".to_owned()),
+                ("Content-Length", body.len().to_string()),
+            ],
+            body,
+        }
+    }
+
+    /// Get the value of a header (names are compared case-insensitively)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a CID from its canonical string (`bafy…`, `Qm…`) or its hexadecimal form
+pub fn parse_cid(s: &str) -> Option<RawCid> {
+    let bytes = if s.len() == 46 && s.starts_with("Qm") {
+        multibase::decode_base58btc(s)?
+    } else if let Some(base32) = s.strip_prefix(multibase::BASE32_LOWER_PREFIX) {
+        multibase::decode_base32_lower(base32)?
+    } else {
+        RawCid::from_hex(s).ok()?.bytes().to_vec()
+    };
+    let (cid, len) = RawCid::try_read_bytes(&bytes).ok()?;
+    (len == bytes.len()).then_some(cid)
+}
+
+/// Handle a gateway request against the DataStore
+///
+/// Only `GET` and `HEAD` requests on `/ipfs/{cid}` asking for a raw block, or for an entity-scoped CAR, are
+/// supported, the other requests are answered with the relevant error status:
+/// - `400 Bad Request` for invalid CIDs, paths below a CID, invalid `entity-bytes` ranges, and CAR requests
+///   on something else than a UnixFS file.
+/// - `403 Forbidden` for blocks denied by the serving policy, and CAR requests turned down by the admission policy.
+/// - `404 Not Found` for unknown routes and blocks.
+/// - `405 Method Not Allowed` for methods other than `GET` and `HEAD`.
+/// - `406 Not Acceptable` when another response format than raw blocks and CARs is requested, or another
+///   `dag-scope` than `entity`.
+/// - `410 Gone` for tombstoned blocks.
+/// - `500 Internal Server Error` when the block could not be read.
+pub fn handle(store: &mut DataStore, request: &GatewayRequest) -> GatewayResponse {
+    let head = match request.method {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            let mut response = GatewayResponse::error(405, "Method not allowed");
+            response.headers.push(("Allow", "GET, HEAD".to_owned()));
+            return response;
+        }
+    };
+    let Some(rest) = request.path().strip_prefix("/ipfs/") else {
+        return GatewayResponse::error(404, "Not found");
+    };
+    let (cid_str, subpath) = rest.split_once('/').unwrap_or((rest, ""));
+    let Some(cid) = parse_cid(cid_str) else {
+        return GatewayResponse::error(400, "Invalid CID");
+    };
+    if !subpath.is_empty() {
+        return GatewayResponse::error(400, "Paths are not supported");
+    }
+    match request.response_format() {
+        Some(ResponseFormat::RawBlock) => {}
+        Some(ResponseFormat::Car) => return handle_entity_car(store, request, &cid, head),
+        None => {
+            return GatewayResponse::error(
+                406,
+                "Only raw blocks (format=raw) and CARs (format=car) are supported",
+            );
+        }
+    }
+
+    match store.get_block(&cid) {
+        Ok(data) => {
+            let cid_str = cid.to_cid_string();
+            GatewayResponse {
+                status: 200,
+                headers: vec![
+                    ("Content-Type", RAW_BLOCK_MEDIA_TYPE.to_owned()),
+                    ("Content-Length", data.len().to_string()),
+                    ("Cache-Control", IMMUTABLE_CACHE_CONTROL.to_owned()),
+                    ("Etag", format!("\"{}.raw\"", cid_str)),
+                    ("X-Content-Type-Options", "nosniff".to_owned()),
+                    ("X-Ipfs-Path", format!("/ipfs/{}", cid_str)),
+                    ("Vary", "Accept".to_owned()),
+                ],
+                body: if head { Vec::new() } else { data },
+            }
+        }
+        Err(e) => block_error(&e),
+    }
+}
+
+/// Map a DataStore error to the error response of a block
+fn block_error(error: &DataStoreError) -> GatewayResponse {
+    match error {
+        DataStoreError::NotFound(_) => GatewayResponse::error(404, "Block not found"),
+        DataStoreError::Denied(_) => GatewayResponse::error(403, "Block not servable"),
+        DataStoreError::Tombstoned(_) => GatewayResponse::error(410, "Block tombstoned"),
+        DataStoreError::NotAdmitted(_) => GatewayResponse::error(403, "DAG request not admitted"),
+        DataStoreError::BatchLimit(_) => GatewayResponse::error(429, "Too many blocks requested"),
+        DataStoreError::Io(_) | DataStoreError::Inaccessible(_, _) => {
+            GatewayResponse::error(500, "Block could not be read")
+        }
+    }
+}
+
+/// Answer a CAR request, with the blocks of a byte range of a UnixFS file
+fn handle_entity_car(
+    store: &mut DataStore,
+    request: &GatewayRequest,
+    cid: &RawCid,
+    head: bool,
+) -> GatewayResponse {
+    if request
+        .query_param("dag-scope")
+        .is_some_and(|scope| scope != "entity")
+    {
+        return GatewayResponse::error(406, "Only dag-scope=entity is supported");
+    }
+    let range = match request.query_param("entity-bytes") {
+        Some(range) => match range.parse() {
+            Ok(range) => range,
+            Err(_) => return GatewayResponse::error(400, "Invalid entity-bytes range"),
+        },
+        None => EntityBytes::ALL,
+    };
+    if let Err(e) = store.admit_dag(cid) {
+        return block_error(&e);
+    }
+
+    let mut source = |cid: &RawCid| match store.get_block(cid) {
+        Ok(data) => Ok(Some(data)),
+        Err(DataStoreError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let mut car = Vec::new();
+    match write_entity_bytes_car(&mut source, cid, &range, &mut car) {
+        Ok(_) => {
+            let cid_str = cid.to_cid_string();
+            GatewayResponse {
+                status: 200,
+                headers: vec![
+                    (
+                        "Content-Type",
+                        format!("{}; version=1; order=dfs; dups=y", CAR_MEDIA_TYPE),
+                    ),
+                    ("Content-Length", car.len().to_string()),
+                    ("Cache-Control", IMMUTABLE_CACHE_CONTROL.to_owned()),
+                    (
+                        "Etag",
+                        format!(
+                            "\"{}.car.entity.{}:{}\"",
+                            cid_str,
+                            range.from,
+                            range.to.map_or("*".to_owned(), |to| to.to_string())
+                        ),
+                    ),
+                    ("X-Content-Type-Options", "nosniff".to_owned()),
+                    ("X-Ipfs-Path", format!("/ipfs/{}", cid_str)),
+                    ("Vary", "Accept".to_owned()),
+                ],
+                body: if head { Vec::new() } else { car },
+            }
+        }
+        Err(EntityBytesError::Source(e)) => block_error(&e),
+        Err(EntityBytesError::BlockNotFound(_)) => GatewayResponse::error(404, "Block not found"),
+        Err(
+            EntityBytesError::NotAFile(_)
+            | EntityBytesError::InvalidDagPb(_)
+            | EntityBytesError::InvalidUnixFs(_),
+        ) => GatewayResponse::error(400, "Not a UnixFS file"),
+        Err(EntityBytesError::Io(_) | EntityBytesError::Writer(_)) => {
+            GatewayResponse::error(500, "CAR could not be written")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use navira_car::{CarReader, testdata};
+
+    fn store(name: &str) -> DataStore {
+        let dir = std::env::temp_dir().join(format!(
+            "navira-store-gateway-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("carv1-basic.car"), testdata::CARV1_BASIC).unwrap();
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        store.index().unwrap();
+        store
+    }
+
+    #[test]
+    fn test_gateway_raw_block() {
+        let mut store = store("raw-block");
+        // Raw block "aaaa"
+        let cid = "bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq";
+        let target = format!("/ipfs/{}?format=raw", cid);
+        let response = handle(&mut store, &GatewayRequest::new("GET", &target));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"aaaa");
+        assert_eq!(response.header("content-type"), Some(RAW_BLOCK_MEDIA_TYPE));
+        assert_eq!(response.header("Content-Length"), Some("4"));
+
+        let target = format!("/ipfs/{}", cid);
+        let request = GatewayRequest::new("HEAD", &target)
+            .with_accept("text/html, application/vnd.ipld.raw;q=0.9");
+        let response = handle(&mut store, &request);
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+
+        // CIDv0 dag-pb block
+        let target = format!(
+            "/ipfs/{}?format=raw",
+            testdata::CARV1_BASIC_SECTIONS[1].cid().to_cid_string()
+        );
+        let response = handle(&mut store, &GatewayRequest::new("GET", &target));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.len(), 97);
+    }
+
+    #[test]
+    fn test_gateway_entity_bytes() {
+        let mut store = store("entity-bytes");
+        // Raw block "aaaa", a single-block file
+        let cid = "bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq";
+        let target = format!("/ipfs/{}?dag-scope=entity&entity-bytes=1:2", cid);
+        let request = GatewayRequest::new("GET", &target).with_accept(CAR_MEDIA_TYPE);
+        let response = handle(&mut store, &request);
+        assert_eq!(response.status, 200);
+        assert!(
+            response
+                .header("Content-Type")
+                .unwrap()
+                .starts_with(CAR_MEDIA_TYPE)
+        );
+        let mut reader = CarReader::from_bytes(&response.body).unwrap();
+        assert_eq!(
+            reader.header().unwrap().0.roots()[0].to_raw_cid(),
+            &parse_cid(cid).unwrap()
+        );
+        assert_eq!(reader.read_section().unwrap().block().data(), b"aaaa");
+        assert!(reader.read_section().is_err());
+    }
+
+    #[test]
+    fn test_gateway_errors() {
+        let mut store = store("errors");
+        let cid = "bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq";
+        let status = |store: &mut DataStore, method: &str, target: &str| {
+            handle(store, &GatewayRequest::new(method, target)).status
+        };
+        assert_eq!(status(&mut store, "POST", "/ipfs/x"), 405);
+        assert_eq!(status(&mut store, "GET", "/ipns/example.org"), 404);
+        assert_eq!(status(&mut store, "GET", "/ipfs/notacid?format=raw"), 400);
+        let target = format!("/ipfs/{}/file?format=raw", cid);
+        assert_eq!(status(&mut store, "GET", &target), 400);
+        let target = format!("/ipfs/{}", cid);
+        assert_eq!(status(&mut store, "GET", &target), 406);
+        let target = format!("/ipfs/{}?format=car&dag-scope=all", cid);
+        assert_eq!(status(&mut store, "GET", &target), 406);
+        let target = format!("/ipfs/{}?format=car&entity-bytes=5:2", cid);
+        assert_eq!(status(&mut store, "GET", &target), 400);
+        // The dag-cbor root of the fixture is not a UnixFS file
+        let target = format!(
+            "/ipfs/{}?format=car",
+            testdata::CARV1_BASIC_SECTIONS[0].cid().to_cid_string()
+        );
+        assert_eq!(status(&mut store, "GET", &target), 400);
+        let unknown = format!(
+            "/ipfs/b{}?format=raw",
+            multibase::encode_base32_lower(&[&[1, 0x55, 0x12, 0x20][..], &[0; 32]].concat())
+        );
+        assert_eq!(status(&mut store, "GET", &unknown), 404);
+        // Identity CIDs are always available
+        let identity = format!(
+            "/ipfs/b{}?format=raw",
+            multibase::encode_base32_lower(&[1, 0x55, 0, 1, 0])
+        );
+        assert_eq!(status(&mut store, "GET", &identity), 200);
+
+        // Only the CAR requests are subject to the admission policy
+        store.set_admission_policy(Box::new(crate::policy::AdmitRoots));
+        let target = format!("/ipfs/{}?format=car", cid);
+        assert_eq!(status(&mut store, "GET", &target), 403);
+        let target = format!("/ipfs/{}?format=raw", cid);
+        assert_eq!(status(&mut store, "GET", &target), 200);
+
+        store.tombstone_cid(parse_cid(cid).unwrap()).unwrap();
+        let target = format!("/ipfs/{}?format=raw", cid);
+        assert_eq!(status(&mut store, "GET", &target), 410);
+    }
+}
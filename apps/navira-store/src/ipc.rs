@@ -0,0 +1,196 @@
+//! Unix socket protocol handlers of navira-store
+//!
+//! Local clients (gateways, indexers, …) talk to the DataStore over a Unix socket, with the framing of
+//! [navira_ipc]: each request is answered by a streamed response, as data frames followed by a status trailer.
+//! Blocks and CAR exports are therefore streamed without buffering the whole payload, whatever their size.
+//!
+//! Blocks are served through [DataStore::get_block] and exported with [DataStore::export_car_v1], so the
+//! tombstones and the serving policy apply. The report request is the admin endpoint of the store: it returns
+//! the rendered [StoreReport](crate::datastore::StoreReport), quarantined CAR files included.
+
+use std::io::{Read, Write};
+
+use navira_ipc::{IpcError, Request, ResponseWriter, Status, Trailer, read_request};
+use tracing::{debug, warn};
+
+use crate::datastore::{DataStore, DataStoreError};
+
+/// Map a DataStore error to the trailer of the response
+fn error_trailer(error: &DataStoreError) -> Trailer {
+    let status = match error {
+        DataStoreError::NotFound(_) => Status::NotFound,
+        DataStoreError::Denied(_) | DataStoreError::NotAdmitted(_) => Status::Denied,
+        DataStoreError::Tombstoned(_) => Status::Tombstoned,
+        DataStoreError::BatchLimit(_) => Status::BadRequest,
+        DataStoreError::Io(_) | DataStoreError::Inaccessible(_, _) => Status::Error,
+    };
+    Trailer::new(status, error.to_string())
+}
+
+/// Answer a request, streaming the response to the client
+///
+/// Errors of the DataStore are reported to the client in the trailer, only the I/O errors on the stream
+/// are returned.
+pub fn handle_request<W: Write>(
+    store: &mut DataStore,
+    request: &Request,
+    stream: &mut W,
+) -> std::io::Result<()> {
+    let mut response = ResponseWriter::new(stream);
+    let result = match request {
+        Request::GetBlock(cid) => store
+            .get_block(cid)
+            .and_then(|data| Ok(response.write_all(&data)?)),
+        Request::ExportCar => store.export_car_v1(&mut response, |_| {}).map(|progress| {
+            debug!(
+                "Exported {} blocks ({} bytes) over IPC",
+                progress.blocks, progress.bytes
+            );
+        }),
+        Request::Report => response
+            .write_all(store.report().render().as_bytes())
+            .map_err(Into::into),
+    };
+    let trailer = match result {
+        Ok(()) => Trailer::ok(),
+        Err(e) => error_trailer(&e),
+    };
+    response.finish(&trailer)?;
+    Ok(())
+}
+
+/// Serve the requests of a connected client, until it closes the stream
+///
+/// Malformed requests are answered with a [Status::BadRequest] trailer, then the connection is closed, as the
+/// framing can no longer be trusted.
+pub fn serve<S: Read + Write>(store: &mut DataStore, stream: &mut S) -> std::io::Result<()> {
+    loop {
+        match read_request(stream) {
+            Ok(Some(request)) => handle_request(store, &request, stream)?,
+            Ok(None) => return Ok(()),
+            Err(IpcError::Io(e)) => return Err(e),
+            Err(e) => {
+                warn!("Closing IPC connection after an invalid request: {}", e);
+                ResponseWriter::new(&mut *stream)
+                    .finish(&Trailer::new(Status::BadRequest, e.to_string()))?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use navira_car::{CarReader, testdata};
+    use navira_ipc::{Client, Frame, FrameKind};
+
+    /// Client and server sides of an in-memory connection
+    struct Loopback {
+        requests: std::io::Cursor<Vec<u8>>,
+        responses: Vec<u8>,
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.requests.read(buf)
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.responses.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run the requests against the DataStore, and return the raw responses
+    fn exchange(store: &mut DataStore, requests: &[Request]) -> Vec<u8> {
+        let mut wire = Vec::new();
+        for request in requests {
+            Frame::new(FrameKind::Request, request.encode())
+                .encode(&mut wire)
+                .unwrap();
+        }
+        let mut connection = Loopback {
+            requests: std::io::Cursor::new(wire),
+            responses: Vec::new(),
+        };
+        serve(store, &mut connection).unwrap();
+        connection.responses
+    }
+
+    fn store(name: &str) -> DataStore {
+        let dir =
+            std::env::temp_dir().join(format!("navira-store-ipc-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("carv1-basic.car"), testdata::CARV1_BASIC).unwrap();
+        let mut store = DataStore::new();
+        store.scan_directory(&dir).unwrap();
+        store.index().unwrap();
+        store
+    }
+
+    #[test]
+    fn test_ipc_get_block_and_export() {
+        let mut store = store("get-block");
+        let aaaa = testdata::CARV1_BASIC_SECTIONS[6].cid();
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        store.tombstone_cid(cccc.clone()).unwrap();
+        let responses = exchange(
+            &mut store,
+            &[
+                Request::GetBlock(aaaa.clone()),
+                Request::GetBlock(cccc.clone()),
+                Request::ExportCar,
+                Request::Report,
+            ],
+        );
+
+        // Replay the responses through a client, as if they came from the socket
+        let mut client = Client::new(Loopback {
+            requests: std::io::Cursor::new(responses),
+            responses: Vec::new(),
+        });
+        assert_eq!(client.get_block(&aaaa).unwrap(), b"aaaa");
+        assert!(matches!(
+            client.get_block(&cccc),
+            Err(IpcError::Failed {
+                status: Status::Tombstoned,
+                ..
+            })
+        ));
+        let mut car = Vec::new();
+        client.export_car(&mut car).unwrap();
+        let mut reader = CarReader::from_bytes(&car).unwrap();
+        let mut sections = 0;
+        while let Ok(section) = reader.read_section() {
+            assert_ne!(section.cid(), &cccc);
+            sections += 1;
+        }
+        assert_eq!(sections, testdata::CARV1_BASIC_SECTIONS.len() - 1);
+        assert!(client.report().unwrap().starts_with("tracked_cars 1\n"));
+    }
+
+    #[test]
+    fn test_ipc_bad_request() {
+        let mut store = store("bad-request");
+        let mut wire = Vec::new();
+        Frame::new(FrameKind::Request, vec![0x42])
+            .encode(&mut wire)
+            .unwrap();
+        let mut connection = Loopback {
+            requests: std::io::Cursor::new(wire),
+            responses: Vec::new(),
+        };
+        serve(&mut store, &mut connection).unwrap();
+        let trailer = navira_ipc::ResponseReader::new(connection.responses.as_slice())
+            .finish()
+            .unwrap();
+        assert_eq!(trailer.status, Status::BadRequest);
+    }
+}
@@ -1 +1,13 @@
+pub mod backend;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod cache;
 pub mod datastore;
+pub mod discovery;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+pub mod ipc;
+pub mod metrics;
+pub mod policy;
+pub mod scheduler;
+pub mod tombstone;
@@ -1,7 +1,12 @@
 use clap::Parser;
+use navira_car::inspect::{ExportFormat, SectionExporter, read_appendix};
+use navira_car::wire::cid::RawCid;
+use navira_store::cache::{BlockCache, CacheKeying};
 use navira_store::datastore::DataStore;
-use std::path::PathBuf;
-use tracing::info;
+use navira_store::discovery::DiscoveryConfig;
+use navira_store::ipc;
+use std::{path::PathBuf, time::Duration};
+use tracing::{info, warn};
 
 /// `navira-store` serves your static content over /ipfs/bitswap
 #[derive(Parser, Debug)]
@@ -27,30 +32,239 @@ struct Args {
     /// Important: UDP socket is disabled when a Unix socket is provided
     #[arg(short, long, default_value = "0.0.0.0")]
     address: String,
+
+    /// Root CID (hex) allowed to be served, can be repeated
+    /// If provided, only the blocks reachable from the allowed roots are served
+    /// Default: every indexed block is served
+    #[arg(long = "allow-root", value_parser = parse_cid)]
+    allow_roots: Vec<RawCid>,
+
+    /// File where the tombstoned CIDs and CAR files are persisted (created if missing)
+    /// Tombstoned blocks are never served, while their CAR files are kept untouched
+    /// Default: tombstones are kept in memory only
+    #[arg(long, value_name = "PATH")]
+    tombstones: Option<PathBuf>,
+
+    /// CID (hex) to tombstone, can be repeated
+    /// The tombstone is persisted in the tombstones file, if any
+    #[arg(long = "tombstone", value_parser = parse_cid)]
+    tombstone_cids: Vec<RawCid>,
+
+    /// CAR file to tombstone (all its blocks), can be repeated
+    /// The tombstone is persisted in the tombstones file, if any
+    #[arg(long = "tombstone-car", value_name = "PATH")]
+    tombstone_cars: Vec<PathBuf>,
+
+    /// Size (in bytes) of the in-memory block cache
+    /// If not provided, the block cache is disabled
+    #[arg(long, value_name = "BYTES")]
+    block_cache_size: Option<usize>,
+
+    /// Latency threshold (in milliseconds) above which block lookups are logged as slow
+    /// If not provided, the slow-query log is disabled
+    #[arg(long)]
+    slow_query_ms: Option<u64>,
+
+    /// Verify every block of the tracked CAR files against its CID, exiting with an error if any is invalid
+    /// Indexed CARv2 files are read sequentially with exact reads planned from their index
+    #[arg(long)]
+    verify: bool,
+
+    /// Export the listing of the indexed blocks to stdout (csv or ndjson), then exit
+    #[arg(long, value_name = "FORMAT")]
+    export_index: Option<ExportFormat>,
+
+    /// Display the appendix metadata (producer, creation time, contents) of a CAR file, then exit
+    #[arg(long, value_name = "PATH")]
+    show_appendix: Option<PathBuf>,
+
+    /// Export all the indexed blocks into a single CARv2 file (with a full index), then exit
+    #[arg(long, value_name = "PATH")]
+    export_car: Option<PathBuf>,
+
+    /// Never write to the disk: the tombstones are kept in memory only (the tombstones file is only read)
+    /// Suits datastores on read-only mounts, or owned by another user
+    #[arg(long)]
+    read_only: bool,
+
+    /// Content discovery where the servable roots are announced: none, dht, or a delegated routing endpoint (http://...)
+    /// Default: none
+    #[arg(long, value_name = "DISCOVERY", default_value = "none")]
+    discovery: DiscoveryConfig,
+}
+
+fn parse_cid(s: &str) -> Result<RawCid, String> {
+    RawCid::from_hex(s).map_err(|e| format!("invalid CID {:?}: {}", s, e))
 }
 
 fn main() {
     let args = Args::parse();
     setup_logging();
 
+    if let Some(path) = &args.show_appendix {
+        let appendix = std::fs::File::open(path)
+            .map_err(Into::into)
+            .and_then(read_appendix);
+        match appendix {
+            Ok(Some((cid, appendix))) => print!("appendix: {}\n{}", cid.to_cid_string(), appendix),
+            Ok(None) => println!("No appendix in {:?}", path),
+            Err(e) => {
+                eprintln!("Error reading the appendix of {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     info!("Datastore path: {:?}", args.datastore);
-    if let Some(socket_path) = args.socket {
+    if let Some(socket_path) = &args.socket {
         info!("Listening on Unix socket: {:?}", socket_path);
     } else {
         info!("Listening on UDP {}:{}", args.address, args.port);
     }
 
     let mut store = DataStore::new();
-    let Ok(count) = store.scan_directory(&args.datastore) else {
-        eprintln!("Error scanning directory: {:?}", args.datastore);
-        std::process::exit(1);
+    store.set_read_only(args.read_only);
+    store.set_slow_query_threshold(args.slow_query_ms.map(Duration::from_millis));
+    store.set_block_cache(
+        args.block_cache_size
+            .map(|size| BlockCache::shared(size, CacheKeying::PerStore)),
+    );
+    let count = match store.scan_directory(&args.datastore) {
+        Ok(count) => count,
+        Err(e) => {
+            eprintln!("Error scanning the datastore directory: {}", e);
+            std::process::exit(1);
+        }
     };
 
     info!("Discovered and tracked {} CAR files", count);
     match store.index() {
-        Ok(()) => info!("Indexing completed successfully"),
+        Ok(()) if store.quarantine().is_empty() => info!("Indexing completed successfully"),
+        Ok(()) => {
+            warn!(
+                "Indexing completed, {} CAR files quarantined",
+                store.quarantine().len()
+            );
+            for car in store.quarantine() {
+                warn!("Quarantined {:?}: {}", car.path, car.message);
+            }
+        }
         Err(e) => eprintln!("Error during indexing: {:?}", e),
     }
+
+    if args.verify {
+        let mut failed = false;
+        for verification in store.verify() {
+            let mode = if verification.exact { "exact" } else { "scan" };
+            match verification.result {
+                Ok(report) => info!(
+                    "Verified {:?} ({}): {} sections, {} not hashed, {} index entries",
+                    verification.path, mode, report.sections, report.unhashed, report.indexed
+                ),
+                Err(e) => {
+                    eprintln!(
+                        "Verification of {:?} ({}) failed: {}",
+                        verification.path, mode, e
+                    );
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    }
+
+    if !args.allow_roots.is_empty() {
+        match store.set_allowed_roots(args.allow_roots) {
+            Ok(count) => info!("{} blocks are servable under the serving policy", count),
+            Err(e) => {
+                eprintln!("Error applying the serving policy: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &args.tombstones {
+        match store.load_tombstones(path) {
+            Ok(count) => info!("Loaded {} tombstones from {:?}", count, path),
+            Err(e) => {
+                eprintln!("Error loading the tombstones from {:?}: {:?}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for cid in args.tombstone_cids {
+        if let Err(e) = store.tombstone_cid(cid) {
+            eprintln!("Error persisting the tombstones: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+    for path in args.tombstone_cars {
+        if let Err(e) = store.tombstone_car(&path) {
+            eprintln!("Error tombstoning the CAR file {:?}: {:?}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(format) = args.export_index {
+        let mut exporter = SectionExporter::new(std::io::stdout().lock(), format);
+        match store.export_index(&mut exporter) {
+            Ok(count) => info!("Exported {} indexed blocks", count),
+            Err(e) => {
+                eprintln!("Error exporting the index: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = args.export_car {
+        let result = std::fs::File::create(&path)
+            .map_err(Into::into)
+            .and_then(|file| {
+                let mut sink = std::io::BufWriter::new(file);
+                store.export_all(&mut sink, |p| {
+                    if p.blocks % 10_000 == 0 || p.blocks == p.total_blocks {
+                        info!(
+                            "Exported {}/{} blocks ({} bytes)",
+                            p.blocks, p.total_blocks, p.bytes
+                        );
+                    }
+                })
+            });
+        if let Err(e) = result {
+            eprintln!("Error exporting the datastore to {:?}: {:?}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    match args.discovery.build() {
+        Ok(mut discovery) => match store.announce(discovery.as_mut()) {
+            Ok(count) => info!("Announced {} roots", count),
+            Err(e) => warn!("Error announcing the roots: {}", e),
+        },
+        Err(e) => {
+            eprintln!("Error setting up the content discovery: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(socket_path) = &args.socket {
+        let listener = match std::os::unix::net::UnixListener::bind(socket_path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error binding the Unix socket {:?}: {:?}", socket_path, e);
+                std::process::exit(1);
+            }
+        };
+        for stream in listener.incoming() {
+            let result = stream.and_then(|mut stream| ipc::serve(&mut store, &mut stream));
+            if let Err(e) = result {
+                warn!("IPC connection failed: {}", e);
+            }
+        }
+    }
 }
 
 fn setup_logging() {
@@ -0,0 +1,245 @@
+//! Metrics of navira-store
+//!
+//! This module provides the counters maintained by the [DataStore](crate::datastore::DataStore)
+//! while serving blocks, and renders them in the Prometheus text exposition format so they can be
+//! scraped or dumped by the operators.
+//!
+//! All the counters are atomics, so the metrics can be read (and rendered) while lookups are being recorded.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// Upper bounds (in seconds) of the block lookup latency histogram buckets
+const LATENCY_BUCKETS: [f64; 6] = [0.0001, 0.001, 0.01, 0.1, 1.0, 10.0];
+
+/// Outcome of a block lookup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupOutcome {
+    /// The block was found and served
+    Served,
+    /// The block is not indexed
+    NotFound,
+    /// The block is indexed but not servable according to the serving policy
+    Denied,
+    /// The block could not be read from its CAR file
+    Error,
+    /// The block (or its CAR file) is tombstoned
+    Tombstoned,
+}
+
+impl LookupOutcome {
+    /// All the outcomes, in discriminant order
+    const ALL: [LookupOutcome; 5] = [
+        LookupOutcome::Served,
+        LookupOutcome::NotFound,
+        LookupOutcome::Denied,
+        LookupOutcome::Error,
+        LookupOutcome::Tombstoned,
+    ];
+
+    /// Label of the outcome in the exported metrics
+    pub fn label(&self) -> &'static str {
+        match self {
+            LookupOutcome::Served => "served",
+            LookupOutcome::NotFound => "not_found",
+            LookupOutcome::Denied => "denied",
+            LookupOutcome::Error => "error",
+            LookupOutcome::Tombstoned => "tombstoned",
+        }
+    }
+}
+
+/// Block lookup metrics of a DataStore
+#[derive(Debug, Default)]
+pub struct Metrics {
+    // Number of lookups, by outcome (indexed by LookupOutcome discriminant)
+    lookups: [AtomicU64; LookupOutcome::ALL.len()],
+    // Number of lookups per latency bucket (non-cumulative, indexed as LATENCY_BUCKETS, then +Inf)
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    // Total latency of the lookups, in microseconds
+    latency_micros: AtomicU64,
+    // Number of lookups which exceeded the slow-query threshold
+    slow_lookups: AtomicU64,
+    // Number of reads which had to open their CAR file
+    car_cache_misses: AtomicU64,
+    // Number of pending responses per priority class, as last recorded by the scheduler
+    queue_depths: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    /// Create a new set of metrics, with all the counters set to zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a block lookup with its outcome and latency
+    pub fn record_lookup(&self, outcome: LookupOutcome, elapsed: Duration) {
+        self.lookups[outcome as usize].fetch_add(1, Ordering::Relaxed);
+
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|le| secs <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Record a lookup which exceeded the slow-query threshold
+    pub fn record_slow_lookup(&self) {
+        self.slow_lookups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a read which had to open its CAR file
+    pub fn record_car_cache_miss(&self) {
+        self.car_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the number of pending responses of a priority class (see [FairScheduler](crate::scheduler::FairScheduler))
+    pub fn record_queue_depth(&self, class: &str, depth: usize) {
+        if let Ok(mut depths) = self.queue_depths.lock() {
+            depths.insert(class.to_owned(), depth as u64);
+        }
+    }
+
+    /// Number of pending responses of a priority class, as last recorded
+    pub fn queue_depth(&self, class: &str) -> Option<u64> {
+        self.queue_depths.lock().ok()?.get(class).copied()
+    }
+
+    /// Number of lookups with the given outcome
+    pub fn lookups(&self, outcome: LookupOutcome) -> u64 {
+        self.lookups[outcome as usize].load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups which exceeded the slow-query threshold
+    pub fn slow_lookups(&self) -> u64 {
+        self.slow_lookups.load(Ordering::Relaxed)
+    }
+
+    /// Number of reads which had to open their CAR file
+    pub fn car_cache_misses(&self) -> u64 {
+        self.car_cache_misses.load(Ordering::Relaxed)
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP navira_store_block_lookups_total Number of block lookups, by outcome\n",
+        );
+        out.push_str("# TYPE navira_store_block_lookups_total counter\n");
+        for (outcome, count) in LookupOutcome::ALL.iter().zip(&self.lookups) {
+            let _ = writeln!(
+                out,
+                "navira_store_block_lookups_total{{outcome=\"{}\"}} {}",
+                outcome.label(),
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str(
+            "# HELP navira_store_block_lookup_duration_seconds Latency of the block lookups\n",
+        );
+        out.push_str("# TYPE navira_store_block_lookup_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (i, count) in self.latency_buckets.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let le = LATENCY_BUCKETS
+                .get(i)
+                .map(|le| le.to_string())
+                .unwrap_or_else(|| "+Inf".to_owned());
+            let _ = writeln!(
+                out,
+                "navira_store_block_lookup_duration_seconds_bucket{{le=\"{}\"}} {}",
+                le, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "navira_store_block_lookup_duration_seconds_sum {}",
+            self.latency_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(
+            out,
+            "navira_store_block_lookup_duration_seconds_count {}",
+            cumulative
+        );
+
+        out.push_str("# HELP navira_store_slow_block_lookups_total Number of block lookups exceeding the slow-query threshold\n");
+        out.push_str("# TYPE navira_store_slow_block_lookups_total counter\n");
+        let _ = writeln!(
+            out,
+            "navira_store_slow_block_lookups_total {}",
+            self.slow_lookups()
+        );
+
+        out.push_str("# HELP navira_store_car_cache_misses_total Number of block reads which had to open their CAR file\n");
+        out.push_str("# TYPE navira_store_car_cache_misses_total counter\n");
+        let _ = writeln!(
+            out,
+            "navira_store_car_cache_misses_total {}",
+            self.car_cache_misses()
+        );
+
+        if let Ok(depths) = self.queue_depths.lock()
+            && !depths.is_empty()
+        {
+            out.push_str("# HELP navira_store_serving_queue_depth Number of pending responses, by peer priority class\n");
+            out.push_str("# TYPE navira_store_serving_queue_depth gauge\n");
+            for (class, depth) in depths.iter() {
+                let _ = writeln!(
+                    out,
+                    "navira_store_serving_queue_depth{{class=\"{}\"}} {}",
+                    class, depth
+                );
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render() {
+        let metrics = Metrics::new();
+        metrics.record_lookup(LookupOutcome::Served, Duration::from_micros(50));
+        metrics.record_lookup(LookupOutcome::Served, Duration::from_millis(5));
+        metrics.record_lookup(LookupOutcome::NotFound, Duration::from_secs(20));
+        metrics.record_slow_lookup();
+        metrics.record_car_cache_miss();
+
+        assert_eq!(metrics.lookups(LookupOutcome::Served), 2);
+        assert_eq!(metrics.lookups(LookupOutcome::Denied), 0);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("navira_store_block_lookups_total{outcome=\"served\"} 2\n"));
+        assert!(rendered.contains("navira_store_block_lookups_total{outcome=\"not_found\"} 1\n"));
+        assert!(
+            rendered
+                .contains("navira_store_block_lookup_duration_seconds_bucket{le=\"0.0001\"} 1\n")
+        );
+        assert!(
+            rendered.contains("navira_store_block_lookup_duration_seconds_bucket{le=\"0.01\"} 2\n")
+        );
+        assert!(
+            rendered.contains("navira_store_block_lookup_duration_seconds_bucket{le=\"+Inf\"} 3\n")
+        );
+        assert!(rendered.contains("navira_store_block_lookup_duration_seconds_count 3\n"));
+        assert!(rendered.contains("navira_store_slow_block_lookups_total 1\n"));
+        assert!(rendered.contains("navira_store_car_cache_misses_total 1\n"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Serving policy for navira-store
+//!
+//! A datastore directory might hold both public and private content. The serving policy lets operators
+//! restrict which blocks are servable, by allowing only some roots (and transitively, the blocks reachable
+//! from them). Every request for a block outside of the allowed set is denied, even if the block is indexed.
+//!
+//! The reachability set is precomputed (see [DataStore::set_allowed_roots](crate::datastore::DataStore::set_allowed_roots)),
+//! so checking a request is a simple set lookup.
+//!
+//! On top of it, an [AdmissionPolicy] decides whether a DAG request (e.g. a CAR export of a UnixFS file) should
+//! be attempted at all, before any block is read. It is told whether the requested CID is a root of an indexed
+//! CAR file (see [DataStore::has_root](crate::datastore::DataStore::has_root)), so servers which only serve whole
+//! DAGs can cheaply turn down requests rooted anywhere else.
+
+use std::collections::HashSet;
+
+use navira_car::wire::cid::RawCid;
+
+/// Serving policy of the DataStore
+#[derive(Debug, Clone, Default)]
+pub enum ServingPolicy {
+    /// Every indexed block is servable
+    #[default]
+    AllowAll,
+    /// Only the blocks reachable from the allowed roots are servable
+    AllowRoots {
+        /// Allowed roots
+        roots: HashSet<RawCid>,
+        /// Precomputed set of blocks reachable from the allowed roots (roots included)
+        reachable: HashSet<RawCid>,
+    },
+}
+
+impl ServingPolicy {
+    /// Is the block identified by this CID servable?
+    pub fn is_servable(&self, cid: &RawCid) -> bool {
+        match self {
+            ServingPolicy::AllowAll => true,
+            ServingPolicy::AllowRoots { reachable, .. } => reachable.contains(cid),
+        }
+    }
+}
+
+/// Admission policy of DAG requests, consulted before a DAG request is attempted
+///
+/// Closures taking the requested CID and whether it is a root of an indexed CAR file are admission policies.
+pub trait AdmissionPolicy: Send + Sync {
+    /// Should the request for the DAG rooted at `cid` be attempted?
+    ///
+    /// `is_root` tells whether `cid` is a root of an indexed CAR file.
+    fn admit(&self, cid: &RawCid, is_root: bool) -> bool;
+}
+
+impl<F: Fn(&RawCid, bool) -> bool + Send + Sync> AdmissionPolicy for F {
+    fn admit(&self, cid: &RawCid, is_root: bool) -> bool {
+        self(cid, is_root)
+    }
+}
+
+/// Admit every DAG request (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdmitAll;
+
+impl AdmissionPolicy for AdmitAll {
+    fn admit(&self, _cid: &RawCid, _is_root: bool) -> bool {
+        true
+    }
+}
+
+/// Only admit the DAG requests rooted at a root of an indexed CAR file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdmitRoots;
+
+impl AdmissionPolicy for AdmitRoots {
+    fn admit(&self, _cid: &RawCid, is_root: bool) -> bool {
+        is_root
+    }
+}
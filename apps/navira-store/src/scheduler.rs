@@ -0,0 +1,395 @@
+//! Fair scheduling of the block responses across peers
+//!
+//! When several peers have substantial wantlists, serving them in arrival order lets a bulk-replication peer
+//! (thousands of wanted blocks) delay every latency-sensitive peer (e.g. a gateway fetching a single page) queued
+//! behind it. The [FairScheduler] interleaves the responses instead, with a deficit round robin (DRR) over
+//! configured priority classes:
+//! - every peer belongs to a [PriorityClass] (the default class unless told otherwise);
+//! - on each round, a class with pending responses earns `quantum * weight` bytes of credit, and sends responses
+//!   while its credit covers their size, so the bandwidth is shared in proportion to the class weights whatever
+//!   the block sizes;
+//! - within a class, the peers are served in turn, one response at a time.
+//!
+//! The scheduler is a plain data structure: the serving loop enqueues the responses (or the wanted CIDs) as the
+//! wantlists arrive, and dequeues the next one whenever the transport can send. The queue depths per class are
+//! exposed in the [Metrics](crate::metrics::Metrics) with [FairScheduler::record_queue_depths].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+use crate::metrics::Metrics;
+
+/// Errors related to the configuration of the scheduler
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SchedulerError {
+    /// No class is configured with this name
+    #[error("Unknown priority class {0:?}")]
+    UnknownClass(String),
+}
+
+/// A priority class of peers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriorityClass {
+    /// Name of the class, as exposed in the metrics
+    pub name: String,
+    /// Share of the bandwidth given to the class, relative to the other classes (at least 1)
+    pub weight: u32,
+}
+
+impl PriorityClass {
+    /// Create a priority class, a zero weight being raised to 1
+    pub fn new(name: impl Into<String>, weight: u32) -> Self {
+        Self {
+            name: name.into(),
+            weight: weight.max(1),
+        }
+    }
+}
+
+/// A response dequeued from the scheduler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheduled<P, T> {
+    /// Peer to send the response to
+    pub peer: P,
+    /// The response
+    pub item: T,
+    /// Cost of the response (e.g. the block size), as given when it was enqueued
+    pub cost: usize,
+}
+
+/// Pending responses of a priority class
+#[derive(Debug)]
+struct ClassQueue<P, T> {
+    class: PriorityClass,
+    /// Credit of the class in the current round, in cost units
+    deficit: u64,
+    /// Has the class already earned its quantum in the current round?
+    in_round: bool,
+    /// Peers with pending responses, in serving order
+    peers: VecDeque<P>,
+    /// Pending responses of each peer, with their cost
+    queues: HashMap<P, VecDeque<(T, usize)>>,
+    /// Number of pending responses
+    depth: usize,
+}
+
+impl<P: Clone + Eq + Hash, T> ClassQueue<P, T> {
+    fn new(class: PriorityClass) -> Self {
+        Self {
+            class,
+            deficit: 0,
+            in_round: false,
+            peers: VecDeque::new(),
+            queues: HashMap::new(),
+            depth: 0,
+        }
+    }
+
+    fn push(&mut self, peer: P, item: T, cost: usize) {
+        let queue = self.queues.entry(peer.clone()).or_default();
+        if queue.is_empty() {
+            self.peers.push_back(peer);
+        }
+        queue.push_back((item, cost));
+        self.depth += 1;
+    }
+
+    /// Cost of the next response of the class
+    fn head_cost(&self) -> Option<usize> {
+        let peer = self.peers.front()?;
+        self.queues.get(peer)?.front().map(|(_, cost)| *cost)
+    }
+
+    /// Pop the next response, and move its peer to the back of the class
+    fn pop(&mut self) -> Option<Scheduled<P, T>> {
+        let peer = self.peers.pop_front()?;
+        let queue = self.queues.get_mut(&peer)?;
+        let (item, cost) = queue.pop_front()?;
+        if queue.is_empty() {
+            self.queues.remove(&peer);
+        } else {
+            self.peers.push_back(peer.clone());
+        }
+        self.depth -= 1;
+        Some(Scheduled { peer, item, cost })
+    }
+}
+
+/// Deficit round robin scheduler of the responses to the peers, weighted by priority classes
+///
+/// `P` identifies the peers, and `T` is the queued item (a block, a CID to look up, …).
+#[derive(Debug)]
+pub struct FairScheduler<P, T> {
+    classes: Vec<ClassQueue<P, T>>,
+    /// Credit earned per round by a class of weight 1
+    quantum: u64,
+    /// Class of the peers without an assigned class
+    default_class: usize,
+    /// Classes assigned to the peers
+    peer_classes: HashMap<P, usize>,
+    /// Classes with pending responses, in round order
+    active: VecDeque<usize>,
+}
+
+impl<P: Clone + Eq + Hash, T> FairScheduler<P, T> {
+    /// Default credit earned per round by a class of weight 1 (in bytes, about a typical block)
+    pub const DEFAULT_QUANTUM: usize = 256 * 1024;
+
+    /// Create a scheduler over the given classes, the first one being the default class
+    ///
+    /// Without any class, a single `default` class of weight 1 is used.
+    pub fn new(classes: Vec<PriorityClass>) -> Self {
+        let classes = if classes.is_empty() {
+            vec![PriorityClass::new("default", 1)]
+        } else {
+            classes
+        };
+        Self {
+            classes: classes.into_iter().map(ClassQueue::new).collect(),
+            quantum: Self::DEFAULT_QUANTUM as u64,
+            default_class: 0,
+            peer_classes: HashMap::new(),
+            active: VecDeque::new(),
+        }
+    }
+
+    /// Set the credit earned per round by a class of weight 1 (at least 1)
+    ///
+    /// Smaller quanta interleave the classes more finely, at the cost of more rounds for large responses.
+    pub fn with_quantum(mut self, quantum: usize) -> Self {
+        self.quantum = quantum.max(1) as u64;
+        self
+    }
+
+    /// Set the class of the peers without an assigned class
+    pub fn with_default_class(mut self, class: &str) -> Result<Self, SchedulerError> {
+        self.default_class = self.class_index(class)?;
+        Ok(self)
+    }
+
+    /// Configured priority classes
+    pub fn classes(&self) -> impl Iterator<Item = &PriorityClass> {
+        self.classes.iter().map(|queue| &queue.class)
+    }
+
+    /// Assign a peer to a priority class
+    ///
+    /// Only the responses enqueued afterwards are scheduled in the new class.
+    pub fn set_peer_class(&mut self, peer: P, class: &str) -> Result<(), SchedulerError> {
+        let class = self.class_index(class)?;
+        self.peer_classes.insert(peer, class);
+        Ok(())
+    }
+
+    /// Forget the class of a peer (e.g. once disconnected), which falls back to the default class
+    pub fn remove_peer_class(&mut self, peer: &P) {
+        self.peer_classes.remove(peer);
+    }
+
+    /// Priority class of a peer
+    pub fn peer_class(&self, peer: &P) -> &PriorityClass {
+        let class = self
+            .peer_classes
+            .get(peer)
+            .copied()
+            .unwrap_or(self.default_class);
+        &self.classes[class].class
+    }
+
+    /// Queue a response to a peer, with its cost (e.g. the block size, in bytes)
+    pub fn enqueue(&mut self, peer: P, item: T, cost: usize) {
+        let class = self
+            .peer_classes
+            .get(&peer)
+            .copied()
+            .unwrap_or(self.default_class);
+        let queue = &mut self.classes[class];
+        if queue.depth == 0 {
+            self.active.push_back(class);
+        }
+        queue.push(peer, item, cost);
+    }
+
+    /// Dequeue the next response to send
+    ///
+    /// ## Returns
+    /// - `Some(Scheduled)` with the next response, and its peer.
+    /// - `None` if no response is pending.
+    pub fn dequeue(&mut self) -> Option<Scheduled<P, T>> {
+        loop {
+            let class = *self.active.front()?;
+            let queue = &mut self.classes[class];
+            if !queue.in_round {
+                queue.deficit += self.quantum * u64::from(queue.class.weight);
+                queue.in_round = true;
+            }
+            let Some(cost) = queue.head_cost() else {
+                // Active classes always have pending responses
+                self.active.pop_front();
+                continue;
+            };
+            if cost as u64 > queue.deficit {
+                // Not enough credit left in this round, the next class takes its turn
+                queue.in_round = false;
+                self.active.rotate_left(1);
+                continue;
+            }
+            queue.deficit -= cost as u64;
+            let scheduled = queue.pop();
+            if queue.depth == 0 {
+                // Idle classes do not accumulate credit
+                queue.deficit = 0;
+                queue.in_round = false;
+                self.active.pop_front();
+            }
+            return scheduled;
+        }
+    }
+
+    /// Drop the pending responses to a peer (e.g. once disconnected, or when its wantlist is cancelled)
+    ///
+    /// ## Returns
+    /// The number of dropped responses.
+    pub fn cancel_peer(&mut self, peer: &P) -> usize {
+        let mut dropped = 0;
+        for (class, queue) in self.classes.iter_mut().enumerate() {
+            if let Some(pending) = queue.queues.remove(peer) {
+                queue.peers.retain(|p| p != peer);
+                queue.depth -= pending.len();
+                dropped += pending.len();
+                if queue.depth == 0 {
+                    queue.deficit = 0;
+                    queue.in_round = false;
+                    self.active.retain(|&active| active != class);
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Number of pending responses
+    pub fn len(&self) -> usize {
+        self.classes.iter().map(|queue| queue.depth).sum()
+    }
+
+    /// Is there no pending response?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of pending responses of each class, in configuration order
+    pub fn queue_depths(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.classes
+            .iter()
+            .map(|queue| (queue.class.name.as_str(), queue.depth))
+    }
+
+    /// Record the current queue depths of the classes in the metrics
+    pub fn record_queue_depths(&self, metrics: &Metrics) {
+        for (class, depth) in self.queue_depths() {
+            metrics.record_queue_depth(class, depth);
+        }
+    }
+
+    fn class_index(&self, name: &str) -> Result<usize, SchedulerError> {
+        self.classes
+            .iter()
+            .position(|queue| queue.class.name == name)
+            .ok_or_else(|| SchedulerError::UnknownClass(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_fair_scheduling() {
+        let mut scheduler = FairScheduler::new(vec![
+            PriorityClass::new("bulk", 1),
+            PriorityClass::new("gateway", 4),
+        ])
+        .with_quantum(1000);
+        scheduler.set_peer_class("gw", "gateway").unwrap();
+        assert_eq!(
+            scheduler.set_peer_class("gw", "other"),
+            Err(SchedulerError::UnknownClass("other".to_owned()))
+        );
+        assert_eq!(scheduler.peer_class(&"replica").name, "bulk");
+
+        // A bulk peer with a large wantlist, queued first
+        for i in 0..100 {
+            scheduler.enqueue("replica", i, 1000);
+        }
+        for i in 0..8 {
+            scheduler.enqueue("gw", i, 1000);
+        }
+        assert_eq!(scheduler.len(), 108);
+        let depths: Vec<_> = scheduler.queue_depths().collect();
+        assert_eq!(depths, [("bulk", 100), ("gateway", 8)]);
+
+        // The gateway peer gets 4 blocks per block of the bulk peer, and is done after 10 responses
+        let peers: Vec<_> = (0..10).map(|_| scheduler.dequeue().unwrap().peer).collect();
+        assert_eq!(
+            peers,
+            [
+                "replica", "gw", "gw", "gw", "gw", "replica", "gw", "gw", "gw", "gw"
+            ]
+        );
+        // Then the bulk peer gets all the bandwidth, in order
+        let items: Vec<_> = std::iter::from_fn(|| scheduler.dequeue())
+            .map(|scheduled| scheduled.item)
+            .collect();
+        assert_eq!(items, (2..100).collect::<Vec<_>>());
+        assert!(scheduler.is_empty());
+
+        let metrics = Metrics::new();
+        scheduler.enqueue("gw", 0, 10);
+        scheduler.record_queue_depths(&metrics);
+        let rendered = metrics.render();
+        assert!(rendered.contains("navira_store_serving_queue_depth{class=\"bulk\"} 0\n"));
+        assert!(rendered.contains("navira_store_serving_queue_depth{class=\"gateway\"} 1\n"));
+    }
+
+    #[test]
+    fn test_fair_scheduling_costs_and_peers() {
+        let mut scheduler = FairScheduler::new(Vec::new()).with_quantum(100);
+        // Round robin between the peers of a class, whatever the sizes of their blocks
+        scheduler.enqueue(1, 'a', 250);
+        scheduler.enqueue(1, 'b', 250);
+        scheduler.enqueue(2, 'x', 10);
+        scheduler.enqueue(2, 'y', 10);
+        scheduler.enqueue(3, 'z', 10);
+        let items: Vec<_> = std::iter::from_fn(|| scheduler.dequeue())
+            .map(|scheduled| scheduled.item)
+            .collect();
+        assert_eq!(items, ['a', 'x', 'z', 'b', 'y']);
+
+        // Large responses are sent once enough credit was earned, small ones are not delayed by them
+        let mut scheduler = FairScheduler::new(vec![
+            PriorityClass::new("bulk", 1),
+            PriorityClass::new("gateway", 1),
+        ])
+        .with_quantum(100)
+        .with_default_class("gateway")
+        .unwrap();
+        scheduler.set_peer_class("replica", "bulk").unwrap();
+        scheduler.enqueue("replica", 0, 1000);
+        for i in 1..=12 {
+            scheduler.enqueue("gw", i, 50);
+        }
+        let position = std::iter::from_fn(|| scheduler.dequeue())
+            .position(|scheduled| scheduled.peer == "replica")
+            .unwrap();
+        assert_eq!(position, 12);
+
+        // Cancelled wantlists leave the queues
+        scheduler.enqueue("replica", 1, 10);
+        scheduler.enqueue("gw", 13, 10);
+        assert_eq!(scheduler.cancel_peer(&"replica"), 1);
+        assert_eq!(scheduler.dequeue().unwrap().item, 13);
+        assert!(scheduler.dequeue().is_none());
+    }
+}
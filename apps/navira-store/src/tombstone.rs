@@ -0,0 +1,226 @@
+//! Tombstones of navira-store
+//!
+//! Operators sometimes need to stop serving some content without deleting it from the storage (takedowns,
+//! staged deprecation, etc.). A tombstone marks either a single block (by CID) or a whole CAR file (by path)
+//! as not servable, while keeping the CAR files untouched on disk. Tombstones are consulted before every lookup,
+//! on top of the [serving policy](crate::policy).
+//!
+//! The tombstone set is persisted in a plain text file, so that it survives restarts and can be reviewed
+//! (or edited) by the operators. Each line holds one tombstone, empty lines and `#` comments are ignored:
+//! ```text
+//! # Takedown request 2024-01
+//! cid 01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451
+//! car /srv/navira/deprecated.car
+//! ```
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use navira_car::wire::cid::RawCid;
+
+/// Set of tombstoned CIDs and CAR files
+#[derive(Debug, Clone, Default)]
+pub struct Tombstones {
+    /// Tombstoned blocks
+    cids: HashSet<RawCid>,
+    /// Tombstoned CAR files, by canonical path
+    cars: BTreeSet<PathBuf>,
+    /// File where the tombstones are persisted (in memory only if None)
+    file: Option<PathBuf>,
+}
+
+impl Tombstones {
+    /// Create an empty set of tombstones, kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the tombstones persisted in the given file
+    ///
+    /// The file is created on the first change if it does not exist yet, and every later change
+    /// is persisted to it.
+    ///
+    /// # Returns
+    /// * `Ok(Tombstones)` - The loaded tombstones (empty if the file does not exist)
+    /// * `Err(std::io::Error)` - The file could not be read, or holds a malformed line
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let mut tombstones = Tombstones {
+            file: Some(path.to_path_buf()),
+            ..Self::default()
+        };
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(tombstones),
+            Err(e) => return Err(e),
+        };
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{:?} line {}: {}", path, n + 1, reason),
+                )
+            };
+            match line.split_once(' ') {
+                Some(("cid", cid)) => {
+                    let cid = RawCid::from_hex(cid.trim()).map_err(|e| invalid(&e.to_string()))?;
+                    tombstones.cids.insert(cid);
+                }
+                Some(("car", car)) => {
+                    tombstones.cars.insert(PathBuf::from(car.trim()));
+                }
+                _ => return Err(invalid("expected `cid <hex>` or `car <path>`")),
+            }
+        }
+        Ok(tombstones)
+    }
+
+    /// Stop persisting the tombstones, later changes are kept in memory only
+    pub fn detach(&mut self) {
+        self.file = None;
+    }
+
+    /// Is the block identified by this CID tombstoned?
+    pub fn is_cid_tombstoned(&self, cid: &RawCid) -> bool {
+        self.cids.contains(cid)
+    }
+
+    /// Is this CAR file (canonical path) tombstoned?
+    pub fn is_car_tombstoned(&self, car: &Path) -> bool {
+        self.cars.contains(car)
+    }
+
+    /// Tombstoned CIDs, in no particular order
+    pub fn cids(&self) -> impl Iterator<Item = &RawCid> {
+        self.cids.iter()
+    }
+
+    /// Tombstoned CAR files, in path order
+    pub fn cars(&self) -> impl Iterator<Item = &Path> {
+        self.cars.iter().map(PathBuf::as_path)
+    }
+
+    /// Number of tombstones (CIDs and CAR files)
+    pub fn len(&self) -> usize {
+        self.cids.len() + self.cars.len()
+    }
+
+    /// Is the set of tombstones empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tombstone a block, persisting the change
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the CID was not tombstoned yet
+    /// * `Err(std::io::Error)` - The change could not be persisted (it is kept in memory)
+    pub fn insert_cid(&mut self, cid: RawCid) -> std::io::Result<bool> {
+        let inserted = self.cids.insert(cid);
+        self.persist_if(inserted)
+    }
+
+    /// Tombstone a CAR file (by canonical path), persisting the change
+    ///
+    /// See [Tombstones::insert_cid] for the returned value.
+    pub fn insert_car(&mut self, car: PathBuf) -> std::io::Result<bool> {
+        let inserted = self.cars.insert(car);
+        self.persist_if(inserted)
+    }
+
+    /// Lift the tombstone of a block, persisting the change
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the CID was tombstoned
+    /// * `Err(std::io::Error)` - The change could not be persisted (it is kept in memory)
+    pub fn remove_cid(&mut self, cid: &RawCid) -> std::io::Result<bool> {
+        let removed = self.cids.remove(cid);
+        self.persist_if(removed)
+    }
+
+    /// Lift the tombstone of a CAR file, persisting the change
+    ///
+    /// See [Tombstones::remove_cid] for the returned value.
+    pub fn remove_car(&mut self, car: &Path) -> std::io::Result<bool> {
+        let removed = self.cars.remove(car);
+        self.persist_if(removed)
+    }
+
+    /// Persist the tombstones if they changed, passing the change flag through
+    fn persist_if(&self, changed: bool) -> std::io::Result<bool> {
+        if changed {
+            self.persist()?;
+        }
+        Ok(changed)
+    }
+
+    /// Write the tombstones to their file, if any
+    ///
+    /// The file is replaced atomically, so that a crash never leaves a truncated tombstone set.
+    fn persist(&self) -> std::io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = path.with_file_name(tmp_name);
+        let mut file = std::fs::File::create(&tmp)?;
+        // Sorted, so that the file is stable across changes
+        let mut cids: Vec<String> = self.cids.iter().map(RawCid::to_hex).collect();
+        cids.sort();
+        for cid in cids {
+            writeln!(file, "cid {}", cid)?;
+        }
+        for car in &self.cars {
+            writeln!(file, "car {}", car.display())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tombstones_persistence() {
+        let path = std::env::temp_dir().join(format!(
+            "navira-store-tombstones-{}.txt",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let cid = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+
+        let mut tombstones = Tombstones::load(&path).unwrap();
+        assert!(tombstones.is_empty());
+        assert!(tombstones.insert_cid(cid.clone()).unwrap());
+        assert!(!tombstones.insert_cid(cid.clone()).unwrap());
+        assert!(
+            tombstones
+                .insert_car(PathBuf::from("/srv/old.car"))
+                .unwrap()
+        );
+
+        let mut reloaded = Tombstones::load(&path).unwrap();
+        assert!(reloaded.is_cid_tombstoned(&cid));
+        assert!(reloaded.is_car_tombstoned(Path::new("/srv/old.car")));
+        assert_eq!(reloaded.len(), 2);
+        assert!(reloaded.remove_cid(&cid).unwrap());
+        assert!(!Tombstones::load(&path).unwrap().is_cid_tombstoned(&cid));
+
+        std::fs::write(&path, "# comment\n\nblock 0155\n").unwrap();
+        assert!(Tombstones::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
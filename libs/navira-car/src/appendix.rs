@@ -0,0 +1,436 @@
+//! Appendix metadata blocks of CAR archives
+//!
+//! The CAR format has no place for archive metadata (who produced it, when, what it holds), so teams end up
+//! inventing ad-hoc sidecars. This module standardizes a convention instead: the metadata is stored in the
+//! archive itself, as a dag-cbor block referenced as a secondary root (the last one) and written as the last
+//! section of the payload. Readers unaware of the convention only see an extra root and block.
+//!
+//! The appendix block is a dag-cbor map, with the `type` key set to [APPENDIX_TYPE] and the `version` key set
+//! to [APPENDIX_VERSION]. The other keys are optional:
+//! - `producer` (text): the software which produced the archive, e.g. `navira-car 0.1.1`.
+//! - `created` (unsigned integer): creation time, in seconds since the Unix epoch.
+//! - `description` (text): free-form description of the archive.
+//! - `contents` (list): descriptions of the content, as `{"root": <link>, "description": <text>}` maps.
+//!
+//! Unknown keys are ignored while reading, so the convention can be extended without breaking older readers.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::appendix::Appendix;
+//! use navira_car::wire::cid::RawCid;
+//!
+//! let root = RawCid::from_hex("0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4").unwrap();
+//! let appendix = Appendix::new()
+//!     .with_producer("my-packer 1.0")
+//!     .with_created(1_700_000_000)
+//!     .with_content(root, "The aaaa block");
+//! let block = appendix.to_block();
+//! assert_eq!(Appendix::from_block(&block).unwrap(), appendix);
+//! assert!(appendix.to_string().starts_with("producer: my-packer 1.0\n"));
+//! ```
+
+use std::fmt;
+
+use ciborium::Value;
+
+use crate::ipld::{BlockSource, CODEC_DAG_CBOR, cid_codec, dagcbor};
+use crate::wire::cid::{RawCid, Tag42Encoding};
+#[cfg(feature = "pack")]
+use crate::wire::v1::Section;
+
+/// Value of the `type` key of the appendix blocks
+pub const APPENDIX_TYPE: &str = "car-appendix";
+/// Version of the appendix convention written by this module
+pub const APPENDIX_VERSION: u64 = 1;
+
+/// CIDv1 prefix of dag-cbor blocks hashed with sha2-256 (version, codec, multihash code and length)
+#[cfg(feature = "pack")]
+const DAG_CBOR_SHA2_256_PREFIX: [u8; 4] = [0x01, 0x71, 0x12, 0x20];
+
+/// Errors related to the decoding of appendix blocks
+#[derive(thiserror::Error, Debug)]
+pub enum AppendixError {
+    /// The block is not valid dag-cbor
+    #[error(transparent)]
+    Decode(#[from] dagcbor::DagCborError),
+    /// The block is not an appendix (not a map, or without the appendix `type`)
+    #[error("Not an appendix block")]
+    NotAnAppendix,
+    /// The appendix was written with a later version of the convention
+    #[error("Unsupported appendix version {version}", version = .0)]
+    UnsupportedVersion(u64),
+    /// A known key of the appendix has a value of the wrong type
+    #[error("Invalid appendix field {field:?}", field = .0)]
+    InvalidField(&'static str),
+}
+
+/// Description of a content (a DAG) of the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentDescription {
+    /// Root of the described DAG
+    pub root: RawCid,
+    /// Description of the DAG
+    pub description: String,
+}
+
+/// Metadata of a CAR archive, stored in its appendix block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Appendix {
+    /// Software which produced the archive
+    pub producer: Option<String>,
+    /// Creation time, in seconds since the Unix epoch
+    pub created: Option<u64>,
+    /// Free-form description of the archive
+    pub description: Option<String>,
+    /// Descriptions of the contents of the archive
+    pub contents: Vec<ContentDescription>,
+}
+
+impl Appendix {
+    /// Create an empty appendix
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the software which produced the archive
+    pub fn with_producer(mut self, producer: impl Into<String>) -> Self {
+        self.producer = Some(producer.into());
+        self
+    }
+
+    /// Set the creation time, in seconds since the Unix epoch
+    pub fn with_created(mut self, created: u64) -> Self {
+        self.created = Some(created);
+        self
+    }
+
+    /// Set the description of the archive
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Add the description of a content of the archive
+    pub fn with_content(mut self, root: RawCid, description: impl Into<String>) -> Self {
+        self.contents.push(ContentDescription {
+            root,
+            description: description.into(),
+        });
+        self
+    }
+
+    /// Encode the appendix as a dag-cbor block
+    ///
+    /// The map keys are written in the dag-cbor canonical order (by length, then bytewise), so the same
+    /// metadata always encodes to the same block.
+    pub fn to_block(&self) -> Vec<u8> {
+        let text = |s: &str| Value::Text(s.to_owned());
+        let mut entries = vec![(text("type"), text(APPENDIX_TYPE))];
+        if let Some(created) = self.created {
+            entries.push((text("created"), Value::Integer(created.into())));
+        }
+        entries.push((text("version"), Value::Integer(APPENDIX_VERSION.into())));
+        if !self.contents.is_empty() {
+            let contents = self
+                .contents
+                .iter()
+                .map(|content| {
+                    Value::Map(vec![
+                        (text("root"), Tag42Encoding::Prefixed.encode(&content.root)),
+                        (text("description"), text(&content.description)),
+                    ])
+                })
+                .collect();
+            entries.push((text("contents"), Value::Array(contents)));
+        }
+        if let Some(producer) = &self.producer {
+            entries.push((text("producer"), text(producer)));
+        }
+        if let Some(description) = &self.description {
+            entries.push((text("description"), text(description)));
+        }
+
+        let mut block = Vec::new();
+        // Serializing into a Vec never fails
+        let _ = ciborium::ser::into_writer(&Value::Map(entries), &mut block);
+        block
+    }
+
+    /// Encode the appendix as a section, with a CIDv1 (dag-cbor, sha2-256)
+    ///
+    /// The CID of the section must be added as the last root of the archive, and the section written last.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::appendix::{Appendix, find_appendix};
+    /// use navira_car::wire::{cid::RawCid, v1::CarWriter};
+    /// use std::collections::HashMap;
+    ///
+    /// let root = RawCid::from_hex("0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4").unwrap();
+    /// let appendix = Appendix::new().with_producer("my-packer 1.0");
+    /// let section = appendix.to_section();
+    /// let roots = vec![root, section.cid().clone()];
+    /// let mut writer = CarWriter::new(roots.clone());
+    /// // ... write the sections of the content, then the appendix last
+    /// writer.write_section(&section).unwrap();
+    ///
+    /// let mut blocks = HashMap::new();
+    /// blocks.insert(section.cid().clone(), section.block().data().to_vec());
+    /// let (cid, found) = find_appendix(&roots, &mut blocks).unwrap().unwrap();
+    /// assert_eq!((&cid, &found), (section.cid(), &appendix));
+    /// ```
+    #[cfg(feature = "pack")]
+    #[doc(cfg(feature = "pack"))]
+    pub fn to_section(&self) -> Section {
+        use sha2::{Digest, Sha256};
+
+        let block = self.to_block();
+        let mut cid = Vec::with_capacity(DAG_CBOR_SHA2_256_PREFIX.len() + 32);
+        cid.extend_from_slice(&DAG_CBOR_SHA2_256_PREFIX);
+        cid.extend_from_slice(&Sha256::digest(&block));
+        Section::from((RawCid::new(cid), block))
+    }
+
+    /// Decode an appendix block
+    ///
+    /// # Returns
+    /// * `Ok(Appendix)` - The decoded metadata
+    /// * `Err(AppendixError)` - The block is not an appendix, or is malformed
+    pub fn from_block(data: &[u8]) -> Result<Self, AppendixError> {
+        let value = dagcbor::decode(data)?;
+        let entries = value.as_map().ok_or(AppendixError::NotAnAppendix)?;
+        let get = |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v)
+        };
+        if get("type").and_then(Value::as_text) != Some(APPENDIX_TYPE) {
+            return Err(AppendixError::NotAnAppendix);
+        }
+        let version = get("version")
+            .and_then(as_u64)
+            .ok_or(AppendixError::InvalidField("version"))?;
+        if version > APPENDIX_VERSION {
+            return Err(AppendixError::UnsupportedVersion(version));
+        }
+
+        let text = |key: &'static str| match get(key) {
+            None => Ok(None),
+            Some(value) => value
+                .as_text()
+                .map(|s| Some(s.to_owned()))
+                .ok_or(AppendixError::InvalidField(key)),
+        };
+        let created = match get("created") {
+            None => None,
+            Some(value) => Some(as_u64(value).ok_or(AppendixError::InvalidField("created"))?),
+        };
+        let contents = match get("contents") {
+            None => Vec::new(),
+            Some(value) => value
+                .as_array()
+                .ok_or(AppendixError::InvalidField("contents"))?
+                .iter()
+                .map(content_description)
+                .collect::<Option<_>>()
+                .ok_or(AppendixError::InvalidField("contents"))?,
+        };
+        Ok(Self {
+            producer: text("producer")?,
+            created,
+            description: text("description")?,
+            contents,
+        })
+    }
+}
+
+impl fmt::Display for Appendix {
+    /// Human-readable rendering of the metadata, one field per line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(producer) = &self.producer {
+            writeln!(f, "producer: {}", producer)?;
+        }
+        if let Some(created) = self.created {
+            writeln!(f, "created: {} (Unix time)", created)?;
+        }
+        if let Some(description) = &self.description {
+            writeln!(f, "description: {}", description)?;
+        }
+        for content in &self.contents {
+            writeln!(
+                f,
+                "content {}: {}",
+                content.root.to_cid_string(),
+                content.description
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode an unsigned CBOR integer
+fn as_u64(value: &Value) -> Option<u64> {
+    value.as_integer().and_then(|i| u64::try_from(i).ok())
+}
+
+/// Decode an entry of the `contents` list
+fn content_description(value: &Value) -> Option<ContentDescription> {
+    let entries = value.as_map()?;
+    let get = |key: &str| {
+        entries
+            .iter()
+            .find(|(k, _)| k.as_text() == Some(key))
+            .map(|(_, v)| v)
+    };
+    Some(ContentDescription {
+        root: dagcbor::as_link(get("root")?)?,
+        description: get("description")?.as_text()?.to_owned(),
+    })
+}
+
+/// Roots which may be the appendix of an archive: its secondary dag-cbor roots, from the last one
+///
+/// The roots are expected in header order: the first root is never an appendix.
+pub fn appendix_candidates(roots: &[RawCid]) -> impl Iterator<Item = &RawCid> {
+    roots
+        .iter()
+        .skip(1)
+        .rev()
+        .filter(|root| cid_codec(root) == Some(CODEC_DAG_CBOR))
+}
+
+/// Find the appendix of an archive, given its roots and a source of its blocks
+///
+/// The secondary dag-cbor roots are tried from the last one, the first one decoding as an appendix is returned.
+/// Candidates missing from the source, or which are not appendices, are skipped.
+///
+/// # Returns
+/// * `Ok(Some((RawCid, Appendix)))` - The CID of the appendix block, and its metadata
+/// * `Ok(None)` - The archive has no appendix
+/// * `Err(S::Error)` - The source failed to retrieve a candidate block
+pub fn find_appendix<S: BlockSource>(
+    roots: &[RawCid],
+    source: &mut S,
+) -> Result<Option<(RawCid, Appendix)>, S::Error> {
+    for candidate in appendix_candidates(roots) {
+        if let Some(data) = source.get_block(candidate)?
+            && let Ok(appendix) = Appendix::from_block(&data)
+        {
+            return Ok(Some((candidate.clone(), appendix)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata;
+
+    #[test]
+    fn test_appendix_round_trip() {
+        let appendix = Appendix::new()
+            .with_producer("navira-car")
+            .with_created(1_700_000_000)
+            .with_description("Fixtures")
+            .with_content(testdata::CARV1_BASIC_SECTIONS[0].cid(), "blip")
+            .with_content(testdata::CARV1_BASIC_SECTIONS[6].cid(), "aaaa");
+        let block = appendix.to_block();
+        assert_eq!(Appendix::from_block(&block).unwrap(), appendix);
+
+        // Keys are written in the dag-cbor canonical order
+        let value = dagcbor::decode(&block).unwrap();
+        let keys: Vec<_> = value
+            .as_map()
+            .unwrap()
+            .iter()
+            .map(|(k, _)| k.as_text().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "type",
+                "created",
+                "version",
+                "contents",
+                "producer",
+                "description"
+            ]
+        );
+        // The links to the contents are visible to the DAG traversals
+        assert_eq!(
+            crate::ipld::block_links(&RawCid::new(vec![0x01, 0x71]), &block).len(),
+            2
+        );
+
+        let minimal = Appendix::new();
+        assert_eq!(Appendix::from_block(&minimal.to_block()).unwrap(), minimal);
+        assert!(appendix.to_string().contains("created: 1700000000"));
+    }
+
+    #[test]
+    fn test_appendix_invalid_blocks() {
+        let encode = |value: Value| {
+            let mut block = Vec::new();
+            ciborium::ser::into_writer(&value, &mut block).unwrap();
+            block
+        };
+        let text = |s: &str| Value::Text(s.to_owned());
+        assert!(matches!(
+            Appendix::from_block(b"\xff"),
+            Err(AppendixError::Decode(_))
+        ));
+        let block = encode(Value::Map(vec![(text("type"), text("other"))]));
+        assert!(matches!(
+            Appendix::from_block(&block),
+            Err(AppendixError::NotAnAppendix)
+        ));
+        let block = encode(Value::Map(vec![
+            (text("type"), text(APPENDIX_TYPE)),
+            (text("version"), Value::Integer(2.into())),
+        ]));
+        assert!(matches!(
+            Appendix::from_block(&block),
+            Err(AppendixError::UnsupportedVersion(2))
+        ));
+        let block = encode(Value::Map(vec![
+            (text("type"), text(APPENDIX_TYPE)),
+            (text("version"), Value::Integer(1.into())),
+            (text("created"), text("yesterday")),
+        ]));
+        assert!(matches!(
+            Appendix::from_block(&block),
+            Err(AppendixError::InvalidField("created"))
+        ));
+        // Unknown keys are ignored
+        let block = encode(Value::Map(vec![
+            (text("type"), text(APPENDIX_TYPE)),
+            (text("version"), Value::Integer(1.into())),
+            (text("license"), text("MIT")),
+        ]));
+        assert_eq!(Appendix::from_block(&block).unwrap(), Appendix::new());
+    }
+
+    #[test]
+    #[cfg(feature = "pack")]
+    fn test_find_appendix() {
+        let appendix = Appendix::new().with_producer("navira-car");
+        let section = appendix.to_section();
+        // The second root of carv1-basic.car is a dag-cbor block, but not an appendix
+        let limbo = testdata::CARV1_BASIC_SECTIONS[7].cid();
+        let mut blocks = testdata::carv1_basic_blocks();
+        let roots = vec![testdata::CARV1_BASIC_SECTIONS[0].cid(), limbo.clone()];
+        assert!(find_appendix(&roots, &mut blocks).unwrap().is_none());
+
+        blocks.insert(section.cid().clone(), section.block().data().to_vec());
+        let roots = vec![
+            testdata::CARV1_BASIC_SECTIONS[0].cid(),
+            section.cid().clone(),
+            limbo,
+        ];
+        let (cid, found) = find_appendix(&roots, &mut blocks).unwrap().unwrap();
+        assert_eq!(&cid, section.cid());
+        assert_eq!(found, appendix);
+    }
+}
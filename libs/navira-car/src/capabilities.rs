@@ -0,0 +1,251 @@
+//! Runtime introspection of the wire behaviors supported by this build
+//!
+//! Applications negotiating with peers, or validating the configuration of their operators, need to know what
+//! this build of navira-car supports: which CAR formats and index types it reads, which CID versions it parses,
+//! which hash functions it verifies. Rather than hard-coding it (and drifting from the library), they can ask
+//! [capabilities].
+//!
+//! The capabilities are read from the registries the readers actually use ([CarFormat::ALL], [IndexType::ALL],
+//! [SUPPORTED_CID_VERSIONS], [VERIFICATION_HASHES], ...), so they change along with the library: this is a
+//! structured changelog of its wire behaviors.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::{CarFormat, capabilities};
+//!
+//! let capabilities = navira_car::capabilities();
+//! assert!(capabilities.supports_format(CarFormat::V2));
+//! assert!(capabilities.supports_cid_version(1));
+//! // Identity CIDs can always be verified
+//! assert!(capabilities.supports_hash(0x00));
+//! println!("{}", capabilities);
+//! ```
+
+use std::fmt;
+
+use crate::read::CarFormat;
+use crate::verify::VERIFICATION_HASHES;
+use crate::wire::cid::SUPPORTED_CID_VERSIONS;
+use crate::wire::v2::IndexType;
+use crate::wire::varint::UnsignedVarint;
+
+/// Optional features of the crate, with whether they are enabled in this build
+const FEATURES: [(&str, bool); 11] = [
+    ("std-io", cfg!(feature = "std-io")),
+    ("tokio", cfg!(feature = "tokio")),
+    ("bytes", cfg!(feature = "bytes")),
+    ("cid", cfg!(feature = "cid")),
+    ("hashing", cfg!(feature = "hashing")),
+    ("pack", cfg!(feature = "pack")),
+    ("encryption", cfg!(feature = "encryption")),
+    ("unixfs", cfg!(feature = "unixfs")),
+    ("tracing", cfg!(feature = "tracing")),
+    ("test-fixtures", cfg!(feature = "test-fixtures")),
+    ("test-util", cfg!(feature = "test-util")),
+];
+
+/// Wire behaviors supported by this build of the library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Version of the library
+    pub version: &'static str,
+    /// CAR formats read by the [CarReader](crate::CarReader)
+    pub formats: Vec<CarFormat>,
+    /// CAR v2 index types decoded by the index parser
+    pub index_types: Vec<IndexType>,
+    /// Maximal length of the varints (section lengths, CID fields), in bytes
+    pub max_varint_length: usize,
+    /// CID versions parsed in strict mode (other versions are only carried in lenient mode)
+    pub cid_versions: Vec<u64>,
+    /// Multihash codes of the hash functions verified against the block data
+    pub verification_hashes: Vec<u64>,
+    /// Optional features enabled in this build (e.g. `std-io`, `pack`)
+    pub features: Vec<&'static str>,
+}
+
+impl Capabilities {
+    /// Is this CAR format read?
+    pub fn supports_format(&self, format: CarFormat) -> bool {
+        self.formats.contains(&format)
+    }
+
+    /// Is this CAR v2 index type (multicodec code) decoded?
+    pub fn supports_index_type(&self, code: u64) -> bool {
+        self.index_types
+            .iter()
+            .any(|index_type| index_type.code() == code)
+    }
+
+    /// Is this CID version parsed in strict mode?
+    pub fn supports_cid_version(&self, version: u64) -> bool {
+        self.cid_versions.contains(&version)
+    }
+
+    /// Is the hash function with this multihash code verified?
+    pub fn supports_hash(&self, code: u64) -> bool {
+        self.verification_hashes.contains(&code)
+    }
+
+    /// Is this optional feature enabled?
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    /// Human-readable rendering of the capabilities, one entry per line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |items: Vec<String>| items.join(", ");
+        writeln!(f, "navira-car {}", self.version)?;
+        writeln!(
+            f,
+            "formats: {}",
+            list(
+                self.formats
+                    .iter()
+                    .map(|format| format!("CARv{}", format.version()))
+                    .collect()
+            )
+        )?;
+        writeln!(
+            f,
+            "index types: {}",
+            list(
+                self.index_types
+                    .iter()
+                    .map(|index_type| format!("{:?} ({:#06x})", index_type, index_type.code()))
+                    .collect()
+            )
+        )?;
+        writeln!(f, "max varint length: {} bytes", self.max_varint_length)?;
+        writeln!(
+            f,
+            "CID versions: {}",
+            list(
+                self.cid_versions
+                    .iter()
+                    .map(|version| format!("v{}", version))
+                    .collect()
+            )
+        )?;
+        writeln!(
+            f,
+            "verification hashes: {}",
+            list(
+                self.verification_hashes
+                    .iter()
+                    .map(|code| format!("{:#04x}", code))
+                    .collect()
+            )
+        )?;
+        writeln!(f, "features: {}", self.features.join(", "))
+    }
+}
+
+/// Introspect the wire behaviors supported by this build of the library
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        formats: CarFormat::ALL.to_vec(),
+        index_types: IndexType::ALL.to_vec(),
+        max_varint_length: UnsignedVarint::MAX_LENGTH,
+        cid_versions: SUPPORTED_CID_VERSIONS.to_vec(),
+        verification_hashes: VERIFICATION_HASHES.to_vec(),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| *feature)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata;
+    use crate::verify::{DigestComparison, DigestError, verify_block};
+    use crate::wire::cid::{CidFormatError, RawCid};
+
+    #[test]
+    fn test_capabilities_formats() {
+        let capabilities = capabilities();
+        for format in &capabilities.formats {
+            let fixture = match format.version() {
+                1 => testdata::CARV1_BASIC,
+                2 => testdata::CARV2_BASIC,
+                version => panic!("No fixture for CARv{}", version),
+            };
+            let reader = crate::CarReader::from_bytes(fixture).unwrap();
+            assert_eq!(reader.get_format(), Some(*format));
+        }
+        assert_eq!(capabilities.formats.len(), CarFormat::ALL.len());
+    }
+
+    #[test]
+    fn test_capabilities_index_types() {
+        let capabilities = capabilities();
+        for index_type in &capabilities.index_types {
+            assert_eq!(IndexType::from_u64(index_type.code()), Some(*index_type));
+            assert!(capabilities.supports_index_type(index_type.code()));
+        }
+        assert_eq!(IndexType::from_u64(0x0402), None);
+        assert!(!capabilities.supports_index_type(0x0402));
+    }
+
+    #[test]
+    fn test_capabilities_varints_and_cids() {
+        let capabilities = capabilities();
+        let longest = UnsignedVarint(u64::MAX).encode();
+        assert_eq!(longest.len(), capabilities.max_varint_length);
+        assert!(UnsignedVarint::decode(&longest).is_some());
+        let mut too_long = vec![0x80; capabilities.max_varint_length];
+        too_long.push(0x01);
+        assert!(UnsignedVarint::decode(&too_long).is_none());
+
+        let digest = [0x12, 0x20].into_iter().chain([0xab; 32]);
+        for version in &capabilities.cid_versions {
+            let cid: Vec<u8> = match version {
+                0 => digest.clone().collect(),
+                version => [*version as u8, 0x55]
+                    .into_iter()
+                    .chain(digest.clone())
+                    .collect(),
+            };
+            let (parsed, _) = RawCid::try_read_bytes(&cid).unwrap();
+            assert_eq!(parsed.version(), Some(*version));
+        }
+        let next = capabilities.cid_versions.iter().max().unwrap() + 1;
+        let cid: Vec<u8> = [next as u8, 0x55].into_iter().chain(digest).collect();
+        assert!(matches!(
+            RawCid::try_read_bytes(&cid),
+            Err(CidFormatError::UnsupportedVersion)
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_hashes() {
+        let capabilities = capabilities();
+        for code in &capabilities.verification_hashes {
+            let cid = RawCid::new(
+                [0x01, 0x55]
+                    .into_iter()
+                    .chain(UnsignedVarint(*code).encode())
+                    .chain([0x02, 0xaa, 0xbb])
+                    .collect(),
+            );
+            assert!(!matches!(
+                verify_block(&cid, b"", DigestComparison::Variable),
+                Err(DigestError::UnsupportedHash(_))
+            ));
+        }
+        // sha2-512 is not supported
+        let cid = RawCid::new(vec![0x01, 0x55, 0x13, 0x02, 0xaa, 0xbb]);
+        assert!(!capabilities.supports_hash(0x13));
+        assert_eq!(
+            verify_block(&cid, b"", DigestComparison::Variable),
+            Err(DigestError::UnsupportedHash(0x13))
+        );
+        assert_eq!(capabilities.has_feature("pack"), cfg!(feature = "pack"));
+        assert!(capabilities.to_string().contains("formats: CARv1, CARv2\n"));
+    }
+}
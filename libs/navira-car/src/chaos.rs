@@ -0,0 +1,434 @@
+//! Adversarial feeding of the sans-IO readers, for tests
+//!
+//! The sans-IO readers request data with `InsufficientData(offset, hint)`, and the caller is free to answer
+//! with any amount of data, at any position. [ChaosFeeder] drives a reader with adversarial answers: 1-byte
+//! feeds, chunk boundaries inside varints and CIDs, repeated regions, and data fed at unrelated positions.
+//! It checks that the reader always either progresses or requests data coherently, and the outcome of the run
+//! (items read and final state) can be compared to a run fed with the whole input at once.
+//!
+//! [assert_consistent] runs a battery of chunkings against a reader, and panics on the first inconsistency.
+//!
+//! This module is only available with the `test-util` feature, usually enabled in `[dev-dependencies]`:
+//! ```toml
+//! [dev-dependencies]
+//! navira-car = { version = "*", features = ["test-util"] }
+//! ```
+//!
+//! ## Examples
+//! ```
+//! use navira_car::CarReader;
+//! use navira_car::chaos::{ChaosFeeder, Chunking, assert_consistent};
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let outcome = ChaosFeeder::new(car_bytes)
+//!     .with_chunking(Chunking::Fixed(1))
+//!     .run(CarReader::new())
+//!     .unwrap();
+//! assert_eq!(outcome.items.len(), 1 + 8); // header and sections
+//!
+//! assert_consistent(car_bytes, CarReader::new);
+//! ```
+
+use std::fmt::Debug;
+
+use crate::wire::{
+    v1::{self, LocatableSection},
+    v2,
+};
+use crate::{CarReader, CarReaderError};
+
+/// Outcome of a single step of a sans-IO reader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step<T> {
+    /// The reader progressed, and produced an item
+    Item(T),
+    /// The reader needs data, from this offset (with a length hint, possibly 0)
+    NeedData(usize, usize),
+    /// The reader reached the end of its input
+    Done,
+    /// The reader failed, with this error
+    Failed(String),
+}
+
+/// A sans-IO reader, which can be driven by a [ChaosFeeder]
+pub trait SansIoReader {
+    /// Items produced by the reader
+    type Item: Debug + PartialEq;
+
+    /// Feed data to the reader, at the given position of the input
+    fn receive_data(&mut self, buf: &[u8], pos: usize);
+
+    /// Attempt to make progress
+    fn step(&mut self) -> Step<Self::Item>;
+}
+
+/// Items produced by the CAR readers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadEvent {
+    /// The header was read
+    Header,
+    /// A section was read
+    Section(LocatableSection),
+}
+
+macro_rules! impl_sans_io_reader {
+    ($reader:ty, $error:ty) => {
+        impl SansIoReader for $reader {
+            type Item = ReadEvent;
+
+            fn receive_data(&mut self, buf: &[u8], pos: usize) {
+                <$reader>::receive_data(self, buf, pos)
+            }
+
+            fn step(&mut self) -> Step<ReadEvent> {
+                type ReaderError = $error;
+                let result = if self.has_header() {
+                    self.read_section().map(ReadEvent::Section)
+                } else {
+                    self.read_header().map(|_| ReadEvent::Header)
+                };
+                match result {
+                    Ok(event) => Step::Item(event),
+                    Err(ReaderError::InsufficientData(offset, hint)) => {
+                        Step::NeedData(offset, hint)
+                    }
+                    Err(e) => end_of_sections(e),
+                }
+            }
+        }
+    };
+}
+
+/// Map a terminal error of a reader to a step
+fn end_of_sections<E: Debug>(error: E) -> Step<ReadEvent> {
+    let error = format!("{:?}", error);
+    if error == "EndOfSections" {
+        Step::Done
+    } else {
+        Step::Failed(error)
+    }
+}
+
+impl_sans_io_reader!(CarReader, CarReaderError);
+impl_sans_io_reader!(v1::CarReader, v1::CarReaderError);
+impl_sans_io_reader!(v2::CarReader, v2::CarReaderError);
+
+/// How the requested data is cut into chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunking {
+    /// Feed everything from the requested offset to the end of the input
+    Whole,
+    /// Feed chunks of at most this size (1 for byte-per-byte feeding)
+    Fixed(usize),
+    /// Feed chunks of a pseudo-random size, between 1 and this size
+    Random(usize),
+    /// Cut the chunks at these absolute offsets of the input (e.g. inside varints and CIDs)
+    SplitAt(Vec<usize>),
+}
+
+/// Final state of a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChaosEnd {
+    /// The reader reached the end of its input
+    Done,
+    /// The reader requested data past the end of the input, at this offset
+    EndOfInput(usize),
+    /// The reader failed, with this error
+    Failed(String),
+}
+
+/// Outcome of a run of a [ChaosFeeder]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChaosOutcome<T> {
+    /// Items produced by the reader, in order
+    pub items: Vec<T>,
+    /// Final state of the reader
+    pub end: ChaosEnd,
+    /// Number of steps of the reader
+    pub steps: usize,
+    /// Number of chunks fed to the reader
+    pub feeds: usize,
+}
+
+/// Incoherent behaviors of a reader, detected by a [ChaosFeeder]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ChaosError {
+    /// The reader requested the same offset again after being fed from it, without progressing
+    #[error("Stalled at offset {offset} (hint: {hint}) after {steps} steps")]
+    Stalled {
+        /// Requested offset
+        offset: usize,
+        /// Requested length hint
+        hint: usize,
+        /// Number of steps before the stall
+        steps: usize,
+    },
+    /// The reader did not finish within the step budget
+    #[error("Step budget of {0} exceeded")]
+    StepBudgetExceeded(usize),
+}
+
+/// Driver of a sans-IO reader, with adversarial feeding
+#[derive(Debug, Clone)]
+pub struct ChaosFeeder<'a> {
+    /// Whole input of the reader
+    input: &'a [u8],
+    /// How the requested data is cut into chunks
+    chunking: Chunking,
+    /// Deliver again every n-th chunk, extended with the next bytes (disabled if None)
+    repeat_every: Option<usize>,
+    /// Number of chunks fed at unrelated positions, at pseudo-random points of the run
+    stray_feeds: usize,
+    /// Seed of the pseudo-random generator
+    seed: u64,
+    /// Maximum number of steps of the reader
+    max_steps: usize,
+}
+
+impl<'a> ChaosFeeder<'a> {
+    /// Create a feeder of the input, feeding the whole requested data at once
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            chunking: Chunking::Whole,
+            repeat_every: None,
+            stray_feeds: 0,
+            seed: 0x9e37_79b9_7f4a_7c15,
+            max_steps: 64 * (input.len() + 64),
+        }
+    }
+
+    /// Set how the requested data is cut into chunks
+    pub fn with_chunking(mut self, chunking: Chunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Deliver again every n-th chunk (a repeated region), extended with the next bytes
+    ///
+    /// The reader must only keep the bytes it did not receive yet.
+    pub fn with_repeats(mut self, every: usize) -> Self {
+        self.repeat_every = Some(every.max(1));
+        self
+    }
+
+    /// Feed chunks at unrelated positions (out of order), at pseudo-random points of the run
+    ///
+    /// The reader must keep requesting data coherently afterwards. As the readers may take such data as an
+    /// implicit seek (see [v1::CarReader::receive_data]), their outcome may differ from the reference run.
+    pub fn with_stray_feeds(mut self, count: usize) -> Self {
+        self.stray_feeds = count;
+        self
+    }
+
+    /// Set the seed of the pseudo-random chunk sizes and stray positions
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed.max(1);
+        self
+    }
+
+    /// Set the maximum number of steps of the reader
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Size of the next chunk fed from the offset
+    fn chunk_len(&self, offset: usize, rng: &mut u64) -> usize {
+        let remaining = self.input.len() - offset;
+        let len = match &self.chunking {
+            Chunking::Whole => remaining,
+            Chunking::Fixed(size) => *size,
+            Chunking::Random(max) => 1 + (next_random(rng) % (*max).max(1) as u64) as usize,
+            Chunking::SplitAt(cuts) => cuts
+                .iter()
+                .filter(|&&cut| cut > offset)
+                .min()
+                .map_or(remaining, |cut| cut - offset),
+        };
+        len.clamp(1, remaining)
+    }
+
+    /// Drive the reader until it finishes, fails, or requests data past the end of the input
+    ///
+    /// # Returns
+    /// * `Ok(ChaosOutcome)` - The items produced by the reader, and its final state
+    /// * `Err(ChaosError)` - The reader stalled, or exceeded the step budget
+    pub fn run<R: SansIoReader>(&self, mut reader: R) -> Result<ChaosOutcome<R::Item>, ChaosError> {
+        let mut rng = self.seed;
+        let mut items = Vec::new();
+        let mut feeds = 0;
+        let mut stray_feeds = self.stray_feeds;
+        // Offset the reader was last fed from (following its request), cleared on progress
+        let mut fed_from = None;
+        for steps in 0..self.max_steps {
+            let (offset, hint) = match reader.step() {
+                Step::Item(item) => {
+                    items.push(item);
+                    fed_from = None;
+                    continue;
+                }
+                Step::NeedData(offset, hint) => (offset, hint),
+                Step::Done => return Ok(ChaosOutcome::new(items, ChaosEnd::Done, steps, feeds)),
+                Step::Failed(e) => {
+                    return Ok(ChaosOutcome::new(items, ChaosEnd::Failed(e), steps, feeds));
+                }
+            };
+            if offset >= self.input.len() {
+                let end = ChaosEnd::EndOfInput(offset);
+                return Ok(ChaosOutcome::new(items, end, steps, feeds));
+            }
+            if fed_from == Some(offset) {
+                return Err(ChaosError::Stalled {
+                    offset,
+                    hint,
+                    steps,
+                });
+            }
+
+            feeds += 1;
+            if stray_feeds > 0 && next_random(&mut rng).is_multiple_of(4) {
+                stray_feeds -= 1;
+                let pos = (next_random(&mut rng) % self.input.len() as u64) as usize;
+                let len = self.chunk_len(pos, &mut rng);
+                reader.receive_data(&self.input[pos..pos + len], pos);
+                // The reader must request its data again
+                fed_from = None;
+                continue;
+            }
+            let len = self.chunk_len(offset, &mut rng);
+            reader.receive_data(&self.input[offset..offset + len], offset);
+            if let Some(every) = self.repeat_every
+                && feeds.is_multiple_of(every)
+            {
+                // Deliver the chunk again, overlapping with the next bytes
+                let end = (offset + 2 * len).min(self.input.len());
+                reader.receive_data(&self.input[offset..end], offset);
+            }
+            fed_from = Some(offset);
+        }
+        Err(ChaosError::StepBudgetExceeded(self.max_steps))
+    }
+}
+
+impl<T> ChaosOutcome<T> {
+    fn new(items: Vec<T>, end: ChaosEnd, steps: usize, feeds: usize) -> Self {
+        Self {
+            items,
+            end,
+            steps,
+            feeds,
+        }
+    }
+}
+
+/// Next value of a xorshift64 pseudo-random generator
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Run a battery of adversarial feedings against fresh readers, and compare their outcomes to a whole feeding
+///
+/// The battery covers 1-byte feeds, small fixed and pseudo-random chunks, cuts at every offset of the first
+/// bytes (inside the header varint) and at regular intervals, and repeated regions. Runs with stray feeds
+/// are also checked, but only for stalls and step budget (see [ChaosFeeder::with_stray_feeds]).
+///
+/// # Panics
+/// If a reader stalls, exceeds its step budget, or ends with another outcome than the reference run.
+pub fn assert_consistent<R: SansIoReader>(input: &[u8], new_reader: impl Fn() -> R) {
+    let reference = ChaosFeeder::new(input)
+        .run(new_reader())
+        .unwrap_or_else(|e| panic!("reference run failed: {}", e));
+    let mut feeders = vec![
+        ChaosFeeder::new(input).with_chunking(Chunking::Fixed(1)),
+        ChaosFeeder::new(input).with_chunking(Chunking::Fixed(3)),
+        ChaosFeeder::new(input).with_chunking(Chunking::SplitAt((1..16).collect())),
+        ChaosFeeder::new(input)
+            .with_chunking(Chunking::SplitAt((0..input.len()).step_by(7).collect())),
+        ChaosFeeder::new(input)
+            .with_chunking(Chunking::Fixed(2))
+            .with_repeats(3),
+    ];
+    for seed in 1..=8 {
+        feeders.push(
+            ChaosFeeder::new(input)
+                .with_chunking(Chunking::Random(32))
+                .with_repeats(5)
+                .with_seed(seed),
+        );
+    }
+    for feeder in feeders {
+        let outcome = feeder
+            .run(new_reader())
+            .unwrap_or_else(|e| panic!("{:?}: {}", feeder.chunking, e));
+        assert_eq!(
+            (&outcome.items, &outcome.end),
+            (&reference.items, &reference.end),
+            "{:?} (repeats: {:?}, stray feeds: {:?}, seed: {}) diverged from the reference run",
+            feeder.chunking,
+            feeder.repeat_every,
+            feeder.stray_feeds,
+            feeder.seed
+        );
+    }
+    for seed in 1..=8 {
+        let feeder = ChaosFeeder::new(input)
+            .with_chunking(Chunking::Fixed(5))
+            .with_stray_feeds(4)
+            .with_seed(seed);
+        if let Err(e) = feeder.run(new_reader()) {
+            panic!("stray feeds (seed: {}): {}", seed, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{CARV1_BASIC, CARV2_BASIC};
+
+    #[test]
+    fn test_chaos_fixed_chunks() {
+        for input in [CARV1_BASIC, CARV2_BASIC] {
+            let reference = ChaosFeeder::new(input).run(CarReader::new()).unwrap();
+            let outcome = ChaosFeeder::new(input)
+                .with_chunking(Chunking::Fixed(1))
+                .run(CarReader::new())
+                .unwrap();
+            assert_eq!(outcome.items, reference.items);
+            assert!(outcome.feeds >= reference.feeds);
+        }
+    }
+
+    #[test]
+    fn test_chaos_consistent_readers() {
+        assert_consistent(CARV1_BASIC, CarReader::new);
+        assert_consistent(CARV2_BASIC, CarReader::new);
+        assert_consistent(CARV1_BASIC, v1::CarReader::new);
+        assert_consistent(CARV2_BASIC, v2::CarReader::new);
+    }
+
+    #[test]
+    fn test_chaos_detects_stalls() {
+        /// Always requests the start of the input
+        struct Stuck;
+        impl SansIoReader for Stuck {
+            type Item = ();
+            fn receive_data(&mut self, _: &[u8], _: usize) {}
+            fn step(&mut self) -> Step<()> {
+                Step::NeedData(0, 8)
+            }
+        }
+        assert_eq!(
+            ChaosFeeder::new(CARV1_BASIC).run(Stuck).unwrap_err(),
+            ChaosError::Stalled {
+                offset: 0,
+                hint: 8,
+                steps: 1
+            }
+        );
+    }
+}
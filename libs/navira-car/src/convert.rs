@@ -0,0 +1,326 @@
+//! Conversion of CAR archives between CAR v1 and CAR v2
+//!
+//! A CAR v2 archive is a CAR v1 payload wrapped in an envelope: a fixed-size header locating the payload, and an
+//! index of its sections. [v1_to_v2] wraps an existing CAR v1 stream in a single pass: the payload is copied byte
+//! for byte while the section boundaries are found (only the length prefixes and the CIDs are decoded, never the
+//! blocks), and a MultihashIndexSorted index of all the sections is appended after it.
+//!
+//! Archives received as CAR v1 can thus be converted once, their index being read back (or memory-mapped)
+//! instead of scanning the whole payload at each start.
+//!
+//! The other way around, [v2_to_v1] strips the envelope of a CAR v2 archive (see [PayloadExtractor]), for the tools
+//! which only accept CAR v1: the payload is copied byte for byte, the index is not even read.
+//!
+//! ## Examples
+//! ```
+//! use std::io::Cursor;
+//! use navira_car::CarFormat;
+//! use navira_car::convert::{v1_to_v2, v2_to_v1};
+//! use navira_car::stdio::CarReader;
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let mut car = Cursor::new(Vec::new());
+//! let summary = v1_to_v2(&mut &car_bytes[..], &mut car).unwrap();
+//! assert_eq!(summary.sections, 8);
+//! assert_eq!(summary.header.data_size, car_bytes.len() as u64);
+//!
+//! let car = car.into_inner();
+//! let reader = CarReader::open(Cursor::new(&car[..])).unwrap();
+//! assert_eq!(reader.get_format(), CarFormat::V2);
+//!
+//! // And back to the original CAR v1 stream
+//! let mut v1 = Vec::new();
+//! v2_to_v1(&mut &car[..], &mut v1).unwrap();
+//! assert_eq!(v1, car_bytes);
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::wire::v1::{CarReader, CarReaderError};
+use crate::wire::v2::{
+    self, CAR_V2_PRAGMA, CarV2Header, Characteristics, Index, IndexType, PayloadExtractor,
+};
+
+/// Size of the chunks read from the input
+const READ_SIZE: usize = 1024 * 1024;
+
+/// Summary of a conversion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertSummary {
+    /// Header of the CAR v2 archive
+    pub header: CarV2Header,
+    /// Number of sections of the payload (all of them being indexed)
+    pub sections: u64,
+    /// Number of bytes written to the output
+    pub bytes_written: u64,
+}
+
+/// Wrap a CAR v1 stream in a CAR v2 archive, with a full index of its sections
+///
+/// The CAR v1 stream is read once, to its end, and copied as is as the payload of the CAR v2 archive. The index
+/// lists all the sections (duplicates and identity CIDs included), so the archive is marked as fully indexed.
+///
+/// # Arguments
+/// * `input` - The CAR v1 stream, read from its start.
+/// * `output` - Where to write the CAR v2 archive, from its current position. The header is written last, so the
+///   output must be seekable.
+///
+/// # Returns
+/// * `Ok(ConvertSummary)` - The header of the CAR v2 archive, and statistics about it.
+/// * `Err(ConvertError)` - The input is not a valid CAR v1 stream (or is already a CAR v2 archive), or writing
+///   the output failed. The output may hold a partial archive, with a zeroed header.
+pub fn v1_to_v2<R, W>(input: &mut R, output: &mut W) -> Result<ConvertSummary, ConvertError>
+where
+    R: Read,
+    W: Write + Seek,
+{
+    let start = output.stream_position()?;
+    let data_offset = (CAR_V2_PRAGMA.len() + 40) as u64;
+    output.write_all(CAR_V2_PRAGMA)?;
+    output.write_all(&[0u8; 40])?;
+
+    let mut reader = CarReader::new();
+    let mut buf = vec![0u8; READ_SIZE];
+    // Number of bytes of the payload read (and copied), and end of the last section found
+    let mut read = 0;
+    let mut sections_end = 0;
+    let mut entries = Vec::new();
+    // First bytes of the input, to tell CAR v2 archives apart from invalid headers
+    let mut pragma = Vec::with_capacity(CAR_V2_PRAGMA.len());
+    loop {
+        let result = match reader.has_header() {
+            false => reader.read_header().map(|_| None),
+            true => reader.skip_section().map(Some),
+        };
+        match result {
+            Ok(None) => {}
+            Ok(Some((location, cid))) => {
+                sections_end = location.offset + location.length;
+                if let (Some(code), Some(digest)) = (cid.multihash_code(), cid.digest()) {
+                    entries.push((code, digest.to_vec(), location.offset));
+                }
+            }
+            Err(CarReaderError::InsufficientData(from, _)) => {
+                let len = match input.read(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if len == 0 {
+                    reader.finish(read);
+                    continue;
+                }
+                output.write_all(buf.get(..len).unwrap_or_default())?;
+                let missing = CAR_V2_PRAGMA.len() - pragma.len();
+                pragma.extend_from_slice(buf.get(..len.min(missing)).unwrap_or_default());
+                // The blocks are skipped: only the bytes from the requested offset are given to the reader
+                if from < read + len {
+                    let skip = from.saturating_sub(read);
+                    reader.receive_data(buf.get(skip..len).unwrap_or_default(), read + skip);
+                }
+                read += len;
+            }
+            Err(CarReaderError::EndOfSections) => break,
+            Err(_) if pragma == CAR_V2_PRAGMA => return Err(ConvertError::AlreadyV2),
+            Err(e) => return Err(ConvertError::Input(e)),
+        }
+    }
+    // The last block may have been skipped without being received
+    if sections_end > read as u64 {
+        let missing_bytes = (sections_end - read as u64) as usize;
+        return Err(ConvertError::Input(CarReaderError::Truncated {
+            missing_bytes,
+        }));
+    }
+
+    let index = Index::from_entries(
+        IndexType::MultihashIndexSorted,
+        entries
+            .iter()
+            .map(|(code, digest, offset)| (*code, digest.as_slice(), *offset)),
+    );
+    let index_bytes = index.encode();
+    output.write_all(&index_bytes)?;
+
+    let mut characteristics = Characteristics(0);
+    characteristics.set_has_full_index(true);
+    let header = CarV2Header {
+        characteristics,
+        data_offset,
+        data_size: read as u64,
+        index_offset: data_offset + read as u64,
+    };
+    let end = header.index_offset + index_bytes.len() as u64;
+    output.seek(SeekFrom::Start(start + CAR_V2_PRAGMA.len() as u64))?;
+    output.write_all(&<[u8; 40]>::from(&header))?;
+    output.seek(SeekFrom::Start(start + end))?;
+    output.flush()?;
+    Ok(ConvertSummary {
+        header,
+        sections: entries.len() as u64,
+        bytes_written: end,
+    })
+}
+
+/// Copy the CAR v1 payload of a CAR v2 archive, stripping its envelope
+///
+/// The input is read sequentially, up to the end of the payload only: the index (if any) is left unread. The
+/// output is a byte-exact copy of the payload, i.e. a valid CAR v1 stream if the CAR v2 archive is.
+///
+/// # Arguments
+/// * `input` - The CAR v2 archive, read from its start.
+/// * `output` - Where to write the CAR v1 stream.
+///
+/// # Returns
+/// * `Ok(CarV2Header)` - The header of the CAR v2 archive, locating the copied payload.
+/// * `Err(ConvertError)` - The input is not a valid CAR v2 archive, or is truncated, or writing the output
+///   failed. The output may hold a partial payload.
+pub fn v2_to_v1<R, W>(input: &mut R, output: &mut W) -> Result<CarV2Header, ConvertError>
+where
+    R: Read,
+    W: Write,
+{
+    let mut extractor = PayloadExtractor::new();
+    let mut buf = vec![0u8; READ_SIZE];
+    let mut read = 0;
+    while let Some((offset, length)) = extractor.next_read() {
+        // The padding is read (and dropped) along with the payload
+        let wanted = (offset + length - read).min(buf.len());
+        let len = match input.read(buf.get_mut(..wanted).unwrap_or_default()) {
+            Ok(0) => {
+                let missing_bytes = offset + length - read;
+                return Err(ConvertError::InputV2(v2::CarReaderError::Truncated {
+                    missing_bytes,
+                }));
+            }
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let payload = extractor
+            .extract(buf.get(..len).unwrap_or_default(), read)
+            .map_err(ConvertError::InputV2)?;
+        output.write_all(payload)?;
+        read += len;
+    }
+    output.flush()?;
+    // The payload is only complete once the header is read
+    let header = extractor.header().cloned();
+    header.ok_or(ConvertError::InputV2(v2::CarReaderError::InvalidFormat))
+}
+
+/// Errors related to the conversion of CAR archives
+#[derive(thiserror::Error, Debug)]
+pub enum ConvertError {
+    /// The input is not a valid CAR v1 stream
+    #[error("Invalid input: {0}")]
+    Input(CarReaderError),
+    /// The input is not a valid CAR v2 archive
+    #[error("Invalid CAR v2 input: {0}")]
+    InputV2(v2::CarReaderError),
+    /// The input is already a CAR v2 archive
+    #[error("The input is already a CAR v2 archive")]
+    AlreadyV2,
+    /// IO error while reading the input or writing the output
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::CarFormat;
+    use crate::stdio::CarReader as StdCarReader;
+    use crate::testdata::{CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC};
+
+    /// A reader returning a few bytes at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(7);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_v1_to_v2() {
+        let convert = |input: &mut dyn Read| {
+            let mut car = Cursor::new(vec![0xff; 10]);
+            car.seek(SeekFrom::End(0)).unwrap();
+            let summary = v1_to_v2(&mut { input }, &mut car).unwrap();
+            assert_eq!(car.position(), 10 + summary.bytes_written);
+            (summary, car.into_inner().split_off(10))
+        };
+        let (summary, car) = convert(&mut &CARV1_BASIC[..]);
+        assert_eq!(
+            convert(&mut Trickle(CARV1_BASIC)),
+            (summary.clone(), car.clone())
+        );
+        assert_eq!(summary.sections, 8);
+        assert!(summary.header.characteristics.has_full_index());
+        // The payload is the CAR v1 stream, byte for byte
+        let payload = summary.header.data_offset as usize..summary.header.index_offset as usize;
+        assert_eq!(&car[payload], CARV1_BASIC);
+
+        let mut reader = StdCarReader::open(Cursor::new(car)).unwrap();
+        assert_eq!(reader.get_format(), CarFormat::V2);
+        assert_eq!(reader.get_roots().len(), 2);
+        for section in CARV1_BASIC_SECTIONS {
+            let found = reader.find_section(&section.cid()).unwrap().unwrap();
+            assert_eq!(found.cid(), &section.cid());
+        }
+    }
+
+    #[test]
+    fn test_v2_to_v1() {
+        let mut v2 = Cursor::new(Vec::new());
+        v1_to_v2(&mut &CARV1_BASIC[..], &mut v2).unwrap();
+        let mut v2 = v2.into_inner();
+        v2.extend_from_slice(b"trailing bytes, never read");
+        for input in [&mut &v2[..] as &mut dyn Read, &mut Trickle(&v2)] {
+            let mut v1 = Vec::new();
+            let header = v2_to_v1(&mut { input }, &mut v1).unwrap();
+            assert_eq!(v1, CARV1_BASIC);
+            assert_eq!(header.data_size, CARV1_BASIC.len() as u64);
+        }
+
+        let mut v1 = Vec::new();
+        v2_to_v1(&mut &CARV2_BASIC[..], &mut v1).unwrap();
+        assert_eq!(
+            StdCarReader::open(Cursor::new(v1)).unwrap().get_format(),
+            CarFormat::V1
+        );
+
+        let err = v2_to_v1(&mut &v2[..300], &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            ConvertError::InputV2(v2::CarReaderError::Truncated { missing_bytes }) if missing_bytes == 51 + CARV1_BASIC.len() - 300
+        ));
+        let err = v2_to_v1(&mut &CARV1_BASIC[..], &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            ConvertError::InputV2(v2::CarReaderError::InvalidVersion)
+        ));
+    }
+
+    #[test]
+    fn test_v1_to_v2_errors() {
+        let convert = |input: &[u8]| v1_to_v2(&mut Trickle(input), &mut Cursor::new(Vec::new()));
+        // Cut in the middle of the header, of a section header, and of a block
+        for len in [20, 101, 103, CARV1_BASIC.len() - 1] {
+            let err = convert(&CARV1_BASIC[..len]).unwrap_err();
+            assert!(
+                matches!(err, ConvertError::Input(CarReaderError::Truncated { .. })),
+                "{len}: {err:?}"
+            );
+        }
+        // Already a CAR v2 archive
+        let err = convert(CARV2_BASIC).unwrap_err();
+        assert!(matches!(err, ConvertError::AlreadyV2));
+    }
+}
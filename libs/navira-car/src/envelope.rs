@@ -0,0 +1,736 @@
+//! Encryption envelopes of CAR archives
+//!
+//! Private datasets can be distributed through public IPFS infrastructure (pinning services, gateways, CAR
+//! mirrors) once their blocks are encrypted: this module seals the block payloads of an archive with a
+//! caller-provided AEAD key, and opens them back locally.
+//!
+//! Each block is sealed into a new raw block, `nonce || AEAD(varint(cid length) || plaintext CID || data)`,
+//! addressed by a CIDv1 (raw, sha2-256) computed over the sealed bytes, so the sealed archive is a valid CAR
+//! archive of opaque blocks. The plaintext CID travels inside the ciphertext, and is checked against the
+//! plaintext block when opened. Nonces are derived from the key, the plaintext CID and the block data
+//! ([EnvelopeCipher::nonce]), in the manner of SIV: sealing is deterministic, so the same content always seals
+//! to the same blocks and CIDs (only equal blocks can be told apart, under the same key), and two different
+//! blocks never share a nonce, even under the same (forged) CID.
+//!
+//! The sealed archive has a single root, the sealed [EnvelopeManifest]: a dag-cbor block mapping every
+//! plaintext root to the CID of its sealed block. The manifest is sealed as well, so nothing about the
+//! plaintext (not even its root CIDs) is disclosed to the public infrastructure.
+//!
+//! The cipher is pluggable through the [EnvelopeCipher] trait, and [ChaCha20Poly1305Key] implements it.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::envelope::{ChaCha20Poly1305Key, open_section, seal_section};
+//! use navira_car::wire::{cid::RawCid, v1::Section};
+//!
+//! let cid = RawCid::from_hex("0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4").unwrap();
+//! let section = Section::from((cid, b"aaaa".to_vec()));
+//! let key = ChaCha20Poly1305Key::new([7; 32]);
+//!
+//! let sealed = seal_section(&key, &section).unwrap();
+//! assert_ne!(sealed.cid(), section.cid());
+//! assert_eq!(open_section(&key, &sealed).unwrap(), section);
+//! assert!(open_section(&ChaCha20Poly1305Key::new([8; 32]), &sealed).is_err());
+//! ```
+
+use std::fmt;
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ciborium::Value;
+use sha2::{Digest, Sha256};
+
+use crate::ipld::dagcbor;
+use crate::pack::raw_block_cid;
+use crate::verify::{DigestComparison, DigestError, verify_block};
+use crate::wire::cid::{RawCid, Tag42Encoding};
+use crate::wire::v1::Section;
+use crate::wire::varint::UnsignedVarint;
+
+/// Value of the `type` key of the manifest blocks
+pub const ENVELOPE_TYPE: &str = "car-envelope";
+/// Version of the envelope convention written by this module
+pub const ENVELOPE_VERSION: u64 = 1;
+/// Length of the nonces, prepended to the sealed blocks
+pub const NONCE_LENGTH: usize = 12;
+
+/// CIDv1 prefix of dag-cbor blocks hashed with sha2-256 (version, codec, multihash code and length)
+const DAG_CBOR_SHA2_256_PREFIX: [u8; 4] = [0x01, 0x71, 0x12, 0x20];
+
+/// Errors related to the sealing and opening of envelopes
+#[derive(thiserror::Error, Debug)]
+pub enum EnvelopeError {
+    /// The AEAD cipher failed, e.g. the key is wrong or the sealed block was tampered with
+    #[error("Cannot seal or open the block: wrong key or tampered ciphertext")]
+    Cipher,
+    /// The sealed block is too short, or its plaintext CID can not be decoded
+    #[error("Malformed sealed block {}", .0.to_hex())]
+    MalformedBlock(RawCid),
+    /// The opened block does not match its plaintext CID
+    #[error(transparent)]
+    Digest(#[from] DigestError),
+    /// The manifest is not valid dag-cbor
+    #[error(transparent)]
+    Decode(#[from] dagcbor::DagCborError),
+    /// The block is not a manifest (not a map, or without the envelope `type`)
+    #[error("Not an envelope manifest")]
+    NotAnEnvelope,
+    /// The manifest was written with a later version of the convention
+    #[error("Unsupported envelope version {version}", version = .0)]
+    UnsupportedVersion(u64),
+    /// A known key of the manifest has a value of the wrong type
+    #[error("Invalid manifest field {field:?}", field = .0)]
+    InvalidField(&'static str),
+    /// The archive was sealed with another algorithm than the one of the cipher
+    #[error("Archive sealed with {found}, but the cipher is {expected}")]
+    AlgorithmMismatch {
+        /// Algorithm of the cipher
+        expected: String,
+        /// Algorithm recorded in the manifest
+        found: String,
+    },
+    /// A root of the archive is not one of its sections, so it can not be sealed
+    #[cfg(feature = "std-io")]
+    #[error("Missing root {}", .0.to_hex())]
+    MissingRoot(RawCid),
+    /// The archive could not be read
+    #[cfg(feature = "std-io")]
+    #[error(transparent)]
+    Reader(#[from] crate::stdio::CarReaderError),
+    /// The archive could not be written
+    #[cfg(feature = "std-io")]
+    #[error(transparent)]
+    Writer(#[from] crate::wire::v1::CarWriterError),
+    /// I/O error while writing the archive
+    #[cfg(feature = "std-io")]
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// An AEAD cipher sealing the blocks of an archive, with a caller-provided key
+///
+/// Implementations must derive the nonces from a secret (usually the key), otherwise the nonces of the
+/// sealed blocks would disclose their plaintext CIDs, and from the whole plaintext (CID and data), otherwise
+/// two blocks under the same CID would be sealed with the same nonce.
+pub trait EnvelopeCipher {
+    /// Name of the algorithm, recorded in the manifest (e.g. `chacha20-poly1305`)
+    fn algorithm(&self) -> &str;
+
+    /// Derive the nonce sealing this plaintext block
+    fn nonce(&self, cid: &RawCid, data: &[u8]) -> [u8; NONCE_LENGTH];
+
+    /// Encrypt and authenticate a payload
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The ciphertext, with its authentication tag
+    /// * `Err(EnvelopeError::Cipher)` - The payload can not be sealed
+    fn seal(&self, nonce: &[u8; NONCE_LENGTH], plaintext: &[u8]) -> Result<Vec<u8>, EnvelopeError>;
+
+    /// Authenticate and decrypt a payload
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The plaintext
+    /// * `Err(EnvelopeError::Cipher)` - Wrong key, or the ciphertext was tampered with
+    fn open(&self, nonce: &[u8; NONCE_LENGTH], ciphertext: &[u8])
+    -> Result<Vec<u8>, EnvelopeError>;
+}
+
+/// A 256-bit ChaCha20-Poly1305 key (RFC 8439)
+///
+/// Nonces are the first 96 bits of
+/// `sha2-256("navira-car envelope nonce" || key || varint(cid length) || plaintext CID || data)`.
+#[derive(Clone)]
+pub struct ChaCha20Poly1305Key {
+    key: [u8; 32],
+}
+
+impl ChaCha20Poly1305Key {
+    /// Name of the algorithm, as recorded in the manifests
+    pub const ALGORITHM: &'static str = "chacha20-poly1305";
+
+    /// Use this key to seal and open the blocks
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&Key::from(self.key))
+    }
+}
+
+impl fmt::Debug for ChaCha20Poly1305Key {
+    /// The key itself is never printed
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChaCha20Poly1305Key")
+            .finish_non_exhaustive()
+    }
+}
+
+impl EnvelopeCipher for ChaCha20Poly1305Key {
+    fn algorithm(&self) -> &str {
+        Self::ALGORITHM
+    }
+
+    fn nonce(&self, cid: &RawCid, data: &[u8]) -> [u8; NONCE_LENGTH] {
+        let digest = Sha256::new()
+            .chain_update(b"navira-car envelope nonce")
+            .chain_update(self.key)
+            .chain_update(UnsignedVarint(cid.bytes().len() as u64).encode())
+            .chain_update(cid.bytes())
+            .chain_update(data)
+            .finalize();
+        // The nonce is the digest prefix
+        let mut nonce = [0u8; NONCE_LENGTH];
+        nonce.iter_mut().zip(digest).for_each(|(byte, d)| *byte = d);
+        nonce
+    }
+
+    fn seal(&self, nonce: &[u8; NONCE_LENGTH], plaintext: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        self.cipher()
+            .encrypt(&Nonce::from(*nonce), plaintext)
+            .map_err(|_| EnvelopeError::Cipher)
+    }
+
+    fn open(
+        &self,
+        nonce: &[u8; NONCE_LENGTH],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        self.cipher()
+            .decrypt(&Nonce::from(*nonce), ciphertext)
+            .map_err(|_| EnvelopeError::Cipher)
+    }
+}
+
+/// Seal a section: its CID and block are encrypted into a raw block, addressed by its own CID
+///
+/// # Returns
+/// * `Ok(Section)` - The sealed section, with a CIDv1 (raw, sha2-256) over the sealed block
+/// * `Err(EnvelopeError)` - The cipher failed
+pub fn seal_section<C: EnvelopeCipher + ?Sized>(
+    cipher: &C,
+    section: &Section,
+) -> Result<Section, EnvelopeError> {
+    let cid = section.cid().bytes();
+    let mut plaintext = UnsignedVarint(cid.len() as u64).encode();
+    plaintext.extend_from_slice(cid);
+    plaintext.extend_from_slice(section.block().data());
+
+    let nonce = cipher.nonce(section.cid(), section.block().data());
+    let ciphertext = cipher.seal(&nonce, &plaintext)?;
+    let mut sealed = Vec::with_capacity(NONCE_LENGTH + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(Section::from((raw_block_cid(&sealed), sealed)))
+}
+
+/// Open a sealed section, restoring the plaintext CID and block
+///
+/// The plaintext block is checked against its CID, unless the hash function of the CID is not supported
+/// (the block is still authenticated by the cipher).
+///
+/// # Returns
+/// * `Ok(Section)` - The plaintext section
+/// * `Err(EnvelopeError::Cipher)` - Wrong key, or the sealed block was tampered with
+/// * `Err(EnvelopeError)` - The sealed block is malformed, or does not match its plaintext CID
+pub fn open_section<C: EnvelopeCipher + ?Sized>(
+    cipher: &C,
+    sealed: &Section,
+) -> Result<Section, EnvelopeError> {
+    let malformed = || EnvelopeError::MalformedBlock(sealed.cid().clone());
+    let (nonce, ciphertext) = sealed
+        .block()
+        .data()
+        .split_first_chunk::<NONCE_LENGTH>()
+        .ok_or_else(malformed)?;
+    let plaintext = cipher.open(nonce, ciphertext)?;
+
+    let (length, read) = UnsignedVarint::decode(&plaintext).ok_or_else(malformed)?;
+    let cid_end = usize::try_from(length.0)
+        .ok()
+        .and_then(|length| read.checked_add(length))
+        .filter(|&end| end <= plaintext.len())
+        .ok_or_else(malformed)?;
+    let cid = RawCid::new(plaintext.get(read..cid_end).ok_or_else(malformed)?.to_vec());
+    let data = plaintext.get(cid_end..).ok_or_else(malformed)?.to_vec();
+    match verify_block(&cid, &data, DigestComparison::default()) {
+        Ok(()) | Err(DigestError::UnsupportedHash(_)) => Ok(Section::from((cid, data))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A plaintext root of a sealed archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeRoot {
+    /// CID of the plaintext root block
+    pub plaintext: RawCid,
+    /// CID of the sealed root block
+    pub ciphertext: RawCid,
+}
+
+/// Manifest of a sealed archive, mapping the plaintext roots to their sealed blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeManifest {
+    /// Algorithm of the cipher which sealed the archive
+    pub algorithm: String,
+    /// Plaintext roots of the archive, in header order
+    pub roots: Vec<EnvelopeRoot>,
+}
+
+impl EnvelopeManifest {
+    /// Create a manifest without roots
+    pub fn new(algorithm: impl Into<String>) -> Self {
+        Self {
+            algorithm: algorithm.into(),
+            roots: Vec::new(),
+        }
+    }
+
+    /// Add a plaintext root, and the CID of its sealed block
+    pub fn with_root(mut self, plaintext: RawCid, ciphertext: RawCid) -> Self {
+        self.roots.push(EnvelopeRoot {
+            plaintext,
+            ciphertext,
+        });
+        self
+    }
+
+    /// CID of the sealed block of a plaintext root, if it is one of the roots
+    pub fn sealed_root(&self, plaintext: &RawCid) -> Option<&RawCid> {
+        self.roots
+            .iter()
+            .find(|root| &root.plaintext == plaintext)
+            .map(|root| &root.ciphertext)
+    }
+
+    /// Encode the manifest as a dag-cbor block
+    ///
+    /// The map keys are written in the dag-cbor canonical order (by length, then bytewise), so the same
+    /// manifest always encodes to the same block.
+    pub fn to_block(&self) -> Vec<u8> {
+        let text = |s: &str| Value::Text(s.to_owned());
+        let roots = self
+            .roots
+            .iter()
+            .map(|root| {
+                Value::Map(vec![
+                    (
+                        text("plaintext"),
+                        Tag42Encoding::Prefixed.encode(&root.plaintext),
+                    ),
+                    (
+                        text("ciphertext"),
+                        Tag42Encoding::Prefixed.encode(&root.ciphertext),
+                    ),
+                ])
+            })
+            .collect();
+        let entries = vec![
+            (text("type"), text(ENVELOPE_TYPE)),
+            (text("roots"), Value::Array(roots)),
+            (text("version"), Value::Integer(ENVELOPE_VERSION.into())),
+            (text("algorithm"), text(&self.algorithm)),
+        ];
+
+        let mut block = Vec::new();
+        // Serializing into a Vec never fails
+        let _ = ciborium::ser::into_writer(&Value::Map(entries), &mut block);
+        block
+    }
+
+    /// Encode the manifest as a plaintext section, with a CIDv1 (dag-cbor, sha2-256)
+    ///
+    /// The section is meant to be sealed (see [seal_section]), its sealed CID being the only root of the archive.
+    pub fn to_section(&self) -> Section {
+        let block = self.to_block();
+        let mut cid = Vec::with_capacity(DAG_CBOR_SHA2_256_PREFIX.len() + 32);
+        cid.extend_from_slice(&DAG_CBOR_SHA2_256_PREFIX);
+        cid.extend_from_slice(&Sha256::digest(&block));
+        Section::from((RawCid::new(cid), block))
+    }
+
+    /// Decode a manifest block
+    ///
+    /// # Returns
+    /// * `Ok(EnvelopeManifest)` - The decoded manifest
+    /// * `Err(EnvelopeError)` - The block is not a manifest, or is malformed
+    pub fn from_block(data: &[u8]) -> Result<Self, EnvelopeError> {
+        let value = dagcbor::decode(data)?;
+        let entries = value.as_map().ok_or(EnvelopeError::NotAnEnvelope)?;
+        let get = |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v)
+        };
+        if get("type").and_then(Value::as_text) != Some(ENVELOPE_TYPE) {
+            return Err(EnvelopeError::NotAnEnvelope);
+        }
+        let version = get("version")
+            .and_then(Value::as_integer)
+            .and_then(|i| u64::try_from(i).ok())
+            .ok_or(EnvelopeError::InvalidField("version"))?;
+        if version > ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let algorithm = get("algorithm")
+            .and_then(Value::as_text)
+            .ok_or(EnvelopeError::InvalidField("algorithm"))?
+            .to_owned();
+        let roots = get("roots")
+            .and_then(Value::as_array)
+            .ok_or(EnvelopeError::InvalidField("roots"))?
+            .iter()
+            .map(envelope_root)
+            .collect::<Option<_>>()
+            .ok_or(EnvelopeError::InvalidField("roots"))?;
+        Ok(Self { algorithm, roots })
+    }
+
+    /// Seal the manifest with the cipher, which must be the one recorded in the manifest
+    pub fn seal<C: EnvelopeCipher + ?Sized>(&self, cipher: &C) -> Result<Section, EnvelopeError> {
+        seal_section(cipher, &self.to_section())
+    }
+
+    /// Open a sealed manifest, checking that it was sealed with the algorithm of the cipher
+    ///
+    /// # Returns
+    /// * `Ok(EnvelopeManifest)` - The manifest of the archive
+    /// * `Err(EnvelopeError::AlgorithmMismatch)` - The archive was sealed with another algorithm
+    /// * `Err(EnvelopeError)` - The section can not be opened, or is not a manifest
+    pub fn open<C: EnvelopeCipher + ?Sized>(
+        cipher: &C,
+        sealed: &Section,
+    ) -> Result<Self, EnvelopeError> {
+        let manifest = Self::from_block(open_section(cipher, sealed)?.block().data())?;
+        if manifest.algorithm != cipher.algorithm() {
+            return Err(EnvelopeError::AlgorithmMismatch {
+                expected: cipher.algorithm().to_owned(),
+                found: manifest.algorithm,
+            });
+        }
+        Ok(manifest)
+    }
+}
+
+/// Decode an entry of the `roots` list
+fn envelope_root(value: &Value) -> Option<EnvelopeRoot> {
+    let entries = value.as_map()?;
+    let get = |key: &str| {
+        entries
+            .iter()
+            .find(|(k, _)| k.as_text() == Some(key))
+            .map(|(_, v)| v)
+    };
+    Some(EnvelopeRoot {
+        plaintext: dagcbor::as_link(get("plaintext")?)?,
+        ciphertext: dagcbor::as_link(get("ciphertext")?)?,
+    })
+}
+
+/// Summary of a sealed or opened archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeSummary {
+    /// Manifest of the sealed archive
+    pub manifest: EnvelopeManifest,
+    /// Number of content sections sealed or opened (the manifest excluded)
+    pub sections: usize,
+}
+
+/// Seal a CAR archive (v1 or v2) into a CARv1 archive of sealed blocks
+///
+/// The archive is read twice: once to seal its roots and build the manifest (whose sealed CID is the root
+/// of the sealed archive), then to seal every section. The sealed manifest is written last.
+///
+/// # Returns
+/// * `Ok(EnvelopeSummary)` - The manifest, and the number of sealed sections
+/// * `Err(EnvelopeError::MissingRoot)` - A root of the archive is not one of its sections
+/// * `Err(EnvelopeError)` - The archive could not be read, sealed or written
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub fn seal_car<C, R, W>(
+    cipher: &C,
+    reader: R,
+    sink: &mut W,
+) -> Result<EnvelopeSummary, EnvelopeError>
+where
+    C: EnvelopeCipher + ?Sized,
+    R: std::io::Read + std::io::Seek,
+    W: std::io::Write,
+{
+    let mut car = crate::stdio::CarReader::open(reader)?;
+    let roots: Vec<RawCid> = car
+        .get_roots()
+        .iter()
+        .map(|root| root.to_raw_cid().clone())
+        .collect();
+    let mut sealed_roots: Vec<Option<RawCid>> = vec![None; roots.len()];
+    for section in car.sections() {
+        let section = section?;
+        for (root, sealed) in roots.iter().zip(sealed_roots.iter_mut()) {
+            if root == section.cid() && sealed.is_none() {
+                *sealed = Some(seal_section(cipher, &section)?.cid().clone());
+            }
+        }
+    }
+    let mut manifest = EnvelopeManifest::new(cipher.algorithm());
+    for (root, sealed) in roots.into_iter().zip(sealed_roots) {
+        let sealed = sealed.ok_or_else(|| EnvelopeError::MissingRoot(root.clone()))?;
+        manifest = manifest.with_root(root, sealed);
+    }
+    let sealed_manifest = manifest.seal(cipher)?;
+
+    car.rewind()?;
+    let mut writer = SealedCarWriter::new(vec![sealed_manifest.cid().clone()]);
+    let mut sections = 0;
+    for section in car.sections() {
+        writer.write(&seal_section(cipher, &section?.section)?, sink)?;
+        sections += 1;
+    }
+    writer.write(&sealed_manifest, sink)?;
+    writer.flush(sink)?;
+    Ok(EnvelopeSummary { manifest, sections })
+}
+
+/// Open a sealed CAR archive (v1 or v2) back into a CARv1 archive of the plaintext blocks
+///
+/// The root of the sealed archive must be its sealed manifest. The archive is read twice: once to open the
+/// manifest, then to open every other section. The plaintext roots of the manifest are the roots of the
+/// opened archive.
+///
+/// # Returns
+/// * `Ok(EnvelopeSummary)` - The manifest, and the number of opened sections
+/// * `Err(EnvelopeError::NotAnEnvelope)` - The archive has no sealed manifest as its single root
+/// * `Err(EnvelopeError)` - The archive could not be read, opened or written
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub fn open_car<C, R, W>(
+    cipher: &C,
+    reader: R,
+    sink: &mut W,
+) -> Result<EnvelopeSummary, EnvelopeError>
+where
+    C: EnvelopeCipher + ?Sized,
+    R: std::io::Read + std::io::Seek,
+    W: std::io::Write,
+{
+    let mut car = crate::stdio::CarReader::open(reader)?;
+    let [root] = car.get_roots() else {
+        return Err(EnvelopeError::NotAnEnvelope);
+    };
+    let root = root.to_raw_cid().clone();
+    let mut manifest = None;
+    for section in car.sections() {
+        let section = section?;
+        if section.cid() == &root {
+            manifest = Some(EnvelopeManifest::open(cipher, &section)?);
+            break;
+        }
+    }
+    let manifest = manifest.ok_or(EnvelopeError::NotAnEnvelope)?;
+
+    car.rewind()?;
+    let roots = manifest
+        .roots
+        .iter()
+        .map(|root| root.plaintext.clone())
+        .collect();
+    let mut writer = SealedCarWriter::new(roots);
+    let mut sections = 0;
+    for section in car.sections() {
+        let section = section?;
+        if section.cid() != &root {
+            writer.write(&open_section(cipher, &section)?, sink)?;
+            sections += 1;
+        }
+    }
+    writer.flush(sink)?;
+    Ok(EnvelopeSummary { manifest, sections })
+}
+
+/// CARv1 writer of the sealed and opened archives, flushing to the sink after every section
+#[cfg(feature = "std-io")]
+struct SealedCarWriter {
+    writer: crate::wire::v1::CarWriter,
+    chunk: Vec<u8>,
+}
+
+#[cfg(feature = "std-io")]
+impl SealedCarWriter {
+    fn new(roots: Vec<RawCid>) -> Self {
+        Self {
+            writer: crate::wire::v1::CarWriter::new(roots),
+            chunk: vec![0; 64 * 1024],
+        }
+    }
+
+    fn write<W: std::io::Write>(
+        &mut self,
+        section: &Section,
+        sink: &mut W,
+    ) -> Result<(), EnvelopeError> {
+        self.writer.write_section(section)?;
+        self.flush(sink)
+    }
+
+    fn flush<W: std::io::Write>(&mut self, sink: &mut W) -> Result<(), EnvelopeError> {
+        while self.writer.has_data_to_send() {
+            let length = self.writer.send_data(&mut self.chunk);
+            sink.write_all(self.chunk.get(..length).unwrap_or_default())?;
+        }
+        Ok(sink.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata;
+
+    #[test]
+    fn test_seal_open_section() {
+        let key = ChaCha20Poly1305Key::new([1; 32]);
+        let section = Section::from((testdata::CARV1_BASIC_SECTIONS[6].cid(), b"aaaa".to_vec()));
+        let sealed = seal_section(&key, &section).unwrap();
+        // Deterministic sealing, addressed by the ciphertext
+        assert_eq!(seal_section(&key, &section).unwrap(), sealed);
+        assert_eq!(sealed.cid(), &raw_block_cid(sealed.block().data()));
+        assert!(!sealed.block().data().windows(4).any(|w| w == b"aaaa"));
+        assert_eq!(open_section(&key, &sealed).unwrap(), section);
+
+        assert!(matches!(
+            open_section(&ChaCha20Poly1305Key::new([2; 32]), &sealed),
+            Err(EnvelopeError::Cipher)
+        ));
+        let mut tampered = sealed.block().data().to_vec();
+        tampered[NONCE_LENGTH] ^= 1;
+        let tampered = Section::from((raw_block_cid(&tampered), tampered));
+        assert!(matches!(
+            open_section(&key, &tampered),
+            Err(EnvelopeError::Cipher)
+        ));
+        let short = Section::from((raw_block_cid(b"short"), b"short".to_vec()));
+        assert!(matches!(
+            open_section(&key, &short),
+            Err(EnvelopeError::MalformedBlock(_))
+        ));
+
+        // The plaintext is checked against its CID
+        let forged = Section::from((testdata::CARV1_BASIC_SECTIONS[6].cid(), b"bbbb".to_vec()));
+        let sealed = seal_section(&key, &forged).unwrap();
+        assert!(matches!(
+            open_section(&key, &sealed),
+            Err(EnvelopeError::Digest(DigestError::Mismatch(_)))
+        ));
+    }
+
+    #[test]
+    fn test_seal_forged_duplicate_cid() {
+        let key = ChaCha20Poly1305Key::new([1; 32]);
+        let cid = testdata::CARV1_BASIC_SECTIONS[6].cid();
+        let section = Section::from((cid.clone(), b"aaaa".to_vec()));
+        let forged = Section::from((cid, b"bbbb".to_vec()));
+        let sealed = seal_section(&key, &section).unwrap();
+        let sealed_forged = seal_section(&key, &forged).unwrap();
+        // Same key and CID, but the nonces differ with the data
+        assert_ne!(
+            sealed.block().data()[..NONCE_LENGTH],
+            sealed_forged.block().data()[..NONCE_LENGTH]
+        );
+        assert_eq!(open_section(&key, &sealed).unwrap(), section);
+        assert!(matches!(
+            open_section(&key, &sealed_forged),
+            Err(EnvelopeError::Digest(DigestError::Mismatch(_)))
+        ));
+    }
+
+    #[test]
+    fn test_envelope_manifest() {
+        let key = ChaCha20Poly1305Key::new([1; 32]);
+        let manifest = EnvelopeManifest::new(ChaCha20Poly1305Key::ALGORITHM)
+            .with_root(
+                testdata::CARV1_BASIC_SECTIONS[0].cid(),
+                raw_block_cid(b"sealed blip"),
+            )
+            .with_root(
+                testdata::CARV1_BASIC_SECTIONS[7].cid(),
+                raw_block_cid(b"sealed limbo"),
+            );
+        assert_eq!(
+            EnvelopeManifest::from_block(&manifest.to_block()).unwrap(),
+            manifest
+        );
+        assert_eq!(
+            manifest.sealed_root(&testdata::CARV1_BASIC_SECTIONS[7].cid()),
+            Some(&raw_block_cid(b"sealed limbo"))
+        );
+
+        let sealed = manifest.seal(&key).unwrap();
+        assert_eq!(EnvelopeManifest::open(&key, &sealed).unwrap(), manifest);
+        let other = EnvelopeManifest::new("aes-256-gcm").seal(&key).unwrap();
+        assert!(matches!(
+            EnvelopeManifest::open(&key, &other),
+            Err(EnvelopeError::AlgorithmMismatch { .. })
+        ));
+        assert!(matches!(
+            EnvelopeManifest::from_block(&crate::appendix::Appendix::new().to_block()),
+            Err(EnvelopeError::NotAnEnvelope)
+        ));
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_seal_open_car() {
+        use std::io::Cursor;
+
+        let key = ChaCha20Poly1305Key::new([1; 32]);
+        let mut sealed = Vec::new();
+        let summary = seal_car(&key, Cursor::new(testdata::CARV1_BASIC), &mut sealed).unwrap();
+        assert_eq!(summary.sections, testdata::CARV1_BASIC_SECTIONS.len());
+
+        let mut car = crate::stdio::CarReader::open(Cursor::new(&sealed)).unwrap();
+        let roots: Vec<RawCid> = car
+            .get_roots()
+            .iter()
+            .map(|root| root.to_raw_cid().clone())
+            .collect();
+        assert_eq!(roots.len(), 1);
+        let cids: Vec<RawCid> = car
+            .sections()
+            .map(|section| section.unwrap().cid().clone())
+            .collect();
+        assert_eq!(cids.last(), roots.first());
+        for root in &summary.manifest.roots {
+            assert!(cids.contains(&root.ciphertext));
+            assert!(!cids.contains(&root.plaintext));
+        }
+
+        let mut opened = Vec::new();
+        let reopened = open_car(&key, Cursor::new(&sealed), &mut opened).unwrap();
+        assert_eq!(reopened, summary);
+        let mut original =
+            crate::stdio::CarReader::open(Cursor::new(testdata::CARV1_BASIC)).unwrap();
+        let mut car = crate::stdio::CarReader::open(Cursor::new(&opened)).unwrap();
+        assert_eq!(car.get_roots(), original.get_roots());
+        let sections: Vec<_> = car.sections().map(Result::unwrap).collect();
+        let expected: Vec<_> = original.sections().map(Result::unwrap).collect();
+        assert_eq!(sections.len(), expected.len());
+        for (section, expected) in sections.iter().zip(&expected) {
+            assert_eq!(section.cid(), expected.cid());
+            assert_eq!(section.block(), expected.block());
+        }
+
+        assert!(matches!(
+            open_car(
+                &ChaCha20Poly1305Key::new([2; 32]),
+                Cursor::new(&sealed),
+                &mut Vec::new()
+            ),
+            Err(EnvelopeError::Cipher)
+        ));
+        assert!(matches!(
+            open_car(&key, Cursor::new(testdata::CARV1_BASIC), &mut Vec::new()),
+            Err(EnvelopeError::NotAnEnvelope)
+        ));
+    }
+}
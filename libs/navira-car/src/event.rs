@@ -0,0 +1,244 @@
+//! Event-driven reading of CAR archives
+//!
+//! The [CarReader] API is made of several steps ([read_header](CarReader::read_header),
+//! [read_section](CarReader::read_section), [read_index](crate::wire::v2::CarReader::read_index), ...), each
+//! reporting its needs for data as an error. [CarReader::next_event] drives all of them: the whole archive is
+//! read from a single match loop, where the only thing the caller has to do is to feed the requested data.
+//!
+//! The events are, in order: the [CarEvent::Header], one [CarEvent::Section] per section, then for CAR v2
+//! archives with an index, one [CarEvent::IndexEntry] per index entry, and finally [CarEvent::End]. Any of
+//! them may be preceded by [CarEvent::NeedData] requests.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::{CarEvent, CarReader};
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let mut reader = CarReader::new();
+//! let mut sections = 0;
+//! loop {
+//!     match reader.next_event().unwrap() {
+//!         // The end of a CAR v1 archive is the end of the source
+//!         CarEvent::NeedData { offset, .. } if offset >= car_bytes.len() => break,
+//!         CarEvent::NeedData { offset, hint } => {
+//!             let end = (offset + hint.max(64)).min(car_bytes.len());
+//!             reader.receive_data(&car_bytes[offset..end], offset);
+//!         }
+//!         CarEvent::Header(header, _) => println!("roots: {}", header.roots().len()),
+//!         CarEvent::Section(_) => sections += 1,
+//!         CarEvent::IndexEntry(_) => unreachable!("CAR v1 archives have no index"),
+//!         CarEvent::End => break,
+//!     }
+//! }
+//! assert_eq!(sections, 8);
+//! ```
+//!
+//! CAR v1 archives do not record their size: after the last section, the reader keeps requesting the bytes
+//! following it, and the end of the archive is the end of the source. CAR v2 archives end with [CarEvent::End].
+
+use crate::index::CarIndexEntry;
+use crate::read::{CarReader, CarReaderError, CarUnderlyingReader};
+use crate::wire::v1::{CarHeader, LocatableSection, SectionLocation};
+use crate::wire::v2::CarV2Header;
+
+/// An event of the reading of a CAR archive, see [CarReader::next_event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CarEvent {
+    /// More data is needed: the bytes from `offset` (at least `hint` bytes, if not 0) must be given to
+    /// [CarReader::receive_data] before the next event
+    NeedData {
+        /// Offset of the requested bytes, from the start of the file
+        offset: usize,
+        /// Number of requested bytes, 0 if unknown
+        hint: usize,
+    },
+    /// The headers were read: the CAR v1 header (the inner one, for CAR v2 archives), and the CAR v2 header if any
+    Header(CarHeader, Option<CarV2Header>),
+    /// A section was read, in the order of the archive
+    Section(LocatableSection),
+    /// An entry of the CAR v2 index was read, in the order of the index
+    ///
+    /// The location is absolute, and its length is 0 (the index only records the offsets).
+    IndexEntry(CarIndexEntry),
+    /// The whole archive was read
+    End,
+}
+
+/// Progress of the reader through the events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum EventPhase {
+    /// The headers are not reported yet
+    #[default]
+    Header,
+    /// The sections are being reported
+    Sections,
+    /// The index entries are being reported, the next one being at this bucket and entry position
+    Index(usize, usize),
+    /// Everything was reported
+    End,
+}
+
+impl CarReader {
+    /// Read the archive up to its next event
+    ///
+    /// This is the single entry point of an event loop: it reads the headers, then the sections, then the CAR v2
+    /// index, and reports what it needs as [CarEvent::NeedData] rather than as errors. Duplicates are handled
+    /// like in [CarReader::read_section]. Seeking (e.g. [CarReader::seek_first_section]) goes back to the
+    /// sections.
+    ///
+    /// ## Returns
+    /// - `Ok(CarEvent)` with the next event.
+    /// - `Err(CarReaderError)` if the archive is malformed, on the same errors as the underlying steps. The failed
+    ///   step is tried again by the next call.
+    pub fn next_event(&mut self) -> Result<CarEvent, CarReaderError> {
+        match self.event_phase {
+            EventPhase::Header => match self.read_header() {
+                Ok(()) => {
+                    self.event_phase = EventPhase::Sections;
+                    let (header, v2_header) =
+                        self.header().ok_or(CarReaderError::PreconditionNotMet)?;
+                    Ok(CarEvent::Header(header.clone(), v2_header.cloned()))
+                }
+                Err(err) => need_data(err),
+            },
+            EventPhase::Sections => match self.read_section() {
+                Ok(section) => Ok(CarEvent::Section(section)),
+                Err(CarReaderError::EndOfSections) => {
+                    self.event_phase = EventPhase::Index(0, 0);
+                    self.next_event()
+                }
+                Err(err) => need_data(err),
+            },
+            EventPhase::Index(bucket, entry) => {
+                let Some(CarUnderlyingReader::V2(reader)) = self.get_underlying_reader() else {
+                    self.event_phase = EventPhase::End;
+                    return Ok(CarEvent::End);
+                };
+                let data_offset = reader.header().map_or(0, |(_, header)| header.data_offset);
+                let index = match reader.read_index() {
+                    Ok(Some(index)) => index,
+                    Ok(None) => {
+                        self.event_phase = EventPhase::End;
+                        return Ok(CarEvent::End);
+                    }
+                    Err(err) => return need_data(err.into()),
+                };
+                let Some(index_bucket) = index.buckets.get(bucket) else {
+                    self.event_phase = EventPhase::End;
+                    return Ok(CarEvent::End);
+                };
+                match index_bucket.entries.get(entry) {
+                    Some(index_entry) => {
+                        let event = CarEvent::IndexEntry(CarIndexEntry {
+                            multihash_code: index_bucket.multihash_code,
+                            digest: index_entry.hash.clone(),
+                            location: SectionLocation {
+                                offset: data_offset + index_entry.offset,
+                                length: 0,
+                            },
+                        });
+                        self.event_phase = EventPhase::Index(bucket, entry + 1);
+                        Ok(event)
+                    }
+                    None => {
+                        self.event_phase = EventPhase::Index(bucket + 1, 0);
+                        self.next_event()
+                    }
+                }
+            }
+            EventPhase::End => Ok(CarEvent::End),
+        }
+    }
+}
+
+/// Report the need for data as an event, and the other errors as is
+fn need_data(err: CarReaderError) -> Result<CarEvent, CarReaderError> {
+    match err {
+        CarReaderError::InsufficientData(offset, hint) => Ok(CarEvent::NeedData { offset, hint }),
+        err => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::CarIndex;
+    use crate::testdata::{
+        CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC, CARV2_BASIC_DATA_OFFSET,
+        CARV2_BASIC_INDEX_OFFSET, CARV2_BASIC_SECTIONS, carv2_basic_index,
+    };
+
+    /// Drive the reader over the archive, feeding the requested bytes in small chunks
+    fn collect_events(reader: &mut CarReader, car: &[u8]) -> Vec<CarEvent> {
+        let mut events = Vec::new();
+        loop {
+            match reader.next_event().unwrap() {
+                CarEvent::NeedData { offset, .. } if offset < car.len() => {
+                    let end = (offset + 30).min(car.len());
+                    reader.receive_data(&car[offset..end], offset);
+                }
+                // The end of a CAR v1 archive is the end of the source
+                CarEvent::NeedData { .. } | CarEvent::End => break,
+                event => events.push(event),
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_next_event() {
+        // The index stored in the fixture is incomplete, replace it with the complete one
+        let mut car = CARV2_BASIC[..CARV2_BASIC_INDEX_OFFSET as usize].to_vec();
+        car.extend_from_slice(&carv2_basic_index());
+        let mut reader = CarReader::new();
+        let events = collect_events(&mut reader, &car);
+        assert!(
+            matches!(&events[0], CarEvent::Header(_, Some(v2_header)) if v2_header.data_offset == CARV2_BASIC_DATA_OFFSET)
+        );
+        let sections = &events[1..=CARV2_BASIC_SECTIONS.len()];
+        for (event, fixture) in sections.iter().zip(CARV2_BASIC_SECTIONS) {
+            assert!(
+                matches!(event, CarEvent::Section(section) if section.location == fixture.location())
+            );
+        }
+        let (index, _) = CarIndex::decode(&carv2_basic_index(), CARV2_BASIC_DATA_OFFSET).unwrap();
+        let entries = &events[1 + CARV2_BASIC_SECTIONS.len()..];
+        assert_eq!(entries.len(), index.len());
+        for event in entries {
+            let CarEvent::IndexEntry(entry) = event else {
+                panic!("Unexpected event: {:?}", event);
+            };
+            assert!(index.entries().contains(entry));
+        }
+        // The end is reported again
+        assert_eq!(reader.next_event().unwrap(), CarEvent::End);
+
+        // Seeking goes back to the sections
+        reader.seek_first_section().unwrap();
+        assert!(matches!(
+            reader.next_event().unwrap(),
+            CarEvent::NeedData { .. }
+        ));
+
+        let mut reader = CarReader::new();
+        let events = collect_events(&mut reader, CARV1_BASIC);
+        assert!(matches!(&events[0], CarEvent::Header(_, None)));
+        assert_eq!(events.len(), 1 + CARV1_BASIC_SECTIONS.len());
+    }
+
+    #[test]
+    fn test_next_event_errors() {
+        // The headers read beforehand are reported as well
+        let mut reader = CarReader::from_bytes(CARV1_BASIC).unwrap();
+        assert!(matches!(reader.next_event().unwrap(), CarEvent::Header(..)));
+        assert!(matches!(
+            reader.next_event().unwrap(),
+            CarEvent::Section(..)
+        ));
+
+        // Malformed archives are reported as errors
+        let mut reader = CarReader::new();
+        reader.receive_data(&[0x0a; 64], 0);
+        assert!(reader.next_event().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Selective export of the blocks of a CAR archive
+//!
+//! Large archives often bundle several datasets, each under its own root. [extract_subgraph] copies the blocks
+//! of some of them to a new CAR v1 stream, whose header lists these roots only: the blocks reachable from the
+//! roots are walked in depth-first DAG order (see [DagIter]), and copied as they are, without being re-hashed.
+//! When the blocks to copy are already known (e.g. from an external manifest), [extract_cids] copies an explicit
+//! list of CIDs instead.
+//!
+//! The blocks are fetched from any [BlockSource]: a [CarSlice](crate::CarSlice) for archives in memory, or a
+//! [stdio CarReader](crate::stdio::CarReader) for indexed archives on disk (feature `std-io`).
+//!
+//! ## Examples
+//! ```
+//! use navira_car::{CarReader, CarSlice};
+//! use navira_car::export::extract_subgraph;
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let mut slice = CarSlice::new(car_bytes).unwrap();
+//! let root = slice.header().0.roots()[0].to_raw_cid().clone();
+//!
+//! // Keep the DAG of the first root only
+//! let mut car = Vec::new();
+//! let summary = extract_subgraph(&mut slice, &[root.clone()], &mut car).unwrap();
+//! assert_eq!(summary.blocks, 7);
+//!
+//! let reader = CarReader::from_bytes(&car).unwrap();
+//! assert_eq!(reader.header().unwrap().0.roots().len(), 1);
+//! ```
+
+use std::io::Write;
+
+use crate::ipld::BlockSource;
+use crate::traverse::{DagIter, TraverseError};
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Block, CarWriter, CarWriterError, Section};
+
+/// Summary of an export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportSummary {
+    /// Number of blocks written to the CAR stream
+    pub blocks: u64,
+    /// Number of bytes written to the sink (header included)
+    pub bytes_written: u64,
+}
+
+/// Sans-IO CAR v1 writer draining to a sink, counting the blocks and bytes written
+struct ExportWriter<'a, W: Write> {
+    writer: CarWriter,
+    sink: &'a mut W,
+    buf: Vec<u8>,
+    summary: ExportSummary,
+}
+
+impl<'a, W: Write> ExportWriter<'a, W> {
+    fn new(roots: Vec<RawCid>, sink: &'a mut W) -> Self {
+        Self {
+            writer: CarWriter::new(roots),
+            sink,
+            buf: vec![0u8; 256 * 1024],
+            summary: ExportSummary {
+                blocks: 0,
+                bytes_written: 0,
+            },
+        }
+    }
+
+    /// Write a block to the CAR stream, draining the writer to the sink when its buffer is full
+    ///
+    /// Identity CIDs embed their block, they are not written as sections.
+    fn write_block<E>(&mut self, cid: RawCid, data: Vec<u8>) -> Result<(), ExportError<E>> {
+        if cid.is_identity() {
+            return Ok(());
+        }
+        let section = Section::new(cid, Block::new(data));
+        loop {
+            match self.writer.write_section(&section) {
+                Ok(_) => break,
+                Err(CarWriterError::BufferFull) if self.writer.has_data_to_send() => {
+                    self.drain()?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.summary.blocks += 1;
+        Ok(())
+    }
+
+    /// Move the buffered bytes of the CAR stream to the sink
+    fn drain<E>(&mut self) -> Result<(), ExportError<E>> {
+        while self.writer.has_data_to_send() {
+            let len = self.writer.send_data(&mut self.buf);
+            self.sink
+                .write_all(self.buf.get(..len).unwrap_or_default())?;
+            self.summary.bytes_written += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Flush the end of the CAR stream
+    fn finish<E>(mut self) -> Result<ExportSummary, ExportError<E>> {
+        self.drain()?;
+        self.sink.flush()?;
+        Ok(self.summary)
+    }
+}
+
+/// Copy the blocks reachable from the given roots to a new CAR v1 stream
+///
+/// The new CAR has the given roots as its header roots, and holds the blocks of their DAGs in depth-first order
+/// (parents first), each block once. The links are followed through the dag-pb and dag-cbor blocks.
+///
+/// # Arguments
+/// * `source` - The blocks of the source archive.
+/// * `roots` - The roots of the DAGs to copy, and of the new CAR.
+/// * `sink` - Where to write the new CAR stream.
+///
+/// # Returns
+/// * `Ok(ExportSummary)` - Statistics about the new CAR stream.
+/// * `Err(ExportError)` - A block of the DAGs is missing from the source, or writing the sink failed.
+///   The sink may hold a partial CAR stream.
+pub fn extract_subgraph<S, W>(
+    source: &mut S,
+    roots: &[RawCid],
+    sink: &mut W,
+) -> Result<ExportSummary, ExportError<S::Error>>
+where
+    S: BlockSource,
+    W: Write,
+{
+    let mut writer = ExportWriter::new(roots.to_vec(), sink);
+    for block in DagIter::from_roots(source, roots.iter().cloned()) {
+        let block = block.map_err(|e| match e {
+            TraverseError::Source(e) => ExportError::Source(e),
+            TraverseError::BlockNotFound(cid) => ExportError::BlockNotFound(cid),
+        })?;
+        writer.write_block(block.cid, block.data)?;
+    }
+    writer.finish()
+}
+
+/// Copy an explicit list of blocks to a new CAR v1 stream
+///
+/// The blocks are written in the given order, duplicates included, and their links are not followed: the
+/// new CAR only holds the complete DAGs of its roots if the list does.
+///
+/// # Arguments
+/// * `source` - The blocks of the source archive.
+/// * `roots` - The roots of the new CAR.
+/// * `cids` - The CIDs of the blocks to copy.
+/// * `sink` - Where to write the new CAR stream.
+///
+/// # Returns
+/// * `Ok(ExportSummary)` - Statistics about the new CAR stream.
+/// * `Err(ExportError)` - A block is missing from the source, or writing the sink failed.
+///   The sink may hold a partial CAR stream.
+pub fn extract_cids<S, W, I>(
+    source: &mut S,
+    roots: &[RawCid],
+    cids: I,
+    sink: &mut W,
+) -> Result<ExportSummary, ExportError<S::Error>>
+where
+    S: BlockSource,
+    W: Write,
+    I: IntoIterator<Item = RawCid>,
+{
+    let mut writer = ExportWriter::new(roots.to_vec(), sink);
+    for cid in cids {
+        let data = match cid.identity_data() {
+            Some(data) => data.to_vec(),
+            None => source
+                .get_block(&cid)
+                .map_err(ExportError::Source)?
+                .ok_or_else(|| ExportError::BlockNotFound(cid.clone()))?,
+        };
+        writer.write_block(cid, data)?;
+    }
+    writer.finish()
+}
+
+/// Errors related to the export of blocks to a new CAR
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A block to copy is not available in the source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+    /// IO error while writing the sink
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A block could not be written to the CAR stream
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CarReader;
+    use crate::testdata::{CARV1_BASIC_ROOTS, CARV1_BASIC_SECTIONS, carv1_basic_blocks};
+
+    /// The roots and the CIDs of the sections of a CAR stream
+    fn read_back(car: &[u8]) -> (Vec<RawCid>, Vec<RawCid>) {
+        let mut reader = CarReader::from_bytes(car).unwrap();
+        let roots = reader.header().unwrap().0.roots().iter();
+        let roots = roots.map(|root| root.to_raw_cid().clone()).collect();
+        let cids = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|section| section.cid().clone())
+            .collect();
+        (roots, cids)
+    }
+
+    #[test]
+    fn test_extract_subgraph() {
+        let mut blocks = carv1_basic_blocks();
+        let roots: Vec<_> = CARV1_BASIC_ROOTS
+            .iter()
+            .map(|root| RawCid::from_hex(root).unwrap())
+            .collect();
+
+        // The second root alone
+        let mut car = Vec::new();
+        let summary = extract_subgraph(&mut blocks, &roots[1..], &mut car).unwrap();
+        assert_eq!(summary.blocks, 1);
+        assert_eq!(summary.bytes_written, car.len() as u64);
+        assert_eq!(read_back(&car), (roots[1..].to_vec(), roots[1..].to_vec()));
+
+        // Both roots: the whole archive, in DAG order
+        let mut car = Vec::new();
+        let summary = extract_subgraph(&mut blocks, &roots, &mut car).unwrap();
+        assert_eq!(summary.blocks, 8);
+        let (new_roots, cids) = read_back(&car);
+        assert_eq!(new_roots, roots);
+        assert_eq!(cids[0], roots[0]);
+        assert_eq!(cids[7], roots[1]);
+
+        // A missing block of the DAG
+        blocks.remove(&CARV1_BASIC_SECTIONS[2].cid());
+        let err = extract_subgraph(&mut blocks, &roots, &mut Vec::new()).unwrap_err();
+        assert!(
+            matches!(err, ExportError::BlockNotFound(cid) if cid == CARV1_BASIC_SECTIONS[2].cid())
+        );
+    }
+
+    #[test]
+    fn test_extract_cids() {
+        let mut blocks = carv1_basic_blocks();
+        let root = CARV1_BASIC_SECTIONS[3].cid();
+        let cids = vec![
+            root.clone(),
+            CARV1_BASIC_SECTIONS[6].cid(),
+            // Identity CIDs are not written
+            RawCid::from_hex("0155000568656c6c6f").unwrap(),
+        ];
+        let mut car = Vec::new();
+        let summary = extract_cids(
+            &mut blocks,
+            std::slice::from_ref(&root),
+            cids.clone(),
+            &mut car,
+        )
+        .unwrap();
+        assert_eq!(summary.blocks, 2);
+        assert_eq!(read_back(&car), (vec![root.clone()], cids[..2].to_vec()));
+
+        let missing = RawCid::from_hex(&format!("01551220{}", "00".repeat(32))).unwrap();
+        let err =
+            extract_cids(&mut blocks, &[root], [missing.clone()], &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, ExportError::BlockNotFound(cid) if cid == missing));
+    }
+}
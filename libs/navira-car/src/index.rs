@@ -0,0 +1,307 @@
+//! In-memory index of the sections of a CAR archive
+//!
+//! The CAR v2 [Index] is a faithful model of the wire format: buckets of entries grouped by hash function
+//! and digest width, with offsets relative to the CAR v1 payload. [CarIndex] is the structure applications
+//! query: a single sorted table of the sections, with their absolute location in the archive, looked up by
+//! binary search of the multihash digest.
+//!
+//! It can be built from a parsed CAR v2 index ([CarIndex::from_index], [CarIndex::decode]), or incrementally
+//! from the sections of a reader ([CarIndex::insert_section]), for CAR v1 archives as well. Either way, it can
+//! be serialized back to an IndexSorted or MultihashIndexSorted index with [CarIndex::encode].
+//!
+//! ## Examples
+//! ```
+//! use navira_car::CarReader;
+//! use navira_car::index::CarIndex;
+//! use navira_car::wire::v2::IndexType;
+//!
+//! let mut reader = CarReader::from_bytes(include_bytes!("res/carv2-basic.car")).unwrap();
+//! let data_offset = reader.header().unwrap().1.unwrap().data_offset;
+//! let mut index = CarIndex::new(data_offset);
+//! let mut sections = Vec::new();
+//! while let Ok(section) = reader.read_section() {
+//!     index.insert_section(&section);
+//!     sections.push(section);
+//! }
+//!
+//! for section in &sections {
+//!     assert_eq!(index.lookup(section.cid()), Some(section.location.clone()));
+//! }
+//! // The offsets of the serialized index are relative to the CAR v1 payload
+//! let bytes = index.encode(IndexType::MultihashIndexSorted);
+//! let (decoded, _) = CarIndex::decode(&bytes, data_offset).unwrap();
+//! assert_eq!(decoded.len(), sections.len());
+//! ```
+
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{LocatableSection, SectionLocation};
+use crate::wire::v2::{Index, IndexFormatError, IndexType};
+
+/// A section recorded in a [CarIndex]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarIndexEntry {
+    /// Multihash code of the hash function (None if unknown, e.g. read from an IndexSorted index)
+    pub multihash_code: Option<u64>,
+    /// Raw multihash digest of the section CID
+    pub digest: Vec<u8>,
+    /// Absolute location of the section in the archive
+    ///
+    /// The length is 0 when unknown: CAR v2 indexes only record the section offsets.
+    pub location: SectionLocation,
+}
+
+/// In-memory index of the sections of a CAR archive, looked up by multihash digest
+///
+/// The entries are kept sorted by digest (then by multihash code), so that lookups are binary searches.
+/// Like in CAR v2 indexes, CIDs which only differ by their codec share the same entry, and only the
+/// first section of each digest is recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CarIndex {
+    /// Offset of the CAR v1 payload in the archive (0 for a CAR v1 archive)
+    data_offset: u64,
+    /// Entries, sorted by digest then multihash code
+    entries: Vec<CarIndexEntry>,
+}
+
+impl CarIndex {
+    /// Create an empty index, for an archive whose CAR v1 payload starts at the given offset
+    ///
+    /// The data offset is 0 for CAR v1 archives, and [CarV2Header::data_offset](crate::wire::v2::CarV2Header)
+    /// for CAR v2 archives. It is only used to convert from/to the payload-relative offsets of CAR v2 indexes.
+    pub fn new(data_offset: u64) -> Self {
+        Self {
+            data_offset,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Build the index from a parsed CAR v2 index
+    ///
+    /// The section lengths are not recorded in CAR v2 indexes, so the locations have a length of 0.
+    pub fn from_index(index: &Index, data_offset: u64) -> Self {
+        let mut entries: Vec<_> = index
+            .buckets
+            .iter()
+            .flat_map(|bucket| {
+                bucket.entries.iter().map(|entry| CarIndexEntry {
+                    multihash_code: bucket.multihash_code,
+                    digest: entry.hash.clone(),
+                    location: SectionLocation {
+                        offset: data_offset + entry.offset,
+                        length: 0,
+                    },
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.digest, a.multihash_code).cmp(&(&b.digest, b.multihash_code)));
+        entries.dedup_by(|b, a| a.digest == b.digest && a.multihash_code == b.multihash_code);
+        Self {
+            data_offset,
+            entries,
+        }
+    }
+
+    /// Decode the index from the bytes of a CAR v2 index (starting at the index offset)
+    ///
+    /// ## Returns
+    /// - `Ok((CarIndex, size))` with the index and the number of bytes it spans.
+    /// - `Err(IndexFormatError)` if the index is truncated, malformed or of an unknown type.
+    pub fn decode(bytes: &[u8], data_offset: u64) -> Result<(Self, usize), IndexFormatError> {
+        let (index, size) = Index::decode(bytes)?;
+        Ok((Self::from_index(&index, data_offset), size))
+    }
+
+    /// Offset of the CAR v1 payload in the archive
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// Entries of the index, sorted by digest then multihash code
+    pub fn entries(&self) -> &[CarIndexEntry] {
+        &self.entries
+    }
+
+    /// Number of indexed sections
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no section is indexed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record a section, from its CID and its absolute location
+    ///
+    /// ## Returns
+    /// - `true` if the section was recorded.
+    /// - `false` if the CID is not well-formed (no multihash to index), or if a section with the
+    ///   same digest is already recorded.
+    pub fn insert(&mut self, cid: &RawCid, location: SectionLocation) -> bool {
+        let (Some(code), Some(digest)) = (cid.multihash_code(), cid.digest()) else {
+            return false;
+        };
+        let key = (digest, Some(code));
+        match self
+            .entries
+            .binary_search_by(|entry| (entry.digest.as_slice(), entry.multihash_code).cmp(&key))
+        {
+            Ok(_) => false,
+            Err(position) => {
+                self.entries.insert(
+                    position,
+                    CarIndexEntry {
+                        multihash_code: Some(code),
+                        digest: digest.to_vec(),
+                        location,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Record a section read from the archive
+    ///
+    /// See [CarIndex::insert].
+    pub fn insert_section(&mut self, section: &LocatableSection) -> bool {
+        self.insert(section.cid(), section.location.clone())
+    }
+
+    /// Look up the location of the section with the given CID
+    ///
+    /// The CID is matched by its multihash digest and hash function (entries without a recorded hash
+    /// function match any).
+    ///
+    /// ## Returns
+    /// - `Some(location)` with the absolute location of the section.
+    /// - `None` if the CID is not indexed (or is not a well-formed CID).
+    pub fn lookup(&self, cid: &RawCid) -> Option<SectionLocation> {
+        let code = cid.multihash_code()?;
+        self.matching(cid.digest()?)
+            .iter()
+            .find(|entry| entry.multihash_code.is_none_or(|c| c == code))
+            .map(|entry| entry.location.clone())
+    }
+
+    /// Look up the location of the first section with the given raw multihash digest
+    ///
+    /// ## Returns
+    /// - `Some(location)` with the absolute location of the section.
+    /// - `None` if the digest is not indexed.
+    pub fn lookup_digest(&self, digest: &[u8]) -> Option<SectionLocation> {
+        self.matching(digest)
+            .first()
+            .map(|entry| entry.location.clone())
+    }
+
+    /// Entries with the given digest, found by binary search
+    fn matching(&self, digest: &[u8]) -> &[CarIndexEntry] {
+        let start = self
+            .entries
+            .partition_point(|entry| entry.digest.as_slice() < digest);
+        let end = self
+            .entries
+            .partition_point(|entry| entry.digest.as_slice() <= digest);
+        self.entries.get(start..end).unwrap_or_default()
+    }
+
+    /// Convert to a CAR v2 index of the given type, with payload-relative offsets
+    ///
+    /// Entries without a recorded hash function (read from an IndexSorted index) are left out of
+    /// MultihashIndexSorted indexes.
+    pub fn to_index(&self, index_type: IndexType) -> Index {
+        Index::from_entries(
+            index_type,
+            self.entries
+                .iter()
+                .filter(|entry| {
+                    index_type == IndexType::IndexSorted || entry.multihash_code.is_some()
+                })
+                .map(|entry| {
+                    (
+                        entry.multihash_code.unwrap_or_default(),
+                        entry.digest.as_slice(),
+                        entry.location.offset.saturating_sub(self.data_offset),
+                    )
+                }),
+        )
+    }
+
+    /// Serialize to the bytes of a CAR v2 index of the given type
+    ///
+    /// See [CarIndex::to_index].
+    pub fn encode(&self, index_type: IndexType) -> Vec<u8> {
+        self.to_index(index_type).encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{
+        CARV2_BASIC, CARV2_BASIC_DATA_OFFSET, CARV2_BASIC_SECTIONS, carv2_basic_index,
+    };
+
+    #[test]
+    fn test_car_index_from_sections() {
+        let mut reader = crate::CarReader::from_bytes(CARV2_BASIC).unwrap();
+        let mut index = CarIndex::new(CARV2_BASIC_DATA_OFFSET);
+        while let Ok(section) = reader.read_section() {
+            assert!(index.insert_section(&section));
+            assert!(!index.insert_section(&section));
+        }
+        assert_eq!(index.len(), CARV2_BASIC_SECTIONS.len());
+        for fixture in CARV2_BASIC_SECTIONS {
+            assert_eq!(index.lookup(&fixture.cid()), Some(fixture.location()));
+            let digest = fixture.cid().digest().unwrap().to_vec();
+            assert_eq!(index.lookup_digest(&digest), Some(fixture.location()));
+        }
+        // Serialized back, it is the index of the fixture
+        assert_eq!(
+            index.encode(IndexType::MultihashIndexSorted),
+            carv2_basic_index()
+        );
+
+        let unknown = RawCid::from_hex(
+            "12200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        assert_eq!(index.lookup(&unknown), None);
+        // Another hash function with the same digest is not matched
+        let mut other = fixture_cid_bytes(3);
+        other[2] = 0x13;
+        assert_eq!(index.lookup(&RawCid::new(other)), None);
+    }
+
+    /// Bytes of the CID of the given fixture section
+    fn fixture_cid_bytes(i: usize) -> Vec<u8> {
+        hex::decode(CARV2_BASIC_SECTIONS[i].cid_hex).unwrap()
+    }
+
+    #[test]
+    fn test_car_index_from_index() {
+        let (index, size) =
+            CarIndex::decode(&carv2_basic_index(), CARV2_BASIC_DATA_OFFSET).unwrap();
+        assert_eq!(size, carv2_basic_index().len());
+        assert_eq!(index.len(), CARV2_BASIC_SECTIONS.len());
+        for fixture in CARV2_BASIC_SECTIONS {
+            let location = index.lookup(&fixture.cid()).unwrap();
+            assert_eq!(location.offset, fixture.location().offset);
+            assert_eq!(location.length, 0);
+        }
+        assert_eq!(
+            index.encode(IndexType::MultihashIndexSorted),
+            carv2_basic_index()
+        );
+
+        // IndexSorted does not record the hash functions, which then match any
+        let sorted = index.encode(IndexType::IndexSorted);
+        let (sorted, _) = CarIndex::decode(&sorted, CARV2_BASIC_DATA_OFFSET).unwrap();
+        assert!(sorted.entries().iter().all(|e| e.multihash_code.is_none()));
+        for fixture in CARV2_BASIC_SECTIONS {
+            assert_eq!(sorted.lookup(&fixture.cid()), index.lookup(&fixture.cid()));
+        }
+        assert!(sorted.to_index(IndexType::MultihashIndexSorted).is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+//! Inspection utilities for CAR files
+//!
+//! This module exports machine-readable listings of the sections of CAR files, for integration
+//! with external catalogs and for debugging. Each section is described by a [SectionRecord]
+//! (CID string, offset, length, codec and multihash code), and written by a [SectionExporter]
+//! either as CSV or as newline-delimited JSON ([ExportFormat]).
+//!
+//! The metadata stored in the [appendix](crate::appendix) of a CAR archive can be read with `read_appendix`
+//! (feature `std-io`), and displayed with its [Display](std::fmt::Display) rendering.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::inspect::{ExportFormat, SectionExporter, SectionRecord};
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let mut reader = navira_car::CarReader::from_bytes(car_bytes).unwrap();
+//!
+//! let mut exporter = SectionExporter::new(Vec::new(), ExportFormat::Csv);
+//! while let Ok(sect) = reader.read_section() {
+//!     exporter.write_record(&SectionRecord::new(sect.cid().clone(), &sect.location)).unwrap();
+//! }
+//! let csv = String::from_utf8(exporter.into_inner()).unwrap();
+//! assert!(csv.starts_with("car,cid,offset,length,codec,multihash\n"));
+//! assert!(csv.contains(",bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq,619,41,85,18\n"));
+//! ```
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::ipld::cid_codec;
+use crate::wire::cid::RawCid;
+use crate::wire::v1::SectionLocation;
+
+/// Format of a section listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma-separated values, with a header line
+    Csv,
+    /// Newline-delimited JSON, one object per section
+    Ndjson,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            _ => Err(format!(
+                "unknown export format {:?}, expected csv or ndjson",
+                s
+            )),
+        }
+    }
+}
+
+/// Description of a section, as exported in the listings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionRecord {
+    /// Name of the CAR file containing the section, if the listing spans several files
+    pub car: Option<String>,
+    /// CID of the section
+    pub cid: RawCid,
+    /// Offset of the section in the CAR file
+    pub offset: u64,
+    /// Length of the section in bytes (including the length prefix, CID, and block data)
+    pub length: u64,
+}
+
+impl SectionRecord {
+    /// Create a record for the section with the given CID and location
+    pub fn new(cid: RawCid, location: &SectionLocation) -> Self {
+        Self {
+            car: None,
+            cid,
+            offset: location.offset,
+            length: location.length,
+        }
+    }
+
+    /// Set the name of the CAR file containing the section
+    pub fn with_car(mut self, car: impl Into<String>) -> Self {
+        self.car = Some(car.into());
+        self
+    }
+
+    /// Multicodec of the section CID, if it can be determined
+    pub fn codec(&self) -> Option<u64> {
+        cid_codec(&self.cid)
+    }
+
+    /// Multihash code of the section CID, if it can be determined
+    pub fn multihash_code(&self) -> Option<u64> {
+        self.cid.multihash_code()
+    }
+}
+
+/// Writer of section listings
+///
+/// Codes (codec and multihash) are written as integers, and left empty (CSV) or `null` (ndjson) when unknown.
+pub struct SectionExporter<W: Write> {
+    writer: W,
+    format: ExportFormat,
+    header_written: bool,
+    records: usize,
+}
+
+impl<W: Write> SectionExporter<W> {
+    /// Create a new exporter writing to the given writer
+    pub fn new(writer: W, format: ExportFormat) -> Self {
+        Self {
+            writer,
+            format,
+            header_written: false,
+            records: 0,
+        }
+    }
+
+    /// Write a section record
+    pub fn write_record(&mut self, record: &SectionRecord) -> io::Result<()> {
+        let codec = record.codec();
+        let multihash = record.multihash_code();
+        match self.format {
+            ExportFormat::Csv => {
+                if !self.header_written {
+                    writeln!(self.writer, "car,cid,offset,length,codec,multihash")?;
+                    self.header_written = true;
+                }
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{},{}",
+                    csv_field(record.car.as_deref().unwrap_or_default()),
+                    record.cid.to_cid_string(),
+                    record.offset,
+                    record.length,
+                    codec.map(|c| c.to_string()).unwrap_or_default(),
+                    multihash.map(|c| c.to_string()).unwrap_or_default(),
+                )?;
+            }
+            ExportFormat::Ndjson => {
+                writeln!(
+                    self.writer,
+                    "{{\"car\":{},\"cid\":\"{}\",\"offset\":{},\"length\":{},\"codec\":{},\"multihash\":{}}}",
+                    record
+                        .car
+                        .as_deref()
+                        .map(json_string)
+                        .unwrap_or_else(|| "null".to_owned()),
+                    record.cid.to_cid_string(),
+                    record.offset,
+                    record.length,
+                    codec
+                        .map(|c| c.to_string())
+                        .unwrap_or_else(|| "null".to_owned()),
+                    multihash
+                        .map(|c| c.to_string())
+                        .unwrap_or_else(|| "null".to_owned()),
+                )?;
+            }
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Number of records written so far
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Consume the exporter and return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Export the listing of all the sections of a CAR archive (v1 or v2)
+///
+/// ## Returns
+/// - `Ok(count)` with the number of exported sections.
+/// - `Err(CarReaderError)` if the archive is invalid, or if an IO error occurred.
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub fn export_car<R, W>(
+    reader: R,
+    exporter: &mut SectionExporter<W>,
+) -> Result<usize, crate::stdio::CarReaderError>
+where
+    R: io::Read + io::Seek,
+    W: Write,
+{
+    let mut car = crate::stdio::CarReader::open(reader)?;
+    let mut count = 0;
+    for section in car.sections() {
+        let section = section?;
+        exporter.write_record(&SectionRecord::new(
+            section.cid().clone(),
+            &section.location,
+        ))?;
+        count += 1;
+    }
+    exporter.flush()?;
+    Ok(count)
+}
+
+/// Read the appendix metadata of a CAR archive (v1 or v2)
+///
+/// Only the blocks of the candidate roots (see [appendix_candidates](crate::appendix::appendix_candidates))
+/// are kept while reading the sections.
+///
+/// ## Returns
+/// - `Ok(Some((cid, appendix)))` with the CID of the appendix block and its metadata.
+/// - `Ok(None)` if the archive has no appendix.
+/// - `Err(CarReaderError)` if the archive is invalid, or if an IO error occurred.
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub fn read_appendix<R>(
+    reader: R,
+) -> Result<Option<(RawCid, crate::appendix::Appendix)>, crate::stdio::CarReaderError>
+where
+    R: io::Read + io::Seek,
+{
+    use crate::appendix::{appendix_candidates, find_appendix};
+    use std::collections::HashMap;
+
+    let mut car = crate::stdio::CarReader::open(reader)?;
+    let roots: Vec<RawCid> = car
+        .get_roots()
+        .iter()
+        .map(|root| root.to_raw_cid().clone())
+        .collect();
+    let candidates: Vec<&RawCid> = appendix_candidates(&roots).collect();
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let mut blocks = HashMap::new();
+    for section in car.sections() {
+        let section = section?;
+        if candidates.contains(&section.cid()) {
+            blocks.insert(section.cid().clone(), section.block().data().to_vec());
+        }
+    }
+    let Ok(appendix) = find_appendix(&roots, &mut blocks);
+    Ok(appendix)
+}
+
+/// Quote a CSV field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Encode a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> SectionRecord {
+        let cid = RawCid::from_hex(
+            "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+        )
+        .unwrap();
+        SectionRecord::new(
+            cid,
+            &SectionLocation {
+                offset: 192,
+                length: 133,
+            },
+        )
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let mut exporter = SectionExporter::new(Vec::new(), ExportFormat::Csv);
+        exporter.write_record(&record()).unwrap();
+        exporter
+            .write_record(&record().with_car("a,\"b\".car"))
+            .unwrap();
+        assert_eq!(exporter.records(), 2);
+        assert_eq!(
+            String::from_utf8(exporter.into_inner()).unwrap(),
+            "car,cid,offset,length,codec,multihash\n\
+             ,QmNX6Tffavsya4xgBi2VJQnSuqy9GsxongxZZ9uZBqp16d,192,133,112,18\n\
+             \"a,\"\"b\"\".car\",QmNX6Tffavsya4xgBi2VJQnSuqy9GsxongxZZ9uZBqp16d,192,133,112,18\n"
+        );
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_export_car() {
+        let car_bytes = include_bytes!("res/carv2-basic.car");
+        let mut exporter = SectionExporter::new(Vec::new(), ExportFormat::Ndjson);
+        let count = export_car(io::Cursor::new(&car_bytes[..]), &mut exporter).unwrap();
+        assert_eq!(count, 5);
+        let listing = String::from_utf8(exporter.into_inner()).unwrap();
+        assert_eq!(listing.lines().count(), 5);
+        // First section of the payload, right after the CARv2 header (51 bytes) and the CARv1 header (57 bytes)
+        assert!(listing.starts_with("{\"car\":null,\"cid\":\"QmfEoLyB5NndqeKieExd1rtJzTduQUPEV8TwAYcUiy3H5Z\",\"offset\":108,"));
+    }
+
+    #[cfg(all(feature = "std-io", feature = "pack"))]
+    #[test]
+    fn test_read_appendix() {
+        use crate::appendix::Appendix;
+        use crate::wire::v1::{CarWriter, Section};
+
+        let write_car = |roots: Vec<RawCid>, sections: &[Section]| {
+            let mut writer = CarWriter::new(roots);
+            let mut car = Vec::new();
+            let mut buf = [0u8; 256];
+            for section in sections {
+                writer.write_section(section).unwrap();
+                while writer.has_data_to_send() {
+                    let written = writer.send_data(&mut buf);
+                    car.extend_from_slice(&buf[..written]);
+                }
+            }
+            car
+        };
+        let content = Section::from((record().cid, vec![0xa0]));
+        let car = write_car(vec![record().cid], std::slice::from_ref(&content));
+        assert!(read_appendix(io::Cursor::new(car)).unwrap().is_none());
+
+        let appendix = Appendix::new()
+            .with_description("A single block")
+            .with_content(record().cid, "empty map");
+        let section = appendix.to_section();
+        let car = write_car(
+            vec![record().cid, section.cid().clone()],
+            &[content, section.clone()],
+        );
+        let (cid, found) = read_appendix(io::Cursor::new(car)).unwrap().unwrap();
+        assert_eq!(&cid, section.cid());
+        assert_eq!(found, appendix);
+    }
+
+    #[test]
+    fn test_export_ndjson() {
+        let mut exporter = SectionExporter::new(Vec::new(), ExportFormat::Ndjson);
+        exporter
+            .write_record(&record().with_car("dir\\\"x\".car"))
+            .unwrap();
+        let mut unknown = record();
+        unknown.cid = RawCid::new(vec![0x02, 0x00]);
+        exporter.write_record(&unknown).unwrap();
+        assert_eq!(
+            String::from_utf8(exporter.into_inner()).unwrap(),
+            "{\"car\":\"dir\\\\\\\"x\\\".car\",\"cid\":\"QmNX6Tffavsya4xgBi2VJQnSuqy9GsxongxZZ9uZBqp16d\",\"offset\":192,\"length\":133,\"codec\":112,\"multihash\":18}\n\
+             {\"car\":null,\"cid\":\"baiaa\",\"offset\":192,\"length\":133,\"codec\":null,\"multihash\":null}\n"
+        );
+    }
+}
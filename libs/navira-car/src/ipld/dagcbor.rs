@@ -0,0 +1,210 @@
+//! dag-cbor decoding
+//!
+//! dag-cbor is a strict subset of CBOR, where links to other blocks are encoded as CBOR tag 42
+//! wrapping the binary CID, prefixed by the `0x00` multibase identity byte.
+//!
+//! This module relies on [ciborium] to decode the blocks into its generic [Value] data model,
+//! and provides helpers to recognize the links inside this data model. When only the links are needed
+//! (e.g. to walk a DAG), [extract_links] finds them without building the data model.
+//!
+//! See the [dag-cbor specification](https://ipld.io/specs/codecs/dag-cbor/spec/) for more details.
+
+use ciborium::Value;
+
+use crate::wire::cid::{LINK_MULTIBASE_PREFIX, RawCid, Tag42Encoding};
+
+/// CBOR tag used by dag-cbor to encode links
+pub const CID_TAG: u64 = 42;
+
+/// Decodes a dag-cbor block into the generic CBOR data model
+pub fn decode(bytes: &[u8]) -> Result<Value, DagCborError> {
+    ciborium::from_reader(bytes).map_err(DagCborError::Decode)
+}
+
+/// Returns the CID if the given value is a dag-cbor link (CBOR tag 42), `None` otherwise
+pub fn as_link(value: &Value) -> Option<RawCid> {
+    Tag42Encoding::Prefixed.decode(value).ok()
+}
+
+/// Extracts the links of a dag-cbor block, in block order (duplicates included)
+///
+/// The block is only scanned item by item, nothing is allocated but the links. The links are recognized like
+/// with [as_link]: a tag 42 wrapping a byte string of the CID prefixed by `0x00`. Malformed blocks (including
+/// the indefinite lengths, forbidden in dag-cbor) have no links.
+///
+/// ## Examples
+/// ```
+/// use navira_car::ipld::dagcbor::extract_links;
+///
+/// // {"link": <CIDv0>, "name": "blip"}
+/// let block = hex::decode(
+///     "a2646c696e6bd82a582300122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de646e616d6564626c6970",
+/// )
+/// .unwrap();
+/// let links = extract_links(&block);
+/// assert_eq!(links.len(), 1);
+/// assert_eq!(links[0].to_hex(), "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de");
+/// ```
+pub fn extract_links(bytes: &[u8]) -> Vec<RawCid> {
+    scan_links(bytes).unwrap_or_default()
+}
+
+/// Scan the items of a dag-cbor block for links, `None` if the block is malformed
+///
+/// The nesting of the arrays and maps does not matter to find the links: only the number of items left to read
+/// is tracked, so deeply nested blocks are scanned without recursion.
+fn scan_links(bytes: &[u8]) -> Option<Vec<RawCid>> {
+    let mut links = Vec::new();
+    let mut pos = 0;
+    let mut remaining: usize = 1;
+    let mut tagged_link = false;
+    while remaining > 0 {
+        remaining -= 1;
+        let (major, argument) = read_head(bytes, &mut pos)?;
+        let in_link = std::mem::take(&mut tagged_link);
+        let items = match major {
+            // Byte and text strings
+            2 | 3 => {
+                let end = pos.checked_add(usize::try_from(argument).ok()?)?;
+                let value = bytes.get(pos..end)?;
+                if in_link
+                    && major == 2
+                    && let Some((&LINK_MULTIBASE_PREFIX, cid)) = value.split_first()
+                    && !cid.is_empty()
+                {
+                    links.push(RawCid::new(cid.to_vec()));
+                }
+                pos = end;
+                0
+            }
+            // Arrays and maps
+            4 => argument,
+            5 => argument.checked_mul(2)?,
+            // Tags wrap a single item
+            6 => {
+                tagged_link = argument == CID_TAG;
+                1
+            }
+            // Integers, floats and simple values
+            _ => 0,
+        };
+        // Each item takes at least one byte: larger counts cannot be in the block
+        remaining = remaining.checked_add(usize::try_from(items).ok()?)?;
+        if remaining > bytes.len() - pos {
+            return None;
+        }
+    }
+    (pos == bytes.len()).then_some(links)
+}
+
+/// Reads the head of a CBOR item (major type, argument) and advances the position past it
+fn read_head(bytes: &[u8], pos: &mut usize) -> Option<(u8, u64)> {
+    let initial = *bytes.get(*pos)?;
+    *pos += 1;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let size = match info {
+        0..24 => return Some((major, info as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        // Reserved, or indefinite lengths
+        _ => return None,
+    };
+    let argument = bytes.get(*pos..*pos + size)?;
+    *pos += size;
+    Some((
+        major,
+        argument
+            .iter()
+            .fold(0, |value, byte| value << 8 | *byte as u64),
+    ))
+}
+
+/// Errors related to dag-cbor decoding
+#[derive(thiserror::Error, Debug)]
+pub enum DagCborError {
+    /// The block is not valid CBOR
+    #[error("Invalid dag-cbor block: {0}")]
+    Decode(ciborium::de::Error<std::io::Error>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dagcbor_as_link() {
+        // {"link": <CIDv0>, "name": "blip"}, as in the first root of carv1-basic.car
+        let block = hex::decode(
+            "a2646c696e6bd82a582300122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de646e616d6564626c6970",
+        )
+        .unwrap();
+        let value = decode(&block).unwrap();
+        let map = value.as_map().unwrap();
+        let link = map
+            .iter()
+            .find(|(k, _)| k.as_text() == Some("link"))
+            .map(|(_, v)| v)
+            .unwrap();
+        assert_eq!(
+            as_link(link).unwrap().to_hex(),
+            "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de"
+        );
+        assert!(as_link(&Value::Text("blip".into())).is_none());
+    }
+
+    #[test]
+    fn test_dagcbor_extract_links() {
+        use crate::ipld::{CODEC_DAG_CBOR, cid_codec};
+        use crate::testdata::{CARV1_BASIC, CARV2_BASIC};
+
+        // Same links as found in the decoded data model, for every dag-cbor block of the fixtures
+        fn collect(value: &Value, links: &mut Vec<RawCid>) {
+            if let Some(link) = as_link(value) {
+                return links.push(link);
+            }
+            match value {
+                Value::Array(items) => items.iter().for_each(|item| collect(item, links)),
+                Value::Map(entries) => entries.iter().for_each(|(key, value)| {
+                    collect(key, links);
+                    collect(value, links);
+                }),
+                Value::Tag(_, inner) => collect(inner, links),
+                _ => {}
+            }
+        }
+        let mut count = 0;
+        for car in [CARV1_BASIC, CARV2_BASIC] {
+            let mut reader = crate::CarReader::from_bytes(car).unwrap();
+            while let Ok(section) = reader.read_section() {
+                if cid_codec(section.cid()) == Some(CODEC_DAG_CBOR) {
+                    let data = section.block().data();
+                    let mut expected = Vec::new();
+                    collect(&decode(data).unwrap(), &mut expected);
+                    assert_eq!(extract_links(data), expected);
+                    count += 1;
+                }
+            }
+        }
+        assert_eq!(count, 2);
+
+        // [42("a"), 42(h'01'), [1(42(h'00...'))], 1.5, -1]: only the last tag 42 is a link, nested in another tag
+        let link = RawCid::from_hex("0155000461616161").unwrap();
+        let mut block = vec![0x85, 0xd8, 0x2a, 0x61, 0x61, 0xd8, 0x2a, 0x41, 0x01];
+        block.extend([0x81, 0xc1, 0xd8, 0x2a, 0x49, 0x00]);
+        block.extend(link.bytes());
+        block.extend([0xf9, 0x3e, 0x00, 0x20]);
+        assert_eq!(extract_links(&block), vec![link]);
+
+        // Truncated, with trailing bytes, with an indefinite length, or with an impossible count of items
+        for malformed in [
+            &block[..block.len() - 1],
+            &[block.as_slice(), &[0x00]].concat(),
+            &[0x9f, 0xff][..],
+            &[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff][..],
+        ] {
+            assert!(extract_links(malformed).is_empty());
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! dag-pb decoding and encoding
+//!
+//! dag-pb is the protobuf-based format used by UnixFS to build files and directories.
+//! A dag-pb node is made of an optional opaque data field and a list of named links:
+//!
+//! ```protobuf
+//! message PBLink {
+//!   optional bytes Hash = 1;
+//!   optional string Name = 2;
+//!   optional uint64 Tsize = 3;
+//! }
+//!
+//! message PBNode {
+//!   repeated PBLink Links = 2;
+//!   optional bytes Data = 1;
+//! }
+//! ```
+//!
+//! The nodes are decoded strictly, as required by the specification: the fields must be in the canonical order
+//! (links before data, and hash, name, tsize within a link), each at most once, and the links must point to valid
+//! CIDs. Any decoded node is therefore encoded back to the same bytes.
+//!
+//! See the [dag-pb specification](https://ipld.io/specs/codecs/dag-pb/spec/) for more details.
+
+use crate::wire::cid::RawCid;
+use crate::wire::varint::UnsignedVarint;
+
+/// Protobuf wire type of varint fields
+pub(crate) const WIRE_TYPE_VARINT: u64 = 0;
+/// Protobuf wire type of length-delimited fields (bytes, strings, sub-messages)
+pub(crate) const WIRE_TYPE_LEN: u64 = 2;
+
+/// A decoded dag-pb node
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PbNode {
+    /// Opaque data of the node (UnixFS metadata for instance)
+    pub data: Option<Vec<u8>>,
+    /// Links to other blocks
+    pub links: Vec<PbLink>,
+}
+
+/// A link of a dag-pb node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PbLink {
+    /// CID of the linked block
+    pub cid: RawCid,
+    /// Name of the link (file name for UnixFS directories)
+    pub name: Option<String>,
+    /// Cumulative size of the linked DAG
+    pub tsize: Option<u64>,
+}
+
+impl PbNode {
+    /// Decodes a dag-pb node from the given block data
+    ///
+    /// ## Returns
+    /// - `Ok(PbNode)` if the block is a well-formed dag-pb node.
+    /// - `Err(DagPbError)` if the block cannot be decoded as dag-pb.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DagPbError> {
+        let mut node = PbNode::default();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (field, wire_type) = read_key(bytes, &mut pos)?;
+            // Nothing may follow the data
+            if node.data.is_some() {
+                return Err(DagPbError::NonCanonical { field });
+            }
+            match (field, wire_type) {
+                (1, WIRE_TYPE_LEN) => {
+                    node.data = Some(read_len_delimited(bytes, &mut pos)?.to_vec());
+                }
+                (2, WIRE_TYPE_LEN) => {
+                    let link = read_len_delimited(bytes, &mut pos)?;
+                    node.links.push(PbLink::decode(link)?);
+                }
+                (field, wire_type) => {
+                    return Err(DagPbError::UnexpectedField { field, wire_type });
+                }
+            }
+        }
+        Ok(node)
+    }
+
+    /// Encodes the node in the canonical dag-pb form
+    ///
+    /// As required by the specification, the links are written before the data (regardless of the protobuf
+    /// field numbers), and in the order of [PbNode::links]. Sorting the links (by name, for directories) is left to the caller.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for link in &self.links {
+            write_len_delimited(&mut bytes, 2, &link.encode());
+        }
+        if let Some(data) = &self.data {
+            write_len_delimited(&mut bytes, 1, data);
+        }
+        bytes
+    }
+
+    /// Returns the first link with the given name, if any
+    pub fn link_by_name(&self, name: &str) -> Option<&PbLink> {
+        self.links
+            .iter()
+            .find(|link| link.name.as_deref() == Some(name))
+    }
+}
+
+impl PbLink {
+    /// Encodes the link protobuf message
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_len_delimited(&mut bytes, 1, self.cid.bytes());
+        if let Some(name) = &self.name {
+            write_len_delimited(&mut bytes, 2, name.as_bytes());
+        }
+        if let Some(tsize) = self.tsize {
+            write_key(&mut bytes, 3, WIRE_TYPE_VARINT);
+            bytes.extend(UnsignedVarint(tsize).encode());
+        }
+        bytes
+    }
+
+    /// Decodes a dag-pb link from its protobuf message bytes
+    fn decode(bytes: &[u8]) -> Result<Self, DagPbError> {
+        let mut cid = None;
+        let mut name = None;
+        let mut tsize = None;
+        let mut last_field = 0;
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (field, wire_type) = read_key(bytes, &mut pos)?;
+            // The fields are in increasing order, so each one appears at most once
+            if field <= last_field {
+                return Err(DagPbError::NonCanonical { field });
+            }
+            last_field = field;
+            match (field, wire_type) {
+                (1, WIRE_TYPE_LEN) => {
+                    let hash = read_len_delimited(bytes, &mut pos)?;
+                    match RawCid::try_read_bytes(hash) {
+                        Ok((hash_cid, size)) if size == hash.len() => cid = Some(hash_cid),
+                        _ => return Err(DagPbError::InvalidHash),
+                    }
+                }
+                (2, WIRE_TYPE_LEN) => {
+                    let raw_name = read_len_delimited(bytes, &mut pos)?;
+                    let raw_name = String::from_utf8(raw_name.to_vec())
+                        .map_err(|_| DagPbError::InvalidName)?;
+                    name = Some(raw_name);
+                }
+                (3, WIRE_TYPE_VARINT) => {
+                    tsize = Some(read_varint(bytes, &mut pos)?);
+                }
+                (field, wire_type) => {
+                    return Err(DagPbError::UnexpectedField { field, wire_type });
+                }
+            }
+        }
+        Ok(PbLink {
+            cid: cid.ok_or(DagPbError::MissingHash)?,
+            name,
+            tsize,
+        })
+    }
+}
+
+/// Reads a varint at the given position and advances it
+pub(crate) fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64, DagPbError> {
+    let (varint, size) = UnsignedVarint::decode(bytes.get(*pos..).unwrap_or_default())
+        .ok_or(DagPbError::Truncated)?;
+    *pos += size;
+    Ok(varint.0)
+}
+
+/// Reads a protobuf field key, returning (field number, wire type)
+pub(crate) fn read_key(bytes: &[u8], pos: &mut usize) -> Result<(u64, u64), DagPbError> {
+    let key = read_varint(bytes, pos)?;
+    Ok((key >> 3, key & 0x07))
+}
+
+/// Reads a length-delimited field value and advances the position past it
+pub(crate) fn read_len_delimited<'a>(
+    bytes: &'a [u8],
+    pos: &mut usize,
+) -> Result<&'a [u8], DagPbError> {
+    let len = read_varint(bytes, pos)? as usize;
+    let end = pos.checked_add(len).ok_or(DagPbError::Truncated)?;
+    if end > bytes.len() {
+        return Err(DagPbError::Truncated);
+    }
+    let value = bytes.get(*pos..end).unwrap_or_default();
+    *pos = end;
+    Ok(value)
+}
+
+/// Writes a protobuf field key
+pub(crate) fn write_key(bytes: &mut Vec<u8>, field: u64, wire_type: u64) {
+    bytes.extend(UnsignedVarint(field << 3 | wire_type).encode());
+}
+
+/// Writes a length-delimited field (key, length and value)
+pub(crate) fn write_len_delimited(bytes: &mut Vec<u8>, field: u64, value: &[u8]) {
+    write_key(bytes, field, WIRE_TYPE_LEN);
+    bytes.extend(UnsignedVarint(value.len() as u64).encode());
+    bytes.extend_from_slice(value);
+}
+
+/// Errors related to dag-pb decoding
+#[derive(thiserror::Error, Debug)]
+pub enum DagPbError {
+    /// The node is truncated (a varint or a field is cut in the middle)
+    #[error("Truncated dag-pb node")]
+    Truncated,
+    /// The node contains a field which is not part of the dag-pb schema
+    #[error("Unexpected dag-pb field {field} (wire type {wire_type})")]
+    UnexpectedField {
+        /// Protobuf field number
+        field: u64,
+        /// Protobuf wire type
+        wire_type: u64,
+    },
+    /// A field appears several times, or out of the canonical order
+    #[error("dag-pb field {field} is repeated or out of order")]
+    NonCanonical {
+        /// Protobuf field number
+        field: u64,
+    },
+    /// A link does not have a Hash field
+    #[error("dag-pb link without hash")]
+    MissingHash,
+    /// The Hash field of a link is not a valid CID
+    #[error("dag-pb link hash is not a valid CID")]
+    InvalidHash,
+    /// A link name is not valid UTF-8
+    #[error("dag-pb link name is not valid UTF-8")]
+    InvalidName,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dagpb_decode_fixture_node() {
+        // Block 122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de of carv1-basic.car
+        let block = hex::decode(
+            "122e0a2401551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a274511204626561721804\
+             122f0a22122079a982de3c9907953d4d323cee1d0fb1ed8f45f8ef02870c0cb9e09246bd530a12067365636f6e64189501",
+        )
+        .unwrap();
+        let node = PbNode::decode(&block).unwrap();
+        assert_eq!(node.data, None);
+        assert_eq!(node.links.len(), 2);
+        assert_eq!(node.links[0].name.as_deref(), Some("bear"));
+        assert_eq!(node.links[0].tsize, Some(4));
+        assert_eq!(
+            node.links[0].cid.to_hex(),
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451"
+        );
+        assert_eq!(node.links[1].name.as_deref(), Some("second"));
+        assert_eq!(node.links[1].tsize, Some(149));
+        assert!(node.link_by_name("second").is_some());
+        assert!(node.link_by_name("third").is_none());
+    }
+
+    #[test]
+    fn test_dagpb_encode_roundtrip() {
+        let block = hex::decode(
+            "122e0a2401551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a274511204626561721804\
+             122f0a22122079a982de3c9907953d4d323cee1d0fb1ed8f45f8ef02870c0cb9e09246bd530a12067365636f6e64189501",
+        )
+        .unwrap();
+        let node = PbNode::decode(&block).unwrap();
+        assert_eq!(node.encode(), block);
+
+        // Links are encoded before the data
+        let node = PbNode {
+            data: Some(vec![0x08, 0x01]),
+            links: node.links,
+        };
+        let encoded = node.encode();
+        assert_eq!(encoded[0], 0x12);
+        assert!(encoded.ends_with(&[0x0a, 0x02, 0x08, 0x01]));
+        assert_eq!(PbNode::decode(&encoded).unwrap(), node);
+    }
+
+    #[test]
+    fn test_dagpb_decode_truncated() {
+        let block = hex::decode("122e0a2401551220b6fbd675").unwrap();
+        assert!(matches!(PbNode::decode(&block), Err(DagPbError::Truncated)));
+    }
+
+    #[test]
+    fn test_dagpb_decode_non_canonical() {
+        let link = PbLink {
+            cid: RawCid::from_hex(
+                "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+            )
+            .unwrap(),
+            name: Some("bear".into()),
+            tsize: Some(4),
+        };
+        let mut link_bytes = Vec::new();
+        write_len_delimited(&mut link_bytes, 1, link.cid.bytes());
+        // Links after the data, or the data twice
+        for fields in [[1, 2], [1, 1]] {
+            let mut block = Vec::new();
+            for field in fields {
+                write_len_delimited(&mut block, field, &link_bytes);
+            }
+            assert!(matches!(
+                PbNode::decode(&block),
+                Err(DagPbError::NonCanonical { .. })
+            ));
+        }
+        // Name before hash within a link
+        let mut block = Vec::new();
+        let mut reordered = Vec::new();
+        write_len_delimited(&mut reordered, 2, b"bear");
+        reordered.extend_from_slice(&link_bytes);
+        write_len_delimited(&mut block, 2, &reordered);
+        assert!(matches!(
+            PbNode::decode(&block),
+            Err(DagPbError::NonCanonical { field: 1 })
+        ));
+        // Hash which is not a CID
+        let mut block = Vec::new();
+        let mut invalid = Vec::new();
+        write_len_delimited(&mut invalid, 1, &link.cid.bytes()[..10]);
+        write_len_delimited(&mut block, 2, &invalid);
+        assert!(matches!(
+            PbNode::decode(&block),
+            Err(DagPbError::InvalidHash)
+        ));
+    }
+
+    #[test]
+    fn test_dagpb_fixtures_roundtrip() {
+        use crate::ipld::{CODEC_DAG_PB, cid_codec};
+        use crate::testdata::{CARV1_BASIC, CARV2_BASIC};
+
+        let mut count = 0;
+        for car in [CARV1_BASIC, CARV2_BASIC] {
+            let mut reader = crate::CarReader::from_bytes(car).unwrap();
+            while let Ok(section) = reader.read_section() {
+                if cid_codec(section.cid()) == Some(CODEC_DAG_PB) {
+                    let data = section.block().data();
+                    assert_eq!(PbNode::decode(data).unwrap().encode(), data);
+                    count += 1;
+                }
+            }
+        }
+        assert_eq!(count, 6);
+    }
+}
@@ -0,0 +1,407 @@
+//! Byte range extraction of UnixFS files (`entity-bytes`)
+//!
+//! The [trustless gateway specification](https://specs.ipfs.tech/http-gateways/trustless-gateway/#entity-bytes-request-query-parameter)
+//! lets a client request a byte range of a UnixFS file, with `dag-scope=entity&entity-bytes=from:to`: the response is
+//! a CAR holding the minimal set of blocks needed to verify and read that range. That is the root block, the
+//! intermediate nodes on the way to the range, and the leaves overlapping it.
+//!
+//! The file DAG is traversed depth-first, and the subtrees outside the range are skipped without being fetched,
+//! using the content sizes recorded by their parent (UnixFS `blocksizes`). The dag-pb `Tsize` of the links can not
+//! be used for that purpose, as it counts the encoded size of the subtree rather than its content. When a parent does
+//! not record the content sizes, its children are fetched to learn their size, but only written if they overlap the range.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::ipld::entity::EntityBytes;
+//!
+//! let range: EntityBytes = "-100:*".parse().unwrap();
+//! assert_eq!(range.resolve(1000), Some(900..1000));
+//! let range: EntityBytes = "10:19".parse().unwrap();
+//! assert_eq!(range.resolve(1000), Some(10..20));
+//! assert_eq!(range.resolve(5), None);
+//! ```
+
+use std::io::Write;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::ipld::dagpb::{DagPbError, PbNode};
+use crate::ipld::unixfs::{DataType, UnixFsData, UnixFsError};
+use crate::ipld::{BlockSource, CODEC_DAG_PB, CODEC_RAW, cid_codec};
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Block, CarWriter, CarWriterError, Section};
+
+/// A byte range of the `entity-bytes` parameter (`from:to`)
+///
+/// Both bounds are inclusive. Negative bounds count from the end of the file, and an open end (`*`)
+/// extends the range to the end of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityBytes {
+    /// First byte of the range (negative to count from the end of the file)
+    pub from: i64,
+    /// Last byte of the range, included (negative to count from the end, `None` for `*`)
+    pub to: Option<i64>,
+}
+
+impl EntityBytes {
+    /// The whole file (`0:*`)
+    pub const ALL: EntityBytes = EntityBytes { from: 0, to: None };
+
+    /// Resolve the range against the size of the file
+    ///
+    /// ## Returns
+    /// - `Some(Range<u64>)` with the (half-open) range of bytes to read.
+    /// - `None` if the range does not select any byte of the file.
+    pub fn resolve(&self, size: u64) -> Option<Range<u64>> {
+        let bound = |value: i64| {
+            if value < 0 {
+                size.saturating_sub(value.unsigned_abs())
+            } else {
+                value as u64
+            }
+        };
+        let start = bound(self.from);
+        let end = self
+            .to
+            .map_or(size, |to| bound(to).saturating_add(1))
+            .min(size);
+        (start < end).then_some(start..end)
+    }
+}
+
+impl FromStr for EntityBytes {
+    type Err = InvalidEntityBytes;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidEntityBytes(s.to_owned());
+        let (from, to) = s.split_once(':').ok_or_else(invalid)?;
+        let from = from.parse().map_err(|_| invalid())?;
+        let to = match to {
+            "*" => None,
+            to => Some(to.parse().map_err(|_| invalid())?),
+        };
+        // A range with both bounds counted from the same side can be checked without the file size
+        if let Some(to) = to
+            && (from >= 0) == (to >= 0)
+            && to < from
+        {
+            return Err(invalid());
+        }
+        Ok(EntityBytes { from, to })
+    }
+}
+
+/// Summary of an `entity-bytes` extraction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntitySummary {
+    /// Size of the whole file
+    pub file_size: u64,
+    /// Range of the file covered by the written blocks (`None` if the range selects no byte)
+    pub range: Option<Range<u64>>,
+    /// Number of blocks written to the CAR stream
+    pub blocks: u64,
+    /// Number of bytes written to the sink (header included)
+    pub bytes_written: u64,
+}
+
+/// A node of the file DAG, still to be written
+struct Pending {
+    cid: RawCid,
+    /// Offset of the content of this node in the file
+    offset: u64,
+}
+
+/// A decoded dag-pb node of a file, with its UnixFS metadata
+type FileNode = (PbNode, UnixFsData);
+
+/// Content size of a node, with the decoded dag-pb node if any
+fn content_size<E>(
+    cid: &RawCid,
+    data: &[u8],
+) -> Result<(u64, Option<FileNode>), EntityBytesError<E>> {
+    match cid_codec(cid) {
+        Some(CODEC_RAW) => Ok((data.len() as u64, None)),
+        Some(CODEC_DAG_PB) => {
+            let node = PbNode::decode(data)?;
+            let unixfs = UnixFsData::decode(node.data.as_deref().unwrap_or_default())?;
+            if !matches!(unixfs.data_type, DataType::File | DataType::Raw) {
+                return Err(EntityBytesError::NotAFile(cid.clone()));
+            }
+            let inline = unixfs.data.as_ref().map_or(0, |data| data.len() as u64);
+            let size = unixfs
+                .filesize
+                .unwrap_or_else(|| inline + unixfs.blocksizes.iter().sum::<u64>());
+            Ok((size, Some((node, unixfs))))
+        }
+        _ => Err(EntityBytesError::NotAFile(cid.clone())),
+    }
+}
+
+/// Fetch a block from the source
+fn fetch<S: BlockSource>(
+    source: &mut S,
+    cid: &RawCid,
+) -> Result<Vec<u8>, EntityBytesError<S::Error>> {
+    source
+        .get_block(cid)
+        .map_err(EntityBytesError::Source)?
+        .ok_or_else(|| EntityBytesError::BlockNotFound(cid.clone()))
+}
+
+/// Write a block to the CAR stream, draining the writer to the sink when its buffer is full
+fn write_block<W: Write, E>(
+    writer: &mut CarWriter,
+    sink: &mut W,
+    buf: &mut [u8],
+    cid: RawCid,
+    data: Vec<u8>,
+) -> Result<u64, EntityBytesError<E>> {
+    let section = Section::new(cid, Block::new(data));
+    let mut written = 0;
+    loop {
+        match writer.write_section(&section) {
+            Ok(_) => return Ok(written),
+            Err(CarWriterError::BufferFull) if writer.has_data_to_send() => {
+                while writer.has_data_to_send() {
+                    let len = writer.send_data(buf);
+                    sink.write_all(buf.get(..len).unwrap_or_default())?;
+                    written += len as u64;
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Stream the blocks of a UnixFS file needed to read a byte range, as a CAR v1
+///
+/// The CAR has the file as its only root, and holds its blocks in depth-first order: the root block, then for
+/// each child overlapping the range, the child and its own blocks (see the [module documentation](self)).
+/// If the range selects no byte of the file, only the root block is written, as it is needed to verify the file size.
+///
+/// # Arguments
+/// * `source` - The blocks of the file DAG.
+/// * `root` - The root CID of the file (a dag-pb UnixFS file, or a raw block).
+/// * `range` - The byte range to extract.
+/// * `sink` - Where to write the CAR stream.
+///
+/// # Returns
+/// * `Ok(EntitySummary)` - The file size, the resolved range, and statistics about the CAR stream.
+/// * `Err(EntityBytesError)` - A needed block is missing or not part of a UnixFS file, or writing the sink failed.
+///   The sink may hold a partial CAR stream.
+pub fn write_entity_bytes_car<S, W>(
+    source: &mut S,
+    root: &RawCid,
+    range: &EntityBytes,
+    sink: &mut W,
+) -> Result<EntitySummary, EntityBytesError<S::Error>>
+where
+    S: BlockSource,
+    W: Write,
+{
+    let mut writer = CarWriter::new(vec![root.clone()]);
+    let mut buf = vec![0u8; 256 * 1024];
+    let mut summary = EntitySummary {
+        file_size: 0,
+        range: None,
+        blocks: 0,
+        bytes_written: 0,
+    };
+
+    // Depth-first traversal, the stack holds the nodes still to write in reverse order
+    let mut stack = vec![Pending {
+        cid: root.clone(),
+        offset: 0,
+    }];
+    while let Some(Pending { cid, offset }) = stack.pop() {
+        let data = fetch(source, &cid)?;
+        let (size, node) = content_size(&cid, &data)?;
+        if summary.blocks == 0 {
+            // Root of the file
+            summary.file_size = size;
+            summary.range = range.resolve(size);
+        }
+        let target = summary.range.clone().unwrap_or(0..0);
+
+        let mut children = Vec::new();
+        if let Some((node, unixfs)) = node {
+            let mut child_offset = offset + unixfs.data.as_ref().map_or(0, |d| d.len() as u64);
+            let sizes_known = unixfs.blocksizes.len() == node.links.len();
+            for (i, link) in node.links.into_iter().enumerate() {
+                if child_offset >= target.end {
+                    break;
+                }
+                let child_size = match unixfs.blocksizes.get(i) {
+                    Some(&size) if sizes_known => size,
+                    // The child is fetched again if it is written, to only hold a single block at once
+                    _ => content_size(&link.cid, &fetch(source, &link.cid)?)?.0,
+                };
+                if child_size > 0 && child_offset + child_size > target.start {
+                    children.push(Pending {
+                        cid: link.cid,
+                        offset: child_offset,
+                    });
+                }
+                child_offset += child_size;
+            }
+        }
+
+        summary.bytes_written += write_block(&mut writer, sink, &mut buf, cid, data)?;
+        summary.blocks += 1;
+        stack.extend(children.into_iter().rev());
+    }
+    while writer.has_data_to_send() {
+        let len = writer.send_data(&mut buf);
+        sink.write_all(buf.get(..len).unwrap_or_default())?;
+        summary.bytes_written += len as u64;
+    }
+    sink.flush()?;
+    Ok(summary)
+}
+
+/// The `entity-bytes` value is not a valid range
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid entity-bytes range: {0}")]
+pub struct InvalidEntityBytes(pub String);
+
+/// Errors related to the `entity-bytes` extraction
+#[derive(thiserror::Error, Debug)]
+pub enum EntityBytesError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A block of the file DAG is not available in the source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+    /// A dag-pb block of the file DAG is malformed
+    #[error("Invalid dag-pb block: {0}")]
+    InvalidDagPb(#[from] DagPbError),
+    /// The UnixFS metadata of a node is malformed
+    #[error("Invalid UnixFS data: {0}")]
+    InvalidUnixFs(#[from] UnixFsError),
+    /// A node of the DAG is not part of a UnixFS file (directory, symlink, unsupported codec, etc.)
+    #[error("Not a UnixFS file node: {0}")]
+    NotAFile(RawCid),
+    /// IO error while writing the sink
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A block could not be written to the CAR stream
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipld::dagpb::PbLink;
+    use std::collections::HashMap;
+
+    /// A fake CID of the given codec, distinguished by its last digest byte
+    fn cid(codec: u8, n: u8) -> RawCid {
+        let mut bytes = vec![0x01, codec, 0x12, 0x20];
+        bytes.resize(bytes.len() + 31, 0);
+        bytes.push(n);
+        RawCid::new(bytes)
+    }
+
+    /// A dag-pb UnixFS file node linking the children, recording their sizes or not
+    fn file_node(children: &[(RawCid, u64)], record_sizes: bool) -> Vec<u8> {
+        let mut unixfs = UnixFsData::new(DataType::File);
+        unixfs.filesize = Some(children.iter().map(|(_, size)| size).sum());
+        if record_sizes {
+            unixfs.blocksizes = children.iter().map(|(_, size)| *size).collect();
+        }
+        PbNode {
+            data: Some(unixfs.encode()),
+            links: children
+                .iter()
+                .map(|(cid, size)| PbLink {
+                    cid: cid.clone(),
+                    name: Some(String::new()),
+                    tsize: Some(size + 100),
+                })
+                .collect(),
+        }
+        .encode()
+    }
+
+    /// File "abcdefghij": root -> [a: "abcd", b -> [c: "efg", d: "hij"]]
+    fn file(record_sizes: bool) -> (RawCid, HashMap<RawCid, Vec<u8>>) {
+        let (a, c, d) = (cid(0x55, 1), cid(0x55, 3), cid(0x55, 4));
+        let (b, root) = (cid(0x70, 2), cid(0x70, 0));
+        let mut blocks = HashMap::new();
+        blocks.insert(a.clone(), b"abcd".to_vec());
+        blocks.insert(c.clone(), b"efg".to_vec());
+        blocks.insert(d.clone(), b"hij".to_vec());
+        blocks.insert(b.clone(), file_node(&[(c, 3), (d, 3)], record_sizes));
+        blocks.insert(root.clone(), file_node(&[(a, 4), (b, 6)], record_sizes));
+        (root, blocks)
+    }
+
+    /// Extract a range, and return the last digest byte of the written blocks, in order
+    fn extract(record_sizes: bool, range: &str) -> (EntitySummary, Vec<u8>) {
+        let (root, mut blocks) = file(record_sizes);
+        let mut fetched = 0;
+        let mut source = |cid: &RawCid| {
+            fetched += 1;
+            Ok::<_, std::convert::Infallible>(blocks.get(cid).cloned())
+        };
+        let mut car = Vec::new();
+        let summary =
+            write_entity_bytes_car(&mut source, &root, &range.parse().unwrap(), &mut car).unwrap();
+        let mut reader = crate::CarReader::from_bytes(&car).unwrap();
+        assert_eq!(reader.header().unwrap().0.roots()[0].to_raw_cid(), &root);
+        let written: Vec<u8> = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|section| *section.cid().bytes().last().unwrap())
+            .collect();
+        assert_eq!(summary.blocks, written.len() as u64);
+        assert_eq!(summary.bytes_written, car.len() as u64);
+        if record_sizes {
+            // Skipped subtrees are never fetched
+            assert_eq!(fetched, written.len());
+        }
+        blocks.clear();
+        (summary, written)
+    }
+
+    #[test]
+    fn test_entity_bytes_parse() {
+        assert_eq!("0:*".parse(), Ok(EntityBytes::ALL));
+        assert_eq!(
+            "-5:-1".parse(),
+            Ok(EntityBytes {
+                from: -5,
+                to: Some(-1)
+            })
+        );
+        for invalid in ["", "5", "a:*", "5:4", "-1:-5", "0:"] {
+            assert!(invalid.parse::<EntityBytes>().is_err(), "{}", invalid);
+        }
+        // Mixed signs are only resolved against the file size
+        let range: EntityBytes = "8:-5".parse().unwrap();
+        assert_eq!(range.resolve(20), Some(8..16));
+        assert_eq!(range.resolve(10), None);
+        assert_eq!(EntityBytes::ALL.resolve(0), None);
+    }
+
+    #[test]
+    fn test_entity_bytes_blocks() {
+        for record_sizes in [true, false] {
+            let (summary, written) = extract(record_sizes, "0:*");
+            assert_eq!(summary.file_size, 10);
+            assert_eq!(written, vec![0, 1, 2, 3, 4]);
+
+            // "fg" only needs the second subtree, and its first leaf
+            let (summary, written) = extract(record_sizes, "5:6");
+            assert_eq!(summary.range, Some(5..7));
+            assert_eq!(written, vec![0, 2, 3]);
+            assert_eq!(extract(record_sizes, "-2:*").1, vec![0, 2, 4]);
+            assert_eq!(extract(record_sizes, "3:4").1, vec![0, 1, 2, 3]);
+
+            // Out of range: the root only
+            let (summary, written) = extract(record_sizes, "20:*");
+            assert_eq!(summary.range, None);
+            assert_eq!(written, vec![0]);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Minimal IPLD support for the blocks stored in CAR files
+//!
+//! CAR archives are mostly opaque containers, but a few operations (path resolution, DAG traversal,
+//! verification) need to look inside the blocks to find the links between them.
+//!
+//! This module provides just enough IPLD to do so, without pulling a full IPLD stack:
+//! - [dagpb] decodes and encodes dag-pb nodes (the format used by UnixFS).
+//! - [unixfs] decodes and encodes the UnixFS metadata stored in dag-pb nodes.
+//! - [dagcbor] decodes dag-cbor blocks and recognizes their links.
+//! - [path] resolves IPLD paths (`<cid>/a/b/0`) over a [BlockSource].
+//! - [entity] extracts the blocks covering a byte range of a UnixFS file (`entity-bytes`).
+
+pub mod dagcbor;
+pub mod dagpb;
+pub mod entity;
+pub mod path;
+pub mod unixfs;
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use crate::wire::cid::RawCid;
+
+pub use path::{PathError, Resolved, ResolvedPath, resolve};
+
+/// Multicodec code of raw binary blocks
+pub const CODEC_RAW: u64 = 0x55;
+/// Multicodec code of dag-pb blocks
+pub const CODEC_DAG_PB: u64 = 0x70;
+/// Multicodec code of dag-cbor blocks
+pub const CODEC_DAG_CBOR: u64 = 0x71;
+
+/// A source of blocks, addressed by their CID.
+///
+/// This is the abstraction used by the IPLD utilities to fetch the blocks they need,
+/// whatever the blocks are actually stored (in memory, in a CAR file, in a datastore, etc).
+///
+/// It is implemented for:
+/// - `HashMap<RawCid, Vec<u8>>`, for in-memory sets of blocks.
+/// - Any closure `FnMut(&RawCid) -> Result<Option<Vec<u8>>, E>`, for custom sources.
+pub trait BlockSource {
+    /// Error returned by the underlying source
+    type Error;
+
+    /// Get the block data identified by the given CID
+    ///
+    /// ## Returns
+    /// - `Ok(Some(data))` if the block is available in this source.
+    /// - `Ok(None)` if the block is not part of this source.
+    /// - `Err(Self::Error)` if the source failed to retrieve the block.
+    fn get_block(&mut self, cid: &RawCid) -> Result<Option<Vec<u8>>, Self::Error>;
+}
+
+impl BlockSource for HashMap<RawCid, Vec<u8>> {
+    type Error = Infallible;
+
+    fn get_block(&mut self, cid: &RawCid) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.get(cid).cloned())
+    }
+}
+
+impl<F, E> BlockSource for F
+where
+    F: FnMut(&RawCid) -> Result<Option<Vec<u8>>, E>,
+{
+    type Error = E;
+
+    fn get_block(&mut self, cid: &RawCid) -> Result<Option<Vec<u8>>, Self::Error> {
+        self(cid)
+    }
+}
+
+/// Extracts the links of a block, based on the codec of its CID
+///
+/// Only dag-pb and dag-cbor blocks can have links, other codecs (or undecodable blocks) are considered as leaves.
+/// Links are returned in block order, duplicates included.
+pub fn block_links(cid: &RawCid, data: &[u8]) -> Vec<RawCid> {
+    match cid_codec(cid) {
+        Some(CODEC_DAG_PB) => dagpb::PbNode::decode(data)
+            .map(|node| node.links.into_iter().map(|link| link.cid).collect())
+            .unwrap_or_default(),
+        Some(CODEC_DAG_CBOR) => dagcbor::extract_links(data),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the multicodec of the given CID, if it can be determined.
+///
+/// CIDv0 are always dag-pb, while CIDv1 carry their codec right after the version byte.
+///
+/// See [RawCid::codec].
+pub fn cid_codec(cid: &RawCid) -> Option<u64> {
+    cid.codec()
+}
@@ -0,0 +1,286 @@
+//! IPLD path resolution
+//!
+//! An IPLD path (as in `/ipld/<cid>/a/b/0`) designates a value reachable from a root block,
+//! by descending through the data model of the blocks and transparently following the links
+//! between them. This is the primitive needed for gateway pathing (`/ipfs/<cid>/path/to/file`).
+//!
+//! The descent rules depend on the codec of each block:
+//! - dag-cbor: a segment selects a map entry by key, or a list item by index.
+//! - dag-pb: a segment selects a link by its name (as UnixFS directories do).
+//! - raw (and other codecs): the block cannot be descended into.
+//!
+//! ## Examples
+//! ```
+//! use std::collections::HashMap;
+//! use navira_car::ipld::{Resolved, resolve};
+//! use navira_car::wire::cid::RawCid;
+//!
+//! let car_bytes = include_bytes!("../res/carv1-basic.car");
+//! let mut reader = navira_car::CarReader::from_bytes(car_bytes).unwrap();
+//!
+//! // Load all the blocks in memory
+//! let mut blocks = HashMap::new();
+//! while let Ok(sect) = reader.read_section() {
+//!     blocks.insert(sect.cid().clone(), sect.block().data().to_vec());
+//! }
+//!
+//! // Resolve a path going through a dag-cbor root, a dag-pb node, and ending on a raw block
+//! let root = RawCid::from_hex("01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b").unwrap();
+//! let resolved = resolve(&mut blocks, &root, "link/bear").unwrap();
+//! assert_eq!(resolved.traversed.len(), 3);
+//! assert!(matches!(resolved.node, Resolved::Block { ref data, .. } if data == b"cccc"));
+//! ```
+
+use ciborium::Value;
+
+use crate::ipld::{
+    BlockSource, CODEC_DAG_CBOR, CODEC_DAG_PB, cid_codec,
+    dagcbor::{self, DagCborError},
+    dagpb::{DagPbError, PbNode},
+};
+use crate::wire::cid::RawCid;
+
+/// The node a path resolved to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolved {
+    /// The path ended on a whole block
+    Block {
+        /// CID of the block
+        cid: RawCid,
+        /// Data of the block
+        data: Vec<u8>,
+    },
+    /// The path ended on a value inside a dag-cbor block
+    Value {
+        /// CID of the block containing the value
+        cid: RawCid,
+        /// The value itself
+        value: Value,
+    },
+}
+
+/// Result of a path resolution
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPath {
+    /// The node the path resolved to
+    pub node: Resolved,
+    /// CIDs of all the blocks traversed during the resolution, in order (root first, final block last)
+    pub traversed: Vec<RawCid>,
+}
+
+/// Current position of the resolution inside a block
+enum Cursor {
+    /// Root of a dag-cbor block, or a value inside it
+    DagCbor(Value),
+    /// Root of a dag-pb node
+    DagPb(PbNode),
+    /// Block which cannot be descended into (raw, unknown codec)
+    Opaque,
+}
+
+/// Resolves an IPLD path from a root CID, fetching the blocks from the given source.
+///
+/// The path is a `/`-separated list of segments relative to the root (e.g. `a/b/0`), empty
+/// segments are ignored. If the path ends on a link, the link is followed and the linked block is returned.
+///
+/// ## Arguments
+/// * `source` - The block source to fetch the blocks from.
+/// * `root` - The CID of the block to start the resolution from.
+/// * `path` - The path to resolve, relative to the root.
+///
+/// ## Returns
+/// - `Ok(ResolvedPath)` with the resolved node and the list of traversed CIDs.
+/// - `Err(PathError)` if a block is missing or invalid, or if a segment cannot be resolved.
+pub fn resolve<S: BlockSource>(
+    source: &mut S,
+    root: &RawCid,
+    path: &str,
+) -> Result<ResolvedPath, PathError<S::Error>> {
+    let mut traversed = Vec::new();
+    let (mut cid, mut data, mut cursor) = load(source, root, &mut traversed)?;
+    // Whether the cursor is still at the root of the current block
+    let mut at_block_root = true;
+
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let next = match cursor {
+            Cursor::DagCbor(value) => descend_cbor(value, segment),
+            Cursor::DagPb(node) => node
+                .link_by_name(segment)
+                .map(|link| Value::Tag(dagcbor::CID_TAG, Box::new(link_bytes(&link.cid)))),
+            Cursor::Opaque => None,
+        };
+        let Some(next) = next else {
+            return Err(PathError::SegmentNotFound {
+                cid,
+                segment: segment.to_owned(),
+            });
+        };
+
+        match dagcbor::as_link(&next) {
+            Some(link) => {
+                (cid, data, cursor) = load(source, &link, &mut traversed)?;
+                at_block_root = true;
+            }
+            None => {
+                cursor = Cursor::DagCbor(next);
+                at_block_root = false;
+            }
+        }
+    }
+
+    let node = match cursor {
+        Cursor::DagCbor(value) if !at_block_root => Resolved::Value { cid, value },
+        _ => Resolved::Block { cid, data },
+    };
+    Ok(ResolvedPath { node, traversed })
+}
+
+/// Fetches and decodes a block, recording it as traversed
+fn load<S: BlockSource>(
+    source: &mut S,
+    cid: &RawCid,
+    traversed: &mut Vec<RawCid>,
+) -> Result<(RawCid, Vec<u8>, Cursor), PathError<S::Error>> {
+    let data = source
+        .get_block(cid)
+        .map_err(PathError::Source)?
+        .ok_or_else(|| PathError::BlockNotFound(cid.clone()))?;
+    traversed.push(cid.clone());
+    let cursor = match cid_codec(cid) {
+        Some(CODEC_DAG_CBOR) => Cursor::DagCbor(
+            dagcbor::decode(&data).map_err(|e| PathError::InvalidDagCbor(cid.clone(), e))?,
+        ),
+        Some(CODEC_DAG_PB) => Cursor::DagPb(
+            PbNode::decode(&data).map_err(|e| PathError::InvalidDagPb(cid.clone(), e))?,
+        ),
+        _ => Cursor::Opaque,
+    };
+    Ok((cid.clone(), data, cursor))
+}
+
+/// Selects the map entry or list item designated by the segment
+fn descend_cbor(value: Value, segment: &str) -> Option<Value> {
+    match value {
+        Value::Map(entries) => entries
+            .into_iter()
+            .find(|(key, _)| key.as_text() == Some(segment))
+            .map(|(_, value)| value),
+        Value::Array(items) => {
+            let index: usize = segment.parse().ok()?;
+            items.into_iter().nth(index)
+        }
+        _ => None,
+    }
+}
+
+/// Encodes a CID as the bytes of a dag-cbor link (with the `0x00` multibase prefix)
+fn link_bytes(cid: &RawCid) -> Value {
+    let mut bytes = Vec::with_capacity(cid.bytes().len() + 1);
+    bytes.push(0x00);
+    bytes.extend_from_slice(cid.bytes());
+    Value::Bytes(bytes)
+}
+
+/// Errors related to IPLD path resolution
+#[derive(thiserror::Error, Debug)]
+pub enum PathError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A block needed for the resolution is not available in the block source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+    /// A dag-cbor block could not be decoded
+    #[error("Invalid dag-cbor block {0}: {1}")]
+    InvalidDagCbor(RawCid, DagCborError),
+    /// A dag-pb block could not be decoded
+    #[error("Invalid dag-pb block {0}: {1}")]
+    InvalidDagPb(RawCid, DagPbError),
+    /// A path segment does not exist in the current node
+    #[error("Path segment {segment:?} not found in block {cid}")]
+    SegmentNotFound {
+        /// CID of the block in which the segment was looked up
+        cid: RawCid,
+        /// The segment which could not be resolved
+        segment: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::carv1_basic_blocks;
+
+    fn root() -> RawCid {
+        RawCid::from_hex("01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_empty_path() {
+        let mut blocks = carv1_basic_blocks();
+        let resolved = resolve(&mut blocks, &root(), "").unwrap();
+        assert_eq!(resolved.traversed, vec![root()]);
+        assert!(matches!(resolved.node, Resolved::Block { cid, .. } if cid == root()));
+    }
+
+    #[test]
+    fn test_resolve_cbor_value() {
+        let mut blocks = carv1_basic_blocks();
+        let resolved = resolve(&mut blocks, &root(), "/name").unwrap();
+        assert_eq!(resolved.traversed, vec![root()]);
+        assert_eq!(
+            resolved.node,
+            Resolved::Value {
+                cid: root(),
+                value: Value::Text("blip".into())
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_through_dagpb() {
+        let mut blocks = carv1_basic_blocks();
+        let resolved = resolve(&mut blocks, &root(), "link/second/first/cat/").unwrap();
+        let expected = [
+            "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b",
+            "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+            "122079a982de3c9907953d4d323cee1d0fb1ed8f45f8ef02870c0cb9e09246bd530a",
+            "1220e7dc486e97e6ebe5cdabab3e392bdad128b6e09acc94bb4e2aa2af7b986d24d0",
+            "0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4",
+        ];
+        let traversed: Vec<_> = resolved.traversed.iter().map(RawCid::to_hex).collect();
+        assert_eq!(traversed, expected);
+        assert!(matches!(resolved.node, Resolved::Block { data, .. } if data == b"aaaa"));
+    }
+
+    #[test]
+    fn test_resolve_missing_segment() {
+        let mut blocks = carv1_basic_blocks();
+        let err = resolve(&mut blocks, &root(), "link/nope").unwrap_err();
+        assert!(matches!(err, PathError::SegmentNotFound { segment, .. } if segment == "nope"));
+        let err = resolve(&mut blocks, &root(), "link/bear/deeper").unwrap_err();
+        assert!(matches!(err, PathError::SegmentNotFound { segment, .. } if segment == "deeper"));
+    }
+
+    #[test]
+    fn test_resolve_missing_block() {
+        let mut blocks = carv1_basic_blocks();
+        let pbnode = RawCid::from_hex(
+            "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+        )
+        .unwrap();
+        blocks.remove(&pbnode);
+        let err = resolve(&mut blocks, &root(), "link/bear").unwrap_err();
+        assert!(matches!(err, PathError::BlockNotFound(cid) if cid == pbnode));
+    }
+
+    #[test]
+    fn test_resolve_with_closure_source() {
+        let blocks = carv1_basic_blocks();
+        let mut source =
+            |cid: &RawCid| -> Result<Option<Vec<u8>>, ()> { Ok(blocks.get(cid).cloned()) };
+        let resolved = resolve(&mut source, &root(), "link").unwrap();
+        assert_eq!(resolved.traversed.len(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+//! UnixFS metadata decoding and encoding
+//!
+//! UnixFS represents files and directories on top of dag-pb: the `Data` field of each dag-pb node
+//! holds a protobuf message describing the node:
+//!
+//! ```protobuf
+//! message Data {
+//!   enum DataType { Raw = 0; Directory = 1; File = 2; Metadata = 3; Symlink = 4; HAMTShard = 5; }
+//!   required DataType Type = 1;
+//!   optional bytes Data = 2;
+//!   optional uint64 filesize = 3;
+//!   repeated uint64 blocksizes = 4;
+//!   optional uint64 hashType = 5;
+//!   optional uint64 fanout = 6;
+//!   optional uint32 mode = 7;
+//!   optional UnixTime mtime = 8;
+//! }
+//! ```
+//!
+//! Files are split in chunks: the leaves hold the content (as raw blocks, or as dag-pb nodes of type Raw/File with inline data),
+//! and the intermediate nodes link them in order, recording the content size of each child in `blocksizes`.
+//!
+//! See the [UnixFS specification](https://specs.ipfs.tech/unixfs/) for more details.
+
+use crate::ipld::dagpb::{
+    WIRE_TYPE_LEN, WIRE_TYPE_VARINT, read_key, read_len_delimited, read_varint, write_key,
+    write_len_delimited,
+};
+use crate::wire::varint::UnsignedVarint;
+
+/// Protobuf wire type of 64-bit fields
+const WIRE_TYPE_I64: u64 = 1;
+/// Protobuf wire type of 32-bit fields
+const WIRE_TYPE_I32: u64 = 5;
+
+/// Type of a UnixFS node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    /// Raw content (leaf of a file)
+    Raw = 0,
+    /// Directory, its entries are the named links of the node
+    Directory = 1,
+    /// File, its content is the inline data followed by the content of the linked nodes
+    File = 2,
+    /// Metadata (deprecated)
+    Metadata = 3,
+    /// Symbolic link, its target is the inline data
+    Symlink = 4,
+    /// Shard of a HAMT-sharded directory
+    HamtShard = 5,
+}
+
+impl DataType {
+    /// Creates a DataType from its protobuf value
+    pub fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(DataType::Raw),
+            1 => Some(DataType::Directory),
+            2 => Some(DataType::File),
+            3 => Some(DataType::Metadata),
+            4 => Some(DataType::Symlink),
+            5 => Some(DataType::HamtShard),
+            _ => None,
+        }
+    }
+}
+
+/// Decoded UnixFS metadata of a dag-pb node
+///
+/// The `mode` and `mtime` fields are skipped when decoding, and never encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixFsData {
+    /// Type of the node
+    pub data_type: DataType,
+    /// Inline data (file content, symlink target, HAMT bitfield)
+    pub data: Option<Vec<u8>>,
+    /// Total size of the file content
+    pub filesize: Option<u64>,
+    /// Content size of each linked child, in link order
+    pub blocksizes: Vec<u64>,
+    /// Hash function of the HAMT shards
+    pub hash_type: Option<u64>,
+    /// Fanout of the HAMT shards
+    pub fanout: Option<u64>,
+}
+
+impl UnixFsData {
+    /// Creates the metadata of a node of the given type, with all the other fields unset
+    pub fn new(data_type: DataType) -> Self {
+        Self {
+            data_type,
+            data: None,
+            filesize: None,
+            blocksizes: Vec::new(),
+            hash_type: None,
+            fanout: None,
+        }
+    }
+
+    /// Decodes the UnixFS metadata from the `Data` field of a dag-pb node
+    ///
+    /// ## Returns
+    /// - `Ok(UnixFsData)` if the bytes are a well-formed UnixFS message.
+    /// - `Err(UnixFsError)` if the message is truncated, malformed or misses its type.
+    pub fn decode(bytes: &[u8]) -> Result<Self, UnixFsError> {
+        let mut data_type = None;
+        let mut node = UnixFsData::new(DataType::Raw);
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (field, wire_type) =
+                read_key(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?;
+            match (field, wire_type) {
+                (1, WIRE_TYPE_VARINT) => {
+                    let value = read_varint(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?;
+                    data_type =
+                        Some(DataType::from_u64(value).ok_or(UnixFsError::UnknownType(value))?);
+                }
+                (2, WIRE_TYPE_LEN) => {
+                    let data =
+                        read_len_delimited(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?;
+                    node.data = Some(data.to_vec());
+                }
+                (3, WIRE_TYPE_VARINT) => {
+                    node.filesize =
+                        Some(read_varint(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?);
+                }
+                (4, WIRE_TYPE_VARINT) => {
+                    let size = read_varint(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?;
+                    node.blocksizes.push(size);
+                }
+                (4, WIRE_TYPE_LEN) => {
+                    // Packed repeated field
+                    let packed =
+                        read_len_delimited(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?;
+                    let mut packed_pos = 0;
+                    while packed_pos < packed.len() {
+                        let size = read_varint(packed, &mut packed_pos)
+                            .map_err(|_| UnixFsError::Truncated)?;
+                        node.blocksizes.push(size);
+                    }
+                }
+                (5, WIRE_TYPE_VARINT) => {
+                    node.hash_type =
+                        Some(read_varint(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?);
+                }
+                (6, WIRE_TYPE_VARINT) => {
+                    node.fanout =
+                        Some(read_varint(bytes, &mut pos).map_err(|_| UnixFsError::Truncated)?);
+                }
+                (_, wire_type) => skip_field(bytes, &mut pos, wire_type)?,
+            }
+        }
+        node.data_type = data_type.ok_or(UnixFsError::MissingType)?;
+        Ok(node)
+    }
+
+    /// Encodes the UnixFS metadata, to be stored in the `Data` field of a dag-pb node
+    ///
+    /// Fields are written in field number order, and `blocksizes` is not packed, as done by the
+    /// reference implementations (so that the resulting CIDs match theirs).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_varint_field(&mut bytes, 1, self.data_type as u64);
+        if let Some(data) = &self.data {
+            write_len_delimited(&mut bytes, 2, data);
+        }
+        if let Some(filesize) = self.filesize {
+            write_varint_field(&mut bytes, 3, filesize);
+        }
+        for size in &self.blocksizes {
+            write_varint_field(&mut bytes, 4, *size);
+        }
+        if let Some(hash_type) = self.hash_type {
+            write_varint_field(&mut bytes, 5, hash_type);
+        }
+        if let Some(fanout) = self.fanout {
+            write_varint_field(&mut bytes, 6, fanout);
+        }
+        bytes
+    }
+}
+
+/// Writes a varint field (key and value)
+fn write_varint_field(bytes: &mut Vec<u8>, field: u64, value: u64) {
+    write_key(bytes, field, WIRE_TYPE_VARINT);
+    bytes.extend(UnsignedVarint(value).encode());
+}
+
+/// Skips a field of the given wire type, advancing the position past it
+fn skip_field(bytes: &[u8], pos: &mut usize, wire_type: u64) -> Result<(), UnixFsError> {
+    let size = match wire_type {
+        WIRE_TYPE_VARINT => {
+            read_varint(bytes, pos).map_err(|_| UnixFsError::Truncated)?;
+            0
+        }
+        WIRE_TYPE_LEN => {
+            read_len_delimited(bytes, pos).map_err(|_| UnixFsError::Truncated)?;
+            0
+        }
+        WIRE_TYPE_I64 => 8,
+        WIRE_TYPE_I32 => 4,
+        wire_type => return Err(UnixFsError::UnexpectedWireType(wire_type)),
+    };
+    if *pos + size > bytes.len() {
+        return Err(UnixFsError::Truncated);
+    }
+    *pos += size;
+    Ok(())
+}
+
+/// Errors related to UnixFS decoding
+#[derive(thiserror::Error, Debug)]
+pub enum UnixFsError {
+    /// The message is truncated (a varint or a field is cut in the middle)
+    #[error("Truncated UnixFS data")]
+    Truncated,
+    /// The message does not have a Type field
+    #[error("UnixFS data without type")]
+    MissingType,
+    /// The Type field is not a known UnixFS type
+    #[error("Unknown UnixFS type {0}")]
+    UnknownType(u64),
+    /// A field has a wire type which cannot be skipped
+    #[error("Unexpected protobuf wire type {0}")]
+    UnexpectedWireType(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unixfs_roundtrip() {
+        let mut file = UnixFsData::new(DataType::File);
+        file.filesize = Some(300_000);
+        file.blocksizes = vec![262_144, 37_856];
+        let encoded = file.encode();
+        assert_eq!(&encoded[..2], &[0x08, 0x02]);
+        assert_eq!(UnixFsData::decode(&encoded).unwrap(), file);
+
+        // Packed blocksizes and unknown fields (mode) are accepted
+        let packed = hex::decode("0802180a2202050538a403").unwrap();
+        let decoded = UnixFsData::decode(&packed).unwrap();
+        assert_eq!(decoded.data_type, DataType::File);
+        assert_eq!(decoded.filesize, Some(10));
+        assert_eq!(decoded.blocksizes, vec![5, 5]);
+
+        assert!(matches!(
+            UnixFsData::decode(&[0x18, 0x0a]),
+            Err(UnixFsError::MissingType)
+        ));
+        assert!(matches!(
+            UnixFsData::decode(&[0x08, 0x09]),
+            Err(UnixFsError::UnknownType(9))
+        ));
+    }
+}
@@ -12,10 +12,32 @@
 //!
 //! The main entry point for reading CAR files is the [CarReader] type,
 //! which can handle both CAR v1 and v2 formats transparently.  
-//! On the other hand, [CarWriter] is the way to write a new CAR archive from scratch.
+//! When the whole archive is already in memory, [CarSlice] reads its sections without copying the block data.  
+//! On the other hand, [CarWriter] is the way to write a new CAR archive from scratch, in either format.
+//!
+//! Archive metadata (producer, creation time, content descriptions) can be stored in the archive itself,
+//! following the convention of the [appendix module](appendix).
+//! Sections can be looked up by CID in memory, and CAR v2 indexes built or parsed, with the [index module](index).
+//! To look inside the blocks (e.g. resolving `<cid>/a/b/0` paths), see the [ipld module](ipld).
+//! To walk the blocks in the order of their DAG rather than in file order, see the [traverse module](traverse).
+//! To copy some DAGs of an archive (or a list of blocks) to a new archive, see the [export module](export).
+//! To compact several archives into one, see the `merge` module, and to cut one into size-bounded shards, the `split`
+//! module (feature `std-io`). CAR v1 archives can be wrapped in an indexed CAR v2 envelope (and unwrapped back) with
+//! the `convert` module (feature `std-io`).
+//! To resolve UnixFS paths and extract the content of files from an archive, or to create an archive from files and
+//! directories, see the `unixfs` module (feature `unixfs`).
+//! To pack content into a new CAR file using all the available cores, see the `pack` module (feature `pack`).
+//! To distribute private content, the blocks can be sealed with an AEAD key with the `envelope` module (feature `encryption`).
+//! Known-good CAR archives to test against are available in the `testdata` module (feature `test-fixtures`),
+//! and readers can be driven with adversarial feedings with the `chaos` module (feature `test-util`).
+//!
+//! What this build supports (formats, index types, CID versions, verification hashes) can be introspected at runtime
+//! with [capabilities()].
 //!
 //! If you prefer to not think about IO, you should check the [stdio module](stdio) for utilities
 //! based on [std::io::Read], [std::io::Seek], and [std::io::Write].
+//! Their async counterpart, over tokio I/O, is the `tokio` module (feature `tokio`).
+//! Data received in shared buffers (e.g. network frames) can be fed without copy with the `bytes` feature.
 //!
 //! ## Usages
 //!
@@ -23,32 +45,72 @@
 //! ```rust
 //! let car_bytes = include_bytes!("res/carv1-basic.car");
 //!
-//! // Create a CarReader and feed it the CAR file bytes
-//! let mut reader = navira_car::CarReader::new();
-//! reader.receive_data(car_bytes, 0);
-//!
-//! // Try to read the header (it should succeed since we have the full CAR file in memory)
-//! reader.read_header().unwrap();
+//! // Create a CarReader, feed it the whole CAR file and read the header in one call
+//! let mut reader = navira_car::CarReader::from_bytes(car_bytes).unwrap();
 //! assert_eq!(reader.get_format(), Some(navira_car::CarFormat::V1));
 //!
-//! // Print all the CIDs of the blocks in the CAR file
+//! // Print all the CIDs of the blocks in the CAR file, with their location in the file
 //! while let Ok(sect) = reader.read_section() {
-//!     println!("Block raw/binary CID: {}", sect.cid().to_hex());
+//!     println!(
+//!         "Block raw/binary CID: {} (offset: {}, length: {})",
+//!         sect.cid().to_hex(),
+//!         sect.location.offset,
+//!         sect.location.length
+//!     );
 //! }
 //! assert!(reader.read_section().is_err()); // We should have reached the end of the CAR file
 //!
 //! //>> Output:
-//! // Block raw/binary CID: 01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b
-//! // Block raw/binary CID: 122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de
-//! // Block raw/binary CID: 01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451
-//! // Block raw/binary CID: 122079a982de3c9907953d4d323cee1d0fb1ed8f45f8ef02870c0cb9e09246bd530a
-//! // Block raw/binary CID: 0155122081cc5b17018674b401b42f35ba07bb79e211239c23bffe658da1577e3e646877
-//! // Block raw/binary CID: 1220e7dc486e97e6ebe5cdabab3e392bdad128b6e09acc94bb4e2aa2af7b986d24d0
-//! // Block raw/binary CID: 0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4
-//! // Block raw/binary CID: 0171122069ea0740f9807a28f4d932c62e7c1c83be055e55072c90266ab3e79df63a365b
+//! // Block raw/binary CID: 01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b (offset: 100, length: 92)
+//! // Block raw/binary CID: 122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de (offset: 192, length: 133)
+//! // Block raw/binary CID: 01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451 (offset: 325, length: 41)
+//! // Block raw/binary CID: 122079a982de3c9907953d4d323cee1d0fb1ed8f45f8ef02870c0cb9e09246bd530a (offset: 366, length: 130)
+//! // Block raw/binary CID: 0155122081cc5b17018674b401b42f35ba07bb79e211239c23bffe658da1577e3e646877 (offset: 496, length: 41)
+//! // Block raw/binary CID: 1220e7dc486e97e6ebe5cdabab3e392bdad128b6e09acc94bb4e2aa2af7b986d24d0 (offset: 537, length: 82)
+//! // Block raw/binary CID: 0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4 (offset: 619, length: 41)
+//! // Block raw/binary CID: 0171122069ea0740f9807a28f4d932c62e7c1c83be055e55072c90266ab3e79df63a365b (offset: 660, length: 55)
 //! ```
 //!
+//! ### Feed a CAR file incrementally
+//! When the CAR file is not fully available in memory, the [CarReader] tells you which bytes it needs next,
+//! through [CarReaderError::InsufficientData]:
+//! ```rust
+//! use navira_car::{CarReader, CarReaderError};
 //!
+//! let car_bytes = include_bytes!("res/carv2-basic.car");
+//! let mut reader = CarReader::new();
+//! let mut count = 0;
+//! loop {
+//!     let result = if reader.has_header() {
+//!         reader.read_section().map(|_| count += 1)
+//!     } else {
+//!         reader.read_header()
+//!     };
+//!     match result {
+//!         Ok(()) => continue,
+//!         Err(CarReaderError::InsufficientData(offset, _)) if offset < car_bytes.len() => {
+//!             // Read (at most) 64 bytes at the requested offset
+//!             let end = (offset + 64).min(car_bytes.len());
+//!             reader.receive_data(&car_bytes[offset..end], offset);
+//!         }
+//!         Err(_) => break, // End of the CAR file
+//!     }
+//! }
+//! assert_eq!(count, 5);
+//! ```
+//!
+//! ## Panic-free guarantee
+//!
+//! The parsers and writers of this library are meant to be exposed to untrusted input (e.g. network peers):
+//! malformed archives, headers, sections and indexes are reported with typed errors, and misuses of the
+//! sans-IO state machines (reading before the header, too small buffers, …) with errors or documented
+//! no-ops, never with a panic. This is part of the API contract, and enforced at compile time by denying
+//! `clippy::panic`, `clippy::unwrap_used`, `clippy::expect_used` and `clippy::indexing_slicing` in the library
+//! code, and `clippy::arithmetic_side_effects` in the [wire] module, where the offsets and lengths read from the
+//! archives are computed.
+//!
+//! Only the test helpers are exempt, as they are expected to panic on failures: the `testdata` module (feature
+//! `test-fixtures`) and the `chaos` module (feature `test-util`).
 //!
 //! ## Alternatives
 //!
@@ -57,18 +119,83 @@
 //! - [rust-car](https://crates.io/crates/rust-car)
 //! - [blockless-car](https://crates.io/crates/blockless-car)
 #![feature(doc_cfg)]
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::indexing_slicing
+    )
+)]
 
+mod trace;
+
+pub mod appendix;
+pub mod capabilities;
+pub mod event;
+pub mod export;
+pub mod index;
+pub mod inspect;
+pub mod ipld;
 pub mod read;
+pub mod slice;
+pub mod traverse;
+pub mod verify;
 pub mod wire;
+pub mod write;
+
+#[cfg(feature = "pack")]
+#[doc(cfg(feature = "pack"))]
+pub mod pack;
+
+#[cfg(feature = "encryption")]
+#[doc(cfg(feature = "encryption"))]
+pub mod envelope;
+
+#[cfg(feature = "unixfs")]
+#[doc(cfg(feature = "unixfs"))]
+pub mod unixfs;
+
+#[cfg(any(test, feature = "test-fixtures"))]
+#[doc(cfg(feature = "test-fixtures"))]
+#[allow(clippy::expect_used, clippy::indexing_slicing)]
+pub mod testdata;
+
+#[cfg(any(test, feature = "test-util"))]
+#[doc(cfg(feature = "test-util"))]
+#[allow(clippy::panic, clippy::indexing_slicing)]
+pub mod chaos;
 
 #[cfg(any(feature = "std-io", doc))]
 #[doc(cfg(feature = "std-io"))]
 pub mod stdio;
 
-pub use read::{CarFormat, CarReader, CarReaderError};
-pub use wire::v2::CarWriterError;
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub mod merge;
+
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub mod split;
+
+#[cfg(feature = "std-io")]
+#[doc(cfg(feature = "std-io"))]
+pub mod convert;
+
+#[cfg(feature = "tokio")]
+#[doc(cfg(feature = "tokio"))]
+pub mod tokio;
 
-pub type CarWriter = wire::v2::CarWriter<wire::v2::SectionWritingState>;
+pub use capabilities::capabilities;
+pub use event::CarEvent;
+pub use read::{
+    CarFormat, CarReader, CarReaderBuilder, CarReaderError, DuplicatePolicy, DuplicateSection,
+    PartialState, ReaderCheckpoint,
+};
+pub use slice::CarSlice;
+pub use wire::limits::Limits;
+pub use write::{CarWriter, CarWriterError};
 
 pub(crate) mod types {
     pub trait Sealed {}
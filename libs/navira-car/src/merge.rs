@@ -0,0 +1,310 @@
+//! Merging of several CAR archives into one
+//!
+//! Operators receiving many small archives (e.g. one per upload) often want to compact them into fewer, larger
+//! ones before storing them. [merge_cars] reads the sections of its inputs, one input after the other, and writes
+//! them to a single archive whose header lists the roots of all the inputs. Sections are copied as they are, without
+//! being re-hashed, and the blocks present in several inputs are written once (see [DedupPolicy]).
+//!
+//! The merged archive is written as a CAR v1, or as a CAR v2 with a fresh index of all its sections.
+//!
+//! ## Examples
+//! ```
+//! use std::io::Cursor;
+//! use navira_car::CarFormat;
+//! use navira_car::merge::{MergeOptions, merge_cars};
+//! use navira_car::stdio::CarReader;
+//!
+//! let inputs = [
+//!     &include_bytes!("res/carv1-basic.car")[..],
+//!     &include_bytes!("res/carv2-basic.car")[..],
+//! ];
+//! let readers = inputs.map(|input| CarReader::open(Cursor::new(input)).unwrap());
+//! let options = MergeOptions {
+//!     format: CarFormat::V2,
+//!     ..MergeOptions::default()
+//! };
+//! let mut car = Cursor::new(Vec::new());
+//! let summary = merge_cars(readers, &mut car, &options).unwrap();
+//! assert_eq!(summary.roots.len(), 3);
+//! assert_eq!(summary.sections, 13);
+//!
+//! let mut merged = CarReader::open(Cursor::new(car.into_inner())).unwrap();
+//! assert_eq!(merged.get_roots().len(), 3);
+//! assert_eq!(merged.sections().count(), 13);
+//! ```
+
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::read::CarFormat;
+use crate::stdio::{CarReader, CarReaderError};
+use crate::wire::cid::RawCid;
+use crate::wire::v1::WriteDedup;
+use crate::write::{CarWriter, CarWriterError};
+
+/// How the sections found in several inputs (or several times in the same input) are de-duplicated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupPolicy {
+    /// All the sections are written, duplicates included
+    Keep,
+    /// Only the first section of each CID is written
+    #[default]
+    Cid,
+    /// Only the first section of each multihash is written: the same block under CIDs of different versions or
+    /// codecs (e.g. a CIDv0 and its CIDv1) is written once, under its first CID
+    Multihash,
+}
+
+/// Options of [merge_cars]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// How the duplicate sections are handled, by CID by default
+    pub dedup: DedupPolicy,
+    /// Format of the merged archive, CAR v1 by default
+    pub format: CarFormat,
+    /// Should a CAR v2 archive be indexed? (default: true, ignored for CAR v1)
+    pub index: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            dedup: DedupPolicy::default(),
+            format: CarFormat::V1,
+            index: true,
+        }
+    }
+}
+
+/// Summary of a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Roots of the merged archive: the roots of the inputs, in order, each once
+    pub roots: Vec<RawCid>,
+    /// Number of sections written
+    pub sections: u64,
+    /// Number of duplicate sections skipped
+    pub duplicates: u64,
+    /// Number of bytes written to the output
+    pub bytes_written: u64,
+}
+
+/// Merge CAR archives into a single one
+///
+/// The header of the merged archive lists the roots of all the inputs (in input order, each once), and the
+/// sections follow in input order, the duplicates being skipped according to [MergeOptions::dedup].
+///
+/// # Arguments
+/// * `inputs` - The archives to merge, their headers already read (see [CarReader::open]).
+/// * `output` - Where to write the merged archive, from its current position.
+/// * `options` - The de-duplication policy, and the format of the merged archive.
+///
+/// # Returns
+/// * `Ok(MergeSummary)` - The roots of the merged archive, and statistics about it.
+/// * `Err(MergeError)` - An input is malformed, or writing the output failed. The output may hold a partial archive.
+pub fn merge_cars<R, I, W>(
+    inputs: I,
+    output: &mut W,
+    options: &MergeOptions,
+) -> Result<MergeSummary, MergeError>
+where
+    R: Read + Seek,
+    I: IntoIterator<Item = CarReader<R>>,
+    W: Write + Seek,
+{
+    let mut inputs: Vec<_> = inputs.into_iter().collect();
+    let mut roots = Vec::new();
+    let mut seen_roots = HashSet::new();
+    for input in &inputs {
+        for root in input.get_roots() {
+            let root = root.to_raw_cid();
+            if seen_roots.insert(root) {
+                roots.push(root.clone());
+            }
+        }
+    }
+
+    let start = output.stream_position()?;
+    let mut writer = CarWriter::new(options.format, roots.clone());
+    writer.set_indexed(options.index);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut end = 0;
+    let mut summary = MergeSummary {
+        roots,
+        sections: 0,
+        duplicates: 0,
+        bytes_written: 0,
+    };
+    let mut seen = HashSet::new();
+    for (input_index, input) in inputs.iter_mut().enumerate() {
+        for section in input.sections() {
+            let section = section.map_err(|error| MergeError::Input {
+                input: input_index,
+                error,
+            })?;
+            let key = match options.dedup {
+                DedupPolicy::Keep => None,
+                DedupPolicy::Cid => Some(section.cid().bytes().to_vec()),
+                DedupPolicy::Multihash => WriteDedup::Multihash.key(section.cid()),
+            };
+            if key.is_some_and(|key| !seen.insert(key)) {
+                summary.duplicates += 1;
+                continue;
+            }
+            loop {
+                match writer.write_section(&section.section) {
+                    Ok(_) => break,
+                    Err(CarWriterError::BufferFull) if writer.has_data_to_send() => {
+                        summary.bytes_written +=
+                            drain(&mut writer, output, start, &mut end, &mut buf)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            summary.sections += 1;
+        }
+    }
+    writer.finish();
+    summary.bytes_written += drain(&mut writer, output, start, &mut end, &mut buf)?;
+    // The CAR v2 header is sent last, at the start of the archive
+    output.seek(SeekFrom::Start(start + end))?;
+    output.flush()?;
+    Ok(summary)
+}
+
+/// Move the data of the writer to the output, at the offsets it tells
+///
+/// Returns the number of bytes written, and keeps track of the end of the archive (relative to its start).
+fn drain<W: Write + Seek>(
+    writer: &mut CarWriter,
+    output: &mut W,
+    start: u64,
+    end: &mut u64,
+    buf: &mut [u8],
+) -> io::Result<u64> {
+    let mut written = 0;
+    while writer.has_data_to_send() {
+        let (offset, length) = writer.send_data(buf);
+        output.seek(SeekFrom::Start(start + offset as u64))?;
+        output.write_all(buf.get(..length).unwrap_or_default())?;
+        written += length as u64;
+        *end = (*end).max((offset + length) as u64);
+    }
+    Ok(written)
+}
+
+/// Errors related to the merge of CAR archives
+#[derive(thiserror::Error, Debug)]
+pub enum MergeError {
+    /// An input could not be read
+    #[error("Invalid input #{input}: {error}")]
+    Input {
+        /// Index of the input, in the order they were given
+        input: usize,
+        /// The reading error
+        error: CarReaderError,
+    },
+    /// A section could not be written to the merged archive
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+    /// IO error while writing the output
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testdata::{CARV1_BASIC, CARV2_BASIC};
+    use crate::wire::v1::{Block, CarWriter as CarV1Writer, Section};
+
+    fn open(car: &[u8]) -> CarReader<Cursor<Vec<u8>>> {
+        CarReader::open(Cursor::new(car.to_vec())).unwrap()
+    }
+
+    /// A CAR v1 of the given raw blocks, under the first one
+    fn car_of(blocks: &[(RawCid, &[u8])]) -> Vec<u8> {
+        let mut writer = CarV1Writer::new(vec![blocks[0].0.clone()]);
+        for (cid, data) in blocks {
+            writer
+                .write_section(&Section::new(cid.clone(), Block::new(data.to_vec())))
+                .unwrap();
+        }
+        let mut car = vec![0u8; 4096];
+        let len = writer.send_data(&mut car);
+        car.truncate(len);
+        car
+    }
+
+    #[test]
+    fn test_merge_dedup() {
+        let v0 = RawCid::from_hex(
+            "1220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        let v1 = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        let identity = RawCid::from_hex("0155000568656c6c6f").unwrap();
+        let inputs = || {
+            [
+                car_of(&[(v1.clone(), b"cccc"), (identity.clone(), b"hello")]),
+                car_of(&[(v0.clone(), b"cccc"), (v1.clone(), b"cccc")]),
+            ]
+        };
+
+        for (dedup, sections) in [
+            (DedupPolicy::Keep, 4),
+            (DedupPolicy::Cid, 3),
+            (DedupPolicy::Multihash, 2),
+        ] {
+            let options = MergeOptions {
+                dedup,
+                ..MergeOptions::default()
+            };
+            let mut car = Cursor::new(Vec::new());
+            let summary =
+                merge_cars(inputs().map(|input| open(&input)), &mut car, &options).unwrap();
+            assert_eq!(summary.roots, vec![v1.clone(), v0.clone()]);
+            assert_eq!(summary.sections, sections);
+            assert_eq!(summary.duplicates, 4 - sections);
+            assert_eq!(summary.bytes_written, car.get_ref().len() as u64);
+            let mut merged = open(car.get_ref());
+            assert_eq!(merged.sections().count() as u64, sections);
+        }
+    }
+
+    #[test]
+    fn test_merge_v2_index() {
+        // The second input is merged again: all its sections are duplicates
+        let inputs = [CARV1_BASIC, CARV2_BASIC, CARV2_BASIC].map(open);
+        let options = MergeOptions {
+            format: CarFormat::V2,
+            ..MergeOptions::default()
+        };
+        let mut car = Cursor::new(vec![0xff; 10]);
+        car.seek(SeekFrom::End(0)).unwrap();
+        let summary = merge_cars(inputs, &mut car, &options).unwrap();
+        assert_eq!(summary.duplicates, 5);
+        assert_eq!(car.position(), 10 + summary.bytes_written);
+
+        let mut merged = open(&car.get_ref()[10..]);
+        assert_eq!(merged.get_format(), CarFormat::V2);
+        // Every section is found through the fresh index
+        let mut fixtures = open(CARV1_BASIC).sections().collect::<Vec<_>>();
+        fixtures.extend(open(CARV2_BASIC).sections());
+        for section in fixtures {
+            let section = section.unwrap();
+            let found = merged.find_section(section.cid()).unwrap().unwrap();
+            assert_eq!(found.block().data(), section.block().data());
+        }
+
+        // A malformed input is reported with its position
+        let inputs = [open(CARV1_BASIC), open(&CARV1_BASIC[..300])];
+        let err = merge_cars(inputs, &mut Cursor::new(Vec::new()), &options).unwrap_err();
+        assert!(matches!(err, MergeError::Input { input: 1, .. }));
+    }
+}
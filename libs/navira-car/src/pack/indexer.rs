@@ -0,0 +1,463 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use crate::verify::{DigestComparison, DigestError, verify_block};
+use crate::wire::cid::{CidParsing, RawCid};
+use crate::wire::limits::Limits;
+use crate::wire::v1::{Section, SectionFormatError};
+use crate::wire::v2::{CAR_V2_PRAGMA, Index};
+use crate::wire::varint::UnsignedVarint;
+
+/// Maximum size of the length varint of a section or header
+const MAX_VARINT_SIZE: usize = 10;
+
+/// Tuning knobs of the [Indexer]
+#[derive(Debug, Clone)]
+pub struct IndexerConfig {
+    /// Number of workers parsing (and validating) the sections
+    ///
+    /// Defaults to the available parallelism of the machine.
+    pub workers: usize,
+    /// Capacity of the bounded channel between the scanning thread and the workers
+    ///
+    /// This bounds the memory usage to roughly `(queue_depth + workers) * read_size` bytes.
+    pub queue_depth: usize,
+    /// Number of bytes read at once from the CAR file, 4 MiB by default
+    ///
+    /// Each read is dispatched to a worker as a single batch of sections.
+    pub read_size: usize,
+    /// Whether the blocks are hashed and checked against their CID
+    ///
+    /// Blocks whose hash function is not supported are indexed without being checked.
+    pub validate: bool,
+    /// Size limits applied to the sections
+    pub limits: Limits,
+}
+
+impl Default for IndexerConfig {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_depth: 4,
+            read_size: 4 * 1024 * 1024,
+            validate: true,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Summary of an indexing operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSummary {
+    /// MultihashIndexSorted index of the sections, offsets relative to the start of the CARv1 file
+    pub index: Index,
+    /// Number of sections
+    pub sections: u64,
+    /// Number of blocks which could not be validated (unsupported hash function, or validation disabled)
+    pub unhashed: u64,
+    /// Size of the CARv1 file
+    pub bytes: u64,
+}
+
+impl IndexSummary {
+    /// Write the index as a sidecar of the CAR file (see [sidecar_path])
+    ///
+    /// # Returns
+    /// The path of the written sidecar.
+    pub fn write_sidecar<P: AsRef<Path>>(&self, car_path: P) -> std::io::Result<PathBuf> {
+        let path = sidecar_path(car_path);
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(&self.index.encode())?;
+        file.sync_all()?;
+        Ok(path)
+    }
+}
+
+/// Path of the sidecar index of a CAR file: the path of the CAR file with an `.idx` suffix
+///
+/// ## Examples
+/// ```
+/// use navira_car::pack::sidecar_path;
+///
+/// assert_eq!(sidecar_path("data/blocks.car"), std::path::Path::new("data/blocks.car.idx"));
+/// ```
+pub fn sidecar_path<P: AsRef<Path>>(car_path: P) -> PathBuf {
+    let mut path = car_path.as_ref().as_os_str().to_os_string();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+/// A run of complete sections read from the CAR file, waiting to be parsed
+struct Batch {
+    /// Offset of the first byte of `data` in the CAR file
+    offset: u64,
+    data: Vec<u8>,
+    /// Bounds of the sections in `data`
+    sections: Vec<(usize, usize)>,
+}
+
+/// Index entries of a parsed batch
+struct Parsed {
+    entries: Vec<(RawCid, u64)>,
+    unhashed: u64,
+}
+
+/// Multi-threaded indexer of finished CARv1 files
+///
+/// The indexer is made of two stages, connected with a bounded channel:
+/// 1. A scanning thread reads the file in large chunks, and only decodes the length prefixes to find the
+///    section boundaries. Each chunk of complete sections is dispatched as a batch.
+/// 2. A pool of workers parses the CIDs of the sections of each batch, and hashes the blocks when validating.
+///
+/// The calling thread collects the entries and builds a MultihashIndexSorted index, whose offsets are relative
+/// to the start of the CARv1 file (i.e. suitable for a CARv2 wrapping it at any data offset).
+/// The output does not depend on the number of workers nor on the scheduling.
+#[derive(Debug, Clone, Default)]
+pub struct Indexer {
+    config: IndexerConfig,
+}
+
+impl Indexer {
+    /// Create a new Indexer with the given configuration
+    pub fn new(config: IndexerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the configuration of the Indexer
+    pub fn config(&self) -> &IndexerConfig {
+        &self.config
+    }
+
+    /// Index a CARv1 file read from its start
+    ///
+    /// # Returns
+    /// * `Ok(IndexSummary)` - The index of the file, and statistics about the indexing.
+    /// * `Err(IndexerError)` - The file could not be read, is not a CARv1 file, or a section is invalid.
+    pub fn index<R: Read + Send>(&self, source: R) -> Result<IndexSummary, IndexerError> {
+        let workers = self.config.workers.max(1);
+        let queue_depth = self.config.queue_depth.max(1);
+        let read_size = self.config.read_size.max(MAX_VARINT_SIZE);
+        let limits = self.config.limits;
+        let validate = self.config.validate;
+
+        let (batch_tx, batch_rx) = mpsc::sync_channel::<Batch>(queue_depth);
+        let (parsed_tx, parsed_rx) = mpsc::channel::<Result<Parsed, IndexerError>>();
+        let batch_rx = Arc::new(Mutex::new(batch_rx));
+
+        thread::scope(|scope| {
+            // Stage 1: scanning
+            let scanner = scope.spawn(move || scan(source, read_size, &limits, batch_tx));
+
+            // Stage 2: parsing
+            for _ in 0..workers {
+                let batch_rx = batch_rx.clone();
+                let parsed_tx = parsed_tx.clone();
+                scope.spawn(move || {
+                    loop {
+                        let batch = match batch_rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(batch) = batch else {
+                            return;
+                        };
+                        let parsed = parse_batch(&batch, &limits, validate);
+                        let failed = parsed.is_err();
+                        if parsed_tx.send(parsed).is_err() || failed {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(batch_rx);
+            drop(parsed_tx);
+
+            // Collect the entries, stopping the other stages at the first error
+            let mut entries = Vec::new();
+            let mut unhashed = 0;
+            let mut error = None;
+            for parsed in parsed_rx.iter() {
+                match parsed {
+                    Ok(parsed) => {
+                        entries.extend(parsed.entries);
+                        unhashed += parsed.unhashed;
+                    }
+                    Err(e) => {
+                        // Dropping the receiver stops the workers at their next batch, and then the scanner
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+            drop(parsed_rx);
+            let scanned = scanner.join().map_err(|_| IndexerError::WorkerPanicked)?;
+            if let Some(e) = error {
+                return Err(e);
+            }
+            let (bytes, sections) = scanned?;
+            if entries.len() as u64 != sections {
+                // Some batches were lost, which means a parsing worker panicked
+                return Err(IndexerError::WorkerPanicked);
+            }
+            Ok(IndexSummary {
+                index: Index::multihash_sorted(entries.iter().map(|(cid, offset)| (cid, *offset))),
+                sections,
+                unhashed,
+                bytes,
+            })
+        })
+    }
+
+    /// Index a CARv1 file, and write its index as a sidecar (see [sidecar_path])
+    pub fn index_file<P: AsRef<Path>>(&self, path: P) -> Result<IndexSummary, IndexerError> {
+        let summary = self.index(std::fs::File::open(&path)?)?;
+        summary.write_sidecar(path)?;
+        Ok(summary)
+    }
+}
+
+/// Read a full chunk, shorter only at the end of the reader, appended to `buf`
+fn fill<R: Read>(reader: &mut R, buf: &mut Vec<u8>, size: usize) -> std::io::Result<usize> {
+    let len = buf.len();
+    reader.take(size as u64).read_to_end(buf)?;
+    Ok(buf.len() - len)
+}
+
+/// Scanning stage: finds the section boundaries and dispatches the batches
+///
+/// Returns the size of the file, and the number of dispatched sections.
+fn scan<R: Read>(
+    mut source: R,
+    read_size: usize,
+    limits: &Limits,
+    batch_tx: mpsc::SyncSender<Batch>,
+) -> Result<(u64, u64), IndexerError> {
+    // Skip the header, whose length is known from its prefix
+    let mut data = Vec::with_capacity(read_size);
+    fill(&mut source, &mut data, CAR_V2_PRAGMA.len())?;
+    if data.starts_with(CAR_V2_PRAGMA) {
+        return Err(IndexerError::NotCarV1);
+    }
+    let (UnsignedVarint(header_length), varint_size) =
+        UnsignedVarint::decode(&data).ok_or(IndexerError::InvalidHeader)?;
+    if header_length == 0 {
+        return Err(IndexerError::InvalidHeader);
+    }
+    let header_end = varint_size as u64 + header_length;
+    if header_end > data.len() as u64 {
+        let missing = header_end - data.len() as u64;
+        if std::io::copy(&mut Read::take(&mut source, missing), &mut std::io::sink())? < missing {
+            return Err(IndexerError::InvalidHeader);
+        }
+        data.clear();
+    } else {
+        data.drain(..header_end as usize);
+    }
+
+    let mut offset = header_end;
+    let mut dispatched = 0;
+    loop {
+        let eof = fill(&mut source, &mut data, read_size)? == 0;
+        // Find the complete sections at the start of the buffer
+        let mut sections = Vec::new();
+        let mut start = 0;
+        while start < data.len() {
+            let Some((UnsignedVarint(length), varint_size)) =
+                UnsignedVarint::decode(data.get(start..).unwrap_or_default())
+            else {
+                if data.len() - start >= MAX_VARINT_SIZE {
+                    return Err(IndexerError::Section(
+                        offset + start as u64,
+                        SectionFormatError::InvalidSize(usize::MAX),
+                    ));
+                }
+                break;
+            };
+            if length == 0 || !limits.allows_section(length) {
+                return Err(IndexerError::Section(
+                    offset + start as u64,
+                    SectionFormatError::InvalidSize(length as usize),
+                ));
+            }
+            let end = start + varint_size + length as usize;
+            if end > data.len() {
+                break;
+            }
+            sections.push((start, end));
+            start = end;
+        }
+        if eof {
+            if start < data.len() {
+                return Err(IndexerError::Truncated(offset + start as u64));
+            }
+            dispatched += sections.len() as u64;
+            if !sections.is_empty() {
+                let batch = Batch {
+                    offset,
+                    data,
+                    sections,
+                };
+                // A closed channel means a worker failed, the collector reports its error
+                let _ = batch_tx.send(batch);
+            }
+            return Ok((offset + start as u64, dispatched));
+        }
+        if sections.is_empty() {
+            // The next section is larger than the reads, keep accumulating
+            continue;
+        }
+        let rest = data.get(start..).unwrap_or_default().to_vec();
+        data.truncate(start);
+        dispatched += sections.len() as u64;
+        let batch = Batch {
+            offset,
+            data,
+            sections,
+        };
+        if batch_tx.send(batch).is_err() {
+            return Ok((offset, dispatched));
+        }
+        offset += start as u64;
+        data = Vec::with_capacity(read_size + rest.len());
+        data.extend_from_slice(&rest);
+    }
+}
+
+/// Parsing stage: reads the CIDs of the sections of a batch, and validates their blocks
+fn parse_batch(batch: &Batch, limits: &Limits, validate: bool) -> Result<Parsed, IndexerError> {
+    let mut parsed = Parsed {
+        entries: Vec::with_capacity(batch.sections.len()),
+        unhashed: 0,
+    };
+    for &(start, end) in &batch.sections {
+        let offset = batch.offset + start as u64;
+        let bytes = batch.data.get(start..end).unwrap_or_default();
+        let (section, _) =
+            Section::try_read_header_bytes_with(bytes, limits, CidParsing::default())
+                .map_err(|e| IndexerError::Section(offset, e))?;
+        let (cid, _) = section.into_parts();
+        if validate {
+            // The scanner already decoded the length prefix
+            let data_start =
+                UnsignedVarint::decode(bytes).map_or(0, |(_, size)| size) + cid.bytes().len();
+            match verify_block(
+                &cid,
+                bytes.get(data_start..).unwrap_or_default(),
+                DigestComparison::Variable,
+            ) {
+                Ok(()) => {}
+                Err(DigestError::UnsupportedHash(_)) => parsed.unhashed += 1,
+                Err(e) => return Err(IndexerError::Digest(offset, e)),
+            }
+        } else {
+            parsed.unhashed += 1;
+        }
+        parsed.entries.push((cid, offset));
+    }
+    Ok(parsed)
+}
+
+/// Errors related to the indexing of CARv1 files
+#[derive(thiserror::Error, Debug)]
+pub enum IndexerError {
+    /// IO error while reading the CAR file or writing the sidecar
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is a CARv2 file, which carries its own index
+    #[error("Not a CARv1 file")]
+    NotCarV1,
+    /// The length prefix of the header is invalid, or the header is truncated
+    #[error("Invalid CARv1 header")]
+    InvalidHeader,
+    /// The section at this offset is invalid
+    #[error("Invalid section at offset {0}: {1}")]
+    Section(u64, SectionFormatError),
+    /// The block of the section at this offset does not match its CID
+    #[error("Invalid block at offset {0}: {1}")]
+    Digest(u64, DigestError),
+    /// The file ends in the middle of the section at this offset
+    #[error("Truncated section at offset {0}")]
+    Truncated(u64),
+    /// A thread of the indexer panicked
+    #[error("An indexing thread panicked")]
+    WorkerPanicked,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata;
+
+    fn index_with(
+        workers: usize,
+        read_size: usize,
+        car: &[u8],
+    ) -> Result<IndexSummary, IndexerError> {
+        let config = IndexerConfig {
+            workers,
+            read_size,
+            ..IndexerConfig::default()
+        };
+        Indexer::new(config).index(car)
+    }
+
+    #[test]
+    fn test_indexer_matches_sequential_reader() {
+        let mut reader = crate::CarReader::from_bytes(testdata::CARV1_BASIC).unwrap();
+        let mut expected = Vec::new();
+        while let Ok(section) = reader.read_section() {
+            expected.push((section.cid().clone(), section.location.offset));
+        }
+        let expected = Index::multihash_sorted(expected.iter().map(|(cid, offset)| (cid, *offset)));
+
+        // Reads smaller than a section, or holding the whole file
+        for (workers, read_size) in [(1, 16), (3, 50), (4, 1 << 20)] {
+            let summary = index_with(workers, read_size, testdata::CARV1_BASIC).unwrap();
+            assert_eq!(summary.index, expected);
+            assert_eq!(summary.sections, 8);
+            assert_eq!(summary.bytes, testdata::CARV1_BASIC.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_indexer_errors() {
+        assert!(matches!(
+            index_with(2, 64, testdata::CARV2_BASIC),
+            Err(IndexerError::NotCarV1)
+        ));
+        let truncated = &testdata::CARV1_BASIC[..testdata::CARV1_BASIC.len() - 1];
+        let last = testdata::CARV1_BASIC_SECTIONS.last().unwrap().location();
+        assert!(matches!(
+            index_with(2, 64, truncated),
+            Err(IndexerError::Truncated(offset)) if offset == last.offset
+        ));
+
+        // A corrupted block is caught when validating, and indexed otherwise
+        let mut corrupted = testdata::CARV1_BASIC.to_vec();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert!(matches!(
+            index_with(2, 64, &corrupted),
+            Err(IndexerError::Digest(offset, _)) if offset == last.offset
+        ));
+        let config = IndexerConfig {
+            validate: false,
+            ..IndexerConfig::default()
+        };
+        let summary = Indexer::new(config).index(&corrupted[..]).unwrap();
+        assert_eq!(summary.unhashed, 8);
+    }
+
+    #[test]
+    fn test_indexer_sidecar() {
+        let dir = std::env::temp_dir().join(format!("navira-car-indexer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let car = dir.join("basic.car");
+        std::fs::write(&car, testdata::CARV1_BASIC).unwrap();
+        let summary = Indexer::default().index_file(&car).unwrap();
+        let (index, _) = Index::decode(&std::fs::read(sidecar_path(&car)).unwrap()).unwrap();
+        assert_eq!(index, summary.index);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! CAR packing pipeline
+//!
+//! Packing content into a CAR file is made of three stages: chunking the content into blocks,
+//! hashing the blocks to compute their CIDs, and serializing the sections into the CAR stream.
+//! Hashing is by far the most CPU-intensive stage, so running the stages sequentially on a single
+//! thread leaves fast storage (e.g. NVMe drives) largely idle.
+//!
+//! This module provides:
+//! - [PackerCore], the sans-IO serialization stage. It accepts hashed sections in any order (tagged with
+//!   their sequence number), restores the chunking order and feeds them to a [CarWriter](crate::wire::v1::CarWriter).
+//!   It does not spawn any thread nor perform any IO, so it can also feed async sinks.
+//! - [Packer], a multi-threaded pipeline built on top of [PackerCore]: a chunking thread reads the sources,
+//!   a pool of workers hashes the chunks, and the calling thread serializes them to a [Write](std::io::Write) sink.
+//!   Stages are connected with bounded channels, see [PackerConfig] for the tuning knobs.
+//!
+//! Chunks are packed as raw blocks (CIDv1, raw codec, sha2-256). The CIDs of the chunks of each source are
+//! reported in the [PackSummary], so that the caller can build a DAG on top of them.
+//!
+//! To index a finished CARv1 file (e.g. to wrap it into a CARv2, or to write a sidecar index), see [Indexer]:
+//! it scans the section boundaries on one thread and parses (and validates) the sections on all the cores.
+//!
+//! To migrate an existing UnixFS file to another chunk size (e.g. from 256 KiB to 1 MiB blocks), see [rechunk]:
+//! it streams the file out of its source DAG and writes the new DAG as a CAR stream.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::pack::{Packer, PackerConfig};
+//!
+//! let content = vec![0xAB; 10_000];
+//! let config = PackerConfig {
+//!     chunk_size: 4096,
+//!     ..PackerConfig::default()
+//! };
+//! let mut car = Vec::new();
+//! let summary = Packer::new(config)
+//!     .pack(vec![&content[..]], Vec::new(), &mut car)
+//!     .unwrap();
+//! assert_eq!(summary.sources[0].len(), 3);
+//!
+//! let mut reader = navira_car::CarReader::from_bytes(&car).unwrap();
+//! let first = reader.read_section().unwrap();
+//! assert_eq!(first.cid(), &summary.sources[0][0]);
+//! ```
+
+mod indexer;
+mod pipeline;
+mod rechunk;
+
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::wire::cid::RawCid;
+use crate::wire::limits::Limits;
+use crate::wire::v1::{CarWriter, CarWriterError, Section, SectionLocation};
+
+pub use indexer::{IndexSummary, Indexer, IndexerConfig, IndexerError, sidecar_path};
+pub use pipeline::{PackError, PackSummary, Packer, PackerConfig};
+pub use rechunk::{RechunkError, RechunkOptions, RechunkSummary, rechunk};
+
+/// CIDv1 prefix of raw blocks hashed with sha2-256 (version, codec, multihash code and length)
+const RAW_SHA2_256_PREFIX: [u8; 4] = [0x01, 0x55, 0x12, 0x20];
+
+/// Computes the CIDv1 of a raw block, using sha2-256
+pub fn raw_block_cid(data: &[u8]) -> RawCid {
+    let mut bytes = Vec::with_capacity(RAW_SHA2_256_PREFIX.len() + 32);
+    bytes.extend_from_slice(&RAW_SHA2_256_PREFIX);
+    bytes.extend_from_slice(&Sha256::digest(data));
+    RawCid::new(bytes)
+}
+
+/// Sans-IO serialization stage of the packing pipeline
+///
+/// Sections are pushed with their sequence number (starting at 0), in any order. They are written to the
+/// CAR stream in sequence order, as soon as all the previous sections have been written. The bytes of the
+/// CAR stream are then pulled with [PackerCore::send_data], as with a [CarWriter].
+#[derive(Debug, Clone)]
+pub struct PackerCore {
+    /// Underlying CAR v1 writer
+    writer: CarWriter,
+    /// Sequence number of the next section to write
+    next_seq: u64,
+    /// Sections received ahead of their turn, by sequence number
+    pending: BTreeMap<u64, Section>,
+    /// Locations of the written sections, in sequence order
+    locations: Vec<SectionLocation>,
+}
+
+impl PackerCore {
+    /// Create a new PackerCore writing a CAR v1 stream with the given roots
+    pub fn new(roots: Vec<RawCid>) -> Self {
+        Self::with_writer(CarWriter::new(roots))
+    }
+
+    /// Create a new PackerCore on top of an existing (fresh) CAR v1 writer
+    ///
+    /// This allows to customize the writer buffer size and [Limits].
+    pub fn with_writer(writer: CarWriter) -> Self {
+        Self {
+            writer,
+            next_seq: 0,
+            pending: BTreeMap::new(),
+            locations: Vec::new(),
+        }
+    }
+
+    /// Get the size limits applied to the sections
+    pub fn limits(&self) -> &Limits {
+        self.writer.limits()
+    }
+
+    /// Push a hashed section with its sequence number
+    ///
+    /// ## Returns
+    /// - `Ok(())` if the section was accepted (it may be written later, once the previous sections are pushed).
+    /// - `Err(CarWriterError::SectionTooLarge)` or `Err(CarWriterError::BlockTooLarge)` if the section exceeds
+    ///   the writer limits.
+    /// - `Err(CarWriterError::SectionExceedsBuffer)` if the section can never fit in the writer buffer.
+    pub fn push(&mut self, seq: u64, section: Section) -> Result<(), CarWriterError> {
+        self.writer.check_limits(&section)?;
+        self.writer.check_buffer_capacity(&section)?;
+        self.pending.insert(seq, section);
+        self.write_ready();
+        Ok(())
+    }
+
+    /// Write the pending sections whose turn has come, as long as they fit in the writer buffer
+    fn write_ready(&mut self) {
+        while let Some(section) = self.pending.get(&self.next_seq) {
+            match self.writer.write_section(section) {
+                Ok(location) => {
+                    self.pending.remove(&self.next_seq);
+                    self.locations.push(location);
+                    self.next_seq += 1;
+                }
+                // Buffer is full (the limits and the capacity were checked on push), retry after the next flush
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Flush the CAR stream bytes to the given buffer
+    ///
+    /// See [CarWriter::send_data] for more details.
+    ///
+    /// ## Returns
+    /// The number of bytes written to the buffer.
+    pub fn send_data(&mut self, buf: &mut [u8]) -> usize {
+        let n = self.writer.send_data(buf);
+        self.write_ready();
+        n
+    }
+
+    /// Check if there is data ready to be sent to the underlying sink
+    pub fn has_data_to_send(&self) -> bool {
+        self.writer.has_data_to_send()
+    }
+
+    /// Number of sections received ahead of their turn, waiting for the previous ones
+    pub fn pending_sections(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Locations of the written sections, in sequence order
+    pub fn locations(&self) -> &[SectionLocation] {
+        &self.locations
+    }
+
+    /// Check if every pushed section has been written and flushed
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && !self.has_data_to_send()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::v1::Block;
+
+    #[test]
+    fn test_raw_block_cid() {
+        // Raw block "aaaa" of carv1-basic.car
+        assert_eq!(
+            raw_block_cid(b"aaaa").to_hex(),
+            "0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4"
+        );
+    }
+
+    #[test]
+    fn test_packer_core_reorders_sections() {
+        let blocks: Vec<&[u8]> = vec![b"aaaa", b"bbbb", b"cccc"];
+        let mut core = PackerCore::new(Vec::new());
+        for seq in [2u64, 0, 1] {
+            let data = blocks[seq as usize];
+            let section = Section::new(raw_block_cid(data), Block::new(data.to_vec()));
+            core.push(seq, section).unwrap();
+        }
+        assert_eq!(core.pending_sections(), 0);
+
+        let mut car = Vec::new();
+        let mut buf = [0u8; 64];
+        while core.has_data_to_send() {
+            let n = core.send_data(&mut buf);
+            car.extend_from_slice(&buf[..n]);
+        }
+        assert!(core.is_done());
+
+        let mut reader = crate::CarReader::from_bytes(&car).unwrap();
+        for data in blocks {
+            assert_eq!(reader.read_section().unwrap().block().data(), data);
+        }
+    }
+
+    #[test]
+    fn test_packer_core_section_exceeds_buffer() {
+        let mut core = PackerCore::with_writer(CarWriter::with_buffer_size(Vec::new(), 300));
+        let data = vec![0xAB; 300];
+        let section = Section::new(raw_block_cid(&data), Block::new(data));
+        assert!(matches!(
+            core.push(0, section),
+            Err(CarWriterError::SectionExceedsBuffer { .. })
+        ));
+        assert_eq!(core.pending_sections(), 0);
+    }
+}
@@ -0,0 +1,344 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use crate::pack::{PackerCore, raw_block_cid};
+use crate::wire::cid::RawCid;
+use crate::wire::limits::Limits;
+use crate::wire::v1::{Block, CarWriter, CarWriterError, Section};
+
+/// Tuning knobs of the [Packer] pipeline
+#[derive(Debug, Clone)]
+pub struct PackerConfig {
+    /// Number of hashing workers
+    ///
+    /// Defaults to the available parallelism of the machine.
+    pub workers: usize,
+    /// Capacity of the bounded channels between the stages
+    ///
+    /// This bounds the number of chunks in flight (and therefore the memory usage) to roughly
+    /// `2 * queue_depth + workers` chunks.
+    pub queue_depth: usize,
+    /// Size of the chunks (and therefore of the raw blocks), 256 KiB by default
+    pub chunk_size: usize,
+    /// Size of the CAR writer buffer, 16 MiB by default
+    ///
+    /// The sections of the chunks must fit in it, otherwise the packing fails with
+    /// [CarWriterError::SectionExceedsBuffer].
+    pub buffer_size: usize,
+    /// Size limits applied to the written sections
+    pub limits: Limits,
+}
+
+impl Default for PackerConfig {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_depth: 64,
+            chunk_size: 256 * 1024,
+            buffer_size: 16 * 1024 * 1024,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Summary of a packing operation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackSummary {
+    /// CIDs of the chunks of each source, in source order
+    pub sources: Vec<Vec<RawCid>>,
+    /// Number of sections written
+    pub sections: u64,
+    /// Number of bytes written to the sink (header included)
+    pub bytes_written: u64,
+}
+
+/// A chunk read from a source, waiting to be hashed
+struct Chunk {
+    seq: u64,
+    source: usize,
+    data: Vec<u8>,
+}
+
+/// A hashed chunk, waiting to be serialized
+struct Hashed {
+    seq: u64,
+    source: usize,
+    section: Section,
+}
+
+/// Multi-threaded CAR packer
+///
+/// The pipeline is made of three stages, connected with bounded channels:
+/// 1. A chunking thread reads the sources one after the other, and cuts them into fixed-size chunks.
+/// 2. A pool of workers hashes the chunks and builds their sections.
+/// 3. The calling thread restores the chunking order (using a [PackerCore]) and writes the CAR stream to the sink.
+///
+/// The output is deterministic: it does not depend on the number of workers nor on the scheduling.
+#[derive(Debug, Clone, Default)]
+pub struct Packer {
+    config: PackerConfig,
+}
+
+impl Packer {
+    /// Create a new Packer with the given configuration
+    pub fn new(config: PackerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the configuration of the Packer
+    pub fn config(&self) -> &PackerConfig {
+        &self.config
+    }
+
+    /// Pack the given sources into a CAR v1 stream written to the sink
+    ///
+    /// # Arguments
+    /// * `sources` - The contents to pack, chunked one after the other.
+    /// * `roots` - The roots of the CAR header.
+    /// * `sink` - Where to write the CAR stream.
+    ///
+    /// # Returns
+    /// * `Ok(PackSummary)` - The CIDs of the chunks of each source, and statistics about the packing.
+    /// * `Err(PackError)` - Error occurred while reading a source, writing the sink or serializing a section.
+    pub fn pack<I, R, W>(
+        &self,
+        sources: I,
+        roots: Vec<RawCid>,
+        sink: &mut W,
+    ) -> Result<PackSummary, PackError>
+    where
+        I: IntoIterator<Item = R>,
+        I::IntoIter: Send,
+        R: Read,
+        W: Write,
+    {
+        let workers = self.config.workers.max(1);
+        let queue_depth = self.config.queue_depth.max(1);
+        let chunk_size = self.config.chunk_size.max(1);
+
+        let mut writer = CarWriter::with_buffer_size(roots, self.config.buffer_size);
+        writer.set_limits(self.config.limits);
+        let mut core = PackerCore::with_writer(writer);
+
+        let (chunk_tx, chunk_rx) = mpsc::sync_channel::<Chunk>(queue_depth);
+        let (hashed_tx, hashed_rx) = mpsc::sync_channel::<Hashed>(queue_depth);
+        // Shared by the workers only, so that the chunking stage stops when all the workers are gone
+        let chunk_rx = Arc::new(Mutex::new(chunk_rx));
+        let sources = sources.into_iter();
+
+        thread::scope(|scope| {
+            // Stage 1: chunking
+            let chunker = scope.spawn(move || -> std::io::Result<()> {
+                let mut seq = 0;
+                for (source, mut reader) in sources.enumerate() {
+                    loop {
+                        let data = read_chunk(&mut reader, chunk_size)?;
+                        if data.is_empty() {
+                            break;
+                        }
+                        if chunk_tx.send(Chunk { seq, source, data }).is_err() {
+                            // The serialization stage stopped, it will report its own error
+                            return Ok(());
+                        }
+                        seq += 1;
+                    }
+                }
+                Ok(())
+            });
+
+            // Stage 2: hashing
+            for _ in 0..workers {
+                let chunk_rx = chunk_rx.clone();
+                let hashed_tx = hashed_tx.clone();
+                scope.spawn(move || {
+                    loop {
+                        let chunk = match chunk_rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(chunk) = chunk else {
+                            return;
+                        };
+                        let cid = raw_block_cid(&chunk.data);
+                        let hashed = Hashed {
+                            seq: chunk.seq,
+                            source: chunk.source,
+                            section: Section::new(cid, Block::new(chunk.data)),
+                        };
+                        if hashed_tx.send(hashed).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(chunk_rx);
+            drop(hashed_tx);
+
+            // Stage 3: serialization
+            let result = serialize(&mut core, hashed_rx, sink);
+            let chunked = chunker.join().map_err(|_| PackError::WorkerPanicked)?;
+            let (cids, bytes_written) = result?;
+            chunked?;
+            if !core.is_done() {
+                // Some chunks were lost, which means a hashing worker panicked
+                return Err(PackError::WorkerPanicked);
+            }
+
+            let mut summary = PackSummary {
+                sources: Vec::new(),
+                sections: core.locations().len() as u64,
+                bytes_written,
+            };
+            for (source, cid) in cids {
+                if summary.sources.len() <= source {
+                    summary.sources.resize(source + 1, Vec::new());
+                }
+                if let Some(cids) = summary.sources.get_mut(source) {
+                    cids.push(cid);
+                }
+            }
+            Ok(summary)
+        })
+    }
+}
+
+/// Read a full chunk from the reader, shorter only at the end of the reader
+fn read_chunk<R: Read>(reader: &mut R, chunk_size: usize) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(chunk_size);
+    reader.take(chunk_size as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Serialization stage: receives the hashed chunks and writes the CAR stream to the sink
+///
+/// Returns the (source, CID) pairs in sequence order, and the number of bytes written.
+fn serialize<W: Write>(
+    core: &mut PackerCore,
+    hashed_rx: mpsc::Receiver<Hashed>,
+    sink: &mut W,
+) -> Result<(Vec<(usize, RawCid)>, u64), PackError> {
+    let mut cids = Vec::new();
+    let mut written = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+    for hashed in hashed_rx {
+        let seq = hashed.seq as usize;
+        if cids.len() <= seq {
+            cids.resize(seq + 1, None);
+        }
+        if let Some(slot) = cids.get_mut(seq) {
+            *slot = Some((hashed.source, hashed.section.cid().clone()));
+        }
+        core.push(hashed.seq, hashed.section)?;
+        while core.has_data_to_send() {
+            let n = core.send_data(&mut buf);
+            sink.write_all(buf.get(..n).unwrap_or_default())?;
+            written += n as u64;
+        }
+    }
+    sink.flush()?;
+    Ok((cids.into_iter().flatten().collect(), written))
+}
+
+/// Errors related to CAR packing
+#[derive(thiserror::Error, Debug)]
+pub enum PackError {
+    /// IO error while reading a source or writing the sink
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A section could not be written to the CAR stream
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+    /// A thread of the pipeline panicked
+    #[error("A packing thread panicked")]
+    WorkerPanicked,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CarReader;
+
+    fn pack_with(workers: usize, sources: &[Vec<u8>]) -> (Vec<u8>, PackSummary) {
+        let config = PackerConfig {
+            workers,
+            queue_depth: 2,
+            chunk_size: 100,
+            ..PackerConfig::default()
+        };
+        let mut car = Vec::new();
+        let summary = Packer::new(config)
+            .pack(sources.iter().map(|s| &s[..]), Vec::new(), &mut car)
+            .unwrap();
+        (car, summary)
+    }
+
+    #[test]
+    fn test_packer_roundtrip() {
+        let sources: Vec<Vec<u8>> = vec![
+            (0..1000).map(|i| (i % 251) as u8).collect(),
+            Vec::new(),
+            (0..250).map(|i| (i % 7) as u8).collect(),
+        ];
+        let (car, summary) = pack_with(4, &sources);
+        assert_eq!(summary.sections, 13);
+        assert_eq!(summary.bytes_written, car.len() as u64);
+        assert_eq!(summary.sources[0].len(), 10);
+        assert_eq!(summary.sources[1].len(), 0);
+        assert_eq!(summary.sources[2].len(), 3);
+
+        // Sections are written in chunking order
+        let mut reader = CarReader::from_bytes(&car).unwrap();
+        let mut content = Vec::new();
+        for cid in summary.sources.iter().flatten() {
+            let section = reader.read_section().unwrap();
+            assert_eq!(section.cid(), cid);
+            assert_eq!(&raw_block_cid(section.block().data()), cid);
+            content.extend_from_slice(section.block().data());
+        }
+        assert!(reader.read_section().is_err());
+        assert_eq!(content, sources.concat());
+    }
+
+    #[test]
+    fn test_packer_deterministic() {
+        let sources = vec![(0..5000).map(|i| (i % 13) as u8).collect::<Vec<u8>>()];
+        let (single, _) = pack_with(1, &sources);
+        let (multi, _) = pack_with(8, &sources);
+        assert_eq!(single, multi);
+    }
+
+    #[test]
+    fn test_packer_chunk_too_large() {
+        let config = PackerConfig {
+            chunk_size: 100,
+            limits: Limits::new().with_max_section_size(64),
+            ..PackerConfig::default()
+        };
+        let mut car = Vec::new();
+        let err = Packer::new(config)
+            .pack(vec![&[0u8; 100][..]], Vec::new(), &mut car)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PackError::Writer(CarWriterError::SectionTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_packer_chunk_exceeds_buffer() {
+        let config = PackerConfig {
+            chunk_size: 1000,
+            buffer_size: 300,
+            ..PackerConfig::default()
+        };
+        let mut car = Vec::new();
+        let err = Packer::new(config)
+            .pack(vec![&[0u8; 1000][..]], Vec::new(), &mut car)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PackError::Writer(CarWriterError::SectionExceedsBuffer { .. })
+        ));
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::HashSet;
+use std::io::{Seek, SeekFrom, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::ipld::dagpb::{DagPbError, PbLink, PbNode};
+use crate::ipld::unixfs::{DataType, UnixFsData, UnixFsError};
+use crate::ipld::{BlockSource, CODEC_DAG_PB, CODEC_RAW, cid_codec};
+use crate::pack::raw_block_cid;
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Block, CarWriter, CarWriterError, Section};
+
+/// CIDv1 prefix of dag-pb blocks hashed with sha2-256 (version, codec, multihash code and length)
+const DAG_PB_SHA2_256_PREFIX: [u8; 4] = [0x01, 0x70, 0x12, 0x20];
+
+/// Options of the re-chunking
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RechunkOptions {
+    /// Size of the new chunks (and therefore of the raw leaves), 1 MiB by default
+    pub chunk_size: usize,
+    /// Maximum number of links of the intermediate nodes, 174 by default (as the reference implementations)
+    pub max_links: usize,
+}
+
+impl Default for RechunkOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024 * 1024,
+            max_links: 174,
+        }
+    }
+}
+
+/// Summary of a re-chunking operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RechunkSummary {
+    /// Root CID of the new file DAG, also the only root of the new CAR
+    pub root: RawCid,
+    /// Number of sections written to the new CAR
+    pub sections: u64,
+    /// Size of the file content
+    pub file_size: u64,
+    /// Number of bytes written to the sink (header included)
+    pub bytes_written: u64,
+}
+
+/// A node of the new DAG, as seen by its parent
+#[derive(Debug, Clone)]
+struct Child {
+    cid: RawCid,
+    /// Size of the content below this node
+    file_size: u64,
+    /// Size of the blocks of the subgraph (the tsize of the link)
+    dag_size: u64,
+}
+
+/// Builder of the new file DAG, writing its blocks to the CAR stream as they are completed
+struct DagBuilder<'a, W: Write> {
+    options: &'a RechunkOptions,
+    writer: CarWriter,
+    sink: &'a mut W,
+    /// Content waiting to fill the next chunk
+    pending: Vec<u8>,
+    /// Nodes waiting for their parent, by tree level (leaves at level 0)
+    levels: Vec<Vec<Child>>,
+    /// CIDs already written, identical chunks are stored once
+    written: HashSet<RawCid>,
+    sections: u64,
+    file_size: u64,
+    bytes_written: u64,
+}
+
+impl<W: Write> DagBuilder<'_, W> {
+    /// Push file content, cutting and writing the chunks as soon as they are complete
+    fn push_content<E>(&mut self, mut data: &[u8]) -> Result<(), RechunkError<E>> {
+        self.file_size += data.len() as u64;
+        while !data.is_empty() {
+            let take = (self.options.chunk_size - self.pending.len()).min(data.len());
+            self.pending
+                .extend_from_slice(data.get(..take).unwrap_or_default());
+            data = data.get(take..).unwrap_or_default();
+            if self.pending.len() == self.options.chunk_size {
+                self.flush_chunk()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the pending content as a raw leaf
+    fn flush_chunk<E>(&mut self) -> Result<(), RechunkError<E>> {
+        let data = std::mem::take(&mut self.pending);
+        let cid = raw_block_cid(&data);
+        let file_size = data.len() as u64;
+        let dag_size = self.write_block(cid.clone(), data)?;
+        self.push_child(
+            0,
+            Child {
+                cid,
+                file_size,
+                dag_size,
+            },
+        )
+    }
+
+    /// Push a completed node at the given level, collapsing the level when it is full
+    fn push_child<E>(&mut self, level: usize, child: Child) -> Result<(), RechunkError<E>> {
+        if self.levels.len() <= level {
+            self.levels.resize(level + 1, Vec::new());
+        }
+        let full = self.levels.get_mut(level).is_some_and(|nodes| {
+            nodes.push(child);
+            nodes.len() == self.options.max_links
+        });
+        if full {
+            self.collapse(level)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the nodes of a level by their parent, pushed to the level above
+    fn collapse<E>(&mut self, level: usize) -> Result<(), RechunkError<E>> {
+        let children = self
+            .levels
+            .get_mut(level)
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let parent = self.write_file_node(&children)?;
+        self.push_child(level + 1, parent)
+    }
+
+    /// Write a dag-pb UnixFS File node linking the given children
+    fn write_file_node<E>(&mut self, children: &[Child]) -> Result<Child, RechunkError<E>> {
+        let mut unixfs = UnixFsData::new(DataType::File);
+        unixfs.filesize = Some(children.iter().map(|c| c.file_size).sum());
+        unixfs.blocksizes = children.iter().map(|c| c.file_size).collect();
+        let node = PbNode {
+            data: Some(unixfs.encode()),
+            links: children
+                .iter()
+                .map(|c| PbLink {
+                    cid: c.cid.clone(),
+                    name: Some(String::new()),
+                    tsize: Some(c.dag_size),
+                })
+                .collect(),
+        };
+        let data = node.encode();
+        let mut cid = DAG_PB_SHA2_256_PREFIX.to_vec();
+        cid.extend_from_slice(&Sha256::digest(&data));
+        let cid = RawCid::new(cid);
+        let block_size = self.write_block(cid.clone(), data)?;
+        Ok(Child {
+            cid,
+            file_size: unixfs.filesize.unwrap_or(0),
+            dag_size: block_size + children.iter().map(|c| c.dag_size).sum::<u64>(),
+        })
+    }
+
+    /// Write a block to the CAR stream (unless already written), returning its size
+    fn write_block<E>(&mut self, cid: RawCid, data: Vec<u8>) -> Result<u64, RechunkError<E>> {
+        let size = data.len() as u64;
+        if !self.written.insert(cid.clone()) {
+            return Ok(size);
+        }
+        let section = Section::new(cid, Block::new(data));
+        loop {
+            match self.writer.write_section(&section) {
+                Ok(_) => break,
+                Err(CarWriterError::BufferFull) if self.writer.has_data_to_send() => {
+                    self.flush()?
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.sections += 1;
+        Ok(size)
+    }
+
+    /// Flush the CAR writer buffer to the sink
+    fn flush<E>(&mut self) -> Result<(), RechunkError<E>> {
+        let mut buf = vec![0u8; 64 * 1024];
+        while self.writer.has_data_to_send() {
+            let n = self.writer.send_data(&mut buf);
+            self.sink.write_all(buf.get(..n).unwrap_or_default())?;
+            self.bytes_written += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Write the last chunk and the remaining intermediate nodes, returning the root of the new DAG
+    fn finish<E>(&mut self) -> Result<RawCid, RechunkError<E>> {
+        if !self.pending.is_empty() {
+            self.flush_chunk()?;
+        }
+        let mut level = 0;
+        while let Some(count) = self.levels.get(level).map(Vec::len) {
+            let top = level + 1 == self.levels.len();
+            if top
+                && count == 1
+                && let Some(root) = self.levels.get_mut(level).and_then(Vec::pop)
+            {
+                self.flush()?;
+                return Ok(root.cid);
+            }
+            if count > 0 {
+                self.collapse(level)?;
+            }
+            level += 1;
+        }
+        // Empty file, represented by a File node without any link
+        let root = self.write_file_node(&[])?;
+        self.flush()?;
+        Ok(root.cid)
+    }
+}
+
+/// Re-chunks a UnixFS file DAG into a new DAG and CAR stream
+///
+/// The content of the file rooted at `root` is streamed from the `source` (in a depth-first traversal
+/// of its DAG, so that only a single chunk is buffered at once), cut in chunks of [RechunkOptions::chunk_size]
+/// bytes, and rebuilt as a balanced DAG with raw leaves (CIDv1, sha2-256), as done by the reference implementations.
+/// Blocks are written to the `sink` as a CAR v1 stream whose only root is the new root, identical
+/// chunks are written once.
+///
+/// The source DAG may use raw leaves or dag-pb leaves (UnixFS File or Raw nodes with inline data).
+/// The sink must be seekable, as the header is rewritten with the new root once it is known.
+///
+/// # Arguments
+/// * `source` - The blocks of the source DAG.
+/// * `root` - The root CID of the source file.
+/// * `options` - The chunk size and the shape of the new DAG.
+/// * `sink` - Where to write the new CAR stream, from its start.
+///
+/// # Returns
+/// * `Ok(RechunkSummary)` - The new root CID, and statistics about the new CAR.
+/// * `Err(RechunkError)` - The source DAG is not a well-formed UnixFS file, or writing the sink failed.
+pub fn rechunk<S, W>(
+    source: &mut S,
+    root: &RawCid,
+    options: &RechunkOptions,
+    sink: &mut W,
+) -> Result<RechunkSummary, RechunkError<S::Error>>
+where
+    S: BlockSource,
+    W: Write + Seek,
+{
+    let options = RechunkOptions {
+        chunk_size: options.chunk_size.max(1),
+        max_links: options.max_links.max(2),
+    };
+    // Placeholder root, the same size as the final one (CIDv1, sha2-256)
+    let mut placeholder = DAG_PB_SHA2_256_PREFIX.to_vec();
+    placeholder.resize(placeholder.len() + 32, 0);
+    let header_start = sink.stream_position().map_err(RechunkError::Io)?;
+    let mut builder = DagBuilder {
+        options: &options,
+        writer: CarWriter::with_buffer_size(
+            vec![RawCid::new(placeholder)],
+            (2 * options.chunk_size).max(4 * 1024 * 1024),
+        ),
+        sink,
+        pending: Vec::with_capacity(options.chunk_size),
+        levels: Vec::new(),
+        written: HashSet::new(),
+        sections: 0,
+        file_size: 0,
+        bytes_written: 0,
+    };
+
+    // Depth-first traversal of the source DAG, the stack holds the nodes still to stream in reverse order
+    let mut stack = vec![root.clone()];
+    while let Some(cid) = stack.pop() {
+        let data = source
+            .get_block(&cid)
+            .map_err(RechunkError::Source)?
+            .ok_or_else(|| RechunkError::BlockNotFound(cid.clone()))?;
+        match cid_codec(&cid) {
+            Some(CODEC_RAW) => builder.push_content(&data)?,
+            Some(CODEC_DAG_PB) => {
+                let node = PbNode::decode(&data)?;
+                let unixfs = UnixFsData::decode(node.data.as_deref().unwrap_or_default())?;
+                if !matches!(unixfs.data_type, DataType::File | DataType::Raw) {
+                    return Err(RechunkError::NotAFile(cid));
+                }
+                if let Some(inline) = &unixfs.data {
+                    builder.push_content(inline)?;
+                }
+                stack.extend(node.links.into_iter().rev().map(|link| link.cid));
+            }
+            _ => return Err(RechunkError::NotAFile(cid)),
+        }
+    }
+    let new_root = builder.finish()?;
+    let summary = RechunkSummary {
+        root: new_root.clone(),
+        sections: builder.sections,
+        file_size: builder.file_size,
+        bytes_written: builder.bytes_written,
+    };
+
+    // Rewrite the header with the new root, it has the same size as the placeholder one
+    let mut header = CarWriter::new(vec![new_root]);
+    let mut header_bytes = vec![0u8; 256];
+    let n = header.send_data(&mut header_bytes);
+    sink.seek(SeekFrom::Start(header_start))?;
+    sink.write_all(header_bytes.get(..n).unwrap_or_default())?;
+    sink.seek(SeekFrom::Start(header_start + summary.bytes_written))?;
+    sink.flush()?;
+    Ok(summary)
+}
+
+/// Errors related to the re-chunking of a UnixFS file
+#[derive(thiserror::Error, Debug)]
+pub enum RechunkError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A block of the source DAG is not available in the source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+    /// A dag-pb block of the source DAG is malformed
+    #[error("Invalid dag-pb block: {0}")]
+    InvalidDagPb(#[from] DagPbError),
+    /// The UnixFS metadata of a source node is malformed
+    #[error("Invalid UnixFS data: {0}")]
+    InvalidUnixFs(#[from] UnixFsError),
+    /// A node of the source DAG is not part of a UnixFS file (directory, symlink, unsupported codec, etc.)
+    #[error("Not a UnixFS file node: {0}")]
+    NotAFile(RawCid),
+    /// IO error while writing the sink
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A block could not be written to the CAR stream
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CarReader;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    /// Rechunks the file, returning the new CAR and its blocks
+    fn rechunk_to(
+        source: &mut HashMap<RawCid, Vec<u8>>,
+        root: &RawCid,
+        chunk_size: usize,
+        max_links: usize,
+    ) -> (RechunkSummary, Vec<u8>, HashMap<RawCid, Vec<u8>>) {
+        let options = RechunkOptions {
+            chunk_size,
+            max_links,
+        };
+        let mut car = Cursor::new(Vec::new());
+        let summary = rechunk(source, root, &options, &mut car).unwrap();
+        let car = car.into_inner();
+        assert_eq!(summary.bytes_written, car.len() as u64);
+
+        let mut reader = CarReader::from_bytes(&car).unwrap();
+        assert_eq!(
+            reader.header().unwrap().0.roots()[0].to_raw_cid(),
+            &summary.root
+        );
+        let mut blocks = HashMap::new();
+        while let Ok(section) = reader.read_section() {
+            blocks.insert(section.cid().clone(), section.block().data().to_vec());
+        }
+        assert_eq!(blocks.len() as u64, summary.sections);
+        (summary, car, blocks)
+    }
+
+    /// Streams the content of a file DAG
+    fn cat(blocks: &HashMap<RawCid, Vec<u8>>, cid: &RawCid) -> Vec<u8> {
+        let data = &blocks[cid];
+        if cid_codec(cid) == Some(CODEC_RAW) {
+            return data.clone();
+        }
+        let node = PbNode::decode(data).unwrap();
+        let unixfs = UnixFsData::decode(node.data.as_deref().unwrap()).unwrap();
+        let mut content = unixfs.data.clone().unwrap_or_default();
+        for (link, size) in node.links.iter().zip(&unixfs.blocksizes) {
+            let child = cat(blocks, &link.cid);
+            assert_eq!(child.len() as u64, *size);
+            content.extend(child);
+        }
+        content
+    }
+
+    #[test]
+    fn test_rechunk_roundtrip() {
+        let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut source = HashMap::new();
+        source.insert(raw_block_cid(&content), content.clone());
+
+        // Small chunks and narrow nodes, to get a deep DAG
+        let (small, _, mut small_blocks) =
+            rechunk_to(&mut source, &raw_block_cid(&content), 100, 4);
+        assert_eq!(small.file_size, 10_000);
+        assert_eq!(cat(&small_blocks, &small.root), content);
+        assert_eq!(small.root.bytes()[..2], [0x01, 0x70]);
+
+        // Back to larger chunks
+        let (large, _, large_blocks) = rechunk_to(&mut small_blocks, &small.root, 4096, 174);
+        assert_eq!(large.sections, 4);
+        assert_eq!(cat(&large_blocks, &large.root), content);
+
+        // A single chunk is its own root
+        let (single, _, _) = rechunk_to(&mut small_blocks, &small.root, 16_384, 174);
+        assert_eq!(single.root, raw_block_cid(&content));
+        assert_eq!(single.sections, 1);
+    }
+
+    #[test]
+    fn test_rechunk_dedup_and_empty() {
+        let mut source = HashMap::new();
+        let content = vec![0u8; 1000];
+        source.insert(raw_block_cid(&content), content.clone());
+        let (summary, _, blocks) = rechunk_to(&mut source, &raw_block_cid(&content), 100, 174);
+        // 10 identical leaves and their parent
+        assert_eq!(summary.sections, 2);
+        assert_eq!(cat(&blocks, &summary.root), content);
+
+        source.insert(raw_block_cid(&[]), Vec::new());
+        let (summary, _, blocks) = rechunk_to(&mut source, &raw_block_cid(&[]), 100, 174);
+        assert_eq!(summary.file_size, 0);
+        assert!(cat(&blocks, &summary.root).is_empty());
+    }
+
+    #[test]
+    fn test_rechunk_not_a_file() {
+        let mut source = HashMap::new();
+        let directory = PbNode {
+            data: Some(UnixFsData::new(DataType::Directory).encode()),
+            links: Vec::new(),
+        }
+        .encode();
+        let mut cid = DAG_PB_SHA2_256_PREFIX.to_vec();
+        cid.extend_from_slice(&Sha256::digest(&directory));
+        let cid = RawCid::new(cid);
+        source.insert(cid.clone(), directory);
+        let mut car = Cursor::new(Vec::new());
+        let err = rechunk(&mut source, &cid, &RechunkOptions::default(), &mut car).unwrap_err();
+        assert!(matches!(err, RechunkError::NotAFile(_)));
+
+        let missing = raw_block_cid(b"missing");
+        let err = rechunk(&mut source, &missing, &RechunkOptions::default(), &mut car).unwrap_err();
+        assert!(matches!(err, RechunkError::BlockNotFound(_)));
+    }
+}
@@ -6,33 +6,280 @@
 //!
 //! Instead, it operates on byte slices (`&[u8]`) and provides methods to read headers, sections, and blocks from those byte slices.
 
-use crate::wire::cid::RawCid;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::event::EventPhase;
+use crate::trace::warn_event;
+use crate::wire::cid::{CidParsing, RawCid};
+use crate::wire::limits::Limits;
+use crate::wire::v1::Block;
 use crate::wire::v1::CarHeader as CarHeaderV1;
 use crate::wire::v1::CarReader as CarReaderV1;
 use crate::wire::v1::CarReaderError as CarReaderV1Error;
 use crate::wire::v1::LocatableSection;
+use crate::wire::v1::Section;
 use crate::wire::v1::SectionFormatError;
+use crate::wire::v1::SectionLocation;
+use crate::wire::v1::SectionRef;
 use crate::wire::v2::CAR_V2_PRAGMA;
 use crate::wire::v2::CarReader as CarReaderV2;
 use crate::wire::v2::CarReaderError as CarReaderV2Error;
 use crate::wire::v2::CarV2Header as CarHeaderV2;
+use crate::wire::v2::IndexFormatError;
+use crate::wire::v2::PaddingCheck;
+use crate::wire::varint::UnsignedVarint;
 
 /// Main CAR reader type that can read both CAR v1 and v2 formats transparently.
 #[derive(Debug)]
-pub struct CarReader(CarReaderState);
+pub struct CarReader {
+    state: CarReaderState,
+    /// Minimum hint length returned in InsufficientData errors
+    min_read_hint: usize,
+    /// Size limits applied to the header and the sections
+    limits: Limits,
+    /// Parsing mode of the section CIDs
+    cid_parsing: CidParsing,
+    /// Checking mode of the CAR v2 pre-payload padding
+    padding_check: PaddingCheck,
+    /// Total length of the file, once declared by [CarReader::finish]
+    end: Option<usize>,
+    /// Behavior when a CID appears in several sections
+    duplicate_policy: DuplicatePolicy,
+    /// Offset of the first section of each read CID (only tracked if duplicates are checked)
+    first_offsets: HashMap<RawCid, u64>,
+    /// Duplicates found in [DuplicatePolicy::Warn] mode, not taken yet
+    duplicates: Vec<DuplicateSection>,
+    /// Section being read by [CarReader::read_section_at], apart from the sequential reads
+    random_read: Option<RandomRead>,
+    /// Progress through the events of [CarReader::next_event]
+    pub(crate) event_phase: EventPhase,
+}
+
+/// Behavior of the [CarReader] when the same CID appears in several sections
+///
+/// The CAR specifications allow duplicates, but some consumers (e.g. Filecoin proofs, deterministic pipelines)
+/// must reject such archives as early as possible. Duplicates are detected by [CarReader::read_section],
+/// which then has to remember the offset of every read CID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Duplicates are read like any other section, and not tracked
+    #[default]
+    Allow,
+    /// Duplicates are read, reported as a warning event and recorded (see [CarReader::take_duplicates])
+    Warn,
+    /// Duplicates are rejected with [CarReaderError::DuplicateSection]
+    Error,
+}
+
+/// A section whose CID already appeared in a previous section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateSection {
+    /// The duplicated CID
+    pub cid: RawCid,
+    /// Offset of the first section with this CID
+    pub first_offset: u64,
+    /// Offset of the duplicate section
+    pub offset: u64,
+}
 
 /// Internal state of the CarReader, which can be either:
 /// - Unclear: The reader has not yet determined whether the input is CAR v1 or v2, and
 ///   is accumulating bytes until it can make that determination.
 /// - V1: The reader has determined that the input is CAR v1 and is using a CarReaderV1 to read the data.
 /// - V2: The reader has determined that the input is CAR v2 and is using a CarReaderV2 to read the data.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum CarReaderState {
     Unclear(Vec<u8>),
     V1(CarReaderV1),
     V2(CarReaderV2),
 }
 
+/// A section read at a known location by [CarReader::read_section_at]
+///
+/// Its bytes are buffered apart from the ones of the sequential reads, which are left untouched.
+#[derive(Debug, Clone)]
+struct RandomRead {
+    /// Offset of the section
+    offset: u64,
+    /// Bytes of the section received so far
+    data: Vec<u8>,
+}
+
+impl RandomRead {
+    /// Offset right after the received bytes
+    fn end(&self) -> usize {
+        self.offset as usize + self.data.len()
+    }
+
+    /// Buffer the received bytes following the ones of the section
+    ///
+    /// ## Returns
+    /// - `true` if the bytes were buffered, and must not be fed to the sequential reader.
+    /// - `false` if the bytes are not contiguous to the section.
+    fn receive_data(&mut self, buf: &[u8], pos: usize) -> bool {
+        let end = self.end();
+        if pos <= end && pos + buf.len() > end {
+            // Only append the bytes not buffered yet
+            self.data
+                .extend_from_slice(buf.get(end - pos..).unwrap_or_default());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A partially received section, exported from a [CarReader] to be resumed by another one
+///
+/// See [CarReader::export_partial_state] and [CarReader::import_partial_state].
+#[derive(Debug, Clone)]
+pub struct PartialState {
+    /// Headers and position of the reader, without the buffered bytes
+    snapshot: CarReaderState,
+    /// Offset of the first buffered byte
+    offset: u64,
+    /// Bytes buffered but not consumed yet
+    buffered: Vec<u8>,
+}
+
+impl PartialState {
+    /// Format of the CAR archive
+    pub fn format(&self) -> CarFormat {
+        match self.snapshot {
+            CarReaderState::V2(_) => CarFormat::V2,
+            _ => CarFormat::V1,
+        }
+    }
+
+    /// Offset of the first buffered byte, usually the start of the partially received section
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Bytes buffered but not consumed yet
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffered
+    }
+
+    /// Offset right after the buffered bytes, where the resumed connection must start
+    pub fn resume_offset(&self) -> u64 {
+        self.offset + self.buffered.len() as u64
+    }
+}
+
+/// Position of a [CarReader] in an archive, to resume parsing it later, possibly in another process
+///
+/// Unlike [PartialState], a checkpoint holds no buffered bytes: only the parsed headers and the offset of the next
+/// section, so it stays small whatever the size of the sections, and can be persisted with [ReaderCheckpoint::encode].
+/// See [CarReader::save_state] and [CarReader::restore_state].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReaderCheckpoint {
+    /// CAR v1 header (the inner one, for CAR v2 archives)
+    header: CarHeaderV1,
+    /// Size of the CAR v1 header, including its length prefix
+    header_size: u64,
+    /// CAR v2 header, if any
+    v2_header: Option<CarHeaderV2>,
+    /// Absolute offset of the next section
+    position: u64,
+}
+
+impl ReaderCheckpoint {
+    /// Version of the encoding of the checkpoints
+    const ENCODING_VERSION: u8 = 1;
+
+    /// Format of the CAR archive
+    pub fn format(&self) -> CarFormat {
+        match self.v2_header {
+            Some(_) => CarFormat::V2,
+            None => CarFormat::V1,
+        }
+    }
+
+    /// Headers of the archive: the CAR v1 header, and the CAR v2 header if any
+    pub fn header(&self) -> (&CarHeaderV1, Option<&CarHeaderV2>) {
+        (&self.header, self.v2_header.as_ref())
+    }
+
+    /// Absolute offset of the next section, where the source must resume
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Offset of the first section of the CAR v1 payload
+    fn first_section(&self) -> u64 {
+        self.v2_header
+            .as_ref()
+            .map_or(0, |v2_header| v2_header.data_offset)
+            .saturating_add(self.header_size)
+    }
+
+    /// Serialize the checkpoint to bytes, to be persisted
+    ///
+    /// The encoding is versioned, and made of the CAR v2 header (if any), the size of the CAR v1 header and
+    /// the position as varints, then the CAR v1 header in DAG-CBOR.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![Self::ENCODING_VERSION, self.format().version() as u8];
+        if let Some(v2_header) = &self.v2_header {
+            bytes.extend_from_slice(&<[u8; 40]>::from(v2_header));
+        }
+        bytes.extend(UnsignedVarint(self.header_size).encode());
+        bytes.extend(UnsignedVarint(self.position).encode());
+        bytes.extend(self.header.encode());
+        bytes
+    }
+
+    /// Deserialize a checkpoint serialized with [ReaderCheckpoint::encode]
+    ///
+    /// ## Returns
+    /// - `Ok(ReaderCheckpoint)` with the decoded checkpoint.
+    /// - `Err(CarReaderError::InvalidHeader)` if the CAR v1 header can not be decoded.
+    /// - `Err(CarReaderError::InvalidFormat)` if the bytes are not a checkpoint (of a supported version),
+    ///   or the position is outside of the sections.
+    pub fn decode(bytes: &[u8]) -> Result<Self, CarReaderError> {
+        let ([encoding, version], rest) = bytes
+            .split_first_chunk::<2>()
+            .ok_or(CarReaderError::InvalidFormat)?;
+        if *encoding != Self::ENCODING_VERSION {
+            return Err(CarReaderError::InvalidFormat);
+        }
+        let (v2_header, rest) = match version {
+            1 => (None, rest),
+            2 => {
+                let (v2_header, rest) = rest
+                    .split_first_chunk::<40>()
+                    .ok_or(CarReaderError::InvalidFormat)?;
+                (Some(CarHeaderV2::from(*v2_header)), rest)
+            }
+            _ => return Err(CarReaderError::InvalidFormat),
+        };
+        let (header_size, size) =
+            UnsignedVarint::decode(rest).ok_or(CarReaderError::InvalidFormat)?;
+        let rest = rest.get(size..).unwrap_or_default();
+        let (position, size) = UnsignedVarint::decode(rest).ok_or(CarReaderError::InvalidFormat)?;
+        let rest = rest.get(size..).unwrap_or_default();
+        let header = ciborium::from_reader(rest).map_err(CarReaderError::InvalidHeader)?;
+
+        let checkpoint = ReaderCheckpoint {
+            header,
+            header_size: header_size.0,
+            v2_header,
+            position: position.0,
+        };
+        let end = checkpoint.v2_header.as_ref().map_or(u64::MAX, |v2_header| {
+            v2_header.data_offset.saturating_add(v2_header.data_size)
+        });
+        if checkpoint.position < checkpoint.first_section()
+            || checkpoint.position > end
+            || end > usize::MAX as u64
+        {
+            return Err(CarReaderError::InvalidFormat);
+        }
+        Ok(checkpoint)
+    }
+}
+
 /// CAR format indicates the version of the CAR file being read/write, which can be either v1 or v2.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CarFormat {
@@ -46,6 +293,19 @@ pub enum CarFormat {
     V2,
 }
 
+impl CarFormat {
+    /// All the formats read by the [CarReader]
+    pub const ALL: [CarFormat; 2] = [CarFormat::V1, CarFormat::V2];
+
+    /// Version number of the format, as written in its header
+    pub fn version(self) -> u64 {
+        match self {
+            CarFormat::V1 => 1,
+            CarFormat::V2 => 2,
+        }
+    }
+}
+
 /// Underlying reader for the CarReader, which can be either a CarReaderV1 or CarReaderV2 depending on the determined format.
 #[derive(Debug)]
 pub enum CarUnderlyingReader<'a> {
@@ -60,7 +320,169 @@ impl CarReader {
     ///
     /// Initially, the reader is in an "unclear" state where it has not yet determined the format of the input data.
     pub fn new() -> Self {
-        CarReader(CarReaderState::Unclear(Vec::new()))
+        Self::with_min_read_hint(0)
+    }
+
+    /// Creates a builder of CarReader, to set several options at once (see [CarReaderBuilder])
+    pub fn builder() -> CarReaderBuilder {
+        CarReaderBuilder::new()
+    }
+
+    /// Creates a new CarReader with a minimum read hint
+    ///
+    /// The hint length returned with [CarReaderError::InsufficientData] is computed from what the reader
+    /// already knows about the upcoming bytes (remaining header size, declared section length, etc.), which
+    /// can be as small as a few bytes. The minimum read hint is a floor applied to every hint, so that callers
+    /// following the hints naturally coalesce small reads into larger, more efficient ones.
+    pub fn with_min_read_hint(min_read_hint: usize) -> Self {
+        CarReader {
+            state: CarReaderState::Unclear(Vec::new()),
+            min_read_hint,
+            limits: Limits::default(),
+            cid_parsing: CidParsing::default(),
+            padding_check: PaddingCheck::default(),
+            end: None,
+            duplicate_policy: DuplicatePolicy::default(),
+            first_offsets: HashMap::new(),
+            duplicates: Vec::new(),
+            random_read: None,
+            event_phase: EventPhase::default(),
+        }
+    }
+
+    /// Get the minimum hint length returned in InsufficientData errors
+    pub fn min_read_hint(&self) -> usize {
+        self.min_read_hint
+    }
+
+    /// Set the minimum hint length returned in InsufficientData errors
+    ///
+    /// See [CarReader::with_min_read_hint] for more details.
+    pub fn set_min_read_hint(&mut self, min_read_hint: usize) {
+        self.min_read_hint = min_read_hint;
+        match &mut self.state {
+            CarReaderState::Unclear(_) => {}
+            CarReaderState::V1(reader) => reader.set_min_read_hint(min_read_hint),
+            CarReaderState::V2(reader) => reader.set_min_read_hint(min_read_hint),
+        }
+    }
+
+    /// Capacity of the internal buffers, in bytes
+    ///
+    /// Consumed bytes are not moved after each section, but dropped once they make up half of the buffer, and
+    /// the allocation is reused between sections: the capacity follows the largest section (or received chunk)
+    /// so far. See [CarReader::shrink] to release it.
+    pub fn buffer_capacity(&self) -> usize {
+        match &self.state {
+            CarReaderState::Unclear(data) => data.capacity(),
+            CarReaderState::V1(reader) => reader.buffer_capacity(),
+            CarReaderState::V2(reader) => reader.buffer_capacity(),
+        }
+    }
+
+    /// Release the memory of the internal buffers which is not needed by the unconsumed bytes
+    ///
+    /// Useful after reading an unusually large section, on long-lived readers.
+    pub fn shrink(&mut self) {
+        match &mut self.state {
+            CarReaderState::Unclear(data) => data.shrink_to_fit(),
+            CarReaderState::V1(reader) => reader.shrink(),
+            CarReaderState::V2(reader) => reader.shrink(),
+        }
+    }
+
+    /// Get the size limits applied to the header and the sections
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Set the size limits applied to the header and the sections
+    ///
+    /// Sections exceeding these limits are rejected with [SectionFormatError::InvalidSize], and headers with
+    /// [CarReaderError::HeaderTooLarge].
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        match &mut self.state {
+            CarReaderState::Unclear(_) => {}
+            CarReaderState::V1(reader) => reader.set_limits(limits),
+            CarReaderState::V2(reader) => reader.set_limits(limits),
+        }
+    }
+
+    /// Get the parsing mode of the section CIDs
+    pub fn cid_parsing(&self) -> CidParsing {
+        self.cid_parsing
+    }
+
+    /// Set the parsing mode of the section CIDs
+    ///
+    /// By default, sections whose CID is neither a CIDv0 nor a CIDv1 are rejected with [SectionFormatError::InvalidCid].
+    /// In [CidParsing::Lenient] mode, CIDs of future versions are carried opaquely, so a single unknown CID does not
+    /// abort the whole scan.
+    pub fn set_cid_parsing(&mut self, cid_parsing: CidParsing) {
+        self.cid_parsing = cid_parsing;
+        match &mut self.state {
+            CarReaderState::Unclear(_) => {}
+            CarReaderState::V1(reader) => reader.set_cid_parsing(cid_parsing),
+            CarReaderState::V2(reader) => reader.set_cid_parsing(cid_parsing),
+        }
+    }
+
+    /// Get the checking mode of the CAR v2 pre-payload padding
+    pub fn padding_check(&self) -> PaddingCheck {
+        self.padding_check
+    }
+
+    /// Set the checking mode of the CAR v2 pre-payload padding
+    ///
+    /// See [CarReaderV2::set_padding_check] for more details, the mode has no effect on CAR v1 archives.
+    pub fn set_padding_check(&mut self, padding_check: PaddingCheck) {
+        self.padding_check = padding_check;
+        if let CarReaderState::V2(reader) = &mut self.state {
+            reader.set_padding_check(padding_check);
+        }
+    }
+
+    /// Get the behavior when a CID appears in several sections
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// Set the behavior when a CID appears in several sections
+    ///
+    /// The policy should be set before reading the first section, as the CIDs read in [DuplicatePolicy::Allow]
+    /// mode are not tracked. A section read again at the same offset (e.g. after a seek) is not a duplicate.
+    pub fn set_duplicate_policy(&mut self, duplicate_policy: DuplicatePolicy) {
+        self.duplicate_policy = duplicate_policy;
+        if duplicate_policy == DuplicatePolicy::Allow {
+            self.first_offsets = HashMap::new();
+        }
+    }
+
+    /// Take the duplicates found so far in [DuplicatePolicy::Warn] mode
+    pub fn take_duplicates(&mut self) -> Vec<DuplicateSection> {
+        std::mem::take(&mut self.duplicates)
+    }
+
+    /// Creates a CarReader from an in-memory CAR archive, ready to iterate over its sections.
+    ///
+    /// This is a shortcut for the common case where the whole CAR archive is already in memory:
+    /// it feeds the entire buffer to a new reader and reads the header(s), so the caller can
+    /// directly call [CarReader::read_section] or [CarReader::find_section] without any
+    /// `receive_data` bookkeeping.
+    ///
+    /// ## Arguments
+    /// * `bytes` - The whole CAR archive (v1 or v2).
+    ///
+    /// ## Returns
+    /// - `Ok(CarReader)` if the header(s) could be read from the buffer.
+    /// - `Err(CarReaderError)` if the buffer does not contain a valid CAR archive
+    ///   (for instance, [CarReaderError::InsufficientData] if it is truncated before the end of the header).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CarReaderError> {
+        let mut reader = Self::new();
+        reader.receive_data(bytes, 0);
+        reader.read_header()?;
+        Ok(reader)
     }
 
     /// Receives more data to process
@@ -72,32 +494,48 @@ impl CarReader {
     /// * `buf` - A slice of bytes containing the new data to process.
     /// * `pos` - The position in the overall input stream where these bytes belong.
     pub fn receive_data(&mut self, buf: &[u8], pos: usize) {
-        match &mut self.0 {
+        if let Some(random_read) = &mut self.random_read
+            && random_read.receive_data(buf, pos)
+        {
+            return;
+        }
+        match &mut self.state {
             CarReaderState::Unclear(buffer) => {
-                if pos != buffer.len() {
-                    // This means that the caller is trying to provide bytes at a position that
-                    // does not match the current buffer length, which indicates a logic error in the
-                    // caller's code (e.g., providing bytes out of order).
+                if pos > buffer.len() {
+                    // This means that the caller is trying to provide bytes past the current buffer
+                    // length, which indicates a logic error in the caller's code (e.g., providing bytes out of order).
                     return;
                 }
 
-                buffer.extend_from_slice(buf);
+                // Only append the bytes not buffered yet (the caller may provide overlapping data)
+                buffer.extend_from_slice(buf.get(buffer.len() - pos..).unwrap_or_default());
                 // Try to determine the format (CAR v1 or v2) based on the accumulated bytes
                 if let Some(format) = Self::determine_format(buffer) {
                     // If we can determine the format, transition to the appropriate state
                     let new_state = match format {
                         CarFormat::V1 => {
-                            let mut v1 = CarReaderV1::new();
+                            let mut v1 = CarReaderV1::with_min_read_hint(self.min_read_hint);
+                            v1.set_limits(self.limits);
+                            v1.set_cid_parsing(self.cid_parsing);
+                            if let Some(end) = self.end {
+                                v1.finish(end);
+                            }
                             v1.receive_data(buffer, 0); // Assuming buffer is fully valid
                             CarReaderState::V1(v1)
                         }
                         CarFormat::V2 => {
-                            let mut v2 = CarReaderV2::new();
+                            let mut v2 = CarReaderV2::with_min_read_hint(self.min_read_hint);
+                            v2.set_limits(self.limits);
+                            v2.set_cid_parsing(self.cid_parsing);
+                            v2.set_padding_check(self.padding_check);
+                            if let Some(end) = self.end {
+                                v2.finish(end);
+                            }
                             v2.receive_data(buffer, 0); // Assuming buffer is fully valid
                             CarReaderState::V2(v2)
                         }
                     };
-                    self.0 = new_state;
+                    self.state = new_state;
                 }
             }
             CarReaderState::V1(reader) => reader.receive_data(buf, pos),
@@ -105,6 +543,241 @@ impl CarReader {
         }
     }
 
+    /// Receive data held in [Bytes](bytes::Bytes) (or [BytesMut](bytes::BytesMut)), without copying it when possible
+    ///
+    /// This is [CarReader::receive_data] for data received in shared buffers (e.g. network frames): once the
+    /// format is known, and as long as the reader is fed chunk after chunk, the chunks are kept as is instead of
+    /// being copied into the internal buffer, and [CarReader::read_section_bytes] returns slices of them.
+    #[cfg(feature = "bytes")]
+    #[doc(cfg(feature = "bytes"))]
+    pub fn receive_bytes(&mut self, buf: impl Into<bytes::Bytes>, pos: usize) {
+        let buf = buf.into();
+        if let Some(random_read) = &mut self.random_read
+            && random_read.receive_data(&buf, pos)
+        {
+            return;
+        }
+        match &mut self.state {
+            CarReaderState::Unclear(_) => self.receive_data(&buf, pos),
+            CarReaderState::V1(reader) => reader.receive_bytes(buf, pos),
+            CarReaderState::V2(reader) => reader.receive_bytes(buf, pos),
+        }
+    }
+
+    /// Declare the end of the file, with its total length
+    ///
+    /// A CAR v1 file has no explicit end: without this call, the reader requests more data forever after its last
+    /// section. Once the end is declared (e.g. when the source reports its end), and all the bytes up to it are
+    /// received, reading past the last section returns [CarReaderError::EndOfSections] (like at the end of a CAR
+    /// v2 payload), and reading a header or section cut by the end of the file returns [CarReaderError::Truncated].
+    ///
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    ///
+    /// let car_bytes = include_bytes!("res/carv1-basic.car");
+    /// // The file is cut in the middle of its last section
+    /// let truncated = &car_bytes[..car_bytes.len() - 10];
+    /// let mut reader = CarReader::from_bytes(truncated).unwrap();
+    /// reader.finish(truncated.len());
+    /// let mut count = 0;
+    /// let missing_bytes = loop {
+    ///     match reader.read_section() {
+    ///         Ok(_) => count += 1,
+    ///         Err(CarReaderError::Truncated { missing_bytes }) => break missing_bytes,
+    ///         Err(e) => panic!("unexpected error: {e}"),
+    ///     }
+    /// };
+    /// assert_eq!((count, missing_bytes), (7, 10));
+    ///
+    /// let mut reader = CarReader::from_bytes(car_bytes).unwrap();
+    /// reader.finish(car_bytes.len());
+    /// while reader.read_section().is_ok() {}
+    /// assert!(matches!(reader.read_section(), Err(CarReaderError::EndOfSections)));
+    /// ```
+    pub fn finish(&mut self, total_len: usize) {
+        self.end = Some(total_len);
+        match &mut self.state {
+            CarReaderState::Unclear(_) => {}
+            CarReaderState::V1(reader) => reader.finish(total_len),
+            CarReaderState::V2(reader) => reader.finish(total_len),
+        }
+    }
+
+    /// Export the partially received section and the position of the reader
+    ///
+    /// When a network source disconnects in the middle of a (possibly long) section, the buffered bytes are not lost:
+    /// the exported state holds them, along with the headers and the position of the reader. Once imported into a
+    /// fresh reader (see [CarReader::import_partial_state]), the source only has to resume from
+    /// [PartialState::resume_offset], rather than from the start of the section.
+    ///
+    /// ## Returns
+    /// - `Ok(PartialState)` with the headers, the position and the buffered bytes of the reader.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the headers are not read yet.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    ///
+    /// let car_bytes = include_bytes!("res/carv1-basic.car");
+    /// let mut reader = CarReader::new();
+    /// // The connection drops in the middle of the second section (offset 192, length 133)
+    /// reader.receive_data(&car_bytes[..250], 0);
+    /// reader.read_header().unwrap();
+    /// reader.read_section().unwrap();
+    /// assert!(reader.read_section().is_err());
+    /// let state = reader.export_partial_state().unwrap();
+    /// assert_eq!((state.offset(), state.resume_offset()), (192, 250));
+    ///
+    /// // A fresh reader, fed from a resumed connection
+    /// let mut reader = CarReader::new();
+    /// reader.import_partial_state(state);
+    /// assert!(matches!(reader.read_section(), Err(CarReaderError::InsufficientData(250, _))));
+    /// reader.receive_data(&car_bytes[250..], 250);
+    /// assert_eq!(reader.read_section().unwrap().location.offset, 192);
+    /// ```
+    pub fn export_partial_state(&self) -> Result<PartialState, CarReaderError> {
+        if !self.has_header() {
+            return Err(CarReaderError::PreconditionNotMet);
+        }
+        let mut snapshot = self.state.clone();
+        let (offset, buffered) = match &mut snapshot {
+            CarReaderState::Unclear(_) => None,
+            CarReaderState::V1(reader) => Some(reader.take_buffered()),
+            CarReaderState::V2(reader) => reader.take_buffered(),
+        }
+        .ok_or(CarReaderError::PreconditionNotMet)?;
+        Ok(PartialState {
+            snapshot,
+            offset: offset as u64,
+            buffered,
+        })
+    }
+
+    /// Import a partially received section, exported by another reader (see [CarReader::export_partial_state])
+    ///
+    /// The reader takes the headers and the position of the exported state, and its buffered bytes, so the
+    /// source only has to provide the bytes from [PartialState::resume_offset]. The options of this reader (read
+    /// hint, limits, CID parsing, padding check, duplicate policy) are kept; the duplicates are only detected
+    /// among the sections read after the import.
+    pub fn import_partial_state(&mut self, state: PartialState) {
+        let PartialState {
+            snapshot,
+            offset,
+            buffered,
+        } = state;
+        self.state = snapshot;
+        self.apply_options();
+        self.first_offsets.clear();
+        self.duplicates.clear();
+        self.receive_data(&buffered, offset as usize);
+    }
+
+    /// Save the headers and the position of the reader, to resume parsing the archive later
+    ///
+    /// The checkpoint is the offset of the next section, along with the parsed headers: the buffered bytes (if any)
+    /// are not saved, they will be requested again. Encoded with [ReaderCheckpoint::encode], it can be persisted
+    /// by long-running jobs, so that a restarted process resumes a huge archive without reading it from byte 0.
+    ///
+    /// ## Returns
+    /// - `Ok(ReaderCheckpoint)` with the headers and the position of the reader.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the headers are not read yet.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError, ReaderCheckpoint};
+    ///
+    /// let car_bytes = include_bytes!("res/carv2-basic.car");
+    /// let mut reader = CarReader::from_bytes(car_bytes).unwrap();
+    /// reader.read_section().unwrap();
+    /// let saved = reader.save_state().unwrap().encode();
+    ///
+    /// // After a restart
+    /// let checkpoint = ReaderCheckpoint::decode(&saved).unwrap();
+    /// let mut resumed = CarReader::new();
+    /// resumed.restore_state(&checkpoint);
+    /// let position = checkpoint.position() as usize;
+    /// assert!(matches!(resumed.read_section(), Err(CarReaderError::InsufficientData(offset, _)) if offset == position));
+    /// resumed.receive_data(&car_bytes[position..], position);
+    /// assert_eq!(resumed.read_section().unwrap(), reader.read_section().unwrap());
+    /// ```
+    pub fn save_state(&self) -> Result<ReaderCheckpoint, CarReaderError> {
+        let (reader, data_offset, v2_header) = match &self.state {
+            CarReaderState::Unclear(_) => None,
+            CarReaderState::V1(reader) => Some((reader, 0, None)),
+            CarReaderState::V2(reader) => {
+                reader
+                    .payload_reader()
+                    .zip(reader.header())
+                    .map(|(inner, (_, v2_header))| {
+                        (inner, v2_header.data_offset, Some(v2_header.clone()))
+                    })
+            }
+        }
+        .ok_or(CarReaderError::PreconditionNotMet)?;
+        let (header, sections) = reader
+            .header()
+            .zip(reader.sections_range())
+            .ok_or(CarReaderError::PreconditionNotMet)?;
+        Ok(ReaderCheckpoint {
+            header: header.clone(),
+            header_size: sections.start,
+            v2_header,
+            position: data_offset + reader.position() as u64,
+        })
+    }
+
+    /// Restore the headers and the position saved by [CarReader::save_state], possibly by another process
+    ///
+    /// The reader is positioned on the next section of the checkpoint: the next [CarReaderError::InsufficientData]
+    /// requests exactly [ReaderCheckpoint::position]. Like with [CarReader::import_partial_state], the options of this
+    /// reader are kept and the duplicates are only detected among the sections read after the restore. The CAR v2
+    /// index and pre-payload padding are not part of the checkpoint: the index is read again when needed, and the
+    /// padding is reported as not checked.
+    pub fn restore_state(&mut self, checkpoint: &ReaderCheckpoint) {
+        let data_offset = checkpoint
+            .v2_header
+            .as_ref()
+            .map_or(0, |v2_header| v2_header.data_offset);
+        let mut inner = CarReaderV1::new();
+        inner.restore(
+            checkpoint.header.clone(),
+            checkpoint.header_size as usize,
+            checkpoint.position.saturating_sub(data_offset) as usize,
+        );
+        self.state = match &checkpoint.v2_header {
+            Some(v2_header) => CarReaderState::V2(CarReaderV2::restore(v2_header.clone(), inner)),
+            None => CarReaderState::V1(inner),
+        };
+        self.apply_options();
+        self.first_offsets.clear();
+        self.duplicates.clear();
+        self.random_read = None;
+    }
+
+    /// Apply the options of the reader to the inner reader
+    fn apply_options(&mut self) {
+        match &mut self.state {
+            CarReaderState::Unclear(_) => {}
+            CarReaderState::V1(reader) => {
+                reader.set_min_read_hint(self.min_read_hint);
+                reader.set_limits(self.limits);
+                reader.set_cid_parsing(self.cid_parsing);
+                if let Some(end) = self.end {
+                    reader.finish(end);
+                }
+            }
+            CarReaderState::V2(reader) => {
+                reader.set_min_read_hint(self.min_read_hint);
+                reader.set_limits(self.limits);
+                reader.set_cid_parsing(self.cid_parsing);
+                reader.set_padding_check(self.padding_check);
+                if let Some(end) = self.end {
+                    reader.finish(end);
+                }
+            }
+        }
+    }
+
     /// Determines the CAR format (v1 or v2) based on the accumulated bytes.
     /// Returns `Some(CarFormat)` if the format can be determined, or `None` if more bytes are needed.
     fn determine_format(bytes: &[u8]) -> Option<CarFormat> {
@@ -127,7 +800,7 @@ impl CarReader {
     /// - `Some(CarFormat::V2)` if the reader has determined that the input is CAR v2.
     /// - `None` if the reader has not yet determined the format.
     pub fn get_format(&self) -> Option<CarFormat> {
-        match &self.0 {
+        match &self.state {
             CarReaderState::Unclear(_) => None,
             CarReaderState::V1(_) => Some(CarFormat::V1),
             CarReaderState::V2(_) => Some(CarFormat::V2),
@@ -140,7 +813,7 @@ impl CarReader {
     /// This allows the caller to interact with the specific reader once the format is known,
     /// while still using the unified CarReader interface.
     pub fn get_underlying_reader(&'_ mut self) -> Option<CarUnderlyingReader<'_>> {
-        match &mut self.0 {
+        match &mut self.state {
             CarReaderState::Unclear(_) => None,
             CarReaderState::V1(reader) => Some(CarUnderlyingReader::V1(reader)),
             CarReaderState::V2(reader) => Some(CarUnderlyingReader::V2(reader)),
@@ -149,7 +822,7 @@ impl CarReader {
 
     /// Has the header been read?
     pub fn has_header(&self) -> bool {
-        match self.0 {
+        match self.state {
             CarReaderState::Unclear(_) => false,
             CarReaderState::V1(ref reader) => reader.has_header(),
             CarReaderState::V2(ref reader) => reader.has_header(),
@@ -163,7 +836,7 @@ impl CarReader {
     /// - `Some((&CarHeaderV1, None))` if the reader has read the CAR v1 header (and is in CAR v1 format).
     /// - `Some((&CarHeaderV1, Some(&CarHeaderV2)))` if the reader has read both the CAR v1 and v2 headers (and is in CAR v2 format).
     pub fn header(&self) -> Option<(&CarHeaderV1, Option<&CarHeaderV2>)> {
-        match self.0 {
+        match self.state {
             CarReaderState::Unclear(_) => None,
             CarReaderState::V1(ref reader) => reader.header().map(|h| (h, None)),
             CarReaderState::V2(ref reader) => {
@@ -178,8 +851,21 @@ impl CarReader {
 
     /// Read the CAR headers if not already read
     pub fn read_header(&mut self) -> Result<(), CarReaderError> {
-        match &mut self.0 {
-            CarReaderState::Unclear(_) => Err(CarReaderError::InsufficientData(0, 12)), // We need at least 12 bytes to determine the format and read the header
+        match &mut self.state {
+            CarReaderState::Unclear(buffer) if self.end.is_some_and(|end| buffer.len() >= end) => {
+                // The file ends before its format could be determined
+                Err(CarReaderError::Truncated {
+                    missing_bytes: 51usize.saturating_sub(buffer.len()).max(1),
+                })
+            }
+            CarReaderState::Unclear(buffer) => {
+                // We need at least the CARv2 pragma to determine the format, and the whole CARv2 header (51 bytes)
+                // is small enough to be a sensible first read for both formats.
+                Err(CarReaderError::InsufficientData(
+                    buffer.len(),
+                    51usize.saturating_sub(buffer.len()).max(self.min_read_hint),
+                ))
+            }
             CarReaderState::V1(reader) => reader.read_header().map_err(CarReaderError::from),
             CarReaderState::V2(reader) => reader.read_header().map_err(CarReaderError::from),
         }
@@ -206,6 +892,9 @@ impl CarReader {
     /// it might skip some sections and return an error that the section is not found,
     /// even if it is present in the file.
     ///
+    /// Identity CIDs embed their data: they are satisfied right away, without scanning nor moving the
+    /// reader, with a section located at [SectionLocation::INLINE].
+    ///
     /// ## Arguments
     /// - `cid` - The CID of the section to find.
     ///
@@ -214,28 +903,446 @@ impl CarReader {
     /// - `Err(CarReaderError)` if an error occurs during the search, such as an invalid section
     ///   format or if the reader is still in an unclear state.
     pub fn find_section(&mut self, cid: &RawCid) -> Result<LocatableSection, CarReaderError> {
-        match &mut self.0 {
+        if let Some(data) = cid.inline_data() {
+            return Ok(LocatableSection {
+                section: Section::new(cid.clone(), Block::new(data.to_vec())),
+                location: SectionLocation::INLINE,
+            });
+        }
+        match &mut self.state {
             CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
             CarReaderState::V1(reader) => reader.find_section(cid).map_err(CarReaderError::from),
             CarReaderState::V2(reader) => reader.find_section(cid).map_err(CarReaderError::from),
         }
     }
 
+    /// Finds the first section whose CID has the given multihash digest.
+    ///
+    /// CAR v2 indexes key their entries by digest rather than by CID, so this is the lookup matching them:
+    /// sections whose CIDs only differ by their codec are matched alike. Positioning requirements are the
+    /// same as [CarReader::find_section].
+    ///
+    /// ## Arguments
+    /// - `digest` - The raw multihash digest of the section to find.
+    ///
+    /// ## Returns
+    /// - `Ok(Section)` if a section with the specified digest is found.
+    /// - `Err(CarReaderError)` if an error occurs during the search, or if the reader is still in an unclear state.
+    pub fn find_section_by_digest(
+        &mut self,
+        digest: &[u8],
+    ) -> Result<LocatableSection, CarReaderError> {
+        match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader
+                .find_section_by_digest(digest)
+                .map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader
+                .find_section_by_digest(digest)
+                .map_err(CarReaderError::from),
+        }
+    }
+
     /// Reads the next section from the current position in the reader.
     ///
     /// This method will read the next section based on the current position of the reader.
     /// It assumes that the reader is already positioned at the beginning of a section (e.g., after seeking to the first section).
     ///
+    /// Duplicate CIDs are handled according to the [DuplicatePolicy] (see [CarReader::set_duplicate_policy]).
+    ///
     /// ## Returns
     /// - `Ok(Section)` if a section is successfully read.
     /// - `Err(CarReaderError)` if an error occurs during reading, such as an invalid section format
     ///    or if the reader is still in an unclear state.
+    /// - `Err(CarReaderError::DuplicateSection)` if the CID of the section was already read, in
+    ///   [DuplicatePolicy::Error] mode. The reader is positioned after the duplicate.
     pub fn read_section(&mut self) -> Result<LocatableSection, CarReaderError> {
-        match &mut self.0 {
+        let section = match &mut self.state {
             CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
             CarReaderState::V1(reader) => reader.read_section().map_err(CarReaderError::from),
             CarReaderState::V2(reader) => reader.read_section().map_err(CarReaderError::from),
+        }?;
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            self.check_duplicate(&section)?;
+        }
+        Ok(section)
+    }
+
+    /// Reads the next section, borrowing its block data from the internal buffer.
+    ///
+    /// This is [CarReader::read_section] without the copy of the block data, for high-throughput passes which
+    /// never need the payload (indexing, layout checks, ...). The section borrows the reader, and the
+    /// [SectionRef::to_owned] escape hatch copies it out when needed. Duplicates are handled like in
+    /// [CarReader::read_section].
+    ///
+    /// ## Returns
+    /// - `Ok((SectionLocation, SectionRef))` with the absolute location of the section, and the borrowed section.
+    /// - `Err(CarReaderError)` on the same errors as [CarReader::read_section].
+    pub fn read_section_ref(
+        &mut self,
+    ) -> Result<(SectionLocation, SectionRef<'_>), CarReaderError> {
+        let (location, section) = match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader.read_section_ref().map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.read_section_ref().map_err(CarReaderError::from),
+        }?;
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            check_duplicate(
+                self.duplicate_policy,
+                &mut self.first_offsets,
+                &mut self.duplicates,
+                section.cid(),
+                location.offset,
+            )?;
+        }
+        Ok((location, section))
+    }
+
+    /// Reads the next section, with its block data as a [Bytes](bytes::Bytes) slice of the received chunk.
+    ///
+    /// When the section lies in a chunk given to [CarReader::receive_bytes], the block shares its memory, and
+    /// can be kept or forwarded without copy. Otherwise, the block data is copied once. Duplicates are handled
+    /// like in [CarReader::read_section].
+    ///
+    /// ## Returns
+    /// - `Ok((SectionLocation, SectionBytes))` with the absolute location of the section, and the section.
+    /// - `Err(CarReaderError)` on the same errors as [CarReader::read_section].
+    #[cfg(feature = "bytes")]
+    #[doc(cfg(feature = "bytes"))]
+    pub fn read_section_bytes(
+        &mut self,
+    ) -> Result<(SectionLocation, crate::wire::v1::SectionBytes), CarReaderError> {
+        let (location, section) = match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader.read_section_bytes().map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.read_section_bytes().map_err(CarReaderError::from),
+        }?;
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            check_duplicate(
+                self.duplicate_policy,
+                &mut self.first_offsets,
+                &mut self.duplicates,
+                section.cid(),
+                location.offset,
+            )?;
+        }
+        Ok((location, section))
+    }
+
+    /// Skips the next section, only reading its length prefix and CID.
+    ///
+    /// The block data is neither requested nor buffered: the next [CarReaderError::InsufficientData] points
+    /// right after the section, so that the caller seeks over the block instead of reading it. Listing or
+    /// indexing a large archive then only reads a few bytes per section. Duplicates are handled like in
+    /// [CarReader::read_section].
+    ///
+    /// ## Returns
+    /// - `Ok((SectionLocation, RawCid))` with the absolute location of the section, and its CID.
+    /// - `Err(CarReaderError)` on the same errors as [CarReader::read_section].
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    ///
+    /// let car_bytes = include_bytes!("res/carv1-basic.car");
+    /// let mut reader = CarReader::new();
+    /// reader.receive_data(&car_bytes[..110], 0);
+    /// reader.read_header().unwrap();
+    ///
+    /// // Only the beginning of the first section is requested, and fed
+    /// assert!(matches!(reader.skip_section(), Err(CarReaderError::InsufficientData(110, _))));
+    /// reader.receive_data(&car_bytes[110..160], 110);
+    /// let (location, _cid) = reader.skip_section().unwrap();
+    /// assert_eq!(location.offset, 100);
+    /// // The next request is right after the block of the first section
+    /// assert!(matches!(
+    ///     reader.skip_section(),
+    ///     Err(CarReaderError::InsufficientData(offset, _)) if offset == (location.offset + location.length) as usize
+    /// ));
+    /// ```
+    pub fn skip_section(&mut self) -> Result<(SectionLocation, RawCid), CarReaderError> {
+        let (location, cid) = match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader.skip_section().map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.skip_section().map_err(CarReaderError::from),
+        }?;
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            check_duplicate(
+                self.duplicate_policy,
+                &mut self.first_offsets,
+                &mut self.duplicates,
+                &cid,
+                location.offset,
+            )?;
+        }
+        Ok((location, cid))
+    }
+
+    /// Starts reading the next section in chunks, only reading its length prefix and CID.
+    ///
+    /// The block data is then surfaced by [CarReader::read_block_chunk] as it arrives, instead of being buffered
+    /// until the whole section is received: the memory of the reader is bounded by the size of the received chunks,
+    /// whatever the size of the block. Any other read (or seek) in the middle of the block skips the rest of it.
+    /// Duplicates are handled like in [CarReader::read_section].
+    ///
+    /// ## Returns
+    /// - `Ok((SectionLocation, RawCid, usize))` with the absolute location of the section, its CID and the length
+    ///   of its block.
+    /// - `Err(CarReaderError)` on the same errors as [CarReader::read_section].
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    ///
+    /// let car_bytes = include_bytes!("res/carv1-basic.car");
+    /// let mut reader = CarReader::new();
+    /// // The header, and the length and CID of the first section
+    /// reader.receive_data(&car_bytes[..140], 0);
+    /// reader.read_header().unwrap();
+    ///
+    /// let (location, _cid, block_length) = reader.begin_section().unwrap();
+    /// let mut block = Vec::new();
+    /// let mut offset = 140;
+    /// loop {
+    ///     match reader.read_block_chunk() {
+    ///         Ok(Some(chunk)) => block.extend_from_slice(chunk),
+    ///         Ok(None) => break,
+    ///         // Feed the bytes by chunks of 10 bytes at most
+    ///         Err(CarReaderError::InsufficientData(_, _)) => {
+    ///             reader.receive_data(&car_bytes[offset..offset + 10], offset);
+    ///             offset += 10;
+    ///         }
+    ///         Err(err) => panic!("{err}"),
+    ///     }
+    /// }
+    /// assert_eq!(block.len(), block_length);
+    /// assert!(car_bytes[..(location.offset + location.length) as usize].ends_with(&block));
+    /// ```
+    pub fn begin_section(&mut self) -> Result<(SectionLocation, RawCid, usize), CarReaderError> {
+        let (location, cid, block_length) = match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader.begin_section().map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.begin_section().map_err(CarReaderError::from),
+        }?;
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            check_duplicate(
+                self.duplicate_policy,
+                &mut self.first_offsets,
+                &mut self.duplicates,
+                &cid,
+                location.offset,
+            )?;
         }
+        Ok((location, cid, block_length))
+    }
+
+    /// Reads the next chunk of the block of the section started with [CarReader::begin_section].
+    ///
+    /// ## Returns
+    /// - `Ok(Some(chunk))` with the next bytes of the block received so far, never empty.
+    /// - `Ok(None)` once the whole block is read (or if no section was started).
+    /// - `Err(CarReaderError::InsufficientData)` if more bytes of the block are needed.
+    /// - `Err(CarReaderError::Truncated)` if the file ends in the middle of the block.
+    pub fn read_block_chunk(&mut self) -> Result<Option<&[u8]>, CarReaderError> {
+        match &mut self.state {
+            CarReaderState::Unclear(_) => Ok(None),
+            CarReaderState::V1(reader) => reader.read_block_chunk().map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.read_block_chunk().map_err(CarReaderError::from),
+        }
+    }
+
+    /// Number of bytes of the block not read yet, while a section is read in chunks
+    pub fn block_remaining(&self) -> usize {
+        match &self.state {
+            CarReaderState::Unclear(_) => 0,
+            CarReaderState::V1(reader) => reader.block_remaining(),
+            CarReaderState::V2(reader) => reader.block_remaining(),
+        }
+    }
+
+    /// Skip the corrupt bytes at the current position, up to the next plausible section
+    ///
+    /// After a section failed to be read (e.g. with [CarReaderError::InvalidSectionFormat]), the reader stays on
+    /// it. This method scans forward for the next plausible section boundary (a length within the [Limits], a valid
+    /// CID, and a length consistent with the following bytes), so that the readable sections of a damaged archive
+    /// can be salvaged. It must be called again after feeding the requested data, until it returns the skipped range.
+    ///
+    /// See [CarReaderV1::resync] and [CarReaderV2::resync] for the details of each format.
+    ///
+    /// ## Returns
+    /// - `Ok(Range<u64>)` with the absolute range of the skipped bytes, the reader being positioned on its end.
+    /// - `Err(CarReaderError::InsufficientData)` if more bytes are needed to go on with the scan.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the headers are not read yet.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    ///
+    /// let mut car_bytes = include_bytes!("res/carv1-basic.car").to_vec();
+    /// // Damage the length of the third section
+    /// car_bytes[325] = 0;
+    ///
+    /// let mut reader = CarReader::from_bytes(&car_bytes).unwrap();
+    /// let mut salvaged = Vec::new();
+    /// loop {
+    ///     match reader.read_section() {
+    ///         Ok(section) => salvaged.push(section),
+    ///         Err(CarReaderError::InvalidSectionFormat(_)) => {
+    ///             let skipped = reader.resync().unwrap();
+    ///             println!("Skipped bytes {:?}", skipped);
+    ///         }
+    ///         Err(_) => break,
+    ///     }
+    /// }
+    /// assert_eq!(salvaged.len(), 7);
+    /// ```
+    pub fn resync(&mut self) -> Result<Range<u64>, CarReaderError> {
+        match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader.resync().map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.resync().map_err(CarReaderError::from),
+        }
+    }
+
+    /// Record the offset of the section CID, and apply the duplicate policy if it was already read
+    fn check_duplicate(&mut self, section: &LocatableSection) -> Result<(), CarReaderError> {
+        check_duplicate(
+            self.duplicate_policy,
+            &mut self.first_offsets,
+            &mut self.duplicates,
+            section.cid(),
+            section.location.offset,
+        )
+    }
+
+    /// Reads the section at the given location, without moving the reader.
+    ///
+    /// This is the random access counterpart of [CarReader::read_section], for locations known in advance (from
+    /// an index, or from a previous run): the first [CarReaderError::InsufficientData] requests exactly
+    /// `location.offset`, and the bytes received from there are buffered apart, until the section is read.
+    /// The sequential reads (position, buffered bytes, duplicate tracking) are left untouched, and can go on
+    /// once the section is read.
+    ///
+    /// The location length is only used as a read hint, and can be 0 if unknown (e.g. from a CAR v2 index).
+    ///
+    /// ## Arguments
+    /// - `location` - The location of the section, from the start of the file.
+    ///
+    /// ## Returns
+    /// - `Ok(Section)` with the section at this location.
+    /// - `Err(CarReaderError::InsufficientData)` with the range of the section still to be received.
+    /// - `Err(CarReaderError::InvalidSectionFormat)` if there is no valid section at this location.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the headers are not read yet, or if the location
+    ///   is not in the sections area of the file.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    /// use navira_car::wire::v1::SectionLocation;
+    ///
+    /// let car_bytes = include_bytes!("res/carv1-basic.car");
+    /// let mut reader = CarReader::new();
+    /// reader.receive_data(&car_bytes[..150], 0);
+    /// reader.read_header().unwrap();
+    ///
+    /// let location = SectionLocation { offset: 619, length: 41 };
+    /// assert!(matches!(reader.read_section_at(&location), Err(CarReaderError::InsufficientData(619, 41))));
+    /// reader.receive_data(&car_bytes[619..660], 619);
+    /// assert_eq!(reader.read_section_at(&location).unwrap().block().data(), b"aaaa");
+    ///
+    /// // The sequential reads go on from where they were
+    /// assert!(matches!(reader.read_section(), Err(CarReaderError::InsufficientData(150, _))));
+    /// ```
+    pub fn read_section_at(
+        &mut self,
+        location: &SectionLocation,
+    ) -> Result<LocatableSection, CarReaderError> {
+        let sections = match &self.state {
+            CarReaderState::Unclear(_) => None,
+            CarReaderState::V1(reader) => reader.sections_range(),
+            CarReaderState::V2(reader) => reader.sections_range(),
+        };
+        if !sections.is_some_and(|sections| sections.contains(&location.offset)) {
+            return Err(CarReaderError::PreconditionNotMet);
+        }
+
+        let random_read = match &mut self.random_read {
+            Some(random_read) if random_read.offset == location.offset => random_read,
+            random_read => random_read.insert(RandomRead {
+                offset: location.offset,
+                data: Vec::new(),
+            }),
+        };
+        match Section::try_read_bytes_with(&random_read.data, &self.limits, self.cid_parsing) {
+            Ok((section, section_size)) => {
+                self.random_read = None;
+                Ok(LocatableSection {
+                    section,
+                    location: SectionLocation {
+                        offset: location.offset,
+                        length: section_size as u64,
+                    },
+                })
+            }
+            Err(SectionFormatError::InsufficientData) => {
+                // The declared section length is authoritative once received
+                let size = match UnsignedVarint::decode(&random_read.data) {
+                    Some((length, varint_size)) => varint_size + length.0 as usize,
+                    None => location.length as usize,
+                };
+                Err(CarReaderError::InsufficientData(
+                    random_read.end(),
+                    size.saturating_sub(random_read.data.len())
+                        .max(self.min_read_hint),
+                ))
+            }
+            Err(err) => {
+                self.random_read = None;
+                Err(CarReaderError::InvalidSectionFormat(err))
+            }
+        }
+    }
+
+    /// Seeks to the section at the given location, as returned by [CarReader::read_section] or found in an index.
+    ///
+    /// The internal buffers are reset, and the next [CarReaderError::InsufficientData] requests exactly
+    /// `location.offset`, so the caller only has to feed the bytes starting at this offset.
+    ///
+    /// ## Arguments
+    /// - `location` - The location of the section, from the start of the file.
+    ///
+    /// ## Returns
+    /// - `Ok(())` if the reader is positioned on the location.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the headers are not read yet, or if the location
+    ///   is not in the sections area of the file.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::{CarReader, CarReaderError};
+    /// use navira_car::wire::v1::SectionLocation;
+    ///
+    /// let car_bytes = include_bytes!("res/carv1-basic.car");
+    /// let mut reader = CarReader::new();
+    /// reader.receive_data(&car_bytes[..100], 0);
+    /// reader.read_header().unwrap();
+    ///
+    /// // Jump directly to the 7th section, only feeding its bytes
+    /// let location = SectionLocation { offset: 619, length: 41 };
+    /// reader.seek_to(&location).unwrap();
+    /// assert!(matches!(reader.read_section(), Err(CarReaderError::InsufficientData(619, _))));
+    /// reader.receive_data(&car_bytes[619..660], 619);
+    /// let section = reader.read_section().unwrap();
+    /// assert_eq!(section.block().data(), b"aaaa");
+    /// assert_eq!(section.location, location);
+    /// ```
+    pub fn seek_to(&mut self, location: &SectionLocation) -> Result<(), CarReaderError> {
+        match &mut self.state {
+            CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
+            CarReaderState::V1(reader) => reader.seek_to(location).map_err(CarReaderError::from),
+            CarReaderState::V2(reader) => reader.seek_to(location).map_err(CarReaderError::from),
+        }?;
+        self.rewind_events();
+        Ok(())
     }
 
     /// Seeks to the first section in the reader, which is necessary before performing a linear search for sections by CID.
@@ -244,11 +1351,153 @@ impl CarReader {
     /// after the header(s) and any index (if present). This is important for ensuring that subsequent calls
     /// to `find_section` will not skip any sections during a linear search.
     pub fn seek_first_section(&mut self) -> Result<(), CarReaderError> {
-        match &mut self.0 {
+        match &mut self.state {
             CarReaderState::Unclear(_) => Err(CarReaderError::PreconditionNotMet),
             CarReaderState::V1(reader) => reader.seek_first_section().map_err(CarReaderError::from),
             CarReaderState::V2(reader) => reader.seek_first_section().map_err(CarReaderError::from),
+        }?;
+        self.rewind_events();
+        Ok(())
+    }
+
+    /// Report the sections again with [CarReader::next_event], after a seek
+    fn rewind_events(&mut self) {
+        if self.event_phase != EventPhase::Header {
+            self.event_phase = EventPhase::Sections;
+        }
+    }
+}
+
+/// Record the offset of the CID, and apply the duplicate policy if it was already read
+///
+/// Split from [CarReader] so that the section checked may borrow the reader state.
+fn check_duplicate(
+    duplicate_policy: DuplicatePolicy,
+    first_offsets: &mut HashMap<RawCid, u64>,
+    duplicates: &mut Vec<DuplicateSection>,
+    cid: &RawCid,
+    offset: u64,
+) -> Result<(), CarReaderError> {
+    let first_offset = *first_offsets.entry(cid.clone()).or_insert(offset);
+    if first_offset == offset {
+        return Ok(());
+    }
+    let duplicate = DuplicateSection {
+        cid: cid.clone(),
+        first_offset,
+        offset,
+    };
+    match duplicate_policy {
+        DuplicatePolicy::Allow => Ok(()),
+        DuplicatePolicy::Warn => {
+            warn_event!(
+                WIRE_V1,
+                cid = %duplicate.cid.to_hex(),
+                first_offset,
+                offset,
+                "duplicate section"
+            );
+            duplicates.push(duplicate);
+            Ok(())
         }
+        DuplicatePolicy::Error => Err(CarReaderError::DuplicateSection(
+            duplicate.cid,
+            first_offset,
+            offset,
+        )),
+    }
+}
+
+/// Builder of a [CarReader], collecting all its options
+///
+/// Every option defaults to the value of [CarReader::new], so only the options which matter have to be set.
+/// New options are added to the builder, rather than as new constructors of the reader.
+///
+/// ## Examples
+/// ```
+/// use navira_car::{CarReader, DuplicatePolicy, Limits};
+/// use navira_car::wire::cid::CidParsing;
+///
+/// let reader = CarReader::builder()
+///     .min_read_hint(64 * 1024)
+///     .limits(Limits::default())
+///     .cid_parsing(CidParsing::Lenient)
+///     .duplicate_policy(DuplicatePolicy::Warn)
+///     .build();
+/// assert_eq!(reader.min_read_hint(), 64 * 1024);
+/// assert_eq!(reader.cid_parsing(), CidParsing::Lenient);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CarReaderBuilder {
+    min_read_hint: usize,
+    limits: Limits,
+    cid_parsing: CidParsing,
+    padding_check: PaddingCheck,
+    duplicate_policy: DuplicatePolicy,
+}
+
+impl CarReaderBuilder {
+    /// Create a builder with the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the minimum hint length returned in InsufficientData errors (see [CarReader::with_min_read_hint])
+    pub fn min_read_hint(mut self, min_read_hint: usize) -> Self {
+        self.min_read_hint = min_read_hint;
+        self
+    }
+
+    /// Set the size limits applied to the sections (see [CarReader::set_limits])
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the parsing mode of the section CIDs (see [CarReader::set_cid_parsing])
+    pub fn cid_parsing(mut self, cid_parsing: CidParsing) -> Self {
+        self.cid_parsing = cid_parsing;
+        self
+    }
+
+    /// Set the checking mode of the CAR v2 pre-payload padding (see [CarReader::set_padding_check])
+    pub fn padding_check(mut self, padding_check: PaddingCheck) -> Self {
+        self.padding_check = padding_check;
+        self
+    }
+
+    /// Set the behavior when a CID appears in several sections (see [CarReader::set_duplicate_policy])
+    pub fn duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    /// Reject every anomaly of the input: unknown CID versions, non-zero padding and duplicate sections
+    ///
+    /// The size limits are left untouched.
+    pub fn strict(self) -> Self {
+        self.cid_parsing(CidParsing::Strict)
+            .padding_check(PaddingCheck::Strict)
+            .duplicate_policy(DuplicatePolicy::Error)
+    }
+
+    /// Parse untrusted input: reject every anomaly (see [CarReaderBuilder::strict]) and apply [Limits::hardened]
+    ///
+    /// Every length read from the input (header, padding, sections) is then checked against tight limits before
+    /// the corresponding bytes are requested, so a malicious archive cannot make the caller buffer more than a
+    /// few MiB.
+    pub fn hardened(self) -> Self {
+        self.strict().limits(Limits::hardened())
+    }
+
+    /// Build the reader, in the "unclear" state of [CarReader::new]
+    pub fn build(self) -> CarReader {
+        let mut reader = CarReader::with_min_read_hint(self.min_read_hint);
+        reader.set_limits(self.limits);
+        reader.set_cid_parsing(self.cid_parsing);
+        reader.set_padding_check(self.padding_check);
+        reader.set_duplicate_policy(self.duplicate_policy);
+        reader
     }
 }
 
@@ -263,6 +1512,12 @@ pub enum CarReaderError {
     InvalidFormat,
     #[error("Invalid header format")]
     InvalidHeader(ciborium::de::Error<std::io::Error>),
+    /// The CAR v1 header is larger than the limits, see [Limits::max_header_size]
+    ///
+    /// Also returned for the CAR v2 pre-payload padding in [PaddingCheck::Strict] mode, see
+    /// [CarReaderV2Error::HeaderTooLarge].
+    #[error("Header too large: {0} bytes")]
+    HeaderTooLarge(u64),
     #[error("Invalid CAR version, expected 2")]
     InvalidVersion,
     #[error("Invalid section format")]
@@ -274,7 +1529,7 @@ pub enum CarReaderError {
     ///
     /// # Arguments
     /// * usize - Need to read from this offset
-    /// * usize - Hint length of data to read (if known, otherwise 0)
+    /// * usize - Hint length of data to read (if known, otherwise 0), never below the configured minimum read hint
     #[error("Insufficient data to proceed")]
     InsufficientData(usize, usize),
     /// No more sections available in the CAR file
@@ -283,6 +1538,28 @@ pub enum CarReaderError {
     /// For instance, when you reached the end of the inner CARv1 data in a CARv2 file and try to read another section, you will get this error.
     #[error("No more sections available in the CAR file")]
     EndOfSections,
+    /// The file ends in the middle of a header or of a section (see [CarReader::finish])
+    ///
+    /// `missing_bytes` is the number of bytes missing to complete it, or 1 if its length is not even known.
+    #[error("Truncated CAR file, {missing_bytes} bytes missing")]
+    Truncated { missing_bytes: usize },
+    /// The CAR v2 pre-payload padding contains a non-zero byte, at this absolute offset
+    #[error("Non-zero pre-payload padding at offset {0}")]
+    NonZeroPadding(u64),
+    /// The CID was already read in a previous section (see [DuplicatePolicy::Error])
+    ///
+    /// # Arguments
+    /// * RawCid - The duplicated CID
+    /// * u64 - Offset of the first section with this CID
+    /// * u64 - Offset of the duplicate section
+    #[error("Duplicate section {cid} at offset {offset} (first at offset {first})", cid = .0.to_hex(), first = .1, offset = .2)]
+    DuplicateSection(RawCid, u64, u64),
+    /// The CAR v2 index is malformed or of an unknown type
+    #[error("Invalid index: {0}")]
+    InvalidIndex(IndexFormatError),
+    /// The section is absent from the full CAR v2 index, so it is not in the file
+    #[error("Section not found in the index")]
+    SectionNotFound,
 }
 
 impl From<CarReaderV1Error> for CarReaderError {
@@ -291,11 +1568,16 @@ impl From<CarReaderV1Error> for CarReaderError {
             CarReaderV1Error::InvalidFormat => CarReaderError::InvalidFormat,
             CarReaderV1Error::InvalidVersion(_) => CarReaderError::InvalidVersion,
             CarReaderV1Error::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+            CarReaderV1Error::HeaderTooLarge(length) => CarReaderError::HeaderTooLarge(length),
             CarReaderV1Error::InvalidSectionFormat(e) => CarReaderError::InvalidSectionFormat(e),
             CarReaderV1Error::PreconditionNotMet => CarReaderError::PreconditionNotMet,
             CarReaderV1Error::InsufficientData(offset, hint) => {
                 CarReaderError::InsufficientData(offset, hint)
             }
+            CarReaderV1Error::EndOfSections => CarReaderError::EndOfSections,
+            CarReaderV1Error::Truncated { missing_bytes } => {
+                CarReaderError::Truncated { missing_bytes }
+            }
         }
     }
 }
@@ -306,12 +1588,480 @@ impl From<CarReaderV2Error> for CarReaderError {
             CarReaderV2Error::InvalidFormat => CarReaderError::InvalidFormat,
             CarReaderV2Error::InvalidVersion => CarReaderError::InvalidVersion,
             CarReaderV2Error::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+            CarReaderV2Error::HeaderTooLarge(length) => CarReaderError::HeaderTooLarge(length),
             CarReaderV2Error::InvalidSectionFormat(e) => CarReaderError::InvalidSectionFormat(e),
             CarReaderV2Error::PreconditionNotMet => CarReaderError::PreconditionNotMet,
             CarReaderV2Error::InsufficientData(offset, hint) => {
                 CarReaderError::InsufficientData(offset, hint)
             }
             CarReaderV2Error::EndOfSections => CarReaderError::EndOfSections,
+            CarReaderV2Error::Truncated { missing_bytes } => {
+                CarReaderError::Truncated { missing_bytes }
+            }
+            CarReaderV2Error::NonZeroPadding(offset) => CarReaderError::NonZeroPadding(offset),
+            CarReaderV2Error::InvalidIndex(e) => CarReaderError::InvalidIndex(e),
+            CarReaderV2Error::SectionNotFound => CarReaderError::SectionNotFound,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata;
+    use crate::wire::v1::{self, Section};
+
+    /// CARv1 archive with the "aaaa" block written twice, around the "cccc" block
+    fn car_with_duplicate() -> Vec<u8> {
+        let aaaa = testdata::CARV1_BASIC_SECTIONS[6].cid();
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        let mut writer = v1::CarWriter::with_buffer_size(vec![aaaa.clone()], 1024);
+        for (cid, data) in [(&aaaa, b"aaaa"), (&cccc, b"cccc"), (&aaaa, b"aaaa")] {
+            let section = Section::from((cid.clone(), data.to_vec()));
+            writer.write_section(&section).unwrap();
+        }
+        let mut buf = vec![0u8; 1024];
+        let len = writer.send_data(&mut buf);
+        buf.truncate(len);
+        buf
+    }
+
+    fn read_all(reader: &mut CarReader) -> Result<usize, CarReaderError> {
+        let mut count = 0;
+        loop {
+            match reader.read_section() {
+                Ok(_) => count += 1,
+                Err(CarReaderError::InsufficientData(..)) => return Ok(count),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_car_reader_duplicate_policy() {
+        let car = car_with_duplicate();
+        let mut reader = CarReader::from_bytes(&car).unwrap();
+        assert_eq!(read_all(&mut reader).unwrap(), 3);
+        assert!(reader.take_duplicates().is_empty());
+
+        let mut reader = CarReader::from_bytes(&car).unwrap();
+        reader.set_duplicate_policy(DuplicatePolicy::Warn);
+        assert_eq!(read_all(&mut reader).unwrap(), 3);
+        let duplicates = reader.take_duplicates();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].cid, testdata::CARV1_BASIC_SECTIONS[6].cid());
+        assert!(duplicates[0].first_offset < duplicates[0].offset);
+
+        // Reading the same sections again after a seek is not a duplicate
+        reader.seek_first_section().unwrap();
+        let start = duplicates[0].first_offset as usize;
+        reader.receive_data(&car[start..], start);
+        assert_eq!(read_all(&mut reader).unwrap(), 3);
+        assert_eq!(reader.take_duplicates().len(), 1);
+
+        let mut reader = CarReader::from_bytes(&car).unwrap();
+        reader.set_duplicate_policy(DuplicatePolicy::Error);
+        assert!(reader.read_section().is_ok());
+        assert!(reader.read_section().is_ok());
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::DuplicateSection(cid, first, second))
+                if cid == testdata::CARV1_BASIC_SECTIONS[6].cid() && first < second
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_builder_strict() {
+        let car = car_with_duplicate();
+        let mut reader = CarReader::builder().strict().build();
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        assert_eq!(reader.duplicate_policy(), DuplicatePolicy::Error);
+        assert!(reader.read_section().is_ok());
+        assert!(reader.read_section().is_ok());
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::DuplicateSection(cid, first, second))
+                if cid == testdata::CARV1_BASIC_SECTIONS[6].cid() && first < second
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_hardened() {
+        // A header length of 1 GiB is rejected as soon as its length prefix is received
+        let mut bytes = crate::wire::varint::UnsignedVarint(1 << 30).encode();
+        bytes.extend_from_slice(&[0xa2; 8]);
+        let mut reader = CarReader::builder().hardened().build();
+        reader.receive_data(&bytes, 0);
+        assert!(matches!(
+            reader.read_header(),
+            Err(CarReaderError::HeaderTooLarge(length)) if length == 1 << 30
+        ));
+        assert_eq!(reader.limits(), &Limits::hardened());
+        assert_eq!(reader.duplicate_policy(), DuplicatePolicy::Error);
+
+        // Legitimate archives are still read
+        let mut reader = CarReader::builder().hardened().build();
+        reader.receive_data(testdata::CARV2_BASIC, 0);
+        reader.read_header().unwrap();
+        assert!(reader.read_section().is_ok());
+    }
+
+    #[test]
+    fn test_car_reader_partial_state() {
+        let car = testdata::CARV2_BASIC;
+        let mut expected = CarReader::from_bytes(car).unwrap();
+        let first = expected.read_section().unwrap();
+        let second = expected.read_section().unwrap();
+
+        // Disconnected in the middle of the second section
+        let cut = (second.location.offset + second.location.length / 2) as usize;
+        let mut reader = CarReader::new();
+        assert!(matches!(
+            reader.export_partial_state(),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+        reader.receive_data(&car[..cut], 0);
+        reader.read_header().unwrap();
+        assert_eq!(reader.read_section().unwrap(), first);
+        let state = reader.export_partial_state().unwrap();
+        assert_eq!(state.format(), CarFormat::V2);
+        assert_eq!(state.offset(), second.location.offset);
+        assert_eq!(state.resume_offset(), cut as u64);
+        assert_eq!(state.buffered(), &car[second.location.offset as usize..cut]);
+
+        // The fresh reader keeps its own options, and only needs the bytes after the cut
+        let mut resumed = CarReader::builder().min_read_hint(16).build();
+        resumed.import_partial_state(state.clone());
+        assert_eq!(resumed.get_format(), Some(CarFormat::V2));
+        assert_eq!(resumed.header(), expected.header());
+        assert!(matches!(
+            resumed.read_section(),
+            Err(CarReaderError::InsufficientData(offset, hint)) if offset == cut && hint >= 16
+        ));
+        resumed.receive_data(&car[cut..], cut);
+        assert_eq!(resumed.read_section().unwrap(), second);
+        assert_eq!(
+            resumed.read_section().unwrap(),
+            expected.read_section().unwrap()
+        );
+
+        // The exporting reader is untouched
+        reader.receive_data(&car[cut..], cut);
+        assert_eq!(reader.read_section().unwrap(), second);
+    }
+
+    #[test]
+    fn test_car_reader_find_inline_section() {
+        let mut reader = CarReader::from_bytes(testdata::CARV1_BASIC).unwrap();
+        reader.read_section().unwrap();
+
+        let cid = RawCid::from_hex("0155000568656c6c6f").unwrap();
+        let found = reader.find_section(&cid).unwrap();
+        assert!(found.location.is_inline());
+        assert_eq!(found.cid(), &cid);
+        assert_eq!(found.block().data(), b"hello");
+        // Without moving the reader
+        assert_eq!(
+            reader.read_section().unwrap().location,
+            testdata::CARV1_BASIC_SECTIONS[1].location()
+        );
+
+        // Even before the header is read
+        let mut reader = CarReader::new();
+        let empty = RawCid::from_hex("01550000").unwrap();
+        assert!(reader.find_section(&empty).unwrap().block().is_empty());
+    }
+
+    #[test]
+    fn test_car_reader_skip_section() {
+        for (car, fixtures) in [
+            (testdata::CARV1_BASIC, testdata::CARV1_BASIC_SECTIONS),
+            (testdata::CARV2_BASIC, testdata::CARV2_BASIC_SECTIONS),
+        ] {
+            let mut reader = CarReader::new();
+            let mut skipped = Vec::new();
+            loop {
+                let result = if reader.has_header() {
+                    reader.skip_section().map(|section| skipped.push(section))
+                } else {
+                    reader.read_header()
+                };
+                match result {
+                    Ok(()) => continue,
+                    Err(CarReaderError::InsufficientData(offset, hint)) if offset < car.len() => {
+                        let end = (offset + hint.max(1)).min(car.len());
+                        reader.receive_data(&car[offset..end], offset);
+                    }
+                    Err(_) => break,
+                }
+            }
+            assert_eq!(skipped.len(), fixtures.len());
+            for ((location, cid), fixture) in skipped.iter().zip(fixtures) {
+                assert_eq!(*location, fixture.location());
+                assert_eq!(*cid, fixture.cid());
+            }
+        }
+
+        // Duplicates are checked like with read_section
+        let car = car_with_duplicate();
+        let mut reader = CarReader::builder().strict().build();
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        assert!(reader.skip_section().is_ok());
+        assert!(reader.skip_section().is_ok());
+        assert!(matches!(
+            reader.skip_section(),
+            Err(CarReaderError::DuplicateSection(..))
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_read_section_at() {
+        for (car, fixtures) in [
+            (testdata::CARV1_BASIC, testdata::CARV1_BASIC_SECTIONS),
+            (testdata::CARV2_BASIC, testdata::CARV2_BASIC_SECTIONS),
+        ] {
+            // The sequential reads stop in the middle of the second section
+            let second = fixtures[1].location();
+            let cut = (second.offset + 10) as usize;
+            let mut reader = CarReader::new();
+            reader.receive_data(&car[..cut], 0);
+            reader.read_header().unwrap();
+            assert_eq!(
+                reader.read_section().unwrap().location,
+                fixtures[0].location()
+            );
+
+            // Random reads, in reverse order, only fed with the requested bytes
+            for fixture in fixtures.iter().rev() {
+                // Unknown length, like with the CAR v2 indexes
+                let location = SectionLocation {
+                    offset: fixture.offset,
+                    length: 0,
+                };
+                let section = loop {
+                    match reader.read_section_at(&location) {
+                        Ok(section) => break section,
+                        Err(CarReaderError::InsufficientData(offset, hint)) => {
+                            let end = (offset + hint.max(1)).min(car.len());
+                            reader.receive_data(&car[offset..end], offset);
+                        }
+                        Err(err) => panic!("Unexpected error: {:?}", err),
+                    }
+                };
+                assert_eq!(section.location, fixture.location());
+                assert_eq!(*section.cid(), fixture.cid());
+            }
+
+            // The sequential reads go on
+            assert!(matches!(
+                reader.read_section(),
+                Err(CarReaderError::InsufficientData(offset, _)) if offset == cut
+            ));
+            reader.receive_data(&car[cut..], cut);
+            assert_eq!(reader.read_section().unwrap().location, second);
+        }
+
+        // Locations out of the sections are rejected
+        let mut reader = CarReader::from_bytes(testdata::CARV2_BASIC).unwrap();
+        assert!(matches!(
+            reader.read_section_at(&SectionLocation::INLINE),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+        let index_offset = reader.header().unwrap().1.unwrap().index_offset;
+        assert!(matches!(
+            reader.read_section_at(&SectionLocation {
+                offset: index_offset,
+                length: 0
+            }),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_checkpoint() {
+        for (car, fixtures) in [
+            (testdata::CARV1_BASIC, testdata::CARV1_BASIC_SECTIONS),
+            (testdata::CARV2_BASIC, testdata::CARV2_BASIC_SECTIONS),
+        ] {
+            // Stopped in the middle of the third section
+            let third = fixtures[2].location();
+            let cut = (third.offset + 5) as usize;
+            let mut reader = CarReader::new();
+            reader.receive_data(&car[..cut], 0);
+            reader.read_header().unwrap();
+            reader.read_section().unwrap();
+            reader.read_section().unwrap();
+            let checkpoint = reader.save_state().unwrap();
+            assert_eq!(checkpoint.position(), third.offset);
+            assert_eq!(checkpoint.format(), reader.get_format().unwrap());
+
+            let decoded = ReaderCheckpoint::decode(&checkpoint.encode()).unwrap();
+            assert_eq!(decoded, checkpoint);
+            let mut resumed = CarReader::builder().strict().build();
+            resumed.restore_state(&decoded);
+            assert_eq!(resumed.get_format(), reader.get_format());
+            assert_eq!(resumed.header().unwrap().0, reader.header().unwrap().0);
+            assert!(matches!(
+                resumed.read_section(),
+                Err(CarReaderError::InsufficientData(offset, _)) if offset == third.offset as usize
+            ));
+            resumed.receive_data(&car[third.offset as usize..], third.offset as usize);
+            for fixture in &fixtures[2..] {
+                assert_eq!(resumed.read_section().unwrap().location, fixture.location());
+            }
+        }
+
+        // Nothing to save before the headers
+        assert!(matches!(
+            CarReader::new().save_state(),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+        // Malformed checkpoints are rejected
+        let reader = CarReader::from_bytes(testdata::CARV1_BASIC).unwrap();
+        let encoded = reader.save_state().unwrap().encode();
+        for truncated in 0..encoded.len() {
+            assert!(ReaderCheckpoint::decode(&encoded[..truncated]).is_err());
+        }
+        let mut other_version = encoded.clone();
+        other_version[0] = 2;
+        assert!(ReaderCheckpoint::decode(&other_version).is_err());
+        // The position must be after the header
+        let mut inside_header = encoded;
+        inside_header[3] = 10;
+        assert!(matches!(
+            ReaderCheckpoint::decode(&inside_header),
+            Err(CarReaderError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_read_section_ref() {
+        for car in [testdata::CARV1_BASIC, testdata::CARV2_BASIC] {
+            let mut expected = CarReader::from_bytes(car).unwrap();
+            // Fed in small chunks, so that the borrowed sections are released between two reads
+            let mut reader = CarReader::new();
+            let mut count = 0;
+            loop {
+                let result = if reader.has_header() {
+                    reader.read_section_ref().map(|(location, section)| {
+                        let owned = expected.read_section().unwrap();
+                        assert_eq!(location, owned.location);
+                        assert_eq!(section.to_owned(), owned.section);
+                        count += 1;
+                    })
+                } else {
+                    reader.read_header()
+                };
+                match result {
+                    Ok(()) => continue,
+                    Err(CarReaderError::InsufficientData(offset, _)) if offset < car.len() => {
+                        let end = (offset + 37).min(car.len());
+                        reader.receive_data(&car[offset..end], offset);
+                    }
+                    Err(_) => break,
+                }
+            }
+            assert!(count > 0);
+            assert!(expected.read_section().is_err());
+        }
+
+        // Duplicates are checked like with read_section
+        let car = car_with_duplicate();
+        let mut reader = CarReader::builder().strict().build();
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        assert!(reader.read_section_ref().is_ok());
+        assert!(reader.read_section_ref().is_ok());
+        assert!(matches!(
+            reader.read_section_ref(),
+            Err(CarReaderError::DuplicateSection(..))
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_buffer_capacity() {
+        let car = testdata::CARV1_BASIC;
+        let mut reader = CarReader::from_bytes(car).unwrap();
+        let capacity = reader.buffer_capacity();
+        assert!(capacity >= car.len() - 100);
+
+        // Consumed bytes are not moved, so the buffer is not shrunk while reading
+        let first = reader.read_section().unwrap();
+        assert_eq!(reader.buffer_capacity(), capacity);
+        // Shrinking keeps the unconsumed bytes
+        reader.shrink();
+        assert!(reader.buffer_capacity() < capacity);
+        assert!(
+            reader.buffer_capacity()
+                >= car.len() - (first.location.offset + first.location.length) as usize
+        );
+        assert_eq!(read_all(&mut reader).unwrap(), 7);
+        reader.shrink();
+        assert_eq!(reader.buffer_capacity(), 0);
+        assert!(reader.read_section().is_err());
+
+        // Fed in small chunks, the buffer stays around the size of a section
+        let mut reader = CarReader::new();
+        let mut offset = 0;
+        let mut count = 0;
+        loop {
+            let result = if reader.has_header() {
+                reader.read_section().map(|_| count += 1)
+            } else {
+                reader.read_header()
+            };
+            match result {
+                Ok(()) => continue,
+                Err(CarReaderError::InsufficientData(..)) if offset < car.len() => {
+                    let end = (offset + 16).min(car.len());
+                    reader.receive_data(&car[offset..end], offset);
+                    offset = end;
+                }
+                Err(_) => break,
+            }
+            assert!(reader.buffer_capacity() <= 512);
+        }
+        assert_eq!(count, 8);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_car_reader_receive_bytes() {
+        use bytes::Bytes;
+
+        for car in [testdata::CARV1_BASIC, testdata::CARV2_BASIC] {
+            let car = Bytes::from_static(car);
+            let mut expected = CarReader::from_bytes(&car).unwrap();
+            let mut reader = CarReader::new();
+            let mut shared = 0;
+            loop {
+                let result = if reader.has_header() {
+                    reader.read_section_bytes().map(|(location, section)| {
+                        let owned = expected.read_section().unwrap();
+                        assert_eq!(location, owned.location);
+                        assert_eq!(section.to_owned(), owned.section);
+                        // Sections within a single chunk point into the input
+                        let data = section.block().as_ptr_range();
+                        if car.as_ptr_range().contains(&data.start) {
+                            shared += 1;
+                        }
+                    })
+                } else {
+                    reader.read_header()
+                };
+                match result {
+                    Ok(()) => continue,
+                    Err(CarReaderError::InsufficientData(offset, _)) if offset < car.len() => {
+                        let end = (offset + 200).min(car.len());
+                        reader.receive_bytes(car.slice(offset..end), offset);
+                    }
+                    Err(_) => break,
+                }
+            }
+            assert!(shared > 0);
+            assert!(expected.read_section().is_err());
         }
     }
 }
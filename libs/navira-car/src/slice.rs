@@ -0,0 +1,312 @@
+//! Zero-copy access to CAR archives already loaded in memory
+//!
+//! When the whole archive is available as a `&[u8]` (memory-mapped file, downloaded body, embedded
+//! bytes, ...), pushing it through the buffering [CarReader] only copies it around. [CarSlice] parses the
+//! header(s) once, then reads the sections straight from the input: the yielded [SectionRef] borrow their
+//! block data from it.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::CarSlice;
+//!
+//! let car_bytes = include_bytes!("res/carv2-basic.car");
+//! let car = CarSlice::new(car_bytes).unwrap();
+//! assert_eq!(car.header().0.roots().len(), 1);
+//!
+//! let mut sections = Vec::new();
+//! for item in car.iter_sections() {
+//!     let (location, section) = item.unwrap();
+//!     println!("{} at offset {}", section.cid().to_hex(), location.offset);
+//!     sections.push(section);
+//! }
+//! assert_eq!(sections.len(), 5);
+//!
+//! // The block data points into `car_bytes`
+//! let found = car.get(sections[2].cid()).unwrap();
+//! assert_eq!(found.block().data(), sections[2].block().data());
+//! ```
+
+use std::convert::Infallible;
+use std::ops::Range;
+
+use crate::ipld::BlockSource;
+use crate::read::{CarReader, CarReaderError};
+use crate::wire::cid::{CidParsing, RawCid};
+use crate::wire::limits::Limits;
+use crate::wire::v1::{CarHeader, SectionFormatError, SectionLocation, SectionRef};
+use crate::wire::v2::{CarV2Header, Index};
+use crate::wire::varint::UnsignedVarint;
+
+/// A CAR archive (v1 or v2) held in memory, whose sections are read without copying
+#[derive(Debug, Clone)]
+pub struct CarSlice<'a> {
+    /// Bytes of the whole archive
+    bytes: &'a [u8],
+    /// CAR v1 header (the inner one, for CAR v2 archives)
+    header: CarHeader,
+    /// CAR v2 header, if any
+    v2_header: Option<CarV2Header>,
+    /// Range of the sections in the archive, from the first section to the end of the CAR v1 payload
+    payload: Range<usize>,
+    /// Full CAR v2 index, if the archive advertises one
+    index: Option<Index>,
+    /// Size limits applied to the header and the sections
+    limits: Limits,
+}
+
+impl<'a> CarSlice<'a> {
+    /// Parse the header(s) of the archive held in the given bytes
+    ///
+    /// Only the header(s), and the index of fully indexed CAR v2 archives, are parsed here: the sections
+    /// are read lazily by [CarSlice::iter_sections] and [CarSlice::get].
+    ///
+    /// ## Returns
+    /// - `Ok(CarSlice)` once the header(s) are parsed.
+    /// - `Err(CarReaderError::InsufficientData)` if the bytes are truncated before the end of the header(s).
+    /// - `Err(CarReaderError)` if the header(s) or the index are malformed, or the format is unsupported.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, CarReaderError> {
+        Self::with_limits(bytes, Limits::default())
+    }
+
+    /// Parse the header(s) of the archive held in the given bytes, with custom size limits
+    ///
+    /// See [CarSlice::new]. The limits also apply to the sections read afterwards.
+    pub fn with_limits(bytes: &'a [u8], limits: Limits) -> Result<Self, CarReaderError> {
+        // Only feed the reader the ranges it asks for, not the whole archive
+        let mut reader = CarReader::builder().limits(limits).build();
+        // Range fed last, a request within it means the header can't be completed (e.g. a CAR v2
+        // payload smaller than its CAR v1 header)
+        let mut fed = 0..0;
+        loop {
+            match reader.read_header() {
+                Ok(()) => break,
+                Err(CarReaderError::InsufficientData(offset, hint)) if offset < bytes.len() => {
+                    let end = offset.saturating_add(hint.max(1)).min(bytes.len());
+                    if fed.start <= offset && end <= fed.end {
+                        return Err(CarReaderError::InvalidFormat);
+                    }
+                    reader.receive_data(bytes.get(offset..end).unwrap_or_default(), offset);
+                    fed = offset..end;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let (header, v2_header) = reader
+            .header()
+            .map(|(header, v2_header)| (header.clone(), v2_header.cloned()))
+            .ok_or(CarReaderError::PreconditionNotMet)?;
+
+        let data_offset = v2_header
+            .as_ref()
+            .map_or(0, |v2_header| v2_header.data_offset as usize);
+        let data_end = v2_header.as_ref().map_or(bytes.len(), |v2_header| {
+            (v2_header.data_offset.saturating_add(v2_header.data_size) as usize).min(bytes.len())
+        });
+        // The sections start right after the CAR v1 header and its length prefix
+        let (header_length, varint_size) = bytes
+            .get(data_offset..)
+            .and_then(UnsignedVarint::decode)
+            .ok_or(CarReaderError::InvalidFormat)?;
+        let start = data_offset
+            .saturating_add(varint_size)
+            .saturating_add(header_length.0 as usize);
+
+        let index = match &v2_header {
+            Some(v2_header) if v2_header.characteristics.has_full_index() => {
+                let index_bytes = bytes
+                    .get(v2_header.index_offset as usize..)
+                    .ok_or(CarReaderError::InsufficientData(bytes.len(), 0))?;
+                let (index, _) =
+                    Index::decode(index_bytes).map_err(CarReaderError::InvalidIndex)?;
+                Some(index)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            bytes,
+            header,
+            v2_header,
+            payload: start.min(data_end)..data_end,
+            index,
+            limits,
+        })
+    }
+
+    /// Get the headers of the archive: the CAR v1 header, and the CAR v2 header if any
+    pub fn header(&self) -> (&CarHeader, Option<&CarV2Header>) {
+        (&self.header, self.v2_header.as_ref())
+    }
+
+    /// Get the bytes of the whole archive
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Iterate over the sections of the archive, in order, with their absolute location
+    ///
+    /// The iteration stops after the last section of the CAR v1 payload, or after the first malformed
+    /// section (reported as [CarReaderError::InvalidSectionFormat]).
+    pub fn iter_sections(
+        &self,
+    ) -> impl Iterator<Item = Result<(SectionLocation, SectionRef<'a>), CarReaderError>> + 'a {
+        let payload = self.bytes.get(self.payload.clone()).unwrap_or_default();
+        let base = self.payload.start;
+        let limits = self.limits;
+        let mut position = 0;
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed || position >= payload.len() {
+                return None;
+            }
+            let bytes = payload.get(position..).unwrap_or_default();
+            match read_section_ref(bytes, &limits) {
+                Ok((section, size)) => {
+                    let location = SectionLocation {
+                        offset: (base + position) as u64,
+                        length: size as u64,
+                    };
+                    position += size;
+                    Some(Ok((location, section)))
+                }
+                Err(err) => {
+                    failed = true;
+                    Some(Err(CarReaderError::InvalidSectionFormat(err)))
+                }
+            }
+        })
+    }
+
+    /// Get the section with the given CID
+    ///
+    /// The full CAR v2 index is used when present, otherwise the sections are scanned in order (stopping
+    /// at the first malformed one).
+    ///
+    /// ## Returns
+    /// - `Some(section)` with the first section with this CID.
+    /// - `None` if no section has this CID.
+    pub fn get(&self, cid: &RawCid) -> Option<SectionRef<'a>> {
+        if let Some(index) = &self.index {
+            let offset = index.find(cid)?;
+            let found = self
+                .v2_header
+                .as_ref()
+                .and_then(|v2_header| {
+                    self.bytes.get(
+                        (v2_header.data_offset.saturating_add(offset)) as usize..self.payload.end,
+                    )
+                })
+                .and_then(|bytes| read_section_ref(bytes, &self.limits).ok())
+                .map(|(section, _)| section);
+            match found {
+                Some(section) if section.cid() == cid => return Some(section),
+                // CIDs which only differ by their codec share the same index entry
+                _ => {}
+            }
+        }
+        self.iter_sections()
+            .map_while(Result::ok)
+            .map(|(_, section)| section)
+            .find(|section| section.cid() == cid)
+    }
+}
+
+/// The blocks of the archive, looked up with [CarSlice::get]
+impl BlockSource for CarSlice<'_> {
+    type Error = Infallible;
+
+    fn get_block(&mut self, cid: &RawCid) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.get(cid).map(|section| section.block().data().to_vec()))
+    }
+}
+
+/// Read a section from the bytes, with the strict CID parsing of the [CarReader]
+fn read_section_ref<'a>(
+    bytes: &'a [u8],
+    limits: &Limits,
+) -> Result<(SectionRef<'a>, usize), SectionFormatError> {
+    SectionRef::try_read_bytes_with(bytes, limits, CidParsing::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC, CARV2_BASIC_SECTIONS};
+
+    #[test]
+    fn test_car_slice() {
+        for (car_bytes, fixtures) in [
+            (CARV1_BASIC, CARV1_BASIC_SECTIONS),
+            (CARV2_BASIC, CARV2_BASIC_SECTIONS),
+        ] {
+            let car = CarSlice::new(car_bytes).unwrap();
+            assert!(!car.header().0.roots().is_empty());
+            let sections: Vec<_> = car.iter_sections().map(Result::unwrap).collect();
+            assert_eq!(sections.len(), fixtures.len());
+            for ((location, section), fixture) in sections.iter().zip(fixtures) {
+                assert_eq!(*location, fixture.location());
+                assert_eq!(*section.cid(), fixture.cid());
+                assert_eq!(section.block().len(), fixture.data_length);
+                // Borrowed from the input
+                let data = section.block().data().as_ptr_range();
+                assert!(car_bytes.as_ptr_range().contains(&data.start));
+            }
+
+            let found = car.get(&fixtures[3].cid()).unwrap();
+            assert_eq!(found, sections[3].1);
+            let absent = RawCid::from_hex(
+                "12200000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap();
+            assert!(car.get(&absent).is_none());
+        }
+
+        // Truncated headers and sections are reported
+        assert!(matches!(
+            CarSlice::new(&CARV1_BASIC[..50]),
+            Err(CarReaderError::InsufficientData(..))
+        ));
+        // A CAR v2 payload smaller than its CAR v1 header
+        let mut car_v2 = CARV2_BASIC.to_vec();
+        car_v2[35..43].copy_from_slice(&10u64.to_le_bytes());
+        assert!(matches!(
+            CarSlice::new(&car_v2),
+            Err(CarReaderError::InvalidFormat)
+        ));
+        let car = CarSlice::new(&CARV1_BASIC[..CARV1_BASIC.len() - 10]).unwrap();
+        let items: Vec<_> = car.iter_sections().collect();
+        assert_eq!(items.len(), CARV1_BASIC_SECTIONS.len());
+        assert!(matches!(
+            items.last(),
+            Some(Err(CarReaderError::InvalidSectionFormat(
+                SectionFormatError::InsufficientData
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_car_slice_indexed() {
+        let sections = crate::testdata::carv1_basic_sections();
+        let mut writer =
+            crate::CarWriter::new(crate::CarFormat::V2, vec![sections[0].cid().clone()]);
+        for section in &sections {
+            writer.write_section(section).unwrap();
+        }
+        writer.finish();
+        let mut car_bytes = Vec::new();
+        let mut buf = vec![0u8; 4096];
+        while writer.has_data_to_send() {
+            let (offset, length) = writer.send_data(&mut buf);
+            car_bytes.resize(car_bytes.len().max(offset + length), 0);
+            car_bytes[offset..offset + length].copy_from_slice(&buf[..length]);
+        }
+
+        let car = CarSlice::new(&car_bytes).unwrap();
+        assert!(car.index.is_some());
+        for section in &sections {
+            assert_eq!(car.get(section.cid()).unwrap().to_owned(), *section);
+        }
+        // The index is not a section
+        assert_eq!(car.iter_sections().count(), sections.len());
+    }
+}
@@ -0,0 +1,345 @@
+//! Splitting of a CAR archive into size-bounded shards
+//!
+//! Some storage backends bound the size of the objects they hold: Filecoin pieces, multipart uploads of object stores,
+//! etc. [split_car] cuts an archive into several valid CAR v1 files (the shards), each at most a given size, header
+//! included. The sections are copied as they are, in their original order, and never cut: the shards are made of
+//! consecutive runs of sections.
+//!
+//! The DAGs of the roots usually span several shards. By default, every shard lists all the roots of the archive
+//! (so that the shards can be merged back, see the `merge` module), but they can also list only the roots whose
+//! whole DAG they contain (see [ShardRoots]). The returned [SplitSummary] is a manifest of the shards: their roots,
+//! sizes and the CIDs they hold.
+//!
+//! ## Examples
+//! ```
+//! use std::io::Cursor;
+//! use navira_car::split::{SplitOptions, split_car};
+//! use navira_car::stdio::CarReader;
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let mut reader = CarReader::open(Cursor::new(&car_bytes[..])).unwrap();
+//! // The shards would usually be files, e.g. `File::create(format!("shard-{index}.car"))`
+//! let summary = split_car(&mut reader, 400, &SplitOptions::default(), |_index| {
+//!     Ok(std::io::sink())
+//! })
+//! .unwrap();
+//! assert_eq!(summary.shards.len(), 3);
+//! assert!(summary.shards.iter().all(|shard| shard.bytes_written <= 400));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, Write};
+
+use crate::ipld::block_links;
+use crate::stdio::{CarReader, CarReaderError};
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{CarWriter, CarWriterError};
+
+/// Roots listed in the header of each shard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShardRoots {
+    /// All the roots of the input archive, in every shard
+    #[default]
+    All,
+    /// The roots of the input archive whose whole DAG is in the shard (possibly none)
+    ///
+    /// The links are followed through the dag-pb and dag-cbor blocks, which requires to decode all the blocks.
+    Contained,
+}
+
+/// Options of [split_car]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitOptions {
+    /// Roots listed in the header of each shard
+    pub roots: ShardRoots,
+}
+
+/// A shard written by [split_car]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardSummary {
+    /// Roots listed in the header of the shard
+    pub roots: Vec<RawCid>,
+    /// CIDs of the sections of the shard, in order
+    pub cids: Vec<RawCid>,
+    /// Size of the shard, header included
+    pub bytes_written: u64,
+}
+
+/// Summary of a split, the manifest of the shards
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitSummary {
+    /// The shards, in order
+    pub shards: Vec<ShardSummary>,
+}
+
+/// Split a CAR archive into CAR v1 shards of at most `max_shard_bytes` bytes each
+///
+/// The archive is read twice: a first pass plans the shards (and finds the roots contained in each of them, if
+/// requested), and a second one copies the sections. The shards are filled greedily, a new one being started when
+/// the next section does not fit in the current one. An archive without any section gives a single shard.
+///
+/// # Arguments
+/// * `input` - The archive to split.
+/// * `max_shard_bytes` - The maximum size of a shard, header included.
+/// * `options` - The roots listed in the shards.
+/// * `create_shard` - Called with the index of each shard (from 0), returning where to write it.
+///
+/// # Returns
+/// * `Ok(SplitSummary)` - The manifest of the shards.
+/// * `Err(SplitError)` - The input is malformed, a section does not fit in a shard on its own, or writing a
+///   shard failed. The shards already written are left as they are.
+pub fn split_car<R, W, F>(
+    input: &mut CarReader<R>,
+    max_shard_bytes: u64,
+    options: &SplitOptions,
+    mut create_shard: F,
+) -> Result<SplitSummary, SplitError>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(usize) -> io::Result<W>,
+{
+    let roots: Vec<_> = input
+        .get_roots()
+        .iter()
+        .map(|root| root.to_raw_cid().clone())
+        .collect();
+    // Listing fewer roots can only shrink the header, so the shards are planned for the largest one
+    let max_header = header_bytes(&roots).len() as u64;
+
+    // Planning: the CIDs of each shard, and the links of the blocks if needed
+    let mut shards = vec![Vec::new()];
+    let mut links = HashMap::new();
+    let mut size = max_header;
+    for section in input.sections() {
+        let section = section?;
+        let length = section.location.length;
+        if max_header + length > max_shard_bytes {
+            return Err(SplitError::SectionTooLarge {
+                cid: section.cid().clone(),
+                length,
+            });
+        }
+        if size + length > max_shard_bytes {
+            shards.push(Vec::new());
+            size = max_header;
+        }
+        size += length;
+        if options.roots == ShardRoots::Contained {
+            let cid_links = block_links(section.cid(), section.block().data());
+            links.insert(section.cid().clone(), cid_links);
+        }
+        if let Some(shard) = shards.last_mut() {
+            shard.push(section.cid().clone());
+        }
+    }
+
+    // Copy: the sections are read again, in the same order
+    let mut summary = SplitSummary { shards: Vec::new() };
+    let mut sections = input.sections();
+    let mut buf = vec![0u8; 64 * 1024];
+    for (index, cids) in shards.into_iter().enumerate() {
+        let shard_roots = match options.roots {
+            ShardRoots::All => roots.clone(),
+            ShardRoots::Contained => contained_roots(&roots, &cids, &links),
+        };
+        let mut sink = create_shard(index)?;
+        let mut writer = CarWriter::new(shard_roots.clone());
+        let mut bytes_written = 0;
+        for _ in 0..cids.len() {
+            let section = sections.next().ok_or(CarReaderError::EndOfSections)??;
+            loop {
+                match writer.write_section(&section.section) {
+                    Ok(_) => break,
+                    Err(CarWriterError::BufferFull) if writer.has_data_to_send() => {
+                        bytes_written += drain(&mut writer, &mut sink, &mut buf)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        bytes_written += drain(&mut writer, &mut sink, &mut buf)?;
+        sink.flush()?;
+        summary.shards.push(ShardSummary {
+            roots: shard_roots,
+            cids,
+            bytes_written,
+        });
+    }
+    Ok(summary)
+}
+
+/// The roots whose whole DAG is made of the given CIDs
+fn contained_roots(
+    roots: &[RawCid],
+    cids: &[RawCid],
+    links: &HashMap<RawCid, Vec<RawCid>>,
+) -> Vec<RawCid> {
+    let present: HashSet<_> = cids.iter().collect();
+    roots
+        .iter()
+        .filter(|root| {
+            let mut visited = HashSet::new();
+            let mut stack = vec![*root];
+            while let Some(cid) = stack.pop() {
+                if cid.is_identity() || !visited.insert(cid) {
+                    continue;
+                }
+                if !present.contains(cid) {
+                    return false;
+                }
+                stack.extend(links.get(cid).into_iter().flatten());
+            }
+            true
+        })
+        .cloned()
+        .collect()
+}
+
+/// The bytes of a CAR v1 header with the given roots
+fn header_bytes(roots: &[RawCid]) -> Vec<u8> {
+    let mut writer = CarWriter::new(roots.to_vec());
+    let mut header = Vec::new();
+    let mut buf = vec![0u8; 4096];
+    while writer.has_data_to_send() {
+        let len = writer.send_data(&mut buf);
+        header.extend_from_slice(buf.get(..len).unwrap_or_default());
+    }
+    header
+}
+
+/// Move the buffered bytes of the CAR stream to the sink, returning their number
+fn drain<W: Write>(writer: &mut CarWriter, sink: &mut W, buf: &mut [u8]) -> io::Result<u64> {
+    let mut written = 0;
+    while writer.has_data_to_send() {
+        let len = writer.send_data(buf);
+        sink.write_all(buf.get(..len).unwrap_or_default())?;
+        written += len as u64;
+    }
+    Ok(written)
+}
+
+/// Errors related to the split of a CAR archive
+#[derive(thiserror::Error, Debug)]
+pub enum SplitError {
+    /// The input could not be read
+    #[error("Invalid input: {0}")]
+    Input(#[from] CarReaderError),
+    /// A section is larger than a shard (with its header)
+    #[error("Section {cid} of {length} bytes does not fit in a shard")]
+    SectionTooLarge {
+        /// CID of the section
+        cid: RawCid,
+        /// Length of the section, length prefix included
+        length: u64,
+    },
+    /// A section could not be written to a shard
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+    /// IO error while creating or writing a shard
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::testdata::{CARV1_BASIC, CARV1_BASIC_SECTIONS};
+    use crate::verify::DagVerifier;
+
+    /// A shard kept in memory, readable once the split is done
+    #[derive(Clone, Default)]
+    struct Shard(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shard {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Split the fixture, returning the manifest and the shards
+    fn split(max_shard_bytes: u64, roots: ShardRoots) -> (SplitSummary, Vec<Vec<u8>>) {
+        let mut reader = CarReader::open(Cursor::new(CARV1_BASIC)).unwrap();
+        let mut shards: Vec<Shard> = Vec::new();
+        let options = SplitOptions { roots };
+        let summary = split_car(&mut reader, max_shard_bytes, &options, |index| {
+            assert_eq!(index, shards.len());
+            shards.push(Shard::default());
+            Ok(shards[index].clone())
+        })
+        .unwrap();
+        let shards = shards.into_iter().map(|shard| shard.0.take()).collect();
+        (summary, shards)
+    }
+
+    #[test]
+    fn test_split_car() {
+        for max in [300, 400, 715, 1000] {
+            let (summary, shards) = split(max, ShardRoots::All);
+            let mut cids = Vec::new();
+            for (manifest, shard) in summary.shards.iter().zip(&shards) {
+                assert!(shard.len() as u64 <= max);
+                assert_eq!(manifest.bytes_written, shard.len() as u64);
+                let mut reader = CarReader::open(Cursor::new(shard)).unwrap();
+                assert_eq!(reader.get_roots().len(), 2);
+                let shard_cids: Vec<_> = reader
+                    .sections()
+                    .map(|section| section.unwrap().cid().clone())
+                    .collect();
+                assert_eq!(shard_cids, manifest.cids);
+                cids.extend(shard_cids);
+            }
+            // All the sections, in order
+            let expected: Vec<_> = CARV1_BASIC_SECTIONS.iter().map(|s| s.cid()).collect();
+            assert_eq!(cids, expected);
+            assert_eq!(summary.shards.len() == 1, max >= 715, "{max}");
+        }
+
+        // The header (100 bytes) and the largest section (133 bytes) must fit
+        let mut reader = CarReader::open(Cursor::new(CARV1_BASIC)).unwrap();
+        let err = split_car(&mut reader, 200, &SplitOptions::default(), |_| {
+            Ok(Vec::new())
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SplitError::SectionTooLarge { length: 133, .. }
+        ));
+    }
+
+    #[test]
+    fn test_split_contained_roots() {
+        let (summary, _) = split(715, ShardRoots::Contained);
+        assert_eq!(summary.shards[0].roots.len(), 2);
+
+        let (summary, shards) = split(400, ShardRoots::Contained);
+        let mut listed = 0;
+        for (manifest, shard) in summary.shards.iter().zip(&shards) {
+            let mut reader = CarReader::open(Cursor::new(shard)).unwrap();
+            let roots: Vec<_> = reader
+                .get_roots()
+                .iter()
+                .map(|r| r.to_raw_cid().clone())
+                .collect();
+            assert_eq!(roots, manifest.roots);
+            // The listed roots are complete in their shard
+            let mut verifier = DagVerifier::new(roots);
+            for section in reader.sections() {
+                let section = section.unwrap();
+                verifier.add_block(section.cid(), section.block().data());
+            }
+            assert!(verifier.verify().missing.is_empty());
+            listed += manifest.roots.len();
+        }
+        // The first root links to blocks of every shard, so it is not listed
+        assert!(listed < 2);
+    }
+}
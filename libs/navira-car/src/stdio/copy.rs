@@ -0,0 +1,668 @@
+//! Hash-verified copies of CAR archives, resumable after an interruption
+//!
+//! [copy] streams the sections of a source archive (CARv1 or CARv2) to a CARv1 destination, verifying each
+//! block against its CID on the way. Every [CopyOptions::checkpoint_interval] bytes, the destination is flushed
+//! and a [CopyToken] is handed to a checkpoint callback: the offset of the next section in the source, the
+//! length of the destination written so far, and the number of copied sections. Given the last persisted token,
+//! an interrupted copy restarts from there, seeking the source past the already copied sections.
+//!
+//! [copy_file] wires it all for files: the token is persisted next to the destination, the destination is
+//! truncated back to the token on resumption, and the token is removed once the copy completes.
+//!
+//! ## Examples
+//! ```
+//! use std::io::Cursor;
+//! use navira_car::stdio::{CopyOptions, CopyToken, copy};
+//! use navira_car::testdata::CARV2_BASIC;
+//!
+//! let mut tokens = Vec::new();
+//! let options = CopyOptions { checkpoint_interval: 64, ..CopyOptions::default() };
+//! let destination = Cursor::new(Vec::new());
+//! let checkpoint = |_: &mut _, token: &CopyToken| {
+//!     tokens.push(*token);
+//!     Ok(())
+//! };
+//! let summary = copy(Cursor::new(CARV2_BASIC), destination, None, &options, checkpoint).unwrap();
+//! assert_eq!(summary.sections, 5);
+//! assert!(!tokens.is_empty());
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    CarReader as SansIoCarReader, CarReaderError as SansIoCarReaderError,
+    verify::{DigestComparison, DigestError, verify_block},
+    wire::{
+        limits::MAX_SECTION_SIZE,
+        v1::{self, LocatableSection, SectionLocation},
+    },
+};
+
+use super::read::{CarReaderError, map_underlying_error};
+
+/// Magic bytes starting a serialized [CopyToken]
+const TOKEN_MAGIC: &[u8; 8] = b"NVRCOPY1";
+
+/// Length of a serialized [CopyToken]
+const TOKEN_LENGTH: usize = TOKEN_MAGIC.len() + 3 * 8;
+
+/// Progress of a copy, from which it can be resumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyToken {
+    /// Offset in the source of the next section to copy
+    pub source_offset: u64,
+    /// Length of the destination holding the header and the copied sections
+    pub destination_offset: u64,
+    /// Number of sections copied
+    pub sections: u64,
+}
+
+impl CopyToken {
+    /// Serialize the token into a fixed-size record
+    pub fn to_bytes(&self) -> [u8; TOKEN_LENGTH] {
+        let mut bytes = [0u8; TOKEN_LENGTH];
+        bytes[..8].copy_from_slice(TOKEN_MAGIC);
+        bytes[8..16].copy_from_slice(&self.source_offset.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.destination_offset.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.sections.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a token from the record written by [CopyToken::to_bytes]
+    ///
+    /// # Returns
+    ///
+    /// * `Some(CopyToken)`, if the record is valid.
+    /// * `None`, if it has not the expected length or magic bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != TOKEN_LENGTH || !bytes.starts_with(TOKEN_MAGIC) {
+            return None;
+        }
+        let field = |i: usize| {
+            bytes
+                .get(i..)
+                .and_then(<[u8]>::first_chunk::<8>)
+                .map(|field| u64::from_le_bytes(*field))
+        };
+        Some(Self {
+            source_offset: field(8)?,
+            destination_offset: field(16)?,
+            sections: field(24)?,
+        })
+    }
+
+    /// Load the token persisted at `path`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(CopyToken))`, if a valid token is persisted.
+    /// * `Ok(None)`, if there is no token file.
+    /// * `Err(io::Error)`, if it cannot be read, or is not a token ([io::ErrorKind::InvalidData]).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Self::from_bytes(&bytes)
+                .map(Some)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid copy token")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Persist the token at `path`
+    ///
+    /// The token is written to a temporary file which replaces `path` once synced, so a crash never leaves
+    /// a torn token behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = suffixed(path, ".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// Options of a copy
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    /// Number of destination bytes written between two checkpoints (default: 64 MiB)
+    pub checkpoint_interval: u64,
+    /// Strategy used to compare the digests of the blocks with their CIDs
+    pub digest_comparison: DigestComparison,
+    /// Size of the reads from the source, and of the writing buffer (default: 4 MiB)
+    ///
+    /// The writing buffer always holds at least the largest section allowed by the [Limits](crate::Limits).
+    pub buffer_size: usize,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            checkpoint_interval: 64 * 1024 * 1024,
+            digest_comparison: DigestComparison::default(),
+            buffer_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// Summary of a completed copy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopySummary {
+    /// Total number of sections copied, including those copied before a resumption
+    pub sections: u64,
+    /// Length of the destination
+    pub bytes: u64,
+    /// Token the copy was resumed from, if any
+    pub resumed_from: Option<CopyToken>,
+}
+
+/// Errors related to the copy of CAR archives
+#[derive(thiserror::Error, Debug)]
+pub enum CopyError {
+    /// The source could not be read
+    #[error("Cannot read the source: {0}")]
+    Reader(CarReaderError),
+    /// A section could not be written to the destination
+    #[error("Cannot write section: {0}")]
+    Writer(v1::CarWriterError),
+    /// A block of the source does not match its CID
+    #[error("Invalid section at offset {offset}: {error}", offset = .0, error = .1)]
+    Digest(u64, DigestError),
+    /// The source ends in the middle of the section at this offset
+    #[error("Source truncated in the section at offset {0}")]
+    Truncated(u64),
+    /// The resume token does not match the source or the destination
+    #[error("Resume token does not match the copy: {0}")]
+    TokenMismatch(&'static str),
+    /// I/O error occurred while reading the source or writing the destination
+    #[error("I/O error occurred during the copy: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<CarReaderError> for CopyError {
+    fn from(err: CarReaderError) -> Self {
+        match err {
+            CarReaderError::Io(e) => CopyError::Io(e),
+            err => CopyError::Reader(err),
+        }
+    }
+}
+
+/// Copy the sections of the `source` archive to a CARv1 `destination`, verifying every block
+///
+/// The destination keeps the roots of the source. It is expected to be empty for a new copy, and to hold
+/// (at least) the bytes written up to the token when resuming: the copy restarts at `resume.destination_offset`,
+/// overwriting anything written after it.
+/// The `checkpoint` callback is called every [CopyOptions::checkpoint_interval] bytes, once the destination is
+/// flushed, with the token to persist. An error of the callback interrupts the copy.
+///
+/// Verifying sha2-256 blocks requires the `hashing` feature.
+///
+/// # Returns
+///
+/// * `Ok(CopySummary)`, once all the sections are copied.
+/// * `Err(CopyError::Digest)`, if a block does not match its CID, nothing past the previous section is written.
+/// * `Err(CopyError::TokenMismatch)`, if the resume token does not point to a section of the source.
+/// * `Err(CopyError)`, otherwise, if the source cannot be read or the destination written.
+pub fn copy<R, W, C>(
+    source: R,
+    mut destination: W,
+    resume: Option<CopyToken>,
+    options: &CopyOptions,
+    mut checkpoint: C,
+) -> Result<CopySummary, CopyError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    C: FnMut(&mut W, &CopyToken) -> io::Result<()>,
+{
+    let mut source = SourceReader::open(source, options.buffer_size)?;
+    let roots = source
+        .inner
+        .header()
+        .map(|(header, _)| header.roots().iter().map(|r| r.to_raw_cid().clone()))
+        .ok_or(CopyError::Reader(CarReaderError::InvalidFormat))?
+        .collect();
+    let buffer_size = options.buffer_size.max(MAX_SECTION_SIZE + 16);
+    let mut writer = v1::CarWriter::with_buffer_size(roots, buffer_size);
+    let mut output = Output {
+        chunk: vec![0u8; buffer_size],
+        offset: 0,
+    };
+
+    let mut token = CopyToken {
+        source_offset: 0,
+        destination_offset: 0,
+        sections: 0,
+    };
+    match resume {
+        Some(resume) => {
+            // The destination already holds the header (which only depends on the roots): skip it
+            let header_length = output.discard(&mut writer);
+            if resume.destination_offset < header_length {
+                return Err(CopyError::TokenMismatch(
+                    "destination offset inside the header",
+                ));
+            }
+            let location = SectionLocation {
+                offset: resume.source_offset,
+                length: 0,
+            };
+            source
+                .inner
+                .seek_to(&location)
+                .map_err(|_| CopyError::TokenMismatch("source offset outside the sections"))?;
+            destination.seek(SeekFrom::Start(resume.destination_offset))?;
+            output.offset = resume.destination_offset;
+            source.next_offset = resume.source_offset;
+            token = resume;
+        }
+        None => {
+            source
+                .inner
+                .seek_first_section()
+                .map_err(map_underlying_error)?;
+            source.next_offset = source.first_section_offset()?;
+        }
+    }
+
+    let mut since_checkpoint = 0;
+    while let Some(section) = source.next_section()? {
+        verify_block(
+            section.cid(),
+            section.block().data(),
+            options.digest_comparison,
+        )
+        .map_err(|e| CopyError::Digest(section.location.offset, e))?;
+        if let Err(v1::CarWriterError::BufferFull) = writer.write_section(&section) {
+            output.flush(&mut writer, &mut destination)?;
+            writer.write_section(&section).map_err(CopyError::Writer)?;
+        }
+        token.sections += 1;
+        token.source_offset = section.location.offset + section.location.length;
+
+        since_checkpoint += section.total_length() as u64;
+        if since_checkpoint >= options.checkpoint_interval {
+            output.flush(&mut writer, &mut destination)?;
+            destination.flush()?;
+            token.destination_offset = output.offset;
+            checkpoint(&mut destination, &token)?;
+            since_checkpoint = 0;
+        }
+    }
+    output.flush(&mut writer, &mut destination)?;
+    destination.flush()?;
+
+    Ok(CopySummary {
+        sections: token.sections,
+        bytes: output.offset,
+        resumed_from: resume,
+    })
+}
+
+/// Copy the CAR file at `source` to a CARv1 file at `destination`, resuming the previous copy if interrupted
+///
+/// The progress is persisted in a token file, named after the destination with a `.copy` suffix, at every
+/// checkpoint (once the destination is synced to disk). If the token exists, the destination is truncated to it
+/// and the copy resumes; the token is removed once the copy completes. See [copy] for the verifications.
+///
+/// # Returns
+///
+/// * `Ok(CopySummary)`, once the destination is complete and synced.
+/// * `Err(CopyError::TokenMismatch)`, if the destination does not match the token (e.g. it was modified since).
+/// * `Err(CopyError)`, otherwise, see [copy].
+pub fn copy_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    destination: Q,
+    options: &CopyOptions,
+) -> Result<CopySummary, CopyError> {
+    let token_path = copy_token_path(&destination);
+    let resume = CopyToken::load(&token_path)?;
+    let source = File::open(source)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(resume.is_none())
+        .open(destination)?;
+    if let Some(token) = &resume {
+        if file.metadata()?.len() < token.destination_offset {
+            return Err(CopyError::TokenMismatch(
+                "destination shorter than the token",
+            ));
+        }
+        file.set_len(token.destination_offset)?;
+    }
+
+    let mut destination = io::BufWriter::new(file);
+    let checkpoint = |destination: &mut &mut io::BufWriter<File>, token: &CopyToken| {
+        // The token must never point past the bytes durably written
+        destination.get_ref().sync_data()?;
+        token.save(&token_path)
+    };
+    let summary = copy(source, &mut destination, resume, options, checkpoint)?;
+    let file = destination.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    match std::fs::remove_file(&token_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(summary),
+    }
+}
+
+/// Path of the token file of a copy to `destination`, the destination path with a `.copy` suffix
+pub fn copy_token_path<P: AsRef<Path>>(destination: P) -> PathBuf {
+    suffixed(destination.as_ref(), ".copy")
+}
+
+/// Append a suffix to the file name of the path
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Destination side of a copy, tracking the offset of the bytes sent
+struct Output {
+    chunk: Vec<u8>,
+    /// Offset in the destination of the next byte to write
+    offset: u64,
+}
+
+impl Output {
+    /// Write all the bytes buffered by the writer to the destination
+    fn flush<W: Write>(
+        &mut self,
+        writer: &mut v1::CarWriter,
+        destination: &mut W,
+    ) -> io::Result<()> {
+        while writer.has_data_to_send() {
+            let length = writer.send_data(&mut self.chunk);
+            destination.write_all(self.chunk.get(..length).unwrap_or_default())?;
+            self.offset += length as u64;
+        }
+        Ok(())
+    }
+
+    /// Drop the bytes buffered by the writer, returning their length
+    fn discard(&mut self, writer: &mut v1::CarWriter) -> u64 {
+        let mut length = 0;
+        while writer.has_data_to_send() {
+            length += writer.send_data(&mut self.chunk) as u64;
+        }
+        length
+    }
+}
+
+/// Feeds a sans-IO reader from a seekable source, noticing truncated sections
+struct SourceReader<R: Read + Seek> {
+    inner: SansIoCarReader,
+    source: R,
+    buffer: Vec<u8>,
+    /// Offset of the section following the last one read
+    next_offset: u64,
+    /// Offset requested by the inner reader when the end of the source was reached
+    eof_offset: u64,
+}
+
+impl<R: Read + Seek> SourceReader<R> {
+    /// Read the header of the source
+    fn open(source: R, read_size: usize) -> Result<Self, CopyError> {
+        let mut reader = Self {
+            inner: SansIoCarReader::with_min_read_hint(read_size),
+            source,
+            buffer: Vec::new(),
+            next_offset: 0,
+            eof_offset: 0,
+        };
+        loop {
+            match reader.inner.read_header() {
+                Ok(()) => return Ok(reader),
+                Err(e) => {
+                    if !reader.feed(e)? {
+                        return Err(CopyError::Truncated(0));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Offset of the first section of the source, the reader being positioned on the first section
+    fn first_section_offset(&mut self) -> Result<u64, CopyError> {
+        match self.inner.read_section() {
+            // Already buffered along with the header: rewind to it
+            Ok(section) => {
+                self.inner
+                    .seek_to(&section.location)
+                    .map_err(map_underlying_error)?;
+                Ok(section.location.offset)
+            }
+            Err(SansIoCarReaderError::InsufficientData(offset, _)) => Ok(offset as u64),
+            _ => Ok(0),
+        }
+    }
+
+    /// Read the next section of the source
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(LocatableSection))`, if a section was read.
+    /// * `Ok(None)`, at the end of the sections.
+    /// * `Err(CopyError::Truncated)`, if the source ends in the middle of a section.
+    fn next_section(&mut self) -> Result<Option<LocatableSection>, CopyError> {
+        loop {
+            match self.inner.read_section() {
+                Ok(section) => {
+                    self.next_offset = section.location.offset + section.location.length;
+                    return Ok(Some(section));
+                }
+                Err(SansIoCarReaderError::EndOfSections) => return Ok(None),
+                Err(e) => {
+                    // Ending right after a section is the end of a CARv1 payload
+                    if !self.feed(e)? {
+                        return match self.eof_offset == self.next_offset {
+                            true => Ok(None),
+                            false => Err(CopyError::Truncated(self.next_offset)),
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Feed the inner reader with the data it requested, or map its error
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)`, if data was fed.
+    /// * `Ok(false)`, at the end of the source.
+    /// * `Err(CopyError)`, otherwise.
+    fn feed(&mut self, err: SansIoCarReaderError) -> Result<bool, CopyError> {
+        let SansIoCarReaderError::InsufficientData(offset, hint) = err else {
+            return Err(map_underlying_error(err).into());
+        };
+        if self.buffer.len() < hint {
+            self.buffer.resize(hint, 0);
+        }
+        self.source.seek(SeekFrom::Start(offset as u64))?;
+        let bytes_read = loop {
+            match self
+                .source
+                .read(self.buffer.get_mut(..hint).unwrap_or_default())
+            {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        if bytes_read == 0 {
+            self.eof_offset = offset as u64;
+            return Ok(false);
+        }
+        self.inner
+            .receive_data(self.buffer.get(..bytes_read).unwrap_or_default(), offset);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC, CARV2_BASIC_SECTIONS};
+    use std::io::Cursor;
+
+    fn sections(car: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut reader = crate::stdio::CarReader::open(Cursor::new(car)).unwrap();
+        reader
+            .sections()
+            .map(|s| {
+                let s = s.unwrap();
+                (s.cid().bytes().to_vec(), s.block().data().to_vec())
+            })
+            .collect()
+    }
+
+    fn copy_all(source: &[u8]) -> Vec<u8> {
+        let mut destination = Cursor::new(Vec::new());
+        let options = CopyOptions::default();
+        copy(
+            Cursor::new(source),
+            &mut destination,
+            None,
+            &options,
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        destination.into_inner()
+    }
+
+    #[test]
+    fn test_copy() {
+        let copied = copy_all(CARV2_BASIC);
+        assert_eq!(sections(&copied), sections(CARV2_BASIC));
+        assert_eq!(sections(&copied).len(), CARV2_BASIC_SECTIONS.len());
+        let copied = copy_all(CARV1_BASIC);
+        assert_eq!(sections(&copied), sections(CARV1_BASIC));
+        assert_eq!(sections(&copied).len(), CARV1_BASIC_SECTIONS.len());
+    }
+
+    #[test]
+    fn test_copy_resume() {
+        let expected = copy_all(CARV1_BASIC);
+        let options = CopyOptions {
+            checkpoint_interval: 100,
+            ..CopyOptions::default()
+        };
+
+        // Interrupt the copy at its second checkpoint, after it persisted the first one
+        let mut destination = Cursor::new(Vec::new());
+        let mut tokens = Vec::new();
+        let interrupted = copy(
+            Cursor::new(CARV1_BASIC),
+            &mut destination,
+            None,
+            &options,
+            |_, token| {
+                tokens.push(*token);
+                match tokens.len() {
+                    1 => Ok(()),
+                    _ => Err(io::Error::other("interrupted")),
+                }
+            },
+        );
+        assert!(matches!(interrupted, Err(CopyError::Io(_))));
+        let token = tokens[0];
+        assert!(token.sections > 0 && token.sections < CARV1_BASIC_SECTIONS.len() as u64);
+        assert_eq!(CopyToken::from_bytes(&token.to_bytes()), Some(token));
+
+        // Simulate the loss of the bytes written after the checkpoint
+        let mut written = destination.into_inner();
+        written.truncate(token.destination_offset as usize);
+        written.extend_from_slice(b"garbage");
+        let mut destination = Cursor::new(written);
+        let summary = copy(
+            Cursor::new(CARV1_BASIC),
+            &mut destination,
+            Some(token),
+            &options,
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        assert_eq!(summary.sections, CARV1_BASIC_SECTIONS.len() as u64);
+        assert_eq!(summary.resumed_from, Some(token));
+        let mut written = destination.into_inner();
+        written.truncate(summary.bytes as usize);
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_copy_file_resume() {
+        let dir = std::env::temp_dir().join(format!("navira-copy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.car");
+        let destination = dir.join("destination.car");
+        std::fs::write(&source, CARV2_BASIC).unwrap();
+
+        // A copy interrupted after its first checkpoint left its token and some extra bytes behind
+        let options = CopyOptions {
+            checkpoint_interval: 1,
+            ..CopyOptions::default()
+        };
+        let mut partial = Cursor::new(Vec::new());
+        let mut token = None;
+        let _ = copy(
+            Cursor::new(CARV2_BASIC),
+            &mut partial,
+            None,
+            &options,
+            |_, t| {
+                token = Some(*t);
+                Err(io::Error::other("interrupted"))
+            },
+        );
+        let token = token.unwrap();
+        let mut partial = partial.into_inner();
+        partial.extend_from_slice(&[0xff; 10]);
+        std::fs::write(&destination, &partial).unwrap();
+        token.save(copy_token_path(&destination)).unwrap();
+
+        let summary = copy_file(&source, &destination, &options).unwrap();
+        assert_eq!(summary.resumed_from, Some(token));
+        assert!(!copy_token_path(&destination).exists());
+        assert_eq!(std::fs::read(&destination).unwrap(), copy_all(CARV2_BASIC));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_errors() {
+        // A corrupted block is never written
+        let mut corrupted = CARV1_BASIC.to_vec();
+        let location = CARV1_BASIC_SECTIONS[6].location();
+        let last = (location.offset + location.length - 1) as usize;
+        corrupted[last] ^= 0xff;
+        let mut destination = Cursor::new(Vec::new());
+        let options = CopyOptions::default();
+        let result = copy(
+            Cursor::new(&corrupted),
+            &mut destination,
+            None,
+            &options,
+            |_, _| Ok(()),
+        );
+        assert!(matches!(result, Err(CopyError::Digest(offset, _)) if offset == location.offset));
+        assert!(destination.get_ref().len() as u64 <= location.offset);
+
+        // A truncated source is reported, rather than copied partially
+        let truncated = &CARV1_BASIC[..last];
+        let result = copy(
+            Cursor::new(truncated),
+            Cursor::new(Vec::new()),
+            None,
+            &options,
+            |_, _| Ok(()),
+        );
+        assert!(matches!(result, Err(CopyError::Truncated(offset)) if offset == location.offset));
+    }
+}
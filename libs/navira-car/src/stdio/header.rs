@@ -0,0 +1,358 @@
+//! Rewriting the roots of existing CARv1 files
+//!
+//! Pinning services frequently need to adjust the declared roots of a CAR file after the fact. Rewriting the
+//! whole file is expensive for large archives, so the header is rewritten in place whenever the new header has
+//! the same encoded length as the old one. Otherwise, if padding is allowed, the new header is padded to the old
+//! length by encoding its CBOR heads (map, array and `version` integer) on more bytes than necessary; such headers
+//! are valid CBOR but not canonical DAG-CBOR, so strict decoders may reject them. As a last resort, the file is
+//! rewritten in a streaming fashion (new header, then the sections copied as is).
+//!
+//! In all cases, the entries of the old header other than `version` and `roots` (e.g. vendor extensions) are kept.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::wire::{
+    cid::RawCid,
+    limits::MAX_HEADER_SIZE,
+    v1::{CarHeader, encode_header},
+    varint::UnsignedVarint,
+};
+
+/// Width (in bytes following the initial byte) of the CBOR heads, `0` meaning the value is inlined
+const HEAD_WIDTHS: [usize; 5] = [0, 1, 2, 4, 8];
+
+/// How the roots of a CAR file were rewritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootsRewrite {
+    /// The new header had the same length, and was written in place
+    InPlace,
+    /// The new header was padded to the old length (by the given number of bytes), and written in place
+    Padded(usize),
+    /// The whole file was rewritten with the new header
+    Streamed,
+}
+
+/// Errors related to the rewriting of a CAR header
+#[derive(thiserror::Error, Debug)]
+pub enum HeaderRewriteError {
+    /// I/O error while reading or writing the CAR file
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The existing header could not be decoded
+    #[error("Invalid CAR header: {0}")]
+    InvalidHeader(String),
+    /// The file is not a CARv1 file (e.g. a CARv2 file, whose inner header is not rewritten)
+    #[error("Unsupported CAR version: {0}")]
+    UnsupportedVersion(u64),
+}
+
+/// Rewrite the roots of a CARv1 file in place, if the new header fits the footprint of the old one
+///
+/// # Arguments
+/// * `file` - The CARv1 file, positioned anywhere
+/// * `roots` - The new roots
+/// * `allow_padding` - Whether a shorter header may be padded to the old length (see the [module](self) documentation)
+///
+/// # Returns
+/// * `Ok(Some(RootsRewrite))` - The header was rewritten in place
+/// * `Ok(None)` - The new header does not fit, the file is untouched
+/// * `Err(HeaderRewriteError)` - The old header could not be read, or the new one written
+pub fn rewrite_roots_in_place<F: Read + Write + Seek>(
+    file: &mut F,
+    roots: &[RawCid],
+    allow_padding: bool,
+) -> Result<Option<RootsRewrite>, HeaderRewriteError> {
+    file.seek(SeekFrom::Start(0))?;
+    let old = read_header_length(file)?;
+    let minimal = encode_header(roots.iter(), 1, old.header.extra(), [0; 3]);
+    let (header, rewrite) = if minimal.len() == old.cbor_length {
+        (minimal, RootsRewrite::InPlace)
+    } else if allow_padding && minimal.len() < old.cbor_length {
+        let Some(header) = pad_header(roots, old.header.extra(), old.cbor_length) else {
+            return Ok(None);
+        };
+        (
+            header,
+            RootsRewrite::Padded(old.cbor_length - minimal.len()),
+        )
+    } else {
+        return Ok(None);
+    };
+    // Same CBOR length, hence the same length prefix
+    file.seek(SeekFrom::Start(old.prefix_length as u64))?;
+    file.write_all(&header)?;
+    file.flush()?;
+    Ok(Some(rewrite))
+}
+
+/// Copy a CARv1 file to the sink, replacing its roots
+///
+/// The sections are copied as is, after the new (canonical) header. The extra entries of the old header are kept.
+///
+/// # Returns
+/// * `Ok(u64)` - Number of bytes written to the sink
+/// * `Err(HeaderRewriteError)` - The old header could not be read, or the copy failed
+pub fn copy_with_roots<R: Read, W: Write>(
+    source: &mut R,
+    sink: &mut W,
+    roots: &[RawCid],
+) -> Result<u64, HeaderRewriteError> {
+    let old = read_header_length(source)?;
+    let header = encode_header(roots.iter(), 1, old.header.extra(), [0; 3]);
+    let prefix = UnsignedVarint(header.len() as u64).encode();
+    sink.write_all(&prefix)?;
+    sink.write_all(&header)?;
+    let copied = io::copy(source, sink)?;
+    Ok(prefix.len() as u64 + header.len() as u64 + copied)
+}
+
+/// Rewrite the roots of a CARv1 file, in place if possible, otherwise by rewriting the whole file
+///
+/// The streamed rewrite goes through a temporary file next to the CAR file, which then replaces it
+/// atomically: a crash never leaves a truncated CAR file.
+///
+/// See [rewrite_roots_in_place] for the `allow_padding` argument.
+pub fn rewrite_roots<P: AsRef<Path>>(
+    path: P,
+    roots: &[RawCid],
+    allow_padding: bool,
+) -> Result<RootsRewrite, HeaderRewriteError> {
+    let path = path.as_ref();
+    let mut file = File::options().read(true).write(true).open(path)?;
+    if let Some(rewrite) = rewrite_roots_in_place(&mut file, roots, allow_padding)? {
+        file.sync_all()?;
+        return Ok(rewrite);
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    let result = (|| {
+        let mut sink = BufWriter::new(File::create(&tmp)?);
+        copy_with_roots(&mut BufReader::new(file), &mut sink, roots)?;
+        sink.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(RootsRewrite::Streamed)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Version field of a header, common to the CARv1 header and the CARv2 pragma
+#[derive(Deserialize)]
+struct HeaderVersion {
+    version: u64,
+}
+
+/// The existing header, and its length
+struct HeaderLength {
+    /// The decoded header
+    header: CarHeader,
+    /// Length of the varint length prefix
+    prefix_length: usize,
+    /// Length of the CBOR header
+    cbor_length: usize,
+}
+
+/// Read the header at the current position, checking it is a CARv1 header
+///
+/// The reader is left positioned right after the header.
+fn read_header_length<R: Read>(reader: &mut R) -> Result<HeaderLength, HeaderRewriteError> {
+    let mut prefix = Vec::with_capacity(10);
+    let (length, prefix_length) = loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        prefix.push(byte[0]);
+        if let Some((UnsignedVarint(length), n)) = UnsignedVarint::decode(&prefix) {
+            break (length, n);
+        }
+        if prefix.len() >= 10 {
+            return Err(HeaderRewriteError::InvalidHeader(
+                "invalid length prefix".to_owned(),
+            ));
+        }
+    };
+    // Checked before allocating the header buffer, like the readers do
+    if length > MAX_HEADER_SIZE as u64 {
+        return Err(HeaderRewriteError::InvalidHeader(format!(
+            "header length {} exceeds {} bytes",
+            length, MAX_HEADER_SIZE
+        )));
+    }
+    let mut cbor = vec![0u8; length as usize];
+    reader.read_exact(&mut cbor)?;
+    // The version is checked first, as the CARv2 pragma has no roots
+    let invalid =
+        |e: ciborium::de::Error<io::Error>| HeaderRewriteError::InvalidHeader(e.to_string());
+    let pragma: HeaderVersion = ciborium::de::from_reader(cbor.as_slice()).map_err(invalid)?;
+    if pragma.version != 1 {
+        return Err(HeaderRewriteError::UnsupportedVersion(pragma.version));
+    }
+    let header = ciborium::de::from_reader(cbor.as_slice()).map_err(invalid)?;
+    Ok(HeaderLength {
+        header,
+        prefix_length,
+        cbor_length: cbor.len(),
+    })
+}
+
+/// Find the head widths padding the header to exactly `length` bytes, preferring the fewest padded heads
+fn pad_header(
+    roots: &[RawCid],
+    extra: &BTreeMap<String, Vec<u8>>,
+    length: usize,
+) -> Option<Vec<u8>> {
+    let mut candidates: Vec<[usize; 3]> = HEAD_WIDTHS
+        .iter()
+        .flat_map(|&map| {
+            HEAD_WIDTHS.iter().flat_map(move |&array| {
+                HEAD_WIDTHS
+                    .iter()
+                    .map(move |&version| [map, array, version])
+            })
+        })
+        .collect();
+    candidates.sort_by_key(|widths| widths.iter().filter(|&&w| w > 0).count());
+    candidates
+        .into_iter()
+        .map(|widths| encode_header(roots.iter(), 1, extra, widths))
+        .find(|header| header.len() == length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata;
+    use crate::wire::cid::RawLink;
+    use std::io::Cursor;
+
+    fn roots(file: &[u8]) -> Vec<RawLink> {
+        let mut reader = crate::stdio::CarReader::open(Cursor::new(file)).unwrap();
+        let roots = reader.get_roots().to_vec();
+        assert_eq!(
+            reader.sections().count(),
+            testdata::CARV1_BASIC_SECTIONS.len()
+        );
+        roots
+    }
+
+    #[test]
+    fn test_rewrite_roots_in_place() {
+        let aaaa = testdata::CARV1_BASIC_SECTIONS[6].cid().clone();
+        let cccc = testdata::CARV1_BASIC_SECTIONS[2].cid().clone();
+        let original_roots = roots(testdata::CARV1_BASIC);
+
+        // A CIDv0 root is 2 bytes shorter than the original CIDv1 root: padding is needed
+        let cidv0 = testdata::CARV1_BASIC_SECTIONS[1].cid().clone();
+        let shorter = [original_roots[0].to_raw_cid().clone(), cidv0.clone()];
+        let mut file = Cursor::new(testdata::CARV1_BASIC.to_vec());
+        assert_eq!(
+            rewrite_roots_in_place(&mut file, &shorter, false).unwrap(),
+            None
+        );
+        assert_eq!(file.get_ref().as_slice(), testdata::CARV1_BASIC);
+        assert_eq!(
+            rewrite_roots_in_place(&mut file, &shorter, true).unwrap(),
+            Some(RootsRewrite::Padded(2))
+        );
+        assert_eq!(file.get_ref().len(), testdata::CARV1_BASIC.len());
+        assert_eq!(roots(file.get_ref())[1], RawLink::new(cidv0));
+
+        // A single root leaves more room than the padding can fill
+        let mut file = Cursor::new(testdata::CARV1_BASIC.to_vec());
+        let single = std::slice::from_ref(&aaaa);
+        assert_eq!(
+            rewrite_roots_in_place(&mut file, single, true).unwrap(),
+            None
+        );
+
+        // Same number of roots, of the same lengths: rewritten as is
+        let swapped: Vec<RawCid> = original_roots
+            .iter()
+            .rev()
+            .map(|root| root.to_raw_cid().clone())
+            .collect();
+        let mut file = Cursor::new(testdata::CARV1_BASIC.to_vec());
+        assert_eq!(
+            rewrite_roots_in_place(&mut file, &swapped, false).unwrap(),
+            Some(RootsRewrite::InPlace)
+        );
+        assert_eq!(roots(file.get_ref())[0], original_roots[1]);
+
+        // Larger header: does not fit
+        let mut file = Cursor::new(testdata::CARV1_BASIC.to_vec());
+        let more = [aaaa.clone(), cccc.clone(), aaaa.clone()];
+        assert_eq!(
+            rewrite_roots_in_place(&mut file, &more, true).unwrap(),
+            None
+        );
+
+        // But can be streamed
+        let mut sink = Vec::new();
+        copy_with_roots(&mut Cursor::new(testdata::CARV1_BASIC), &mut sink, &more).unwrap();
+        assert_eq!(roots(&sink).len(), 3);
+    }
+
+    #[test]
+    fn test_rewrite_roots_keeps_extra_entries() {
+        let roots_list: Vec<RawCid> = (0..2)
+            .map(|i| testdata::CARV1_BASIC_SECTIONS[i].cid().clone())
+            .collect();
+        let mut header = CarHeader::new(roots_list.clone());
+        header.set_extra("x-vendor", &ciborium::Value::Text("navira".to_owned()));
+        let mut writer = crate::wire::v1::CarWriter::with_header(header);
+        let mut car = vec![0u8; 4096];
+        let len = writer.send_data(&mut car);
+        car.truncate(len);
+
+        let extra = |file: &[u8]| {
+            let reader = crate::stdio::CarReader::open(Cursor::new(file)).unwrap();
+            let (header, _) = reader.header().unwrap();
+            header.extra_value("x-vendor")
+        };
+        let expected = Some(ciborium::Value::Text("navira".to_owned()));
+        // The CIDv1 root is replaced by the shorter CIDv0 root: padded header
+        let shorter = [roots_list[1].clone(), roots_list[1].clone()];
+        let mut file = Cursor::new(car.clone());
+        assert_eq!(
+            rewrite_roots_in_place(&mut file, &shorter, true).unwrap(),
+            Some(RootsRewrite::Padded(2))
+        );
+        assert_eq!(extra(file.get_ref()), expected);
+        let mut sink = Vec::new();
+        copy_with_roots(&mut Cursor::new(&car), &mut sink, &roots_list[..1]).unwrap();
+        assert_eq!(extra(&sink), expected);
+    }
+
+    #[test]
+    fn test_rewrite_roots_file() {
+        let path =
+            std::env::temp_dir().join(format!("navira-car-roots-{}.car", std::process::id()));
+        std::fs::write(&path, testdata::CARV1_BASIC).unwrap();
+        let roots_list: Vec<RawCid> = (0..4)
+            .map(|i| testdata::CARV1_BASIC_SECTIONS[i].cid().clone())
+            .collect();
+        assert_eq!(
+            rewrite_roots(&path, &roots_list, true).unwrap(),
+            RootsRewrite::Streamed
+        );
+        assert_eq!(roots(&std::fs::read(&path).unwrap()).len(), 4);
+
+        std::fs::write(&path, testdata::CARV2_BASIC).unwrap();
+        assert!(matches!(
+            rewrite_roots(&path, &roots_list, true),
+            Err(HeaderRewriteError::UnsupportedVersion(2))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
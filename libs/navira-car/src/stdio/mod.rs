@@ -3,12 +3,29 @@
 //! This module provides utilities and method to read and write easily CAR files using
 //! the standard [Read](std::io::Read), [Write](std::io::Write), [Seek](std::io::Seek) traits.
 
+#[cfg(feature = "pack")]
+mod copy;
+mod header;
 mod read;
+mod reindex;
+mod stream;
+mod sync_write;
 mod write;
 
 use std::{fs::File, path::Path};
 
+#[cfg(feature = "pack")]
+#[doc(cfg(feature = "pack"))]
+pub use copy::{CopyError, CopyOptions, CopySummary, CopyToken, copy, copy_file, copy_token_path};
+pub use header::{
+    HeaderRewriteError, RootsRewrite, copy_with_roots, rewrite_roots, rewrite_roots_in_place,
+};
 pub use read::*;
+pub use reindex::{ReindexError, reindex, reindex_in_place};
+pub use stream::{
+    Recv, SectionStream, SectionStreamItem, SectionStreamOptions, spawn_section_stream,
+};
+pub use sync_write::{SyncCarWriter, SyncWriterError};
 pub use write::*;
 
 /// Open a CAR file from the given path and return a [CarReader] for it.
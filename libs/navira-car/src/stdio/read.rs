@@ -1,9 +1,19 @@
 use crate::{
     CarFormat, CarReader as SansIoCarReader, CarReaderError as SansIoCarReaderError,
-    wire::{cid::RawLink, v1::SectionFormatError},
+    ipld::BlockSource,
+    wire::{
+        cid::{RawCid, RawLink},
+        limits::Limits,
+        v1::{CarHeader, LocatableSection, SectionFormatError, SectionLocation},
+        v2::CarV2Header,
+        v2::IndexFormatError,
+    },
 };
 use std::{io, iter::FusedIterator};
 
+/// Minimum number of bytes read at once from the underlying reader
+pub(crate) const MIN_READ_SIZE: usize = 8 * 1024;
+
 /// Errors related to CarReader operations
 #[derive(thiserror::Error, Debug)]
 pub enum CarReaderError {
@@ -12,6 +22,9 @@ pub enum CarReaderError {
     InvalidFormat,
     #[error("Invalid header format")]
     InvalidHeader(ciborium::de::Error<std::io::Error>),
+    /// The CAR v1 header is larger than the [Limits](crate::Limits) of the reader
+    #[error("Header too large: {0} bytes")]
+    HeaderTooLarge(u64),
     #[error("Invalid CAR version, expected 2")]
     InvalidVersion,
     #[error("Invalid section format")]
@@ -22,11 +35,60 @@ pub enum CarReaderError {
     /// For instance, when you reached the end of the inner CARv1 data in a CARv2 file and try to read another section, you will get this error.
     #[error("No more sections available in the CAR file")]
     EndOfSections,
+    /// The file ends in the middle of a header or of a section
+    ///
+    /// `missing_bytes` is the number of bytes missing to complete it, or 1 if its length is not even known.
+    #[error("Truncated CAR file, {missing_bytes} bytes missing")]
+    Truncated { missing_bytes: usize },
+    /// The CAR v2 pre-payload padding contains a non-zero byte, at this absolute offset
+    #[error("Non-zero pre-payload padding at offset {0}")]
+    NonZeroPadding(u64),
+    /// The CID was already read in a previous section, at this offset (see [crate::DuplicatePolicy::Error])
+    #[error("Duplicate section {cid} at offset {offset} (first at offset {first})", cid = .0.to_hex(), first = .1, offset = .2)]
+    DuplicateSection(RawCid, u64, u64),
+    /// The CAR v2 index is malformed or of an unknown type
+    #[error("Invalid index: {0}")]
+    InvalidIndex(IndexFormatError),
+    /// The section is absent from the full CAR v2 index, so it is not in the file
+    #[error("Section not found in the index")]
+    SectionNotFound,
+    /// The reader was used before its header was read
+    ///
+    /// This is a misuse of the inner (sans-IO) reader, which the std-io wrappers never expose.
+    #[error("The CAR header must be read first")]
+    PreconditionNotMet,
     /// I/O error occurred during reading
     #[error("I/O error occurred during reading: {0}")]
     Io(#[from] std::io::Error),
 }
 
+/// Map an error of the inner (sans-IO) CarReader, other than [SansIoCarReaderError::InsufficientData]
+pub(crate) fn map_underlying_error(err: SansIoCarReaderError) -> CarReaderError {
+    match err {
+        SansIoCarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+        SansIoCarReaderError::HeaderTooLarge(length) => CarReaderError::HeaderTooLarge(length),
+        SansIoCarReaderError::InvalidVersion => CarReaderError::InvalidVersion,
+        SansIoCarReaderError::InvalidSectionFormat(e) => CarReaderError::InvalidSectionFormat(e),
+        SansIoCarReaderError::EndOfSections => CarReaderError::EndOfSections,
+        SansIoCarReaderError::Truncated { missing_bytes } => {
+            CarReaderError::Truncated { missing_bytes }
+        }
+        SansIoCarReaderError::NonZeroPadding(offset) => CarReaderError::NonZeroPadding(offset),
+        SansIoCarReaderError::InvalidIndex(e) => CarReaderError::InvalidIndex(e),
+        SansIoCarReaderError::SectionNotFound => CarReaderError::SectionNotFound,
+        SansIoCarReaderError::DuplicateSection(cid, first_offset, offset) => {
+            CarReaderError::DuplicateSection(cid, first_offset, offset)
+        }
+        SansIoCarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
+        // Insufficient data is normally handled by the caller, by reading more: here, the data is missing for good
+        SansIoCarReaderError::InsufficientData(_, _) => CarReaderError::Io(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Unexpected end of file while reading CAR data",
+        )),
+        SansIoCarReaderError::PreconditionNotMet => CarReaderError::PreconditionNotMet,
+    }
+}
+
 /// A std-io wrapper to read CAR archives from any type that implements [std::io::Read] and [std::io::Seek].
 ///
 /// # Examples
@@ -48,6 +110,10 @@ pub enum CarReaderError {
 pub struct CarReader<R: std::io::Read + io::Seek> {
     inner: SansIoCarReader,
     reader: R,
+    /// Format of the archive, known once the header is read
+    format: CarFormat,
+    /// Length of the underlying reader, once its end is reached
+    len: Option<u64>,
 }
 
 /// An iterator over the sections of a CAR archive.
@@ -57,6 +123,10 @@ pub struct CarReader<R: std::io::Read + io::Seek> {
 /// or if there is an I/O error while reading the underlying reader.
 pub struct CarSectionIterator<'a, R: std::io::Read + io::Seek> {
     car_reader: &'a mut CarReader<R>,
+    /// Error which occurred while rewinding the archive, yielded alone
+    error: Option<CarReaderError>,
+    /// Has the rewinding error been yielded?
+    failed: bool,
 }
 
 impl<R: io::Read + io::Seek> CarReader<R> {
@@ -64,33 +134,31 @@ impl<R: io::Read + io::Seek> CarReader<R> {
     /// Otherwise, this function will just map to the proper error.
     fn handle_underlying_error(&mut self, err: SansIoCarReaderError) -> Result<(), CarReaderError> {
         match err {
-            SansIoCarReaderError::InvalidHeader(e) => Err(CarReaderError::InvalidHeader(e)),
-            SansIoCarReaderError::InvalidVersion => Err(CarReaderError::InvalidVersion),
-            SansIoCarReaderError::InvalidSectionFormat(e) => {
-                Err(CarReaderError::InvalidSectionFormat(e))
-            }
-            SansIoCarReaderError::EndOfSections => Err(CarReaderError::EndOfSections),
-            SansIoCarReaderError::InvalidFormat => Err(CarReaderError::InvalidFormat),
-            SansIoCarReaderError::InsufficientData(offset, _) => {
+            SansIoCarReaderError::InsufficientData(offset, hint) => {
                 // We need to read more data from the underlying reader and feed it to the inner CarReader
-                let mut buffer = vec![0u8; 1024];
+                // The inner reader never hints below MIN_READ_SIZE, so reads are naturally coalesced
+                let mut buffer = vec![0u8; hint];
                 self.reader.seek(io::SeekFrom::Start(offset as u64))?;
                 let bytes_read = self.reader.read(&mut buffer)?;
+                if bytes_read == 0 && self.len.is_none() {
+                    // The end of the file is reached: the inner reader tells a clean end from a truncated file
+                    let len = self.reader.seek(io::SeekFrom::End(0))?;
+                    self.len = Some(len);
+                    self.inner.finish(len as usize);
+                    return Ok(());
+                }
                 if bytes_read == 0 {
                     return Err(CarReaderError::Io(std::io::Error::new(
                         std::io::ErrorKind::UnexpectedEof,
                         "Unexpected end of file while reading CAR data",
                     )));
                 }
-                self.inner.receive_data(&buffer[..bytes_read], offset);
+                self.inner
+                    .receive_data(buffer.get(..bytes_read).unwrap_or_default(), offset);
                 // After feeding the new data, we can try to read again
                 Ok(())
             }
-            SansIoCarReaderError::PreconditionNotMet => {
-                panic!(
-                    "Precondition not met error should never be returned by the inner CarReader since we are not exposing any method that can cause it. This is a bug in the inner CarReader implementation."
-                );
-            }
+            err => Err(map_underlying_error(err)),
         }
     }
 
@@ -115,35 +183,181 @@ impl<R: io::Read + io::Seek> CarReader<R> {
     /// * `Ok(Self)`, if the CAR archive can be successfully opened (meaning at least the header could be decoded).
     /// * `Err(CarReaderError)`, otherwise, indicating the CAR archive is corrupted, invalid or just unsupported.
     pub fn open(reader: R) -> Result<Self, CarReaderError> {
+        Self::open_with_limits(reader, Limits::default())
+    }
+
+    /// Open a CAR archive, with custom size limits
+    ///
+    /// See [CarReader::open]. Headers and sections exceeding the limits are rejected with
+    /// [CarReaderError::HeaderTooLarge] and [CarReaderError::InvalidSectionFormat].
+    pub fn open_with_limits(reader: R, limits: Limits) -> Result<Self, CarReaderError> {
+        let mut inner = SansIoCarReader::with_min_read_hint(MIN_READ_SIZE);
+        inner.set_limits(limits);
         let mut car_reader = Self {
-            inner: SansIoCarReader::new(),
+            inner,
             reader,
+            format: CarFormat::V1,
+            len: None,
         };
         car_reader.read_header()?;
+        car_reader.format = car_reader
+            .inner
+            .get_format()
+            .ok_or(CarReaderError::InvalidFormat)?;
         Ok(car_reader)
     }
 
     /// Get the root CIDs of the archive as [RawLink].
     pub fn get_roots(&self) -> &[RawLink] {
-        self.inner.header().unwrap().0.roots()
+        self.inner
+            .header()
+            .map_or(&[], |(v1_header, _)| v1_header.roots())
     }
 
     /// Get the CAR archive format
     pub fn get_format(&self) -> CarFormat {
-        self.inner.get_format().unwrap()
+        self.format
+    }
+
+    /// Get the headers of the archive
+    ///
+    /// ## Returns
+    /// - `Some((&CarHeaderV1, None))` for a CAR v1 archive.
+    /// - `Some((&CarHeaderV1, Some(&CarHeaderV2)))` for a CAR v2 archive.
+    ///
+    /// The headers are read by [CarReader::open], so this is never `None` on an opened archive.
+    pub fn header(&self) -> Option<(&CarHeader, Option<&CarV2Header>)> {
+        self.inner.header()
+    }
+
+    /// Read the next section of the archive, blocking on the underlying reader
+    ///
+    /// The sections are read in file order, from the first one once the archive is opened (or rewound).
+    ///
+    /// ## Returns
+    /// - `Ok(Some(section))` with the next section.
+    /// - `Ok(None)` once all the sections are read.
+    /// - `Err(CarReaderError::Truncated)` if the file ends in the middle of a section.
+    /// - `Err(CarReaderError)` if the section is malformed, or on I/O errors.
+    pub fn next_section(&mut self) -> Result<Option<LocatableSection>, CarReaderError> {
+        loop {
+            match self.inner.read_section() {
+                Ok(section) => return Ok(Some(section)),
+                Err(e) => match self.handle_underlying_error(e) {
+                    Ok(()) => continue, // We handled the error by reading more data, try to read the section again
+                    Err(CarReaderError::Io(err))
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        return Ok(None); // We reached the end of the underlying reader
+                    }
+                    Err(CarReaderError::EndOfSections) => return Ok(None), // We reached the end of the sections in the CAR file
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+    }
+
+    /// Read the next section of the archive, copying its block to the given writer as it is read
+    ///
+    /// Unlike [CarReader::next_section], the block is never held in memory as a whole: it is read in chunks of at
+    /// most 64 KiB (see [SansIoCarReader::begin_section]), so that blocks of any size can be copied, e.g. to a file.
+    ///
+    /// ## Returns
+    /// - `Ok(Some((location, cid)))` with the location and CID of the section, once its block is copied.
+    /// - `Ok(None)` once all the sections are read.
+    /// - `Err(CarReaderError::Truncated)` if the file ends in the middle of a section.
+    /// - `Err(CarReaderError)` if the section is malformed, or on I/O errors (the block may then be partially copied).
+    pub fn next_section_into<W: io::Write>(
+        &mut self,
+        block: &mut W,
+    ) -> Result<Option<(SectionLocation, RawCid)>, CarReaderError> {
+        let (location, cid, _) = loop {
+            match self.inner.begin_section() {
+                Ok(started) => break started,
+                Err(e) => match self.handle_underlying_error(e) {
+                    Ok(()) => continue,
+                    Err(CarReaderError::Io(err))
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        return Ok(None);
+                    }
+                    Err(CarReaderError::EndOfSections) => return Ok(None),
+                    Err(err) => return Err(err),
+                },
+            }
+        };
+        loop {
+            match self.inner.read_block_chunk() {
+                Ok(Some(chunk)) => block.write_all(chunk)?,
+                Ok(None) => return Ok(Some((location, cid))),
+                Err(e) => self.handle_underlying_error(e)?,
+            }
+        }
+    }
+
+    /// Find the section with the given CID, blocking on the underlying reader
+    ///
+    /// CAR v2 archives with a full index jump directly to the section. Otherwise, the whole archive is
+    /// searched from its first section. Either way, [CarReader::next_section] then continues after the
+    /// found section.
+    ///
+    /// ## Returns
+    /// - `Ok(Some(section))` with the found section.
+    /// - `Ok(None)` if no section has this CID.
+    /// - `Err(CarReaderError)` if a section (or the index) is malformed, or on I/O errors.
+    pub fn find_section(
+        &mut self,
+        cid: &RawCid,
+    ) -> Result<Option<LocatableSection>, CarReaderError> {
+        self.rewind()?;
+        loop {
+            match self.inner.find_section(cid) {
+                Ok(section) => return Ok(Some(section)),
+                Err(SansIoCarReaderError::SectionNotFound) => return Ok(None),
+                Err(e) => match self.handle_underlying_error(e) {
+                    Ok(()) => continue,
+                    Err(CarReaderError::Io(err))
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        return Ok(None);
+                    }
+                    Err(CarReaderError::EndOfSections) => return Ok(None),
+                    Err(err) => return Err(err),
+                },
+            }
+        }
     }
 
     /// Rewind the archive to its beggining.
     ///
     /// You probably do not need to use this function.
-    pub fn rewind(&mut self) {
-        self.inner.seek_first_section().unwrap();
+    pub fn rewind(&mut self) -> Result<(), CarReaderError> {
+        self.inner
+            .seek_first_section()
+            .map_err(map_underlying_error)
     }
 
     /// Get an iterator over all the sections of the archive.
+    ///
+    /// If the archive can not be rewound, the iterator only yields the error.
     pub fn sections(&mut self) -> CarSectionIterator<'_, R> {
-        self.rewind();
-        CarSectionIterator { car_reader: self }
+        let error = self.rewind().err();
+        CarSectionIterator {
+            car_reader: self,
+            error,
+            failed: false,
+        }
+    }
+}
+
+/// The blocks of the archive, looked up with [CarReader::find_section] (through the full index, if any)
+impl<R: io::Read + io::Seek> BlockSource for CarReader<R> {
+    type Error = CarReaderError;
+
+    fn get_block(&mut self, cid: &RawCid) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self
+            .find_section(cid)?
+            .map(|section| section.block().data().to_vec()))
     }
 }
 
@@ -151,21 +365,14 @@ impl<R: io::Read + io::Seek> Iterator for CarSectionIterator<'_, R> {
     type Item = Result<crate::wire::v1::LocatableSection, CarReaderError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match self.car_reader.inner.read_section() {
-                Ok(section) => return Some(Ok(section)),
-                Err(e) => match self.car_reader.handle_underlying_error(e) {
-                    Ok(()) => continue, // We handled the error by reading more data, try to read the section again
-                    Err(CarReaderError::Io(err))
-                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
-                    {
-                        return None; // We reached the end of the underlying reader, return None to indicate that there are no more sections
-                    }
-                    Err(CarReaderError::EndOfSections) => return None, // We reached the end of the sections in the CAR file, return None to indicate that there are no more sections
-                    Err(err) => return Some(Err(err)), // An unrecoverable error occurred, return it
-                },
-            }
+        if let Some(err) = self.error.take() {
+            self.failed = true;
+            return Some(Err(err));
         }
+        if self.failed {
+            return None;
+        }
+        self.car_reader.next_section().transpose()
     }
 }
 
@@ -219,4 +426,108 @@ mod tests {
         assert_eq!(sections.len(), 5);
         assert!(sections.iter().all(|s| s.is_ok()));
     }
+
+    #[test]
+    fn test_car_reader_next_section_into() {
+        use crate::testdata::{
+            CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC, CARV2_BASIC_SECTIONS,
+        };
+
+        for (car_bytes, fixtures) in [
+            (CARV1_BASIC, CARV1_BASIC_SECTIONS),
+            (CARV2_BASIC, CARV2_BASIC_SECTIONS),
+        ] {
+            let mut reader = CarReader::open(Cursor::new(car_bytes)).unwrap();
+            let mut expected = CarReader::open(Cursor::new(car_bytes)).unwrap();
+            for fixture in fixtures {
+                let mut block = Vec::new();
+                let (location, cid) = reader.next_section_into(&mut block).unwrap().unwrap();
+                assert_eq!(location, fixture.location());
+                assert_eq!(cid, fixture.cid());
+                let section = expected.next_section().unwrap().unwrap();
+                assert_eq!(block, section.block().data());
+            }
+            assert!(reader.next_section_into(&mut Vec::new()).unwrap().is_none());
+        }
+
+        // A block cut by the end of the file
+        let mut reader = CarReader::open(Cursor::new(&CARV1_BASIC[..150])).unwrap();
+        assert!(matches!(
+            reader.next_section_into(&mut Vec::new()),
+            Err(CarReaderError::Truncated { missing_bytes: 42 })
+        ));
+    }
+
+    #[test]
+    fn test_car_reader_next_and_find_section() {
+        use crate::testdata::{
+            CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC, CARV2_BASIC_SECTIONS,
+        };
+
+        for (car_bytes, fixtures) in [
+            (CARV1_BASIC, CARV1_BASIC_SECTIONS),
+            (CARV2_BASIC, CARV2_BASIC_SECTIONS),
+        ] {
+            let mut reader = CarReader::open(Cursor::new(car_bytes)).unwrap();
+            assert_eq!(
+                reader.header().unwrap().1.is_some(),
+                reader.get_format() == CarFormat::V2
+            );
+            for fixture in fixtures {
+                let section = reader.next_section().unwrap().unwrap();
+                assert_eq!(section.location, fixture.location());
+            }
+            assert!(reader.next_section().unwrap().is_none());
+
+            // Sections are found wherever the reader is, and the reading continues after them
+            let last = fixtures.last().unwrap();
+            let found = reader.find_section(&fixtures[1].cid()).unwrap().unwrap();
+            assert_eq!(found.location, fixtures[1].location());
+            let next = reader.next_section().unwrap().unwrap();
+            assert_eq!(next.location, fixtures[2].location());
+            let found = reader.find_section(&last.cid()).unwrap().unwrap();
+            assert_eq!(found.location, last.location());
+            let absent = RawCid::from_hex(
+                "12200000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap();
+            assert!(reader.find_section(&absent).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_car_reader_truncated() {
+        use crate::testdata::{CARV1_BASIC, CARV2_BASIC};
+
+        // Both files are cut 10 bytes before the end of their last section
+        for (car_bytes, sections) in [
+            (&CARV1_BASIC[..CARV1_BASIC.len() - 10], 7),
+            (&CARV2_BASIC[..489], 4),
+        ] {
+            let mut reader = CarReader::open(Cursor::new(car_bytes)).unwrap();
+            for _ in 0..sections {
+                reader.next_section().unwrap().unwrap();
+            }
+            assert!(matches!(
+                reader.next_section(),
+                Err(CarReaderError::Truncated { missing_bytes: 10 })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_car_reader_block_source() {
+        use crate::traverse::DagIter;
+
+        let mut reader = CarReader::open(Cursor::new(crate::testdata::CARV2_BASIC)).unwrap();
+        let roots: Vec<_> = reader
+            .get_roots()
+            .iter()
+            .map(|root| root.to_raw_cid().clone())
+            .collect();
+        let blocks: Vec<_> = DagIter::from_roots(&mut reader, roots)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(blocks.len(), 5);
+    }
 }
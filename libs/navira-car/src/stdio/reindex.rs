@@ -0,0 +1,159 @@
+//! Regenerating the index of existing CARv2 files
+//!
+//! CARv2 files received from third parties frequently lack an index, or carry a stale one. The helpers of this
+//! module scan the CARv1 payload with a [Reindexer] (seeking past the blocks), write a fresh index right after the
+//! payload, and patch the CARv2 header to locate it. The payload itself is never rewritten.
+//!
+//! The index is written before the header: if the process is interrupted in between, the old header still
+//! locates the old index offset, which may then hold a partial index, so the reindexing must simply be run again.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::wire::v2::{self, CAR_V2_PRAGMA, IndexType, Reindexed, Reindexer};
+
+/// Size of the chunks read from the file
+const READ_SIZE: usize = 64 * 1024;
+
+/// Errors related to the reindexing of a CARv2 file
+#[derive(thiserror::Error, Debug)]
+pub enum ReindexError {
+    /// I/O error while reading or writing the CAR file
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The file is not a valid CARv2 file, or its payload is truncated or corrupt
+    #[error("Invalid CAR v2 file: {0}")]
+    Invalid(v2::CarReaderError),
+}
+
+/// Regenerate the index of a CARv2 file in place
+///
+/// The file is scanned, then the fresh index is written right after the CARv1 payload and the header is patched
+/// (index offset and full-index characteristic). The file is not truncated: if the old file was longer (e.g. a
+/// larger stale index), it must be truncated to [Reindexed::file_len] by the caller, see [reindex].
+///
+/// # Arguments
+/// * `file` - The CARv2 file, positioned anywhere
+/// * `index_type` - Type of the index to build
+///
+/// # Returns
+/// * `Ok(Reindexed)` - The header and index written
+/// * `Err(ReindexError)` - The file could not be scanned (it is then untouched), or the new index written
+pub fn reindex_in_place<F: Read + Write + Seek>(
+    file: &mut F,
+    index_type: IndexType,
+) -> Result<Reindexed, ReindexError> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let mut reindexer = Reindexer::new(index_type);
+    reindexer.finish(file_len as usize);
+    let mut buf = vec![0u8; READ_SIZE];
+    loop {
+        match reindexer.scan() {
+            Ok(()) => break,
+            Err(v2::CarReaderError::InsufficientData(offset, hint)) => {
+                // The blocks are skipped by seeking to the next requested offset
+                file.seek(SeekFrom::Start(offset as u64))?;
+                if buf.len() < hint {
+                    buf.resize(hint, 0);
+                }
+                let len = match file.read(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if len == 0 {
+                    // The file shrank while being scanned
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                reindexer.receive_data(buf.get(..len).unwrap_or_default(), offset);
+            }
+            Err(e) => return Err(ReindexError::Invalid(e)),
+        }
+    }
+    let reindexed = reindexer.reindexed().map_err(ReindexError::Invalid)?;
+    file.seek(SeekFrom::Start(reindexed.header.index_offset))?;
+    file.write_all(&reindexed.index)?;
+    file.seek(SeekFrom::Start(CAR_V2_PRAGMA.len() as u64))?;
+    file.write_all(&<[u8; 40]>::from(&reindexed.header))?;
+    file.flush()?;
+    Ok(reindexed)
+}
+
+/// Regenerate the index of the CARv2 file at the given path
+///
+/// See [reindex_in_place]. The file is then truncated right after the new index, and synced.
+pub fn reindex<P: AsRef<Path>>(path: P, index_type: IndexType) -> Result<Reindexed, ReindexError> {
+    let mut file = File::options().read(true).write(true).open(path)?;
+    let reindexed = reindex_in_place(&mut file, index_type)?;
+    file.set_len(reindexed.file_len())?;
+    file.sync_all()?;
+    Ok(reindexed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{self, CARV2_BASIC, CARV2_BASIC_INDEX_OFFSET};
+    use std::io::Cursor;
+
+    /// Find all the fixture sections through the index of the file
+    fn assert_indexed(file: &[u8]) {
+        let mut reader = v2::CarReader::new();
+        reader.receive_data(file, 0);
+        reader.read_header().unwrap();
+        assert_eq!(reader.read_index().unwrap().unwrap().len(), 5);
+        for section in testdata::CARV2_BASIC_SECTIONS {
+            let found = reader.find_section(&section.cid()).unwrap();
+            assert_eq!(found.location, section.location());
+        }
+    }
+
+    #[test]
+    fn test_reindex_in_place() {
+        // Without index: the index is appended, and the header patched
+        let mut car = CARV2_BASIC[..CARV2_BASIC_INDEX_OFFSET as usize].to_vec();
+        car[43..51].fill(0);
+        let mut file = Cursor::new(car);
+        let reindexed = reindex_in_place(&mut file, IndexType::MultihashIndexSorted).unwrap();
+        assert_eq!(reindexed.header.index_offset, CARV2_BASIC_INDEX_OFFSET);
+        assert!(reindexed.header.characteristics.has_full_index());
+        assert_eq!(file.get_ref().len() as u64, reindexed.file_len());
+        assert_eq!(reindexed.index, testdata::carv2_basic_index());
+        assert_indexed(file.get_ref());
+
+        // Stale index, longer than the new one
+        let mut car = CARV2_BASIC[..CARV2_BASIC_INDEX_OFFSET as usize].to_vec();
+        car.extend_from_slice(&[0xff; 1024]);
+        let mut file = Cursor::new(car);
+        let reindexed = reindex_in_place(&mut file, IndexType::IndexSorted).unwrap();
+        let mut car = file.into_inner();
+        car.truncate(reindexed.file_len() as usize);
+        assert_indexed(&car);
+
+        // Truncated payload: the file is untouched
+        let car = CARV2_BASIC[..CARV2_BASIC_INDEX_OFFSET as usize - 10].to_vec();
+        let mut file = Cursor::new(car.clone());
+        assert!(matches!(
+            reindex_in_place(&mut file, IndexType::MultihashIndexSorted),
+            Err(ReindexError::Invalid(v2::CarReaderError::Truncated { .. }))
+        ));
+        assert_eq!(file.get_ref(), &car);
+    }
+
+    #[test]
+    fn test_reindex_file() {
+        let path =
+            std::env::temp_dir().join(format!("navira-car-reindex-{}.car", std::process::id()));
+        let mut car = CARV2_BASIC[..CARV2_BASIC_INDEX_OFFSET as usize].to_vec();
+        car.extend_from_slice(&[0xff; 1024]);
+        std::fs::write(&path, &car).unwrap();
+        let reindexed = reindex(&path, IndexType::MultihashIndexSorted).unwrap();
+        let car = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(car.len() as u64, reindexed.file_len());
+        assert_indexed(&car);
+    }
+}
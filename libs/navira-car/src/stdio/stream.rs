@@ -0,0 +1,426 @@
+//! Bridge between the blocking read loop and async consumers.
+//!
+//! [spawn_section_stream] runs the read loop on a dedicated thread, reading the archive sequentially
+//! (no [Seek](std::io::Seek) needed, so pipes such as stdin work), and hands the sections over a bounded
+//! channel to a [SectionStream]. The sections are moved through the channel, their blocks are never copied.
+//!
+//! The channel does not depend on any async runtime: [SectionStream::recv] is a plain future, woken by the
+//! reading thread. When the channel is full, the reading thread blocks until the consumer catches up
+//! (backpressure), and it stops as soon as the [SectionStream] is dropped.
+//!
+//! ```
+//! use navira_car::stdio::{SectionStreamOptions, spawn_section_stream};
+//!
+//! let car_bytes: &'static [u8] = include_bytes!("../res/carv1-basic.car");
+//! let mut stream = spawn_section_stream(car_bytes, SectionStreamOptions::default()).unwrap();
+//! // From an async context: `while let Some(section) = stream.recv().await { ... }`
+//! let mut count = 0;
+//! while let Some(section) = stream.blocking_recv() {
+//!     assert!(section.is_ok());
+//!     count += 1;
+//! }
+//! assert_eq!(count, 8);
+//! assert_eq!(stream.roots().unwrap().len(), 2);
+//! ```
+
+use super::read::{CarReaderError, map_underlying_error};
+use crate::{
+    CarReader as SansIoCarReader, CarReaderError as SansIoCarReaderError,
+    wire::{cid::RawLink, v1::LocatableSection},
+};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+/// Item delivered by a [SectionStream]
+pub type SectionStreamItem = Result<LocatableSection, CarReaderError>;
+
+/// Options of [spawn_section_stream]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionStreamOptions {
+    /// Maximum number of sections waiting in the channel before the reading thread blocks
+    pub capacity: usize,
+    /// Minimum number of bytes read at once from the source
+    pub read_size: usize,
+}
+
+impl Default for SectionStreamOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            read_size: 64 * 1024,
+        }
+    }
+}
+
+/// State shared between the reading thread and the [SectionStream]
+struct Shared {
+    state: Mutex<ChannelState>,
+    /// Notified when room is made in the queue, or when the consumer is gone
+    not_full: Condvar,
+    /// Notified when an item is queued, or when the reading thread is done (see [SectionStream::blocking_recv])
+    not_empty: Condvar,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    queue: VecDeque<SectionStreamItem>,
+    /// Roots of the archive, once its header has been read
+    roots: Option<Vec<RawLink>>,
+    /// The reading thread is done, no item will be queued anymore
+    closed: bool,
+    /// The [SectionStream] was dropped
+    abandoned: bool,
+    /// Waker of the pending [SectionStream::recv]
+    waker: Option<Waker>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        // The state stays consistent even if a thread panicked while holding the lock
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queue an item, waiting for room in the queue
+    ///
+    /// Returns false if the consumer is gone.
+    fn send(&self, item: SectionStreamItem, capacity: usize) -> bool {
+        let mut state = self.lock();
+        while state.queue.len() >= capacity && !state.abandoned {
+            state = self.not_full.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.abandoned {
+            return false;
+        }
+        state.queue.push_back(item);
+        let waker = state.waker.take();
+        drop(state);
+        self.not_empty.notify_one();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        true
+    }
+
+    fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        let waker = state.waker.take();
+        drop(state);
+        self.not_empty.notify_one();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Receiving end of [spawn_section_stream]
+///
+/// Sections are delivered in file order. The stream ends after an error, or at the end of the archive.
+pub struct SectionStream {
+    shared: Arc<Shared>,
+}
+
+impl SectionStream {
+    /// Poll for the next section
+    ///
+    /// # Returns
+    /// * `Poll::Ready(Some(item))` - The next section, or the error which ended the stream
+    /// * `Poll::Ready(None)` - The end of the archive was reached
+    /// * `Poll::Pending` - No section is available yet, the task will be woken once one is
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<SectionStreamItem>> {
+        let mut state = self.shared.lock();
+        if let Some(item) = state.queue.pop_front() {
+            drop(state);
+            self.shared.not_full.notify_one();
+            return Poll::Ready(Some(item));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Receive the next section (see [SectionStream::poll_recv])
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv { stream: self }
+    }
+
+    /// Receive the next section, blocking the current thread until one is available
+    ///
+    /// This must not be called from an async context, prefer [SectionStream::recv] there.
+    pub fn blocking_recv(&mut self) -> Option<SectionStreamItem> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = state.queue.pop_front() {
+                drop(state);
+                self.shared.not_full.notify_one();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self
+                .shared
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Roots of the archive, once its header has been read
+    ///
+    /// The header is always read before the first section is delivered.
+    pub fn roots(&self) -> Option<Vec<RawLink>> {
+        self.shared.lock().roots.clone()
+    }
+}
+
+impl Drop for SectionStream {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.abandoned = true;
+        state.queue.clear();
+        drop(state);
+        self.shared.not_full.notify_one();
+    }
+}
+
+/// Future returned by [SectionStream::recv]
+pub struct Recv<'a> {
+    stream: &'a mut SectionStream,
+}
+
+impl Future for Recv<'_> {
+    type Output = Option<SectionStreamItem>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.stream.poll_recv(cx)
+    }
+}
+
+/// Spawn a thread reading the sections of a CAR archive, and delivering them to the returned [SectionStream]
+///
+/// The source is read sequentially, so it does not need to be seekable (e.g. stdin or a socket).
+/// The CARv2 index, if any, is never read.
+///
+/// # Arguments
+/// * `source` - The CAR archive (v1 or v2), read from its start
+/// * `options` - Capacity of the channel and size of the reads
+///
+/// # Returns
+/// * `Ok(SectionStream)` - The receiving end of the channel. Dropping it stops the reading thread (after its
+///   current read).
+/// * `Err(io::Error)` - The reading thread could not be spawned.
+pub fn spawn_section_stream<R: io::Read + Send + 'static>(
+    source: R,
+    options: SectionStreamOptions,
+) -> io::Result<SectionStream> {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(ChannelState::default()),
+        not_full: Condvar::new(),
+        not_empty: Condvar::new(),
+    });
+    let producer = Arc::clone(&shared);
+    std::thread::Builder::new()
+        .name("navira-car-sections".into())
+        .spawn(move || {
+            let mut reader = SequentialReader::new(source, options.read_size);
+            reader.run(&producer, options.capacity.max(1));
+            producer.close();
+        })?;
+    Ok(SectionStream { shared })
+}
+
+/// Feeds a sans-IO reader from a non-seekable source
+struct SequentialReader<R: io::Read> {
+    inner: SansIoCarReader,
+    source: R,
+    /// Offset of the next byte of the source
+    position: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: io::Read> SequentialReader<R> {
+    fn new(source: R, read_size: usize) -> Self {
+        Self {
+            inner: SansIoCarReader::with_min_read_hint(read_size),
+            source,
+            position: 0,
+            buffer: vec![0u8; read_size],
+        }
+    }
+
+    /// Read the source and queue its sections, until the end of the archive, an error, or the consumer is gone
+    fn run(&mut self, shared: &Shared, capacity: usize) {
+        loop {
+            let result = match self.inner.read_header() {
+                Ok(()) => break,
+                Err(e) => self.handle_error(e),
+            };
+            if let Err(e) = result {
+                shared.send(Err(e), capacity);
+                return;
+            }
+        }
+        shared.lock().roots = self
+            .inner
+            .header()
+            .map(|(header, _)| header.roots().to_vec());
+
+        loop {
+            let result = match self.inner.read_section() {
+                Ok(section) => {
+                    if !shared.send(Ok(section), capacity) {
+                        return;
+                    }
+                    continue;
+                }
+                Err(e) => self.handle_error(e),
+            };
+            match result {
+                Ok(()) => continue,
+                // The end of the source or the end of the payload, the same as the sections iterator
+                Err(CarReaderError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return;
+                }
+                Err(CarReaderError::EndOfSections) => return,
+                Err(err) => {
+                    shared.send(Err(err), capacity);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Feed the inner reader with the data it requested, or map its error
+    fn handle_error(&mut self, err: SansIoCarReaderError) -> Result<(), CarReaderError> {
+        let SansIoCarReaderError::InsufficientData(offset, hint) = err else {
+            return Err(map_underlying_error(err));
+        };
+        if offset < self.position {
+            return Err(CarReaderError::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Cannot read backwards (offset {}) in a sequential source (at offset {})",
+                    offset, self.position
+                ),
+            )));
+        }
+        // Skip the bytes the reader does not need (e.g. the CARv2 padding)
+        let skip = (offset - self.position) as u64;
+        let skipped = io::copy(&mut io::Read::take(&mut self.source, skip), &mut io::sink())?;
+        self.position += skipped as usize;
+        if skipped < skip {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        if self.buffer.len() < hint {
+            self.buffer.resize(hint, 0);
+        }
+        let bytes_read = loop {
+            match self.source.read(&mut self.buffer) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        if bytes_read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Unexpected end of file while reading CAR data",
+            )
+            .into());
+        }
+        self.inner.receive_data(
+            self.buffer.get(..bytes_read).unwrap_or_default(),
+            self.position,
+        );
+        self.position += bytes_read;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    /// Waker counting its wake-ups
+    #[derive(Default)]
+    struct CountingWaker(std::sync::atomic::AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// Source delivering its bytes a few at a time, as a pipe would
+    struct Trickle(&'static [u8]);
+
+    impl io::Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_section_stream_v2() {
+        let car_bytes: &'static [u8] = include_bytes!("../res/carv2-basic.car");
+        let options = SectionStreamOptions {
+            capacity: 1,
+            read_size: 16,
+        };
+        let mut stream = spawn_section_stream(Trickle(car_bytes), options).unwrap();
+        let mut offsets = Vec::new();
+        while let Some(section) = stream.blocking_recv() {
+            offsets.push(section.unwrap().location.offset);
+        }
+        assert_eq!(offsets, vec![108, 190, 325, 414, 455]);
+        assert_eq!(stream.roots().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_section_stream_poll() {
+        let car_bytes: &'static [u8] = include_bytes!("../res/carv1-basic.car");
+        let mut stream = spawn_section_stream(car_bytes, SectionStreamOptions::default()).unwrap();
+        let counter = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&counter));
+        let mut cx = Context::from_waker(&waker);
+        let mut count = 0;
+        loop {
+            match Pin::new(&mut stream.recv()).poll(&mut cx) {
+                Poll::Ready(Some(section)) => {
+                    assert!(section.is_ok());
+                    count += 1;
+                }
+                Poll::Ready(None) => break,
+                // Wait for the reading thread to wake us up
+                Poll::Pending => {
+                    while counter.0.load(std::sync::atomic::Ordering::SeqCst) == 0 {
+                        std::thread::yield_now();
+                    }
+                    counter.0.store(0, std::sync::atomic::Ordering::SeqCst);
+                }
+            }
+        }
+        assert_eq!(count, 8);
+    }
+
+    #[test]
+    fn test_section_stream_error() {
+        let car_bytes: &'static [u8] = b"\x01\x00garbage";
+        let mut stream = spawn_section_stream(car_bytes, SectionStreamOptions::default()).unwrap();
+        assert!(stream.blocking_recv().unwrap().is_err());
+        assert!(stream.blocking_recv().is_none());
+    }
+}
@@ -0,0 +1,296 @@
+//! Writing CAR archives of either format to a [Write] + [Seek] sink
+//!
+//! The sans-IO [CarWriter] tells at which offset each chunk of bytes must be written, and leaves the flushing loop
+//! to the caller: a CAR v2 header is only sent at the very end, at offset 0. [SyncCarWriter] owns the sink and
+//! this loop, so that writing an archive is only a matter of [SyncCarWriter::write_section] and
+//! [SyncCarWriter::finish].
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::read::CarFormat;
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Section, SectionLocation};
+use crate::write::{CarWriter, CarWriterError};
+
+/// Size of the chunks moved from the sans-IO writer to the sink (at least a CAR v2 header)
+const SEND_SIZE: usize = 64 * 1024;
+
+/// Size of the chunks read from the source of a streamed block
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Errors related to the [SyncCarWriter] operations
+#[derive(thiserror::Error, Debug)]
+pub enum SyncWriterError {
+    /// The section was rejected by the sans-IO writer (e.g. too large for the limits or for the buffer)
+    #[error("Cannot write section: {0}")]
+    Writer(CarWriterError),
+    /// I/O error occurred while writing to the sink (or reading a streamed block)
+    #[error("I/O error occurred during writing: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// A [CarWriter] owning its sink, and moving the written bytes to it
+///
+/// The sink is expected to be empty and positioned at its start. The bytes are moved to the sink whenever the
+/// buffer of the writer is full, and the rest of the archive (including the CAR v2 index and header) by
+/// [SyncCarWriter::finish].
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use navira_car::CarFormat;
+/// use navira_car::stdio::{CarReader, SyncCarWriter};
+/// use navira_car::wire::{cid::RawCid, v1::Section};
+///
+/// let cid = RawCid::from_hex("0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4").unwrap();
+/// let mut writer = SyncCarWriter::new(Cursor::new(Vec::new()), CarFormat::V2, vec![cid.clone()]);
+/// writer.write_section(&Section::from((cid, b"aaaa".to_vec()))).unwrap();
+/// let sink = writer.finish().unwrap();
+///
+/// let mut reader = CarReader::open(Cursor::new(sink.into_inner())).unwrap();
+/// assert_eq!(reader.sections().count(), 1);
+/// ```
+#[derive(Debug)]
+pub struct SyncCarWriter<W: Write + Seek> {
+    writer: CarWriter,
+    sink: W,
+    buf: Vec<u8>,
+    /// Current position of the sink
+    position: u64,
+    /// End of the bytes written to the sink so far
+    end: u64,
+}
+
+impl<W: Write + Seek> SyncCarWriter<W> {
+    /// Create a writer for an archive of the given format and roots, with the default buffer size (16 MiB)
+    pub fn new(sink: W, format: CarFormat, roots: Vec<RawCid>) -> Self {
+        Self::from_writer(sink, CarWriter::new(format, roots))
+    }
+
+    /// Create a writer with the given buffer size, which bounds the size of the written sections
+    ///
+    /// See [CarWriter::with_buffer_size].
+    pub fn with_buffer_size(
+        sink: W,
+        format: CarFormat,
+        roots: Vec<RawCid>,
+        buffer_size: usize,
+    ) -> Self {
+        Self::from_writer(
+            sink,
+            CarWriter::with_buffer_size(format, roots, buffer_size),
+        )
+    }
+
+    /// Wrap a sans-IO writer, e.g. configured with a CAR v2 data offset or alignment
+    ///
+    /// No section must have been sent by the writer yet.
+    pub fn from_writer(sink: W, writer: CarWriter) -> Self {
+        Self {
+            writer,
+            sink,
+            buf: vec![0u8; SEND_SIZE],
+            position: 0,
+            end: 0,
+        }
+    }
+
+    /// Get the sans-IO writer
+    pub fn inner(&self) -> &CarWriter {
+        &self.writer
+    }
+
+    /// Get the sans-IO writer, e.g. to set its limits, its link validation or its duplicate suppression
+    pub fn inner_mut(&mut self) -> &mut CarWriter {
+        &mut self.writer
+    }
+
+    /// Get the sink
+    pub fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+    /// Write a section, moving the buffered bytes to the sink when needed
+    ///
+    /// # Returns
+    /// * `Ok(SectionLocation)` - The absolute location of the section in the archive
+    /// * `Err(SyncWriterError)` - The section was rejected by the sans-IO writer, or the sink failed
+    pub fn write_section(&mut self, section: &Section) -> Result<SectionLocation, SyncWriterError> {
+        match self.writer.write_section(section) {
+            Err(CarWriterError::BufferFull) => {
+                self.flush()?;
+                self.writer.write_section(section)
+            }
+            result => result,
+        }
+        .map_err(SyncWriterError::Writer)
+    }
+
+    /// Write the exact bytes of a section (length prefix, CID and block data)
+    ///
+    /// See [CarWriter::write_raw_section].
+    pub fn write_raw_section(
+        &mut self,
+        bytes: &[u8],
+        cid: &RawCid,
+    ) -> Result<SectionLocation, SyncWriterError> {
+        match self.writer.write_raw_section(bytes, cid) {
+            Err(CarWriterError::BufferFull) => {
+                self.flush()?;
+                self.writer.write_raw_section(bytes, cid)
+            }
+            result => result,
+        }
+        .map_err(SyncWriterError::Writer)
+    }
+
+    /// Write a section whose block is read from the given source, without holding the whole block in memory
+    ///
+    /// Exactly `block_length` bytes are read from the source (see [CarWriter::begin_section]). If the source fails
+    /// or ends early, the section is left incomplete, and the archive cannot be finished.
+    pub fn write_section_from<R: Read>(
+        &mut self,
+        cid: &RawCid,
+        block_length: usize,
+        source: &mut R,
+    ) -> Result<SectionLocation, SyncWriterError> {
+        match self.writer.begin_section(cid, block_length) {
+            Err(CarWriterError::BufferFull) => {
+                self.flush()?;
+                self.writer.begin_section(cid, block_length)
+            }
+            result => result,
+        }
+        .map_err(SyncWriterError::Writer)?;
+        let mut chunk = vec![0u8; block_length.min(CHUNK_SIZE)];
+        let mut remaining = block_length;
+        while remaining > 0 {
+            let length = remaining.min(chunk.len());
+            source.read_exact(chunk.get_mut(..length).unwrap_or_default())?;
+            let mut written = 0;
+            while written < length {
+                written += self
+                    .writer
+                    .write_block_chunk(chunk.get(written..length).unwrap_or_default())
+                    .map_err(SyncWriterError::Writer)?;
+                if written < length {
+                    self.flush()?;
+                }
+            }
+            remaining -= length;
+        }
+        self.writer.end_section().map_err(SyncWriterError::Writer)
+    }
+
+    /// Move all the buffered bytes to the sink, at their offsets
+    ///
+    /// The sink itself is not flushed, see [SyncCarWriter::finish].
+    pub fn flush(&mut self) -> io::Result<()> {
+        while self.writer.has_data_to_send() {
+            let (offset, length) = self.writer.send_data(&mut self.buf);
+            if length == 0 {
+                break;
+            }
+            let offset = offset as u64;
+            if offset != self.position {
+                self.sink.seek(SeekFrom::Start(offset))?;
+            }
+            self.sink
+                .write_all(self.buf.get(..length).unwrap_or_default())?;
+            self.position = offset + length as u64;
+            self.end = self.end.max(self.position);
+        }
+        Ok(())
+    }
+
+    /// Complete the archive, and return the sink
+    ///
+    /// The rest of the archive is written (for CAR v2, the index if enabled, then the header at offset 0), and the
+    /// sink is flushed, and left positioned at the end of the archive.
+    ///
+    /// # Returns
+    /// * `Ok(W)` - The sink, once the archive is complete
+    /// * `Err(SyncWriterError)` - The sink failed, or a section is still in progress: the archive is incomplete
+    pub fn finish(mut self) -> Result<W, SyncWriterError> {
+        self.writer.finish();
+        self.flush()?;
+        if !self.writer.is_finished() {
+            return Err(SyncWriterError::Writer(CarWriterError::SectionInProgress));
+        }
+        if self.position != self.end {
+            self.sink.seek(SeekFrom::Start(self.end))?;
+        }
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CarReader;
+    use crate::testdata;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_sync_car_writer() {
+        for (car, format) in [
+            (testdata::CARV1_BASIC, CarFormat::V1),
+            (testdata::CARV2_BASIC, CarFormat::V2),
+        ] {
+            let mut reader = CarReader::from_bytes(car).unwrap();
+            let (header, _) = reader.header().unwrap();
+            let roots = header.roots().iter().map(|root| root.to_raw_cid().clone());
+            // A small buffer, to move the sections to the sink several times
+            let mut writer = SyncCarWriter::with_buffer_size(
+                Cursor::new(Vec::new()),
+                format,
+                roots.collect(),
+                300,
+            );
+            let mut sections = Vec::new();
+            while let Ok(section) = reader.read_section() {
+                let location = if sections.len() % 2 == 0 {
+                    writer.write_section(&section.section).unwrap()
+                } else {
+                    let data = section.block().data();
+                    writer
+                        .write_section_from(section.cid(), data.len(), &mut &data[..])
+                        .unwrap()
+                };
+                assert_eq!(location, section.location);
+                sections.push(section);
+            }
+            let sink = writer.finish().unwrap();
+            assert_eq!(sink.position(), sink.get_ref().len() as u64);
+
+            let mut reader = CarReader::from_bytes(sink.get_ref()).unwrap();
+            assert_eq!(reader.get_format(), Some(format));
+            for section in &sections {
+                assert_eq!(&reader.read_section().unwrap(), section);
+            }
+            if format == CarFormat::V2 {
+                let (_, v2_header) = reader.header().unwrap();
+                assert!(v2_header.unwrap().characteristics.has_full_index());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sync_car_writer_incomplete_section() {
+        let cid = RawCid::from_hex(
+            "0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4",
+        )
+        .unwrap();
+        let mut writer = SyncCarWriter::new(Cursor::new(Vec::new()), CarFormat::V2, vec![]);
+        assert!(matches!(
+            writer.write_section_from(&cid, 8, &mut &b"aaaa"[..]),
+            Err(SyncWriterError::Io(_))
+        ));
+        assert!(matches!(
+            writer.finish(),
+            Err(SyncWriterError::Writer(CarWriterError::SectionInProgress))
+        ));
+    }
+}
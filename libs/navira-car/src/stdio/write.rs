@@ -1 +1,556 @@
+//! Writing CARv2 archives to seekable sinks
+//!
+//! The CARv2 header sits at the very start of the file, but it is only known once the payload (and the
+//! index) have been written: the sans-IO [v2::CarWriter] reserves its bytes, and relies on the caller to
+//! write the header back at offset 0 at the end. Forgetting this positioned write leaves a zeroed header,
+//! which no reader accepts. [CarWriter] makes the two phases explicit:
+//! 1. the sections are written with [CarWriter::write_section], then [CarWriter::flush_payload] writes all
+//!    the pending bytes of the payload;
+//! 2. [FlushedCarWriter::finalize] appends the index (if enabled) and writes the header at offset 0, in a
+//!    single call.
+//!
+//! For files, [PendingCarFile] writes to a temporary file next to the target, which only replaces the target
+//! once finalized (see [FlushedCarWriter::finalize_file]): a crash or an error never leaves a partial CAR file.
 
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::wire::{
+    cid::RawCid,
+    v1::{MIN_BUFFER_SIZE, Section},
+    v2::{self, CarV2Header, Index, SectionLocation},
+};
+
+/// Size of the internal buffer of the sans-IO writer, flushed to the sink when full
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Size of the chunks read from the source of a streamed block
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Errors related to the std-io CarWriter operations
+#[derive(thiserror::Error, Debug)]
+pub enum CarWriterError {
+    /// The section could not be written (e.g. too large for the limits or for the buffer)
+    #[error("Cannot write section: {0}")]
+    Writer(v2::CarWriterError),
+    /// I/O error occurred while writing to the sink
+    #[error("I/O error occurred during writing: {0}")]
+    Io(#[from] io::Error),
+    /// The sans-IO writer still had bytes to send after being flushed to the sink
+    #[error("Pending data could not be flushed to the sink")]
+    PendingData,
+}
+
+/// A std-io wrapper to write CARv2 archives to any type that implements [std::io::Write] and [std::io::Seek].
+///
+/// The sink is passed to each call rather than owned, so it can be inspected (or reused) in between.
+/// The sink is expected to be empty and positioned at its start, and to only be written by the writer until
+/// [FlushedCarWriter::finalize] returns.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use navira_car::stdio::{CarReader, CarWriter};
+/// use navira_car::wire::{cid::RawCid, v1::Section};
+///
+/// let cid = RawCid::from_hex("0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4").unwrap();
+/// let mut sink = Cursor::new(Vec::new());
+/// let mut writer = CarWriter::new(vec![cid.clone()]);
+/// writer.write_section(&mut sink, &Section::from((cid, b"aaaa".to_vec()))).unwrap();
+/// let header = writer.flush_payload(&mut sink).unwrap().finalize(&mut sink).unwrap();
+/// assert!(header.characteristics.has_full_index());
+///
+/// let mut reader = CarReader::open(Cursor::new(sink.into_inner())).unwrap();
+/// assert_eq!(reader.sections().count(), 1);
+/// ```
+#[derive(Debug)]
+pub struct CarWriter {
+    writer: v2::CarWriter<v2::SectionWritingState>,
+    buf: Vec<u8>,
+    entries: Vec<(RawCid, u64)>,
+    indexed: bool,
+}
+
+/// A [CarWriter] whose payload has been written, waiting for [FlushedCarWriter::finalize]
+#[derive(Debug)]
+pub struct FlushedCarWriter {
+    state: FlushedState,
+    buf: Vec<u8>,
+}
+
+/// State of the sans-IO writer once the payload is written
+#[derive(Debug)]
+enum FlushedState {
+    /// The index is still to be written
+    Index(v2::CarWriter<v2::IndexWritingState>, Index),
+    /// No index, only the header is still to be written
+    Header(v2::CarWriter<v2::FinalizedWritingState>),
+}
+
+impl CarWriter {
+    /// Create a writer for a CARv2 archive with the given roots
+    pub fn new(roots: Vec<RawCid>) -> Self {
+        Self::with_buffer_size(roots, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Create a writer with the given buffer size, which bounds the size of the written sections
+    ///
+    /// Buffer sizes below [MIN_BUFFER_SIZE] are raised to it.
+    pub fn with_buffer_size(roots: Vec<RawCid>, buffer_size: usize) -> Self {
+        Self {
+            writer: v2::CarWriter::with_buffer_size(roots, buffer_size),
+            buf: vec![0u8; buffer_size.max(MIN_BUFFER_SIZE)],
+            entries: Vec::new(),
+            indexed: true,
+        }
+    }
+
+    /// Set the offset of the payload, leaving zero padding after the header
+    ///
+    /// See [v2::CarWriter::with_data_offset], to be called before writing the first section.
+    pub fn with_data_offset(self, data_offset: u64) -> Self {
+        Self {
+            writer: self.writer.with_data_offset(data_offset),
+            ..self
+        }
+    }
+
+    /// Align the payload on the given boundary, e.g. 4096 bytes for a page-aligned payload
+    ///
+    /// See [v2::CarWriter::with_data_alignment], to be called before writing the first section.
+    pub fn with_data_alignment(self, alignment: u64) -> Self {
+        Self {
+            writer: self.writer.with_data_alignment(alignment),
+            ..self
+        }
+    }
+
+    /// Leave at least `padding` zero bytes between the payload and the index
+    ///
+    /// See [v2::CarWriter::with_index_padding].
+    pub fn with_index_padding(self, padding: u64) -> Self {
+        Self {
+            writer: self.writer.with_index_padding(padding),
+            ..self
+        }
+    }
+
+    /// Align the index on the given boundary
+    ///
+    /// See [v2::CarWriter::with_index_alignment].
+    pub fn with_index_alignment(self, alignment: u64) -> Self {
+        Self {
+            writer: self.writer.with_index_alignment(alignment),
+            ..self
+        }
+    }
+
+    /// Is a (full) index appended to the payload when finalizing? (enabled by default)
+    pub fn indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Enable or disable the index appended to the payload
+    pub fn set_indexed(&mut self, indexed: bool) {
+        self.indexed = indexed;
+    }
+
+    /// Get the sans-IO writer, e.g. to set its limits, its link validation or its duplicate suppression
+    pub fn inner_mut(&mut self) -> &mut v2::CarWriter<v2::SectionWritingState> {
+        &mut self.writer
+    }
+
+    /// Write a section, flushing the buffered sections to the sink when needed
+    ///
+    /// # Returns
+    /// * `Ok(SectionLocation)` - The absolute location of the section in the CARv2 file
+    /// * `Err(CarWriterError)` - The section was rejected by the sans-IO writer, or the sink failed
+    pub fn write_section<W: Write + Seek>(
+        &mut self,
+        sink: &mut W,
+        section: &Section,
+    ) -> Result<SectionLocation, CarWriterError> {
+        let skipped = self.writer.skipped_duplicates();
+        let location = match self.writer.write_section(section) {
+            Err(v2::CarWriterError::BufferFull) => {
+                flush(&mut self.writer, sink, &mut self.buf)?;
+                self.writer.write_section(section)
+            }
+            result => result,
+        }
+        .map_err(CarWriterError::Writer)?;
+        // A duplicate skipped by the sans-IO writer (see v2::CarWriter::set_dedup) is already indexed
+        if self.indexed && self.writer.skipped_duplicates() == skipped {
+            let offset = location.offset - self.writer.data_offset();
+            self.entries.push((section.cid().clone(), offset));
+        }
+        Ok(location)
+    }
+
+    /// Write a section whose block is read from the given source, without holding the whole block in memory
+    ///
+    /// Exactly `block_length` bytes are read from the source, and flushed to the sink as the buffer fills up (see
+    /// [v2::CarWriter::begin_section]). The section is written even when duplicates are suppressed. If the source
+    /// fails or ends early, the section is left incomplete, and the archive cannot be finalized.
+    ///
+    /// # Returns
+    /// * `Ok(SectionLocation)` - The absolute location of the section in the CARv2 file
+    /// * `Err(CarWriterError)` - The section was rejected by the sans-IO writer, or the source or the sink failed
+    pub fn write_section_from<W: Write + Seek, R: Read>(
+        &mut self,
+        sink: &mut W,
+        cid: &RawCid,
+        block_length: usize,
+        source: &mut R,
+    ) -> Result<SectionLocation, CarWriterError> {
+        let location = match self.writer.begin_section(cid, block_length) {
+            Err(v2::CarWriterError::BufferFull) => {
+                flush(&mut self.writer, sink, &mut self.buf)?;
+                self.writer.begin_section(cid, block_length)
+            }
+            result => result,
+        }
+        .map_err(CarWriterError::Writer)?;
+        if self.indexed {
+            let offset = location.offset - self.writer.data_offset();
+            self.entries.push((cid.clone(), offset));
+        }
+        let mut chunk = vec![0u8; block_length.min(CHUNK_SIZE)];
+        let mut remaining = block_length;
+        while remaining > 0 {
+            let length = remaining.min(chunk.len());
+            source.read_exact(chunk.get_mut(..length).unwrap_or_default())?;
+            let mut written = 0;
+            while written < length {
+                written += self
+                    .writer
+                    .write_block_chunk(chunk.get(written..length).unwrap_or_default())
+                    .map_err(CarWriterError::Writer)?;
+                if written < length {
+                    flush(&mut self.writer, sink, &mut self.buf)?;
+                }
+            }
+            remaining -= length;
+        }
+        self.writer.end_section().map_err(CarWriterError::Writer)
+    }
+
+    /// Write all the pending bytes of the payload to the sink, ending the sections phase
+    ///
+    /// Fails with [v2::CarWriterError::SectionInProgress] if a section was left incomplete.
+    pub fn flush_payload<W: Write + Seek>(
+        mut self,
+        sink: &mut W,
+    ) -> Result<FlushedCarWriter, CarWriterError> {
+        if self.writer.section_in_progress() {
+            return Err(CarWriterError::Writer(
+                v2::CarWriterError::SectionInProgress,
+            ));
+        }
+        flush(&mut self.writer, sink, &mut self.buf)?;
+        let state = if self.indexed {
+            let writer = self
+                .writer
+                .finalize_sections()
+                .map_err(|_| CarWriterError::PendingData)?;
+            let index =
+                Index::multihash_sorted(self.entries.iter().map(|(cid, offset)| (cid, *offset)));
+            FlushedState::Index(writer, index)
+        } else {
+            let writer = self
+                .writer
+                .finalize_all()
+                .map_err(|_| CarWriterError::PendingData)?;
+            FlushedState::Header(writer)
+        };
+        Ok(FlushedCarWriter {
+            state,
+            buf: self.buf,
+        })
+    }
+}
+
+impl FlushedCarWriter {
+    /// Append the index (if enabled), then write the CARv2 header at the start of the sink
+    ///
+    /// The sink is flushed, and left positioned at the end of the archive.
+    ///
+    /// # Returns
+    /// * `Ok(CarV2Header)` - The written header, once the archive is complete
+    /// * `Err(CarWriterError)` - The sink failed, the archive is incomplete
+    pub fn finalize<W: Write + Seek>(
+        mut self,
+        sink: &mut W,
+    ) -> Result<CarV2Header, CarWriterError> {
+        let mut writer = match self.state {
+            FlushedState::Index(mut writer, index) => {
+                writer.write_index(&index);
+                flush(&mut writer, sink, &mut self.buf)?;
+                writer
+                    .finalize_full_index()
+                    .map_err(|_| CarWriterError::PendingData)?
+            }
+            FlushedState::Header(writer) => writer,
+        };
+        let header = writer.header().clone();
+        let end = sink.stream_position()?;
+        flush(&mut writer, sink, &mut self.buf)?;
+        sink.seek(SeekFrom::Start(end))?;
+        sink.flush()?;
+        Ok(header)
+    }
+
+    /// Finalize the archive into a [PendingCarFile], and move it to its target path
+    ///
+    /// # Returns
+    /// * `Ok(File)` - The CAR file, synced to disk and renamed to its target path
+    /// * `Err(CarWriterError)` - The archive could not be completed, the temporary file is removed and the
+    ///   target path is untouched
+    pub fn finalize_file(self, mut file: PendingCarFile) -> Result<File, CarWriterError> {
+        self.finalize(&mut file)?;
+        Ok(file.persist()?)
+    }
+}
+
+/// Write all the pending bytes of the sans-IO writer at their positions in the sink
+fn flush<C: v2::CarWriteV2, W: Write + Seek>(
+    writer: &mut C,
+    sink: &mut W,
+    buf: &mut [u8],
+) -> io::Result<()> {
+    while writer.has_data_to_send() {
+        let (offset, length) = writer.send_data(buf);
+        if length == 0 {
+            break;
+        }
+        sink.seek(SeekFrom::Start(offset as u64))?;
+        sink.write_all(buf.get(..length).unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+/// A CAR file being written, as a temporary file next to its target path
+///
+/// The temporary file is named after the target, with a `.tmp` suffix. It replaces the target when persisted,
+/// and is removed if dropped before.
+#[derive(Debug)]
+pub struct PendingCarFile {
+    file: Option<BufWriter<File>>,
+    tmp: PathBuf,
+    path: PathBuf,
+}
+
+impl PendingCarFile {
+    /// Create the temporary file of the CAR file at `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = path.with_file_name(tmp_name);
+        let file = File::create(&tmp)?;
+        Ok(Self {
+            file: Some(BufWriter::new(file)),
+            tmp,
+            path,
+        })
+    }
+
+    /// Target path of the CAR file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sync the temporary file to disk, and rename it to the target path
+    fn persist(mut self) -> io::Result<File> {
+        let Some(file) = self.file.take() else {
+            unreachable!("the file is only taken when persisted");
+        };
+        let result = (|| {
+            let file = file.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+            std::fs::rename(&self.tmp, &self.path)?;
+            Ok(file)
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+        result
+    }
+
+    fn file(&mut self) -> io::Result<&mut BufWriter<File>> {
+        // The file is only taken when persisted
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the pending CAR file is already persisted"))
+    }
+}
+
+impl Write for PendingCarFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Seek for PendingCarFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file()?.seek(pos)
+    }
+}
+
+impl Drop for PendingCarFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdio::CarReader;
+    use crate::testdata;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_car_writer_two_phases() {
+        let sections = testdata::carv1_basic_sections();
+        let mut sink = Cursor::new(Vec::new());
+        // A small buffer, to flush the sections several times
+        let mut writer = CarWriter::with_buffer_size(vec![sections[0].cid().clone()], 300)
+            .with_index_padding(1000);
+        for section in &sections {
+            writer.write_section(&mut sink, section).unwrap();
+        }
+        let writer = writer.flush_payload(&mut sink).unwrap();
+        // The header is only written by the finalization
+        assert!(sink.get_ref()[11..51].iter().all(|&b| b == 0));
+        let header = writer.finalize(&mut sink).unwrap();
+        assert_eq!(sink.position(), sink.get_ref().len() as u64);
+        assert!(header.characteristics.has_full_index());
+        assert!(header.index_offset >= header.data_offset + header.data_size + 1000);
+
+        let mut reader = CarReader::open(Cursor::new(sink.into_inner())).unwrap();
+        let read: Vec<_> = reader.sections().map(|s| s.unwrap().section).collect();
+        assert_eq!(read, sections);
+    }
+
+    #[test]
+    fn test_car_writer_section_from() {
+        let sections = testdata::carv1_basic_sections();
+        let mut sink = Cursor::new(Vec::new());
+        // The blocks are larger than the chunks fitting in the buffer
+        let mut writer = CarWriter::with_buffer_size(vec![sections[0].cid().clone()], 300);
+        for section in &sections {
+            let data = section.block().data();
+            let location = writer
+                .write_section_from(&mut sink, section.cid(), data.len(), &mut &data[..])
+                .unwrap();
+            assert_eq!(location.length, section.total_length() as u64);
+        }
+        // A source ending early leaves the section incomplete
+        let data = sections[0].block().data();
+        assert!(matches!(
+            writer.write_section_from(&mut sink, sections[0].cid(), data.len() + 1, &mut &data[..]),
+            Err(CarWriterError::Io(_))
+        ));
+        assert!(matches!(
+            writer.flush_payload(&mut sink),
+            Err(CarWriterError::Writer(
+                v2::CarWriterError::SectionInProgress
+            ))
+        ));
+
+        let mut sink = Cursor::new(Vec::new());
+        let mut writer = CarWriter::with_buffer_size(vec![sections[0].cid().clone()], 300);
+        for section in &sections {
+            let data = section.block().data();
+            writer
+                .write_section_from(&mut sink, section.cid(), data.len(), &mut &data[..])
+                .unwrap();
+        }
+        writer
+            .flush_payload(&mut sink)
+            .unwrap()
+            .finalize(&mut sink)
+            .unwrap();
+        let mut reader = CarReader::open(Cursor::new(sink.into_inner())).unwrap();
+        let read: Vec<_> = reader.sections().map(|s| s.unwrap().section).collect();
+        assert_eq!(read, sections);
+    }
+
+    #[test]
+    fn test_car_writer_without_index() {
+        let sections = testdata::carv1_basic_sections();
+        let mut sink = Cursor::new(Vec::new());
+        let mut writer = CarWriter::new(vec![]).with_data_offset(100);
+        writer.set_indexed(false);
+        for section in &sections {
+            writer.write_section(&mut sink, section).unwrap();
+        }
+        let header = writer
+            .flush_payload(&mut sink)
+            .unwrap()
+            .finalize(&mut sink)
+            .unwrap();
+        assert_eq!(header.index_offset, 0);
+        assert_eq!(header.data_offset, 100);
+        assert_eq!(
+            sink.get_ref().len() as u64,
+            header.data_offset + header.data_size
+        );
+        let mut reader = CarReader::open(Cursor::new(sink.into_inner())).unwrap();
+        assert_eq!(reader.sections().count(), sections.len());
+    }
+
+    #[test]
+    fn test_car_writer_empty_buffer() {
+        // The buffer is raised to the minimum size, so that the header and index are flushed
+        let mut sink = Cursor::new(Vec::new());
+        let header = CarWriter::with_buffer_size(vec![], 0)
+            .flush_payload(&mut sink)
+            .unwrap()
+            .finalize(&mut sink)
+            .unwrap();
+        assert!(header.characteristics.has_full_index());
+        let mut reader = CarReader::open(Cursor::new(sink.into_inner())).unwrap();
+        assert_eq!(reader.sections().count(), 0);
+    }
+
+    #[test]
+    fn test_car_writer_file() {
+        let dir = std::env::temp_dir().join(format!("navira-car-write-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.car");
+        let sections = testdata::carv1_basic_sections();
+
+        // Dropped before being finalized: nothing is left behind
+        let mut file = PendingCarFile::create(&path).unwrap();
+        let mut writer = CarWriter::new(vec![]);
+        writer.write_section(&mut file, &sections[0]).unwrap();
+        drop(writer.flush_payload(&mut file).unwrap());
+        drop(file);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        let mut file = PendingCarFile::create(&path).unwrap();
+        let mut writer = CarWriter::new(vec![]);
+        for section in &sections {
+            writer.write_section(&mut file, section).unwrap();
+        }
+        assert!(!path.exists());
+        let writer = writer.flush_payload(&mut file).unwrap();
+        writer.finalize_file(file).unwrap();
+        let mut reader = crate::stdio::open_file(&path).unwrap();
+        assert_eq!(reader.sections().count(), sections.len());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
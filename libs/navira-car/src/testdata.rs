@@ -0,0 +1,216 @@
+//! Known-good CAR archives, to write tests against
+//!
+//! This module exposes the CAR files used by the test suite of navira-car, along with their
+//! documented contents (roots, sections and their locations), so that downstream crates and examples
+//! can test their CAR handling without vendoring their own binary blobs.
+//!
+//! This module is only available with the `test-fixtures` feature, usually enabled in `[dev-dependencies]`:
+//! ```toml
+//! [dev-dependencies]
+//! navira-car = { version = "*", features = ["test-fixtures"] }
+//! ```
+//!
+//! ## Examples
+//! ```
+//! use navira_car::CarReader;
+//! use navira_car::testdata::{CARV1_BASIC, CARV1_BASIC_SECTIONS};
+//!
+//! let mut reader = CarReader::from_bytes(CARV1_BASIC).unwrap();
+//! for expected in CARV1_BASIC_SECTIONS {
+//!     let section = reader.read_section().unwrap();
+//!     assert_eq!(section.cid(), &expected.cid());
+//!     assert_eq!(section.location, expected.location());
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Section, SectionLocation};
+
+/// A section of a fixture CAR archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixtureSection {
+    /// CID of the section, hex-encoded
+    pub cid_hex: &'static str,
+    /// Offset of the section, from the start of the file
+    pub offset: u64,
+    /// Length of the section, including its length varint
+    pub length: u64,
+    /// Length of the block data
+    pub data_length: usize,
+}
+
+impl FixtureSection {
+    /// CID of the section
+    pub fn cid(&self) -> RawCid {
+        RawCid::from_hex(self.cid_hex).expect("fixture CIDs are valid")
+    }
+
+    /// Location of the section, as returned by the readers
+    pub fn location(&self) -> SectionLocation {
+        SectionLocation {
+            offset: self.offset,
+            length: self.length,
+        }
+    }
+}
+
+/// A basic CAR v1 archive (715 bytes)
+///
+/// This is the `carv1-basic.car` fixture of the CAR specification: two dag-cbor roots,
+/// and 8 sections mixing dag-cbor, dag-pb (CIDv0) and raw blocks.
+/// The header is 100 bytes long, see [CARV1_BASIC_SECTIONS] for the sections.
+pub const CARV1_BASIC: &[u8] = include_bytes!("res/carv1-basic.car");
+
+/// Roots of [CARV1_BASIC], hex-encoded
+pub const CARV1_BASIC_ROOTS: &[&str] = &[
+    "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b",
+    "0171122069ea0740f9807a28f4d932c62e7c1c83be055e55072c90266ab3e79df63a365b",
+];
+
+/// Sections of [CARV1_BASIC], in file order
+pub const CARV1_BASIC_SECTIONS: &[FixtureSection] = &[
+    FixtureSection {
+        cid_hex: "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b",
+        offset: 100,
+        length: 92,
+        data_length: 55,
+    },
+    FixtureSection {
+        cid_hex: "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+        offset: 192,
+        length: 133,
+        data_length: 97,
+    },
+    FixtureSection {
+        cid_hex: "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        offset: 325,
+        length: 41,
+        data_length: 4,
+    },
+    FixtureSection {
+        cid_hex: "122079a982de3c9907953d4d323cee1d0fb1ed8f45f8ef02870c0cb9e09246bd530a",
+        offset: 366,
+        length: 130,
+        data_length: 94,
+    },
+    FixtureSection {
+        cid_hex: "0155122081cc5b17018674b401b42f35ba07bb79e211239c23bffe658da1577e3e646877",
+        offset: 496,
+        length: 41,
+        data_length: 4,
+    },
+    FixtureSection {
+        cid_hex: "1220e7dc486e97e6ebe5cdabab3e392bdad128b6e09acc94bb4e2aa2af7b986d24d0",
+        offset: 537,
+        length: 82,
+        data_length: 47,
+    },
+    FixtureSection {
+        cid_hex: "0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4",
+        offset: 619,
+        length: 41,
+        data_length: 4,
+    },
+    FixtureSection {
+        cid_hex: "0171122069ea0740f9807a28f4d932c62e7c1c83be055e55072c90266ab3e79df63a365b",
+        offset: 660,
+        length: 55,
+        data_length: 18,
+    },
+];
+
+/// Sections of [CARV1_BASIC] (CIDs and blocks), in file order
+///
+/// See [CARV1_BASIC_SECTIONS] for their locations.
+pub fn carv1_basic_sections() -> Vec<Section> {
+    let mut reader =
+        crate::CarReader::from_bytes(CARV1_BASIC).expect("the fixture header is valid");
+    CARV1_BASIC_SECTIONS
+        .iter()
+        .map(|_| {
+            reader
+                .read_section()
+                .expect("the fixture sections are valid")
+                .section
+        })
+        .collect()
+}
+
+/// Blocks of [CARV1_BASIC], by CID
+pub fn carv1_basic_blocks() -> HashMap<RawCid, Vec<u8>> {
+    carv1_basic_sections()
+        .into_iter()
+        .map(|section| (section.cid().clone(), section.block().data().to_vec()))
+        .collect()
+}
+
+/// A basic CAR v2 archive (715 bytes)
+///
+/// This is the `carv2-basic.car` fixture of the CAR specification: a single dag-pb root (CIDv0)
+/// and 5 sections. The CAR v1 payload starts at [CARV2_BASIC_DATA_OFFSET] and is [CARV2_BASIC_DATA_SIZE]
+/// bytes long, followed by its index at [CARV2_BASIC_INDEX_OFFSET].
+///
+/// Note: the index stored in this fixture lacks its 14 leading bytes (index codec, multihash count and code),
+/// use [carv2_basic_index] to get the complete index.
+pub const CARV2_BASIC: &[u8] = include_bytes!("res/carv2-basic.car");
+
+/// Offset of the CAR v1 payload of [CARV2_BASIC]
+pub const CARV2_BASIC_DATA_OFFSET: u64 = 51;
+/// Size of the CAR v1 payload of [CARV2_BASIC]
+pub const CARV2_BASIC_DATA_SIZE: u64 = 448;
+/// Offset of the index of [CARV2_BASIC]
+pub const CARV2_BASIC_INDEX_OFFSET: u64 = 499;
+
+/// Roots of [CARV2_BASIC], hex-encoded
+pub const CARV2_BASIC_ROOTS: &[&str] =
+    &["1220fb16f5083412ef1371d031ed4aa239903d84efdadf1ba3cd678e6475b1a232f8"];
+
+/// Sections of [CARV2_BASIC], in file order
+///
+/// Offsets are absolute (from the start of the CAR v2 file), as returned by the readers.
+pub const CARV2_BASIC_SECTIONS: &[FixtureSection] = &[
+    FixtureSection {
+        cid_hex: "1220fb16f5083412ef1371d031ed4aa239903d84efdadf1ba3cd678e6475b1a232f8",
+        offset: 108,
+        length: 82,
+        data_length: 47,
+    },
+    FixtureSection {
+        cid_hex: "1220d9c0d5376d26f1931f7ad52d7acc00fc1090d2edb0808bf61eeb0a152826f626",
+        offset: 190,
+        length: 135,
+        data_length: 99,
+    },
+    FixtureSection {
+        cid_hex: "1220d745b7757f5b4593eeab7820306c7bc64eb496a7410a0d07df7a34ffec4b97f1",
+        offset: 325,
+        length: 89,
+        data_length: 54,
+    },
+    FixtureSection {
+        cid_hex: "01551220b474a99a2705e23cf905a484ec6d14ef58b56bbe62e9292783466ec363b5072d",
+        offset: 414,
+        length: 41,
+        data_length: 4,
+    },
+    FixtureSection {
+        cid_hex: "01551220a2e1c40da1ae335d4dffe729eb4d5ca23b74b9e51fc535f4a804a261080c294d",
+        offset: 455,
+        length: 44,
+        data_length: 7,
+    },
+];
+
+/// Complete MultihashIndexSorted index of [CARV2_BASIC]
+///
+/// Index offsets are relative to the CAR v1 payload, as specified by CAR v2.
+pub fn carv2_basic_index() -> Vec<u8> {
+    // Type (0x0401), one multihash code (sha2-256), then the stored bucket of 5 entries of 40 bytes
+    let mut bytes = vec![
+        0x81, 0x08, 0x01, 0x00, 0x00, 0x00, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    bytes.extend_from_slice(&CARV2_BASIC[CARV2_BASIC_INDEX_OFFSET as usize..]);
+    bytes
+}
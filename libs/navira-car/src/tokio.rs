@@ -0,0 +1,325 @@
+//! Async reading of CAR archives over tokio I/O
+//!
+//! [AsyncCarReader] is the async counterpart of the [stdio::CarReader](crate::stdio::CarReader): it drives the
+//! sans-io [CarReader](crate::CarReader), answering its [InsufficientData](crate::CarReaderError::InsufficientData)
+//! requests by seeking and reading the underlying [AsyncRead] + [AsyncSeek] source (async files, network
+//! streams with range requests, ...). Errors are the ones of the [stdio module](crate::stdio).
+//!
+//! ## Examples
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! use std::io::Cursor;
+//! use navira_car::tokio::AsyncCarReader;
+//!
+//! let car_bytes = include_bytes!("res/carv2-basic.car");
+//! let mut reader = AsyncCarReader::new(Cursor::new(car_bytes.as_ref()));
+//! reader.read_header().await.unwrap();
+//! let mut count = 0;
+//! while let Some(section) = reader.next_section().await.unwrap() {
+//!     println!("{} at offset {}", section.cid().to_hex(), section.location.offset);
+//!     count += 1;
+//! }
+//! assert_eq!(count, 5);
+//! # }
+//! ```
+//!
+//! The sections can also be consumed as a [Stream], with the usual combinators:
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! use std::io::Cursor;
+//! use futures_util::TryStreamExt;
+//! use navira_car::tokio::AsyncCarReader;
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let reader = AsyncCarReader::new(Cursor::new(car_bytes.as_ref()));
+//! let total = reader
+//!     .into_section_stream()
+//!     .try_fold(0, |total, section| async move { Ok(total + section.block().len()) })
+//!     .await
+//!     .unwrap();
+//! assert!(total > 0);
+//! # }
+//! ```
+
+use std::io;
+
+use ::tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use futures_core::Stream;
+
+use crate::stdio::{CarReaderError, MIN_READ_SIZE, map_underlying_error};
+use crate::wire::cid::{RawCid, RawLink};
+use crate::wire::limits::Limits;
+use crate::wire::v1::{CarHeader, LocatableSection};
+use crate::wire::v2::CarV2Header;
+use crate::{CarFormat, CarReader as SansIoCarReader, CarReaderError as SansIoCarReaderError};
+
+/// An async wrapper to read CAR archives from any type that implements [AsyncRead] and [AsyncSeek].
+pub struct AsyncCarReader<R: AsyncRead + AsyncSeek + Unpin> {
+    inner: SansIoCarReader,
+    reader: R,
+    /// Length of the source, once its end is reached
+    len: Option<u64>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncCarReader<R> {
+    /// Create a reader over the given source, without reading anything yet
+    ///
+    /// The header must be read with [AsyncCarReader::read_header] before the sections.
+    pub fn new(reader: R) -> Self {
+        Self::with_limits(reader, Limits::default())
+    }
+
+    /// Create a reader over the given source with custom size limits, without reading anything yet
+    ///
+    /// See [stdio::CarReader::open_with_limits](crate::stdio::CarReader::open_with_limits).
+    pub fn with_limits(reader: R, limits: Limits) -> Self {
+        let mut inner = SansIoCarReader::with_min_read_hint(MIN_READ_SIZE);
+        inner.set_limits(limits);
+        Self {
+            inner,
+            reader,
+            len: None,
+        }
+    }
+
+    /// Get back the underlying source
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Answer the error of the inner reader: read the requested bytes, or map the error
+    async fn handle_underlying_error(
+        &mut self,
+        err: SansIoCarReaderError,
+    ) -> Result<(), CarReaderError> {
+        match err {
+            SansIoCarReaderError::InsufficientData(offset, hint) => {
+                // The inner reader never hints below MIN_READ_SIZE, so reads are naturally coalesced
+                let mut buffer = vec![0u8; hint];
+                self.reader.seek(io::SeekFrom::Start(offset as u64)).await?;
+                let bytes_read = self.reader.read(&mut buffer).await?;
+                if bytes_read == 0 && self.len.is_none() {
+                    // The end of the source is reached: the inner reader tells a clean end from a truncated file
+                    let len = self.reader.seek(io::SeekFrom::End(0)).await?;
+                    self.len = Some(len);
+                    self.inner.finish(len as usize);
+                    return Ok(());
+                }
+                if bytes_read == 0 {
+                    return Err(CarReaderError::Io(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Unexpected end of file while reading CAR data",
+                    )));
+                }
+                self.inner
+                    .receive_data(buffer.get(..bytes_read).unwrap_or_default(), offset);
+                Ok(())
+            }
+            err => Err(map_underlying_error(err)),
+        }
+    }
+
+    /// Read the CAR header(s), if not already read
+    ///
+    /// ## Returns
+    /// - `Ok(())` once the header is read.
+    /// - `Err(CarReaderError)` if the archive is corrupted, invalid or unsupported, or on I/O errors.
+    pub async fn read_header(&mut self) -> Result<(), CarReaderError> {
+        loop {
+            match self.inner.read_header() {
+                Ok(()) => return Ok(()),
+                Err(e) => self.handle_underlying_error(e).await?,
+            }
+        }
+    }
+
+    /// Get the headers of the archive, once read
+    ///
+    /// See [CarReader::header](crate::CarReader::header).
+    pub fn header(&self) -> Option<(&CarHeader, Option<&CarV2Header>)> {
+        self.inner.header()
+    }
+
+    /// Get the root CIDs of the archive as [RawLink] (empty until the header is read)
+    pub fn get_roots(&self) -> &[RawLink] {
+        self.inner
+            .header()
+            .map_or(&[], |(v1_header, _)| v1_header.roots())
+    }
+
+    /// Get the CAR archive format, once the header is read
+    pub fn get_format(&self) -> Option<CarFormat> {
+        self.inner.get_format()
+    }
+
+    /// Rewind the archive to its first section
+    pub fn rewind(&mut self) -> Result<(), CarReaderError> {
+        self.inner
+            .seek_first_section()
+            .map_err(map_underlying_error)
+    }
+
+    /// Read the next section of the archive
+    ///
+    /// See [stdio::CarReader::next_section](crate::stdio::CarReader::next_section).
+    ///
+    /// ## Returns
+    /// - `Ok(Some(section))` with the next section.
+    /// - `Ok(None)` once all the sections are read.
+    /// - `Err(CarReaderError::Truncated)` if the source ends in the middle of a section.
+    /// - `Err(CarReaderError)` if the section is malformed, or on I/O errors.
+    pub async fn next_section(&mut self) -> Result<Option<LocatableSection>, CarReaderError> {
+        loop {
+            match self.inner.read_section() {
+                Ok(section) => return Ok(Some(section)),
+                Err(e) => match self.handle_underlying_error(e).await {
+                    Ok(()) => continue,
+                    Err(CarReaderError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        return Ok(None);
+                    }
+                    Err(CarReaderError::EndOfSections) => return Ok(None),
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+    }
+
+    /// Turn the reader into a [Stream] of the sections
+    ///
+    /// The header is read first if needed, then the sections are streamed from the current position.
+    /// The stream ends after the last section, or after the first error (e.g. [CarReaderError::Truncated] if the
+    /// source ends in the middle of a section).
+    pub fn into_section_stream(
+        self,
+    ) -> impl Stream<Item = Result<LocatableSection, CarReaderError>> {
+        futures_util::stream::unfold(Some(self), |reader| async move {
+            let mut reader = reader?;
+            if !reader.inner.has_header()
+                && let Err(err) = reader.read_header().await
+            {
+                return Some((Err(err), None));
+            }
+            match reader.next_section().await {
+                Ok(Some(section)) => Some((Ok(section), Some(reader))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Find the section with the given CID
+    ///
+    /// See [stdio::CarReader::find_section](crate::stdio::CarReader::find_section): the full CAR v2 index
+    /// is used when present, otherwise the whole archive is searched.
+    ///
+    /// ## Returns
+    /// - `Ok(Some(section))` with the found section.
+    /// - `Ok(None)` if no section has this CID.
+    /// - `Err(CarReaderError)` if a section (or the index) is malformed, or on I/O errors.
+    pub async fn find_section(
+        &mut self,
+        cid: &RawCid,
+    ) -> Result<Option<LocatableSection>, CarReaderError> {
+        self.rewind()?;
+        loop {
+            match self.inner.find_section(cid) {
+                Ok(section) => return Ok(Some(section)),
+                Err(SansIoCarReaderError::SectionNotFound) => return Ok(None),
+                Err(e) => match self.handle_underlying_error(e).await {
+                    Ok(()) => continue,
+                    Err(CarReaderError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        return Ok(None);
+                    }
+                    Err(CarReaderError::EndOfSections) => return Ok(None),
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{CARV1_BASIC, CARV1_BASIC_SECTIONS, CARV2_BASIC, CARV2_BASIC_SECTIONS};
+    use std::io::Cursor;
+
+    #[::tokio::test(flavor = "current_thread")]
+    async fn test_async_car_reader() {
+        for (car_bytes, fixtures, format) in [
+            (CARV1_BASIC, CARV1_BASIC_SECTIONS, CarFormat::V1),
+            (CARV2_BASIC, CARV2_BASIC_SECTIONS, CarFormat::V2),
+        ] {
+            let mut reader = AsyncCarReader::new(Cursor::new(car_bytes));
+            assert_eq!(reader.get_format(), None);
+            reader.read_header().await.unwrap();
+            assert_eq!(reader.get_format(), Some(format));
+            assert!(!reader.get_roots().is_empty());
+            for fixture in fixtures {
+                let section = reader.next_section().await.unwrap().unwrap();
+                assert_eq!(section.location, fixture.location());
+            }
+            assert!(reader.next_section().await.unwrap().is_none());
+
+            let found = reader.find_section(&fixtures[2].cid()).await.unwrap();
+            assert_eq!(found.unwrap().location, fixtures[2].location());
+            let absent = RawCid::from_hex(
+                "12200000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap();
+            assert!(reader.find_section(&absent).await.unwrap().is_none());
+        }
+
+        // Corrupted archives are reported
+        let mut reader = AsyncCarReader::new(Cursor::new(&CARV1_BASIC[..50]));
+        assert!(reader.read_header().await.is_err());
+    }
+
+    #[::tokio::test(flavor = "current_thread")]
+    async fn test_async_car_reader_stream() {
+        use futures_util::StreamExt;
+
+        let reader = AsyncCarReader::new(Cursor::new(CARV2_BASIC));
+        let sections: Vec<_> = reader.into_section_stream().collect().await;
+        assert_eq!(sections.len(), CARV2_BASIC_SECTIONS.len());
+        for (section, fixture) in sections.into_iter().zip(CARV2_BASIC_SECTIONS) {
+            assert_eq!(section.unwrap().location, fixture.location());
+        }
+
+        // The stream ends after the first error
+        let reader = AsyncCarReader::new(Cursor::new(&CARV1_BASIC[..50]));
+        let items: Vec<_> = reader.into_section_stream().collect().await;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_err());
+
+        // A truncated archive does not end silently
+        let reader = AsyncCarReader::new(Cursor::new(&CARV1_BASIC[..CARV1_BASIC.len() - 10]));
+        let items: Vec<_> = reader.into_section_stream().collect().await;
+        assert_eq!(items.len(), 8);
+        assert!(matches!(
+            items[7],
+            Err(CarReaderError::Truncated { missing_bytes: 10 })
+        ));
+    }
+
+    #[::tokio::test(flavor = "current_thread")]
+    async fn test_async_car_reader_truncated() {
+        // Both files are cut 10 bytes before the end of their last section
+        for (car_bytes, sections) in [
+            (&CARV1_BASIC[..CARV1_BASIC.len() - 10], 7),
+            (&CARV2_BASIC[..489], 4),
+        ] {
+            let mut reader = AsyncCarReader::new(Cursor::new(car_bytes));
+            reader.read_header().await.unwrap();
+            for _ in 0..sections {
+                reader.next_section().await.unwrap().unwrap();
+            }
+            assert!(matches!(
+                reader.next_section().await,
+                Err(CarReaderError::Truncated { missing_bytes: 10 })
+            ));
+        }
+    }
+}
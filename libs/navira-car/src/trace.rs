@@ -0,0 +1,44 @@
+//! Trace-level instrumentation of the wire layers
+//!
+//! With the `tracing` feature, the state machines emit structured `TRACE` events (header parsing, section boundaries,
+//! index bucket loads, …) under one target per layer, so that `RUST_LOG` can light up exactly one of them:
+//! - `navira_car::wire::v1` for the CARv1 headers and sections (reader and writer),
+//! - `navira_car::wire::v2` for the CARv2 headers,
+//! - `navira_car::wire::v2::index` for the CARv2 indexes.
+//!
+//! Recoverable anomalies of the input are reported as `WARN` events under the same targets.
+//!
+//! Without the feature, the events are compiled out.
+
+#![cfg_attr(not(feature = "tracing"), allow(dead_code))]
+
+/// Target of the CARv1 events
+pub(crate) const WIRE_V1: &str = "navira_car::wire::v1";
+/// Target of the CARv2 header events
+pub(crate) const WIRE_V2: &str = "navira_car::wire::v2";
+/// Target of the CARv2 index events
+pub(crate) const WIRE_V2_INDEX: &str = "navira_car::wire::v2::index";
+
+/// Emit a `TRACE` event under the given target if the `tracing` feature is enabled
+///
+/// The target is the name of one of the constants of this module, followed by the fields and message
+/// with the syntax of `tracing::trace`.
+macro_rules! trace_event {
+    ($target:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(target: $crate::trace::$target, $($arg)+);
+    };
+}
+
+/// Emit a `WARN` event under the given target if the `tracing` feature is enabled
+///
+/// Used for recoverable anomalies of the input (e.g. non-zero padding), with the same syntax as [trace_event].
+macro_rules! warn_event {
+    ($target:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::warn!(target: $crate::trace::$target, $($arg)+);
+    };
+}
+
+pub(crate) use trace_event;
+pub(crate) use warn_event;
@@ -0,0 +1,188 @@
+//! Traversal of the DAG stored in a CAR archive
+//!
+//! The sections of a CAR file are in wire order, chosen by its writer. Many consumers need the blocks in the
+//! order of the DAG instead: deterministic re-encoding (two archives of the same DAG written in the same order),
+//! selective sync (sending the blocks of a sub-DAG, parents first), etc.
+//!
+//! [DagIter] walks the DAG from a root, depth-first, following the links of the dag-pb and dag-cbor blocks (see
+//! [block_links]). The blocks are fetched from any [BlockSource]: a [CarSlice](crate::CarSlice) for archives in
+//! memory, a [stdio CarReader](crate::stdio::CarReader) for indexed archives on disk (feature `std-io`), a
+//! `HashMap` of blocks, or a closure.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::CarSlice;
+//! use navira_car::traverse::DagIter;
+//!
+//! let car_bytes = include_bytes!("res/carv1-basic.car");
+//! let mut slice = CarSlice::new(car_bytes).unwrap();
+//! let root = slice.header().0.roots()[0].to_raw_cid().clone();
+//!
+//! // The DAG of the first root holds 7 of the 8 blocks of the archive
+//! let blocks: Vec<_> = DagIter::new(&mut slice, &root).collect::<Result<_, _>>().unwrap();
+//! assert_eq!(blocks.len(), 7);
+//! assert_eq!(blocks[0].cid, root);
+//! assert_eq!(blocks[0].depth, 0);
+//! ```
+
+use std::collections::HashSet;
+
+use crate::ipld::{BlockSource, block_links};
+use crate::wire::cid::RawCid;
+
+/// A block of the DAG, as yielded by [DagIter]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DagBlock {
+    /// CID of the block
+    pub cid: RawCid,
+    /// Data of the block
+    pub data: Vec<u8>,
+    /// Number of links followed from the root to reach the block (0 for the root)
+    pub depth: usize,
+}
+
+/// Depth-first iterator over the blocks of a DAG
+///
+/// The blocks are yielded parents first, and the links of each block in block order: this is the order of a
+/// recursive walk, without its recursion (deep DAGs do not overflow the stack). A block linked several times is
+/// only yielded at its first encounter, so shared sub-DAGs are walked once.
+///
+/// Identity CIDs embed their block, they are yielded without being fetched. A block absent from the source is
+/// reported as [TraverseError::BlockNotFound], and the walk goes on with the next links (without its sub-DAG).
+/// A failure of the source is reported as [TraverseError::Source], and ends the walk.
+#[derive(Debug)]
+pub struct DagIter<'a, S: BlockSource> {
+    /// Source of the blocks
+    source: &'a mut S,
+    /// Blocks to visit, with their depth, the next one last
+    stack: Vec<(RawCid, usize)>,
+    /// CIDs already yielded (or reported missing)
+    visited: HashSet<RawCid>,
+    /// Has the source failed?
+    failed: bool,
+}
+
+impl<'a, S: BlockSource> DagIter<'a, S> {
+    /// Create an iterator over the DAG of the given root
+    pub fn new(source: &'a mut S, root: &RawCid) -> Self {
+        Self::from_roots(source, [root.clone()])
+    }
+
+    /// Create an iterator over the DAGs of several roots (e.g. the roots of a CAR header), one after the other
+    pub fn from_roots(source: &'a mut S, roots: impl IntoIterator<Item = RawCid>) -> Self {
+        let mut stack: Vec<_> = roots.into_iter().map(|root| (root, 0)).collect();
+        stack.reverse();
+        Self {
+            source,
+            stack,
+            visited: HashSet::new(),
+            failed: false,
+        }
+    }
+}
+
+impl<S: BlockSource> Iterator for DagIter<'_, S> {
+    type Item = Result<DagBlock, TraverseError<S::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let (cid, depth) = self.stack.pop()?;
+            if !self.visited.insert(cid.clone()) {
+                continue;
+            }
+            let data = match cid.identity_data() {
+                Some(data) => data.to_vec(),
+                None => match self.source.get_block(&cid) {
+                    Ok(Some(data)) => data,
+                    Ok(None) => return Some(Err(TraverseError::BlockNotFound(cid))),
+                    Err(e) => {
+                        self.failed = true;
+                        return Some(Err(TraverseError::Source(e)));
+                    }
+                },
+            };
+            let links = block_links(&cid, &data);
+            self.stack
+                .extend(links.into_iter().rev().map(|link| (link, depth + 1)));
+            return Some(Ok(DagBlock { cid, data, depth }));
+        }
+    }
+}
+
+/// Errors related to the DAG traversal
+#[derive(thiserror::Error, Debug)]
+pub enum TraverseError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A block of the DAG is not available in the block source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::CarSlice;
+    use crate::testdata::CARV1_BASIC;
+
+    #[test]
+    fn test_dag_iter() {
+        let slice = CarSlice::new(CARV1_BASIC).unwrap();
+        let roots: Vec<_> = slice
+            .header()
+            .0
+            .roots()
+            .iter()
+            .map(|root| root.to_raw_cid().clone())
+            .collect();
+        let mut blocks: HashMap<_, _> = slice
+            .iter_sections()
+            .map(|section| {
+                let (_, section) = section.unwrap();
+                (section.cid().clone(), section.block().data().to_vec())
+            })
+            .collect();
+
+        // Every block is reachable from the roots, once
+        let walked: Vec<_> = DagIter::from_roots(&mut blocks, roots.clone())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(walked.len(), 8);
+        assert_eq!(walked[0].cid, roots[0]);
+        assert_eq!(walked.last().unwrap().cid, roots[1]);
+        // Parents come before their children, one level deeper
+        for (i, block) in walked.iter().enumerate().skip(1) {
+            let parent = walked[..i]
+                .iter()
+                .rev()
+                .find(|parent| block_links(&parent.cid, &parent.data).contains(&block.cid));
+            match parent {
+                Some(parent) => assert_eq!(block.depth, parent.depth + 1),
+                None => assert_eq!(block.depth, 0),
+            }
+        }
+
+        // A missing block is reported, and its sub-DAG skipped: only the root is left
+        let missing = walked[1].cid.clone();
+        blocks.remove(&missing);
+        let results: Vec<_> = DagIter::new(&mut blocks, &roots[0]).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(&results[1], Err(TraverseError::BlockNotFound(cid)) if *cid == missing));
+
+        // A failing source ends the walk
+        let mut failing = |_: &RawCid| Err::<Option<Vec<u8>>, _>("unavailable");
+        let mut iter = DagIter::new(&mut failing, &roots[0]);
+        assert!(matches!(
+            iter.next(),
+            Some(Err(TraverseError::Source("unavailable")))
+        ));
+        assert!(iter.next().is_none());
+    }
+}
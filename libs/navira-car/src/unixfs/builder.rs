@@ -0,0 +1,860 @@
+//! Creation of CAR archives from files and directories
+//!
+//! [UnixFsBuilder] imports content as UnixFS DAGs, and writes their blocks as a CAR v1 stream:
+//! - files are cut in chunks, either of a fixed size or at content-defined boundaries ([Chunker::Rabin], so that
+//!   an insertion in a file only changes the chunks around it);
+//! - the chunks become raw leaves (CIDv1, sha2-256), linked by dag-pb File nodes laid out as a balanced tree (the
+//!   default of the reference implementations) or as a trickle tree (suited to sequential reads), see [Layout];
+//! - directories are dag-pb Directory nodes linking their entries by name, and symbolic links are Symlink nodes.
+//!
+//! Identical blocks are only written once. The root of the archive is only known once all its content is imported:
+//! the header is written with a placeholder root, and rewritten at the end, so the sink must be seekable.
+//!
+//! ## Examples
+//! ```
+//! use std::io::Cursor;
+//! use navira_car::unixfs::builder::{BuilderOptions, Chunker, UnixFsBuilder};
+//!
+//! let options = BuilderOptions {
+//!     chunker: Chunker::FixedSize(4),
+//!     ..BuilderOptions::default()
+//! };
+//! let mut car = Cursor::new(Vec::new());
+//! let mut builder = UnixFsBuilder::new(options, &mut car).unwrap();
+//! let file = builder.add_file(&b"hello world"[..]).unwrap();
+//! let readme = builder.add_file(&b"# Hello"[..]).unwrap();
+//! let root = builder
+//!     .add_directory(vec![("hello.txt".into(), file), ("README.md".into(), readme)])
+//!     .unwrap();
+//! let summary = builder.finish(&root).unwrap();
+//! assert_eq!(summary.root, root.cid);
+//!
+//! // The archive holds the whole DAG, under the directory root
+//! let reader = navira_car::CarReader::from_bytes(car.get_ref()).unwrap();
+//! assert_eq!(reader.header().unwrap().0.roots()[0].to_raw_cid(), &root.cid);
+//! ```
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::ipld::dagpb::{PbLink, PbNode};
+use crate::ipld::unixfs::{DataType, UnixFsData};
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Block, CarWriter, CarWriterError, Section};
+
+/// CIDv1 prefix of raw blocks hashed with sha2-256 (version, codec, multihash code and length)
+const RAW_SHA2_256_PREFIX: [u8; 4] = [0x01, 0x55, 0x12, 0x20];
+/// CIDv1 prefix of dag-pb blocks hashed with sha2-256 (version, codec, multihash code and length)
+const DAG_PB_SHA2_256_PREFIX: [u8; 4] = [0x01, 0x70, 0x12, 0x20];
+/// Irreducible polynomial of the Rabin fingerprints (degree 53)
+const RABIN_POLYNOMIAL: u64 = 0x3D_A335_8B4D_C173;
+/// Size of the rolling window of the Rabin fingerprints
+const RABIN_WINDOW: usize = 64;
+/// Number of subtrees of each depth in a trickle tree, as the reference implementations
+const TRICKLE_LAYER_REPEAT: usize = 4;
+
+/// How files are cut in chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunker {
+    /// Chunks of the given size (the last one may be shorter)
+    FixedSize(usize),
+    /// Content-defined chunks, cut where the Rabin fingerprint of the last 64 bytes matches a mask
+    Rabin {
+        /// Minimum size of a chunk (the last one may be shorter)
+        min_size: usize,
+        /// Average size of the chunks, rounded up to a power of two
+        avg_size: usize,
+        /// Maximum size of a chunk
+        max_size: usize,
+    },
+}
+
+impl Chunker {
+    /// Content-defined chunks of the given average size, between a third and one and a half of it
+    pub fn rabin(avg_size: usize) -> Self {
+        Chunker::Rabin {
+            min_size: avg_size / 3,
+            avg_size,
+            max_size: avg_size + avg_size / 2,
+        }
+    }
+
+    /// Maximum size of a chunk
+    fn max_size(&self) -> usize {
+        match *self {
+            Chunker::FixedSize(size) => size.max(1),
+            Chunker::Rabin { max_size, .. } => max_size.max(1),
+        }
+    }
+}
+
+impl Default for Chunker {
+    /// Chunks of 256 KiB, as the reference implementations
+    fn default() -> Self {
+        Chunker::FixedSize(256 * 1024)
+    }
+}
+
+/// Shape of the DAG of the files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// All the leaves at the same depth, the tree being as shallow as possible
+    #[default]
+    Balanced,
+    /// The first chunks linked right below the root, followed by subtrees of growing depth, so that the beginning
+    /// of the file is read with few blocks
+    Trickle,
+}
+
+/// Options of the [UnixFsBuilder]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuilderOptions {
+    /// How files are cut in chunks
+    pub chunker: Chunker,
+    /// Shape of the DAG of the files
+    pub layout: Layout,
+    /// Maximum number of links of the intermediate nodes of the files, 174 by default (as the reference implementations)
+    pub max_links: usize,
+}
+
+impl Default for BuilderOptions {
+    fn default() -> Self {
+        Self {
+            chunker: Chunker::default(),
+            layout: Layout::default(),
+            max_links: 174,
+        }
+    }
+}
+
+/// A node imported by the [UnixFsBuilder], to be linked from a directory or used as the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltNode {
+    /// CID of the node
+    pub cid: RawCid,
+    /// Size of the blocks of the DAG of the node (the `Tsize` of the links to it)
+    pub dag_size: u64,
+}
+
+/// A node of a file DAG, as seen by its parent
+#[derive(Debug, Clone)]
+struct Child {
+    cid: RawCid,
+    /// Size of the content below this node
+    file_size: u64,
+    /// Size of the blocks of the subgraph
+    dag_size: u64,
+}
+
+/// Summary of a CAR archive built by the [UnixFsBuilder]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildSummary {
+    /// Root CID of the archive
+    pub root: RawCid,
+    /// Number of sections written
+    pub sections: u64,
+    /// Number of bytes written to the sink (header included)
+    pub bytes_written: u64,
+}
+
+/// Builder of a CAR archive of UnixFS content
+///
+/// Files, directories and symbolic links are imported bottom-up: the nodes returned by [UnixFsBuilder::add_file]
+/// (and the like) are linked by the directories, and the archive is closed with [UnixFsBuilder::finish], naming its root.
+/// [UnixFsBuilder::add_path] imports a whole directory tree from the file system.
+///
+/// Blocks are written to the sink as soon as they are complete, so only a chunk (and the nodes on the way from the
+/// current chunk to the root of its file) is held in memory.
+#[derive(Debug)]
+pub struct UnixFsBuilder<'a, W: Write + Seek> {
+    options: BuilderOptions,
+    writer: CarWriter,
+    sink: &'a mut W,
+    /// Position of the header in the sink
+    header_start: u64,
+    /// CIDs already written, identical blocks are stored once
+    written: HashSet<RawCid>,
+    sections: u64,
+    bytes_written: u64,
+}
+
+impl<'a, W: Write + Seek> UnixFsBuilder<'a, W> {
+    /// Create a builder writing a CAR v1 stream to the sink, from its current position
+    pub fn new(options: BuilderOptions, sink: &'a mut W) -> Result<Self, BuildError> {
+        let header_start = sink.stream_position()?;
+        let buffer_size = (2 * options.chunker.max_size()).max(4 * 1024 * 1024);
+        Ok(Self {
+            options: BuilderOptions {
+                max_links: options.max_links.max(2),
+                ..options
+            },
+            writer: CarWriter::with_buffer_size(vec![placeholder_root()], buffer_size),
+            sink,
+            header_start,
+            written: HashSet::new(),
+            sections: 0,
+            bytes_written: 0,
+        })
+    }
+
+    /// Import the content of a file, returning the root of its DAG
+    ///
+    /// A file of a single chunk (including the empty file) is imported as a single raw block.
+    pub fn add_file<R: Read>(&mut self, content: R) -> Result<BuiltNode, BuildError> {
+        let mut chunks = ChunkStream::new(content, self.options.chunker);
+        let root = match self.options.layout {
+            Layout::Balanced => self.build_balanced(&mut chunks)?,
+            Layout::Trickle => self.build_trickle(&mut chunks)?,
+        };
+        Ok(BuiltNode {
+            cid: root.cid,
+            dag_size: root.dag_size,
+        })
+    }
+
+    /// Import a directory, linking the given entries by name
+    ///
+    /// The entries are sorted by name, as required by UnixFS. The names must be unique and non-empty, and must
+    /// not contain a `/`.
+    pub fn add_directory(
+        &mut self,
+        mut entries: Vec<(String, BuiltNode)>,
+    ) -> Result<BuiltNode, BuildError> {
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (i, (name, _)) in entries.iter().enumerate() {
+            let duplicate = i
+                .checked_sub(1)
+                .and_then(|previous| entries.get(previous))
+                .is_some_and(|(previous, _)| previous == name);
+            if name.is_empty() || name.contains('/') || duplicate {
+                return Err(BuildError::InvalidName(name.clone()));
+            }
+        }
+        let node = PbNode {
+            data: Some(UnixFsData::new(DataType::Directory).encode()),
+            links: entries
+                .iter()
+                .map(|(name, entry)| PbLink {
+                    cid: entry.cid.clone(),
+                    name: Some(name.clone()),
+                    tsize: Some(entry.dag_size),
+                })
+                .collect(),
+        };
+        let children_size: u64 = entries.iter().map(|(_, entry)| entry.dag_size).sum();
+        let (cid, size) = self.write_node(&node)?;
+        Ok(BuiltNode {
+            cid,
+            dag_size: size + children_size,
+        })
+    }
+
+    /// Import a symbolic link to the given target
+    pub fn add_symlink(&mut self, target: &str) -> Result<BuiltNode, BuildError> {
+        let mut unixfs = UnixFsData::new(DataType::Symlink);
+        unixfs.data = Some(target.as_bytes().to_vec());
+        let node = PbNode {
+            data: Some(unixfs.encode()),
+            links: Vec::new(),
+        };
+        let (cid, dag_size) = self.write_node(&node)?;
+        Ok(BuiltNode { cid, dag_size })
+    }
+
+    /// Import a file, a symbolic link, or a whole directory tree from the file system
+    ///
+    /// Symbolic links are imported as such, not followed. The names (and link targets) must be valid UTF-8, and
+    /// special files (sockets, devices, etc.) are rejected.
+    pub fn add_path<P: AsRef<Path>>(&mut self, path: P) -> Result<BuiltNode, BuildError> {
+        let path = path.as_ref();
+        let metadata = path.symlink_metadata()?;
+        if metadata.is_file() {
+            self.add_file(File::open(path)?)
+        } else if metadata.is_symlink() {
+            let target = path.read_link()?;
+            let target = target
+                .to_str()
+                .ok_or_else(|| BuildError::InvalidPath(path.to_path_buf()))?;
+            self.add_symlink(target)
+        } else if metadata.is_dir() {
+            let mut entries = Vec::new();
+            for entry in path.read_dir()? {
+                let entry = entry?;
+                let name = entry
+                    .file_name()
+                    .into_string()
+                    .map_err(|_| BuildError::InvalidPath(entry.path()))?;
+                entries.push((name, self.add_path(entry.path())?));
+            }
+            self.add_directory(entries)
+        } else {
+            Err(BuildError::InvalidPath(path.to_path_buf()))
+        }
+    }
+
+    /// Flush the remaining blocks, and rewrite the header with the given root
+    ///
+    /// The sink is left positioned at the end of the archive.
+    pub fn finish(mut self, root: &BuiltNode) -> Result<BuildSummary, BuildError> {
+        self.flush()?;
+        // The placeholder has the size of any CIDv1 sha2-256, as all the built nodes
+        let mut header = CarWriter::new(vec![root.cid.clone()]);
+        let mut header_bytes = vec![0u8; 256];
+        let n = header.send_data(&mut header_bytes);
+        self.sink.seek(SeekFrom::Start(self.header_start))?;
+        self.sink
+            .write_all(header_bytes.get(..n).unwrap_or_default())?;
+        self.sink
+            .seek(SeekFrom::Start(self.header_start + self.bytes_written))?;
+        self.sink.flush()?;
+        Ok(BuildSummary {
+            root: root.cid.clone(),
+            sections: self.sections,
+            bytes_written: self.bytes_written,
+        })
+    }
+
+    /// Build a balanced file DAG: each level is filled before the tree grows by a level
+    fn build_balanced<R: Read>(
+        &mut self,
+        chunks: &mut ChunkStream<R>,
+    ) -> Result<Child, BuildError> {
+        let mut root = self.first_leaf(chunks)?;
+        let mut depth = 0;
+        while chunks.has_more()? {
+            let mut children = vec![root];
+            while children.len() < self.options.max_links
+                && let Some(child) = self.fill_balanced(chunks, depth)?
+            {
+                children.push(child);
+            }
+            root = self.write_file_node(&children)?;
+            depth += 1;
+        }
+        Ok(root)
+    }
+
+    /// Build a full subtree of the given depth (or less, at the end of the file)
+    fn fill_balanced<R: Read>(
+        &mut self,
+        chunks: &mut ChunkStream<R>,
+        depth: usize,
+    ) -> Result<Option<Child>, BuildError> {
+        if depth == 0 {
+            return self.next_leaf(chunks);
+        }
+        let mut children = Vec::new();
+        while children.len() < self.options.max_links
+            && let Some(child) = self.fill_balanced(chunks, depth - 1)?
+        {
+            children.push(child);
+        }
+        if children.is_empty() {
+            return Ok(None);
+        }
+        self.write_file_node(&children).map(Some)
+    }
+
+    /// Build a trickle file DAG: direct leaves, then [TRICKLE_LAYER_REPEAT] subtrees of each growing depth
+    fn build_trickle<R: Read>(&mut self, chunks: &mut ChunkStream<R>) -> Result<Child, BuildError> {
+        let first = self.first_leaf(chunks)?;
+        if !chunks.has_more()? {
+            return Ok(first);
+        }
+        let mut children = vec![first];
+        self.fill_trickle(chunks, &mut children, None)?;
+        self.write_file_node(&children)
+    }
+
+    /// Fill the children of a trickle node, with subtrees up to the given depth (unbounded for the root)
+    fn fill_trickle<R: Read>(
+        &mut self,
+        chunks: &mut ChunkStream<R>,
+        children: &mut Vec<Child>,
+        max_depth: Option<usize>,
+    ) -> Result<(), BuildError> {
+        while children.len() < self.options.max_links
+            && let Some(leaf) = self.next_leaf(chunks)?
+        {
+            children.push(leaf);
+        }
+        let mut depth = 1;
+        while max_depth.is_none_or(|max_depth| depth < max_depth) {
+            for _ in 0..TRICKLE_LAYER_REPEAT {
+                if !chunks.has_more()? {
+                    return Ok(());
+                }
+                let mut sub_children = Vec::new();
+                self.fill_trickle(chunks, &mut sub_children, Some(depth))?;
+                children.push(self.write_file_node(&sub_children)?);
+            }
+            depth += 1;
+        }
+        Ok(())
+    }
+
+    /// Write the first chunk as a raw leaf, an empty content having a single empty chunk
+    fn first_leaf<R: Read>(&mut self, chunks: &mut ChunkStream<R>) -> Result<Child, BuildError> {
+        match chunks.next_chunk()? {
+            Some(chunk) => self.write_leaf(chunk),
+            None => self.write_leaf(Vec::new()),
+        }
+    }
+
+    /// Write the next chunk as a raw leaf, if any
+    fn next_leaf<R: Read>(
+        &mut self,
+        chunks: &mut ChunkStream<R>,
+    ) -> Result<Option<Child>, BuildError> {
+        match chunks.next_chunk()? {
+            Some(chunk) => self.write_leaf(chunk).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Write a chunk as a raw leaf
+    fn write_leaf(&mut self, chunk: Vec<u8>) -> Result<Child, BuildError> {
+        let cid = block_cid(RAW_SHA2_256_PREFIX, &chunk);
+        let file_size = chunk.len() as u64;
+        let dag_size = self.write_block(cid.clone(), chunk)?;
+        Ok(Child {
+            cid,
+            file_size,
+            dag_size,
+        })
+    }
+
+    /// Write a dag-pb UnixFS File node linking the given children
+    fn write_file_node(&mut self, children: &[Child]) -> Result<Child, BuildError> {
+        let mut unixfs = UnixFsData::new(DataType::File);
+        unixfs.filesize = Some(children.iter().map(|c| c.file_size).sum());
+        unixfs.blocksizes = children.iter().map(|c| c.file_size).collect();
+        let node = PbNode {
+            data: Some(unixfs.encode()),
+            links: children
+                .iter()
+                .map(|c| PbLink {
+                    cid: c.cid.clone(),
+                    name: Some(String::new()),
+                    tsize: Some(c.dag_size),
+                })
+                .collect(),
+        };
+        let (cid, size) = self.write_node(&node)?;
+        Ok(Child {
+            cid,
+            file_size: unixfs.filesize.unwrap_or(0),
+            dag_size: size + children.iter().map(|c| c.dag_size).sum::<u64>(),
+        })
+    }
+
+    /// Write a dag-pb node, returning its CID and size
+    fn write_node(&mut self, node: &PbNode) -> Result<(RawCid, u64), BuildError> {
+        let data = node.encode();
+        let cid = block_cid(DAG_PB_SHA2_256_PREFIX, &data);
+        let size = self.write_block(cid.clone(), data)?;
+        Ok((cid, size))
+    }
+
+    /// Write a block to the CAR stream (unless already written), returning its size
+    fn write_block(&mut self, cid: RawCid, data: Vec<u8>) -> Result<u64, BuildError> {
+        let size = data.len() as u64;
+        if !self.written.insert(cid.clone()) {
+            return Ok(size);
+        }
+        let section = Section::new(cid, Block::new(data));
+        loop {
+            match self.writer.write_section(&section) {
+                Ok(_) => break,
+                Err(CarWriterError::BufferFull) if self.writer.has_data_to_send() => {
+                    self.flush()?
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.sections += 1;
+        Ok(size)
+    }
+
+    /// Flush the CAR writer buffer to the sink
+    fn flush(&mut self) -> Result<(), BuildError> {
+        let mut buf = vec![0u8; 64 * 1024];
+        while self.writer.has_data_to_send() {
+            let n = self.writer.send_data(&mut buf);
+            self.sink.write_all(buf.get(..n).unwrap_or_default())?;
+            self.bytes_written += n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Build a CAR archive of a file or a directory tree of the file system, see [UnixFsBuilder::add_path]
+///
+/// # Arguments
+/// * `path` - The file or directory to import.
+/// * `options` - How the files are chunked and laid out.
+/// * `sink` - Where to write the CAR stream, from its current position.
+///
+/// # Returns
+/// * `Ok(BuildSummary)` - The root CID, and statistics about the CAR.
+/// * `Err(BuildError)` - Reading the file system or writing the sink failed, or a name is invalid.
+pub fn write_car<P: AsRef<Path>, W: Write + Seek>(
+    path: P,
+    options: &BuilderOptions,
+    sink: &mut W,
+) -> Result<BuildSummary, BuildError> {
+    let mut builder = UnixFsBuilder::new(options.clone(), sink)?;
+    let root = builder.add_path(path)?;
+    builder.finish(&root)
+}
+
+/// Computes the CIDv1 of a block with the given prefix, using sha2-256
+fn block_cid(prefix: [u8; 4], data: &[u8]) -> RawCid {
+    let mut bytes = Vec::with_capacity(prefix.len() + 32);
+    bytes.extend_from_slice(&prefix);
+    bytes.extend_from_slice(&Sha256::digest(data));
+    RawCid::new(bytes)
+}
+
+/// Placeholder root of the header, the same size as the final one (CIDv1, sha2-256)
+fn placeholder_root() -> RawCid {
+    let mut placeholder = DAG_PB_SHA2_256_PREFIX.to_vec();
+    placeholder.resize(placeholder.len() + 32, 0);
+    RawCid::new(placeholder)
+}
+
+/// Chunks of a content, cut by a [Chunker]
+#[derive(Debug)]
+struct ChunkStream<R: Read> {
+    reader: R,
+    chunker: Chunker,
+    /// Tables of the Rabin fingerprints, for the Rabin chunker
+    rabin: Option<Box<RabinTables>>,
+    /// Content read but not chunked yet
+    buf: Vec<u8>,
+    /// Has the end of the content been reached?
+    eof: bool,
+    /// Next chunk, read ahead by [ChunkStream::has_more]
+    peeked: Option<Vec<u8>>,
+}
+
+impl<R: Read> ChunkStream<R> {
+    fn new(reader: R, chunker: Chunker) -> Self {
+        Self {
+            reader,
+            chunker,
+            rabin: matches!(chunker, Chunker::Rabin { .. }).then(|| Box::new(RabinTables::new())),
+            buf: Vec::new(),
+            eof: false,
+            peeked: None,
+        }
+    }
+
+    /// Returns the next chunk, `None` at the end of the content
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if let Some(chunk) = self.peeked.take() {
+            return Ok(Some(chunk));
+        }
+        self.fill()?;
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let len = match (self.chunker, &self.rabin) {
+            (
+                Chunker::Rabin {
+                    min_size,
+                    avg_size,
+                    max_size,
+                },
+                Some(rabin),
+            ) => rabin.cut(&self.buf, min_size, avg_size, max_size.max(1)),
+            (chunker, _) => chunker.max_size().min(self.buf.len()),
+        };
+        Ok(Some(self.buf.drain(..len).collect()))
+    }
+
+    /// Returns true if there is a chunk left
+    fn has_more(&mut self) -> io::Result<bool> {
+        if self.peeked.is_none() {
+            self.peeked = self.next_chunk()?;
+        }
+        Ok(self.peeked.is_some())
+    }
+
+    /// Read the content until a whole chunk is buffered (or the end of the content)
+    fn fill(&mut self) -> io::Result<()> {
+        let target = self.chunker.max_size();
+        while !self.eof && self.buf.len() < target {
+            let start = self.buf.len();
+            self.buf.resize(target, 0);
+            let read = self
+                .reader
+                .read(self.buf.get_mut(start..).unwrap_or_default());
+            self.buf.truncate(start + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) => self.eof = true,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lookup tables of the Rabin fingerprints over a rolling window, for [RABIN_POLYNOMIAL]
+#[derive(Debug)]
+struct RabinTables {
+    /// Contribution of the byte leaving the window
+    out: [u64; 256],
+    /// Reduction of the top byte of the fingerprint, as it is shifted
+    reduce: [u64; 256],
+}
+
+impl RabinTables {
+    fn new() -> Self {
+        let degree = poly_degree(RABIN_POLYNOMIAL);
+        let mut tables = Self {
+            out: [0; 256],
+            reduce: [0; 256],
+        };
+        let entries = tables.out.iter_mut().zip(tables.reduce.iter_mut());
+        for (byte, (out, reduce)) in (0..256u64).zip(entries) {
+            let mut hash = poly_mod(byte, RABIN_POLYNOMIAL);
+            for _ in 1..RABIN_WINDOW {
+                hash = poly_mod(hash << 8, RABIN_POLYNOMIAL);
+            }
+            *out = hash;
+            *reduce = poly_mod(byte << degree, RABIN_POLYNOMIAL) | byte << degree;
+        }
+        tables
+    }
+
+    /// Length of the next chunk of the data
+    ///
+    /// The chunk ends after the first byte (past the minimum size) where the low bits of the fingerprint are zero,
+    /// as many bits as needed for the average size. The data is either longer than the maximum size, or the end of
+    /// the content.
+    fn cut(&self, data: &[u8], min_size: usize, avg_size: usize, max_size: usize) -> usize {
+        let mask = avg_size.max(1).next_power_of_two() as u64 - 1;
+        let shift = poly_degree(RABIN_POLYNOMIAL) - 8;
+        let mut window = [0u8; RABIN_WINDOW];
+        let mut digest = 0u64;
+        for (i, &byte) in data.iter().take(max_size).enumerate() {
+            if let Some(slot) = window.get_mut(i % RABIN_WINDOW) {
+                digest ^= self.out.get(*slot as usize).copied().unwrap_or_default();
+                *slot = byte;
+            }
+            let top = (digest >> shift) as usize & 0xff;
+            digest =
+                (digest << 8 | byte as u64) ^ self.reduce.get(top).copied().unwrap_or_default();
+            if i + 1 >= min_size && digest & mask == 0 {
+                return i + 1;
+            }
+        }
+        data.len().min(max_size)
+    }
+}
+
+/// Degree of a polynomial over GF(2), its bits being the coefficients
+fn poly_degree(p: u64) -> u32 {
+    u64::BITS - 1 - p.leading_zeros()
+}
+
+/// Remainder of the division of two polynomials over GF(2)
+fn poly_mod(mut x: u64, p: u64) -> u64 {
+    let degree = poly_degree(p);
+    while x != 0 && poly_degree(x) >= degree {
+        x ^= p << (poly_degree(x) - degree);
+    }
+    x
+}
+
+/// Errors related to the creation of a CAR archive of UnixFS content
+#[derive(thiserror::Error, Debug)]
+pub enum BuildError {
+    /// IO error while reading the content or writing the sink
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// A block could not be written to the CAR stream
+    #[error("CAR writer error: {0}")]
+    Writer(#[from] CarWriterError),
+    /// A directory entry name is empty, duplicated, or contains a `/`
+    #[error("Invalid directory entry name: {0:?}")]
+    InvalidName(String),
+    /// A path is not valid UTF-8, or is neither a file, a directory nor a symbolic link
+    #[error("Unsupported path: {0:?}")]
+    InvalidPath(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::ipld::BlockSource;
+    use crate::unixfs::{cat, resolve};
+
+    /// Read back the blocks of a CAR archive, with its root
+    fn blocks(car: &[u8]) -> (RawCid, HashMap<RawCid, Vec<u8>>) {
+        let mut reader = crate::CarReader::from_bytes(car).unwrap();
+        let root = reader.header().unwrap().0.roots()[0].to_raw_cid().clone();
+        let mut blocks = HashMap::new();
+        while let Ok(section) = reader.read_section() {
+            blocks.insert(section.cid().clone(), section.block().data().to_vec());
+        }
+        (root, blocks)
+    }
+
+    /// Build a CAR of a single file
+    fn build_file(content: &[u8], options: BuilderOptions) -> (BuildSummary, Vec<u8>) {
+        let mut car = Cursor::new(Vec::new());
+        let mut builder = UnixFsBuilder::new(options, &mut car).unwrap();
+        let file = builder.add_file(content).unwrap();
+        let summary = builder.finish(&file).unwrap();
+        assert_eq!(summary.bytes_written, car.get_ref().len() as u64);
+        (summary, car.into_inner())
+    }
+
+    /// Depth of the file DAG below a node (0 for a leaf)
+    fn depth(blocks: &mut HashMap<RawCid, Vec<u8>>, cid: &RawCid) -> usize {
+        let data = blocks.get_block(cid).unwrap().unwrap();
+        match PbNode::decode(&data) {
+            Ok(node) if cid.codec() == Some(crate::ipld::CODEC_DAG_PB) => {
+                1 + node
+                    .links
+                    .iter()
+                    .map(|link| depth(blocks, &link.cid))
+                    .max()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_builder_file_layouts() {
+        let content: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for layout in [Layout::Balanced, Layout::Trickle] {
+            for len in [0, 1, 10, 11, 100, 1000] {
+                let options = BuilderOptions {
+                    chunker: Chunker::FixedSize(10),
+                    layout,
+                    max_links: 3,
+                };
+                let (summary, car) = build_file(&content[..len], options);
+                let (root, mut blocks) = blocks(&car);
+                assert_eq!(root, summary.root);
+                assert_eq!(cat(&mut blocks, &root).unwrap(), &content[..len]);
+                // A single chunk is a raw block
+                assert_eq!(root.codec() == Some(0x55), len <= 10, "{layout:?} {len}");
+            }
+        }
+
+        // 100 chunks of at most 3 links per node: a balanced tree is 5 levels deep, while the root of a trickle tree
+        // links 3 leaves, 4 subtrees of depth 1, 4 of depth 2 (15 leaves each), and 1 of depth 3 for the last 25
+        let options = |layout| BuilderOptions {
+            chunker: Chunker::FixedSize(10),
+            layout,
+            max_links: 3,
+        };
+        let (_, car) = build_file(&content, options(Layout::Balanced));
+        let (root, mut balanced) = blocks(&car);
+        assert_eq!(depth(&mut balanced, &root), 5);
+        let (_, car) = build_file(&content, options(Layout::Trickle));
+        let (root, mut trickle) = blocks(&car);
+        assert_eq!(depth(&mut trickle, &root), 4);
+        let node = PbNode::decode(&trickle[&root]).unwrap();
+        assert_eq!(node.links.len(), 12);
+        // The first chunks are right below the root of a trickle tree
+        assert_eq!(trickle[&node.links[2].cid], &content[20..30]);
+    }
+
+    #[test]
+    fn test_builder_rabin_chunks() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let content: Vec<u8> = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let chunks = |content: &[u8]| {
+            let mut stream = ChunkStream::new(content, Chunker::rabin(4096));
+            std::iter::from_fn(|| stream.next_chunk().unwrap()).collect::<Vec<_>>()
+        };
+        let original = chunks(&content);
+        assert_eq!(original.concat(), content);
+        assert!(original.len() > 20);
+        for chunk in &original[..original.len() - 1] {
+            assert!((4096 / 3..=4096 + 2048).contains(&chunk.len()));
+        }
+
+        // An insertion only changes the chunks around it
+        let mut edited = content.clone();
+        edited.insert(100_000, 0);
+        let edited = chunks(&edited);
+        let shared = edited
+            .iter()
+            .filter(|chunk| original.contains(chunk))
+            .count();
+        assert!(shared + 3 >= original.len(), "{shared}/{}", original.len());
+    }
+
+    #[test]
+    fn test_builder_directories() {
+        let dir = std::env::temp_dir().join(format!("navira-car-unixfs-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("hello.txt"), b"hello world").unwrap();
+        std::fs::write(dir.join("sub/data.bin"), vec![7u8; 5000]).unwrap();
+        std::fs::write(dir.join("sub/copy.txt"), b"hello world").unwrap();
+
+        let options = BuilderOptions {
+            chunker: Chunker::FixedSize(1024),
+            ..BuilderOptions::default()
+        };
+        let mut car = Cursor::new(Vec::new());
+        let summary = write_car(&dir, &options, &mut car).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (root, mut blocks) = blocks(car.get_ref());
+        assert_eq!(root, summary.root);
+        // Identical blocks are written once: the two copies, and the 4 full chunks of data.bin
+        assert_eq!(summary.sections, blocks.len() as u64);
+        assert_eq!(blocks.len(), 6);
+        let file = resolve(&mut blocks, &root, "sub/data.bin").unwrap();
+        assert_eq!(cat(&mut blocks, &file).unwrap(), vec![7u8; 5000]);
+        let copy = resolve(&mut blocks, &root, "sub/copy.txt").unwrap();
+        assert_eq!(resolve(&mut blocks, &root, "hello.txt").unwrap(), copy);
+
+        // Entries are sorted, and the link sizes add up
+        let node = PbNode::decode(&blocks[&root]).unwrap();
+        let names: Vec<_> = node
+            .links
+            .iter()
+            .filter_map(|l| l.name.as_deref())
+            .collect();
+        assert_eq!(names, ["hello.txt", "sub"]);
+
+        let mut car = Cursor::new(Vec::new());
+        let mut builder = UnixFsBuilder::new(options, &mut car).unwrap();
+        let file = builder.add_file(&b"a"[..]).unwrap();
+        for invalid in ["", "a/b"] {
+            assert!(matches!(
+                builder.add_directory(vec![(invalid.into(), file.clone())]),
+                Err(BuildError::InvalidName(_))
+            ));
+        }
+        assert!(matches!(
+            builder.add_directory(vec![("a".into(), file.clone()), ("a".into(), file)]),
+            Err(BuildError::InvalidName(name)) if name == "a"
+        ));
+    }
+}
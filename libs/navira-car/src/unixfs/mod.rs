@@ -0,0 +1,683 @@
+//! Extraction of UnixFS content from the blocks of a CAR archive
+//!
+//! The [ipld module](crate::ipld) decodes the dag-pb nodes and their UnixFS metadata, block by block. This module
+//! builds on it to get the content back out of an archive: [FileReader] reassembles a UnixFS file from its root
+//! CID, streaming its content chunk by chunk, and [cat] collects it in memory.
+//!
+//! A UnixFS file is either a single raw block, or a tree of dag-pb nodes: each node holds some inline data (usually
+//! none, except in the leaves), followed by the content of its children, in link order. The tree is walked
+//! depth-first, so that only the path from the root to the current chunk is held in memory.
+//!
+//! Paths below a root (`<cid>/a/b/c.txt`) are resolved with [resolve], through plain directories (their entries are
+//! the named links of the node) as well as HAMT-sharded directories (large directories, spread over a tree of shards).
+//!
+//! The other way around, the [builder] imports files and directory trees as UnixFS DAGs, written as a new CAR archive.
+//!
+//! ## Examples
+//! ```
+//! use std::collections::HashMap;
+//! use navira_car::ipld::dagpb::{PbLink, PbNode};
+//! use navira_car::ipld::unixfs::{DataType, UnixFsData};
+//! use navira_car::unixfs::{FileReader, cat};
+//! use navira_car::wire::cid::RawCid;
+//!
+//! // "hello world", split in two raw leaves (identity CIDs, to keep the example short)
+//! let hello = RawCid::from_hex("0155000668656c6c6f20").unwrap();
+//! let world = RawCid::from_hex("01550005776f726c64").unwrap();
+//! let mut metadata = UnixFsData::new(DataType::File);
+//! metadata.filesize = Some(11);
+//! metadata.blocksizes = vec![6, 5];
+//! let links = [hello, world].map(|cid| PbLink { cid, name: Some(String::new()), tsize: None });
+//! let root_node = PbNode { data: Some(metadata.encode()), links: links.to_vec() };
+//!
+//! let root = RawCid::from_hex(&format!("01701220{}", "00".repeat(32))).unwrap(); // any CID, the source is a map
+//! let mut blocks = HashMap::from([(root.clone(), root_node.encode())]);
+//! assert_eq!(cat(&mut blocks, &root).unwrap(), b"hello world");
+//!
+//! // Or chunk by chunk, knowing the size of the file beforehand
+//! let reader = FileReader::new(&mut blocks, &root).unwrap();
+//! assert_eq!(reader.file_size(), 11);
+//! let chunks: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+//! assert_eq!(chunks, [b"hello ".to_vec(), b"world".to_vec()]);
+//! ```
+
+pub mod builder;
+
+use crate::ipld::dagpb::{DagPbError, PbNode};
+use crate::ipld::unixfs::{DataType, UnixFsData, UnixFsError};
+use crate::ipld::{BlockSource, CODEC_DAG_PB, CODEC_RAW, cid_codec};
+use crate::wire::cid::RawCid;
+
+/// A part of the file still to read
+#[derive(Debug)]
+enum Pending {
+    /// A node of the file DAG, to fetch
+    Node(RawCid),
+    /// Inline data of a node already fetched
+    Data(Vec<u8>),
+}
+
+/// Streaming reader of a UnixFS file, yielding its content chunk by chunk
+///
+/// Each chunk is the content of a leaf (a raw block, or the inline data of a dag-pb node), in file order. Empty
+/// chunks are skipped. The chunks are fetched from any [BlockSource] as they are read, nothing is prefetched.
+///
+/// The content read is checked against the size recorded in the root: a file shorter or longer than announced
+/// ends with [CatError::SizeMismatch]. Any error ends the reading.
+#[derive(Debug)]
+pub struct FileReader<'a, S: BlockSource> {
+    /// Source of the blocks
+    source: &'a mut S,
+    /// Parts of the file still to read, the next one last
+    stack: Vec<Pending>,
+    /// Size of the file, as recorded in its root
+    file_size: u64,
+    /// Number of bytes of content yielded so far
+    position: u64,
+    /// Has the reading ended (end of the file, or error)?
+    done: bool,
+}
+
+impl<'a, S: BlockSource> FileReader<'a, S> {
+    /// Create a reader of the file with the given root
+    ///
+    /// The root block is fetched right away, to check that it is a file and learn its size.
+    ///
+    /// ## Returns
+    /// - `Ok(FileReader)` if the root is a raw block or a UnixFS file node.
+    /// - `Err(CatError)` if the root block cannot be fetched, or is not a file.
+    pub fn new(source: &'a mut S, root: &RawCid) -> Result<Self, CatError<S::Error>> {
+        let mut reader = Self {
+            source,
+            stack: Vec::new(),
+            file_size: 0,
+            position: 0,
+            done: false,
+        };
+        let data = reader.fetch(root)?;
+        reader.file_size = reader.expand(root, data)?;
+        Ok(reader)
+    }
+
+    /// Size of the file, as recorded in its root (the length of a raw root block)
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Number of bytes of content yielded so far
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Fetch a block from the source
+    fn fetch(&mut self, cid: &RawCid) -> Result<Vec<u8>, CatError<S::Error>> {
+        if let Some(data) = cid.identity_data() {
+            return Ok(data.to_vec());
+        }
+        self.source
+            .get_block(cid)
+            .map_err(CatError::Source)?
+            .ok_or_else(|| CatError::BlockNotFound(cid.clone()))
+    }
+
+    /// Push the parts of a node on the stack, returning the content size it records
+    fn expand(&mut self, cid: &RawCid, data: Vec<u8>) -> Result<u64, CatError<S::Error>> {
+        match cid_codec(cid) {
+            Some(CODEC_RAW) => {
+                let size = data.len() as u64;
+                self.stack.push(Pending::Data(data));
+                Ok(size)
+            }
+            Some(CODEC_DAG_PB) => {
+                let node = PbNode::decode(&data)?;
+                let unixfs = UnixFsData::decode(node.data.as_deref().unwrap_or_default())?;
+                if !matches!(unixfs.data_type, DataType::File | DataType::Raw) {
+                    return Err(CatError::NotAFile(cid.clone()));
+                }
+                self.stack.extend(
+                    node.links
+                        .into_iter()
+                        .rev()
+                        .map(|link| Pending::Node(link.cid)),
+                );
+                let inline = unixfs.data.unwrap_or_default();
+                let size = unixfs
+                    .filesize
+                    .unwrap_or_else(|| inline.len() as u64 + unixfs.blocksizes.iter().sum::<u64>());
+                self.stack.push(Pending::Data(inline));
+                Ok(size)
+            }
+            _ => Err(CatError::NotAFile(cid.clone())),
+        }
+    }
+
+    /// Read the next chunk of content, `None` at the end of the file
+    fn read_chunk(&mut self) -> Result<Option<Vec<u8>>, CatError<S::Error>> {
+        while let Some(pending) = self.stack.pop() {
+            match pending {
+                Pending::Data(data) if data.is_empty() => continue,
+                Pending::Data(data) => {
+                    self.position += data.len() as u64;
+                    if self.position > self.file_size {
+                        break;
+                    }
+                    return Ok(Some(data));
+                }
+                Pending::Node(cid) => {
+                    let data = self.fetch(&cid)?;
+                    self.expand(&cid, data)?;
+                }
+            }
+        }
+        if self.position != self.file_size {
+            return Err(CatError::SizeMismatch {
+                expected: self.file_size,
+                actual: self.position,
+            });
+        }
+        Ok(None)
+    }
+}
+
+impl<S: BlockSource> Iterator for FileReader<'_, S> {
+    type Item = Result<Vec<u8>, CatError<S::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_chunk().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Read the whole content of a UnixFS file in memory
+///
+/// See [FileReader] to stream large files instead.
+pub fn cat<S: BlockSource>(source: &mut S, root: &RawCid) -> Result<Vec<u8>, CatError<S::Error>> {
+    let mut reader = FileReader::new(source, root)?;
+    let mut content = Vec::with_capacity(reader.file_size().min(1 << 24) as usize);
+    for chunk in &mut reader {
+        content.extend_from_slice(&chunk?);
+    }
+    Ok(content)
+}
+
+/// Multicodec code of the murmur3-x64-64 hash, the only hash function of the UnixFS HAMT shards
+const HAMT_HASH_MURMUR3: u64 = 0x22;
+
+/// Resolve a UnixFS path from a root CID, returning the CID of the node it designates
+///
+/// The path is a `/`-separated list of entry names relative to the root (e.g. `a/b/c.txt`), empty segments are
+/// ignored, so the empty path resolves to the root itself. Every node but the last one must be a directory: either
+/// a plain one, whose entries are its named links, or a HAMT-sharded one, whose entries are looked up by the hash
+/// of their name (only the shards on the way to the entry are fetched). The terminal node itself is not fetched.
+///
+/// ## Arguments
+/// * `source` - The block source to fetch the directory blocks from.
+/// * `root` - The CID of the directory to start the resolution from.
+/// * `path` - The path to resolve, relative to the root.
+///
+/// ## Returns
+/// - `Ok(RawCid)` with the CID of the terminal node.
+/// - `Err(ResolveError)` if a block is missing or invalid, a node on the way is not a directory, or an entry does not exist.
+pub fn resolve<S: BlockSource>(
+    source: &mut S,
+    root: &RawCid,
+    path: &str,
+) -> Result<RawCid, ResolveError<S::Error>> {
+    let mut cid = root.clone();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let (node, unixfs) = load_directory(source, &cid)?;
+        let entry = match unixfs.data_type {
+            DataType::Directory => node.link_by_name(segment).map(|link| link.cid.clone()),
+            _ => lookup_hamt(source, &cid, node, &unixfs, segment)?,
+        };
+        cid = entry.ok_or_else(|| ResolveError::EntryNotFound {
+            cid,
+            name: segment.to_owned(),
+        })?;
+    }
+    Ok(cid)
+}
+
+/// Fetch and decode a directory node (plain directory or HAMT shard)
+fn load_directory<S: BlockSource>(
+    source: &mut S,
+    cid: &RawCid,
+) -> Result<(PbNode, UnixFsData), ResolveError<S::Error>> {
+    if cid_codec(cid) != Some(CODEC_DAG_PB) {
+        return Err(ResolveError::NotADirectory(cid.clone()));
+    }
+    let data = match cid.identity_data() {
+        Some(data) => data.to_vec(),
+        None => source
+            .get_block(cid)
+            .map_err(ResolveError::Source)?
+            .ok_or_else(|| ResolveError::BlockNotFound(cid.clone()))?,
+    };
+    let node = PbNode::decode(&data)?;
+    let unixfs = UnixFsData::decode(node.data.as_deref().unwrap_or_default())?;
+    if !matches!(unixfs.data_type, DataType::Directory | DataType::HamtShard) {
+        return Err(ResolveError::NotADirectory(cid.clone()));
+    }
+    Ok((node, unixfs))
+}
+
+/// Look up an entry of a HAMT-sharded directory, descending through its shards
+///
+/// At each level, the next `log2(fanout)` bits of the hash of the name give the index of the bucket, and the links of
+/// the shard are named after the index of their bucket (upper-case hex, padded to the width of `fanout - 1`): a link
+/// named by the index alone leads to a sub-shard, while an entry link has its name right after the index.
+fn lookup_hamt<S: BlockSource>(
+    source: &mut S,
+    cid: &RawCid,
+    mut node: PbNode,
+    unixfs: &UnixFsData,
+    name: &str,
+) -> Result<Option<RawCid>, ResolveError<S::Error>> {
+    let fanout = match (unixfs.hash_type, unixfs.fanout) {
+        (Some(HAMT_HASH_MURMUR3), Some(fanout)) if fanout > 1 && fanout.is_power_of_two() => fanout,
+        _ => return Err(ResolveError::InvalidHamt(cid.clone())),
+    };
+    let bits = fanout.trailing_zeros();
+    let width = format!("{:X}", fanout - 1).len();
+    let hash = murmur3_x64_64(name.as_bytes());
+    let mut shard = cid.clone();
+    let mut consumed = 0;
+    loop {
+        // Names colliding on the whole hash cannot be told apart
+        if consumed + bits > u64::BITS {
+            return Err(ResolveError::InvalidHamt(shard));
+        }
+        let index = (hash << consumed) >> (u64::BITS - bits);
+        consumed += bits;
+        let prefix = format!("{index:0width$X}");
+        let Some(link) = node.links.into_iter().find(|link| {
+            link.name
+                .as_deref()
+                .is_some_and(|link_name| link_name.starts_with(&prefix))
+        }) else {
+            return Ok(None);
+        };
+        match link
+            .name
+            .as_deref()
+            .map(|link_name| &link_name[prefix.len()..])
+        {
+            Some("") => {
+                let (sub_node, sub_unixfs) = load_directory(source, &link.cid)?;
+                if sub_unixfs.data_type != DataType::HamtShard || sub_unixfs.fanout != Some(fanout)
+                {
+                    return Err(ResolveError::InvalidHamt(link.cid));
+                }
+                shard = link.cid;
+                node = sub_node;
+            }
+            Some(entry) if entry == name => return Ok(Some(link.cid)),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// The 64-bit murmur3 hash used by the HAMT shards: the first half of murmur3-x64-128, with a zero seed
+fn murmur3_x64_64(data: &[u8]) -> u64 {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    let read_le = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |acc, byte| acc << 8 | *byte as u64)
+    };
+
+    let (mut h1, mut h2) = (0u64, 0u64);
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        let (k1, k2) = block.split_at(8);
+        h1 ^= mix_k1(read_le(k1));
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+        h2 ^= mix_k2(read_le(k2));
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+    let (k1, k2) = blocks.remainder().split_at(blocks.remainder().len().min(8));
+    if !k2.is_empty() {
+        h2 ^= mix_k2(read_le(k2));
+    }
+    if !k1.is_empty() {
+        h1 ^= mix_k1(read_le(k1));
+    }
+
+    let fmix = |mut k: u64| {
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        k ^ (k >> 33)
+    };
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    fmix(h1).wrapping_add(fmix(h2))
+}
+
+/// Errors related to the extraction of a UnixFS file
+#[derive(thiserror::Error, Debug)]
+pub enum CatError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A block of the file DAG is not available in the source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+    /// A dag-pb block of the file DAG is malformed
+    #[error("Invalid dag-pb block: {0}")]
+    InvalidDagPb(#[from] DagPbError),
+    /// The UnixFS metadata of a node is malformed
+    #[error("Invalid UnixFS data: {0}")]
+    InvalidUnixFs(#[from] UnixFsError),
+    /// A node of the DAG is not part of a UnixFS file (directory, symlink, unsupported codec, etc.)
+    #[error("Not a UnixFS file node: {0}")]
+    NotAFile(RawCid),
+    /// The content of the file does not match the size recorded in its root
+    #[error("File size mismatch: expected {expected} bytes, read {actual}")]
+    SizeMismatch {
+        /// Size recorded in the root
+        expected: u64,
+        /// Size of the content read (so far, if the file is longer)
+        actual: u64,
+    },
+}
+
+/// Errors related to UnixFS path resolution
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveError<E> {
+    /// The block source failed to retrieve a block
+    #[error("Block source error")]
+    Source(E),
+    /// A directory block on the way is not available in the source
+    #[error("Block not found: {0}")]
+    BlockNotFound(RawCid),
+    /// A dag-pb block on the way is malformed
+    #[error("Invalid dag-pb block: {0}")]
+    InvalidDagPb(#[from] DagPbError),
+    /// The UnixFS metadata of a node on the way is malformed
+    #[error("Invalid UnixFS data: {0}")]
+    InvalidUnixFs(#[from] UnixFsError),
+    /// A node on the way is not a directory (file, symlink, unsupported codec, etc.)
+    #[error("Not a UnixFS directory: {0}")]
+    NotADirectory(RawCid),
+    /// A HAMT shard has an unsupported hash function or fanout, or its sub-shards are inconsistent
+    #[error("Invalid HAMT shard: {0}")]
+    InvalidHamt(RawCid),
+    /// The directory has no entry of that name
+    #[error("Entry {name:?} not found in directory {cid}")]
+    EntryNotFound {
+        /// CID of the directory (the root shard for HAMT-sharded directories)
+        cid: RawCid,
+        /// The name which could not be found
+        name: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::ipld::dagpb::PbLink;
+
+    /// A fake CID of the given codec, distinguished by its last digest byte
+    fn cid(codec: u8, n: u8) -> RawCid {
+        let mut bytes = vec![0x01, codec, 0x12, 0x20];
+        bytes.resize(bytes.len() + 31, 0);
+        bytes.push(n);
+        RawCid::new(bytes)
+    }
+
+    /// A dag-pb UnixFS node of the given type, with inline data and children
+    fn node(data_type: DataType, inline: &[u8], children: &[(RawCid, u64)]) -> Vec<u8> {
+        let mut unixfs = UnixFsData::new(data_type);
+        unixfs.data = (!inline.is_empty()).then(|| inline.to_vec());
+        unixfs.blocksizes = children.iter().map(|(_, size)| *size).collect();
+        unixfs.filesize = Some(inline.len() as u64 + unixfs.blocksizes.iter().sum::<u64>());
+        PbNode {
+            data: Some(unixfs.encode()),
+            links: children
+                .iter()
+                .map(|(cid, _)| PbLink {
+                    cid: cid.clone(),
+                    name: Some(String::new()),
+                    tsize: None,
+                })
+                .collect(),
+        }
+        .encode()
+    }
+
+    /// File "abcdefghij": root -> [a: "abcd", b -> ["ef", c: "g", d: "hij"]]
+    fn file() -> (RawCid, HashMap<RawCid, Vec<u8>>) {
+        let (a, d) = (cid(0x55, 1), cid(0x55, 4));
+        let (b, c, root) = (cid(0x70, 2), cid(0x70, 3), cid(0x70, 0));
+        let mut blocks = HashMap::new();
+        blocks.insert(a.clone(), b"abcd".to_vec());
+        blocks.insert(c.clone(), node(DataType::Raw, b"g", &[]));
+        blocks.insert(d.clone(), b"hij".to_vec());
+        blocks.insert(b.clone(), node(DataType::File, b"ef", &[(c, 1), (d, 3)]));
+        blocks.insert(root.clone(), node(DataType::File, b"", &[(a, 4), (b, 6)]));
+        (root, blocks)
+    }
+
+    #[test]
+    fn test_unixfs_file_reader() {
+        let (root, mut blocks) = file();
+        let mut reader = FileReader::new(&mut blocks, &root).unwrap();
+        assert_eq!(reader.file_size(), 10);
+        let chunks: Vec<_> = (&mut reader).collect::<Result<_, _>>().unwrap();
+        assert_eq!(chunks, [&b"abcd"[..], b"ef", b"g", b"hij"]);
+        assert_eq!(reader.position(), 10);
+        assert!(reader.next().is_none());
+        assert_eq!(cat(&mut blocks, &root).unwrap(), b"abcdefghij");
+
+        // A raw root is a single chunk file
+        let leaf = cid(0x55, 1);
+        assert_eq!(cat(&mut blocks, &leaf).unwrap(), b"abcd");
+
+        // Lazy fetching: a missing leaf is only reported when reached
+        blocks.remove(&cid(0x55, 4));
+        let results: Vec<_> = FileReader::new(&mut blocks, &root).unwrap().collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(&results[3], Err(CatError::BlockNotFound(cid)) if cid.bytes()[35] == 4));
+    }
+
+    #[test]
+    fn test_unixfs_cat_errors() {
+        let (root, mut blocks) = file();
+
+        // Directories are not files
+        let dir = cid(0x70, 9);
+        blocks.insert(dir.clone(), node(DataType::Directory, b"", &[]));
+        assert!(matches!(cat(&mut blocks, &dir), Err(CatError::NotAFile(_))));
+
+        // A leaf longer than announced
+        blocks.insert(cid(0x55, 4), b"hijk".to_vec());
+        assert!(matches!(
+            cat(&mut blocks, &root),
+            Err(CatError::SizeMismatch {
+                expected: 10,
+                actual: 11
+            })
+        ));
+        // Or shorter
+        blocks.insert(cid(0x55, 4), b"hi".to_vec());
+        assert!(matches!(
+            cat(&mut blocks, &root),
+            Err(CatError::SizeMismatch {
+                expected: 10,
+                actual: 9
+            })
+        ));
+
+        // A failing source
+        let mut failing = |_: &RawCid| Err::<Option<Vec<u8>>, _>("unavailable");
+        assert!(matches!(
+            cat(&mut failing, &root),
+            Err(CatError::Source("unavailable"))
+        ));
+    }
+
+    /// A dag-pb directory node of the given type, with the given entries
+    fn directory(unixfs: UnixFsData, entries: &[(String, RawCid)]) -> Vec<u8> {
+        PbNode {
+            data: Some(unixfs.encode()),
+            links: entries
+                .iter()
+                .map(|(name, cid)| PbLink {
+                    cid: cid.clone(),
+                    name: Some(name.clone()),
+                    tsize: None,
+                })
+                .collect(),
+        }
+        .encode()
+    }
+
+    #[test]
+    fn test_unixfs_murmur3() {
+        assert_eq!(murmur3_x64_64(b""), 0);
+        assert_eq!(murmur3_x64_64(b"hello"), 0xcbd8_a7b3_41bd_9b02);
+        assert_eq!(
+            murmur3_x64_64(b"The quick brown fox jumps over the lazy dog"),
+            0xe34b_bc7b_bc07_1b6c
+        );
+    }
+
+    #[test]
+    fn test_unixfs_resolve() {
+        // root -> {a -> {b -> {c.txt}}, f}
+        let (file, c) = (cid(0x55, 1), cid(0x70, 2));
+        let (b, a, root) = (cid(0x70, 3), cid(0x70, 4), cid(0x70, 0));
+        let dir = || UnixFsData::new(DataType::Directory);
+        let mut blocks = HashMap::new();
+        blocks.insert(c.clone(), node(DataType::File, b"c", &[]));
+        blocks.insert(b.clone(), directory(dir(), &[("c.txt".into(), c.clone())]));
+        blocks.insert(a.clone(), directory(dir(), &[("b".into(), b.clone())]));
+        blocks.insert(
+            root.clone(),
+            directory(
+                dir(),
+                &[("a".into(), a.clone()), ("f".into(), file.clone())],
+            ),
+        );
+
+        assert_eq!(resolve(&mut blocks, &root, "").unwrap(), root);
+        assert_eq!(resolve(&mut blocks, &root, "a/b/c.txt").unwrap(), c);
+        assert_eq!(resolve(&mut blocks, &root, "/a//b/").unwrap(), b);
+        assert_eq!(resolve(&mut blocks, &root, "f").unwrap(), file);
+
+        assert!(matches!(
+            resolve(&mut blocks, &root, "a/nope"),
+            Err(ResolveError::EntryNotFound { cid, name }) if cid == a && name == "nope"
+        ));
+        assert!(matches!(
+            resolve(&mut blocks, &root, "f/x"),
+            Err(ResolveError::NotADirectory(cid)) if cid == file
+        ));
+        assert!(matches!(
+            resolve(&mut blocks, &root, "a/b/c.txt/x"),
+            Err(ResolveError::NotADirectory(cid)) if cid == c
+        ));
+        blocks.remove(&b);
+        assert!(matches!(
+            resolve(&mut blocks, &root, "a/b/c.txt"),
+            Err(ResolveError::BlockNotFound(cid)) if cid == b
+        ));
+    }
+
+    #[test]
+    fn test_unixfs_resolve_hamt() {
+        // Fanout 4: each level of the HAMT takes 2 bits of the hash
+        let shard = || {
+            let mut unixfs = UnixFsData::new(DataType::HamtShard);
+            unixfs.hash_type = Some(HAMT_HASH_MURMUR3);
+            unixfs.fanout = Some(4);
+            unixfs
+        };
+        let bucket =
+            |name: &str, level: u32| (murmur3_x64_64(name.as_bytes()) << (2 * level)) >> 62;
+        // Two names in the same bucket of the root shard (but not of the sub-shard), and one elsewhere
+        let names: Vec<_> = (0..64).map(|i| format!("file-{i}")).collect();
+        let first = &names[0];
+        let second = names
+            .iter()
+            .find(|name| bucket(name, 0) == bucket(first, 0) && bucket(name, 1) != bucket(first, 1))
+            .unwrap();
+        let other = names
+            .iter()
+            .find(|name| bucket(name, 0) != bucket(first, 0))
+            .unwrap();
+
+        let (first_cid, second_cid, other_cid) = (cid(0x55, 1), cid(0x55, 2), cid(0x55, 3));
+        let (sub_shard, root) = (cid(0x70, 4), cid(0x70, 0));
+        let mut blocks = HashMap::new();
+        blocks.insert(
+            sub_shard.clone(),
+            directory(
+                shard(),
+                &[
+                    (format!("{:X}{first}", bucket(first, 1)), first_cid.clone()),
+                    (
+                        format!("{:X}{second}", bucket(second, 1)),
+                        second_cid.clone(),
+                    ),
+                ],
+            ),
+        );
+        blocks.insert(
+            root.clone(),
+            directory(
+                shard(),
+                &[
+                    (format!("{:X}", bucket(first, 0)), sub_shard.clone()),
+                    (format!("{:X}{other}", bucket(other, 0)), other_cid.clone()),
+                ],
+            ),
+        );
+
+        assert_eq!(resolve(&mut blocks, &root, first).unwrap(), first_cid);
+        assert_eq!(resolve(&mut blocks, &root, second).unwrap(), second_cid);
+        assert_eq!(resolve(&mut blocks, &root, other).unwrap(), other_cid);
+        for missing in names
+            .iter()
+            .filter(|name| ![first, second, other].contains(name))
+        {
+            assert!(matches!(
+                resolve(&mut blocks, &root, missing),
+                Err(ResolveError::EntryNotFound { cid, .. }) if cid == root
+            ));
+        }
+
+        // Only murmur3 shards are supported
+        let mut unsupported = shard();
+        unsupported.hash_type = Some(0x12);
+        blocks.insert(root.clone(), directory(unsupported, &[]));
+        assert!(matches!(
+            resolve(&mut blocks, &root, first),
+            Err(ResolveError::InvalidHamt(cid)) if cid == root
+        ));
+    }
+}
@@ -0,0 +1,813 @@
+//! Verification utilities for CAR files
+//!
+//! The CARv2 header advertises some characteristics of the archive, which readers may rely on to take
+//! shortcuts. For instance, when `has_full_index` is set, a reader may answer "not in this archive" from
+//! the index alone, without scanning the sections. An untruthful flag therefore leads to silently missing blocks.
+//!
+//! This module provides read-only checks of these characteristics against the actual content of the archive.
+//!
+//! It also provides the digest checks of the blocks against their CID. When verification is used in
+//! authentication-adjacent contexts (signed manifests, proof checking), the digests can be compared in
+//! constant time (see [DigestComparison]), to avoid timing side channels in server deployments.
+//!
+//! Whole indexed archives can be verified with sequential and exact reads, planned from their index
+//! (see [IndexedVerifier]).
+//!
+//! Finally, the completeness of the DAG of an archive (every block reachable from its roots is present) is
+//! checked by walking the links of its dag-pb and dag-cbor blocks (see [DagVerifier]).
+//!
+//! ## Examples
+//! ```
+//! use navira_car::verify::{IndexCoverage, verify_full_index};
+//! use navira_car::wire::v2::{CarV2Header, Characteristics, Index, IndexType};
+//!
+//! let header = CarV2Header {
+//!     characteristics: Characteristics(1), // has_full_index
+//!     data_offset: 51,
+//!     data_size: 0,
+//!     index_offset: 51,
+//! };
+//! let index = Index { index_type: IndexType::MultihashIndexSorted, buckets: Vec::new() };
+//!
+//! // An empty archive is fully covered by an empty index
+//! let coverage = IndexCoverage::compute(&index, []);
+//! assert!(verify_full_index(&header, &coverage).is_ok());
+//! ```
+
+use subtle::ConstantTimeEq;
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+
+use crate::ipld::block_links;
+use crate::wire::cid::{IDENTITY_MULTIHASH_CODE, RawCid};
+use crate::wire::v1::{CarHeader, Section, SectionFormatError};
+use crate::wire::v2::{CarV2Header, Index};
+
+/// Multihash code of sha2-256
+#[cfg(feature = "hashing")]
+const SHA2_256_MULTIHASH_CODE: u64 = 0x12;
+
+/// Multihash codes of the hash functions supported by [verify_block], in this build
+///
+/// sha2-256 is only available with the `hashing` feature (enabled by `pack`), which brings the hash function.
+pub const VERIFICATION_HASHES: &[u64] = &[
+    IDENTITY_MULTIHASH_CODE,
+    #[cfg(feature = "hashing")]
+    SHA2_256_MULTIHASH_CODE,
+];
+
+/// Strategy used to compare digests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DigestComparison {
+    /// Plain comparison, returning at the first differing byte
+    #[default]
+    Variable,
+    /// Constant-time comparison, whose duration does not depend on the content of the digests
+    ///
+    /// Only the lengths of the digests may leak, as they are public (given by the hash function).
+    ConstantTime,
+}
+
+impl DigestComparison {
+    /// Compare two digests with this strategy
+    pub fn digests_eq(self, a: &[u8], b: &[u8]) -> bool {
+        match self {
+            DigestComparison::Variable => a == b,
+            DigestComparison::ConstantTime => constant_time_eq(a, b),
+        }
+    }
+}
+
+/// Compare two byte strings in constant time (for a given length)
+///
+/// ## Examples
+/// ```
+/// use navira_car::verify::constant_time_eq;
+///
+/// assert!(constant_time_eq(b"digest", b"digest"));
+/// assert!(!constant_time_eq(b"digest", b"digesT"));
+/// assert!(!constant_time_eq(b"digest", b"dig"));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Verifies that a digest computed from the block data matches the digest of its CID
+///
+/// ## Returns
+/// - `Ok(())` if the digests match.
+/// - `Err(DigestError::MalformedCid)` if the CID has no multihash digest.
+/// - `Err(DigestError::Mismatch)` if the digests differ.
+pub fn verify_digest(
+    cid: &RawCid,
+    digest: &[u8],
+    comparison: DigestComparison,
+) -> Result<(), DigestError> {
+    let expected = cid.digest().ok_or(DigestError::MalformedCid)?;
+    if comparison.digests_eq(expected, digest) {
+        Ok(())
+    } else {
+        Err(DigestError::Mismatch(cid.clone()))
+    }
+}
+
+/// Verifies that the block data hashes to the digest of its CID
+///
+/// Identity CIDs are always supported. sha2-256 CIDs require the `hashing` feature, which brings the hash function.
+///
+/// ## Returns
+/// - `Ok(())` if the block matches its CID.
+/// - `Err(DigestError::UnsupportedHash)` if the hash function of the CID is not supported.
+/// - `Err(DigestError)` otherwise, see [verify_digest].
+pub fn verify_block(
+    cid: &RawCid,
+    data: &[u8],
+    comparison: DigestComparison,
+) -> Result<(), DigestError> {
+    match cid.multihash_code().ok_or(DigestError::MalformedCid)? {
+        IDENTITY_MULTIHASH_CODE => verify_digest(cid, data, comparison),
+        #[cfg(feature = "hashing")]
+        SHA2_256_MULTIHASH_CODE => {
+            use sha2::{Digest, Sha256};
+            verify_digest(cid, &Sha256::digest(data), comparison)
+        }
+        code => Err(DigestError::UnsupportedHash(code)),
+    }
+}
+
+/// Errors related to the verification of block digests
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum DigestError {
+    /// The CID has no multihash digest
+    #[error("CID has no multihash digest")]
+    MalformedCid,
+    /// The hash function of the CID is not supported
+    #[error("Unsupported hash function: {0:#x}")]
+    UnsupportedHash(u64),
+    /// The block data does not hash to the digest of its CID
+    #[error("Block does not match its CID {}", .0.to_hex())]
+    Mismatch(RawCid),
+}
+
+/// Coverage of the sections of an archive by its index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexCoverage {
+    /// Number of sections found in the index
+    pub indexed: usize,
+    /// Number of sections with an identity CID, which do not need to be indexed
+    pub identity: usize,
+    /// CIDs of the sections missing from the index
+    pub missing: Vec<RawCid>,
+}
+
+impl IndexCoverage {
+    /// Computes the coverage of the given section CIDs by the index
+    ///
+    /// # Arguments
+    /// * `index` - The decoded index of the archive.
+    /// * `cids` - The CIDs of all the sections of the archive.
+    pub fn compute<'a, I>(index: &Index, cids: I) -> Self
+    where
+        I: IntoIterator<Item = &'a RawCid>,
+    {
+        let mut coverage = IndexCoverage::default();
+        for cid in cids {
+            if cid.is_identity() {
+                coverage.identity += 1;
+            } else if index.find(cid).is_some() {
+                coverage.indexed += 1;
+            } else {
+                coverage.missing.push(cid.clone());
+            }
+        }
+        coverage
+    }
+
+    /// Returns true if every non-identity section is present in the index
+    pub fn is_full(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Verifies that the `has_full_index` characteristic of the header is truthful
+///
+/// The flag is truthful if it is unset, or if the archive has an index covering every non-identity section.
+/// An unset flag with a full index is valid (though suboptimal), so it is not reported.
+///
+/// ## Returns
+/// - `Ok(())` if the flag is truthful.
+/// - `Err(CharacteristicsError)` if the flag is set but the archive has no index, or some sections are missing from it.
+pub fn verify_full_index(
+    header: &CarV2Header,
+    coverage: &IndexCoverage,
+) -> Result<(), CharacteristicsError> {
+    if !header.characteristics.has_full_index() {
+        return Ok(());
+    }
+    if header.index_offset == 0 {
+        return Err(CharacteristicsError::MissingIndex);
+    }
+    if !coverage.is_full() {
+        return Err(CharacteristicsError::FullIndexMismatch {
+            missing: coverage.missing.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Errors related to the verification of the CARv2 characteristics
+#[derive(thiserror::Error, Debug)]
+pub enum CharacteristicsError {
+    /// The archive claims to be fully indexed, but has no index
+    #[error("has_full_index is set but the archive has no index")]
+    MissingIndex,
+    /// The archive claims to be fully indexed, but some sections are missing from its index
+    #[error("has_full_index is set but {} sections are missing from the index", missing.len())]
+    FullIndexMismatch {
+        /// CIDs of the sections missing from the index
+        missing: Vec<RawCid>,
+    },
+}
+
+/// Counters of a whole-archive verification
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Number of verified sections (decoded, and matching their CID)
+    pub sections: usize,
+    /// Number of sections whose hash function is not supported, decoded but not hashed
+    pub unhashed: usize,
+    /// Number of index entries checked against the section at their offset
+    pub indexed: usize,
+    /// Number of bytes of the sections read
+    pub bytes: u64,
+}
+
+/// Verification of a CARv2 payload with exact reads, planned from its index
+///
+/// The hint-driven loop of the readers discovers the length of each section as it goes, with reads that may
+/// stop in the middle of a section and be resumed later. When the archive has an index, the offsets of the
+/// sections are known upfront: sorted by offset, two consecutive entries delimit exactly the bytes of a section
+/// (or of a few sections, when some are not indexed, e.g. identity CIDs). This verifier plans these reads,
+/// from the first section to the end of the payload, so the payload is read sequentially, once, and exactly:
+/// - [IndexedVerifier::next_read] gives the (absolute) byte range to read next,
+/// - [IndexedVerifier::verify_read] decodes the sections of these bytes, checks their digest, and checks that
+///   the index entry of the range points at the first of them.
+///
+/// The largest read is known upfront too ([IndexedVerifier::max_read_length]), so a single buffer can be
+/// allocated for the whole verification. Nothing is skipped: every byte between the first section and the end
+/// of the payload belongs to a verified section.
+///
+/// ## Examples
+/// ```
+/// use navira_car::CarReader;
+/// use navira_car::verify::IndexedVerifier;
+/// use navira_car::wire::v2::Index;
+///
+/// let car = include_bytes!("res/carv2-basic.car");
+/// let mut reader = CarReader::from_bytes(car).unwrap();
+/// let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok()).collect();
+/// let (_, Some(header)) = reader.header().unwrap() else { unreachable!() };
+/// let sections_start = sections[0].location.offset;
+/// // An index of the sections (offsets relative to the payload), as it would be read from the archive
+/// let index = Index::multihash_sorted(
+///     sections.iter().map(|s| (s.cid(), s.location.offset - header.data_offset)),
+/// );
+///
+/// let mut verifier = IndexedVerifier::new(header, sections_start, &index).unwrap();
+/// while let Some(range) = verifier.next_read() {
+///     verifier.verify_read(&car[range.start as usize..range.end as usize]).unwrap();
+/// }
+/// assert_eq!(verifier.report().indexed, 5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct IndexedVerifier {
+    /// Planned reads, with the index entry at their start
+    reads: Vec<(Range<u64>, Option<ExpectedEntry>)>,
+    /// Position of the next read in the plan
+    next: usize,
+    /// Comparison of the digests
+    comparison: DigestComparison,
+    /// Counters of the verification so far
+    report: VerifyReport,
+}
+
+impl IndexedVerifier {
+    /// Plan the exact reads of the payload from the index
+    ///
+    /// # Arguments
+    /// * `header` - The CARv2 header of the archive
+    /// * `sections_start` - Absolute offset of the first section (after the CARv1 header of the payload)
+    /// * `index` - The decoded index of the archive
+    ///
+    /// # Returns
+    /// * `Ok(IndexedVerifier)` - The verifier, ready for the first read
+    /// * `Err(ExactReadError::OffsetOutOfPayload)` - An index entry points outside of the sections, or past
+    ///   [u64::MAX] (the reported offset is then [u64::MAX])
+    pub fn new(
+        header: &CarV2Header,
+        sections_start: u64,
+        index: &Index,
+    ) -> Result<Self, ExactReadError> {
+        let data_end = header
+            .data_offset
+            .checked_add(header.data_size)
+            .ok_or(ExactReadError::OffsetOutOfPayload(header.data_offset))?;
+        let mut entries = BTreeMap::new();
+        for bucket in &index.buckets {
+            for entry in &bucket.entries {
+                let offset = header
+                    .data_offset
+                    .checked_add(entry.offset)
+                    .ok_or(ExactReadError::OffsetOutOfPayload(u64::MAX))?;
+                if offset < sections_start || offset >= data_end {
+                    return Err(ExactReadError::OffsetOutOfPayload(offset));
+                }
+                entries.insert(offset, (bucket.multihash_code, entry.hash.clone()));
+            }
+        }
+        let mut reads = Vec::with_capacity(entries.len() + 1);
+        let mut start = sections_start;
+        let mut expected = None;
+        for (offset, entry) in entries {
+            if offset > start {
+                reads.push((start..offset, expected.take()));
+            }
+            start = offset;
+            expected = Some(entry);
+        }
+        if data_end > start {
+            reads.push((start..data_end, expected));
+        }
+        Ok(Self {
+            reads,
+            next: 0,
+            comparison: DigestComparison::default(),
+            report: VerifyReport::default(),
+        })
+    }
+
+    /// Set the comparison of the digests (variable-time by default)
+    pub fn set_digest_comparison(&mut self, comparison: DigestComparison) {
+        self.comparison = comparison;
+    }
+
+    /// Length of the largest planned read, to size the read buffer
+    pub fn max_read_length(&self) -> usize {
+        self.reads
+            .iter()
+            .map(|(range, _)| (range.end - range.start) as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Number of planned reads
+    pub fn read_count(&self) -> usize {
+        self.reads.len()
+    }
+
+    /// Absolute byte range to read next, `None` once the whole payload is verified
+    pub fn next_read(&self) -> Option<Range<u64>> {
+        self.reads.get(self.next).map(|(range, _)| range.clone())
+    }
+
+    /// Verify the bytes of the range given by [IndexedVerifier::next_read]
+    ///
+    /// # Returns
+    /// * `Ok(())` - The sections of the range are valid, the verifier moves to the next read
+    /// * `Err(ExactReadError)` - The range is invalid, the verification cannot go on
+    pub fn verify_read(&mut self, bytes: &[u8]) -> Result<(), ExactReadError> {
+        let Some((range, expected)) = self.reads.get(self.next) else {
+            return Err(ExactReadError::UnexpectedRead);
+        };
+        if bytes.len() as u64 != range.end - range.start {
+            return Err(ExactReadError::UnexpectedRead);
+        }
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let offset = range.start + pos as u64;
+            let (section, length) = Section::try_read_bytes(bytes.get(pos..).unwrap_or_default())
+                .map_err(|e| match e {
+                // The section goes past the next index entry (or the end of the payload)
+                SectionFormatError::InsufficientData => ExactReadError::Misaligned(range.end),
+                e => ExactReadError::InvalidSection(offset, e),
+            })?;
+            if pos == 0
+                && let Some((code, digest)) = expected
+            {
+                let matches = section.cid().digest().is_some_and(|actual| {
+                    self.comparison.digests_eq(actual, digest)
+                        && code.is_none_or(|code| section.cid().multihash_code() == Some(code))
+                });
+                if !matches {
+                    return Err(ExactReadError::IndexMismatch(offset));
+                }
+                self.report.indexed += 1;
+            }
+            match verify_block(section.cid(), section.block().data(), self.comparison) {
+                Ok(()) => self.report.sections += 1,
+                Err(DigestError::UnsupportedHash(_)) => self.report.unhashed += 1,
+                Err(e) => return Err(ExactReadError::Digest(offset, e)),
+            }
+            pos += length;
+        }
+        self.report.bytes += bytes.len() as u64;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Counters of the verification so far
+    pub fn report(&self) -> &VerifyReport {
+        &self.report
+    }
+}
+
+/// Multihash code (if known by the index) and digest of an index entry
+type ExpectedEntry = (Option<u64>, Vec<u8>);
+
+/// Errors of the verification with exact reads (see [IndexedVerifier])
+#[derive(thiserror::Error, Debug)]
+pub enum ExactReadError {
+    /// An index entry points outside of the sections of the payload, at this absolute offset
+    #[error("Index entry out of the payload, at offset {0}")]
+    OffsetOutOfPayload(u64),
+    /// A section overlaps this absolute offset, where an index entry (or the end of the payload) is
+    #[error("A section overlaps the index entry (or payload end) at offset {0}")]
+    Misaligned(u64),
+    /// The section at this absolute offset is not the one of its index entry
+    #[error("The section at offset {0} does not match its index entry")]
+    IndexMismatch(u64),
+    /// The section at this absolute offset cannot be decoded
+    #[error("Invalid section at offset {0}: {1}")]
+    InvalidSection(u64, SectionFormatError),
+    /// The block of the section at this absolute offset does not match its CID
+    #[error("Invalid block at offset {0}: {1}")]
+    Digest(u64, DigestError),
+    /// The bytes given are not those of the planned read (or all the reads are done)
+    #[error("Unexpected read, not matching the planned range")]
+    UnexpectedRead,
+}
+
+/// Verification of the completeness of the DAG of an archive, walked from its roots
+///
+/// Nothing in the CAR format requires an archive to hold a complete DAG, but most consumers expect it: a block
+/// missing from a partial export only shows up when the DAG is walked. This verifier records the links of every
+/// block of the archive (see [block_links], only dag-pb and dag-cbor blocks have links), then walks the DAG from
+/// the roots:
+/// - the linked blocks absent from the archive are reported as missing, with the block linking to them,
+/// - the blocks of the archive not reachable from the roots are reported as orphans.
+///
+/// The blocks can be added in any order, as the sections of a CAR file need not follow the DAG order. Only the
+/// CIDs are kept in memory, never the block data. Identity CIDs embed their block, so they are never missing.
+///
+/// ## Examples
+/// ```
+/// use navira_car::CarReader;
+/// use navira_car::verify::DagVerifier;
+///
+/// let car = include_bytes!("res/carv1-basic.car");
+/// let mut reader = CarReader::from_bytes(car).unwrap();
+/// let (header, _) = reader.header().unwrap();
+/// let mut verifier = DagVerifier::from_header(header);
+/// while let Ok(section) = reader.read_section() {
+///     verifier.add_section(&section);
+/// }
+/// let report = verifier.verify();
+/// assert!(report.is_complete());
+/// assert_eq!(report.reachable, 8);
+/// assert!(report.orphans.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DagVerifier {
+    /// CIDs of the roots, where the walk starts
+    roots: Vec<RawCid>,
+    /// Links of each added block
+    links: HashMap<RawCid, Vec<RawCid>>,
+    /// CIDs of the added blocks, in the order they were added (duplicates excluded)
+    order: Vec<RawCid>,
+}
+
+impl DagVerifier {
+    /// Create a verifier of the DAG starting at the given roots
+    pub fn new(roots: impl IntoIterator<Item = RawCid>) -> Self {
+        Self {
+            roots: roots.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Create a verifier of the DAG starting at the roots of the given header
+    pub fn from_header(header: &CarHeader) -> Self {
+        Self::new(header.roots().iter().map(|root| root.to_raw_cid().clone()))
+    }
+
+    /// Record a block of the archive, and its links
+    ///
+    /// Blocks added several times (duplicate sections) are only recorded once.
+    pub fn add_block(&mut self, cid: &RawCid, data: &[u8]) {
+        if self.links.contains_key(cid) {
+            return;
+        }
+        self.links.insert(cid.clone(), block_links(cid, data));
+        self.order.push(cid.clone());
+    }
+
+    /// Record the block of a section, see [DagVerifier::add_block]
+    pub fn add_section(&mut self, section: &Section) {
+        self.add_block(section.cid(), section.block().data());
+    }
+
+    /// Number of distinct blocks recorded
+    pub fn block_count(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Walk the DAG from the roots, and report the missing and orphan blocks
+    ///
+    /// The DAG is walked breadth-first, so the missing blocks are reported in this order, each one once (with the
+    /// first block found linking to it).
+    pub fn verify(&self) -> DagReport {
+        let mut report = DagReport::default();
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<_> = self.roots.iter().map(|root| (root.clone(), None)).collect();
+        while let Some((cid, referrer)) = queue.pop_front() {
+            if !visited.insert(cid.clone()) {
+                continue;
+            }
+            let links = match (self.links.get(&cid), cid.identity_data()) {
+                (Some(links), _) => links.clone(),
+                (None, Some(data)) => block_links(&cid, data),
+                (None, None) => {
+                    report.missing.push(MissingBlock { cid, referrer });
+                    continue;
+                }
+            };
+            report.reachable += 1;
+            queue.extend(links.into_iter().map(|link| (link, Some(cid.clone()))));
+        }
+        report.orphans = self
+            .order
+            .iter()
+            .filter(|cid| !visited.contains(*cid))
+            .cloned()
+            .collect();
+        report
+    }
+}
+
+/// Outcome of a DAG verification (see [DagVerifier])
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DagReport {
+    /// Number of blocks reachable from the roots and available (in the archive, or embedded in identity CIDs)
+    pub reachable: usize,
+    /// Blocks reachable from the roots but absent from the archive, in walk order
+    pub missing: Vec<MissingBlock>,
+    /// Blocks of the archive not reachable from the roots, in the order they were added
+    pub orphans: Vec<RawCid>,
+}
+
+impl DagReport {
+    /// Returns true if no block reachable from the roots is missing
+    ///
+    /// Orphan blocks do not make the DAG incomplete, check [DagReport::orphans] to also reject them.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// A block reachable from the roots, but absent from the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingBlock {
+    /// CID of the missing block
+    pub cid: RawCid,
+    /// CID of the (first found) block linking to it, `None` for a missing root
+    pub referrer: Option<RawCid>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::v2::{Characteristics, IndexBucket, IndexType, OwnedIndexEntry};
+
+    fn header(full: bool, index_offset: u64) -> CarV2Header {
+        let mut characteristics = Characteristics(0);
+        characteristics.set_has_full_index(full);
+        CarV2Header {
+            characteristics,
+            data_offset: 51,
+            data_size: 100,
+            index_offset,
+        }
+    }
+
+    #[test]
+    fn test_verify_full_index() {
+        let indexed = RawCid::from_hex(
+            "0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4",
+        )
+        .unwrap();
+        let unindexed = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        let identity = RawCid::from_hex("0155000461616161").unwrap();
+        let index = Index {
+            index_type: IndexType::MultihashIndexSorted,
+            buckets: vec![IndexBucket {
+                multihash_code: Some(0x12),
+                entry_width: 40,
+                entries: vec![OwnedIndexEntry {
+                    hash: indexed.digest().unwrap().to_vec(),
+                    offset: 0,
+                }],
+            }],
+        };
+
+        let coverage = IndexCoverage::compute(&index, [&indexed, &identity]);
+        assert_eq!(coverage.indexed, 1);
+        assert_eq!(coverage.identity, 1);
+        assert!(verify_full_index(&header(true, 151), &coverage).is_ok());
+
+        let coverage = IndexCoverage::compute(&index, [&indexed, &unindexed]);
+        assert!(matches!(
+            verify_full_index(&header(true, 151), &coverage),
+            Err(CharacteristicsError::FullIndexMismatch { missing }) if missing == vec![unindexed]
+        ));
+        // The flag is not set, nothing is claimed
+        assert!(verify_full_index(&header(false, 151), &coverage).is_ok());
+        assert!(matches!(
+            verify_full_index(&header(true, 0), &IndexCoverage::default()),
+            Err(CharacteristicsError::MissingIndex)
+        ));
+    }
+
+    #[test]
+    fn test_verify_block() {
+        let identity = RawCid::from_hex("0155000461616161").unwrap();
+        for comparison in [DigestComparison::Variable, DigestComparison::ConstantTime] {
+            assert_eq!(verify_block(&identity, b"aaaa", comparison), Ok(()));
+            assert_eq!(
+                verify_block(&identity, b"aaab", comparison),
+                Err(DigestError::Mismatch(identity.clone()))
+            );
+        }
+        let blake3 = RawCid::from_hex("01551e0461616161").unwrap();
+        assert_eq!(
+            verify_block(&blake3, b"aaaa", DigestComparison::ConstantTime),
+            Err(DigestError::UnsupportedHash(0x1e))
+        );
+    }
+
+    /// Header, first section offset and index of the CARv2 fixture
+    fn carv2_basic() -> (CarV2Header, u64, Index) {
+        let car = crate::testdata::CARV2_BASIC;
+        let mut reader = crate::CarReader::from_bytes(car).unwrap();
+        let sections_start = reader.read_section().unwrap().location.offset;
+        let header = reader.header().unwrap().1.unwrap().clone();
+        let (index, _) = Index::decode(&crate::testdata::carv2_basic_index()).unwrap();
+        (header, sections_start, index)
+    }
+
+    fn verify_all(verifier: &mut IndexedVerifier, car: &[u8]) -> Result<(), ExactReadError> {
+        while let Some(range) = verifier.next_read() {
+            verifier.verify_read(&car[range.start as usize..range.end as usize])?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_indexed_verifier() {
+        let car = crate::testdata::CARV2_BASIC;
+        let (header, sections_start, index) = carv2_basic();
+        let mut verifier = IndexedVerifier::new(&header, sections_start, &index).unwrap();
+        assert_eq!(verifier.read_count(), 5);
+        verify_all(&mut verifier, car).unwrap();
+        let report = verifier.report();
+        assert_eq!(report.indexed, 5);
+        assert_eq!(report.sections + report.unhashed, 5);
+        assert_eq!(
+            report.bytes,
+            header.data_offset + header.data_size - sections_start
+        );
+        assert!(matches!(
+            verifier.verify_read(&[]),
+            Err(ExactReadError::UnexpectedRead)
+        ));
+
+        // An index entry in the middle of a section
+        let mut shifted = index.clone();
+        shifted.buckets[0].entries[0].offset += 1;
+        let mut verifier = IndexedVerifier::new(&header, sections_start, &shifted).unwrap();
+        assert!(matches!(
+            verify_all(&mut verifier, car),
+            Err(ExactReadError::Misaligned(_) | ExactReadError::InvalidSection(..))
+        ));
+
+        // Two index entries swapped: the sections are not the expected ones
+        let mut swapped = index.clone();
+        let entries = &mut swapped.buckets[0].entries;
+        let offset = entries[0].offset;
+        entries[0].offset = entries[1].offset;
+        entries[1].offset = offset;
+        let mut verifier = IndexedVerifier::new(&header, sections_start, &swapped).unwrap();
+        assert!(matches!(
+            verify_all(&mut verifier, car),
+            Err(ExactReadError::IndexMismatch(_))
+        ));
+
+        let mut outside = index.clone();
+        outside.buckets[0].entries[0].offset = header.data_size;
+        assert!(matches!(
+            IndexedVerifier::new(&header, sections_start, &outside),
+            Err(ExactReadError::OffsetOutOfPayload(_))
+        ));
+
+        // Offsets past u64::MAX, from the index or from the header
+        outside.buckets[0].entries[0].offset = u64::MAX;
+        assert!(matches!(
+            IndexedVerifier::new(&header, sections_start, &outside),
+            Err(ExactReadError::OffsetOutOfPayload(u64::MAX))
+        ));
+        let mut overflowing = header.clone();
+        overflowing.data_size = u64::MAX;
+        assert!(matches!(
+            IndexedVerifier::new(&overflowing, sections_start, &index),
+            Err(ExactReadError::OffsetOutOfPayload(_))
+        ));
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn test_indexed_verifier_corrupted_block() {
+        let mut car = crate::testdata::CARV2_BASIC.to_vec();
+        let (header, sections_start, index) = carv2_basic();
+        // Last byte of the last block
+        let last = (header.data_offset + header.data_size - 1) as usize;
+        car[last] ^= 0xff;
+        let mut verifier = IndexedVerifier::new(&header, sections_start, &index).unwrap();
+        assert!(matches!(
+            verify_all(&mut verifier, &car),
+            Err(ExactReadError::Digest(_, DigestError::Mismatch(_)))
+        ));
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn test_verify_block_sha2_256() {
+        use crate::wire::cid::Multihash;
+        let cid = RawCid::from_data(crate::ipld::CODEC_RAW, Multihash::Sha2_256, b"aaaa");
+        assert_eq!(
+            verify_block(&cid, b"aaaa", DigestComparison::ConstantTime),
+            Ok(())
+        );
+        assert_eq!(
+            verify_block(&cid, b"cccc", DigestComparison::ConstantTime),
+            Err(DigestError::Mismatch(cid.clone()))
+        );
+    }
+
+    #[test]
+    fn test_dag_verifier() {
+        let mut reader = crate::CarReader::from_bytes(crate::testdata::CARV1_BASIC).unwrap();
+        let header = reader.header().unwrap().0.clone();
+        let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok()).collect();
+        let unlinked = Section::from((RawCid::from_hex("0155000461616161").unwrap(), vec![]));
+
+        // The second section is dropped, another one is added twice, and an unlinked block is added
+        let mut verifier = DagVerifier::from_header(&header);
+        for (i, section) in sections.iter().enumerate() {
+            if i != 1 {
+                verifier.add_section(section);
+            }
+        }
+        verifier.add_section(&sections[0]);
+        verifier.add_section(&unlinked);
+        assert_eq!(verifier.block_count(), 8);
+
+        let report = verifier.verify();
+        assert!(!report.is_complete());
+        // The dropped block was the only link to the subtree of the next five blocks, now orphans
+        assert_eq!(
+            report.missing,
+            vec![MissingBlock {
+                cid: sections[1].cid().clone(),
+                referrer: Some(sections[0].cid().clone()),
+            }]
+        );
+        let mut orphans: Vec<_> = sections[2..7].iter().map(|s| s.cid().clone()).collect();
+        orphans.push(unlinked.cid().clone());
+        assert_eq!(report.orphans, orphans);
+        assert_eq!(report.reachable, 2);
+
+        // A missing root has no referrer
+        let root = sections[1].cid().clone();
+        let report = DagVerifier::new([root.clone()]).verify();
+        assert_eq!(
+            report.missing,
+            vec![MissingBlock {
+                cid: root,
+                referrer: None
+            }]
+        );
+    }
+}
@@ -11,16 +11,25 @@
 //! for validating that the bytes conform to the expected structure of a CID (e.g., CIDv0 or CIDv1)
 //! without needing to fully understand the internal structure of the CID (e.g., multihash coherence).
 //!
-//! ***TODO:** In the future, we will add the conversion fuctions to convert between RawCid and a
-//! more structured CID type (e.g., using the [cid crate](https://crates.io/crates/cid)) to make CAR operations easier.*
+//! The fields of a well-formed CID can be read without a full parse with [RawCid::version], [RawCid::codec],
+//! [RawCid::multihash_code] and [RawCid::digest]. With the `cid` feature, RawCid converts from/to the structured
+//! `Cid` type of the [cid crate](https://crates.io/crates/cid).
 
 use std::ops::Deref;
+use std::str::FromStr;
 
 use ciborium::Value;
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
 
+use crate::wire::multibase;
 use crate::wire::varint::UnsignedVarint;
 
+/// CID versions parsed in [CidParsing::Strict] mode (CIDv0 and CIDv1)
+pub const SUPPORTED_CID_VERSIONS: [u64; 2] = [0, 1];
+
+/// Multihash code of the identity hash function, whose CIDs embed the block data as their digest
+pub const IDENTITY_MULTIHASH_CODE: u64 = 0x00;
+
 /// Raw CID (Content Identifier), basically a dumb wrapper around a byte vector.
 ///
 /// This struct is used to represent CIDs in their raw byte form, without any parsing or interpretation.
@@ -67,12 +76,192 @@ impl RawCid {
         hex::encode(&self.0)
     }
 
+    /// Returns the canonical string representation of the CID
+    ///
+    /// CIDv0 are encoded in base58btc (`Qm…`), and CIDv1 in multibase base32 lowercase (`bafy…`),
+    /// as done by most IPFS implementations. CIDs which are neither are encoded in multibase base32 as well.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::wire::cid::RawCid;
+    /// let cid = RawCid::from_hex("0155122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4").unwrap();
+    /// assert_eq!(cid.to_cid_string(), "bafkreidbxzk2ryxwwtqxem4l3xyyjvw35yu4tcct4cqeqxwo47zhxgxqwq");
+    /// ```
+    pub fn to_cid_string(&self) -> String {
+        if self.0.len() == 34 && self.0.starts_with(&[0x12, 0x20]) {
+            return multibase::encode_base58btc(&self.0);
+        }
+        let mut out = String::from(multibase::BASE32_LOWER_PREFIX);
+        out.push_str(&multibase::encode_base32_lower(&self.0));
+        out
+    }
+
+    /// Returns the CIDv1 string of the CID, in multibase base32 lowercase (`bafy…`)
+    ///
+    /// CIDv0 are first converted to the equivalent CIDv1 (dag-pb codec, same multihash).
+    /// CIDs which are neither CIDv0 nor CIDv1 are encoded as is.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::wire::cid::RawCid;
+    /// let cid: RawCid = "QmbWqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR".parse().unwrap();
+    /// assert_eq!(cid.to_string_v1(), "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi");
+    /// ```
+    pub fn to_string_v1(&self) -> String {
+        let mut out = String::from(multibase::BASE32_LOWER_PREFIX);
+        if self.version() == Some(0) {
+            let v1: Vec<u8> = [0x01, 0x70].iter().chain(&self.0).copied().collect();
+            out.push_str(&multibase::encode_base32_lower(&v1));
+        } else {
+            out.push_str(&multibase::encode_base32_lower(&self.0));
+        }
+        out
+    }
+
+    /// Returns the CIDv0 string of the CID, in base58btc (`Qm…`)
+    ///
+    /// Only CIDv0, and CIDv1 which could be CIDv0 (dag-pb codec and 32-byte sha2-256 multihash), have a
+    /// CIDv0 string. Returns `None` for any other CID.
+    pub fn to_string_v0(&self) -> Option<String> {
+        match self.version()? {
+            0 => Some(multibase::encode_base58btc(&self.0)),
+            1 if self.codec() == Some(0x70) => {
+                let multihash = self.multihash_bytes()?;
+                (multihash.len() == 34 && multihash.starts_with(&[0x12, 0x20]))
+                    .then(|| multibase::encode_base58btc(multihash))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the multihash part of the CID (the multihash code and length prefix included)
+    ///
+    /// For CIDv0, this is the whole CID. For CIDv1, this is everything after the version and the codec.
+    /// Returns `None` if the CID is not well-formed.
+    fn multihash_bytes(&self) -> Option<&[u8]> {
+        let bytes = &self.0;
+        if bytes.len() == 34 && bytes.starts_with(&[0x12, 0x20]) {
+            return Some(bytes);
+        }
+        if bytes.first() != Some(&0x01) {
+            return None;
+        }
+        let (_, codec_size) = UnsignedVarint::decode(bytes.get(1..).unwrap_or_default())?;
+        Some(
+            bytes
+                .get(codec_size.saturating_add(1)..)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Returns the multicodec of the content (0x70 for dag-pb, 0x55 for raw, 0x71 for dag-cbor, etc)
+    ///
+    /// CIDv0 are always dag-pb, while CIDv1 carry their codec right after the version.
+    /// Returns `None` if the CID is neither a CIDv0 nor a CIDv1.
+    pub fn codec(&self) -> Option<u64> {
+        let bytes = &self.0;
+        if bytes.len() == 34 && bytes.starts_with(&[0x12, 0x20]) {
+            return Some(0x70);
+        }
+        if bytes.first() != Some(&0x01) {
+            return None;
+        }
+        UnsignedVarint::decode(bytes.get(1..)?).map(|(codec, _)| codec.0)
+    }
+
+    /// Returns the multihash code of the CID (0x12 for sha2-256, 0x00 for identity, etc)
+    ///
+    /// Returns `None` if the CID is not well-formed.
+    pub fn multihash_code(&self) -> Option<u64> {
+        let multihash = self.multihash_bytes()?;
+        UnsignedVarint::decode(multihash).map(|(code, _)| code.0)
+    }
+
+    /// Returns the raw digest of the CID multihash, as keyed in the CARv2 indexes
+    ///
+    /// Returns `None` if the CID is not well-formed (including a digest length not matching the declared one).
+    pub fn digest(&self) -> Option<&[u8]> {
+        let multihash = self.multihash_bytes()?;
+        let (_, code_size) = UnsignedVarint::decode(multihash)?;
+        let (length, length_size) =
+            UnsignedVarint::decode(multihash.get(code_size..).unwrap_or_default())?;
+        let digest = multihash
+            .get(code_size.saturating_add(length_size)..)
+            .unwrap_or_default();
+        (digest.len() as u64 == length.0).then_some(digest)
+    }
+
+    /// Returns true if the CID uses the identity multihash, i.e. embeds the block data
+    pub fn is_identity(&self) -> bool {
+        self.multihash_code() == Some(IDENTITY_MULTIHASH_CODE)
+    }
+
+    /// Returns the block data embedded in an identity CID
+    ///
+    /// Returns `None` if the CID does not use the identity multihash, or is not well-formed.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::wire::cid::RawCid;
+    /// // Raw codec, identity multihash of "hello"
+    /// let cid = RawCid::from_hex("0155000568656c6c6f").unwrap();
+    /// assert_eq!(cid.identity_data(), Some(&b"hello"[..]));
+    /// ```
+    pub fn identity_data(&self) -> Option<&[u8]> {
+        if self.is_identity() {
+            self.digest()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the data inlined in the CID, for identity CIDs
+    ///
+    /// Identity CIDs do not point to a block: they are the block. Such CIDs appear as roots, as links, and
+    /// sometimes as section CIDs; their data can be used directly, without looking for a section in the archive
+    /// (see [CarReader::find_section](crate::CarReader::find_section)).
+    ///
+    /// Returns `None` if the CID does not use the identity multihash, or is not well-formed.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::wire::cid::RawCid;
+    /// let cid = RawCid::from_hex("0155000568656c6c6f").unwrap();
+    /// assert_eq!(cid.inline_data(), Some(&b"hello"[..]));
+    /// // Empty inline data
+    /// let empty = RawCid::from_hex("01550000").unwrap();
+    /// assert_eq!(empty.inline_data(), Some(&b""[..]));
+    /// ```
+    pub fn inline_data(&self) -> Option<&[u8]> {
+        self.identity_data()
+    }
+
+    /// Returns true if the CID multihash digest is the given digest
+    ///
+    /// The comparison is done in place, without allocating. This is how CIDs are matched against the
+    /// CARv2 index entries, which are keyed by digest: CIDs which only differ by their codec (or version) match the same digest.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::wire::cid::RawCid;
+    /// let v1 = RawCid::from_hex("01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451").unwrap();
+    /// let v0 = RawCid::from_hex("1220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451").unwrap();
+    /// assert!(v1.digest_eq(v0.digest().unwrap()));
+    /// assert!(!v1.digest_eq(&[0; 32]));
+    /// ```
+    pub fn digest_eq(&self, digest: &[u8]) -> bool {
+        self.digest() == Some(digest)
+    }
+
     /// Tries to read a properly formed CID from the given bytes
     ///
     /// This function attempts to parse the input bytes as a CID, supporting both CIDv0 and CIDv1 formats.
     /// It acts as a dumb parser, therefore it does not validate the multihash coherence, but only that
     /// the CID conforms to the expected binary structure of either CIDv0 or CIDv1.
     ///
+    /// Identity CIDs (multihash code 0x00) are CIDv1 whose digest is the block data itself, of any length
+    /// (including empty): the whole inline data is read as part of the CID, see [RawCid::inline_data].
+    ///
     /// ## Returns
     /// - `Ok((RawCid, bytes_read))` if the input bytes contain a valid CID, where
     ///   `RawCid` is the parsed CID and `bytes_read` is the number of bytes consumed during parsing.
@@ -86,49 +275,246 @@ impl RawCid {
     /// let (parsed_cidv0, size_v0) = RawCid::try_read_bytes(&cidv0_bytes).unwrap();
     /// assert_eq!(size_v0, 34);
     /// assert_eq!(parsed_cidv0.bytes(), &cidv0_bytes[..34]);
+    ///
+    /// // Identity CID embedding "hello", followed by other data
+    /// let (inline, size) = RawCid::try_read_bytes(&hex::decode("0155000568656c6c6fffff").unwrap()).unwrap();
+    /// assert_eq!(size, 9);
+    /// assert_eq!(inline.inline_data(), Some(&b"hello"[..]));
     /// ```
     pub fn try_read_bytes(bytes: &[u8]) -> Result<(Self, usize), CidFormatError> {
+        Self::try_read_bytes_with(bytes, CidParsing::Strict)
+    }
+
+    /// Tries to read a CID from the given bytes, with the given parsing mode
+    ///
+    /// In [CidParsing::Strict] mode, this is exactly [RawCid::try_read_bytes]. In [CidParsing::Lenient] mode,
+    /// CIDs of unknown versions are also accepted, as long as they follow the CIDv1 framing
+    /// (version varint, codec varint, multihash), so they can be carried opaquely.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::wire::cid::{CidFormatError, CidParsing, RawCid};
+    /// // A (future) CIDv2, raw codec, identity multihash of 2 bytes
+    /// let cidv2_bytes = hex::decode("025500021234").unwrap();
+    /// assert!(matches!(
+    ///     RawCid::try_read_bytes(&cidv2_bytes),
+    ///     Err(CidFormatError::UnsupportedVersion)
+    /// ));
+    /// let (cid, size) = RawCid::try_read_bytes_with(&cidv2_bytes, CidParsing::Lenient).unwrap();
+    /// assert_eq!(size, 6);
+    /// assert_eq!(cid.version(), Some(2));
+    /// ```
+    pub fn try_read_bytes_with(
+        bytes: &[u8],
+        parsing: CidParsing,
+    ) -> Result<(Self, usize), CidFormatError> {
         if bytes.len() < 2 {
             return Err(CidFormatError::InsufficientData);
         }
         // Handle CIDv0 (DagProtobuf, SHA256-256, 32 bytes hash) - prefix Qm...
         if bytes.starts_with(&[0x12, 0x20]) {
-            if bytes.len() < 34 {
-                return Err(CidFormatError::InsufficientData);
-            }
-            let cid_bytes = bytes[..34].to_vec();
-            return Ok((RawCid::new(cid_bytes), 34));
+            let cid_bytes = bytes.get(..34).ok_or(CidFormatError::InsufficientData)?;
+            return Ok((RawCid::new(cid_bytes.to_vec()), 34));
+        }
+        // Handle CIDv1 (version, multicodec, multihash), and the future versions in lenient mode
+        let (version, version_size) = match UnsignedVarint::decode(bytes) {
+            Some((version, size)) => (version.0, size),
+            None => return Err(CidFormatError::InsufficientData),
+        };
+        if version != 1 && parsing == CidParsing::Strict {
+            // Otherwise it is not supported yet
+            return Err(CidFormatError::UnsupportedVersion);
         }
-        // Handle CIDv1 (multibase, multicodec, multihash)
-        if bytes[0] == 0x01 {
-            // Read the multicodec
-            let (_multicodec, mc_size) = match UnsignedVarint::decode(&bytes[1..]) {
+        // Read the multicodec
+        let mc_start = version_size;
+        let (_multicodec, mc_size) =
+            match UnsignedVarint::decode(bytes.get(mc_start..).unwrap_or_default()) {
                 Some((mc, size)) => (mc.0, size),
                 None => return Err(CidFormatError::InsufficientData),
             };
-            // Read the multihash
-            let mh_start = 1 + mc_size;
-            let (_mh_code, mh_code_size) = match UnsignedVarint::decode(&bytes[mh_start..]) {
+        // Read the multihash
+        let mh_start = mc_start.saturating_add(mc_size);
+        let (_mh_code, mh_code_size) =
+            match UnsignedVarint::decode(bytes.get(mh_start..).unwrap_or_default()) {
                 Some((code, size)) => (code.0, size),
                 None => return Err(CidFormatError::InsufficientData),
             };
-            let mh_len_start = mh_start + mh_code_size;
-            let (mh_len, mh_len_size) = match UnsignedVarint::decode(&bytes[mh_len_start..]) {
-                Some((len, size)) => (len.0 as usize, size),
+        let mh_len_start = mh_start.saturating_add(mh_code_size);
+        let (mh_len, mh_len_size) =
+            match UnsignedVarint::decode(bytes.get(mh_len_start..).unwrap_or_default()) {
+                Some((len, size)) => (len.0, size),
                 None => return Err(CidFormatError::InsufficientData),
             };
-            let total_cid_size = 1 + mc_size + mh_code_size + mh_len_size + mh_len;
-            if bytes.len() < total_cid_size {
-                return Err(CidFormatError::InsufficientData);
+        // Identity digests are the inline data: their declared length is arbitrary, never trust it
+        let total_cid_size = usize::try_from(mh_len)
+            .ok()
+            .and_then(|mh_len| mh_len_start.saturating_add(mh_len_size).checked_add(mh_len))
+            .unwrap_or(usize::MAX);
+        let cid_bytes = bytes
+            .get(..total_cid_size)
+            .ok_or(CidFormatError::InsufficientData)?;
+        Ok((RawCid::new(cid_bytes.to_vec()), total_cid_size))
+    }
+
+    /// Returns the version of the CID (0 for CIDv0, 1 for CIDv1, etc)
+    ///
+    /// Returns `None` if the version cannot be decoded.
+    pub fn version(&self) -> Option<u64> {
+        if self.0.len() == 34 && self.0.starts_with(&[0x12, 0x20]) {
+            return Some(0);
+        }
+        UnsignedVarint::decode(&self.0).map(|(version, _)| version.0)
+    }
+}
+
+/// Hash functions computing the multihash of a block, see [RawCid::from_data]
+#[cfg(feature = "hashing")]
+#[doc(cfg(feature = "hashing"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Multihash {
+    /// Identity: the digest is the data itself, only meant for small blocks
+    Identity,
+    /// sha2-256, the most common hash function of IPFS
+    Sha2_256,
+}
+
+#[cfg(feature = "hashing")]
+#[doc(cfg(feature = "hashing"))]
+impl Multihash {
+    /// Returns the multihash code of the hash function
+    pub fn code(self) -> u64 {
+        match self {
+            Multihash::Identity => IDENTITY_MULTIHASH_CODE,
+            Multihash::Sha2_256 => 0x12,
+        }
+    }
+
+    /// Computes the digest of the data
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Multihash::Identity => data.to_vec(),
+            Multihash::Sha2_256 => {
+                use sha2::{Digest, Sha256};
+                Sha256::digest(data).to_vec()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "hashing")]
+#[doc(cfg(feature = "hashing"))]
+impl RawCid {
+    /// Computes the CIDv1 of the block data, with the given codec and hash function
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::ipld::CODEC_RAW;
+    /// use navira_car::wire::cid::{Multihash, RawCid};
+    ///
+    /// let cid = RawCid::from_data(CODEC_RAW, Multihash::Identity, b"hello");
+    /// assert_eq!(cid.to_hex(), "0155000568656c6c6f");
+    /// assert_eq!(cid.inline_data(), Some(&b"hello"[..]));
+    /// ```
+    pub fn from_data(codec: u64, hasher: Multihash, data: &[u8]) -> Self {
+        let digest = hasher.digest(data);
+        let mut bytes = UnsignedVarint(1).encode();
+        bytes.extend(UnsignedVarint(codec).encode());
+        bytes.extend(UnsignedVarint(hasher.code()).encode());
+        bytes.extend(UnsignedVarint(digest.len() as u64).encode());
+        bytes.extend(digest);
+        RawCid::new(bytes)
+    }
+}
+
+#[cfg(feature = "cid")]
+#[doc(cfg(feature = "cid"))]
+impl TryFrom<&RawCid> for cid::Cid {
+    type Error = cid::Error;
+
+    /// Parses the raw bytes into a structured CID
+    fn try_from(cid: &RawCid) -> Result<Self, Self::Error> {
+        cid::Cid::try_from(cid.bytes())
+    }
+}
+
+#[cfg(feature = "cid")]
+#[doc(cfg(feature = "cid"))]
+impl TryFrom<RawCid> for cid::Cid {
+    type Error = cid::Error;
+
+    /// Parses the raw bytes into a structured CID
+    fn try_from(cid: RawCid) -> Result<Self, Self::Error> {
+        cid::Cid::try_from(&cid)
+    }
+}
+
+#[cfg(feature = "cid")]
+#[doc(cfg(feature = "cid"))]
+impl From<&cid::Cid> for RawCid {
+    /// Encodes the structured CID into its raw bytes
+    fn from(cid: &cid::Cid) -> Self {
+        let mut bytes = vec![0u8; cid.encoded_len()];
+        // Writing into a buffer of the encoded length does not fail
+        let written = cid.write_bytes(bytes.as_mut_slice()).unwrap_or_default();
+        bytes.truncate(written);
+        RawCid::new(bytes)
+    }
+}
+
+#[cfg(feature = "cid")]
+#[doc(cfg(feature = "cid"))]
+impl From<cid::Cid> for RawCid {
+    /// Encodes the structured CID into its raw bytes
+    fn from(cid: cid::Cid) -> Self {
+        RawCid::from(&cid)
+    }
+}
+
+impl FromStr for RawCid {
+    type Err = CidStringError;
+
+    /// Parses a CID string: a CIDv0 in base58btc (`Qm…`), or a multibase CID in base32 lowercase (`b…`) or
+    /// base58btc (`z…`)
+    ///
+    /// The decoded bytes must hold exactly one CID, parsed in [CidParsing::Strict] mode.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = if s.len() == 46 && s.starts_with("Qm") {
+            multibase::decode_base58btc(s)
+        } else {
+            let mut chars = s.chars();
+            match chars.next() {
+                Some(multibase::BASE32_LOWER_PREFIX) => {
+                    multibase::decode_base32_lower(chars.as_str())
+                }
+                Some(multibase::BASE58_BTC_PREFIX) => multibase::decode_base58btc(chars.as_str()),
+                Some(prefix) => return Err(CidStringError::UnsupportedMultibase(prefix)),
+                None => return Err(CidStringError::Empty),
             }
-            let cid_bytes = bytes[..total_cid_size].to_vec();
-            return Ok((RawCid::new(cid_bytes), total_cid_size));
         }
-        // Otherwise it is not supported yet
-        Err(CidFormatError::UnsupportedVersion)
+        .ok_or(CidStringError::InvalidEncoding)?;
+        let (cid, size) = RawCid::try_read_bytes(&bytes)?;
+        if size != bytes.len() {
+            return Err(CidStringError::TrailingBytes(
+                bytes.len().saturating_sub(size),
+            ));
+        }
+        Ok(cid)
     }
 }
 
+/// Parsing mode of the CIDs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CidParsing {
+    /// Only CIDv0 and CIDv1 are accepted, anything else is rejected with [CidFormatError::UnsupportedVersion]
+    #[default]
+    Strict,
+    /// CIDs of unknown versions are accepted if their length can be decoded with the CIDv1 framing
+    /// (version varint, codec varint, multihash code varint, digest length varint, digest)
+    ///
+    /// This allows to scan CAR files containing CIDs of future versions, carrying them opaquely.
+    Lenient,
+}
+
 impl std::fmt::Debug for RawCid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "RawCid({})", self.to_hex())
@@ -141,13 +527,86 @@ impl std::fmt::Display for RawCid {
     }
 }
 
+/// CBOR tag of the IPLD links
+const CID_TAG: u64 = 42;
+
+/// Multibase prefix of the binary CIDs wrapped in a CBOR tag 42 (identity, i.e. raw bytes)
+pub const LINK_MULTIBASE_PREFIX: u8 = 0x00;
+
+/// Encoding of the CID bytes wrapped in a CBOR tag 42
+///
+/// [RawCid] and [RawLink] are (de)serialized with [Tag42Encoding::Prefixed]. The [legacy_tag42] module
+/// can be used with `#[serde(with = "...")]` to keep reading data written with [Tag42Encoding::Legacy].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tag42Encoding {
+    /// The CID bytes are prefixed by [LINK_MULTIBASE_PREFIX], as required by the
+    /// [dag-cbor specification](https://ipld.io/specs/codecs/dag-cbor/spec/#links) (and written by go-car)
+    #[default]
+    Prefixed,
+    /// The CID bytes are not prefixed, as [RawCid] was serialized by previous versions of navira-car
+    Legacy,
+}
+
+impl Tag42Encoding {
+    /// Encode the CID as a CBOR tag 42
+    pub fn encode(self, cid: &RawCid) -> Value {
+        let bytes = match self {
+            Tag42Encoding::Prefixed => {
+                let mut bytes = Vec::with_capacity(cid.0.len().saturating_add(1));
+                bytes.push(LINK_MULTIBASE_PREFIX);
+                bytes.extend_from_slice(&cid.0);
+                bytes
+            }
+            Tag42Encoding::Legacy => cid.0.clone(),
+        };
+        Value::Tag(CID_TAG, Box::new(Value::Bytes(bytes)))
+    }
+
+    /// Decode a CID from a CBOR tag 42
+    ///
+    /// ## Returns
+    /// - `Ok(RawCid)` if the value is a tag 42 wrapping the CID bytes, with the expected prefix.
+    /// - `Err(LinkFormatError)` otherwise.
+    pub fn decode(self, value: &Value) -> Result<RawCid, LinkFormatError> {
+        let bytes = match value {
+            Value::Tag(CID_TAG, inner) => match inner.as_ref() {
+                Value::Bytes(bytes) => bytes,
+                _ => return Err(LinkFormatError::NotALink),
+            },
+            _ => return Err(LinkFormatError::NotALink),
+        };
+        match self {
+            Tag42Encoding::Prefixed => match bytes.split_first() {
+                Some((&LINK_MULTIBASE_PREFIX, [])) | None => Err(LinkFormatError::EmptyCid),
+                Some((&LINK_MULTIBASE_PREFIX, cid)) => Ok(RawCid::new(cid.to_vec())),
+                Some((&prefix, _)) => Err(LinkFormatError::InvalidMultibasePrefix(prefix)),
+            },
+            Tag42Encoding::Legacy if bytes.is_empty() => Err(LinkFormatError::EmptyCid),
+            Tag42Encoding::Legacy => Ok(RawCid::new(bytes.clone())),
+        }
+    }
+}
+
+/// Errors related to the decoding of CBOR tag 42 links
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum LinkFormatError {
+    /// The value is not a tag 42 wrapping a byte string
+    #[error("Not a CBOR tag 42 link")]
+    NotALink,
+    /// The link does not contain any CID byte
+    #[error("Empty CID in link")]
+    EmptyCid,
+    /// The CID bytes are prefixed by another multibase than the identity (0x00)
+    #[error("Invalid multibase prefix {0:#x} in link, expected 0x00")]
+    InvalidMultibasePrefix(u8),
+}
+
 impl Serialize for RawCid {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let value = Value::Tag(42, Box::new(Value::Bytes(self.0.clone())));
-        value.serialize(serializer)
+        Tag42Encoding::Prefixed.encode(self).serialize(serializer)
     }
 }
 
@@ -157,12 +616,37 @@ impl<'de> Deserialize<'de> for RawCid {
         D: Deserializer<'de>,
     {
         let value = Value::deserialize(deserializer)?;
-        if let Value::Tag(42, boxed_value) = value {
-            if let Value::Bytes(bytes) = *boxed_value {
-                return Ok(RawCid::new(bytes));
-            }
-        }
-        Err(D::Error::custom("Invalid CID format"))
+        Tag42Encoding::Prefixed
+            .decode(&value)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// (De)serialization of a [RawCid] with the [Tag42Encoding::Legacy] encoding
+///
+/// Use it with `#[serde(with = "navira_car::wire::cid::legacy_tag42")]` on the fields written by previous
+/// versions of navira-car. While reading, the spec-compliant encoding is accepted too.
+pub mod legacy_tag42 {
+    use super::*;
+
+    /// Serialize the CID without the multibase prefix
+    pub fn serialize<S: Serializer>(cid: &RawCid, serializer: S) -> Result<S::Ok, S::Error> {
+        Tag42Encoding::Legacy.encode(cid).serialize(serializer)
+    }
+
+    /// Deserialize a CID, with or without the multibase prefix
+    ///
+    /// A leading 0x00 byte can not start a legacy CID (it would be a CIDv0 or CIDv1), so it is always read
+    /// as the multibase prefix.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RawCid, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Tag42Encoding::Prefixed
+            .decode(&value)
+            .or_else(|e| match e {
+                LinkFormatError::InvalidMultibasePrefix(_) => Tag42Encoding::Legacy.decode(&value),
+                e => Err(e),
+            })
+            .map_err(D::Error::custom)
     }
 }
 
@@ -194,6 +678,26 @@ pub enum CidFormatError {
     UnsupportedVersion,
 }
 
+/// Errors related to the parsing of CID strings
+#[derive(thiserror::Error, Debug)]
+pub enum CidStringError {
+    /// The string is empty
+    #[error("Empty CID string")]
+    Empty,
+    /// The multibase encoding of the string (given by its prefix) is not supported
+    #[error("Unsupported multibase prefix '{0}'")]
+    UnsupportedMultibase(char),
+    /// The string holds characters outside of the alphabet of its multibase encoding
+    #[error("Invalid multibase encoding")]
+    InvalidEncoding,
+    /// The decoded bytes are not a valid CID
+    #[error("Invalid CID: {0}")]
+    InvalidCid(#[from] CidFormatError),
+    /// The decoded bytes hold more than a CID (number of bytes after the CID)
+    #[error("{0} trailing bytes after the CID")]
+    TrailingBytes(usize),
+}
+
 /// RawLink is the equivalent of a IPLD Link in the context of CAR files.
 ///
 /// Link is essentially a wrapper around a CID, and for historical reasons, both exists
@@ -254,11 +758,7 @@ impl Serialize for RawLink {
     where
         S: Serializer,
     {
-        let mut cid_bytes = self.0.bytes().to_vec();
-        // Preprend the multihash 0x00 (base 256) to indicate that this is a raw CID, as per the IPLD specification for raw CIDs in Links.
-        cid_bytes.insert(0, 0x00);
-        let value = Value::Tag(42, Box::new(Value::Bytes(cid_bytes)));
-        value.serialize(serializer)
+        self.0.serialize(serializer)
     }
 }
 
@@ -267,22 +767,42 @@ impl<'de> Deserialize<'de> for RawLink {
     where
         D: Deserializer<'de>,
     {
-        let value = Value::deserialize(deserializer)?;
-        if let Value::Tag(42, boxed_value) = value {
-            if let Value::Bytes(bytes) = *boxed_value {
-                // Remove the leading 0x00 byte before creating the RawCid
-                return Ok(RawLink(RawCid::new(bytes[1..].to_vec())));
-            }
-        }
-        Err(D::Error::custom("Invalid CID format"))
+        RawCid::deserialize(deserializer).map(RawLink)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::testdata;
     use crate::wire::cid::RawLink;
 
-    use super::RawCid;
+    use super::*;
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn test_raw_cid_from_data() {
+        use crate::ipld::CODEC_RAW;
+        use crate::wire::v1::Section;
+
+        let cid = RawCid::from_data(CODEC_RAW, Multihash::Sha2_256, b"cccc");
+        assert_eq!(
+            cid.to_hex(),
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451"
+        );
+        // The CIDv1 of the fixture (all sha2-256) are computed back from their blocks
+        let mut reader = crate::CarReader::from_bytes(testdata::CARV1_BASIC).unwrap();
+        while let Ok(section) = reader.read_section() {
+            let cid = section.cid();
+            if cid.version() == Some(1) {
+                let data = section.block().data().to_vec();
+                let computed = Section::from_data(cid.codec().unwrap(), Multihash::Sha2_256, data);
+                assert_eq!(computed, section.section);
+            }
+        }
+        // Codecs over 0x7f take several varint bytes (dag-json)
+        let cid = RawCid::from_data(0x0129, Multihash::Identity, b"");
+        assert_eq!(cid.to_hex(), "01a9020000");
+    }
 
     #[test]
     fn test_raw_cid_serialization() {
@@ -290,16 +810,75 @@ mod tests {
 
         let mut buf = Vec::new();
         ciborium::ser::into_writer(&raw_cid, &mut buf).unwrap();
-        let expected = vec![0xD8, 0x2A, 0x45, 0x01, 0x55, 0x02, 0x03, 0x04]; // Tag 42
+        let expected = vec![0xD8, 0x2A, 0x46, 0x00, 0x01, 0x55, 0x02, 0x03, 0x04]; // Tag 42 + prepended 0x00
         assert_eq!(buf, expected);
     }
 
     #[test]
     fn test_raw_cid_deserialization() {
-        let data = vec![0xD8, 0x2A, 0x45, 0x01, 0x55, 0x02, 0x03, 0x04]; // Tag 42
+        let data = vec![0xD8, 0x2A, 0x46, 0x00, 0x01, 0x55, 0x02, 0x03, 0x04]; // Tag 42 + prepended 0x00
         let raw_cid: RawCid = ciborium::de::from_reader(data.as_slice()).unwrap();
         let expected = RawCid::new(vec![0x01, 0x55, 0x02, 0x03, 0x04]);
         assert_eq!(raw_cid, expected);
+
+        // Missing (legacy) or non-zero multibase prefix
+        let data = vec![0xD8, 0x2A, 0x45, 0x01, 0x55, 0x02, 0x03, 0x04];
+        assert!(ciborium::de::from_reader::<RawCid, _>(data.as_slice()).is_err());
+        let value = Value::Tag(42, Box::new(Value::Bytes(vec![0x00])));
+        assert_eq!(
+            Tag42Encoding::Prefixed.decode(&value),
+            Err(LinkFormatError::EmptyCid)
+        );
+        let value = Value::Tag(42, Box::new(Value::Bytes(vec![0x01, 0x55])));
+        assert_eq!(
+            Tag42Encoding::Prefixed.decode(&value),
+            Err(LinkFormatError::InvalidMultibasePrefix(0x01))
+        );
+    }
+
+    #[test]
+    fn test_raw_cid_legacy_tag42() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Legacy(#[serde(with = "legacy_tag42")] RawCid);
+
+        let cid = RawCid::new(vec![0x01, 0x55, 0x02, 0x03, 0x04]);
+        let legacy = vec![0xD8, 0x2A, 0x45, 0x01, 0x55, 0x02, 0x03, 0x04];
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&Legacy(cid.clone()), &mut buf).unwrap();
+        assert_eq!(buf, legacy);
+
+        // Both encodings are read back
+        let read: Legacy = ciborium::de::from_reader(legacy.as_slice()).unwrap();
+        assert_eq!(read.0, cid);
+        let mut prefixed = Vec::new();
+        ciborium::ser::into_writer(&cid, &mut prefixed).unwrap();
+        let read: Legacy = ciborium::de::from_reader(prefixed.as_slice()).unwrap();
+        assert_eq!(read.0, cid);
+    }
+
+    #[test]
+    fn test_tag42_go_car_fixtures() {
+        // The roots of the fixtures, as encoded by go-car in the headers
+        for (car, roots) in [
+            (testdata::CARV1_BASIC, testdata::CARV1_BASIC_ROOTS),
+            (&testdata::CARV2_BASIC[51..], testdata::CARV2_BASIC_ROOTS),
+        ] {
+            for root in roots {
+                let cid = RawCid::from_hex(root).unwrap();
+                let mut encoded = Vec::new();
+                ciborium::ser::into_writer(&cid, &mut encoded).unwrap();
+                let mut link = Vec::new();
+                ciborium::ser::into_writer(&RawLink::new(cid.clone()), &mut link).unwrap();
+                assert_eq!(link, encoded);
+                assert!(
+                    car.windows(encoded.len()).any(|w| w == encoded),
+                    "{} is not encoded as in the fixture",
+                    root
+                );
+                let decoded: RawLink = ciborium::de::from_reader(encoded.as_slice()).unwrap();
+                assert_eq!(decoded.to_raw_cid(), &cid);
+            }
+        }
     }
 
     #[test]
@@ -330,6 +909,37 @@ mod tests {
         assert_eq!(parsed_cidv1.bytes(), &cidv1_bytes[..]);
     }
 
+    #[test]
+    fn test_raw_cid_to_cid_string() {
+        let cidv0 = RawCid::from_hex(
+            "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+        )
+        .unwrap();
+        assert_eq!(
+            cidv0.to_cid_string(),
+            "QmNX6Tffavsya4xgBi2VJQnSuqy9GsxongxZZ9uZBqp16d"
+        );
+    }
+
+    #[test]
+    fn test_raw_cid_multihash_accessors() {
+        let cidv0 = RawCid::from_hex(
+            "122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+        )
+        .unwrap();
+        let cidv1 = RawCid::from_hex(
+            "0155122002acecc5de2438ea4126a3010ecb1f8a599c8eff22fff1a1dcffe999b27fd3de",
+        )
+        .unwrap();
+        assert_eq!(cidv0.multihash_code(), Some(0x12));
+        assert_eq!(cidv1.multihash_code(), Some(0x12));
+        assert_eq!(cidv0.digest(), Some(&cidv0.bytes()[2..]));
+        assert_eq!(cidv0.digest(), cidv1.digest());
+
+        let truncated = RawCid::new(cidv1.bytes()[..20].to_vec());
+        assert_eq!(truncated.digest(), None);
+    }
+
     #[test]
     fn test_raw_cid_bin_parsing_cidv1_insufficient() {
         let cidv1_bytes = vec![
@@ -343,6 +953,36 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_raw_cid_bin_parsing_identity() {
+        // dag-cbor, identity multihash of the empty map
+        let (cid, size) = RawCid::try_read_bytes(&[0x01, 0x71, 0x00, 0x01, 0xa0, 0xff]).unwrap();
+        assert_eq!(size, 5);
+        assert_eq!(cid.inline_data(), Some(&[0xa0][..]));
+        let (empty, size) = RawCid::try_read_bytes(&[0x01, 0x55, 0x00, 0x00]).unwrap();
+        assert_eq!(size, 4);
+        assert_eq!(empty.inline_data(), Some(&[][..]));
+
+        // Truncated inline data, or a length which does not fit in memory
+        assert!(matches!(
+            RawCid::try_read_bytes(&[0x01, 0x55, 0x00, 0x05, 0x68, 0x65]),
+            Err(CidFormatError::InsufficientData)
+        ));
+        let mut huge = vec![0x01, 0x55, 0x00];
+        huge.extend(UnsignedVarint(u64::MAX).encode());
+        assert!(matches!(
+            RawCid::try_read_bytes(&huge),
+            Err(CidFormatError::InsufficientData)
+        ));
+
+        // Not an identity CID
+        let cidv0 = RawCid::from_hex(
+            "12200e7071c59df3b9454d1d18a15270aa36d54f89606a576dc621757afd44ad1d2e",
+        )
+        .unwrap();
+        assert_eq!(cidv0.inline_data(), None);
+    }
+
     #[test]
     fn test_link_serialization() {
         let link = RawLink(RawCid::new(vec![0x01, 0x55, 0x02, 0x03, 0x04]));
@@ -360,4 +1000,87 @@ mod tests {
         let expected = RawLink(RawCid::new(vec![0x01, 0x55, 0x02, 0x03, 0x04]));
         assert_eq!(link, expected);
     }
+
+    #[test]
+    fn test_raw_cid_accessors() {
+        for (fixture, version, codec) in [
+            (&testdata::CARV1_BASIC_SECTIONS[0], 1, 0x71),
+            (&testdata::CARV1_BASIC_SECTIONS[1], 0, 0x70),
+            (&testdata::CARV1_BASIC_SECTIONS[2], 1, 0x55),
+        ] {
+            let cid = fixture.cid();
+            assert_eq!(cid.version(), Some(version));
+            assert_eq!(cid.codec(), Some(codec));
+            assert_eq!(cid.multihash_code(), Some(0x12));
+            assert_eq!(cid.digest().map(<[u8]>::len), Some(32));
+        }
+        let unknown = RawCid::new(vec![0x02, 0x55, 0x12, 0x01, 0xaa]);
+        assert_eq!(unknown.codec(), None);
+        assert_eq!(unknown.digest(), None);
+    }
+
+    #[cfg(feature = "cid")]
+    #[test]
+    fn test_raw_cid_conversion() {
+        for fixture in testdata::CARV1_BASIC_SECTIONS {
+            let raw = fixture.cid();
+            let cid = cid::Cid::try_from(&raw).unwrap();
+            assert_eq!(u64::from(cid.version()), raw.version().unwrap());
+            assert_eq!(cid.codec(), raw.codec().unwrap());
+            assert_eq!(cid.hash().code(), raw.multihash_code().unwrap());
+            assert_eq!(cid.hash().digest(), raw.digest().unwrap());
+            assert_eq!(RawCid::from(cid), raw);
+        }
+        assert!(cid::Cid::try_from(RawCid::new(vec![0x02, 0x55])).is_err());
+    }
+
+    #[test]
+    fn test_raw_cid_strings() {
+        let v0 = "QmbWqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR";
+        let v1 = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+        let cid_v0 = RawCid::from_str(v0).unwrap();
+        let cid_v1 = RawCid::from_str(v1).unwrap();
+        assert_eq!(cid_v0.version(), Some(0));
+        assert_eq!(cid_v1.version(), Some(1));
+        assert_eq!(cid_v0.digest(), cid_v1.digest());
+        // Round-trips, and conversions between the versions
+        assert_eq!(cid_v0.to_string_v0().as_deref(), Some(v0));
+        assert_eq!(cid_v0.to_string_v1(), v1);
+        assert_eq!(cid_v1.to_string_v0().as_deref(), Some(v0));
+        assert_eq!(cid_v1.to_string_v1(), v1);
+        assert_eq!(cid_v1.to_cid_string(), v1);
+        // Multibase base58btc
+        let z = format!("z{}", multibase::encode_base58btc(cid_v1.bytes()));
+        assert_eq!(RawCid::from_str(&z).unwrap(), cid_v1);
+
+        for fixture in testdata::CARV1_BASIC_SECTIONS {
+            let cid = fixture.cid();
+            assert_eq!(RawCid::from_str(&cid.to_cid_string()).unwrap(), cid);
+        }
+        // Raw CIDs have no CIDv0 string
+        let raw = testdata::CARV1_BASIC_SECTIONS[2].cid();
+        assert_eq!(raw.to_string_v0(), None);
+
+        assert!(matches!(RawCid::from_str(""), Err(CidStringError::Empty)));
+        assert!(matches!(
+            RawCid::from_str("Bafy"),
+            Err(CidStringError::UnsupportedMultibase('B'))
+        ));
+        assert!(matches!(
+            RawCid::from_str("bafy0"),
+            Err(CidStringError::InvalidEncoding)
+        ));
+        assert!(matches!(
+            RawCid::from_str("bafybeigd"),
+            Err(CidStringError::InvalidCid(CidFormatError::InsufficientData))
+        ));
+        let trailing = format!(
+            "b{}",
+            multibase::encode_base32_lower(&[cid_v1.bytes(), &[0]].concat())
+        );
+        assert!(matches!(
+            RawCid::from_str(&trailing),
+            Err(CidStringError::TrailingBytes(1))
+        ));
+    }
 }
@@ -0,0 +1,496 @@
+//! Framing of CAR sections for frame-based transports
+//!
+//! Framed transports (QUIC streams and datagrams, WebSocket messages, etc.) bound the size of each frame,
+//! while a section can be as large as the [Limits] allow. [SectionFramer] slices the serialized sections into
+//! frames of at most a given size, each starting with a tiny header; [SectionReassembler] puts them back together
+//! on the other side. Both are sans-IO: they only produce and consume byte buffers, the transport is up to the caller.
+//!
+//! ## Frame format
+//!
+//! Each frame is made of a header of unsigned varints, followed by a slice of the serialized section
+//! (as in a CARv1 payload: length varint, CID and block data):
+//! - the sequence number of the section, counted from 0 by the framer;
+//! - the offset of the slice in the serialized section;
+//! - only in the first frame of a section (offset 0), the length of the serialized section.
+//!
+//! As the frames locate their slice, they may be delivered out of order, interleaved, or duplicated (e.g. over
+//! datagrams): the reassembler returns each section once all its bytes are received. The sequence numbers
+//! give the order in which the sections were framed.
+//!
+//! ## Examples
+//! ```
+//! use navira_car::wire::cid::RawCid;
+//! use navira_car::wire::frame::{SectionFramer, SectionReassembler};
+//! use navira_car::wire::v1::{Block, Section};
+//!
+//! let cid = RawCid::from_hex(
+//!     "015512200000000000000000000000000000000000000000000000000000000000000000",
+//! )
+//! .unwrap();
+//! let section = Section::new(cid, Block::new(b"aaaa".to_vec()));
+//!
+//! let mut framer = SectionFramer::new(32).unwrap();
+//! framer.push_section(&section);
+//! let mut reassembler = SectionReassembler::new();
+//! let mut received = None;
+//! while let Some(frame) = framer.next_frame() {
+//!     assert!(frame.len() <= 32);
+//!     received = reassembler.receive_frame(&frame).unwrap().or(received);
+//! }
+//! assert_eq!(received, Some((0, section)));
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::wire::{
+    limits::Limits,
+    v1::{Section, SectionFormatError},
+    varint::UnsignedVarint,
+};
+
+/// Maximum size of a frame header: three varints of at most 10 bytes
+pub const MAX_FRAME_HEADER_SIZE: usize = 30;
+
+/// Smallest maximum frame size accepted by the framer, leaving room for a header and some payload
+pub const MIN_FRAME_SIZE: usize = MAX_FRAME_HEADER_SIZE + 2;
+
+/// Default maximum number of sections being reassembled at once
+const DEFAULT_MAX_PENDING: usize = 64;
+
+/// Errors related to the framing of sections
+#[derive(thiserror::Error, Debug)]
+pub enum FrameError {
+    /// The maximum frame size cannot hold a frame header and some payload (see [MIN_FRAME_SIZE])
+    #[error("Frame size too small: {0} bytes (min: {MIN_FRAME_SIZE} bytes)")]
+    FrameTooSmall(usize),
+    /// The frame header is truncated or malformed
+    #[error("Malformed frame header")]
+    MalformedHeader,
+    /// The section announced by the frame exceeds the configured size limits
+    #[error("Section {id} too large: {length} bytes (max: {max} bytes)")]
+    SectionTooLarge {
+        /// Sequence number of the section
+        id: u64,
+        /// Length of the serialized section (or end of the frame slice)
+        length: u64,
+        /// Maximum section size allowed by the limits, including the length varint
+        max: u64,
+    },
+    /// The frame slice overlaps another slice of the section, or goes past its end
+    #[error("Inconsistent frame for section {id} at offset {offset}")]
+    Inconsistent {
+        /// Sequence number of the section
+        id: u64,
+        /// Offset of the slice in the section
+        offset: u64,
+    },
+    /// Too many sections are being reassembled at once
+    #[error("Too many sections being reassembled (max: {0})")]
+    TooManyPending(usize),
+    /// The reassembled bytes are not a valid section
+    #[error("Invalid section {id}: {error}", id = .0, error = .1)]
+    InvalidSection(u64, SectionFormatError),
+}
+
+/// A section being sliced into frames
+#[derive(Debug)]
+struct OutgoingSection {
+    id: u64,
+    bytes: Vec<u8>,
+    /// Offset of the next slice to frame
+    offset: usize,
+}
+
+/// Slices serialized sections into frames of bounded size
+///
+/// Sections are queued with [SectionFramer::push_section], and their frames are taken with
+/// [SectionFramer::next_frame], or [SectionFramer::next_frame_within] to also respect the current byte budget
+/// of the transport (e.g. its flow-control window).
+#[derive(Debug)]
+pub struct SectionFramer {
+    max_frame_size: usize,
+    next_id: u64,
+    queue: VecDeque<OutgoingSection>,
+}
+
+impl SectionFramer {
+    /// Create a framer producing frames of at most `max_frame_size` bytes
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SectionFramer)`, if the size can hold a frame header and some payload.
+    /// * `Err(FrameError::FrameTooSmall)`, if it is below [MIN_FRAME_SIZE].
+    pub fn new(max_frame_size: usize) -> Result<Self, FrameError> {
+        if max_frame_size < MIN_FRAME_SIZE {
+            return Err(FrameError::FrameTooSmall(max_frame_size));
+        }
+        Ok(Self {
+            max_frame_size,
+            next_id: 0,
+            queue: VecDeque::new(),
+        })
+    }
+
+    /// Get the maximum size of the produced frames
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Queue a section to be framed, returning its sequence number
+    pub fn push_section(&mut self, section: &Section) -> u64 {
+        let id = self.next_id;
+        self.next_id = self.next_id.saturating_add(1);
+        self.queue.push_back(OutgoingSection {
+            id,
+            bytes: section.to_bytes(),
+            offset: 0,
+        });
+        id
+    }
+
+    /// Check if frames are waiting to be sent
+    pub fn has_frames(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Number of bytes of the queued sections not framed yet, excluding the frame headers
+    pub fn pending_bytes(&self) -> usize {
+        self.queue
+            .iter()
+            .map(|s| s.bytes.len().saturating_sub(s.offset))
+            .sum()
+    }
+
+    /// Take the next frame, of at most the maximum frame size
+    ///
+    /// The frames of a section are produced in order, and a section is only started once the previous one is
+    /// fully framed.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        self.next_frame_within(self.max_frame_size)
+    }
+
+    /// Take the next frame, of at most `budget` bytes (and of the maximum frame size)
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Vec<u8>)`, the frame to send.
+    /// * `None`, if no frame is waiting, or if the budget cannot hold the frame header and at least one byte.
+    pub fn next_frame_within(&mut self, budget: usize) -> Option<Vec<u8>> {
+        let budget = budget.min(self.max_frame_size);
+        let current = self.queue.front_mut()?;
+        let mut frame = UnsignedVarint(current.id).encode();
+        frame.extend(UnsignedVarint(current.offset as u64).encode());
+        if current.offset == 0 {
+            frame.extend(UnsignedVarint(current.bytes.len() as u64).encode());
+        }
+        let remaining = current.bytes.len().saturating_sub(current.offset);
+        let length = remaining.min(budget.checked_sub(frame.len())?);
+        if length == 0 && remaining > 0 {
+            return None;
+        }
+        let end = current.offset.saturating_add(length);
+        frame.extend_from_slice(current.bytes.get(current.offset..end).unwrap_or_default());
+        current.offset = end;
+        if current.offset == current.bytes.len() {
+            self.queue.pop_front();
+        }
+        Some(frame)
+    }
+}
+
+/// A section being reassembled from its frames
+#[derive(Debug, Default)]
+struct IncomingSection {
+    /// Length of the serialized section, known once its first frame is received
+    length: Option<u64>,
+    /// Received slices, by offset
+    slices: BTreeMap<u64, Vec<u8>>,
+    /// Number of bytes received
+    received: u64,
+}
+
+/// Reassembles the sections sliced by a [SectionFramer]
+///
+/// Frames can be received in any order: a section is returned by [SectionReassembler::receive_frame] as soon as
+/// all its bytes are received. The memory is bounded by the [Limits] (for each section) and by the maximum
+/// number of sections being reassembled at once.
+#[derive(Debug)]
+pub struct SectionReassembler {
+    limits: Limits,
+    max_pending: usize,
+    pending: BTreeMap<u64, IncomingSection>,
+    /// Sequence numbers of the returned sections, from `delivered_below` (all the lower ones were returned)
+    delivered: BTreeSet<u64>,
+    delivered_below: u64,
+}
+
+impl Default for SectionReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SectionReassembler {
+    /// Create a reassembler with the default limits, holding at most 64 sections being reassembled
+    pub fn new() -> Self {
+        Self {
+            limits: Limits::default(),
+            max_pending: DEFAULT_MAX_PENDING,
+            pending: BTreeMap::new(),
+            delivered: BTreeSet::new(),
+            delivered_below: 0,
+        }
+    }
+
+    /// Get the size limits applied to the sections
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Set the size limits applied to the sections
+    ///
+    /// Frames announcing (or reaching) a larger section are rejected with [FrameError::SectionTooLarge].
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Get the maximum number of sections being reassembled at once
+    pub fn max_pending(&self) -> usize {
+        self.max_pending
+    }
+
+    /// Set the maximum number of sections being reassembled at once
+    ///
+    /// Over an ordered transport, a single section is reassembled at a time. Over datagrams, the frames of the
+    /// next sections may arrive before the previous ones are complete.
+    pub fn set_max_pending(&mut self, max_pending: usize) {
+        self.max_pending = max_pending;
+    }
+
+    /// Number of sections partially received
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Receive a frame
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some((id, Section)))`, if the frame completes the section with this sequence number.
+    /// * `Ok(None)`, if more frames are needed (or if the frame duplicates an already received slice, or belongs
+    ///   to an already returned section).
+    /// * `Err(FrameError)`, if the frame is malformed, inconsistent with the previous ones, or exceeds the limits.
+    ///   The section it belongs to is dropped.
+    pub fn receive_frame(&mut self, frame: &[u8]) -> Result<Option<(u64, Section)>, FrameError> {
+        let mut position = 0;
+        let mut next_varint = || {
+            let (value, size) = UnsignedVarint::decode(frame.get(position..).unwrap_or_default())
+                .ok_or(FrameError::MalformedHeader)?;
+            position = position.saturating_add(size);
+            Ok::<_, FrameError>(value.0)
+        };
+        let id = next_varint()?;
+        let offset = next_varint()?;
+        let length = match offset {
+            0 => Some(next_varint()?),
+            _ => None,
+        };
+        let slice = frame.get(position..).unwrap_or_default();
+
+        let result = self.insert(id, offset, length, slice);
+        if result.is_err() {
+            self.pending.remove(&id);
+        }
+        result
+    }
+
+    /// Record the slice of a section, reassembling it once complete
+    fn insert(
+        &mut self,
+        id: u64,
+        offset: u64,
+        length: Option<u64>,
+        slice: &[u8],
+    ) -> Result<Option<(u64, Section)>, FrameError> {
+        // The length varint of the section is not counted by the limits
+        let max = (self.limits.max_section_size as u64).saturating_add(10);
+        let end = offset.saturating_add(slice.len() as u64);
+        if let Some(length) = length.filter(|&length| length > max) {
+            return Err(FrameError::SectionTooLarge { id, length, max });
+        }
+        if end > max {
+            return Err(FrameError::SectionTooLarge {
+                id,
+                length: end,
+                max,
+            });
+        }
+        if id < self.delivered_below || self.delivered.contains(&id) {
+            return Ok(None);
+        }
+        if !self.pending.contains_key(&id) && self.pending.len() >= self.max_pending {
+            return Err(FrameError::TooManyPending(self.max_pending));
+        }
+
+        let incoming = self.pending.entry(id).or_default();
+        if length.is_some() {
+            incoming.length = length;
+        }
+        let inconsistent = FrameError::Inconsistent { id, offset };
+        if incoming.length.is_some_and(|length| end > length) {
+            return Err(inconsistent);
+        }
+        if incoming.slices.get(&offset).map(Vec::as_slice) == Some(slice) {
+            return Ok(None);
+        }
+        let previous_end = incoming
+            .slices
+            .range(..=offset)
+            .next_back()
+            .map(|(start, bytes)| start.saturating_add(bytes.len() as u64));
+        let next_start = incoming
+            .slices
+            .range(offset..)
+            .next()
+            .map(|(start, _)| *start);
+        if previous_end.is_some_and(|previous_end| previous_end > offset)
+            || next_start.is_some_and(|next_start| next_start < end)
+        {
+            return Err(inconsistent);
+        }
+        incoming.slices.insert(offset, slice.to_vec());
+        incoming.received = incoming.received.saturating_add(slice.len() as u64);
+
+        if incoming.length != Some(incoming.received) {
+            return Ok(None);
+        }
+        let incoming = self.pending.remove(&id).unwrap_or_default();
+        self.delivered.insert(id);
+        while self.delivered.remove(&self.delivered_below) {
+            self.delivered_below = self.delivered_below.saturating_add(1);
+        }
+        let bytes: Vec<u8> = incoming.slices.into_values().flatten().collect();
+        match Section::try_read_bytes_with_limits(&bytes, &self.limits) {
+            Ok((section, size)) if size == bytes.len() => Ok(Some((id, section))),
+            Ok((_, size)) => Err(FrameError::InvalidSection(
+                id,
+                SectionFormatError::InvalidSize(size),
+            )),
+            Err(e) => Err(FrameError::InvalidSection(id, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::carv1_basic_sections;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let sections = carv1_basic_sections();
+        let mut framer = SectionFramer::new(MIN_FRAME_SIZE).unwrap();
+        for section in &sections {
+            framer.push_section(section);
+        }
+        let mut reassembler = SectionReassembler::new();
+        let mut received = Vec::new();
+        while let Some(frame) = framer.next_frame() {
+            assert!(frame.len() <= MIN_FRAME_SIZE);
+            if let Some((_, section)) = reassembler.receive_frame(&frame).unwrap() {
+                received.push(section);
+                assert_eq!(reassembler.pending(), 0);
+            }
+        }
+        assert_eq!(received, sections);
+        assert_eq!(framer.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn test_frame_out_of_order() {
+        let sections = carv1_basic_sections();
+        let mut framer = SectionFramer::new(40).unwrap();
+        for section in &sections {
+            framer.push_section(section);
+        }
+        let mut frames: Vec<Vec<u8>> = std::iter::from_fn(|| framer.next_frame()).collect();
+        frames.reverse();
+        // A duplicated frame is ignored
+        frames.push(frames[0].clone());
+
+        let mut reassembler = SectionReassembler::new();
+        let mut received = BTreeMap::new();
+        for frame in &frames {
+            if let Some((id, section)) = reassembler.receive_frame(frame).unwrap() {
+                received.insert(id, section);
+            }
+        }
+        assert_eq!(received.into_values().collect::<Vec<_>>(), sections);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_frame_budget() {
+        let section = &carv1_basic_sections()[1];
+        let mut framer = SectionFramer::new(1024).unwrap();
+        framer.push_section(section);
+        // The budget cannot hold the header and a byte of payload
+        assert_eq!(framer.next_frame_within(3), None);
+        let frame = framer.next_frame_within(10).unwrap();
+        assert_eq!(frame.len(), 10);
+        let header = 2 + UnsignedVarint(section.total_length() as u64).encode().len();
+        assert_eq!(
+            framer.pending_bytes(),
+            section.total_length() - (10 - header)
+        );
+        assert!(framer.next_frame().is_some());
+        assert!(!framer.has_frames());
+    }
+
+    #[test]
+    fn test_frame_errors() {
+        assert!(matches!(
+            SectionFramer::new(8),
+            Err(FrameError::FrameTooSmall(8))
+        ));
+
+        let mut reassembler = SectionReassembler::new();
+        reassembler.set_limits(Limits::new().with_max_section_size(64));
+        assert!(matches!(
+            reassembler.receive_frame(&[0x80]),
+            Err(FrameError::MalformedHeader)
+        ));
+        // Announcing a section larger than the limits
+        assert!(matches!(
+            reassembler.receive_frame(&[0, 0, 0xff, 0x01]),
+            Err(FrameError::SectionTooLarge { id: 0, .. })
+        ));
+        // A slice ending past the largest offset
+        let mut frame = vec![0];
+        frame.extend(UnsignedVarint(u64::MAX).encode());
+        frame.push(0);
+        assert!(matches!(
+            reassembler.receive_frame(&frame),
+            Err(FrameError::SectionTooLarge { id: 0, .. })
+        ));
+        // Overlapping slices
+        reassembler.receive_frame(&[1, 0, 10, 1, 2, 3, 4]).unwrap();
+        assert!(matches!(
+            reassembler.receive_frame(&[1, 2, 9, 9]),
+            Err(FrameError::Inconsistent { id: 1, offset: 2 })
+        ));
+        assert_eq!(reassembler.pending(), 0);
+        // Bounded number of sections being reassembled
+        reassembler.set_max_pending(1);
+        reassembler.receive_frame(&[2, 1, 0]).unwrap();
+        assert!(matches!(
+            reassembler.receive_frame(&[3, 1, 0]),
+            Err(FrameError::TooManyPending(1))
+        ));
+        // Complete, but not a section
+        assert!(matches!(
+            reassembler.receive_frame(&[2, 0, 2, 0xff]),
+            Err(FrameError::InvalidSection(2, _))
+        ));
+    }
+}
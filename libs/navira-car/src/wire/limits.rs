@@ -0,0 +1,100 @@
+//! Size limits shared by the CAR readers and writers
+//!
+//! The readers reject the headers and sections exceeding these limits, and the writers refuse to emit them,
+//! so that any CAR file written with a given [Limits] can be read back with the same [Limits].
+//!
+//! The defaults follow the usual IPFS conventions (blocks of at most 2 MiB), archives with larger blocks (e.g.
+//! Filecoin pieces) are read by raising them:
+//! ```
+//! use navira_car::{CarReader, Limits};
+//!
+//! let limits = Limits::new().with_max_block_size(8 << 20);
+//! assert!(limits.allows_block(4 << 20));
+//! // The maximum section size follows, leaving room for the CID
+//! assert!(limits.allows_section(8 << 20));
+//! let reader = CarReader::builder().limits(limits).build();
+//! assert_eq!(reader.limits().max_block_size, 8 << 20);
+//! ```
+
+/// Maximum size of a block, 2 MiB by spec
+pub const MAX_BLOCK_SIZE: usize = 1 << 21;
+/// Maximum size of a section (excluding its length prefix), allowing some overhead for the CID
+pub const MAX_SECTION_SIZE: usize = MAX_BLOCK_SIZE + 128;
+/// Maximum size of a CAR v1 header (excluding its length prefix), enough for about 100k roots
+pub const MAX_HEADER_SIZE: usize = 4 << 20;
+/// Maximum size of a CAR v1 header in [Limits::hardened], enough for about 1.5k roots
+pub const HARDENED_MAX_HEADER_SIZE: usize = 64 << 10;
+
+/// Size limits applied when reading and writing CAR archives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of a section, CID and block data included (the length prefix is excluded)
+    pub max_section_size: usize,
+    /// Maximum size of the block data of a section
+    pub max_block_size: usize,
+    /// Maximum size of the CAR v1 header (the length prefix is excluded)
+    pub max_header_size: usize,
+}
+
+impl Limits {
+    /// Create the default limits, see [MAX_SECTION_SIZE], [MAX_BLOCK_SIZE] and [MAX_HEADER_SIZE]
+    pub fn new() -> Self {
+        Self {
+            max_section_size: MAX_SECTION_SIZE,
+            max_block_size: MAX_BLOCK_SIZE,
+            max_header_size: MAX_HEADER_SIZE,
+        }
+    }
+
+    /// Create the limits for untrusted input, see [HARDENED_MAX_HEADER_SIZE]
+    ///
+    /// The sections keep the default limits, as they are bounded by the spec, but the header is bounded
+    /// much tighter than by default: archives with many roots are rare, and every byte of the header is
+    /// buffered before it can be parsed.
+    pub fn hardened() -> Self {
+        Self::new().with_max_header_size(HARDENED_MAX_HEADER_SIZE)
+    }
+
+    /// Set the maximum section size
+    pub fn with_max_section_size(mut self, max_section_size: usize) -> Self {
+        self.max_section_size = max_section_size;
+        self
+    }
+
+    /// Set the maximum block size
+    ///
+    /// The maximum section size is set accordingly, with the same overhead for the CID as the defaults.
+    /// Use [Limits::with_max_section_size] afterwards to set it otherwise.
+    pub fn with_max_block_size(mut self, max_block_size: usize) -> Self {
+        self.max_block_size = max_block_size;
+        self.max_section_size = max_block_size.saturating_add(MAX_SECTION_SIZE - MAX_BLOCK_SIZE);
+        self
+    }
+
+    /// Set the maximum header size
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    /// Returns true if a section of the given length (as in its length prefix) is within the limits
+    pub fn allows_section(&self, length: u64) -> bool {
+        length <= self.max_section_size as u64
+    }
+
+    /// Returns true if a block of the given length is within the limits
+    pub fn allows_block(&self, length: usize) -> bool {
+        length <= self.max_block_size
+    }
+
+    /// Returns true if a CAR v1 header of the given length (as in its length prefix) is within the limits
+    pub fn allows_header(&self, length: u64) -> bool {
+        length <= self.max_header_size as u64
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,8 +3,12 @@
 //! This module contains all the structures, serialization/deserialization logic, and utilities related to
 //! the "wire" format of CAR files. The "wire" format refers to the actual byte-level representation of CAR files,
 //! including headers, sections, and blocks.
+#![cfg_attr(not(test), deny(clippy::arithmetic_side_effects))]
 
 pub mod cid;
+pub mod frame;
+pub mod limits;
+pub mod multibase;
 pub mod v1;
 pub mod v2;
 pub mod varint;
@@ -0,0 +1,160 @@
+//! Multibase encodings used by the textual representation of CIDs
+//!
+//! Only the two encodings of the canonical CID strings are supported (encoding and decoding):
+//! - base58btc, used (without multibase prefix) by CIDv0 strings (`Qm…`).
+//! - base32 lowercase, without padding, used (with the `b` prefix) by CIDv1 strings (`bafy…`).
+//!
+//! See the [multibase specification](https://github.com/multiformats/multibase) for more details.
+
+/// Multibase prefix of the base32 lowercase encoding
+pub const BASE32_LOWER_PREFIX: char = 'b';
+
+/// Multibase prefix of the base58btc encoding (CIDv0 strings are not prefixed)
+pub const BASE58_BTC_PREFIX: char = 'z';
+
+/// Alphabet of the base32 lowercase encoding (RFC 4648, lowercased)
+const BASE32_LOWER_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Alphabet of the base58btc encoding
+const BASE58_BTC_ALPHABET: &[u8; 58] =
+    b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Character of the base32 alphabet for the low 5 bits of the given value
+fn base32_char(value: u16) -> Option<char> {
+    BASE32_LOWER_ALPHABET
+        .get((value & 0x1F) as usize)
+        .map(|c| *c as char)
+}
+
+/// Encodes bytes in base32 lowercase, without padding nor multibase prefix
+pub fn encode_base32_lower(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5).saturating_mul(8));
+    let mut buffer: u16 = 0;
+    let mut bits = 0u32;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u16;
+        // At most 4 bits are left over from the previous byte
+        bits = bits.wrapping_add(8);
+        while bits >= 5 {
+            bits = bits.wrapping_sub(5);
+            out.extend(base32_char(buffer >> bits));
+        }
+    }
+    if bits > 0 {
+        out.extend(base32_char(buffer << 5u32.saturating_sub(bits)));
+    }
+    out
+}
+
+/// Encodes bytes in base58btc, without multibase prefix
+pub fn encode_base58btc(bytes: &[u8]) -> String {
+    // Leading zero bytes are encoded as leading '1's
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+    // Base58 digits, least significant first
+    let mut digits: Vec<u8> =
+        Vec::with_capacity((bytes.len().saturating_mul(138) / 100).saturating_add(1));
+    for byte in bytes.get(zeros..).unwrap_or_default() {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            // At most 57 << 8 plus a carry below 256, far from overflowing
+            carry = carry.wrapping_add((*digit as u32) << 8);
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let mut out = String::with_capacity(zeros.saturating_add(digits.len()));
+    out.extend(std::iter::repeat_n('1', zeros));
+    out.extend(
+        digits
+            .iter()
+            .rev()
+            .filter_map(|d| BASE58_BTC_ALPHABET.get(*d as usize))
+            .map(|c| *c as char),
+    );
+    out
+}
+
+/// Decodes base32 lowercase (without padding nor multibase prefix)
+///
+/// Returns `None` if the string holds a character outside of the alphabet, or non-zero trailing bits.
+pub fn decode_base32_lower(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len().saturating_mul(5) / 8);
+    let mut buffer: u16 = 0;
+    let mut bits = 0u32;
+    for c in s.bytes() {
+        let value = BASE32_LOWER_ALPHABET.iter().position(|a| *a == c)? as u16;
+        buffer = (buffer << 5) | value;
+        // At most 7 bits are left over from the previous characters
+        bits = bits.wrapping_add(5);
+        if bits >= 8 {
+            bits = bits.wrapping_sub(8);
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    (buffer & (1u16 << bits).wrapping_sub(1) == 0).then_some(out)
+}
+
+/// Decodes base58btc (without multibase prefix)
+///
+/// Returns `None` if the string holds a character outside of the alphabet.
+pub fn decode_base58btc(s: &str) -> Option<Vec<u8>> {
+    // Leading '1's are decoded as leading zero bytes
+    let zeros = s.bytes().take_while(|c| *c == b'1').count();
+    // Bytes, least significant first
+    let mut bytes: Vec<u8> =
+        Vec::with_capacity((s.len().saturating_mul(733) / 1000).saturating_add(1));
+    for c in s.bytes().skip(zeros) {
+        let mut carry = BASE58_BTC_ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            // At most 255 * 58 plus a carry below 256, far from overflowing
+            carry = carry.wrapping_add((*byte as u32).wrapping_mul(58));
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let mut out = vec![0; zeros];
+    out.extend(bytes.iter().rev());
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_base32_lower() {
+        assert_eq!(encode_base32_lower(b""), "");
+        assert_eq!(encode_base32_lower(b"f"), "my");
+        assert_eq!(encode_base32_lower(b"foobar"), "mzxw6ytboi");
+    }
+
+    #[test]
+    fn test_encode_base58btc() {
+        assert_eq!(encode_base58btc(b""), "");
+        assert_eq!(encode_base58btc(&[0, 0, 1, 2]), "115T");
+    }
+
+    #[test]
+    fn test_decode_roundtrip() {
+        for bytes in [&b""[..], b"f", b"foobar", &[0, 0, 1, 2], &[0xFF; 34]] {
+            assert_eq!(
+                decode_base32_lower(&encode_base32_lower(bytes)).as_deref(),
+                Some(bytes)
+            );
+            assert_eq!(
+                decode_base58btc(&encode_base58btc(bytes)).as_deref(),
+                Some(bytes)
+            );
+        }
+        assert_eq!(decode_base32_lower("mzxw6ytbo1"), None);
+        assert_eq!(decode_base58btc("0OIl"), None);
+    }
+}
@@ -1,9 +1,9 @@
 use std::ops::Deref;
 
-use crate::wire::cid::{CidFormatError, RawCid};
-
-const MAX_BLOCK_SIZE: usize = 1 << 21; // 2 MiB by spec
-const MAX_SECTION_SIZE: usize = MAX_BLOCK_SIZE + 128; // Allow some overhead for CID and varint
+#[cfg(feature = "hashing")]
+use crate::wire::cid::Multihash;
+use crate::wire::cid::{CidFormatError, CidParsing, RawCid};
+use crate::wire::limits::{Limits, MAX_BLOCK_SIZE};
 
 /// A Block represents a data block in a CAR file.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -24,6 +24,59 @@ impl Block {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    /// Returns true if the block data is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Consumes the Block and returns its data
+    pub fn into_data(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<Vec<u8>> for Block {
+    fn from(data: Vec<u8>) -> Self {
+        Block(data)
+    }
+}
+
+impl From<Block> for Vec<u8> {
+    fn from(block: Block) -> Self {
+        block.0
+    }
+}
+
+/// A BlockRef is a data block borrowed from the bytes of a CAR file, see [Block] for the owned version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRef<'a>(&'a [u8]);
+
+impl<'a> BlockRef<'a> {
+    /// Creates a new BlockRef borrowing the given data
+    pub fn new(data: &'a [u8]) -> Self {
+        BlockRef(data)
+    }
+
+    /// Returns the block data, with the lifetime of the borrowed bytes
+    pub fn data(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Returns the size of the block data in bytes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the block data is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Copies the block data into an owned [Block]
+    pub fn to_owned(&self) -> Block {
+        Block(self.0.to_vec())
+    }
 }
 
 /// A LocatableSection represents a Section that has been read from a CAR file
@@ -53,6 +106,22 @@ pub struct SectionLocation {
     pub length: u64,
 }
 
+impl SectionLocation {
+    /// Location of the sections resolved from identity CIDs, whose data is inlined in the CID
+    ///
+    /// Such sections are not read from the archive: the first bytes of a CAR file are always its header,
+    /// so no actual section can be found at offset 0.
+    pub const INLINE: SectionLocation = SectionLocation {
+        offset: 0,
+        length: 0,
+    };
+
+    /// Returns true if this is the location of a section resolved from an identity CID
+    pub fn is_inline(&self) -> bool {
+        *self == Self::INLINE
+    }
+}
+
 /// A Section represents a section in a CAR v1 file,
 /// which includes the length prefix, CID, and data block.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -68,11 +137,29 @@ pub struct Section {
 
 impl Section {
     /// Creates a new Section
+    ///
+    /// The length of the section is always computed from the CID and the block, so that the written
+    /// length prefix can not disagree with the content.
     pub fn new(cid: RawCid, block: Block) -> Self {
-        let length = cid.bytes().len() as u64 + block.len() as u64;
+        let length = (cid.bytes().len() as u64).saturating_add(block.len() as u64);
         Section { length, cid, block }
     }
 
+    /// Creates a new Section of the block data, computing its CIDv1 with the given codec and hash function
+    ///
+    /// See [RawCid::from_data]. Unlike [Section::new], the CID always matches the data.
+    #[cfg(feature = "hashing")]
+    #[doc(cfg(feature = "hashing"))]
+    pub fn from_data(codec: u64, hasher: Multihash, data: Vec<u8>) -> Self {
+        let cid = RawCid::from_data(codec, hasher, &data);
+        Section::new(cid, Block::new(data))
+    }
+
+    /// Consumes the Section and returns its CID and block
+    pub fn into_parts(self) -> (RawCid, Block) {
+        (self.cid, self.block)
+    }
+
     /// Returns the length of the section
     pub fn length(&self) -> u64 {
         self.length
@@ -97,6 +184,27 @@ impl Section {
     /// * Ok((Section, total_section_size)) - Successfully read the section header and return the whole size of the section
     /// * Err(SectionFormatError) - Error occurred during parsing
     pub fn try_read_header_bytes(bytes: &[u8]) -> Result<(Self, usize), SectionFormatError> {
+        Self::try_read_header_bytes_with_limits(bytes, &Limits::default())
+    }
+
+    /// Tries to read a section header (length and CID) from the given bytes, with custom size limits
+    ///
+    /// See [Section::try_read_header_bytes] for more details.
+    pub fn try_read_header_bytes_with_limits(
+        bytes: &[u8],
+        limits: &Limits,
+    ) -> Result<(Self, usize), SectionFormatError> {
+        Self::try_read_header_bytes_with(bytes, limits, CidParsing::Strict)
+    }
+
+    /// Tries to read a section header (length and CID) from the given bytes, with custom size limits and CID parsing mode
+    ///
+    /// See [Section::try_read_header_bytes] for more details.
+    pub fn try_read_header_bytes_with(
+        bytes: &[u8],
+        limits: &Limits,
+        cid_parsing: CidParsing,
+    ) -> Result<(Self, usize), SectionFormatError> {
         // Read the first 16 bytes looking for the length varint
         let (length_varint, varint_size) = match crate::wire::varint::UnsignedVarint::decode(bytes)
         {
@@ -110,27 +218,165 @@ impl Section {
             }
         };
         // Validate length
-        if length_varint as usize > MAX_SECTION_SIZE {
+        if !limits.allows_section(length_varint) {
             return Err(SectionFormatError::InvalidSize(length_varint as usize));
         }
         // Try to read the CID
         let cid_start = varint_size;
-        let (cid, cid_size) = match RawCid::try_read_bytes(&bytes[cid_start..]) {
+        let (cid, cid_size) = match RawCid::try_read_bytes_with(
+            bytes.get(cid_start..).unwrap_or_default(),
+            cid_parsing,
+        ) {
             Ok((cid, size)) => (cid, size),
             Err(CidFormatError::InsufficientData) => {
                 return Err(SectionFormatError::InsufficientData);
             }
             Err(e) => return Err(SectionFormatError::InvalidCid(e)),
         };
-        let block_size = length_varint as usize - cid_size;
-        Ok((
-            Section::new(cid, Block::new(Vec::new())),
-            varint_size + cid_size + block_size,
-        ))
+        // The CID must fit in the section
+        let block_size = (length_varint as usize)
+            .checked_sub(cid_size)
+            .ok_or(SectionFormatError::InvalidSize(length_varint as usize))?;
+        if !limits.allows_block(block_size) {
+            return Err(SectionFormatError::InvalidSize(block_size));
+        }
+        // The section length overflows once added to its varint when the limits allow it
+        let section_size = (length_varint as usize)
+            .checked_add(varint_size)
+            .ok_or(SectionFormatError::InvalidSize(length_varint as usize))?;
+        Ok((Section::new(cid, Block::new(Vec::new())), section_size))
     }
 
     /// Tries to read a Section from the given bytes
     pub fn try_read_bytes(bytes: &[u8]) -> Result<(Self, usize), SectionFormatError> {
+        Self::try_read_bytes_with_limits(bytes, &Limits::default())
+    }
+
+    /// Tries to read a Section from the given bytes, with custom size limits
+    pub fn try_read_bytes_with_limits(
+        bytes: &[u8],
+        limits: &Limits,
+    ) -> Result<(Self, usize), SectionFormatError> {
+        Self::try_read_bytes_with(bytes, limits, CidParsing::Strict)
+    }
+
+    /// Tries to read a Section from the given bytes, with custom size limits and CID parsing mode
+    ///
+    /// The block data is copied, see [SectionRef::try_read_bytes_with] to borrow it instead.
+    pub fn try_read_bytes_with(
+        bytes: &[u8],
+        limits: &Limits,
+        cid_parsing: CidParsing,
+    ) -> Result<(Self, usize), SectionFormatError> {
+        SectionRef::try_read_bytes_with(bytes, limits, cid_parsing)
+            .map(|(section, size)| (section.to_owned(), size))
+    }
+
+    /// Converts the Section into bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.total_length());
+        bytes.extend_from_slice(&crate::wire::varint::UnsignedVarint(self.length).encode());
+        bytes.extend_from_slice(self.cid.bytes());
+        bytes.extend_from_slice(self.block.data());
+        bytes
+    }
+
+    /// Write the section to the given writer
+    pub fn write_to<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        // Write length varint
+        let length_varint = crate::wire::varint::UnsignedVarint(self.length);
+        writer.write_all(&length_varint.encode())?;
+        // Write CID bytes
+        writer.write_all(self.cid.bytes())?;
+        // Write block data
+        writer.write_all(self.block.data())?;
+        Ok(())
+    }
+
+    /// Calculates the total length of the section in bytes (length varint + CID + block data)
+    pub fn total_length(&self) -> usize {
+        let length_varint = crate::wire::varint::UnsignedVarint(self.length);
+        let enc_length_varint = length_varint.encode();
+        enc_length_varint
+            .len()
+            .saturating_add(self.cid.bytes().len())
+            .saturating_add(self.block.len())
+    }
+}
+
+/// A SectionRef is a [Section] whose block data is borrowed from the bytes it was read from
+///
+/// Reading a SectionRef never copies the block data, which is the way to go for passes which only
+/// look at the CIDs (indexing, verification of the layout, ...) or which process the blocks in place.
+/// The CID itself is small, and owned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionRef<'a> {
+    /// Length of the section in bytes (excluding the length prefix)
+    length: u64,
+    /// CID of the block
+    cid: RawCid,
+    /// Borrowed data block
+    block: BlockRef<'a>,
+    /// Bytes the section was read from, length prefix included
+    raw: Option<&'a [u8]>,
+}
+
+impl<'a> SectionRef<'a> {
+    /// Creates a new SectionRef
+    ///
+    /// Like [Section::new], the length is computed from the CID and the block.
+    pub fn new(cid: RawCid, block: BlockRef<'a>) -> Self {
+        let length = (cid.bytes().len() as u64).saturating_add(block.len() as u64);
+        SectionRef {
+            length,
+            cid,
+            block,
+            raw: None,
+        }
+    }
+
+    /// Returns the length of the section
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the CID of the section
+    pub fn cid(&self) -> &RawCid {
+        &self.cid
+    }
+
+    /// Returns the borrowed data block of the section
+    pub fn block(&self) -> BlockRef<'a> {
+        self.block
+    }
+
+    /// Returns the exact bytes the section was read from (length prefix, CID and block data)
+    ///
+    /// They can be written as they are with [CarWriter::write_raw_section](super::CarWriter::write_raw_section),
+    /// keeping the length prefix byte for byte (even when not minimally encoded).
+    ///
+    /// Returns `None` if the section was not read from bytes (see [SectionRef::new]).
+    pub fn raw_bytes(&self) -> Option<&'a [u8]> {
+        self.raw
+    }
+
+    /// Copies the block data into an owned [Section]
+    pub fn to_owned(&self) -> Section {
+        Section {
+            length: self.length,
+            cid: self.cid.clone(),
+            block: self.block.to_owned(),
+        }
+    }
+
+    /// Tries to read a SectionRef from the given bytes, borrowing its block data
+    ///
+    /// See [Section::try_read_bytes_with], the parsing and the returned size are the same.
+    pub fn try_read_bytes_with(
+        bytes: &'a [u8],
+        limits: &Limits,
+        cid_parsing: CidParsing,
+    ) -> Result<(Self, usize), SectionFormatError> {
         // Read the first 16 bytes looking for the length varint
         let (length_varint, varint_size) = match crate::wire::varint::UnsignedVarint::decode(bytes)
         {
@@ -144,57 +390,147 @@ impl Section {
             }
         };
         // Validate length
-        if length_varint as usize > MAX_SECTION_SIZE {
+        if !limits.allows_section(length_varint) {
             return Err(SectionFormatError::InvalidSize(length_varint as usize));
         }
         // Try to read the CID
         let cid_start = varint_size;
-        let (cid, cid_size) = match RawCid::try_read_bytes(&bytes[cid_start..]) {
+        let (cid, cid_size) = match RawCid::try_read_bytes_with(
+            bytes.get(cid_start..).unwrap_or_default(),
+            cid_parsing,
+        ) {
             Ok((cid, size)) => (cid, size),
             Err(CidFormatError::InsufficientData) => {
                 return Err(SectionFormatError::InsufficientData);
             }
             Err(e) => return Err(SectionFormatError::InvalidCid(e)),
         };
-        // Calculate block size
-        let block_size = length_varint as usize - cid_size;
-        if bytes.len() < varint_size + cid_size + block_size {
-            return Err(SectionFormatError::InsufficientData);
+        // Calculate block size, the CID must fit in the section
+        let block_size = (length_varint as usize)
+            .checked_sub(cid_size)
+            .ok_or(SectionFormatError::InvalidSize(length_varint as usize))?;
+        if !limits.allows_block(block_size) {
+            return Err(SectionFormatError::InvalidSize(block_size));
         }
-        // Read the block data
-        let block_start = varint_size + cid_size;
-        let block_data = &bytes[block_start..block_start + block_size];
-        let block = Block::new(block_data.to_vec());
-        Ok((
-            Section::new(cid, block),
-            varint_size + cid_size + block_size,
-        ))
+        // Borrow the block data
+        let block_start = varint_size.saturating_add(cid_size);
+        let section_size = block_start
+            .checked_add(block_size)
+            .ok_or(SectionFormatError::InvalidSize(length_varint as usize))?;
+        let block_data = bytes
+            .get(block_start..section_size)
+            .ok_or(SectionFormatError::InsufficientData)?;
+        let section = SectionRef {
+            raw: bytes.get(..section_size),
+            ..SectionRef::new(cid, BlockRef::new(block_data))
+        };
+        Ok((section, section_size))
     }
+}
 
-    /// Converts the Section into bytes
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        self.write_to(&mut bytes).unwrap(); // This should never fail since we are writing to a Vec<u8>
-        bytes
+/// A [Section] whose block data is a cheaply cloned [Bytes](bytes::Bytes) slice of the received data
+///
+/// Returned by [CarReader::read_section_bytes](crate::CarReader::read_section_bytes): when the reader was fed with
+/// [Bytes](bytes::Bytes) chunks, the block shares their memory, so it can be kept (or sent further) without copy.
+#[cfg(feature = "bytes")]
+#[doc(cfg(feature = "bytes"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionBytes {
+    /// Length of the section in bytes (excluding the length prefix)
+    length: u64,
+    /// CID of the block
+    cid: RawCid,
+    /// Shared data block
+    block: bytes::Bytes,
+}
+
+#[cfg(feature = "bytes")]
+impl SectionBytes {
+    /// Creates a new SectionBytes
+    ///
+    /// Like [Section::new], the length is computed from the CID and the block.
+    pub fn new(cid: RawCid, block: bytes::Bytes) -> Self {
+        let length = (cid.bytes().len() as u64).saturating_add(block.len() as u64);
+        SectionBytes { length, cid, block }
     }
 
-    /// Write the section to the given writer
-    pub fn write_to<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        // Write length varint
-        let length_varint = crate::wire::varint::UnsignedVarint(self.length);
-        writer.write_all(&length_varint.encode())?;
-        // Write CID bytes
-        writer.write_all(self.cid.bytes())?;
-        // Write block data
-        writer.write_all(self.block.data())?;
-        Ok(())
+    /// Returns the length of the section
+    pub fn length(&self) -> u64 {
+        self.length
     }
 
-    /// Calculates the total length of the section in bytes (length varint + CID + block data)
-    pub fn total_length(&self) -> usize {
-        let length_varint = crate::wire::varint::UnsignedVarint(self.length);
-        let enc_length_varint = length_varint.encode();
-        enc_length_varint.len() + self.cid.bytes().len() + self.block.len()
+    /// Returns the CID of the section
+    pub fn cid(&self) -> &RawCid {
+        &self.cid
+    }
+
+    /// Returns the shared data block of the section
+    pub fn block(&self) -> &bytes::Bytes {
+        &self.block
+    }
+
+    /// Splits the section into its CID and its shared data block
+    pub fn into_parts(self) -> (RawCid, bytes::Bytes) {
+        (self.cid, self.block)
+    }
+
+    /// Borrows the section as a [SectionRef]
+    pub fn as_section_ref(&self) -> SectionRef<'_> {
+        SectionRef::new(self.cid.clone(), BlockRef::new(&self.block))
+    }
+
+    /// Copies the block data into an owned [Section]
+    pub fn to_owned(&self) -> Section {
+        Section {
+            length: self.length,
+            cid: self.cid.clone(),
+            block: Block(self.block.to_vec()),
+        }
+    }
+}
+
+impl From<(RawCid, Block)> for Section {
+    fn from((cid, block): (RawCid, Block)) -> Self {
+        Section::new(cid, block)
+    }
+}
+
+/// Creates a Section from a CID and the block data
+///
+/// ## Examples
+/// ```
+/// use navira_car::wire::{cid::RawCid, v1::Section};
+/// let cid = RawCid::from_hex("0155000568656c6c6f").unwrap();
+/// let section = Section::from((cid.clone(), b"hello".to_vec()));
+/// assert_eq!(section.length(), 9 + 5);
+/// let (read, _) = Section::try_read_bytes(&section.to_bytes()).unwrap();
+/// assert_eq!(read.into_parts(), (cid, b"hello".to_vec().into()));
+/// ```
+impl From<(RawCid, Vec<u8>)> for Section {
+    fn from((cid, data): (RawCid, Vec<u8>)) -> Self {
+        Section::new(cid, Block::new(data))
+    }
+}
+
+impl From<Section> for (RawCid, Vec<u8>) {
+    fn from(section: Section) -> Self {
+        (section.cid, section.block.0)
+    }
+}
+
+/// Parses an encoded section (length prefix, CID and block data), with the default limits
+///
+/// Unlike [Section::try_read_bytes], the bytes must hold exactly one section:
+/// trailing bytes are rejected with [SectionFormatError::InvalidSize].
+impl TryFrom<&[u8]> for Section {
+    type Error = SectionFormatError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let (section, size) = Section::try_read_bytes(bytes)?;
+        if size != bytes.len() {
+            return Err(SectionFormatError::InvalidSize(bytes.len()));
+        }
+        Ok(section)
     }
 }
 
@@ -0,0 +1,195 @@
+//! Write-time suppression of duplicate sections
+//!
+//! DAG builders naturally revisit shared subtrees, and would write their blocks once per visit. With duplicate
+//! suppression enabled, the writer remembers the sections already written (by CID, or by multihash to also catch
+//! the same block under another CID version or codec), and silently skips the repeated writes: the location of the
+//! section written first is returned instead.
+//!
+//! The written sections are remembered in a [DedupSet], an exact map by default. Custom sets can bound the memory
+//! used, as long as they never report a section which was not written (see [DedupSet]).
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::wire::cid::RawCid;
+use crate::wire::v1::SectionLocation;
+
+/// Duplicate suppression mode of a writer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteDedup {
+    /// All the sections are written, duplicates included
+    #[default]
+    Off,
+    /// Only the first section of each CID is written
+    Cid,
+    /// Only the first section of each multihash is written: the same block under CIDs of different versions or
+    /// codecs (e.g. a CIDv0 and its CIDv1) is written once, under its first CID
+    Multihash,
+}
+
+impl WriteDedup {
+    /// Key of a section in the [DedupSet], if duplicates are suppressed
+    pub(crate) fn key(self, cid: &RawCid) -> Option<Vec<u8>> {
+        match self {
+            WriteDedup::Off => None,
+            WriteDedup::Cid => Some(cid.bytes().to_vec()),
+            WriteDedup::Multihash => Some(multihash_key(cid)),
+        }
+    }
+}
+
+/// The multihash of a CID (code and digest), or the whole CID if it cannot be parsed
+fn multihash_key(cid: &RawCid) -> Vec<u8> {
+    match (cid.multihash_code(), cid.digest()) {
+        (Some(code), Some(digest)) => {
+            let mut key = code.to_be_bytes().to_vec();
+            key.extend_from_slice(digest);
+            key
+        }
+        _ => cid.bytes().to_vec(),
+    }
+}
+
+/// Tracker of the written sections, to suppress the duplicates
+#[derive(Debug, Clone)]
+pub(crate) struct DedupTracker {
+    /// Duplicate suppression mode
+    pub(crate) mode: WriteDedup,
+    /// Locations of the written sections
+    pub(crate) seen: Box<dyn DedupSet>,
+    /// Number of writes suppressed as duplicates
+    pub(crate) skipped: u64,
+}
+
+impl DedupTracker {
+    /// Create a tracker remembering the written sections in the given set
+    pub(crate) fn new(mode: WriteDedup, seen: Box<dyn DedupSet>) -> Self {
+        Self {
+            mode,
+            seen,
+            skipped: 0,
+        }
+    }
+
+    /// Location of the section already written with this CID, counting the suppressed write
+    pub(crate) fn find(&mut self, cid: &RawCid) -> Option<SectionLocation> {
+        let location = self.seen.get(&self.mode.key(cid)?)?;
+        self.skipped = self.skipped.saturating_add(1);
+        Some(location)
+    }
+
+    /// Remember a written section, unless a section with the same key is already remembered
+    pub(crate) fn record(&mut self, cid: &RawCid, location: &SectionLocation) {
+        if let Some(key) = self.mode.key(cid)
+            && self.seen.get(&key).is_none()
+        {
+            self.seen.insert(key, location.clone());
+        }
+    }
+}
+
+/// A set of the written sections, with their location
+///
+/// The keys are opaque bytes, derived from the CIDs according to the [WriteDedup] mode. The locations are the ones
+/// of the writer remembering them (relative to the CAR v1 payload for a CAR v2 writer).
+///
+/// Implementations may forget sections to bound their memory, a forgotten section being merely written again. They
+/// must however never return a location for a key which was not inserted: a probabilistic filter (e.g. a bloom
+/// filter) can only be used in front of an exact map, to skip the lookups of the keys never seen.
+///
+/// It is implemented for:
+/// - `HashMap<Vec<u8>, SectionLocation>`, remembering all the sections (the default).
+/// - [BoundedDedupSet], remembering only the most recent sections.
+pub trait DedupSet: std::fmt::Debug + Send + Sync {
+    /// Location of the section written with this key, if remembered
+    fn get(&self, key: &[u8]) -> Option<SectionLocation>;
+
+    /// Remember a written section
+    fn insert(&mut self, key: Vec<u8>, location: SectionLocation);
+
+    /// Clone the set, for the writers to be cloneable
+    fn clone_box(&self) -> Box<dyn DedupSet>;
+}
+
+impl Clone for Box<dyn DedupSet> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl DedupSet for HashMap<Vec<u8>, SectionLocation> {
+    fn get(&self, key: &[u8]) -> Option<SectionLocation> {
+        HashMap::get(self, key).cloned()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, location: SectionLocation) {
+        HashMap::insert(self, key, location);
+    }
+
+    fn clone_box(&self) -> Box<dyn DedupSet> {
+        Box::new(self.clone())
+    }
+}
+
+/// A [DedupSet] remembering at most a given number of sections, forgetting the oldest ones first
+///
+/// Shared subtrees are usually revisited shortly after their first visit, so the most recent sections catch most of
+/// the duplicates, within a fixed memory budget.
+#[derive(Debug, Clone)]
+pub struct BoundedDedupSet {
+    /// Maximum number of remembered sections
+    capacity: usize,
+    /// Remembered sections
+    locations: HashMap<Vec<u8>, SectionLocation>,
+    /// Keys of the remembered sections, from the oldest
+    order: VecDeque<Vec<u8>>,
+}
+
+impl BoundedDedupSet {
+    /// Create a set remembering at most `capacity` sections (at least one)
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            locations: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Maximum number of remembered sections
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of remembered sections
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Is no section remembered?
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+}
+
+impl DedupSet for BoundedDedupSet {
+    fn get(&self, key: &[u8]) -> Option<SectionLocation> {
+        self.locations.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, location: SectionLocation) {
+        if self.locations.contains_key(&key) {
+            return;
+        }
+        if self.locations.len() >= self.capacity
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.locations.remove(&oldest);
+        }
+        self.order.push_back(key.clone());
+        self.locations.insert(key, location);
+    }
+
+    fn clone_box(&self) -> Box<dyn DedupSet> {
+        Box::new(self.clone())
+    }
+}
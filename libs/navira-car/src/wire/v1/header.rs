@@ -1,22 +1,35 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
 use crate::wire::cid::{IntoRawLink, RawCid, RawLink};
-use serde::{Deserialize, Serialize};
+use ciborium::Value;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::{self, SerializeMap};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// CAR v1 Header structure
 ///
 /// # Fields
 /// - `version`: The version of the CAR format (should be 1 for CAR v1)
 /// - `roots`: A vector of root CIDs in raw byte format
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// - `extra`: The other entries of the header map (e.g. vendor extensions), kept so that rewriting a header does not
+///   drop them. Their values are stored CBOR-encoded (as re-encoded by ciborium: floats may be narrowed).
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CarHeader {
     version: u64,
     roots: Vec<RawLink>,
+    extra: BTreeMap<String, Vec<u8>>,
 }
 
 impl CarHeader {
     /// Creates a new CAR v1 header with the specified root CIDs
     pub fn new(roots: Vec<RawCid>) -> Self {
         let roots = roots.into_iter().map(IntoRawLink::into_link).collect();
-        CarHeader { roots, version: 1 }
+        CarHeader {
+            roots,
+            version: 1,
+            extra: BTreeMap::new(),
+        }
     }
 
     /// Returns the version of the CAR format
@@ -38,6 +51,216 @@ impl CarHeader {
     pub fn is_empty(&self) -> bool {
         self.roots.is_empty()
     }
+
+    /// Returns the extra entries of the header (all but `version` and `roots`), with their CBOR-encoded values
+    pub fn extra(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.extra
+    }
+
+    /// Returns the decoded value of an extra entry of the header
+    pub fn extra_value(&self, key: &str) -> Option<Value> {
+        let bytes = self.extra.get(key)?;
+        ciborium::from_reader(bytes.as_slice()).ok()
+    }
+
+    /// Sets an extra entry of the header
+    ///
+    /// Returns `false`, leaving the header untouched, if the key is `version` or `roots`.
+    pub fn set_extra(&mut self, key: impl Into<String>, value: &Value) -> bool {
+        let key = key.into();
+        let mut bytes = Vec::new();
+        if is_reserved(&key) || ciborium::into_writer(value, &mut bytes).is_err() {
+            return false;
+        }
+        self.extra.insert(key, bytes);
+        true
+    }
+
+    /// Removes an extra entry of the header, returning its CBOR-encoded value
+    pub fn remove_extra(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.extra.remove(key)
+    }
+
+    /// Encodes the header in canonical DAG-CBOR (without its length prefix)
+    ///
+    /// The map keys are sorted (`roots` before `version`) and all the lengths are definite and minimally encoded,
+    /// so the header is byte-identical to the one written by go-car for the same roots.
+    pub fn encode(&self) -> Vec<u8> {
+        let roots = self.roots.iter().map(RawLink::to_raw_cid);
+        encode_header(roots, self.version, &self.extra, [0; 3])
+    }
+}
+
+/// Is the key one of the entries modelled by [CarHeader]?
+fn is_reserved(key: &str) -> bool {
+    key == "version" || key == "roots"
+}
+
+/// DAG-CBOR map key order: shortest first, then bytewise
+fn canonical_order<'a>(keys: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut keys: Vec<_> = keys.collect();
+    keys.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+    keys
+}
+
+impl Serialize for CarHeader {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let keys = canonical_order(
+            ["roots", "version"]
+                .into_iter()
+                .chain(self.extra.keys().map(String::as_str)),
+        );
+        let mut map = serializer.serialize_map(Some(keys.len()))?;
+        for key in keys {
+            match key {
+                "roots" => map.serialize_entry(key, &self.roots)?,
+                "version" => map.serialize_entry(key, &self.version)?,
+                _ => {
+                    let value = self
+                        .extra_value(key)
+                        .ok_or_else(|| ser::Error::custom("invalid extra value"))?;
+                    map.serialize_entry(key, &value)?
+                }
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CarHeader {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(CarHeaderVisitor)
+    }
+}
+
+/// Visitor of the header map, keeping the unknown entries
+struct CarHeaderVisitor;
+
+impl<'de> Visitor<'de> for CarHeaderVisitor {
+    type Value = CarHeader;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a CAR v1 header map")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut version = None;
+        let mut roots = None;
+        let mut extra = BTreeMap::new();
+        while let Some(key) = map.next_key::<Value>()? {
+            match key {
+                Value::Text(key) if key == "version" => {
+                    if version.replace(map.next_value()?).is_some() {
+                        return Err(de::Error::duplicate_field("version"));
+                    }
+                }
+                Value::Text(key) if key == "roots" => {
+                    if roots.replace(map.next_value()?).is_some() {
+                        return Err(de::Error::duplicate_field("roots"));
+                    }
+                }
+                Value::Text(key) => {
+                    let value: Value = map.next_value()?;
+                    let mut bytes = Vec::new();
+                    ciborium::into_writer(&value, &mut bytes).map_err(de::Error::custom)?;
+                    if extra.insert(key, bytes).is_some() {
+                        return Err(de::Error::custom("duplicate header entry"));
+                    }
+                }
+                // Not representable in DAG-CBOR, ignored as before
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CarHeader {
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            roots: roots.ok_or_else(|| de::Error::missing_field("roots"))?,
+            extra,
+        })
+    }
+}
+
+/// Encode a CBOR head, on at least `min_width` bytes after the initial byte
+fn encode_head(buf: &mut Vec<u8>, major: u8, value: u64, min_width: usize) {
+    let width = match value {
+        0..24 if min_width == 0 => 0,
+        0..=0xff if min_width <= 1 => 1,
+        0..=0xffff if min_width <= 2 => 2,
+        0..=0xffff_ffff if min_width <= 4 => 4,
+        _ => 8,
+    };
+    match width {
+        0 => buf.push(major << 5 | value as u8),
+        1 => buf.extend([major << 5 | 24, value as u8]),
+        2 => {
+            buf.push(major << 5 | 25);
+            buf.extend((value as u16).to_be_bytes());
+        }
+        4 => {
+            buf.push(major << 5 | 26);
+            buf.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(major << 5 | 27);
+            buf.extend(value.to_be_bytes());
+        }
+    }
+}
+
+/// Encode a CARv1 header, in DAG-CBOR key order, with the given minimal widths of the (map, array, version) heads
+///
+/// The extra entries are written with their CBOR-encoded values as they are.
+/// With minimal widths (`[0; 3]`), this is the canonical encoding, see [CarHeader::encode].
+pub(crate) fn encode_header<'a>(
+    mut roots: impl ExactSizeIterator<Item = &'a RawCid>,
+    version: u64,
+    extra: &BTreeMap<String, Vec<u8>>,
+    [map, array, version_width]: [usize; 3],
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let keys = canonical_order(
+        ["roots", "version"].into_iter().chain(
+            extra
+                .keys()
+                .map(String::as_str)
+                .filter(|key| !is_reserved(key)),
+        ),
+    );
+    encode_head(&mut buf, 5, keys.len() as u64, map);
+    for key in keys {
+        encode_head(&mut buf, 3, key.len() as u64, 0);
+        buf.extend_from_slice(key.as_bytes());
+        match key {
+            "roots" => {
+                encode_head(&mut buf, 4, roots.len() as u64, array);
+                for root in roots.by_ref() {
+                    // Tag 42, then the CID bytes prefixed by the multibase identity prefix
+                    encode_head(&mut buf, 6, 42, 0);
+                    encode_head(
+                        &mut buf,
+                        2,
+                        (root.bytes().len() as u64).saturating_add(1),
+                        0,
+                    );
+                    buf.push(0x00);
+                    buf.extend_from_slice(root.bytes());
+                }
+            }
+            "version" => encode_head(&mut buf, 0, version, version_width),
+            _ => buf.extend_from_slice(extra.get(key).map(Vec::as_slice).unwrap_or_default()),
+        }
+    }
+    buf
 }
 
 #[cfg(test)]
@@ -88,5 +311,77 @@ mod tests {
         // Ordering of map keys in CBOR may vary, so we check for content rather than exact byte-for-byte match
         let deserialized_header: CarHeader = ciborium::de::from_reader(buf.as_slice()).unwrap();
         assert_eq!(deserialized_header, header);
+
+        // The canonical encoding is byte-identical to go-car's
+        assert_eq!(header.encode(), CAR_V1_HEADER1);
+    }
+
+    #[test]
+    fn test_encode_header_widths() {
+        let root = RawCid::from_hex(
+            "01711220f88bc853804cf294fe417e4fa83028689fcdb1b1592c5102e1474dbc200fab8b",
+        )
+        .unwrap();
+        // Wider heads are still valid CBOR, decoded to the same header
+        for widths in [[0; 3], [8, 2, 4]] {
+            let bytes = encode_header([&root].into_iter(), 1, &BTreeMap::new(), widths);
+            let header: CarHeader = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+            assert_eq!(header, CarHeader::new(vec![root.clone()]));
+        }
+        let header: CarHeader = ciborium::de::from_reader(
+            encode_header([].into_iter(), 2, &BTreeMap::new(), [0; 3]).as_slice(),
+        )
+        .unwrap();
+        assert_eq!(header.version(), 2);
+        assert!(header.is_empty());
+    }
+
+    #[test]
+    fn test_car_v1_header_extra_entries() {
+        // {"roots": [], "version": 1, "x-vendor": {"a": 1}, "details": "go"}, in a non-canonical order
+        let mut bytes = vec![0xA4, 0x68];
+        bytes.extend_from_slice(b"x-vendor");
+        bytes.extend_from_slice(&[0xA1, 0x61, b'a', 0x01, 0x67]);
+        bytes.extend_from_slice(b"version");
+        bytes.extend_from_slice(&[0x01, 0x65]);
+        bytes.extend_from_slice(b"roots");
+        bytes.extend_from_slice(&[0x80, 0x67]);
+        bytes.extend_from_slice(b"details");
+        bytes.extend_from_slice(&[0x62, b'g', b'o']);
+        let mut header: CarHeader = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(header.extra().len(), 2);
+        assert_eq!(
+            header.extra_value("details"),
+            Some(Value::Text("go".to_owned()))
+        );
+
+        // Re-encoded in canonical order, the extra entries included
+        let encoded = header.encode();
+        assert_eq!(encoded.len(), bytes.len());
+        assert_eq!(&encoded[..7], [0xA4, 0x65, b'r', b'o', b'o', b't', b's']);
+        let mut serialized = Vec::new();
+        ciborium::ser::into_writer(&header, &mut serialized).unwrap();
+        assert_eq!(serialized, encoded);
+        let decoded: CarHeader = ciborium::de::from_reader(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, header);
+
+        // The modelled entries cannot be overridden
+        assert!(!header.set_extra("version", &Value::Integer(2.into())));
+        assert!(header.set_extra("details", &Value::Bool(true)));
+        assert_eq!(header.extra_value("details"), Some(Value::Bool(true)));
+        assert!(header.remove_extra("x-vendor").is_some());
+        let decoded: CarHeader = ciborium::de::from_reader(header.encode().as_slice()).unwrap();
+        assert_eq!(decoded.extra_value("details"), Some(Value::Bool(true)));
+        assert!(decoded.extra_value("x-vendor").is_none());
+
+        // Duplicate and missing entries are rejected
+        let mut duplicate = vec![0xA3];
+        for entry in [&b"\x65roots\x80"[..], b"\x65roots\x80", b"\x67version\x01"] {
+            duplicate.extend_from_slice(entry);
+        }
+        assert!(ciborium::de::from_reader::<CarHeader, _>(&duplicate[..]).is_err());
+        duplicate[0] = 0xA1;
+        let missing = &duplicate[..8];
+        assert!(ciborium::de::from_reader::<CarHeader, _>(missing).is_err());
     }
 }
@@ -0,0 +1,105 @@
+//! Write-time validation of the links between sections
+//!
+//! A CAR file usually holds a complete DAG, but nothing in the format enforces it: a packing bug (a forgotten
+//! chunk, a wrong CID) only shows up when a consumer walks the DAG and misses a block. With link validation
+//! enabled, the writer decodes the links of every dag-pb and dag-cbor block it writes, and keeps track of the
+//! links whose target has not been written. Links may point forward (parents are commonly written before their
+//! children), so only the links still unresolved once every section is written are reported.
+//!
+//! Identity CIDs embed their content, so links to them are never reported.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ipld::block_links;
+use crate::wire::cid::RawCid;
+
+/// Link validation mode of a writer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkValidation {
+    /// Links are not tracked
+    #[default]
+    Off,
+    /// Dangling links are tracked and reported, but never rejected
+    Warn,
+    /// Dangling links are tracked, and rejected when checking the links before finalizing
+    Strict,
+}
+
+/// A link to a block which has not been written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingLink {
+    /// CID of the missing block
+    pub target: RawCid,
+    /// CID of the (first written) block linking to it
+    pub referrer: RawCid,
+}
+
+/// Summary of dangling links for error messages: their count, and the first one
+pub(crate) fn describe_dangling_links(links: &[DanglingLink]) -> String {
+    match links.first() {
+        Some(first) => format!(
+            "{} dangling links, first to {} from {}",
+            links.len(),
+            first.target.to_hex(),
+            first.referrer.to_hex()
+        ),
+        None => "No dangling links".to_owned(),
+    }
+}
+
+/// Tracker of the written CIDs and of the links not resolved yet
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LinkTracker {
+    /// Link validation mode
+    pub(crate) mode: LinkValidation,
+    /// CIDs of the written sections
+    written: HashSet<RawCid>,
+    /// Targets of the unresolved links, with their first referrer
+    pending: HashMap<RawCid, RawCid>,
+    /// Targets of the links not written at the time of their first reference, in reference order
+    order: Vec<RawCid>,
+}
+
+impl LinkTracker {
+    /// Create a tracker in the given mode
+    pub(crate) fn new(mode: LinkValidation) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// Record a written section, resolving the links to it and tracking its own links
+    pub(crate) fn record(&mut self, cid: &RawCid, data: &[u8]) {
+        self.record_cid(cid);
+        for link in block_links(cid, data) {
+            if link.is_identity()
+                || self.written.contains(&link)
+                || self.pending.contains_key(&link)
+            {
+                continue;
+            }
+            self.order.push(link.clone());
+            self.pending.insert(link, cid.clone());
+        }
+    }
+
+    /// Record a written section whose block is not available, only resolving the links to it
+    pub(crate) fn record_cid(&mut self, cid: &RawCid) {
+        self.pending.remove(cid);
+        self.written.insert(cid.clone());
+    }
+
+    /// Links still unresolved, in the order of their first reference
+    pub(crate) fn dangling_links(&self) -> Vec<DanglingLink> {
+        self.order
+            .iter()
+            .filter_map(|target| {
+                self.pending.get(target).map(|referrer| DanglingLink {
+                    target: target.clone(),
+                    referrer: referrer.clone(),
+                })
+            })
+            .collect()
+    }
+}
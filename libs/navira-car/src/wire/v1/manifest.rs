@@ -0,0 +1,45 @@
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{CarHeader, SectionLocation};
+use crate::wire::v2::Index;
+
+/// Layout of a CAR file, as recorded by the writer which wrote it
+///
+/// Returned by `finish_with_manifest` on the v1 and v2 writers (once the manifest recording is enabled),
+/// so that the written file can be indexed without being scanned again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteManifest {
+    /// CAR v1 header (the roots) of the file
+    pub header: CarHeader,
+    /// Written sections, in file order, with their absolute location in the file
+    ///
+    /// Padding sections inserted by the placement constraints are included.
+    pub sections: Vec<(RawCid, SectionLocation)>,
+    /// Offset of the CAR v1 payload in the file (0 for a CAR v1 file)
+    pub data_offset: u64,
+    /// Absolute location of the CAR v2 index, if one was written
+    pub index_location: Option<SectionLocation>,
+}
+
+impl WriteManifest {
+    /// Create an empty manifest, for a file with the given header
+    pub(crate) fn new(header: CarHeader) -> Self {
+        Self {
+            header,
+            sections: Vec::new(),
+            data_offset: 0,
+            index_location: None,
+        }
+    }
+
+    /// Build a CAR v2 index of the written sections
+    ///
+    /// The offsets of the index are relative to the CAR v1 payload, as expected by
+    /// [v2::CarWriter::write_index](crate::wire::v2::CarWriter::write_index).
+    pub fn to_index(&self) -> Index {
+        Index::multihash_sorted(
+            self.sections
+                .iter()
+                .map(|(cid, location)| (cid, location.offset.saturating_sub(self.data_offset))),
+        )
+    }
+}
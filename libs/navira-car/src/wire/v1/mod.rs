@@ -7,13 +7,29 @@
 //!
 //! However, if you only need to work with CAR v1 headers or sections, you can use the types in this module directly.
 
-pub use data::{Block, LocatableSection, Section, SectionFormatError, SectionLocation};
+#[cfg(feature = "bytes")]
+pub use data::SectionBytes;
+pub use data::{
+    Block, BlockRef, LocatableSection, Section, SectionFormatError, SectionLocation, SectionRef,
+};
+pub use dedup::{BoundedDedupSet, DedupSet, WriteDedup};
 pub use header::CarHeader;
+#[cfg(any(feature = "std-io", doc))]
+pub(crate) use header::encode_header;
+pub(crate) use links::describe_dangling_links;
+pub use links::{DanglingLink, LinkValidation};
+pub use manifest::WriteManifest;
+pub(crate) use placement::resolve_placement;
+pub use placement::{MAX_FILLER_DIGEST, PlacedSection, Placement};
 pub use read::{CarReader, CarReaderError};
-pub use write::{CarWriter, CarWriterError};
+pub use write::{CarWriter, CarWriterError, MIN_BUFFER_SIZE};
 
 mod data;
+mod dedup;
 mod header;
+mod links;
+mod manifest;
+mod placement;
 mod read;
 mod write;
 
@@ -22,60 +38,61 @@ mod tests {
     use super::{CarReader, CarReaderError};
     use crate::wire::{
         cid::{IntoRawLink as _, RawCid},
-        v1::{Block, CarWriter, CarWriterError, Section},
+        limits::Limits,
+        v1::{Block, CarWriter, CarWriterError, Section, SectionFormatError, SectionLocation},
+        varint::UnsignedVarint,
     };
 
-    const CAR_V1: [u8; 715] = [
-        // Offset 0x00000000 to 0x000002CA
-        0x63, 0xA2, 0x65, 0x72, 0x6F, 0x6F, 0x74, 0x73, 0x82, 0xD8, 0x2A, 0x58, 0x25, 0x00, 0x01,
-        0x71, 0x12, 0x20, 0xF8, 0x8B, 0xC8, 0x53, 0x80, 0x4C, 0xF2, 0x94, 0xFE, 0x41, 0x7E, 0x4F,
-        0xA8, 0x30, 0x28, 0x68, 0x9F, 0xCD, 0xB1, 0xB1, 0x59, 0x2C, 0x51, 0x02, 0xE1, 0x47, 0x4D,
-        0xBC, 0x20, 0x0F, 0xAB, 0x8B, 0xD8, 0x2A, 0x58, 0x25, 0x00, 0x01, 0x71, 0x12, 0x20, 0x69,
-        0xEA, 0x07, 0x40, 0xF9, 0x80, 0x7A, 0x28, 0xF4, 0xD9, 0x32, 0xC6, 0x2E, 0x7C, 0x1C, 0x83,
-        0xBE, 0x05, 0x5E, 0x55, 0x07, 0x2C, 0x90, 0x26, 0x6A, 0xB3, 0xE7, 0x9D, 0xF6, 0x3A, 0x36,
-        0x5B, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6F, 0x6E, 0x01, 0x5B, 0x01, 0x71, 0x12, 0x20,
-        0xF8, 0x8B, 0xC8, 0x53, 0x80, 0x4C, 0xF2, 0x94, 0xFE, 0x41, 0x7E, 0x4F, 0xA8, 0x30, 0x28,
-        0x68, 0x9F, 0xCD, 0xB1, 0xB1, 0x59, 0x2C, 0x51, 0x02, 0xE1, 0x47, 0x4D, 0xBC, 0x20, 0x0F,
-        0xAB, 0x8B, 0xA2, 0x64, 0x6C, 0x69, 0x6E, 0x6B, 0xD8, 0x2A, 0x58, 0x23, 0x00, 0x12, 0x20,
-        0x02, 0xAC, 0xEC, 0xC5, 0xDE, 0x24, 0x38, 0xEA, 0x41, 0x26, 0xA3, 0x01, 0x0E, 0xCB, 0x1F,
-        0x8A, 0x59, 0x9C, 0x8E, 0xFF, 0x22, 0xFF, 0xF1, 0xA1, 0xDC, 0xFF, 0xE9, 0x99, 0xB2, 0x7F,
-        0xD3, 0xDE, 0x64, 0x6E, 0x61, 0x6D, 0x65, 0x64, 0x62, 0x6C, 0x69, 0x70, 0x83, 0x01, 0x12,
-        0x20, 0x02, 0xAC, 0xEC, 0xC5, 0xDE, 0x24, 0x38, 0xEA, 0x41, 0x26, 0xA3, 0x01, 0x0E, 0xCB,
-        0x1F, 0x8A, 0x59, 0x9C, 0x8E, 0xFF, 0x22, 0xFF, 0xF1, 0xA1, 0xDC, 0xFF, 0xE9, 0x99, 0xB2,
-        0x7F, 0xD3, 0xDE, 0x12, 0x2E, 0x0A, 0x24, 0x01, 0x55, 0x12, 0x20, 0xB6, 0xFB, 0xD6, 0x75,
-        0xF9, 0x8E, 0x2A, 0xBD, 0x22, 0xD4, 0xED, 0x29, 0xFD, 0xC8, 0x31, 0x50, 0xFE, 0xDC, 0x48,
-        0x59, 0x7E, 0x92, 0xDD, 0x1A, 0x7A, 0x24, 0x38, 0x1D, 0x44, 0xA2, 0x74, 0x51, 0x12, 0x04,
-        0x62, 0x65, 0x61, 0x72, 0x18, 0x04, 0x12, 0x2F, 0x0A, 0x22, 0x12, 0x20, 0x79, 0xA9, 0x82,
-        0xDE, 0x3C, 0x99, 0x07, 0x95, 0x3D, 0x4D, 0x32, 0x3C, 0xEE, 0x1D, 0x0F, 0xB1, 0xED, 0x8F,
-        0x45, 0xF8, 0xEF, 0x02, 0x87, 0x0C, 0x0C, 0xB9, 0xE0, 0x92, 0x46, 0xBD, 0x53, 0x0A, 0x12,
-        0x06, 0x73, 0x65, 0x63, 0x6F, 0x6E, 0x64, 0x18, 0x95, 0x01, 0x28, 0x01, 0x55, 0x12, 0x20,
-        0xB6, 0xFB, 0xD6, 0x75, 0xF9, 0x8E, 0x2A, 0xBD, 0x22, 0xD4, 0xED, 0x29, 0xFD, 0xC8, 0x31,
-        0x50, 0xFE, 0xDC, 0x48, 0x59, 0x7E, 0x92, 0xDD, 0x1A, 0x7A, 0x24, 0x38, 0x1D, 0x44, 0xA2,
-        0x74, 0x51, 0x63, 0x63, 0x63, 0x63, 0x80, 0x01, 0x12, 0x20, 0x79, 0xA9, 0x82, 0xDE, 0x3C,
-        0x99, 0x07, 0x95, 0x3D, 0x4D, 0x32, 0x3C, 0xEE, 0x1D, 0x0F, 0xB1, 0xED, 0x8F, 0x45, 0xF8,
-        0xEF, 0x02, 0x87, 0x0C, 0x0C, 0xB9, 0xE0, 0x92, 0x46, 0xBD, 0x53, 0x0A, 0x12, 0x2D, 0x0A,
-        0x24, 0x01, 0x55, 0x12, 0x20, 0x81, 0xCC, 0x5B, 0x17, 0x01, 0x86, 0x74, 0xB4, 0x01, 0xB4,
-        0x2F, 0x35, 0xBA, 0x07, 0xBB, 0x79, 0xE2, 0x11, 0x23, 0x9C, 0x23, 0xBF, 0xFE, 0x65, 0x8D,
-        0xA1, 0x57, 0x7E, 0x3E, 0x64, 0x68, 0x77, 0x12, 0x03, 0x64, 0x6F, 0x67, 0x18, 0x04, 0x12,
-        0x2D, 0x0A, 0x22, 0x12, 0x20, 0xE7, 0xDC, 0x48, 0x6E, 0x97, 0xE6, 0xEB, 0xE5, 0xCD, 0xAB,
-        0xAB, 0x3E, 0x39, 0x2B, 0xDA, 0xD1, 0x28, 0xB6, 0xE0, 0x9A, 0xCC, 0x94, 0xBB, 0x4E, 0x2A,
-        0xA2, 0xAF, 0x7B, 0x98, 0x6D, 0x24, 0xD0, 0x12, 0x05, 0x66, 0x69, 0x72, 0x73, 0x74, 0x18,
-        0x33, 0x28, 0x01, 0x55, 0x12, 0x20, 0x81, 0xCC, 0x5B, 0x17, 0x01, 0x86, 0x74, 0xB4, 0x01,
-        0xB4, 0x2F, 0x35, 0xBA, 0x07, 0xBB, 0x79, 0xE2, 0x11, 0x23, 0x9C, 0x23, 0xBF, 0xFE, 0x65,
-        0x8D, 0xA1, 0x57, 0x7E, 0x3E, 0x64, 0x68, 0x77, 0x62, 0x62, 0x62, 0x62, 0x51, 0x12, 0x20,
-        0xE7, 0xDC, 0x48, 0x6E, 0x97, 0xE6, 0xEB, 0xE5, 0xCD, 0xAB, 0xAB, 0x3E, 0x39, 0x2B, 0xDA,
-        0xD1, 0x28, 0xB6, 0xE0, 0x9A, 0xCC, 0x94, 0xBB, 0x4E, 0x2A, 0xA2, 0xAF, 0x7B, 0x98, 0x6D,
-        0x24, 0xD0, 0x12, 0x2D, 0x0A, 0x24, 0x01, 0x55, 0x12, 0x20, 0x61, 0xBE, 0x55, 0xA8, 0xE2,
-        0xF6, 0xB4, 0xE1, 0x72, 0x33, 0x8B, 0xDD, 0xF1, 0x84, 0xD6, 0xDB, 0xEE, 0x29, 0xC9, 0x88,
-        0x53, 0xE0, 0xA0, 0x48, 0x5E, 0xCE, 0xE7, 0xF2, 0x7B, 0x9A, 0xF0, 0xB4, 0x12, 0x03, 0x63,
-        0x61, 0x74, 0x18, 0x04, 0x28, 0x01, 0x55, 0x12, 0x20, 0x61, 0xBE, 0x55, 0xA8, 0xE2, 0xF6,
-        0xB4, 0xE1, 0x72, 0x33, 0x8B, 0xDD, 0xF1, 0x84, 0xD6, 0xDB, 0xEE, 0x29, 0xC9, 0x88, 0x53,
-        0xE0, 0xA0, 0x48, 0x5E, 0xCE, 0xE7, 0xF2, 0x7B, 0x9A, 0xF0, 0xB4, 0x61, 0x61, 0x61, 0x61,
-        0x36, 0x01, 0x71, 0x12, 0x20, 0x69, 0xEA, 0x07, 0x40, 0xF9, 0x80, 0x7A, 0x28, 0xF4, 0xD9,
-        0x32, 0xC6, 0x2E, 0x7C, 0x1C, 0x83, 0xBE, 0x05, 0x5E, 0x55, 0x07, 0x2C, 0x90, 0x26, 0x6A,
-        0xB3, 0xE7, 0x9D, 0xF6, 0x3A, 0x36, 0x5B, 0xA2, 0x64, 0x6C, 0x69, 0x6E, 0x6B, 0xF6, 0x64,
-        0x6E, 0x61, 0x6D, 0x65, 0x65, 0x6C, 0x69, 0x6D, 0x62, 0x6F,
-    ];
+    use crate::testdata::CARV1_BASIC as CAR_V1;
+
+    #[test]
+    fn test_car_v1_malformed_lengths() {
+        // A header length which overflows once added to its varint (when allowed by the limits)
+        let mut reader = CarReader::new();
+        reader.set_limits(Limits::new().with_max_header_size(usize::MAX));
+        reader.receive_data(&UnsignedVarint(u64::MAX).encode(), 0);
+        assert!(matches!(
+            reader.read_header(),
+            Err(CarReaderError::InvalidFormat)
+        ));
+
+        // A section length shorter than its CID
+        let cid = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let mut bytes = UnsignedVarint(3).encode();
+        bytes.extend_from_slice(cid.bytes());
+        assert!(matches!(
+            Section::try_read_bytes(&bytes),
+            Err(SectionFormatError::InvalidSize(3))
+        ));
+        assert!(matches!(
+            Section::try_read_header_bytes(&bytes),
+            Err(SectionFormatError::InvalidSize(3))
+        ));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_car_v1_receive_bytes_after_oversized_section() {
+        // A section length overflowing once added to its varint, rejected by the limits
+        let header_len = usize::from(CAR_V1[0]) + 1;
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..header_len], 0);
+        reader.read_header().unwrap();
+        let length = UnsignedVarint(u64::MAX).encode();
+        reader.receive_data(&length, header_len);
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InvalidSectionFormat(
+                SectionFormatError::InvalidSize(usize::MAX)
+            ))
+        ));
+        // Receiving more bytes must not overflow the missing section bytes
+        reader.receive_bytes(vec![0u8; 16], header_len + length.len());
+        assert!(reader.read_section().is_err());
+    }
 
     #[test]
     fn test_car_v1_reader_read_header() {
@@ -214,6 +231,464 @@ mod tests {
         assert_eq!(block_bytes, 4);
     }
 
+    #[test]
+    fn test_car_v1_reader_find_by_digest() {
+        let mut reader = CarReader::new();
+        reader.receive_data(CAR_V1, 0);
+        reader.read_header().unwrap();
+
+        // CIDv0 (dag-pb) with the digest of the raw block "aaaa"
+        let alias = RawCid::from_hex(
+            "122061be55a8e2f6b4e172338bddf184d6dbee29c98853e0a0485ecee7f27b9af0b4",
+        )
+        .unwrap();
+        let section = reader
+            .find_section_by_digest(alias.digest().unwrap())
+            .unwrap();
+        assert_eq!(section.block().data(), b"aaaa");
+        assert!(section.cid().digest_eq(alias.digest().unwrap()));
+        assert_ne!(section.cid(), &alias);
+        assert_eq!(section.location.offset, 619);
+
+        assert!(matches!(
+            reader.find_section_by_digest(&[0; 32]),
+            Err(CarReaderError::InsufficientData(715, _))
+        ));
+    }
+
+    #[test]
+    fn test_car_v1_reader_read_hints() {
+        // The header is 100 bytes long, the first section 92 bytes long
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..10], 0);
+        assert!(matches!(
+            reader.read_header(),
+            Err(CarReaderError::InsufficientData(10, 90))
+        ));
+        reader.receive_data(&CAR_V1[10..110], 10);
+        reader.read_header().unwrap();
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InsufficientData(110, 82))
+        ));
+
+        // The minimum read hint is a floor for every hint
+        let mut reader = CarReader::with_min_read_hint(4096);
+        reader.receive_data(&CAR_V1[..110], 0);
+        reader.read_header().unwrap();
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InsufficientData(110, 4096))
+        ));
+    }
+
+    #[test]
+    fn test_car_v1_reader_skip_section() {
+        let mut expected = CarReader::new();
+        expected.receive_data(CAR_V1, 0);
+        expected.read_header().unwrap();
+
+        // Only feed the requested bytes: the blocks are never read
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..100], 0);
+        reader.read_header().unwrap();
+        let mut fed = 0;
+        loop {
+            match reader.skip_section() {
+                Ok((location, cid)) => {
+                    let section = expected.read_section().unwrap();
+                    assert_eq!(location, section.location);
+                    assert_eq!(&cid, section.cid());
+                }
+                Err(CarReaderError::InsufficientData(offset, hint)) if offset < CAR_V1.len() => {
+                    assert!(hint > 0);
+                    let end = (offset + hint).min(CAR_V1.len());
+                    reader.receive_data(&CAR_V1[offset..end], offset);
+                    fed += end - offset;
+                }
+                Err(CarReaderError::InsufficientData(offset, _)) => {
+                    assert_eq!(offset, CAR_V1.len());
+                    break;
+                }
+                Err(err) => panic!("Unexpected error: {:?}", err),
+            }
+        }
+        assert!(expected.read_section().is_err());
+        assert!(fed < CAR_V1.len() - 100);
+
+        // Skipped sections can still be read afterwards
+        reader.seek_first_section().unwrap();
+        reader.receive_data(&CAR_V1[100..], 100);
+        reader.skip_section().unwrap();
+        assert_eq!(reader.read_section().unwrap().location.offset, 192);
+    }
+
+    #[test]
+    fn test_car_v1_reader_block_chunks() {
+        let mut expected = CarReader::new();
+        expected.receive_data(CAR_V1, 0);
+        expected.read_header().unwrap();
+
+        // Feed 16 bytes at a time: the blocks are surfaced as they arrive
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..100], 0);
+        reader.read_header().unwrap();
+        reader.finish(CAR_V1.len());
+        reader.shrink();
+        let mut offset = 100;
+        let mut feed = |reader: &mut CarReader| {
+            let end = (offset + 16).min(CAR_V1.len());
+            reader.receive_data(&CAR_V1[offset..end], offset);
+            offset = end;
+        };
+        loop {
+            let (location, cid, block_length) = match reader.begin_section() {
+                Ok(started) => started,
+                Err(CarReaderError::InsufficientData(_, _)) => {
+                    feed(&mut reader);
+                    continue;
+                }
+                Err(CarReaderError::EndOfSections) => break,
+                Err(err) => panic!("Unexpected error: {:?}", err),
+            };
+            let mut block = Vec::new();
+            loop {
+                match reader.read_block_chunk() {
+                    Ok(Some(chunk)) => {
+                        assert!(chunk.len() <= 16);
+                        block.extend_from_slice(chunk);
+                    }
+                    Ok(None) => break,
+                    Err(CarReaderError::InsufficientData(_, hint)) => {
+                        assert!(hint <= block_length);
+                        feed(&mut reader);
+                    }
+                    Err(err) => panic!("Unexpected error: {:?}", err),
+                }
+            }
+            let section = expected.read_section().unwrap();
+            assert_eq!(location, section.location);
+            assert_eq!(&cid, section.cid());
+            assert_eq!(block, section.block().data());
+            // Only the length and CID of a section are buffered, not its whole block
+            assert!(reader.buffer_capacity() <= 64);
+        }
+        assert!(expected.read_section().is_err());
+
+        // Reading the next section skips the rest of the block
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..150], 0);
+        reader.read_header().unwrap();
+        let (_, _, block_length) = reader.begin_section().unwrap();
+        // The block starts after the length (1 byte) and the CID (36 bytes)
+        assert_eq!(reader.read_block_chunk().unwrap().unwrap().len(), 150 - 137);
+        assert_eq!(reader.block_remaining(), block_length - 13);
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InsufficientData(192, _))
+        ));
+        assert_eq!(reader.read_block_chunk().unwrap(), None);
+
+        // A block cut by the end of the file
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..150], 0);
+        reader.finish(150);
+        reader.read_header().unwrap();
+        reader.begin_section().unwrap();
+        assert!(reader.read_block_chunk().unwrap().is_some());
+        assert!(matches!(
+            reader.read_block_chunk(),
+            Err(CarReaderError::Truncated { missing_bytes: 42 })
+        ));
+    }
+
+    #[test]
+    fn test_car_v1_reader_overlapping_data() {
+        // Data delivered twice, or overlapping with the buffered bytes, is only appended once
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V1[..60], 0);
+        reader.receive_data(&CAR_V1[40..60], 40);
+        reader.receive_data(&CAR_V1[50..200], 50);
+        reader.read_header().unwrap();
+        assert_eq!(reader.read_section().unwrap().location.offset, 100);
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InsufficientData(200, _))
+        ));
+
+        crate::chaos::assert_consistent(CAR_V1, CarReader::new);
+    }
+
+    #[test]
+    fn test_car_v1_reader_seek_to() {
+        // Collect all the sections with their locations
+        let mut reader = CarReader::new();
+        reader.receive_data(CAR_V1, 0);
+        reader.read_header().unwrap();
+        let mut sections = Vec::new();
+        while let Ok(section) = reader.read_section() {
+            sections.push(section);
+        }
+        assert_eq!(sections.len(), 8);
+
+        // Jump to every section, in reverse order, feeding only the requested bytes
+        let mut reader = CarReader::new();
+        assert!(matches!(
+            reader.seek_to(&sections[0].location),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+        reader.receive_data(&CAR_V1[..100], 0);
+        reader.read_header().unwrap();
+        for expected in sections.iter().rev() {
+            let offset = expected.location.offset as usize;
+            let end = offset + expected.location.length as usize;
+            reader.seek_to(&expected.location).unwrap();
+            match reader.read_section() {
+                Err(CarReaderError::InsufficientData(read_from, _)) => {
+                    assert_eq!(read_from, offset)
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            reader.receive_data(&CAR_V1[offset..end], offset);
+            let section = reader.read_section().unwrap();
+            assert_eq!(section.cid(), expected.cid());
+            assert_eq!(section.location, expected.location);
+        }
+
+        // Locations inside the header are rejected
+        let location = SectionLocation {
+            offset: 10,
+            length: 20,
+        };
+        assert!(matches!(
+            reader.seek_to(&location),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+    }
+
+    #[test]
+    fn test_car_v1_reader_lenient_cids() {
+        use crate::wire::cid::{CidFormatError, CidParsing};
+
+        // A (future) CIDv2 section, followed by a regular CIDv1 section
+        let future_cid = RawCid::from_hex("0255000401020304").unwrap();
+        let cid = RawCid::from_hex(
+            "01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451",
+        )
+        .unwrap();
+        let mut writer = CarWriter::new(vec![cid.clone()]);
+        writer
+            .write_section(&Section::new(
+                future_cid.clone(),
+                Block::new(vec![1, 2, 3, 4]),
+            ))
+            .unwrap();
+        writer
+            .write_section(&Section::new(cid.clone(), Block::new(b"cccc".to_vec())))
+            .unwrap();
+        let mut car = vec![0u8; 1024];
+        let written = writer.send_data(&mut car);
+        car.truncate(written);
+
+        // Strict mode (default) rejects the unknown CID version
+        let mut reader = CarReader::new();
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InvalidSectionFormat(
+                SectionFormatError::InvalidCid(CidFormatError::UnsupportedVersion)
+            ))
+        ));
+
+        // Lenient mode carries it opaquely and goes on
+        let mut reader = CarReader::new();
+        reader.set_cid_parsing(CidParsing::Lenient);
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        let section = reader.read_section().unwrap();
+        assert_eq!(section.cid(), &future_cid);
+        assert_eq!(section.block().data(), &[1, 2, 3, 4]);
+        assert_eq!(reader.read_section().unwrap().cid(), &cid);
+    }
+
+    #[test]
+    fn test_car_v1_limits_symmetry() {
+        // The first section of CAR_V1 is 91 bytes long (excluding its length prefix)
+        let limits = Limits::new().with_max_section_size(64);
+        let mut reader = CarReader::new();
+        reader.set_limits(limits);
+        reader.receive_data(CAR_V1, 0);
+        reader.read_header().unwrap();
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InvalidSectionFormat(
+                SectionFormatError::InvalidSize(91)
+            ))
+        ));
+
+        // The same section is refused by a writer using the same limits
+        let mut reader = CarReader::new();
+        reader.receive_data(CAR_V1, 0);
+        reader.read_header().unwrap();
+        let section = reader.read_section().unwrap();
+        let mut writer = CarWriter::new(vec![section.cid().clone()]);
+        writer.set_limits(limits);
+        assert!(matches!(
+            writer.write_section(&section),
+            Err(CarWriterError::SectionTooLarge {
+                length: 91,
+                max: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn test_car_v1_block_and_header_limits() {
+        // The first section of CAR_V1 holds a 36-byte CID and a 55-byte block
+        let limits = Limits::new().with_max_block_size(54);
+        assert!(limits.allows_section(91));
+        let mut reader = CarReader::new();
+        reader.set_limits(limits);
+        reader.receive_data(CAR_V1, 0);
+        reader.read_header().unwrap();
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InvalidSectionFormat(
+                SectionFormatError::InvalidSize(55)
+            ))
+        ));
+        let mut writer = CarWriter::new(Vec::new());
+        writer.set_limits(limits);
+        let section = Section::try_read_bytes(&CAR_V1[CAR_V1[0] as usize + 1..])
+            .unwrap()
+            .0;
+        assert!(matches!(
+            writer.write_section(&section),
+            Err(CarWriterError::BlockTooLarge {
+                length: 55,
+                max: 54
+            })
+        ));
+
+        // Raising the limits is enough to read larger blocks
+        let mut reader = CarReader::new();
+        reader.set_limits(Limits::new().with_max_block_size(55));
+        reader.receive_data(CAR_V1, 0);
+        reader.read_header().unwrap();
+        assert!(reader.read_section().is_ok());
+
+        // The header length is checked before waiting for the header itself
+        let mut reader = CarReader::new();
+        reader.set_limits(Limits::new().with_max_header_size(16));
+        reader.receive_data(&CAR_V1[..1], 0);
+        assert!(matches!(
+            reader.read_header(),
+            Err(CarReaderError::HeaderTooLarge(length)) if length == CAR_V1[0] as u64
+        ));
+    }
+
+    #[test]
+    fn test_car_v1_resync() {
+        // Damage the length of the third section (325..366), and feed the reader with small chunks
+        let mut car = CAR_V1.to_vec();
+        car[325] = 0;
+        let mut reader = CarReader::new();
+        let mut offsets = Vec::new();
+        let mut skipped = Vec::new();
+        let mut resyncing = false;
+        loop {
+            let result = if !reader.has_header() {
+                reader.read_header()
+            } else if resyncing {
+                reader.resync().map(|range| {
+                    skipped.push(range);
+                    resyncing = false;
+                })
+            } else {
+                reader
+                    .read_section()
+                    .map(|section| offsets.push(section.location.offset))
+            };
+            match result {
+                Ok(()) => {}
+                Err(CarReaderError::InsufficientData(offset, _)) if offset < car.len() => {
+                    let end = (offset + 20).min(car.len());
+                    reader.receive_data(&car[offset..end], offset);
+                }
+                Err(CarReaderError::InvalidSectionFormat(_)) => resyncing = true,
+                Err(_) => break,
+            }
+        }
+        assert_eq!(skipped, vec![325..366]);
+        assert_eq!(offsets, vec![100, 192, 366, 496, 537, 619, 660]);
+    }
+
+    #[test]
+    fn test_car_v1_finish() {
+        // Complete file: the end of the sections is reported
+        let mut reader = CarReader::new();
+        reader.receive_data(CAR_V1, 0);
+        reader.finish(CAR_V1.len());
+        reader.read_header().unwrap();
+        for _ in 0..8 {
+            reader.read_section().unwrap();
+        }
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::EndOfSections)
+        ));
+
+        // Cut in the header, or in the section at 325 (41 bytes long)
+        for (len, sections, missing) in [(50, 0, 50), (330, 2, 36)] {
+            let mut reader = CarReader::new();
+            reader.receive_data(&CAR_V1[..len], 0);
+            let result = reader.read_header().and_then(|()| {
+                for _ in 0..sections {
+                    reader.read_section()?;
+                }
+                reader.read_section().map(|_| ())
+            });
+            // More data is requested until the end is declared
+            assert!(
+                matches!(result, Err(CarReaderError::InsufficientData(offset, _)) if offset == len)
+            );
+            reader.finish(len);
+            let result = match reader.has_header() {
+                true => reader.read_section().map(|_| ()),
+                false => reader.read_header(),
+            };
+            assert!(
+                matches!(result, Err(CarReaderError::Truncated { missing_bytes }) if missing_bytes == missing)
+            );
+        }
+
+        // Nothing plausible until the end: resync skips everything
+        let mut car = CAR_V1.to_vec();
+        car[660] = 0;
+        let mut reader = CarReader::new();
+        reader.receive_data(&car[..700], 0);
+        reader.finish(car.len());
+        reader.read_header().unwrap();
+        reader
+            .seek_to(&SectionLocation {
+                offset: 660,
+                length: 0,
+            })
+            .unwrap();
+        assert!(reader.read_section().is_err());
+        assert!(matches!(
+            reader.resync(),
+            Err(CarReaderError::InsufficientData(..))
+        ));
+        reader.receive_data(&car[660..], 660);
+        assert_eq!(reader.resync().unwrap(), 660..car.len() as u64);
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::EndOfSections)
+        ));
+    }
+
     #[test]
     fn test_car_v1_writer_reader_compatibility() {
         let root_cid = RawCid::from_hex(
@@ -257,6 +732,7 @@ mod tests {
                         section_to_write.push(section); // Put the section back to try writing it again after flushing
                         continue;
                     }
+                    Err(e) => panic!("Unexpected error: {:?}", e),
                 }
             }
         }
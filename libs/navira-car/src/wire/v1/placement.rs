@@ -0,0 +1,171 @@
+//! Placement of sections at requested offsets
+//!
+//! The CAR v1 payload is a plain sequence of sections, there is no room for gaps between them.
+//! To move a section to a given offset, the writer fills the gap with *padding sections*: raw blocks
+//! of zeros addressed by their identity CID (`bafkqa…`). They are valid sections, understood by any
+//! CAR reader, and verifiable as their CID is their own content.
+//!
+//! Identity digests are capped at [MAX_FILLER_DIGEST] bytes (the usual limit of IPFS implementations), so
+//! a single padding section spans between 5 and 263 bytes, and large gaps are filled with several ones.
+//! Not every gap can be filled: gaps of 1 to 4, 6 and 8 bytes are too small for any combination of padding sections.
+
+use crate::wire::cid::RawCid;
+use crate::wire::v1::{Block, Section, SectionLocation};
+use crate::wire::varint::UnsignedVarint;
+
+/// Maximum digest size of the identity CIDs of the padding sections
+pub const MAX_FILLER_DIGEST: usize = 128;
+
+/// Size of the largest padding section
+const MAX_FILLER_SIZE: u64 = 263;
+
+/// Gaps up to this size are solved exactly, larger ones are first reduced with the largest padding sections
+const SOLVED_GAP_SIZE: usize = 1024;
+
+/// Placement constraint of a section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// The section must start exactly at this offset
+    At(u64),
+    /// The section must start at an offset which is a multiple of this alignment
+    ///
+    /// The smallest satisfiable offset is used. An alignment of 0 or 1 is always satisfied.
+    Aligned(u64),
+}
+
+/// Layout achieved by the placement of a section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedSection {
+    /// Location of the placed section
+    pub location: SectionLocation,
+    /// Locations of the padding sections inserted before the placed section, in order
+    pub padding: Vec<SectionLocation>,
+}
+
+impl PlacedSection {
+    /// Total size of the padding inserted before the placed section
+    pub fn padding_size(&self) -> u64 {
+        self.padding.iter().map(|loc| loc.length).sum()
+    }
+}
+
+/// Builds the padding section with a digest of `digest_size` zeros
+fn filler_section(digest_size: usize) -> Section {
+    // CIDv1, raw codec, identity multihash
+    let mut cid = vec![0x01, 0x55, 0x00];
+    cid.extend(UnsignedVarint(digest_size as u64).encode());
+    cid.resize(cid.len().saturating_add(digest_size), 0);
+    Section::new(RawCid::new(cid), Block::new(vec![0; digest_size]))
+}
+
+/// Total size of the padding section with a digest of `digest_size` bytes
+fn filler_size(digest_size: usize) -> u64 {
+    let varint_size = |value: u64| UnsignedVarint(value).encode().len() as u64;
+    let length = varint_size(digest_size as u64)
+        .saturating_add(3)
+        .saturating_add((digest_size as u64).saturating_mul(2));
+    varint_size(length).saturating_add(length)
+}
+
+/// Builds the padding sections filling exactly `gap` bytes
+///
+/// ## Returns
+/// - `Some(sections)` with the padding sections (empty if `gap` is 0).
+/// - `None` if no combination of padding sections spans exactly `gap` bytes.
+pub(crate) fn padding_sections(gap: u64) -> Option<Vec<Section>> {
+    let mut digests = Vec::new();
+    let mut remaining = gap;
+    while remaining > SOLVED_GAP_SIZE as u64 {
+        digests.push(MAX_FILLER_DIGEST);
+        remaining = remaining.saturating_sub(MAX_FILLER_SIZE);
+    }
+
+    // Fewest padding sections to fill each gap size, by dynamic programming over the filler sizes
+    // best[n] = (count, digest size of the last filler)
+    let fillers: Vec<(usize, usize)> = (0..=MAX_FILLER_DIGEST)
+        .map(|digest| (filler_size(digest) as usize, digest))
+        .collect();
+    let remaining = remaining as usize;
+    let mut best: Vec<Option<(usize, usize)>> = Vec::with_capacity(remaining.saturating_add(1));
+    best.push(Some((0, 0)));
+    for n in 1..=remaining {
+        let fewest = fillers
+            .iter()
+            .filter_map(|(size, digest)| {
+                let (count, _) = best.get(n.checked_sub(*size)?).copied().flatten()?;
+                Some((count.saturating_add(1), *digest))
+            })
+            .min_by_key(|(count, _)| *count);
+        best.push(fewest);
+    }
+
+    let mut n = remaining;
+    while n > 0 {
+        let (_, digest) = best.get(n).copied().flatten()?;
+        digests.push(digest);
+        n = n.checked_sub(filler_size(digest) as usize)?;
+    }
+    Some(digests.into_iter().map(filler_section).collect())
+}
+
+/// Returns true if a gap of `gap` bytes can be filled with padding sections
+pub(crate) fn is_fillable(gap: u64) -> bool {
+    // Every gap above the small unfillable ones can be filled
+    !matches!(gap, 1..=4 | 6 | 8)
+}
+
+/// Resolves a placement constraint into the offset where the section must start
+///
+/// ## Returns
+/// - `Some(offset)` with the smallest offset satisfying the constraint, reachable from `position`.
+/// - `None` if the constraint cannot be satisfied from `position`.
+pub(crate) fn resolve_placement(placement: Placement, position: u64) -> Option<u64> {
+    match placement {
+        Placement::At(offset) => offset
+            .checked_sub(position)
+            .is_some_and(is_fillable)
+            .then_some(offset),
+        Placement::Aligned(0 | 1) => Some(position),
+        Placement::Aligned(alignment) => {
+            let first = position.div_ceil(alignment).checked_mul(alignment)?;
+            (0..)
+                .map_while(|i: u64| i.checked_mul(alignment)?.checked_add(first))
+                .find(|offset| is_fillable(offset.saturating_sub(position)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padding_sections_sizes() {
+        for gap in (0..300).chain([1023, 1024, 1025, 1500, 4096, 100_000]) {
+            let sections = padding_sections(gap);
+            assert_eq!(sections.is_some(), is_fillable(gap), "gap {}", gap);
+            if let Some(sections) = sections {
+                let size: usize = sections.iter().map(|s| s.total_length()).sum();
+                assert_eq!(size as u64, gap);
+                assert!(
+                    sections
+                        .iter()
+                        .all(|s| s.cid().digest() == Some(s.block().data()))
+                );
+            }
+        }
+        assert_eq!(filler_size(MAX_FILLER_DIGEST), MAX_FILLER_SIZE);
+    }
+
+    #[test]
+    fn test_resolve_placement() {
+        assert_eq!(resolve_placement(Placement::At(100), 100), Some(100));
+        assert_eq!(resolve_placement(Placement::At(105), 100), Some(105));
+        assert_eq!(resolve_placement(Placement::At(106), 100), None);
+        assert_eq!(resolve_placement(Placement::At(99), 100), None);
+        assert_eq!(resolve_placement(Placement::Aligned(64), 128), Some(128));
+        // 128 and 130 are too close to the position, the next multiple is used
+        assert_eq!(resolve_placement(Placement::Aligned(2), 127), Some(132));
+        assert_eq!(resolve_placement(Placement::Aligned(1), 127), Some(127));
+    }
+}
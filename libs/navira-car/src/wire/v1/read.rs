@@ -1,29 +1,341 @@
-use crate::wire::cid::RawCid;
-use crate::wire::v1::{CarHeader, LocatableSection, Section, SectionFormatError, SectionLocation};
+use std::ops::Range;
+
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+
+use crate::trace::trace_event;
+use crate::wire::cid::{CidParsing, RawCid};
+use crate::wire::limits::Limits;
+#[cfg(feature = "bytes")]
+use crate::wire::v1::SectionBytes;
+use crate::wire::v1::{
+    CarHeader, LocatableSection, Section, SectionFormatError, SectionLocation, SectionRef,
+};
 use crate::wire::varint::UnsignedVarint;
 
+/// Number of bytes requested for the CID of a skipped section
+///
+/// Enough for the usual CIDs (CIDv0 and CIDv1 with a 32-byte digest are 34 and 36 bytes long). Longer CIDs
+/// (e.g. identity CIDs) are completed by further requests.
+const SKIP_CID_HINT: usize = 64;
+
+/// Maximum number of bytes requested for the block of a section read in chunks
+///
+/// The memory of the reader is then bounded by the chunks received, whatever the size of the block.
+const BLOCK_CHUNK_HINT: usize = 64 * 1024;
+
 /// CAR v1 reader
 ///
 /// This struct provides functionality to read CAR v1 files, in a sans-io manner
 #[derive(Debug, Clone)]
 pub struct CarReader {
     /// Internal data buffer
+    ///
+    /// The bytes before the cursor are already consumed, they are only dropped when the buffer is compacted.
     data: Vec<u8>,
-    /// Internal data start position
+    /// Received chunk held without copy, in place of the internal data buffer (feature `bytes`)
+    ///
+    /// Set by [CarReader::receive_bytes] when nothing was buffered, the cursor then points into it.
+    #[cfg(feature = "bytes")]
+    shared: Option<Bytes>,
+    /// Rest of a received chunk, following the owned buffer, to be used as the shared chunk once the owned
+    /// buffer is consumed (feature `bytes`)
+    ///
+    /// Set by [CarReader::receive_bytes], when only the end of a section spread over two chunks is copied.
+    #[cfg(feature = "bytes")]
+    pending: Option<Bytes>,
+    /// Position of the first unconsumed byte in the internal buffer
+    cursor: usize,
+    /// Offset in the file of the first unconsumed byte
     start: usize,
     /// Parsed header, if available
     /// (CarHeader, total_header_size including length varint)
     header: Option<(CarHeader, usize)>,
+    /// Minimum hint length returned in InsufficientData errors
+    min_read_hint: usize,
+    /// Size limits applied to the header and the sections
+    limits: Limits,
+    /// Parsing mode of the section CIDs
+    cid_parsing: CidParsing,
+    /// Offset of the first skipped byte, while resynchronizing (see [CarReader::resync])
+    resync_start: Option<usize>,
+    /// Total length of the file, once declared by [CarReader::finish]
+    end: Option<usize>,
+    /// Bytes of the block not read yet, while a section is read in chunks (see [CarReader::begin_section])
+    block_remaining: usize,
 }
 
 impl CarReader {
     /// Creates a new CarReader
     pub fn new() -> Self {
+        Self::with_min_read_hint(0)
+    }
+
+    /// Creates a new CarReader with a minimum read hint
+    ///
+    /// The hint length returned with [CarReaderError::InsufficientData] is computed from what the reader
+    /// already knows about the upcoming bytes (remaining header size, declared section length, etc.), which
+    /// can be as small as a few bytes. The minimum read hint is a floor applied to every hint, so that callers
+    /// following the hints naturally coalesce small reads into larger, more efficient ones.
+    pub fn with_min_read_hint(min_read_hint: usize) -> Self {
         CarReader {
             data: Vec::new(),
+            #[cfg(feature = "bytes")]
+            shared: None,
+            #[cfg(feature = "bytes")]
+            pending: None,
+            cursor: 0,
             start: 0,
             header: None,
+            min_read_hint,
+            limits: Limits::default(),
+            cid_parsing: CidParsing::default(),
+            resync_start: None,
+            end: None,
+            block_remaining: 0,
+        }
+    }
+
+    /// Get the minimum hint length returned in InsufficientData errors
+    pub fn min_read_hint(&self) -> usize {
+        self.min_read_hint
+    }
+
+    /// Set the minimum hint length returned in InsufficientData errors
+    ///
+    /// See [CarReader::with_min_read_hint] for more details.
+    pub fn set_min_read_hint(&mut self, min_read_hint: usize) {
+        self.min_read_hint = min_read_hint;
+    }
+
+    /// Declare the end of the file, with its total length
+    ///
+    /// A CAR v1 file has no explicit end: without this call, the reader requests more data forever after the
+    /// last section. Once the end is declared, and all the bytes up to it are received, reading past the last
+    /// section returns [CarReaderError::EndOfSections], and reading a header or section cut by the end of the
+    /// file returns [CarReaderError::Truncated].
+    pub fn finish(&mut self, total_len: usize) {
+        self.end = Some(total_len);
+    }
+
+    /// Total length of the file, if declared with [CarReader::finish]
+    pub fn total_len(&self) -> Option<usize> {
+        self.end
+    }
+
+    /// Get the size limits applied to the header and the sections
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Set the size limits applied to the header and the sections
+    ///
+    /// Sections exceeding these limits are rejected with [SectionFormatError::InvalidSize], and headers with
+    /// [CarReaderError::HeaderTooLarge].
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Get the parsing mode of the section CIDs
+    pub fn cid_parsing(&self) -> CidParsing {
+        self.cid_parsing
+    }
+
+    /// Set the parsing mode of the section CIDs
+    ///
+    /// In [CidParsing::Lenient] mode, sections with CIDs of unknown versions are read (their CID carried opaquely)
+    /// instead of being rejected with [SectionFormatError::InvalidCid].
+    pub fn set_cid_parsing(&mut self, cid_parsing: CidParsing) {
+        self.cid_parsing = cid_parsing;
+    }
+
+    /// Build an InsufficientData error requesting the bytes right after the buffered ones
+    ///
+    /// `needed` is the number of bytes known to be needed (0 if unknown), the minimum read hint is applied on top of it.
+    ///
+    /// Once the end of the file is reached (see [CarReader::finish]), no more bytes can come: the reader is either
+    /// at the end of the sections, or in the middle of a truncated header or section.
+    fn insufficient_data(&self, needed: usize) -> CarReaderError {
+        if let Some(end) = self.end
+            && self.buffered_end() >= end
+        {
+            if self.has_header() && self.buffered().is_empty() {
+                trace_event!(WIRE_V1, offset = self.start, "End of sections");
+                return CarReaderError::EndOfSections;
+            }
+            let missing_bytes = self.missing_section_bytes().max(1);
+            trace_event!(
+                WIRE_V1,
+                offset = self.start,
+                missing_bytes,
+                "Truncated file"
+            );
+            return CarReaderError::Truncated { missing_bytes };
+        }
+        trace_event!(
+            WIRE_V1,
+            offset = self.buffered_end(),
+            needed,
+            "Waiting for more data"
+        );
+        CarReaderError::InsufficientData(self.buffered_end(), needed.max(self.min_read_hint))
+    }
+
+    /// Offset of the end of the buffered bytes, where the next received bytes are expected
+    fn buffered_end(&self) -> usize {
+        self.start.saturating_add(self.buffered().len())
+    }
+
+    /// Number of bytes missing from the buffer to complete the section (or header) starting at the buffer start
+    ///
+    /// Once the length varint of the section is available, the exact section size is known.
+    /// Otherwise, 0 is returned as the size is unknown.
+    fn missing_section_bytes(&self) -> usize {
+        let buffered = self.buffered();
+        match UnsignedVarint::decode(buffered) {
+            Some((length, varint_size)) => usize::try_from(length.0)
+                .unwrap_or(usize::MAX)
+                .saturating_add(varint_size)
+                .saturating_sub(buffered.len()),
+            None => 0,
+        }
+    }
+
+    /// Number of bytes missing from the buffer to read the length and CID of the section starting at the buffer start
+    ///
+    /// The block is not requested, see [CarReader::skip_section]. Falls back to [CarReader::missing_section_bytes]
+    /// when the CID is longer than expected.
+    fn missing_section_header_bytes(&self) -> usize {
+        let buffered = self.buffered();
+        match UnsignedVarint::decode(buffered) {
+            Some((length, varint_size)) => {
+                match varint_size
+                    .saturating_add((length.0 as usize).min(SKIP_CID_HINT))
+                    .saturating_sub(buffered.len())
+                {
+                    0 => self.missing_section_bytes(),
+                    missing => missing,
+                }
+            }
+            // The length is not known yet: request the usual size of the length and CID
+            None => SKIP_CID_HINT.saturating_sub(buffered.len()),
+        }
+    }
+
+    /// Whole internal buffer (the shared chunk, if any), consumed bytes included
+    fn storage(&self) -> &[u8] {
+        #[cfg(feature = "bytes")]
+        if let Some(shared) = &self.shared {
+            return shared;
+        }
+        &self.data
+    }
+
+    /// Unconsumed bytes of the internal buffer
+    fn buffered(&self) -> &[u8] {
+        self.storage().get(self.cursor..).unwrap_or_default()
+    }
+
+    /// Consume `n` bytes by moving the cursor, without moving the buffered bytes
+    ///
+    /// Consuming past the buffered bytes skips the bytes not received yet.
+    fn consume(&mut self, n: usize) {
+        let len = self.storage().len();
+        let consumed = self.cursor.saturating_add(n);
+        let skipped = consumed.saturating_sub(len);
+        self.cursor = consumed.min(len);
+        self.start = self.start.saturating_add(n);
+        if self.cursor == len {
+            // Nothing left, the buffer is reused from its start
+            self.data.clear();
+            self.cursor = 0;
+            #[cfg(feature = "bytes")]
+            {
+                self.shared = self
+                    .pending
+                    .take()
+                    .filter(|pending| pending.len() > skipped)
+                    .map(|pending| pending.slice(skipped..));
+            }
+            #[cfg(not(feature = "bytes"))]
+            let _ = skipped;
+        }
+    }
+
+    /// Use the pending chunk as the shared chunk, once the owned buffer is consumed
+    ///
+    /// Called before parsing, as [CarReader::read_section_ref] only moves the cursor.
+    #[cfg(feature = "bytes")]
+    fn promote_pending(&mut self) {
+        if self.pending.is_some() && self.cursor == self.storage().len() {
+            self.consume(0);
+        }
+    }
+
+    /// Drop all the buffered bytes, keeping the allocation
+    ///
+    /// This happens on seeks, so any resynchronization (or block read in chunks) in progress is abandoned.
+    fn clear_buffer(&mut self) {
+        self.data.clear();
+        self.cursor = 0;
+        self.resync_start = None;
+        self.block_remaining = 0;
+        #[cfg(feature = "bytes")]
+        {
+            self.shared = None;
+            self.pending = None;
+        }
+    }
+
+    /// Drop the consumed bytes, once they are at least half of the buffer
+    ///
+    /// Each byte is then moved at most once on average, so parsing is amortized O(section size),
+    /// instead of moving all the remaining bytes after each section.
+    fn compact(&mut self) {
+        #[cfg(feature = "bytes")]
+        if let Some(shared) = self.shared.take() {
+            // Appending to the shared chunk requires owning its unconsumed bytes
+            self.data.clear();
+            self.data
+                .extend_from_slice(shared.get(self.cursor..).unwrap_or_default());
+            self.cursor = 0;
+            return;
+        }
+        #[cfg(feature = "bytes")]
+        if let Some(pending) = self.pending.take() {
+            // Same for the pending chunk, which follows the owned buffer
+            self.data.drain(..self.cursor);
+            self.data.extend_from_slice(&pending);
+            self.cursor = 0;
+            return;
+        }
+        if self.cursor > 0 && self.cursor >= self.data.len() / 2 {
+            self.data.drain(..self.cursor);
+            self.cursor = 0;
+        }
+    }
+
+    /// Capacity of the internal buffer, in bytes
+    ///
+    /// The buffer keeps its allocation between sections, so this is about the size of the largest
+    /// section (or received chunk) so far. A shared chunk (see `receive_bytes`) is not owned, so not counted.
+    pub fn buffer_capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Release the memory of the internal buffer which is not needed by the unconsumed bytes
+    ///
+    /// Useful after reading an unusually large section, as the buffer otherwise keeps its largest size.
+    pub fn shrink(&mut self) {
+        #[cfg(feature = "bytes")]
+        if self.shared.is_some() {
+            // The unconsumed bytes are in the shared chunk
+            self.data = Vec::new();
+            return;
         }
+        self.data.drain(..self.cursor);
+        self.cursor = 0;
+        self.data.shrink_to_fit();
     }
 
     /// Has the header already been parsed?
@@ -52,7 +364,7 @@ impl CarReader {
                     return Ok(());
                 }
                 // Clear the buffer and set start to the end of the header
-                self.data.clear();
+                self.clear_buffer();
                 self.start = total_header_size;
                 Ok(())
             }
@@ -60,6 +372,69 @@ impl CarReader {
         }
     }
 
+    /// Seek to the section at the given location
+    ///
+    /// The internal buffer is reset, so that the next [CarReaderError::InsufficientData] requests exactly
+    /// `location.offset`, and the next section read is the one starting at this offset. This is the way to
+    /// position the reader on a location known in advance (from an index, or from a previous run).
+    ///
+    /// # Returns
+    ///
+    /// * Ok(()) - Successfully seeked to the location
+    /// * Err(CarReaderError::PreconditionNotMet) - The header is not parsed yet, or the location is inside the header
+    pub fn seek_to(&mut self, location: &SectionLocation) -> Result<(), CarReaderError> {
+        match self.header {
+            Some((_, total_header_size)) if location.offset as usize >= total_header_size => {
+                trace_event!(WIRE_V1, offset = location.offset, "Seek to section");
+                self.clear_buffer();
+                self.start = location.offset as usize;
+                Ok(())
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
+    /// Range of offsets where sections can start (after the header), once the header is parsed
+    pub(crate) fn sections_range(&self) -> Option<Range<u64>> {
+        self.header
+            .as_ref()
+            .map(|(_, total_header_size)| *total_header_size as u64..u64::MAX)
+    }
+
+    /// Position the reader on the given offset, with an already parsed header
+    ///
+    /// The buffered bytes are dropped, like with [CarReader::seek_to]. See [CarReader::restore_state](crate::CarReader::restore_state).
+    pub(crate) fn restore(&mut self, header: CarHeader, header_size: usize, position: usize) {
+        self.clear_buffer();
+        self.header = Some((header, header_size));
+        self.start = position;
+    }
+
+    /// Offset of the next byte to be read (the start of the next section, once the header is read)
+    ///
+    /// While a section is read in chunks, this is the end of the section.
+    pub(crate) fn position(&self) -> usize {
+        self.start.saturating_add(self.block_remaining)
+    }
+
+    /// Take the bytes buffered but not consumed yet (e.g. a partially received section)
+    ///
+    /// The reader keeps its position, as if the bytes had never been received.
+    ///
+    /// # Returns
+    ///
+    /// * (usize, Vec<u8>) - Offset of the first buffered byte, and the buffered bytes
+    pub(crate) fn take_buffered(&mut self) -> (usize, Vec<u8>) {
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        if self.shared.is_some() || self.pending.is_some() {
+            self.compact();
+        }
+        self.data.drain(..self.cursor);
+        self.cursor = 0;
+        (self.start, std::mem::take(&mut self.data))
+    }
+
     /// Receive data into the reader's buffer
     ///
     /// # Arguments
@@ -67,17 +442,66 @@ impl CarReader {
     /// * `pos` - Offset position inside the CAR file which the buffer has been read from
     pub fn receive_data(&mut self, buf: &[u8], pos: usize) {
         // Internal behavior:
-        // If pos == start + data.len(), append to the end
+        // If the data is contiguous to (or overlaps) the buffer, append the bytes not buffered yet
         // Otherwise, a "seek" has occurred, so reset the buffer
-        if pos == self.start + self.data.len() {
-            self.data.extend_from_slice(buf);
+        // (prefer an explicit CarReader::seek_to, so the reader requests the right offset)
+        #[cfg(feature = "bytes")]
+        if self.pending.is_some() {
+            // The pending chunk is part of the buffered bytes
+            self.compact();
+        }
+        let end = self.buffered_end();
+        if (self.start..=end).contains(&pos) {
+            self.compact();
+            self.data
+                .extend_from_slice(buf.get(end.saturating_sub(pos)..).unwrap_or_default());
         } else {
-            self.data.clear();
+            self.clear_buffer();
             self.data.extend_from_slice(buf);
             self.start = pos;
         }
     }
 
+    /// Receive data held in [Bytes] (or [BytesMut](bytes::BytesMut)), without copying it when possible
+    ///
+    /// When all the buffered bytes are consumed, the chunk is kept as is: [CarReader::read_section_bytes] then
+    /// returns slices of it. When a section is spread over the buffered bytes and the chunk, only its end is
+    /// copied, and the rest of the chunk is kept as is once the section is read. Otherwise, the chunk is copied
+    /// like with [CarReader::receive_data].
+    ///
+    /// # Arguments
+    /// * `buf` - Received bytes
+    /// * `pos` - Offset position inside the CAR file which the bytes have been read from
+    #[cfg(feature = "bytes")]
+    #[doc(cfg(feature = "bytes"))]
+    pub fn receive_bytes(&mut self, buf: impl Into<Bytes>, pos: usize) {
+        let buf = buf.into();
+        let buffered = self.buffered().len();
+        if buffered > 0 {
+            let end = self.start.saturating_add(buffered);
+            let needed = self.missing_section_bytes();
+            if self.pending.is_none()
+                && needed > 0
+                && (self.start..=end).contains(&pos)
+                && pos.saturating_add(buf.len()) > end.saturating_add(needed)
+            {
+                // Only copy the end of the section, the rest of the chunk is kept as is
+                let split = end.saturating_add(needed).saturating_sub(pos);
+                self.receive_data(buf.get(..split).unwrap_or_default(), pos);
+                self.pending = Some(buf.slice(split..));
+            } else {
+                self.receive_data(&buf, pos);
+            }
+            return;
+        }
+        // Nothing buffered: either contiguous (pos == start), or a seek
+        self.clear_buffer();
+        self.start = pos;
+        if !buf.is_empty() {
+            self.shared = Some(buf);
+        }
+    }
+
     /// Attempt to read and parse the CAR header
     ///
     //// # Returns
@@ -94,49 +518,62 @@ impl CarReader {
             // If start != 0, that means we are not at the beginning of the file
             // Seek at the beginning is required for CAR v1
             if self.start != 0 {
-                return Err(CarReaderError::InsufficientData(0, 8));
+                return Err(CarReaderError::InsufficientData(
+                    0,
+                    8.max(self.min_read_hint),
+                ));
             }
 
             // CARv1 header length is stored as an unsigned varint at the start of the file
-            match UnsignedVarint::decode(&self.data) {
+            let buffered = self.buffered();
+            match UnsignedVarint::decode(buffered) {
                 Some((varint_len, varint_size)) => {
+                    if !self.limits.allows_header(varint_len.0) {
+                        return Err(CarReaderError::HeaderTooLarge(varint_len.0));
+                    }
                     let header_len = varint_len.0 as usize;
-                    let total_header_size = varint_size + header_len;
+                    let Some(total_header_size) = varint_size.checked_add(header_len) else {
+                        return Err(CarReaderError::InvalidFormat);
+                    };
 
-                    if self.data.len() < total_header_size {
+                    if buffered.len() < total_header_size {
                         // Not enough data to parse the full header
-                        return Err(CarReaderError::InsufficientData(
-                            self.start + self.data.len(),
-                            total_header_size - self.data.len(),
-                        ));
+                        return Err(self
+                            .insufficient_data(total_header_size.saturating_sub(buffered.len())));
                     }
 
                     // Parse the header
-                    let header: CarHeader =
-                        match ciborium::from_reader(&self.data[varint_size..total_header_size]) {
-                            Ok(h) => h,
-                            Err(err) => {
-                                return Err(CarReaderError::InvalidHeader(err));
-                            }
-                        };
+                    let header: CarHeader = match ciborium::from_reader(
+                        buffered
+                            .get(varint_size..total_header_size)
+                            .unwrap_or_default(),
+                    ) {
+                        Ok(h) => h,
+                        Err(err) => {
+                            return Err(CarReaderError::InvalidHeader(err));
+                        }
+                    };
 
+                    trace_event!(
+                        WIRE_V1,
+                        version = header.version(),
+                        roots = header.roots().len(),
+                        header_size = total_header_size,
+                        "Header parsed"
+                    );
                     // Store the parsed header
                     self.header = Some((header.clone(), total_header_size));
 
-                    // Remove the parsed header from the buffer
-                    self.data.drain(0..total_header_size);
-                    self.start += total_header_size;
+                    // Consume the parsed header
+                    self.consume(total_header_size);
                 }
                 None => {
                     // Not enough data to parse the varint (which is very strange, but possible)
-                    if self.data.len() > 8 {
+                    if buffered.len() > 8 {
                         // If we have more than 8 bytes and still can't parse varint, it's an error
                         return Err(CarReaderError::InvalidFormat);
                     }
-                    return Err(CarReaderError::InsufficientData(
-                        self.start + self.data.len(),
-                        8,
-                    ));
+                    return Err(self.insufficient_data(8));
                 }
             }
         }
@@ -156,40 +593,369 @@ impl CarReader {
     ///
     /// Precondition: Header must be parsed before calling this method.
     pub fn read_section(&mut self) -> Result<LocatableSection, CarReaderError> {
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
         // Header must be parsed before reading sections
         if !self.has_header() {
             return Err(CarReaderError::PreconditionNotMet);
         }
 
         // Attempt to parse a section
-        match Section::try_read_bytes(&self.data) {
+        match Section::try_read_bytes_with(self.buffered(), &self.limits, self.cid_parsing) {
             Ok((section, section_size)) => {
-                // Remove the parsed section from the buffer
-                self.data.drain(0..section_size);
-                self.start += section_size;
+                // Consume the parsed section
+                let offset = self.start;
+                self.consume(section_size);
+                trace_event!(
+                    WIRE_V1,
+                    offset,
+                    length = section_size,
+                    cid = %section.cid().to_hex(),
+                    "Section read"
+                );
 
                 Ok(LocatableSection {
                     section,
                     location: SectionLocation {
-                        offset: (self.start - section_size) as u64,
+                        offset: offset as u64,
                         length: section_size as u64,
                     },
                 })
             }
             Err(SectionFormatError::InsufficientData) => {
                 // Not enough data to parse a full section
-                Err(CarReaderError::InsufficientData(
-                    self.start + self.data.len(),
-                    0,
-                ))
+                Err(self.insufficient_data(self.missing_section_bytes()))
             }
             Err(err) => {
                 // Some other error occurred during section parsing
+                trace_event!(WIRE_V1, offset = self.start, error = ?err, "Invalid section");
+                Err(CarReaderError::InvalidSectionFormat(err))
+            }
+        }
+    }
+
+    /// Read the next section, borrowing its block data from the internal buffer
+    ///
+    /// This is [CarReader::read_section] without the copy of the block data, for passes which only look at
+    /// the CIDs (e.g. indexing). The section bytes are never moved while borrowed.
+    ///
+    /// # Returns
+    ///
+    /// * Ok((SectionLocation, SectionRef)) - Location of the section, and the borrowed section
+    /// * Err(CarReaderError) - Same errors as [CarReader::read_section]
+    ///
+    /// Precondition: Header must be parsed before calling this method.
+    pub fn read_section_ref(
+        &mut self,
+    ) -> Result<(SectionLocation, SectionRef<'_>), CarReaderError> {
+        if !self.has_header() {
+            return Err(CarReaderError::PreconditionNotMet);
+        }
+
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
+        // Borrow the fields rather than calling buffered(), so that the cursor can move while the section is borrowed
+        #[cfg(feature = "bytes")]
+        let storage = self.shared.as_deref().unwrap_or(&self.data);
+        #[cfg(not(feature = "bytes"))]
+        let storage = self.data.as_slice();
+        let buffered = storage.get(self.cursor..).unwrap_or_default();
+        match SectionRef::try_read_bytes_with(buffered, &self.limits, self.cid_parsing) {
+            Ok((section, section_size)) => {
+                trace_event!(
+                    WIRE_V1,
+                    offset = self.start,
+                    length = section_size,
+                    cid = %section.cid().to_hex(),
+                    "Section read"
+                );
+                let location = SectionLocation {
+                    offset: self.start as u64,
+                    length: section_size as u64,
+                };
+                // Only the cursor moves, the bytes stay in place until the next compaction
+                self.cursor = self.cursor.saturating_add(section_size);
+                self.start = self.start.saturating_add(section_size);
+                Ok((location, section))
+            }
+            Err(SectionFormatError::InsufficientData) => {
+                Err(self.insufficient_data(self.missing_section_bytes()))
+            }
+            Err(err) => {
+                trace_event!(WIRE_V1, offset = self.start, error = ?err, "Invalid section");
+                Err(CarReaderError::InvalidSectionFormat(err))
+            }
+        }
+    }
+
+    /// Read the next section, with its block data as a [Bytes] slice of the received chunk
+    ///
+    /// The block shares the memory of the chunk given to [CarReader::receive_bytes] when the section lies in
+    /// it. Otherwise (section spread over several chunks, or received with [CarReader::receive_data]), the block
+    /// data is copied.
+    ///
+    /// # Returns
+    ///
+    /// * Ok((SectionLocation, SectionBytes)) - Location of the section, and the section
+    /// * Err(CarReaderError) - Same errors as [CarReader::read_section]
+    #[cfg(feature = "bytes")]
+    #[doc(cfg(feature = "bytes"))]
+    pub fn read_section_bytes(
+        &mut self,
+    ) -> Result<(SectionLocation, SectionBytes), CarReaderError> {
+        // The cursor moves, but the chunk itself is kept until the next mutable call
+        self.promote_pending();
+        let shared = self.shared.clone();
+        let (location, section) = self.read_section_ref()?;
+        let data = section.block().data();
+        let block = match shared {
+            Some(shared) => shared.slice_ref(data),
+            None => Bytes::copy_from_slice(data),
+        };
+        Ok((location, SectionBytes::new(section.cid().clone(), block)))
+    }
+
+    /// Skip the next section, only reading its length and CID
+    ///
+    /// The block data is never requested nor buffered: the reader moves past it, so that the next
+    /// [CarReaderError::InsufficientData] points after the section, and the caller can seek there instead of
+    /// reading the block. This is the way to index or list large archives without going through all their data.
+    ///
+    /// # Returns
+    ///
+    /// * Ok((SectionLocation, RawCid)) - Location and CID of the skipped section
+    /// * Err(CarReaderError) - Same errors as [CarReader::read_section], the data requested being only the
+    ///   length and CID of the section
+    ///
+    /// Precondition: Header must be parsed before calling this method.
+    pub fn skip_section(&mut self) -> Result<(SectionLocation, RawCid), CarReaderError> {
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
+        if !self.has_header() {
+            return Err(CarReaderError::PreconditionNotMet);
+        }
+
+        match Section::try_read_header_bytes_with(self.buffered(), &self.limits, self.cid_parsing) {
+            Ok((section, section_size)) => {
+                let location = SectionLocation {
+                    offset: self.start as u64,
+                    length: section_size as u64,
+                };
+                trace_event!(
+                    WIRE_V1,
+                    offset = self.start,
+                    length = section_size,
+                    cid = %section.cid().to_hex(),
+                    "Section skipped"
+                );
+                // Consuming past the buffered bytes moves the reader after the block not received yet
+                self.consume(section_size);
+                let (cid, _) = section.into_parts();
+                Ok((location, cid))
+            }
+            Err(SectionFormatError::InsufficientData) => {
+                Err(self.insufficient_data(self.missing_section_header_bytes()))
+            }
+            Err(err) => {
+                trace_event!(WIRE_V1, offset = self.start, error = ?err, "Invalid section");
+                Err(CarReaderError::InvalidSectionFormat(err))
+            }
+        }
+    }
+
+    /// Start reading the next section in chunks, only reading its length and CID
+    ///
+    /// The block data is then surfaced by [CarReader::read_block_chunk] as it is received, rather than once the
+    /// whole section is buffered: the memory of the reader is bounded by the size of the received chunks instead of
+    /// the size of the block. Any other read (or seek) in the middle of the block skips the rest of it.
+    ///
+    /// # Returns
+    ///
+    /// * Ok((SectionLocation, RawCid, usize)) - Location and CID of the section, and the length of its block
+    /// * Err(CarReaderError) - Same errors as [CarReader::read_section], the data requested being only the
+    ///   length and CID of the section
+    ///
+    /// Precondition: Header must be parsed before calling this method.
+    pub fn begin_section(&mut self) -> Result<(SectionLocation, RawCid, usize), CarReaderError> {
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
+        if !self.has_header() {
+            return Err(CarReaderError::PreconditionNotMet);
+        }
+
+        match Section::try_read_header_bytes_with(self.buffered(), &self.limits, self.cid_parsing) {
+            Ok((section, section_size)) => {
+                let location = SectionLocation {
+                    offset: self.start as u64,
+                    length: section_size as u64,
+                };
+                let (cid, _) = section.into_parts();
+                let head_size = UnsignedVarint::decode(self.buffered())
+                    .map_or(0, |(_, varint_size)| varint_size)
+                    .saturating_add(cid.bytes().len());
+                let block_length = section_size.saturating_sub(head_size);
+                trace_event!(
+                    WIRE_V1,
+                    offset = self.start,
+                    length = section_size,
+                    cid = %cid.to_hex(),
+                    "Section started"
+                );
+                self.consume(head_size);
+                self.block_remaining = block_length;
+                Ok((location, cid, block_length))
+            }
+            Err(SectionFormatError::InsufficientData) => {
+                Err(self.insufficient_data(self.missing_section_header_bytes()))
+            }
+            Err(err) => {
+                trace_event!(WIRE_V1, offset = self.start, error = ?err, "Invalid section");
                 Err(CarReaderError::InvalidSectionFormat(err))
             }
         }
     }
 
+    /// Read the next chunk of the block of the section started with [CarReader::begin_section]
+    ///
+    /// The chunk is made of the block bytes received so far, and borrows the internal buffer.
+    ///
+    /// # Returns
+    ///
+    /// * Ok(Some(chunk)) - The next bytes of the block, never empty
+    /// * Ok(None) - The whole block is read (or no section was started), the next section can be read
+    /// * Err(CarReaderError::InsufficientData) - More bytes of the block are needed, at most 64 KiB are requested
+    /// * Err(CarReaderError::Truncated) - The file ends in the middle of the block (see [CarReader::finish])
+    pub fn read_block_chunk(&mut self) -> Result<Option<&[u8]>, CarReaderError> {
+        if self.block_remaining == 0 {
+            return Ok(None);
+        }
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
+        let length = self.buffered().len().min(self.block_remaining);
+        if length == 0 {
+            if self.end.is_some_and(|end| self.start >= end) {
+                trace_event!(
+                    WIRE_V1,
+                    offset = self.start,
+                    missing_bytes = self.block_remaining,
+                    "Truncated file"
+                );
+                return Err(CarReaderError::Truncated {
+                    missing_bytes: self.block_remaining,
+                });
+            }
+            return Err(self.insufficient_data(self.block_remaining.min(BLOCK_CHUNK_HINT)));
+        }
+        // Like read_section_ref, only the cursor moves while the chunk is borrowed
+        let cursor = self.cursor;
+        self.cursor = cursor.saturating_add(length);
+        self.start = self.start.saturating_add(length);
+        self.block_remaining = self.block_remaining.saturating_sub(length);
+        Ok(self.storage().get(cursor..self.cursor))
+    }
+
+    /// Number of bytes of the block not read yet, while a section is read in chunks (see [CarReader::begin_section])
+    pub fn block_remaining(&self) -> usize {
+        self.block_remaining
+    }
+
+    /// Skip the rest of the block read in chunks, if any
+    fn skip_block_remaining(&mut self) {
+        if self.block_remaining > 0 {
+            // Consuming past the buffered bytes moves the reader after the block not received yet
+            self.consume(self.block_remaining);
+            self.block_remaining = 0;
+        }
+    }
+
+    /// Skip the corrupt bytes at the current position, up to the next plausible section
+    ///
+    /// When a section cannot be read (e.g. [CarReaderError::InvalidSectionFormat]), the reader stays on it. This
+    /// method scans the following bytes for the start of a plausible section: a length within the [Limits], a
+    /// valid CID, and a section followed by another plausible section header (or ending the received bytes).
+    /// The reader is then positioned on it, so that the readable sections after a damaged area can be salvaged.
+    ///
+    /// The scan may need more data: the skipped bytes are consumed as they are scanned, so it must be called
+    /// again (after receiving the requested bytes) until it returns the skipped range. Without a plausible section
+    /// until the end of the file declared with [CarReader::finish], everything up to it is skipped. Otherwise, as
+    /// with [CarReader::read_section], more data is requested forever.
+    ///
+    /// # Returns
+    ///
+    /// * Ok(Range<u64>) - The range of skipped bytes, the reader being positioned on its end
+    /// * Err(CarReaderError::InsufficientData) - More bytes are needed to go on with the scan
+    /// * Err(CarReaderError::PreconditionNotMet) - The header is not parsed yet
+    pub fn resync(&mut self) -> Result<Range<u64>, CarReaderError> {
+        self.resync_within(None)
+    }
+
+    /// Resynchronize like [CarReader::resync], with sections ending at most at the given offset
+    ///
+    /// Once the scan reaches the end without finding a plausible section, everything up to it is skipped.
+    pub(crate) fn resync_within(
+        &mut self,
+        end: Option<usize>,
+    ) -> Result<Range<u64>, CarReaderError> {
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
+        if !self.has_header() {
+            return Err(CarReaderError::PreconditionNotMet);
+        }
+        let end = end.into_iter().chain(self.end).min();
+        // On the first call, the current position is the corrupt section: the scan starts at the next byte
+        let first = usize::from(self.resync_start.is_none());
+        let resync_start = *self.resync_start.get_or_insert(self.start);
+
+        let buffered = self.buffered();
+        let mut outcome = None;
+        for skip in first..buffered.len() {
+            let remaining = end.map(|end| end.saturating_sub(self.start.saturating_add(skip)));
+            let candidate = buffered.get(skip..).unwrap_or_default();
+            match check_candidate(candidate, &self.limits, remaining) {
+                Candidate::Implausible => continue,
+                candidate => {
+                    outcome = Some((skip, candidate));
+                    break;
+                }
+            }
+        }
+        let scanned = buffered.len().max(first);
+        match outcome {
+            Some((skip, Candidate::Plausible)) => {
+                self.consume(skip);
+                self.resync_start = None;
+                trace_event!(
+                    WIRE_V1,
+                    from = resync_start,
+                    to = self.start,
+                    "Resynchronized on a plausible section"
+                );
+                Ok(resync_start as u64..self.start as u64)
+            }
+            Some((skip, Candidate::Unknown(missing))) => {
+                self.consume(skip);
+                Err(self.insufficient_data(missing))
+            }
+            _ => match end {
+                // Nothing plausible until the end: everything is skipped
+                Some(end) if self.start.saturating_add(scanned) >= end => {
+                    self.clear_buffer();
+                    self.start = end;
+                    Ok(resync_start as u64..end as u64)
+                }
+                _ => {
+                    self.consume(scanned);
+                    Err(self.insufficient_data(SKIP_CID_HINT))
+                }
+            },
+        }
+    }
+
     /// Find and return the section with the given CID
     ///
     /// This method will read through sections until it finds the one with the specified CID.
@@ -208,34 +974,63 @@ impl CarReader {
     /// seek to the first section before calling this method. Otherwise, it will start searching
     /// from the current position, which may lead to missing the desired section.
     pub fn find_section(&mut self, cid: &RawCid) -> Result<LocatableSection, CarReaderError> {
+        self.find_section_matching(|section_cid| section_cid == cid)
+    }
+
+    /// Find and return the first section whose CID has the given multihash digest
+    ///
+    /// This is the lookup used by the CAR v2 indexes, which key their entries by digest rather than CID:
+    /// sections whose CIDs only differ by their codec (or version) are matched alike. The digest is compared
+    /// in place, without allocating.
+    ///
+    /// # Arguments
+    /// * `digest` - The raw multihash digest of the section to find
+    ///
+    /// # Returns
+    ///
+    /// * Ok(LocatableSection) - The first section with the specified digest
+    /// * Err(CarReaderError) - Error occurred during searching
+    ///
+    /// Precondition and positioning are the same as [CarReader::find_section].
+    pub fn find_section_by_digest(
+        &mut self,
+        digest: &[u8],
+    ) -> Result<LocatableSection, CarReaderError> {
+        self.find_section_matching(|section_cid| section_cid.digest_eq(digest))
+    }
+
+    /// Read through the sections until one has a CID accepted by the predicate, and return it
+    fn find_section_matching(
+        &mut self,
+        matches: impl Fn(&RawCid) -> bool,
+    ) -> Result<LocatableSection, CarReaderError> {
+        self.skip_block_remaining();
+        #[cfg(feature = "bytes")]
+        self.promote_pending();
         // Header must be parsed before searching sections
         if !self.has_header() {
             return Err(CarReaderError::PreconditionNotMet);
         }
 
         loop {
-            match Section::try_read_header_bytes(&self.data) {
+            match Section::try_read_header_bytes_with(
+                self.buffered(),
+                &self.limits,
+                self.cid_parsing,
+            ) {
                 Ok((section, section_size)) => {
                     // Check if the CID matches
-                    if section.cid() == cid {
+                    if matches(section.cid()) {
                         // CID matches, now read the full section
                         return self.read_section();
                     } else {
                         // CID does not match, continue searching
-                        if self.data.len() <= section_size {
-                            self.data.clear();
-                        } else {
-                            self.data.drain(0..section_size);
-                        }
-                        self.start += section_size;
+                        self.consume(section_size);
                     }
                 }
                 Err(SectionFormatError::InsufficientData) => {
                     // Not enough data to parse a full section
-                    return Err(CarReaderError::InsufficientData(
-                        self.start + self.data.len(),
-                        0,
-                    ));
+                    return Err(self.insufficient_data(self.missing_section_bytes()));
                 }
                 Err(err) => {
                     // Some other error occurred during section parsing
@@ -246,6 +1041,61 @@ impl CarReader {
     }
 }
 
+/// Outcome of the check of a resynchronization candidate, see [CarReader::resync]
+enum Candidate {
+    /// A plausible section starts here
+    Plausible,
+    /// No section can start here
+    Implausible,
+    /// More bytes are needed to decide (at least this number of bytes)
+    Unknown(usize),
+}
+
+/// Check whether a plausible section starts at the beginning of the bytes
+///
+/// `remaining` is the number of bytes until the end of the sections, if known: the section (and the header of
+/// the next one) must not cross it.
+fn check_candidate(bytes: &[u8], limits: &Limits, remaining: Option<usize>) -> Candidate {
+    let remaining = remaining.unwrap_or(usize::MAX);
+    let size = match candidate_header(bytes, limits, remaining) {
+        Ok(size) => size,
+        Err(candidate) => return candidate,
+    };
+    if size == remaining {
+        // The section is the last one
+        return Candidate::Plausible;
+    }
+    // The length must be consistent: the section is followed by another section header
+    match bytes.get(size..) {
+        None => Candidate::Unknown(
+            size.saturating_sub(bytes.len())
+                .saturating_add(SKIP_CID_HINT),
+        ),
+        Some([]) => Candidate::Plausible,
+        Some(next) => match candidate_header(next, limits, remaining.saturating_sub(size)) {
+            Ok(_) => Candidate::Plausible,
+            Err(candidate) => candidate,
+        },
+    }
+}
+
+/// Read the length and CID of a candidate section, returning the section size
+fn candidate_header(bytes: &[u8], limits: &Limits, remaining: usize) -> Result<usize, Candidate> {
+    match Section::try_read_header_bytes_with(bytes, limits, CidParsing::Strict) {
+        Ok((_, size)) if size <= remaining => Ok(size),
+        // More bytes only help if the CID can still fit in the section, before the end of the sections
+        Err(SectionFormatError::InsufficientData)
+            if bytes.len() < remaining
+                && UnsignedVarint::decode(bytes).is_none_or(|(length, varint_size)| {
+                    bytes.len() < varint_size.saturating_add(length.0 as usize)
+                }) =>
+        {
+            Err(Candidate::Unknown(SKIP_CID_HINT))
+        }
+        _ => Err(Candidate::Implausible),
+    }
+}
+
 /// Errors related to CarReader operations
 #[derive(thiserror::Error, Debug)]
 pub enum CarReaderError {
@@ -254,6 +1104,11 @@ pub enum CarReaderError {
     InvalidFormat,
     #[error("Invalid header format")]
     InvalidHeader(ciborium::de::Error<std::io::Error>),
+    /// The header is larger than the limits, see [Limits::max_header_size]
+    ///
+    /// Checked as soon as its length prefix is read, before buffering it.
+    #[error("Header too large: {0} bytes")]
+    HeaderTooLarge(u64),
     #[error("Invalid CAR version, expected 1, got {0}")]
     InvalidVersion(usize),
     #[error("Invalid section format")]
@@ -265,7 +1120,15 @@ pub enum CarReaderError {
     ///
     /// # Arguments
     /// * usize - Need to read from this offset
-    /// * usize - Hint length of data to read (if known, otherwise 0)
+    /// * usize - Hint length of data to read (if known, otherwise 0), never below the configured minimum read hint
     #[error("Insufficient data to proceed")]
     InsufficientData(usize, usize),
+    /// No more sections, the end of the file is reached at a section boundary (see [CarReader::finish])
+    #[error("No more sections available in the CAR file")]
+    EndOfSections,
+    /// The file ends in the middle of the header or of a section (see [CarReader::finish])
+    ///
+    /// `missing_bytes` is the number of bytes missing to complete it, or 1 if its length is not even known.
+    #[error("Truncated CAR file, {missing_bytes} bytes missing")]
+    Truncated { missing_bytes: usize },
 }
@@ -1,5 +1,12 @@
-use crate::wire::cid::RawCid;
-use crate::wire::v1::{CarHeader, Section, SectionLocation};
+use std::collections::HashMap;
+
+use crate::trace::trace_event;
+use crate::wire::cid::{CidParsing, RawCid};
+use crate::wire::limits::Limits;
+use crate::wire::v1::dedup::{DedupSet, DedupTracker, WriteDedup};
+use crate::wire::v1::links::{DanglingLink, LinkTracker, LinkValidation, describe_dangling_links};
+use crate::wire::v1::placement::{self, PlacedSection, Placement};
+use crate::wire::v1::{CarHeader, Section, SectionLocation, SectionRef, WriteManifest};
 use crate::wire::varint::UnsignedVarint;
 
 /// CAR v1 writer
@@ -13,18 +20,44 @@ pub struct CarWriter {
     ///
     /// The offset does not take into account the current data buffer, which is only flushed to the underlying sink when `flush` is called.
     offset: u64,
+    /// Size limits applied to the written sections
+    limits: Limits,
+    /// Link validation mode, written CIDs and unresolved links (only maintained once link validation is enabled)
+    links: Option<Box<LinkTracker>>,
+    /// Written sections (only recorded once the manifest recording is enabled)
+    manifest: Option<Box<WriteManifest>>,
+    /// Written sections and suppressed duplicates (only maintained once duplicate suppression is enabled)
+    dedup: Option<Box<DedupTracker>>,
+    /// Section whose block is being written in chunks, see [CarWriter::begin_section]
+    streamed: Option<Box<StreamedSection>>,
+    /// Header of the CAR file, kept for the manifest (boxed, as it is seldom used after the start)
+    header: Box<CarHeader>,
+}
+
+/// A section whose block is being written in chunks
+#[derive(Debug, Clone)]
+struct StreamedSection {
+    /// CID of the section
+    cid: RawCid,
+    /// Location of the whole section
+    location: SectionLocation,
+    /// Declared length of the block data
+    block_length: u64,
+    /// Length of the block data written so far
+    written: u64,
 }
 
+/// Minimum size of the internal buffer of a [CarWriter], see [CarWriter::with_buffer_size]
+pub const MIN_BUFFER_SIZE: usize = 256;
+
 impl CarWriter {
     /// Internal method to write the header to the data buffer
-    fn write_header(&mut self, header: CarHeader) {
-        // Serialize the header using CBOR and write it to the data buffer
-        ciborium::ser::into_writer(&header, &mut self.data)
-            .expect("Failed to serialize CAR header -- it is a bug if this happens");
-        // The header is prefixed by a varint-encoded length, so we need to insert that at the beginning of the data buffer
-        let header_length = self.data.len() as u64;
-        let header_length_varint = UnsignedVarint(header_length).encode();
-        self.data.splice(0..0, header_length_varint);
+    fn write_header(&mut self) {
+        // Serialize the header in canonical DAG-CBOR, prefixed by its varint-encoded length
+        let header = self.header.encode();
+        self.data
+            .extend_from_slice(&UnsignedVarint(header.len() as u64).encode());
+        self.data.extend_from_slice(&header);
     }
 }
 
@@ -54,39 +87,563 @@ impl CarWriter {
     ///
     /// Reasonably, the buffer size should be larger than the size of the biggest section you expect to write (CID length + block size + a bit more),
     /// otherwise you might run into issues where a single section cannot fit in the buffer and causes an error.
-    /// You should not go below 256 bytes for the buffer size, as the header itself can be around that size depending on the number of roots.
+    /// Buffer sizes below 256 bytes ([MIN_BUFFER_SIZE]) are raised to it, as the header itself can be around that size
+    /// depending on the number of roots.
     ///
     /// See [CarWriter::new] for more details on the expected usage of the CarWriter and the roots.
     pub fn with_buffer_size(roots: Vec<RawCid>, buffer_size: usize) -> Self {
-        debug_assert!(
-            buffer_size > 256,
-            "Buffer size must be greater than 256 bytes to accommodate the header"
-        );
+        Self::from_header(CarHeader::new(roots), buffer_size)
+    }
+
+    /// Create a new CarWriter writing the given header, with the default buffer size
+    ///
+    /// Unlike [CarWriter::new], the extra entries of the header are written too: a header read from another CAR file
+    /// is kept as it is (in canonical form).
+    pub fn with_header(header: CarHeader) -> Self {
+        Self::from_header(header, 16 * 1024 * 1024)
+    }
+
+    /// Resume the writing of a CAR v1 file, whose header (and possibly some sections) is already written
+    ///
+    /// The header is not written again: the sections are appended to the existing file, and their locations continue
+    /// from `current_offset`, the end of the last complete section (or of the header). After a crash, the partial
+    /// section at the end of the file must be dropped first, e.g. with the position of a [ReaderCheckpoint] saved
+    /// once the complete sections are read.
+    ///
+    /// The sections written before are unknown to the resumed writer: they are not in its manifest, and the links
+    /// to their blocks are reported as dangling by the link validation.
+    ///
+    /// ## Examples
+    /// ```
+    /// use navira_car::CarReader;
+    /// use navira_car::wire::v1::CarWriter;
+    ///
+    /// let car_bytes = include_bytes!("../../res/carv1-basic.car");
+    /// // The previous run crashed in the middle of the fourth section
+    /// let mut file = car_bytes[..400].to_vec();
+    /// let mut reader = CarReader::from_bytes(&file).unwrap();
+    /// let mut remaining = CarReader::from_bytes(car_bytes).unwrap();
+    /// while reader.read_section().is_ok() {
+    ///     remaining.read_section().unwrap();
+    /// }
+    /// let checkpoint = reader.save_state().unwrap();
+    /// file.truncate(checkpoint.position() as usize);
+    ///
+    /// let mut writer = CarWriter::resume(checkpoint.header().0.clone(), checkpoint.position());
+    /// while let Ok(section) = remaining.read_section() {
+    ///     let location = writer.write_section(&section.section).unwrap();
+    ///     assert_eq!(location, section.location);
+    /// }
+    /// let mut buf = vec![0u8; 1024];
+    /// let length = writer.send_data(&mut buf);
+    /// file.extend_from_slice(&buf[..length]);
+    /// assert_eq!(file, car_bytes);
+    /// ```
+    ///
+    /// [ReaderCheckpoint]: crate::ReaderCheckpoint
+    pub fn resume(header: CarHeader, current_offset: u64) -> Self {
+        let mut writer = Self::from_header(header, 16 * 1024 * 1024);
+        // Drop the header written by the constructor, it is already in the file
+        writer.data.clear();
+        writer.offset = current_offset;
+        writer
+    }
+
+    /// Create a new CarWriter for the header, with the given buffer size
+    fn from_header(header: CarHeader, buffer_size: usize) -> Self {
         let mut writer = Self {
-            data: Vec::with_capacity(buffer_size),
+            data: Vec::with_capacity(buffer_size.max(MIN_BUFFER_SIZE)),
             offset: 0,
+            limits: Limits::default(),
+            links: None,
+            manifest: None,
+            dedup: None,
+            streamed: None,
+            header: Box::new(header),
         };
-        writer.write_header(CarHeader::new(roots));
+        writer.write_header();
         writer
     }
 
+    /// Get the size limits applied to the written sections
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Set the size limits applied to the written sections
+    ///
+    /// Use the same limits as the readers of the resulting CAR file, so that every written section can be read back.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Get the link validation mode
+    pub fn link_validation(&self) -> LinkValidation {
+        self.links
+            .as_ref()
+            .map_or(LinkValidation::Off, |links| links.mode)
+    }
+
+    /// Set the link validation mode
+    ///
+    /// When enabled, the links of every written dag-pb and dag-cbor block are tracked, so that the links to blocks
+    /// never written can be reported by [CarWriter::dangling_links] and [CarWriter::check_links].
+    /// It must be set before writing the first section, as the previously written sections are not tracked.
+    pub fn set_link_validation(&mut self, link_validation: LinkValidation) {
+        match &mut self.links {
+            Some(links) => links.mode = link_validation,
+            None if link_validation != LinkValidation::Off => {
+                self.links = Some(Box::new(LinkTracker::new(link_validation)))
+            }
+            None => {}
+        }
+    }
+
+    /// Links to blocks which have not been written (yet), in the order of their first reference
+    ///
+    /// Always empty when link validation is [LinkValidation::Off].
+    pub fn dangling_links(&self) -> Vec<DanglingLink> {
+        match &self.links {
+            Some(links) if links.mode != LinkValidation::Off => links.dangling_links(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Check the links of the written sections, once every section has been written
+    ///
+    /// ## Returns
+    /// - `Ok(dangling)` with the dangling links, always empty unless in [LinkValidation::Warn] mode.
+    /// - `Err(CarWriterError::DanglingLinks)` in [LinkValidation::Strict] mode, if any link is dangling.
+    pub fn check_links(&self) -> Result<Vec<DanglingLink>, CarWriterError> {
+        let dangling = self.dangling_links();
+        if self.link_validation() == LinkValidation::Strict && !dangling.is_empty() {
+            return Err(CarWriterError::DanglingLinks(dangling));
+        }
+        Ok(dangling)
+    }
+
+    /// Get the duplicate suppression mode
+    pub fn dedup(&self) -> WriteDedup {
+        self.dedup
+            .as_ref()
+            .map_or(WriteDedup::Off, |dedup| dedup.mode)
+    }
+
+    /// Set the duplicate suppression mode
+    ///
+    /// When enabled, the written sections are remembered, and writing a section already written (by CID or by
+    /// multihash) is silently skipped: [CarWriter::write_section] and [CarWriter::write_raw_section] return the
+    /// location of the first section instead. The sections are remembered in an exact map, see
+    /// [CarWriter::set_dedup_set] to bound its memory. It must be set before writing the first section, as the
+    /// previously written sections are not remembered.
+    pub fn set_dedup(&mut self, dedup: WriteDedup) {
+        match &mut self.dedup {
+            Some(tracker) => tracker.mode = dedup,
+            None if dedup != WriteDedup::Off => {
+                self.dedup = Some(Box::new(DedupTracker::new(dedup, Box::new(HashMap::new()))))
+            }
+            None => {}
+        }
+    }
+
+    /// Set the duplicate suppression mode, remembering the written sections in the given set
+    ///
+    /// See [CarWriter::set_dedup] and [DedupSet]. The set replaces the one of the previous mode, if any.
+    pub fn set_dedup_set(&mut self, dedup: WriteDedup, set: Box<dyn DedupSet>) {
+        let skipped = self.skipped_duplicates();
+        let mut tracker = DedupTracker::new(dedup, set);
+        tracker.skipped = skipped;
+        self.dedup = Some(Box::new(tracker));
+    }
+
+    /// Number of writes suppressed as duplicates so far
+    pub fn skipped_duplicates(&self) -> u64 {
+        self.dedup.as_ref().map_or(0, |dedup| dedup.skipped)
+    }
+
+    /// Location of the section already written with the CID of this one, if duplicates are suppressed
+    fn find_duplicate(&mut self, cid: &RawCid) -> Option<SectionLocation> {
+        let location = self.dedup.as_mut()?.find(cid)?;
+        trace_event!(
+            WIRE_V1,
+            offset = location.offset,
+            cid = %cid.to_hex(),
+            "Duplicate section skipped"
+        );
+        Some(location)
+    }
+
+    /// Is the manifest recording enabled?
+    pub fn record_manifest(&self) -> bool {
+        self.manifest.is_some()
+    }
+
+    /// Enable or disable the manifest recording
+    ///
+    /// When enabled, the CID and location of every written section are recorded, and returned by
+    /// [CarWriter::finish_with_manifest]. It must be enabled before writing the first section, as the previously
+    /// written sections are not recorded. Disabling it drops the recorded sections.
+    pub fn set_record_manifest(&mut self, record_manifest: bool) {
+        match (record_manifest, &self.manifest) {
+            (true, None) => {
+                self.manifest = Some(Box::new(WriteManifest::new(CarHeader::clone(&self.header))))
+            }
+            (false, Some(_)) => self.manifest = None,
+            _ => {}
+        }
+    }
+
+    /// Take the recorded manifest, disabling the recording
+    pub(crate) fn take_manifest(&mut self) -> Option<WriteManifest> {
+        self.manifest.take().map(|manifest| *manifest)
+    }
+
+    /// Finish the CAR stream, and return the manifest of the written file
+    ///
+    /// ## Returns
+    /// - `Ok(WriteManifest)` with the header and the locations of all the written sections.
+    /// - `Err(Self)` if there is still data to send (flush it first), if a section is still in progress (see
+    ///   [CarWriter::begin_section]), or if the manifest recording is not enabled.
+    pub fn finish_with_manifest(self) -> Result<WriteManifest, Self> {
+        if self.has_data_to_send() || self.section_in_progress() {
+            return Err(self);
+        }
+        match self.manifest {
+            Some(manifest) => Ok(*manifest),
+            None => Err(self),
+        }
+    }
+
     /// Write a section to the CAR stream.
     ///
     /// This method will serialize the section and append it to the current CAR stream.
     /// However, it does not actually write to the underlying sink until `send_data` is called.
+    ///
+    /// Check that the section is within the configured [Limits], without writing it
+    ///
+    /// ## Returns
+    /// - `Ok(())` if the section can be written.
+    /// - `Err(CarWriterError::SectionTooLarge)` if the section exceeds the maximum section size.
+    /// - `Err(CarWriterError::BlockTooLarge)` if its block exceeds the maximum block size.
+    pub fn check_limits(&self, section: &Section) -> Result<(), CarWriterError> {
+        self.check_lengths(section.length(), section.block().len())
+    }
+
+    /// Check that a section can fit in the buffer, once flushed
+    ///
+    /// ## Returns
+    /// - `Ok(())` if the section fits in the empty buffer.
+    /// - `Err(CarWriterError::SectionExceedsBuffer)` if the section is larger than the whole buffer.
+    pub fn check_buffer_capacity(&self, section: &Section) -> Result<(), CarWriterError> {
+        let length = section.total_length();
+        if length > self.data.capacity() {
+            return Err(CarWriterError::SectionExceedsBuffer {
+                length,
+                capacity: self.data.capacity(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the length of a section (CID and block data) and of its block against the configured [Limits]
+    fn check_lengths(&self, length: u64, block_length: usize) -> Result<(), CarWriterError> {
+        if !self.limits.allows_section(length) {
+            return Err(CarWriterError::SectionTooLarge {
+                length,
+                max: self.limits.max_section_size,
+            });
+        }
+        if !self.limits.allows_block(block_length) {
+            return Err(CarWriterError::BlockTooLarge {
+                length: block_length,
+                max: self.limits.max_block_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Sections exceeding the configured [Limits] are rejected with [CarWriterError::SectionTooLarge] or
+    /// [CarWriterError::BlockTooLarge].
+    ///
+    /// When duplicates are suppressed (see [CarWriter::set_dedup]), a section already written is skipped, and the
+    /// location of the first one is returned. No section can be written while another one is in progress (see
+    /// [CarWriter::begin_section]).
     pub fn write_section(&mut self, section: &Section) -> Result<SectionLocation, CarWriterError> {
-        let data_pos = self.data.len();
-        let section_size = section.total_length();
-        if data_pos + section_size > self.data.capacity() {
+        self.check_no_section_in_progress()?;
+        if let Some(location) = self.find_duplicate(section.cid()) {
+            return Ok(location);
+        }
+        self.write_new_section(section)
+    }
+
+    /// Write a section, without looking for a previous one with the same CID
+    fn write_new_section(&mut self, section: &Section) -> Result<SectionLocation, CarWriterError> {
+        self.check_limits(section)?;
+        if section.total_length() > self.free_space() {
             return Err(CarWriterError::BufferFull);
         }
         let section_bytes = section.to_bytes();
-        self.data.extend_from_slice(&section_bytes);
+        Ok(self.append_section(section.cid(), section.block().data(), &section_bytes))
+    }
+
+    /// Write the exact bytes of a section (length prefix, CID and block data) to the CAR stream.
+    ///
+    /// This is the pass-through counterpart of [CarWriter::write_section], for sections copied from another
+    /// archive (see [SectionRef::raw_bytes](super::SectionRef::raw_bytes)): the bytes are written as they are,
+    /// without re-encoding the length prefix. Only the length prefix and the CID are parsed, to check that the
+    /// bytes are a single section of the given CID.
+    ///
+    /// ## Returns
+    /// - `Ok(location)` with the location of the written section.
+    /// - `Err(CarWriterError::InvalidRawSection)` if the bytes are not exactly one section, of the given CID.
+    /// - `Err(CarWriterError::SectionTooLarge)` or `Err(CarWriterError::BlockTooLarge)` if the section exceeds the
+    ///   configured [Limits].
+    /// - `Err(CarWriterError::BufferFull)` if the section does not fit in the buffer, flush and retry.
+    ///
+    /// Like [CarWriter::write_section], a section already written is skipped when duplicates are suppressed. The
+    /// bytes are still checked first.
+    pub fn write_raw_section(
+        &mut self,
+        bytes: &[u8],
+        cid: &RawCid,
+    ) -> Result<SectionLocation, CarWriterError> {
+        self.check_no_section_in_progress()?;
+        // The limits are checked afterwards, to report them like write_section
+        let unlimited = Limits::new()
+            .with_max_block_size(usize::MAX)
+            .with_max_section_size(usize::MAX);
+        let section = match SectionRef::try_read_bytes_with(bytes, &unlimited, CidParsing::Lenient)
+        {
+            Ok((section, size)) if size == bytes.len() && section.cid() == cid => section,
+            _ => return Err(CarWriterError::InvalidRawSection(cid.clone())),
+        };
+        self.check_lengths(section.length(), section.block().len())?;
+        if let Some(location) = self.find_duplicate(cid) {
+            return Ok(location);
+        }
+        if bytes.len() > self.free_space() {
+            return Err(CarWriterError::BufferFull);
+        }
+        Ok(self.append_section(cid, section.block().data(), bytes))
+    }
+
+    /// Append the bytes of a section to the buffer, recording its links and its location
+    fn append_section(&mut self, cid: &RawCid, block: &[u8], bytes: &[u8]) -> SectionLocation {
         let section_location = SectionLocation {
-            offset: self.offset + data_pos as u64,
-            length: section_bytes.len() as u64,
+            offset: self.position(),
+            length: bytes.len() as u64,
+        };
+        self.data.extend_from_slice(bytes);
+        if let Some(links) = &mut self.links
+            && links.mode != LinkValidation::Off
+        {
+            links.record(cid, block);
+        }
+        self.record_section(cid, &section_location);
+        section_location
+    }
+
+    /// Record a written section in the manifest and the duplicate tracker
+    fn record_section(&mut self, cid: &RawCid, section_location: &SectionLocation) {
+        if let Some(manifest) = &mut self.manifest {
+            manifest
+                .sections
+                .push((cid.clone(), section_location.clone()));
+        }
+        if let Some(dedup) = &mut self.dedup {
+            dedup.record(cid, section_location);
+        }
+        trace_event!(
+            WIRE_V1,
+            offset = section_location.offset,
+            length = section_location.length,
+            cid = %cid.to_hex(),
+            "Section written"
+        );
+    }
+
+    /// Start a section whose block is written in chunks, for blocks too large to be held in memory
+    ///
+    /// The length prefix and the CID are written right away. The block data must then be given with
+    /// [CarWriter::write_block_chunk], sending the buffer with [CarWriter::send_data] in between as needed, and the
+    /// section completed with [CarWriter::end_section]. No other section can be written in the meantime.
+    ///
+    /// The section is written even when duplicates are suppressed (see [CarWriter::set_dedup]), as the block may
+    /// already be partially streamed from its source. The links of its block are not tracked by the link
+    /// validation, but the links to its CID are resolved.
+    ///
+    /// ## Returns
+    /// - `Ok(location)` with the location of the whole section, once completed.
+    /// - `Err(CarWriterError::SectionInProgress)` if another section is in progress.
+    /// - `Err(CarWriterError::SectionTooLarge)` or `Err(CarWriterError::BlockTooLarge)` if the section exceeds the
+    ///   configured [Limits].
+    /// - `Err(CarWriterError::BufferFull)` if the length prefix and the CID do not fit in the buffer, flush and retry.
+    pub fn begin_section(
+        &mut self,
+        cid: &RawCid,
+        block_length: usize,
+    ) -> Result<SectionLocation, CarWriterError> {
+        self.check_no_section_in_progress()?;
+        let length = cid.bytes().len().saturating_add(block_length) as u64;
+        self.check_lengths(length, block_length)?;
+        let mut head = UnsignedVarint(length).encode();
+        head.extend_from_slice(cid.bytes());
+        if head.len() > self.free_space() {
+            return Err(CarWriterError::BufferFull);
+        }
+        let location = SectionLocation {
+            offset: self.position(),
+            length: (head.len() as u64).saturating_add(block_length as u64),
         };
-        Ok(section_location)
+        self.data.extend_from_slice(&head);
+        self.streamed = Some(Box::new(StreamedSection {
+            cid: cid.clone(),
+            location: location.clone(),
+            block_length: block_length as u64,
+            written: 0,
+        }));
+        Ok(location)
+    }
+
+    /// Write the next chunk of the block of the section in progress (see [CarWriter::begin_section])
+    ///
+    /// Only the part of the chunk fitting in the buffer is written: the rest must be given again once the buffer
+    /// is sent with [CarWriter::send_data].
+    ///
+    /// ## Returns
+    /// - `Ok(written)` with the number of bytes of the chunk written (0 if the buffer is full).
+    /// - `Err(CarWriterError::NoSectionInProgress)` if no section is in progress.
+    /// - `Err(CarWriterError::BlockLengthMismatch)` if the chunk goes past the declared block length, nothing is
+    ///   written then.
+    pub fn write_block_chunk(&mut self, chunk: &[u8]) -> Result<usize, CarWriterError> {
+        let streamed = self
+            .streamed
+            .as_mut()
+            .ok_or(CarWriterError::NoSectionInProgress)?;
+        let written = streamed.written.saturating_add(chunk.len() as u64);
+        if written > streamed.block_length {
+            return Err(CarWriterError::BlockLengthMismatch {
+                declared: streamed.block_length,
+                written,
+            });
+        }
+        let length = chunk
+            .len()
+            .min(self.data.capacity().saturating_sub(self.data.len()));
+        self.data
+            .extend_from_slice(chunk.get(..length).unwrap_or_default());
+        streamed.written = streamed.written.saturating_add(length as u64);
+        Ok(length)
+    }
+
+    /// Complete the section in progress (see [CarWriter::begin_section]), once its whole block is written
+    ///
+    /// ## Returns
+    /// - `Ok(location)` with the location of the section.
+    /// - `Err(CarWriterError::NoSectionInProgress)` if no section is in progress.
+    /// - `Err(CarWriterError::BlockLengthMismatch)` if the block is shorter than declared so far, the section then
+    ///   stays in progress.
+    pub fn end_section(&mut self) -> Result<SectionLocation, CarWriterError> {
+        let streamed = self
+            .streamed
+            .take()
+            .ok_or(CarWriterError::NoSectionInProgress)?;
+        if streamed.written != streamed.block_length {
+            let error = CarWriterError::BlockLengthMismatch {
+                declared: streamed.block_length,
+                written: streamed.written,
+            };
+            self.streamed = Some(streamed);
+            return Err(error);
+        }
+        if let Some(links) = &mut self.links
+            && links.mode != LinkValidation::Off
+        {
+            links.record_cid(&streamed.cid);
+        }
+        self.record_section(&streamed.cid, &streamed.location);
+        Ok(streamed.location)
+    }
+
+    /// Is a section in progress, started with [CarWriter::begin_section] and not yet completed?
+    pub fn section_in_progress(&self) -> bool {
+        self.streamed.is_some()
+    }
+
+    /// Fail with [CarWriterError::SectionInProgress] if a section is in progress
+    fn check_no_section_in_progress(&self) -> Result<(), CarWriterError> {
+        match self.streamed {
+            Some(_) => Err(CarWriterError::SectionInProgress),
+            None => Ok(()),
+        }
+    }
+
+    /// Write a section to the CAR stream, at an offset satisfying the given placement constraint.
+    ///
+    /// The gap between the current end of the stream and the requested offset is filled with padding sections
+    /// (see [Placement] and [MAX_FILLER_DIGEST](super::MAX_FILLER_DIGEST)), which are reported in the achieved layout.
+    /// Either the padding and the section are all written, or nothing is. The section is written even when
+    /// duplicates are suppressed (see [CarWriter::set_dedup]), as its first location would not satisfy the constraint.
+    ///
+    /// ## Returns
+    /// - `Ok(PlacedSection)` with the location of the section and of the inserted padding.
+    /// - `Err(CarWriterError::UnsatisfiablePlacement)` if the constraint cannot be satisfied from the current position
+    ///   (the offset is already behind, or the gap is too small to be padded).
+    /// - `Err(CarWriterError::BufferFull)` if the padding and the section do not fit in the buffer, flush and retry.
+    pub fn write_section_placed(
+        &mut self,
+        section: &Section,
+        placement: Placement,
+    ) -> Result<PlacedSection, CarWriterError> {
+        self.check_no_section_in_progress()?;
+        self.check_limits(section)?;
+        let position = self.position();
+        let offset = placement::resolve_placement(placement, position).ok_or(
+            CarWriterError::UnsatisfiablePlacement {
+                placement,
+                position,
+            },
+        )?;
+        // The gap is checked against the buffer before building its padding, which could be arbitrarily large
+        let available = self.free_space() as u64;
+        let gap = offset.saturating_sub(position);
+        if gap.saturating_add(section.total_length() as u64) > available {
+            return Err(CarWriterError::BufferFull);
+        }
+        let padding =
+            placement::padding_sections(gap).ok_or(CarWriterError::UnsatisfiablePlacement {
+                placement,
+                position,
+            })?;
+
+        // Placed sections are always written, duplicates included (the padding sections are alike)
+        let padding = padding
+            .iter()
+            .map(|filler| self.write_new_section(filler))
+            .collect::<Result<Vec<_>, _>>()?;
+        let location = self.write_new_section(section)?;
+        if location.offset != offset {
+            return Err(CarWriterError::UnsatisfiablePlacement {
+                placement,
+                position,
+            });
+        }
+        Ok(PlacedSection { location, padding })
+    }
+
+    /// Current position in the output stream, where the next section will be written
+    pub fn position(&self) -> u64 {
+        self.offset.saturating_add(self.data.len() as u64)
+    }
+
+    /// Number of bytes which can still be written to the buffer before sending it
+    fn free_space(&self) -> usize {
+        self.data.capacity().saturating_sub(self.data.len())
+    }
+
+    /// Offset of the next byte to send, i.e. the end of the bytes sent so far
+    pub(crate) fn sent_offset(&self) -> u64 {
+        self.offset
     }
 
     /// Flush the current data buffer and return the bytes to be written to the underlying sink.
@@ -103,9 +660,13 @@ impl CarWriter {
     /// The number of bytes written to the buffer.
     pub fn send_data(&mut self, buf: &mut [u8]) -> usize {
         let bytes_to_send = self.data.len().min(buf.len());
-        buf[..bytes_to_send].copy_from_slice(&self.data[..bytes_to_send]);
+        if let (Some(out), Some(data)) =
+            (buf.get_mut(..bytes_to_send), self.data.get(..bytes_to_send))
+        {
+            out.copy_from_slice(data);
+        }
         self.data.drain(..bytes_to_send);
-        self.offset += bytes_to_send as u64;
+        self.offset = self.offset.saturating_add(bytes_to_send as u64);
         bytes_to_send
     }
 
@@ -126,6 +687,67 @@ pub enum CarWriterError {
     /// To resolve this, you can either flush the current buffer to the underlying sink to free up space or increase the buffer size when creating the CarWriter.
     #[error("Buffer is full, cannot write section")]
     BufferFull,
+    /// The section exceeds the configured size limits
+    ///
+    /// Such a section would be rejected by any reader using the same limits, so it is never written.
+    #[error("Section too large: {length} bytes (max: {max} bytes)")]
+    SectionTooLarge {
+        /// Length of the section (CID and block data)
+        length: u64,
+        /// Maximum section size allowed by the limits
+        max: usize,
+    },
+    /// The block of the section exceeds the configured size limits
+    ///
+    /// Like [CarWriterError::SectionTooLarge], such a section is never written.
+    #[error("Block too large: {length} bytes (max: {max} bytes)")]
+    BlockTooLarge {
+        /// Length of the block data
+        length: usize,
+        /// Maximum block size allowed by the limits
+        max: usize,
+    },
+    /// The section is larger than the whole buffer, so it can never be written, even once the buffer is flushed
+    ///
+    /// Only reported by [CarWriter::check_buffer_capacity], the writes report [CarWriterError::BufferFull].
+    #[error("Section of {length} bytes exceeds the buffer capacity ({capacity} bytes)")]
+    SectionExceedsBuffer {
+        /// Length of the section (length prefix, CID and block data)
+        length: usize,
+        /// Capacity of the buffer
+        capacity: usize,
+    },
+    /// The placement constraint of a section cannot be satisfied
+    ///
+    /// Sections are written sequentially, so a section cannot be placed before the current position,
+    /// nor after it by less than the smallest padding (see [Placement]).
+    #[error("Cannot place section ({placement:?}) from position {position}")]
+    UnsatisfiablePlacement {
+        /// The requested placement
+        placement: Placement,
+        /// Position of the writer when the placement was requested
+        position: u64,
+    },
+    /// The bytes given to [CarWriter::write_raw_section] are not exactly one section of the given CID
+    #[error("Invalid raw section for CID {0}")]
+    InvalidRawSection(RawCid),
+    /// Some links point to blocks which have not been written (in [LinkValidation::Strict] mode)
+    #[error("{}", describe_dangling_links(.0))]
+    DanglingLinks(Vec<DanglingLink>),
+    /// A section is in progress (see [CarWriter::begin_section]), it must be completed first
+    #[error("A section is still in progress")]
+    SectionInProgress,
+    /// No section is in progress, see [CarWriter::begin_section]
+    #[error("No section in progress")]
+    NoSectionInProgress,
+    /// The block data written does not match the block length declared by [CarWriter::begin_section]
+    #[error("Block length mismatch: {written} bytes written, {declared} bytes declared")]
+    BlockLengthMismatch {
+        /// Block length declared when the section was started
+        declared: u64,
+        /// Block length written (or that would be written)
+        written: u64,
+    },
 }
 
 #[cfg(test)]
@@ -176,6 +798,7 @@ mod tests {
                         section_to_write.push(section); // Put the section back to try writing it again after flushing
                         continue;
                     }
+                    Err(e) => panic!("Unexpected error: {:?}", e),
                 }
             }
         }
@@ -183,6 +806,325 @@ mod tests {
         assert_eq!(sink.len(), 182);
     }
 
+    #[test]
+    fn test_car_writer_section_too_large() {
+        let cid = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let mut writer = CarWriter::new(vec![cid.clone()]);
+        writer.set_limits(Limits::new().with_max_section_size(64));
+        let section = Section::new(cid.clone(), Block::new(vec![0; 64]));
+        assert!(matches!(
+            writer.write_section(&section),
+            Err(CarWriterError::SectionTooLarge {
+                length: 100,
+                max: 64
+            })
+        ));
+        let section = Section::new(cid, Block::new(vec![0; 28]));
+        assert!(writer.write_section(&section).is_ok());
+    }
+
+    #[test]
+    fn test_car_writer_link_validation() {
+        use crate::testdata::{CARV1_BASIC_SECTIONS, carv1_basic_sections};
+
+        let sections = carv1_basic_sections();
+        let write_all = |validation: LinkValidation, skip: Option<usize>| {
+            let mut writer = CarWriter::new(Vec::new());
+            writer.set_link_validation(validation);
+            for (i, section) in sections.iter().enumerate() {
+                if skip != Some(i) {
+                    writer.write_section(section).unwrap();
+                }
+            }
+            writer
+        };
+
+        // Complete DAG, parents are written before their children
+        assert!(
+            write_all(LinkValidation::Strict, None)
+                .check_links()
+                .unwrap()
+                .is_empty()
+        );
+
+        // Raw block "cccc", linked from the dag-pb block written before it
+        let cccc = CARV1_BASIC_SECTIONS[2].cid();
+        let dangling = vec![DanglingLink {
+            target: cccc,
+            referrer: CARV1_BASIC_SECTIONS[1].cid(),
+        }];
+        let writer = write_all(LinkValidation::Warn, Some(2));
+        assert_eq!(writer.check_links().unwrap(), dangling);
+        match write_all(LinkValidation::Strict, Some(2)).check_links() {
+            Err(CarWriterError::DanglingLinks(links)) => assert_eq!(links, dangling),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(
+            write_all(LinkValidation::Off, Some(2))
+                .check_links()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_car_writer_resume() {
+        let cid = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let header = CarHeader::new(vec![cid.clone()]);
+        let mut writer = CarWriter::resume(header, 1000);
+        // Nothing to send until a section is written, and the locations continue from the offset
+        assert!(!writer.has_data_to_send());
+        assert_eq!(writer.position(), 1000);
+        let section = Section::new(cid.clone(), Block::new(vec![1, 2, 3, 4]));
+        let location = writer.write_section(&section).unwrap();
+        assert_eq!(location.offset, 1000);
+        let placed = writer
+            .write_section_placed(&section, Placement::Aligned(4096))
+            .unwrap();
+        assert_eq!(placed.location.offset, 4096);
+        let mut sink = vec![0u8; 4096];
+        assert_eq!(writer.send_data(&mut sink) as u64, writer.position() - 1000);
+    }
+
+    #[test]
+    fn test_car_writer_dedup() {
+        use crate::wire::v1::BoundedDedupSet;
+
+        let v1 = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        // The CIDv0 of the same multihash
+        let v0 = RawCid::new(v1.bytes()[2..].to_vec());
+        let other = RawCid::from_hex(
+            "015512201111111111111111111111111111111111111111111111111111111111111111",
+        )
+        .unwrap();
+        let section = |cid: &RawCid| Section::new(cid.clone(), Block::new(vec![1, 2, 3, 4]));
+
+        let mut writer = CarWriter::new(vec![v1.clone()]);
+        writer.set_dedup(WriteDedup::Cid);
+        let first = writer.write_section(&section(&v1)).unwrap();
+        let position = writer.position();
+        assert_eq!(writer.write_section(&section(&v1)).unwrap(), first);
+        let raw = section(&v1).to_bytes();
+        assert_eq!(writer.write_raw_section(&raw, &v1).unwrap(), first);
+        assert_eq!(writer.position(), position);
+        assert_eq!(writer.skipped_duplicates(), 2);
+        // Another CID of the same block is written in CID mode
+        assert_ne!(writer.write_section(&section(&v0)).unwrap(), first);
+        // Placed sections are always written
+        let placed = writer
+            .write_section_placed(&section(&v1), Placement::Aligned(1024))
+            .unwrap();
+        assert_eq!(placed.location.offset, 1024);
+
+        let mut writer = CarWriter::new(vec![v1.clone()]);
+        writer.set_dedup(WriteDedup::Multihash);
+        let first = writer.write_section(&section(&v1)).unwrap();
+        assert_eq!(writer.write_section(&section(&v0)).unwrap(), first);
+        assert_eq!(writer.skipped_duplicates(), 1);
+
+        // A bounded set forgets the oldest sections, which are written again
+        let mut writer = CarWriter::new(vec![v1.clone()]);
+        writer.set_dedup_set(WriteDedup::Cid, Box::new(BoundedDedupSet::new(1)));
+        let first = writer.write_section(&section(&v1)).unwrap();
+        writer.write_section(&section(&other)).unwrap();
+        assert_ne!(writer.write_section(&section(&v1)).unwrap(), first);
+        assert_eq!(writer.skipped_duplicates(), 0);
+        assert!(writer.write_section(&section(&v1)).is_ok());
+        assert_eq!(writer.skipped_duplicates(), 1);
+    }
+
+    #[test]
+    fn test_car_writer_section_placed() {
+        use crate::wire::v1::CarReader;
+
+        let cid = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let mut writer = CarWriter::new(vec![cid.clone()]);
+        let first = Section::new(cid.clone(), Block::new(vec![1, 2, 3, 4]));
+        writer.write_section(&first).unwrap();
+        let manifest = Section::new(cid.clone(), Block::new(b"manifest".to_vec()));
+        let placed = writer
+            .write_section_placed(&manifest, Placement::At(1024))
+            .unwrap();
+        assert_eq!(placed.location.offset, 1024);
+        assert_eq!(
+            placed.location.offset - placed.padding_size(),
+            placed.padding[0].offset
+        );
+        assert!(matches!(
+            writer.write_section_placed(&first, Placement::At(1024)),
+            Err(CarWriterError::UnsatisfiablePlacement { position, .. }) if position == writer.position()
+        ));
+        // A gap larger than the buffer is rejected before building its padding
+        assert!(matches!(
+            writer.write_section_placed(&first, Placement::At(u64::MAX)),
+            Err(CarWriterError::BufferFull)
+        ));
+
+        let mut sink = vec![0u8; writer.position() as usize];
+        let written = writer.send_data(&mut sink);
+        assert_eq!(written, sink.len());
+
+        // The padding sections are regular sections for the readers
+        let mut reader = CarReader::new();
+        reader.receive_data(&sink, 0);
+        reader.read_header().unwrap();
+        let mut sections = Vec::new();
+        while let Ok(section) = reader.read_section() {
+            sections.push(section);
+        }
+        assert_eq!(sections.len(), placed.padding.len() + 2);
+        let last = sections.last().unwrap();
+        assert_eq!(last.location, placed.location);
+        assert_eq!(last.block().data(), b"manifest");
+    }
+
+    #[test]
+    fn test_car_writer_raw_section() {
+        use crate::testdata::{CARV1_BASIC, CARV1_BASIC_ROOTS};
+        use crate::wire::v1::CarReader;
+
+        // Copying all the raw sections gives back the same archive, byte for byte
+        let roots: Vec<_> = CARV1_BASIC_ROOTS
+            .iter()
+            .map(|root| RawCid::from_hex(root).unwrap())
+            .collect();
+        let mut writer = CarWriter::new(roots.clone());
+        let mut reader = CarReader::new();
+        reader.receive_data(CARV1_BASIC, 0);
+        reader.read_header().unwrap();
+        while let Ok((location, section)) = reader.read_section_ref() {
+            let raw = section.raw_bytes().unwrap();
+            assert_eq!(raw.len() as u64, location.length);
+            let written = writer.write_raw_section(raw, section.cid()).unwrap();
+            assert_eq!(written, location);
+        }
+        let mut sink = vec![0u8; CARV1_BASIC.len() + 1];
+        let written = writer.send_data(&mut sink);
+        assert_eq!(&sink[..written], CARV1_BASIC);
+
+        // A length prefix which is not minimally encoded is kept as is
+        let cid = roots[1].clone();
+        let mut raw = vec![0x80 | (cid.bytes().len() as u8 + 4), 0x00];
+        raw.extend_from_slice(cid.bytes());
+        raw.extend_from_slice(b"data");
+        let location = writer.write_raw_section(&raw, &cid).unwrap();
+        assert_eq!(location.length, raw.len() as u64);
+        let written = writer.send_data(&mut sink);
+        assert_eq!(&sink[..written], raw);
+
+        // Bytes which are not exactly one section of the CID
+        for (bytes, cid) in [
+            (&raw[..raw.len() - 1], &cid),
+            (&[raw.as_slice(), b"!"].concat(), &cid),
+            (&raw, &roots[0]),
+        ] {
+            assert!(matches!(
+                writer.write_raw_section(bytes, cid),
+                Err(CarWriterError::InvalidRawSection(_))
+            ));
+        }
+        writer.set_limits(Limits::new().with_max_block_size(3));
+        assert!(matches!(
+            writer.write_raw_section(&raw, &cid),
+            Err(CarWriterError::BlockTooLarge { length: 4, max: 3 })
+        ));
+        assert!(!writer.has_data_to_send());
+    }
+
+    #[test]
+    fn test_car_writer_chunked_section() {
+        let cid = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let section = Section::new(cid.clone(), Block::new(data.clone()));
+        let mut expected = CarWriter::new(vec![cid.clone()]);
+        let expected_location = expected.write_section(&section).unwrap();
+        let mut expected_bytes = vec![0u8; 2048];
+        let length = expected.send_data(&mut expected_bytes);
+        expected_bytes.truncate(length);
+
+        // The block is larger than the buffer: the chunks are streamed through it
+        let mut writer = CarWriter::with_buffer_size(vec![cid.clone()], MIN_BUFFER_SIZE);
+        writer.set_record_manifest(true);
+        let location = writer.begin_section(&cid, data.len()).unwrap();
+        assert_eq!(location, expected_location);
+        assert!(writer.section_in_progress());
+        assert!(matches!(
+            writer.write_section(&section),
+            Err(CarWriterError::SectionInProgress)
+        ));
+        assert!(matches!(
+            writer.begin_section(&cid, 1),
+            Err(CarWriterError::SectionInProgress)
+        ));
+        let mut sink = Vec::new();
+        let mut buf = [0u8; 100];
+        let mut remaining = data.as_slice();
+        while !remaining.is_empty() {
+            let written = writer.write_block_chunk(&remaining[..remaining.len().min(300)]);
+            remaining = &remaining[written.unwrap()..];
+            let length = writer.send_data(&mut buf);
+            sink.extend_from_slice(&buf[..length]);
+        }
+        assert!(matches!(
+            writer.write_block_chunk(&[0]),
+            Err(CarWriterError::BlockLengthMismatch {
+                declared: 1000,
+                written: 1001
+            })
+        ));
+        assert_eq!(writer.end_section().unwrap(), location);
+        assert!(!writer.section_in_progress());
+        while writer.has_data_to_send() {
+            let length = writer.send_data(&mut buf);
+            sink.extend_from_slice(&buf[..length]);
+        }
+        assert_eq!(sink, expected_bytes);
+        let manifest = writer.finish_with_manifest().unwrap();
+        assert_eq!(manifest.sections, vec![(cid.clone(), location)]);
+
+        // A block shorter than declared cannot be completed
+        let mut writer = CarWriter::new(vec![cid.clone()]);
+        assert!(matches!(
+            writer.end_section(),
+            Err(CarWriterError::NoSectionInProgress)
+        ));
+        writer.begin_section(&cid, 4).unwrap();
+        assert_eq!(writer.write_block_chunk(&[1, 2, 3]).unwrap(), 3);
+        assert!(matches!(
+            writer.end_section(),
+            Err(CarWriterError::BlockLengthMismatch {
+                declared: 4,
+                written: 3
+            })
+        ));
+        assert!(writer.section_in_progress());
+        assert_eq!(writer.write_block_chunk(&[4]).unwrap(), 1);
+        writer.end_section().unwrap();
+
+        // The limits are checked upfront
+        writer.set_limits(Limits::new().with_max_block_size(3));
+        assert!(matches!(
+            writer.begin_section(&cid, 4),
+            Err(CarWriterError::BlockTooLarge { length: 4, max: 3 })
+        ));
+        assert!(!writer.section_in_progress());
+    }
+
     // TODO: Tests writer and reader match, by writing a CAR file with the writer and then reading
     // it with the reader and checking that the header and sections are the same.
 }
@@ -0,0 +1,205 @@
+use crate::wire::v2::{CAR_V2_PRAGMA, CarReaderError, CarV2Header, header::PRAGMA_AND_HEADER_SIZE};
+
+/// Extractor of the CAR v1 payload of a CAR v2 file
+///
+/// This struct strips the envelope of a CAR v2 file (pragma, header, pre-payload padding and index) in a sans-io
+/// manner: the caller reads the bytes requested by [PayloadExtractor::next_read], and gets back the bytes of the
+/// payload from [PayloadExtractor::extract], to be written as they are. The payload is never parsed, the output is
+/// a byte-exact copy of the inner CAR v1 file.
+///
+/// The bytes can be read sequentially (the padding being received and dropped), or the source can be seeked to the
+/// requested offsets.
+///
+/// ## Examples
+/// ```
+/// use navira_car::wire::v2::PayloadExtractor;
+///
+/// let car_bytes = include_bytes!("../../res/carv2-basic.car");
+/// let mut extractor = PayloadExtractor::new();
+/// let mut payload = Vec::new();
+/// while let Some((offset, length)) = extractor.next_read() {
+///     let buf = &car_bytes[offset..offset + length];
+///     payload.extend_from_slice(extractor.extract(buf, offset).unwrap());
+/// }
+/// assert_eq!(payload.len() as u64, extractor.header().unwrap().data_size);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PayloadExtractor {
+    /// Bytes of the pragma and header received so far
+    head: Vec<u8>,
+    /// Parsed header, once all its bytes are received
+    header: Option<CarV2Header>,
+    /// Offset in the file of the next byte to receive
+    position: usize,
+}
+
+impl PayloadExtractor {
+    /// Creates a new PayloadExtractor, expecting the bytes of a CAR v2 file from its start
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the CAR v2 header, once read
+    pub fn header(&self) -> Option<&CarV2Header> {
+        self.header.as_ref()
+    }
+
+    /// Is the whole payload extracted?
+    pub fn is_done(&self) -> bool {
+        self.next_read().is_none()
+    }
+
+    /// Get the bytes to read next from the CAR v2 file
+    ///
+    /// # Returns
+    /// * Some((offset, length)) - The bytes of the file needed to go on. Reading fewer bytes is fine, as well as
+    ///   reading them along with the bytes before them.
+    /// * None - The whole payload has been extracted, the rest of the file (if any) is not needed.
+    pub fn next_read(&self) -> Option<(usize, usize)> {
+        match &self.header {
+            None => Some((
+                self.head.len(),
+                (PRAGMA_AND_HEADER_SIZE as usize).saturating_sub(self.head.len()),
+            )),
+            Some(header) => {
+                let start = self.position.max(header.data_offset as usize);
+                let end = header.data_offset.saturating_add(header.data_size) as usize;
+                (start < end).then_some((start, end.saturating_sub(start)))
+            }
+        }
+    }
+
+    /// Receive bytes of the CAR v2 file, and get the bytes of the payload among them
+    ///
+    /// # Arguments
+    /// * `buf` - Bytes read from the file
+    /// * `pos` - Offset position inside the CAR v2 file which the bytes have been read from
+    ///
+    /// # Returns
+    /// * Ok(&[u8]) - The bytes of the payload in `buf`, following the ones previously returned (possibly none)
+    /// * Err(CarReaderError::InsufficientData) - The bytes do not start at or before the requested offset (see
+    ///   [PayloadExtractor::next_read]), they are ignored
+    /// * Err(CarReaderError::InvalidVersion) - The file does not start with the CAR v2 pragma
+    /// * Err(CarReaderError::InvalidFormat) - The header does not locate a valid payload
+    pub fn extract<'a>(&mut self, buf: &'a [u8], pos: usize) -> Result<&'a [u8], CarReaderError> {
+        let Some((offset, length)) = self.next_read() else {
+            return Ok(&[]);
+        };
+        if pos > offset {
+            return Err(CarReaderError::InsufficientData(offset, length));
+        }
+        let end = pos.saturating_add(buf.len());
+        if self.header.is_none() {
+            let received = buf
+                .get(self.head.len().saturating_sub(pos)..)
+                .unwrap_or_default();
+            self.head.extend_from_slice(
+                received
+                    .get(..received.len().min(length))
+                    .unwrap_or_default(),
+            );
+            self.position = self.head.len();
+            if self.head.len() < PRAGMA_AND_HEADER_SIZE as usize {
+                return Ok(&[]);
+            }
+            self.header = Some(decode_head(&self.head)?);
+        }
+        match self.next_read() {
+            Some((offset, length)) if offset < end => {
+                let start = offset.saturating_sub(pos);
+                let stop = offset.saturating_add(length).min(end).saturating_sub(pos);
+                self.position = pos.saturating_add(stop);
+                Ok(buf.get(start..stop).unwrap_or_default())
+            }
+            _ => {
+                self.position = self.position.max(end);
+                Ok(&[])
+            }
+        }
+    }
+}
+
+/// Parse the pragma and header of a CAR v2 file, checking that they locate a valid payload
+pub(super) fn decode_head(head: &[u8]) -> Result<CarV2Header, CarReaderError> {
+    if head.get(..CAR_V2_PRAGMA.len()) != Some(CAR_V2_PRAGMA) {
+        return Err(CarReaderError::InvalidVersion);
+    }
+    let Some(Ok(header_bytes)) = head
+        .get(CAR_V2_PRAGMA.len()..PRAGMA_AND_HEADER_SIZE as usize)
+        .map(<[u8; 40]>::try_from)
+    else {
+        return Err(CarReaderError::InvalidFormat);
+    };
+    let header = CarV2Header::from(header_bytes);
+    // The payload must follow the header, and its end must be addressable
+    if header.data_offset < PRAGMA_AND_HEADER_SIZE
+        || header
+            .data_offset
+            .checked_add(header.data_size)
+            .is_none_or(|end| end > usize::MAX as u64)
+    {
+        return Err(CarReaderError::InvalidFormat);
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::CARV2_BASIC;
+
+    /// Extract the payload, reading chunks of the given size sequentially
+    fn extract_sequential(car: &[u8], chunk_size: usize) -> Result<Vec<u8>, CarReaderError> {
+        let mut extractor = PayloadExtractor::new();
+        let mut payload = Vec::new();
+        for (i, chunk) in car.chunks(chunk_size).enumerate() {
+            if extractor.is_done() {
+                break;
+            }
+            payload.extend_from_slice(extractor.extract(chunk, i * chunk_size)?);
+        }
+        assert!(extractor.is_done());
+        Ok(payload)
+    }
+
+    #[test]
+    fn test_payload_extractor() {
+        let header = CarV2Header::from(<[u8; 40]>::try_from(&CARV2_BASIC[11..51]).unwrap());
+        let payload = &CARV2_BASIC[header.data_offset as usize..][..header.data_size as usize];
+        for chunk_size in [1, 7, 51, 64, 4096] {
+            assert_eq!(
+                extract_sequential(CARV2_BASIC, chunk_size).unwrap(),
+                payload
+            );
+        }
+
+        // A padded payload, read in place with seeks
+        let mut car = CARV2_BASIC[..51].to_vec();
+        car[27..35].copy_from_slice(&100u64.to_le_bytes());
+        car.extend_from_slice(&[0; 49]);
+        car.extend_from_slice(payload);
+        car.extend_from_slice(b"index");
+        let mut extractor = PayloadExtractor::new();
+        assert!(extractor.extract(&car[20..], 20).is_err());
+        assert_eq!(extractor.extract(&car[..51], 0).unwrap(), b"");
+        assert_eq!(extractor.next_read(), Some((100, payload.len())));
+        assert!(matches!(
+            extractor.extract(&car[110..], 110),
+            Err(CarReaderError::InsufficientData(100, _))
+        ));
+        assert_eq!(extractor.extract(&car[100..], 100).unwrap(), payload);
+        assert!(extractor.is_done());
+        assert_eq!(extract_sequential(&car, 13).unwrap(), payload);
+
+        // Not a CAR v2 file, or a payload overlapping the header
+        assert!(matches!(
+            extract_sequential(&CARV2_BASIC[1..], 64),
+            Err(CarReaderError::InvalidVersion)
+        ));
+        car[27..35].copy_from_slice(&50u64.to_le_bytes());
+        assert!(matches!(
+            extract_sequential(&car, 64),
+            Err(CarReaderError::InvalidFormat)
+        ));
+    }
+}
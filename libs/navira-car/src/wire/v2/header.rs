@@ -1,3 +1,8 @@
+use std::ops::Range;
+
+/// Size of the CARv2 pragma and header, i.e. offset of the pre-payload region
+pub(super) const PRAGMA_AND_HEADER_SIZE: u64 = 51;
+
 /// CAR v2 header structure
 ///
 /// The CARv2 header is a fixed-size structure that contains metadata
@@ -24,22 +29,80 @@ pub struct CarV2Header {
     pub index_offset: u64,
 }
 
+impl CarV2Header {
+    /// Absolute range of the pre-payload padding, between the end of the header and the CARv1 payload
+    ///
+    /// The range is empty if the payload starts right after the header (`data_offset` is 51).
+    pub fn pre_payload_padding(&self) -> Range<u64> {
+        PRAGMA_AND_HEADER_SIZE..self.data_offset.max(PRAGMA_AND_HEADER_SIZE)
+    }
+}
+
+/// Pre-payload padding of a CARv2 file, as seen by the reader
+///
+/// Some generators insert padding between the header and the CARv1 payload. The padding should be
+/// zero-filled, but its content is never interpreted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PrePayloadPadding {
+    /// Absolute range of the padding, see [CarV2Header::pre_payload_padding]
+    pub range: Range<u64>,
+    /// Absolute offset of the first non-zero byte of the padding, if any
+    ///
+    /// Always `None` if the padding was not checked.
+    pub first_nonzero: Option<u64>,
+    /// Was the content of the padding checked?
+    pub checked: bool,
+}
+
+impl PrePayloadPadding {
+    /// Length of the padding in bytes
+    pub fn len(&self) -> u64 {
+        self.range.end.saturating_sub(self.range.start)
+    }
+
+    /// Is the padding empty?
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    /// Is the padding known to be zero-filled?
+    ///
+    /// ## Returns
+    /// - `Some(true)` if the padding is empty, or was checked and is zero-filled.
+    /// - `Some(false)` if the padding contains a non-zero byte.
+    /// - `None` if the padding was not checked.
+    pub fn is_zero_filled(&self) -> Option<bool> {
+        if self.is_empty() {
+            Some(true)
+        } else if self.checked {
+            Some(self.first_nonzero.is_none())
+        } else {
+            None
+        }
+    }
+}
+
 impl From<[u8; 40]> for CarV2Header {
     fn from(bytes: [u8; 40]) -> Self {
-        let characteristics =
-            Characteristics(u128::from_le_bytes(bytes[0..16].try_into().unwrap()));
-        let data_offset = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
-        let data_size = u64::from_le_bytes(bytes[24..32].try_into().unwrap());
-        let index_offset = u64::from_le_bytes(bytes[32..40].try_into().unwrap());
         CarV2Header {
-            characteristics,
-            data_offset,
-            data_size,
-            index_offset,
+            characteristics: Characteristics(le_field(&bytes, 0, 16)),
+            data_offset: le_field(&bytes, 16, 8) as u64,
+            data_size: le_field(&bytes, 24, 8) as u64,
+            index_offset: le_field(&bytes, 32, 8) as u64,
         }
     }
 }
 
+/// Decode the little-endian field of `size` bytes (at most 16) starting at `start`
+fn le_field(bytes: &[u8; 40], start: usize, size: usize) -> u128 {
+    bytes
+        .iter()
+        .skip(start)
+        .take(size)
+        .rev()
+        .fold(0, |acc, byte| (acc << 8) | u128::from(*byte))
+}
+
 impl From<&CarV2Header> for [u8; 40] {
     fn from(header: &CarV2Header) -> Self {
         let mut bytes = [0u8; 40];
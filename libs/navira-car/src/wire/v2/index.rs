@@ -9,6 +9,7 @@
 //!
 //! The index is stored at the end of the CAR v2 file, and its start offset is indicated in the CAR v2 header.
 //! The first bytes of the index indicate its type (LEB128 varint).
+//! The offsets stored in the index are relative to the start of the CAR v1 payload (see [CarV2Header::data_offset](super::CarV2Header)).
 //!
 //! ## IndexSorted (0x0400)
 //!
@@ -20,22 +21,29 @@
 //!
 //! Those entries are grouped into "buckets" that have a common hash size (32 bytes for SHA-256, etc).
 //! Each bucket starts with the width of an entry (hash size + 8 bytes for offset) as u32le, and
-//! the number of entries in that bucket as u64le, followed by the entries themselves.
-//! All buckets are concatenated together to form the complete index, and sorted by hash size (smallest first).
+//! the total size of its entries in bytes as u64le, followed by the entries themselves.
+//! All buckets are concatenated together to form the complete index, sorted by hash size (smallest first),
+//! and prefixed by the number of buckets as u32le.
 //!
 //! ## MultihashIndexSorted (0x0401)
 //!
 //! The MultihashIndexSorted type is similar to IndexSorted and reuses its structures. However, an additional
 //! dimension is added to specify the hash function used for each bucket of entries.
 //!
-//! Buckets are now grouped by multihash code (u64, LEB128 varint), smallest first. The multihash code is
-//! prefixed to each bucket, followed by the width of an entry (hash size + 8 bytes for offset) as u32le,
-//! the number of entries in that bucket as u64le, and then the entries themselves.
+//! Buckets are now grouped by multihash code (u64le), smallest first. The index starts with the number of
+//! multihash codes as u32le, then for each multihash code, the code itself followed by a complete IndexSorted
+//! structure (without the type prefix) for the entries hashed with this code.
 //!
 //! This allows the index to contain entries for blocks hashed with different algorithms.
 
+use std::collections::BTreeMap;
+
+use crate::trace::trace_event;
+use crate::wire::cid::RawCid;
+use crate::wire::varint::UnsignedVarint;
+
 /// Represents a single entry in the CAR v2 index
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct OwnedIndexEntry {
     /// Raw hash digest of the block
     pub hash: Vec<u8>,
@@ -58,6 +66,8 @@ pub struct IndexSortedBucketHeader {
     /// Width of each entry (hash size + 8 bytes for offset)
     pub entry_width: u32,
     /// Number of entries in this bucket
+    ///
+    /// On the wire, the total size of the entries in bytes is stored instead.
     pub entry_count: u64,
 }
 
@@ -83,12 +93,374 @@ pub enum IndexType {
 }
 
 impl IndexType {
+    /// All the index types supported by the index parser
+    pub const ALL: [IndexType; 2] = [IndexType::IndexSorted, IndexType::MultihashIndexSorted];
+
     /// Creates an IndexType from a u64 value
     pub fn from_u64(value: u64) -> Option<Self> {
-        match value {
-            0x0400 => Some(IndexType::IndexSorted),
-            0x0401 => Some(IndexType::MultihashIndexSorted),
-            _ => None,
+        Self::ALL
+            .into_iter()
+            .find(|index_type| index_type.code() == value)
+    }
+
+    /// Multicodec code of the index type
+    pub fn code(self) -> u64 {
+        self as u64
+    }
+}
+
+/// A decoded CAR v2 index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    /// Type of the index
+    pub index_type: IndexType,
+    /// Buckets of entries, each bucket has a common entry width (and multihash code)
+    pub buckets: Vec<IndexBucket>,
+}
+
+/// A bucket of entries of a CAR v2 index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexBucket {
+    /// Multihash code of the entries (`None` for IndexSorted, which does not record it)
+    pub multihash_code: Option<u64>,
+    /// Width of each entry (hash size + 8 bytes for offset)
+    pub entry_width: u32,
+    /// Entries, sorted by hash
+    pub entries: Vec<OwnedIndexEntry>,
+}
+
+impl Index {
+    /// Decodes a CAR v2 index from the given bytes (starting at the index offset)
+    ///
+    /// ## Returns
+    /// - `Ok((Index, size))` with the decoded index and the number of bytes it spans.
+    /// - `Err(IndexFormatError)` if the index is truncated, malformed or of an unknown type.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize), IndexFormatError> {
+        let (codec, mut pos) =
+            UnsignedVarint::decode(bytes).ok_or(IndexFormatError::InsufficientData)?;
+        let index_type =
+            IndexType::from_u64(codec.0).ok_or(IndexFormatError::UnknownIndexType(codec.0))?;
+        let mut buckets = Vec::new();
+        match index_type {
+            IndexType::IndexSorted => decode_index_sorted(bytes, &mut pos, None, &mut buckets)?,
+            IndexType::MultihashIndexSorted => {
+                let code_count = read_u32(bytes, &mut pos)?;
+                for _ in 0..code_count {
+                    let code = read_u64(bytes, &mut pos)?;
+                    decode_index_sorted(bytes, &mut pos, Some(code), &mut buckets)?;
+                }
+            }
         }
+        trace_event!(
+            WIRE_V2_INDEX,
+            index_type = ?index_type,
+            buckets = buckets.len(),
+            size = pos,
+            "Index decoded"
+        );
+        Ok((
+            Index {
+                index_type,
+                buckets,
+            },
+            pos,
+        ))
+    }
+
+    /// Builds a MultihashIndexSorted index from the given CIDs and their offsets
+    ///
+    /// Offsets must be relative to the start of the CAR v1 payload. CIDs which are not well-formed
+    /// (no multihash to index) are ignored.
+    pub fn multihash_sorted<'a>(entries: impl IntoIterator<Item = (&'a RawCid, u64)>) -> Self {
+        Self::from_entries(
+            IndexType::MultihashIndexSorted,
+            entries
+                .into_iter()
+                .filter_map(|(cid, offset)| Some((cid.multihash_code()?, cid.digest()?, offset))),
+        )
+    }
+
+    /// Builds an IndexSorted index from the given raw digests and their offsets
+    ///
+    /// Offsets must be relative to the start of the CAR v1 payload. The hash functions are not recorded
+    /// in this index type, see [Index::multihash_sorted] to keep them.
+    pub fn sorted<'a>(entries: impl IntoIterator<Item = (&'a [u8], u64)>) -> Self {
+        Self::from_entries(
+            IndexType::IndexSorted,
+            entries
+                .into_iter()
+                .map(|(digest, offset)| (0, digest, offset)),
+        )
+    }
+
+    /// Builds an index of the given type from `(multihash code, digest, offset)` entries
+    ///
+    /// The entries are grouped into buckets (by multihash code for MultihashIndexSorted, then by
+    /// entry width) and sorted by digest, as required by the specification. The multihash codes
+    /// are ignored for IndexSorted.
+    pub fn from_entries<'a>(
+        index_type: IndexType,
+        entries: impl IntoIterator<Item = (u64, &'a [u8], u64)>,
+    ) -> Self {
+        let mut grouped: BTreeMap<(Option<u64>, u32), Vec<OwnedIndexEntry>> = BTreeMap::new();
+        for (code, digest, offset) in entries {
+            let code = (index_type == IndexType::MultihashIndexSorted).then_some(code);
+            grouped
+                .entry((code, (digest.len() as u32).saturating_add(8)))
+                .or_default()
+                .push(OwnedIndexEntry {
+                    hash: digest.to_vec(),
+                    offset,
+                });
+        }
+        let buckets = grouped
+            .into_iter()
+            .map(|((multihash_code, entry_width), mut entries)| {
+                entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+                IndexBucket {
+                    multihash_code,
+                    entry_width,
+                    entries,
+                }
+            })
+            .collect();
+        Index {
+            index_type,
+            buckets,
+        }
+    }
+
+    /// Encodes the index in its wire format (starting with its type)
+    ///
+    /// Buckets are expected to be sorted (by multihash code, then by entry width), as produced by
+    /// [Index::decode] or [Index::from_entries].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = UnsignedVarint(self.index_type as u64).encode();
+        match self.index_type {
+            IndexType::IndexSorted => encode_index_sorted(&self.buckets, &mut bytes),
+            IndexType::MultihashIndexSorted => {
+                let codes: Vec<_> = self
+                    .buckets
+                    .chunk_by(|a, b| a.multihash_code == b.multihash_code)
+                    .collect();
+                bytes.extend_from_slice(&(codes.len() as u32).to_le_bytes());
+                for buckets in codes {
+                    let code = buckets
+                        .first()
+                        .and_then(|bucket| bucket.multihash_code)
+                        .unwrap_or_default();
+                    bytes.extend_from_slice(&code.to_le_bytes());
+                    encode_index_sorted(buckets, &mut bytes);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Total number of entries in the index
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.entries.len()).sum()
+    }
+
+    /// Returns true if the index has no entry
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Looks up the entry of the given CID, by its multihash digest
+    ///
+    /// As the index is keyed by digest, CIDs which only differ by their codec share the same entry.
+    ///
+    /// ## Returns
+    /// - `Some(offset)` with the offset of the section, relative to the start of the CAR v1 payload.
+    /// - `None` if the CID is not indexed (or is not a well-formed CID).
+    pub fn find(&self, cid: &RawCid) -> Option<u64> {
+        self.find_digest(Some(cid.multihash_code()?), cid.digest()?)
+    }
+
+    /// Looks up the entry of the given raw multihash digest
+    ///
+    /// With `multihash_code` set, only the buckets of this hash function (or without recorded function,
+    /// for IndexSorted) are searched. Otherwise, every bucket with the digest width is searched.
+    ///
+    /// ## Returns
+    /// - `Some(offset)` with the offset of the section, relative to the start of the CAR v1 payload.
+    /// - `None` if the digest is not indexed.
+    pub fn find_digest(&self, multihash_code: Option<u64>, digest: &[u8]) -> Option<u64> {
+        self.buckets
+            .iter()
+            .filter(|b| {
+                b.multihash_code
+                    .is_none_or(|c| multihash_code.is_none_or(|code| c == code))
+            })
+            .filter(|b| b.entry_width as usize == digest.len().saturating_add(8))
+            .find_map(|b| {
+                b.entries
+                    .binary_search_by(|e| e.hash.as_slice().cmp(digest))
+                    .ok()
+                    .and_then(|i| b.entries.get(i))
+                    .map(|e| e.offset)
+            })
+    }
+}
+
+/// Decodes an IndexSorted structure (bucket count and buckets) at the given position
+fn decode_index_sorted(
+    bytes: &[u8],
+    pos: &mut usize,
+    multihash_code: Option<u64>,
+    buckets: &mut Vec<IndexBucket>,
+) -> Result<(), IndexFormatError> {
+    let bucket_count = read_u32(bytes, pos)?;
+    for _ in 0..bucket_count {
+        let entry_width = read_u32(bytes, pos)?;
+        let size = read_u64(bytes, pos)?;
+        if entry_width <= 8 {
+            return Err(IndexFormatError::InvalidEntryWidth(entry_width));
+        }
+        if size.checked_rem(entry_width as u64) != Some(0) {
+            return Err(IndexFormatError::InvalidBucketSize(size));
+        }
+        let end = pos
+            .checked_add(size as usize)
+            .ok_or(IndexFormatError::InsufficientData)?;
+        let entries: Vec<_> = bytes
+            .get(*pos..end)
+            .ok_or(IndexFormatError::InsufficientData)?
+            .chunks_exact(entry_width as usize)
+            .filter_map(<[u8]>::split_last_chunk::<8>)
+            .map(|(hash, offset)| OwnedIndexEntry {
+                hash: hash.to_vec(),
+                offset: u64::from_le_bytes(*offset),
+            })
+            .collect();
+        trace_event!(
+            WIRE_V2_INDEX,
+            multihash_code = ?multihash_code,
+            entry_width,
+            entries = entries.len(),
+            "Index bucket loaded"
+        );
+        *pos = end;
+        buckets.push(IndexBucket {
+            multihash_code,
+            entry_width,
+            entries,
+        });
+    }
+    Ok(())
+}
+
+/// Encodes an IndexSorted structure (bucket count and buckets)
+fn encode_index_sorted(buckets: &[IndexBucket], bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&(buckets.len() as u32).to_le_bytes());
+    for bucket in buckets {
+        bytes.extend_from_slice(&bucket.entry_width.to_le_bytes());
+        let size = (bucket.entries.len() as u64).saturating_mul(bucket.entry_width as u64);
+        bytes.extend_from_slice(&size.to_le_bytes());
+        for entry in &bucket.entries {
+            bytes.extend_from_slice(&entry.hash);
+            bytes.extend_from_slice(&entry.offset.to_le_bytes());
+        }
+    }
+}
+
+/// Reads a u32le at the given position and advances it
+fn read_u32(bytes: &[u8], pos: &mut usize) -> Result<u32, IndexFormatError> {
+    let value = bytes
+        .get(*pos..)
+        .and_then(<[u8]>::first_chunk::<4>)
+        .ok_or(IndexFormatError::InsufficientData)?;
+    *pos = pos.saturating_add(4);
+    Ok(u32::from_le_bytes(*value))
+}
+
+/// Reads a u64le at the given position and advances it
+fn read_u64(bytes: &[u8], pos: &mut usize) -> Result<u64, IndexFormatError> {
+    let value = bytes
+        .get(*pos..)
+        .and_then(<[u8]>::first_chunk::<8>)
+        .ok_or(IndexFormatError::InsufficientData)?;
+    *pos = pos.saturating_add(8);
+    Ok(u64::from_le_bytes(*value))
+}
+
+/// Errors related to CAR v2 index parsing
+#[derive(thiserror::Error, Debug)]
+pub enum IndexFormatError {
+    /// Not enough data to parse the index
+    #[error("Insufficient data for index")]
+    InsufficientData,
+    /// The index type is not supported
+    #[error("Unknown index type: {0:#x}")]
+    UnknownIndexType(u64),
+    /// A bucket has an entry width too small to hold an offset
+    #[error("Invalid index entry width: {0}")]
+    InvalidEntryWidth(u32),
+    /// A bucket size is not a multiple of its entry width
+    #[error("Invalid index bucket size: {0}")]
+    InvalidBucketSize(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testdata::carv2_basic_index as fixture_index_bytes;
+
+    #[test]
+    fn test_index_decode_multihash_index_sorted() {
+        let bytes = fixture_index_bytes();
+        let (index, size) = Index::decode(&bytes).unwrap();
+        assert_eq!(size, bytes.len());
+        assert_eq!(index.index_type, IndexType::MultihashIndexSorted);
+        assert_eq!(index.buckets.len(), 1);
+        assert_eq!(index.buckets[0].multihash_code, Some(0x12));
+        assert_eq!(index.len(), 5);
+
+        // Root of carv2-basic.car, the first section of the payload (after the 57 bytes of the CARv1 header)
+        let root = RawCid::from_hex(
+            "1220fb16f5083412ef1371d031ed4aa239903d84efdadf1ba3cd678e6475b1a232f8",
+        )
+        .unwrap();
+        assert_eq!(index.find(&root), Some(57));
+        assert_eq!(index.find_digest(None, root.digest().unwrap()), Some(57));
+        assert_eq!(index.find_digest(Some(0x13), root.digest().unwrap()), None);
+        let unknown = RawCid::from_hex(
+            "12200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        assert_eq!(index.find(&unknown), None);
+    }
+
+    #[test]
+    fn test_index_encode_roundtrip() {
+        use crate::testdata::{CARV2_BASIC_DATA_OFFSET, CARV2_BASIC_SECTIONS};
+
+        let bytes = fixture_index_bytes();
+        let (index, _) = Index::decode(&bytes).unwrap();
+        assert_eq!(index.encode(), bytes);
+
+        // Building the index from the sections gives back the index of the fixture
+        let cids: Vec<_> = CARV2_BASIC_SECTIONS.iter().map(|s| s.cid()).collect();
+        let built = Index::multihash_sorted(
+            cids.iter()
+                .zip(CARV2_BASIC_SECTIONS)
+                .map(|(cid, s)| (cid, s.offset - CARV2_BASIC_DATA_OFFSET)),
+        );
+        assert_eq!(built, index);
+    }
+
+    #[test]
+    fn test_index_decode_errors() {
+        let bytes = fixture_index_bytes();
+        assert!(matches!(
+            Index::decode(&bytes[..bytes.len() - 1]),
+            Err(IndexFormatError::InsufficientData)
+        ));
+        assert!(matches!(
+            Index::decode(&[0x80, 0x10]),
+            Err(IndexFormatError::UnknownIndexType(0x800))
+        ));
     }
 }
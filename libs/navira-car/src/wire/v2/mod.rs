@@ -7,15 +7,24 @@
 //!
 //! However, if you only need to work with CAR v2 headers or sections, you can use the types in this module directly.
 
+mod extract;
 mod header;
 mod index;
 mod read;
+mod reindex;
 mod write;
 
-pub use crate::wire::v1::{Block, LocatableSection, Section, SectionFormatError, SectionLocation};
-pub use header::{CarV2Header, Characteristics};
+#[cfg(feature = "bytes")]
+pub use crate::wire::v1::SectionBytes;
+pub use crate::wire::v1::{
+    Block, BlockRef, LocatableSection, Section, SectionFormatError, SectionLocation, SectionRef,
+    WriteManifest,
+};
+pub use extract::PayloadExtractor;
+pub use header::{CarV2Header, Characteristics, PrePayloadPadding};
 pub use index::*;
-pub use read::{CarReader, CarReaderError};
+pub use read::{CarReader, CarReaderError, PaddingCheck};
+pub use reindex::{Reindexed, Reindexer};
 pub use write::*;
 
 /// CAR v2 pragma bytes
@@ -33,62 +42,12 @@ mod tests {
 
     use super::*;
 
-    const CAR_V2: [u8; 715] = [
-        // Offset 0x00000000 to 0x000002CA
-        0x0A, 0xA1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6F, 0x6E, 0x02, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF3, 0x01,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x38, 0xA2, 0x65, 0x72, 0x6F, 0x6F, 0x74, 0x73, 0x81,
-        0xD8, 0x2A, 0x58, 0x23, 0x00, 0x12, 0x20, 0xFB, 0x16, 0xF5, 0x08, 0x34, 0x12, 0xEF, 0x13,
-        0x71, 0xD0, 0x31, 0xED, 0x4A, 0xA2, 0x39, 0x90, 0x3D, 0x84, 0xEF, 0xDA, 0xDF, 0x1B, 0xA3,
-        0xCD, 0x67, 0x8E, 0x64, 0x75, 0xB1, 0xA2, 0x32, 0xF8, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69,
-        0x6F, 0x6E, 0x01, 0x51, 0x12, 0x20, 0xFB, 0x16, 0xF5, 0x08, 0x34, 0x12, 0xEF, 0x13, 0x71,
-        0xD0, 0x31, 0xED, 0x4A, 0xA2, 0x39, 0x90, 0x3D, 0x84, 0xEF, 0xDA, 0xDF, 0x1B, 0xA3, 0xCD,
-        0x67, 0x8E, 0x64, 0x75, 0xB1, 0xA2, 0x32, 0xF8, 0x12, 0x2D, 0x0A, 0x22, 0x12, 0x20, 0xD9,
-        0xC0, 0xD5, 0x37, 0x6D, 0x26, 0xF1, 0x93, 0x1F, 0x7A, 0xD5, 0x2D, 0x7A, 0xCC, 0x00, 0xFC,
-        0x10, 0x90, 0xD2, 0xED, 0xB0, 0x80, 0x8B, 0xF6, 0x1E, 0xEB, 0x0A, 0x15, 0x28, 0x26, 0xF6,
-        0x26, 0x12, 0x04, 0xF0, 0x9F, 0x8D, 0xA4, 0x18, 0xA4, 0x01, 0x85, 0x01, 0x12, 0x20, 0xD9,
-        0xC0, 0xD5, 0x37, 0x6D, 0x26, 0xF1, 0x93, 0x1F, 0x7A, 0xD5, 0x2D, 0x7A, 0xCC, 0x00, 0xFC,
-        0x10, 0x90, 0xD2, 0xED, 0xB0, 0x80, 0x8B, 0xF6, 0x1E, 0xEB, 0x0A, 0x15, 0x28, 0x26, 0xF6,
-        0x26, 0x12, 0x31, 0x0A, 0x22, 0x12, 0x20, 0xD7, 0x45, 0xB7, 0x75, 0x7F, 0x5B, 0x45, 0x93,
-        0xEE, 0xAB, 0x78, 0x20, 0x30, 0x6C, 0x7B, 0xC6, 0x4E, 0xB4, 0x96, 0xA7, 0x41, 0x0A, 0x0D,
-        0x07, 0xDF, 0x7A, 0x34, 0xFF, 0xEC, 0x4B, 0x97, 0xF1, 0x12, 0x09, 0x62, 0x61, 0x72, 0x72,
-        0x65, 0x6C, 0x65, 0x79, 0x65, 0x18, 0x3A, 0x12, 0x2E, 0x0A, 0x24, 0x01, 0x55, 0x12, 0x20,
-        0xA2, 0xE1, 0xC4, 0x0D, 0xA1, 0xAE, 0x33, 0x5D, 0x4D, 0xFF, 0xE7, 0x29, 0xEB, 0x4D, 0x5C,
-        0xA2, 0x3B, 0x74, 0xB9, 0xE5, 0x1F, 0xC5, 0x35, 0xF4, 0xA8, 0x04, 0xA2, 0x61, 0x08, 0x0C,
-        0x29, 0x4D, 0x12, 0x04, 0xF0, 0x9F, 0x90, 0xA1, 0x18, 0x07, 0x58, 0x12, 0x20, 0xD7, 0x45,
-        0xB7, 0x75, 0x7F, 0x5B, 0x45, 0x93, 0xEE, 0xAB, 0x78, 0x20, 0x30, 0x6C, 0x7B, 0xC6, 0x4E,
-        0xB4, 0x96, 0xA7, 0x41, 0x0A, 0x0D, 0x07, 0xDF, 0x7A, 0x34, 0xFF, 0xEC, 0x4B, 0x97, 0xF1,
-        0x12, 0x34, 0x0A, 0x24, 0x01, 0x55, 0x12, 0x20, 0xB4, 0x74, 0xA9, 0x9A, 0x27, 0x05, 0xE2,
-        0x3C, 0xF9, 0x05, 0xA4, 0x84, 0xEC, 0x6D, 0x14, 0xEF, 0x58, 0xB5, 0x6B, 0xBE, 0x62, 0xE9,
-        0x29, 0x27, 0x83, 0x46, 0x6E, 0xC3, 0x63, 0xB5, 0x07, 0x2D, 0x12, 0x0A, 0x66, 0x69, 0x73,
-        0x68, 0x6D, 0x6F, 0x6E, 0x67, 0x65, 0x72, 0x18, 0x04, 0x28, 0x01, 0x55, 0x12, 0x20, 0xB4,
-        0x74, 0xA9, 0x9A, 0x27, 0x05, 0xE2, 0x3C, 0xF9, 0x05, 0xA4, 0x84, 0xEC, 0x6D, 0x14, 0xEF,
-        0x58, 0xB5, 0x6B, 0xBE, 0x62, 0xE9, 0x29, 0x27, 0x83, 0x46, 0x6E, 0xC3, 0x63, 0xB5, 0x07,
-        0x2D, 0x66, 0x69, 0x73, 0x68, 0x2B, 0x01, 0x55, 0x12, 0x20, 0xA2, 0xE1, 0xC4, 0x0D, 0xA1,
-        0xAE, 0x33, 0x5D, 0x4D, 0xFF, 0xE7, 0x29, 0xEB, 0x4D, 0x5C, 0xA2, 0x3B, 0x74, 0xB9, 0xE5,
-        0x1F, 0xC5, 0x35, 0xF4, 0xA8, 0x04, 0xA2, 0x61, 0x08, 0x0C, 0x29, 0x4D, 0x6C, 0x6F, 0x62,
-        0x73, 0x74, 0x65, 0x72, 0x01, 0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0xC8, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0xA2, 0xE1, 0xC4, 0x0D, 0xA1, 0xAE, 0x33, 0x5D, 0x4D, 0xFF,
-        0xE7, 0x29, 0xEB, 0x4D, 0x5C, 0xA2, 0x3B, 0x74, 0xB9, 0xE5, 0x1F, 0xC5, 0x35, 0xF4, 0xA8,
-        0x04, 0xA2, 0x61, 0x08, 0x0C, 0x29, 0x4D, 0x94, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0xB4, 0x74, 0xA9, 0x9A, 0x27, 0x05, 0xE2, 0x3C, 0xF9, 0x05, 0xA4, 0x84, 0xEC, 0x6D, 0x14,
-        0xEF, 0x58, 0xB5, 0x6B, 0xBE, 0x62, 0xE9, 0x29, 0x27, 0x83, 0x46, 0x6E, 0xC3, 0x63, 0xB5,
-        0x07, 0x2D, 0x6B, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xD7, 0x45, 0xB7, 0x75, 0x7F,
-        0x5B, 0x45, 0x93, 0xEE, 0xAB, 0x78, 0x20, 0x30, 0x6C, 0x7B, 0xC6, 0x4E, 0xB4, 0x96, 0xA7,
-        0x41, 0x0A, 0x0D, 0x07, 0xDF, 0x7A, 0x34, 0xFF, 0xEC, 0x4B, 0x97, 0xF1, 0x12, 0x01, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0xD9, 0xC0, 0xD5, 0x37, 0x6D, 0x26, 0xF1, 0x93, 0x1F, 0x7A,
-        0xD5, 0x2D, 0x7A, 0xCC, 0x00, 0xFC, 0x10, 0x90, 0xD2, 0xED, 0xB0, 0x80, 0x8B, 0xF6, 0x1E,
-        0xEB, 0x0A, 0x15, 0x28, 0x26, 0xF6, 0x26, 0x8B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0xFB, 0x16, 0xF5, 0x08, 0x34, 0x12, 0xEF, 0x13, 0x71, 0xD0, 0x31, 0xED, 0x4A, 0xA2, 0x39,
-        0x90, 0x3D, 0x84, 0xEF, 0xDA, 0xDF, 0x1B, 0xA3, 0xCD, 0x67, 0x8E, 0x64, 0x75, 0xB1, 0xA2,
-        0x32, 0xF8, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    ];
+    use crate::testdata::CARV2_BASIC as CAR_V2;
 
     #[test]
     fn test_car_v2_header_deserialization() {
         let mut reader = CarReader::new();
-        reader.receive_data(&CAR_V2, 0);
+        reader.receive_data(CAR_V2, 0);
         reader.read_header().unwrap();
         let (v1h, v2h) = reader.header().unwrap();
         assert_eq!(v2h.characteristics.0, 0);
@@ -129,7 +88,7 @@ mod tests {
     #[test]
     fn test_car_v2_header_count_blocks() {
         let mut reader = CarReader::new();
-        reader.receive_data(&CAR_V2, 0);
+        reader.receive_data(CAR_V2, 0);
         reader.read_header().unwrap();
 
         let mut block_count = 0;
@@ -154,6 +113,48 @@ mod tests {
         assert_eq!(block_bytes, 211);
     }
 
+    #[test]
+    fn test_car_v2_reader_seek_to() {
+        let mut reader = CarReader::new();
+        reader.receive_data(CAR_V2, 0);
+        reader.read_header().unwrap();
+        let mut sections = Vec::new();
+        while let Ok(section) = reader.read_section() {
+            sections.push(section);
+        }
+        assert_eq!(sections.len(), 5);
+        // Locations are absolute, the first section starts right after the CARv1 header
+        assert!(sections[0].location.offset > 51);
+
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V2[..120], 0);
+        reader.read_header().unwrap();
+        for expected in sections.iter().rev() {
+            let offset = expected.location.offset as usize;
+            reader.seek_to(&expected.location).unwrap();
+            match reader.read_section() {
+                Err(CarReaderError::InsufficientData(read_from, _)) => {
+                    assert_eq!(read_from, offset)
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            // Feed up to the end of the file, the index must not be read as sections
+            reader.receive_data(&CAR_V2[offset..], offset);
+            let section = reader.read_section().unwrap();
+            assert_eq!(section.cid(), expected.cid());
+            assert_eq!(section.location, expected.location);
+        }
+
+        // Locations outside of the CARv1 payload are rejected
+        for offset in [10, 499] {
+            let location = SectionLocation { offset, length: 4 };
+            assert!(matches!(
+                reader.seek_to(&location),
+                Err(CarReaderError::PreconditionNotMet)
+            ));
+        }
+    }
+
     #[test]
     fn test_car_v2_writer_reader_compatibility() {
         let root_cid = RawCid::from_hex(
@@ -214,6 +215,7 @@ mod tests {
                         section_to_write.push(section); // Put the section back to try writing it again after flushing
                         continue;
                     }
+                    Err(e) => panic!("Unexpected error: {:?}", e),
                 }
             } else {
                 // No more sections to write, we just need to flush any remaining data
@@ -305,4 +307,268 @@ mod tests {
             "Not all sections were read correctly"
         );
     }
+
+    /// Insert the padding between the header and the payload of [CAR_V2], shifting the offsets
+    fn with_pre_payload_padding(padding: &[u8]) -> Vec<u8> {
+        let mut header = CarV2Header::from(<[u8; 40]>::try_from(&CAR_V2[11..51]).unwrap());
+        header.data_offset += padding.len() as u64;
+        header.index_offset += padding.len() as u64;
+        let mut car = CAR_V2_PRAGMA.to_vec();
+        car.extend_from_slice(&<[u8; 40]>::from(&header));
+        car.extend_from_slice(padding);
+        car.extend_from_slice(&CAR_V2[51..]);
+        car
+    }
+
+    /// Read all the sections, feeding the requested ranges only
+    fn read_all(reader: &mut CarReader, car: &[u8]) -> Result<usize, CarReaderError> {
+        let mut sections = 0;
+        loop {
+            let result = if reader.has_header() {
+                reader.read_section().map(|_| sections += 1)
+            } else {
+                reader.read_header()
+            };
+            match result {
+                Ok(()) => {}
+                Err(CarReaderError::InsufficientData(offset, hint)) => {
+                    let end = (offset + hint.max(1)).min(car.len());
+                    reader.receive_data(&car[offset..end], offset);
+                }
+                Err(CarReaderError::EndOfSections) => return Ok(sections),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_car_v2_overflowing_header() {
+        // Offsets computed from these headers would overflow
+        for (data_offset, data_size) in [(u64::MAX, 10), (51, u64::MAX - 10)] {
+            let mut header = CarV2Header::from(<[u8; 40]>::try_from(&CAR_V2[11..51]).unwrap());
+            header.data_offset = data_offset;
+            header.data_size = data_size;
+            let mut car = CAR_V2_PRAGMA.to_vec();
+            car.extend_from_slice(&<[u8; 40]>::from(&header));
+            let mut reader = CarReader::new();
+            reader.receive_data(&car, 0);
+            assert!(matches!(
+                reader.read_header(),
+                Err(CarReaderError::InvalidFormat)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_car_v2_pre_payload_padding() {
+        let zeroed = with_pre_payload_padding(&[0; 13]);
+        let mut reader = CarReader::new();
+        assert_eq!(read_all(&mut reader, &zeroed).unwrap(), 5);
+        let padding = reader.pre_payload_padding().unwrap();
+        assert_eq!(padding.range, 51..64);
+        assert_eq!(padding.is_zero_filled(), Some(true));
+
+        let mut dirty = [0; 13];
+        dirty[7] = 0xff;
+        let dirty = with_pre_payload_padding(&dirty);
+        // Warn (default): the non-zero byte is reported, the sections are still readable
+        let mut reader = CarReader::new();
+        assert_eq!(read_all(&mut reader, &dirty).unwrap(), 5);
+        assert_eq!(
+            reader.pre_payload_padding().unwrap().first_nonzero,
+            Some(58)
+        );
+        assert_eq!(
+            reader.pre_payload_padding().unwrap().is_zero_filled(),
+            Some(false)
+        );
+
+        // Skip: the padding is never requested
+        let mut reader = CarReader::new();
+        reader.set_padding_check(PaddingCheck::Skip);
+        assert_eq!(read_all(&mut reader, &dirty).unwrap(), 5);
+        assert_eq!(reader.pre_payload_padding().unwrap().is_zero_filled(), None);
+
+        // Strict: the non-zero byte is rejected
+        let mut reader = CarReader::new();
+        reader.set_padding_check(PaddingCheck::Strict);
+        assert!(matches!(
+            read_all(&mut reader, &dirty),
+            Err(CarReaderError::NonZeroPadding(58))
+        ));
+        let mut reader = CarReader::new();
+        reader.set_padding_check(PaddingCheck::Strict);
+        assert_eq!(read_all(&mut reader, &zeroed).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_car_v2_pre_payload_padding_limits() {
+        use crate::wire::limits::Limits;
+
+        // The CAR v1 header (56 bytes) is within the limits, but not the padding
+        let car = with_pre_payload_padding(&[0; 100]);
+        // Warn: the padding is too large to be buffered, so it is left unchecked
+        let mut reader = CarReader::new();
+        reader.set_limits(Limits::new().with_max_header_size(64));
+        assert_eq!(read_all(&mut reader, &car).unwrap(), 5);
+        assert_eq!(reader.pre_payload_padding().unwrap().is_zero_filled(), None);
+
+        // Strict: the padding is rejected before being requested
+        let mut reader = CarReader::new();
+        reader.set_limits(Limits::new().with_max_header_size(64));
+        reader.set_padding_check(PaddingCheck::Strict);
+        reader.receive_data(&car[..51], 0);
+        assert!(matches!(
+            reader.read_header(),
+            Err(CarReaderError::HeaderTooLarge(100))
+        ));
+    }
+
+    #[test]
+    fn test_car_v2_finish() {
+        // Cut in the CAR v2 header, in the CAR v1 header, in the last section (455..499), and after the payload
+        for (len, sections, result) in [
+            (40, 0, Some(11)),
+            (80, 0, Some(28)),
+            (480, 4, Some(19)),
+            (499, 5, None),
+        ] {
+            let mut reader = CarReader::new();
+            reader.receive_data(&CAR_V2[..len], 0);
+            reader.finish(len);
+            let read = reader.read_header().and_then(|()| {
+                for _ in 0..sections {
+                    reader.read_section()?;
+                }
+                reader.read_section().map(|_| ())
+            });
+            match result {
+                Some(missing) => assert!(
+                    matches!(read, Err(CarReaderError::Truncated { missing_bytes }) if missing_bytes == missing),
+                    "{len}: {read:?}"
+                ),
+                None => assert!(matches!(read, Err(CarReaderError::EndOfSections))),
+            }
+        }
+
+        // Cut at a section boundary within the payload
+        let mut reader = CarReader::new();
+        reader.receive_data(&CAR_V2[..455], 0);
+        reader.finish(455);
+        reader.read_header().unwrap();
+        for _ in 0..4 {
+            reader.read_section().unwrap();
+        }
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::Truncated { missing_bytes: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_car_v2_resync() {
+        // Damage the CID of the section at 190 (the second one), then of the last one at 455
+        let mut car = CAR_V2.to_vec();
+        car[192] = 0xff;
+        car[456] = 0xff;
+        let mut reader = CarReader::new();
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        assert_eq!(reader.read_section().unwrap().location.offset, 108);
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::InvalidSectionFormat(_))
+        ));
+        assert_eq!(reader.resync().unwrap(), 190..325);
+        assert_eq!(reader.read_section().unwrap().location.offset, 325);
+        assert_eq!(reader.read_section().unwrap().location.offset, 414);
+        assert!(reader.read_section().is_err());
+        // No plausible section until the end of the payload
+        assert_eq!(reader.resync().unwrap(), 455..499);
+        assert!(matches!(
+            reader.read_section(),
+            Err(CarReaderError::EndOfSections)
+        ));
+    }
+
+    /// Write the sections of [CARV1_BASIC](crate::testdata::CARV1_BASIC) in a fully indexed CAR v2 file
+    fn fully_indexed_car() -> (Vec<u8>, Vec<crate::wire::v1::Section>) {
+        let mut source = crate::CarReader::from_bytes(crate::testdata::CARV1_BASIC).unwrap();
+        let sections: Vec<_> = std::iter::from_fn(|| source.read_section().ok())
+            .map(|section| section.section)
+            .collect();
+        let mut writer =
+            crate::CarWriter::new(crate::CarFormat::V2, vec![sections[0].cid().clone()]);
+        for section in &sections {
+            writer.write_section(section).unwrap();
+        }
+        writer.finish();
+        let mut car = Vec::new();
+        let mut buf = vec![0u8; 4096];
+        while writer.has_data_to_send() {
+            let (offset, length) = writer.send_data(&mut buf);
+            car.resize(car.len().max(offset + length), 0);
+            car[offset..offset + length].copy_from_slice(&buf[..length]);
+        }
+        (car, sections)
+    }
+
+    #[test]
+    fn test_car_v2_find_section_indexed() {
+        let (car, sections) = fully_indexed_car();
+        let mut reader = CarReader::new();
+        reader.receive_data(&car[..51], 0);
+        while let Err(CarReaderError::InsufficientData(offset, hint)) = reader.read_header() {
+            let end = (offset + hint.max(1)).min(car.len());
+            reader.receive_data(&car[offset..end], offset);
+        }
+        let (_, header) = reader.header().unwrap();
+        assert!(header.characteristics.has_full_index());
+        let index_offset = header.index_offset as usize;
+
+        // Only the index and the section itself are requested, never the sections before it
+        let target = sections.last().unwrap();
+        let mut requested = Vec::new();
+        let found = loop {
+            match reader.find_section(target.cid()) {
+                Ok(found) => break found,
+                Err(CarReaderError::InsufficientData(offset, hint)) => {
+                    requested.push(offset);
+                    let end = (offset + hint.max(1)).min(car.len());
+                    reader.receive_data(&car[offset..end], offset);
+                }
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+        };
+        assert_eq!(found.cid(), target.cid());
+        assert_eq!(requested.first(), Some(&index_offset));
+        assert!(
+            requested
+                .iter()
+                .all(|offset| *offset >= found.location.offset as usize)
+        );
+        assert_eq!(reader.read_index().unwrap().unwrap().len(), sections.len());
+
+        // A CID absent from the full index is not searched in the payload
+        let absent = RawCid::new(
+            [0x01, 0x55, 0x12, 0x20]
+                .into_iter()
+                .chain([0xab; 32])
+                .collect(),
+        );
+        assert!(matches!(
+            reader.find_section(&absent),
+            Err(CarReaderError::SectionNotFound)
+        ));
+
+        // Without a full index, the sections are searched sequentially, until the end of the payload
+        let mut reader = CarReader::new();
+        reader.receive_data(CAR_V2, 0);
+        reader.read_header().unwrap();
+        assert!(!reader.header().unwrap().1.characteristics.has_full_index());
+        assert!(matches!(
+            reader.find_section(&absent),
+            Err(CarReaderError::EndOfSections)
+        ));
+    }
 }
@@ -1,9 +1,33 @@
-use crate::wire::cid::RawCid;
+use std::ops::Range;
+
+use crate::trace::{trace_event, warn_event};
+use crate::wire::cid::{CidParsing, RawCid};
+use crate::wire::limits::Limits;
 use crate::wire::v1;
 use crate::wire::v2::{
-    CAR_V2_PRAGMA, LocatableSection, SectionFormatError, SectionLocation, header,
+    CAR_V2_PRAGMA, Index, IndexFormatError, LocatableSection, PrePayloadPadding,
+    SectionFormatError, SectionLocation, header,
 };
 
+/// Minimum hint length requested while the index is incomplete
+///
+/// The index spans until the end of the file, so its length is only known once decoded: the hints
+/// grow with the buffered bytes, so that large indexes are received in a few rounds.
+const INDEX_READ_HINT: usize = 4096;
+
+/// Checking mode of the pre-payload padding (see [CarV2Header::pre_payload_padding](header::CarV2Header::pre_payload_padding))
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PaddingCheck {
+    /// The padding is not read at all
+    Skip,
+    /// The padding is read before the CARv1 header, and non-zero bytes are reported as a warning event
+    #[default]
+    Warn,
+    /// The padding is read before the CARv1 header, and non-zero bytes are rejected with
+    /// [CarReaderError::NonZeroPadding]
+    Strict,
+}
+
 /// CARv2 Reader
 #[derive(Debug, Clone)]
 pub struct CarReader(CarReaderState);
@@ -21,27 +45,226 @@ struct NoHeaderState {
     data: Vec<u8>,
     /// Internal data start position
     start: usize,
+    /// Minimum hint length returned in InsufficientData errors
+    min_read_hint: usize,
+    /// Size limits applied to the header and the sections
+    limits: Limits,
+    /// Parsing mode of the section CIDs
+    cid_parsing: CidParsing,
+    /// Checking mode of the pre-payload padding
+    padding_check: PaddingCheck,
+    /// Total length of the file, once declared by [CarReader::finish]
+    end: Option<usize>,
+}
+
+impl NoHeaderState {
+    /// Build an InsufficientData error requesting the bytes right after the buffered ones, or a
+    /// [CarReaderError::Truncated] error once the end of the file is reached
+    fn insufficient_data(&self, needed: usize) -> CarReaderError {
+        match self.end {
+            Some(end) if self.data.len() >= end => CarReaderError::Truncated {
+                missing_bytes: needed.max(1),
+            },
+            _ => CarReaderError::InsufficientData(self.data.len(), needed.max(self.min_read_hint)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct HeaderState {
     /// CAR v2 header
     header: header::CarV2Header,
+    /// Pre-payload padding, between the CAR v2 header and the CAR v1 payload
+    padding: PrePayloadPadding,
     /// Inner CAR v1 reader
     ///
     /// Used to read the CAR v1 sections within the CAR v2 file.
     v1_reader: v1::CarReader,
+    /// Index of the CAR v2 file, received after the headers
+    index: IndexState,
+}
+
+#[derive(Debug, Clone, Default)]
+struct IndexState {
+    /// Index bytes received so far, from the index offset
+    data: Vec<u8>,
+    /// Decoded index, once fully received
+    index: Option<Index>,
+}
+
+impl IndexState {
+    /// Buffer the bytes of the index region (from `index_offset` until the end of the file)
+    fn receive_data(&mut self, index_offset: u64, buf: &[u8], pos: usize) {
+        if index_offset == 0 || self.index.is_some() {
+            return;
+        }
+        let end = (index_offset as usize).saturating_add(self.data.len());
+        if pos <= end && pos.saturating_add(buf.len()) > end {
+            // Only append the bytes not buffered yet
+            self.data
+                .extend_from_slice(buf.get(end.saturating_sub(pos)..).unwrap_or_default());
+        }
+    }
 }
 
 impl CarReader {
     /// Creates a new CAR v2 reader
     pub fn new() -> Self {
+        Self::with_min_read_hint(0)
+    }
+
+    /// Creates a new CAR v2 reader with a minimum read hint
+    ///
+    /// The minimum read hint is a floor applied to every hint length returned with
+    /// [CarReaderError::InsufficientData], see [v1::CarReader::with_min_read_hint] for more details.
+    pub fn with_min_read_hint(min_read_hint: usize) -> Self {
         CarReader(CarReaderState::NoHeader(NoHeaderState {
             data: Vec::new(),
             start: 0,
+            min_read_hint,
+            limits: Limits::default(),
+            cid_parsing: CidParsing::default(),
+            padding_check: PaddingCheck::default(),
+            end: None,
         }))
     }
 
+    /// Get the minimum hint length returned in InsufficientData errors
+    pub fn min_read_hint(&self) -> usize {
+        match &self.0 {
+            CarReaderState::NoHeader(state) => state.min_read_hint,
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.min_read_hint()
+            }
+        }
+    }
+
+    /// Set the minimum hint length returned in InsufficientData errors
+    pub fn set_min_read_hint(&mut self, min_read_hint: usize) {
+        match &mut self.0 {
+            CarReaderState::NoHeader(state) => state.min_read_hint = min_read_hint,
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.set_min_read_hint(min_read_hint)
+            }
+        }
+    }
+
+    /// Capacity of the internal buffers (sections, and index), in bytes
+    ///
+    /// See [v1::CarReader::buffer_capacity].
+    pub fn buffer_capacity(&self) -> usize {
+        match &self.0 {
+            CarReaderState::NoHeader(state) => state.data.capacity(),
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => state
+                .v1_reader
+                .buffer_capacity()
+                .saturating_add(state.index.data.capacity()),
+        }
+    }
+
+    /// Release the memory of the internal buffers which is not needed by the unconsumed bytes
+    ///
+    /// See [v1::CarReader::shrink].
+    pub fn shrink(&mut self) {
+        match &mut self.0 {
+            CarReaderState::NoHeader(state) => state.data.shrink_to_fit(),
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.shrink();
+                state.index.data.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Declare the end of the file, with its total length
+    ///
+    /// See [v1::CarReader::finish]. The end of the sections is already known from the CAR v2 header, so this is
+    /// only needed to detect truncated files: reading a header or section cut by the end of the file then returns
+    /// [CarReaderError::Truncated] instead of requesting more data.
+    pub fn finish(&mut self, total_len: usize) {
+        match &mut self.0 {
+            CarReaderState::NoHeader(state) => state.end = Some(total_len),
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                finish_payload(&state.header, &mut state.v1_reader, total_len)
+            }
+        }
+    }
+
+    /// Get the size limits applied to the header and the sections
+    pub fn limits(&self) -> &Limits {
+        match &self.0 {
+            CarReaderState::NoHeader(state) => &state.limits,
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.limits()
+            }
+        }
+    }
+
+    /// Set the size limits applied to the header and the sections
+    pub fn set_limits(&mut self, limits: Limits) {
+        match &mut self.0 {
+            CarReaderState::NoHeader(state) => state.limits = limits,
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.set_limits(limits)
+            }
+        }
+    }
+
+    /// Get the parsing mode of the section CIDs
+    pub fn cid_parsing(&self) -> CidParsing {
+        match &self.0 {
+            CarReaderState::NoHeader(state) => state.cid_parsing,
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.cid_parsing()
+            }
+        }
+    }
+
+    /// Set the parsing mode of the section CIDs
+    ///
+    /// See [v1::CarReader::set_cid_parsing] for more details.
+    pub fn set_cid_parsing(&mut self, cid_parsing: CidParsing) {
+        match &mut self.0 {
+            CarReaderState::NoHeader(state) => state.cid_parsing = cid_parsing,
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state.v1_reader.set_cid_parsing(cid_parsing)
+            }
+        }
+    }
+
+    /// Get the checking mode of the pre-payload padding
+    ///
+    /// Once the CAR v2 header is read, the mode no longer applies, see [CarReader::pre_payload_padding].
+    pub fn padding_check(&self) -> Option<PaddingCheck> {
+        match &self.0 {
+            CarReaderState::NoHeader(state) => Some(state.padding_check),
+            _ => None,
+        }
+    }
+
+    /// Set the checking mode of the pre-payload padding
+    ///
+    /// Unless the mode is [PaddingCheck::Skip], the padding between the CAR v2 header and the CAR v1 payload is
+    /// requested (with [CarReaderError::InsufficientData]) and checked before the CAR v1 header. The mode only
+    /// applies if it is set before the CAR v2 header is read.
+    ///
+    /// A padding larger than [Limits::max_header_size] is never buffered: it is left unchecked with a warning, or
+    /// rejected with [CarReaderError::HeaderTooLarge] in [PaddingCheck::Strict] mode.
+    pub fn set_padding_check(&mut self, padding_check: PaddingCheck) {
+        if let CarReaderState::NoHeader(state) = &mut self.0 {
+            state.padding_check = padding_check;
+        }
+    }
+
+    /// Get the pre-payload padding, once the CAR v2 header is read
+    pub fn pre_payload_padding(&self) -> Option<&PrePayloadPadding> {
+        match &self.0 {
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                Some(&state.padding)
+            }
+            CarReaderState::NoHeader(_) => None,
+        }
+    }
+
     /// Has the header been read?
     pub fn has_header(&self) -> bool {
         matches!(self.0, CarReaderState::HeaderV1(_))
@@ -50,13 +273,69 @@ impl CarReader {
     /// Get the CAR headers if available
     pub fn header(&self) -> Option<(&v1::CarHeader, &header::CarV2Header)> {
         match &self.0 {
-            CarReaderState::HeaderV1(state) => Some((
-                state
-                    .v1_reader
-                    .header()
-                    .expect("Header CARv1 should be present in this state"),
-                &state.header,
-            )),
+            CarReaderState::HeaderV1(state) => state
+                .v1_reader
+                .header()
+                .map(|v1_header| (v1_header, &state.header)),
+            _ => None,
+        }
+    }
+
+    /// Take the bytes of the CAR v1 payload buffered but not consumed yet (e.g. a partially received section)
+    ///
+    /// The reader keeps its position, as if the bytes had never been received.
+    ///
+    /// ## Returns
+    /// - `Some((offset, bytes))` with the absolute offset of the first buffered byte, and the buffered bytes.
+    /// - `None` if the headers are not read yet.
+    pub(crate) fn take_buffered(&mut self) -> Option<(usize, Vec<u8>)> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let (start, data) = state.v1_reader.take_buffered();
+                Some((
+                    (state.header.data_offset as usize).saturating_add(start),
+                    data,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Create a reader with already parsed headers, around the inner CAR v1 reader (positioned in the payload)
+    ///
+    /// The pre-payload padding is reported as not checked. See [CarReader::restore_state](crate::CarReader::restore_state).
+    pub(crate) fn restore(header: header::CarV2Header, v1_reader: v1::CarReader) -> Self {
+        CarReader(CarReaderState::HeaderV1(HeaderState {
+            padding: PrePayloadPadding {
+                range: header.pre_payload_padding(),
+                first_nonzero: None,
+                checked: false,
+            },
+            header,
+            v1_reader,
+            index: IndexState::default(),
+        }))
+    }
+
+    /// Inner CAR v1 reader, once the headers are read
+    pub(crate) fn payload_reader(&self) -> Option<&v1::CarReader> {
+        match &self.0 {
+            CarReaderState::HeaderV1(state) => Some(&state.v1_reader),
+            _ => None,
+        }
+    }
+
+    /// Range of the absolute offsets where sections can start (within the CAR v1 payload, after its header)
+    pub(crate) fn sections_range(&self) -> Option<Range<u64>> {
+        match &self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let data_offset = state.header.data_offset;
+                let sections = state.v1_reader.sections_range()?;
+                Some(
+                    data_offset.saturating_add(sections.start)
+                        ..data_offset.saturating_add(state.header.data_size),
+                )
+            }
             _ => None,
         }
     }
@@ -65,22 +344,63 @@ impl CarReader {
     pub fn receive_data(&mut self, buf: &[u8], pos: usize) {
         match &mut self.0 {
             CarReaderState::NoHeader(state) => {
-                if pos != state.start + state.data.len() {
+                let end = state.start.saturating_add(state.data.len());
+                if !(state.start..=end).contains(&pos) {
                     // Out of order data, ignore
                     return;
                 }
-                state.data.extend_from_slice(buf);
+                // Only append the bytes not buffered yet
+                state
+                    .data
+                    .extend_from_slice(buf.get(end.saturating_sub(pos)..).unwrap_or_default());
             }
             CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state
+                    .index
+                    .receive_data(state.header.index_offset, buf, pos);
                 let v1_data_start = state.header.data_offset as usize;
-                let v1_data_end = v1_data_start + state.header.data_size as usize;
-                if pos < v1_data_start || pos >= v1_data_end {
+                let v1_data_end = v1_data_start.saturating_add(state.header.data_size as usize);
+                if pos.saturating_add(buf.len()) <= v1_data_start || pos >= v1_data_end {
                     // Out of bounds data, ignore
                     return;
                 }
-                let pos = pos - v1_data_start;
-                let len = buf.len().min(v1_data_end - pos);
-                state.v1_reader.receive_data(&buf[..len], pos);
+                // Do not feed the bytes around the CAR v1 payload (padding, index) to the CAR v1 reader
+                let skip = v1_data_start.saturating_sub(pos);
+                let len = buf.len().min(v1_data_end.saturating_sub(pos));
+                state.v1_reader.receive_data(
+                    buf.get(skip..len).unwrap_or_default(),
+                    pos.saturating_add(skip).saturating_sub(v1_data_start),
+                );
+            }
+        }
+    }
+
+    /// Receive data held in [Bytes](bytes::Bytes), without copying the CAR v1 payload when possible
+    ///
+    /// See [v1::CarReader::receive_bytes]. The headers and the index are still copied into the internal buffers.
+    #[cfg(feature = "bytes")]
+    #[doc(cfg(feature = "bytes"))]
+    pub fn receive_bytes(&mut self, buf: impl Into<bytes::Bytes>, pos: usize) {
+        let buf = buf.into();
+        match &mut self.0 {
+            CarReaderState::NoHeader(_) => self.receive_data(&buf, pos),
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => {
+                state
+                    .index
+                    .receive_data(state.header.index_offset, &buf, pos);
+                let v1_data_start = state.header.data_offset as usize;
+                let v1_data_end = v1_data_start.saturating_add(state.header.data_size as usize);
+                if pos.saturating_add(buf.len()) <= v1_data_start || pos >= v1_data_end {
+                    // Out of bounds data, ignore
+                    return;
+                }
+                // Like receive_data, only the CAR v1 payload is fed, as a slice of the same chunk
+                let skip = v1_data_start.saturating_sub(pos);
+                let len = buf.len().min(v1_data_end.saturating_sub(pos));
+                state.v1_reader.receive_bytes(
+                    buf.slice(skip..len),
+                    pos.saturating_add(skip).saturating_sub(v1_data_start),
+                );
             }
         }
     }
@@ -92,25 +412,96 @@ impl CarReader {
         match &mut self.0 {
             CarReaderState::NoHeader(state) => {
                 if state.data.len() < 51 {
-                    return Err(CarReaderError::InsufficientData(
-                        state.data.len(),
-                        51 - state.data.len(),
-                    ));
+                    return Err(state.insufficient_data(51usize.saturating_sub(state.data.len())));
                 }
 
-                if &state.data[0..11] != CAR_V2_PRAGMA {
+                if state.data.get(..11) != Some(CAR_V2_PRAGMA) {
                     return Err(CarReaderError::InvalidVersion);
                 }
 
-                let header_bytes: [u8; 40] = state.data[11..51].try_into().unwrap();
+                let Some(Ok(header_bytes)) = state.data.get(11..51).map(<[u8; 40]>::try_from)
+                else {
+                    return Err(CarReaderError::InvalidFormat);
+                };
                 let header = header::CarV2Header::from(header_bytes);
-                let mut v1_reader = v1::CarReader::new();
+                // The offsets computed from the header must never overflow
+                if header
+                    .data_offset
+                    .checked_add(header.data_size)
+                    .is_none_or(|end| end > usize::MAX as u64)
+                {
+                    return Err(CarReaderError::InvalidFormat);
+                }
+                let mut padding = PrePayloadPadding {
+                    range: header.pre_payload_padding(),
+                    first_nonzero: None,
+                    checked: false,
+                };
+                // The padding is buffered to be checked: beyond the maximum header size, it is only accepted unchecked
+                if state.padding_check != PaddingCheck::Skip
+                    && !state.limits.allows_header(padding.len())
+                {
+                    if state.padding_check == PaddingCheck::Strict {
+                        return Err(CarReaderError::HeaderTooLarge(padding.len()));
+                    }
+                    warn_event!(
+                        WIRE_V2,
+                        length = padding.len(),
+                        "Pre-payload padding too large to be checked"
+                    );
+                } else if state.padding_check != PaddingCheck::Skip && !padding.is_empty() {
+                    let (start, end) = (padding.range.start as usize, padding.range.end as usize);
+                    if state.data.len() < end {
+                        return Err(state.insufficient_data(end.saturating_sub(state.data.len())));
+                    }
+                    padding.first_nonzero = state
+                        .data
+                        .get(start..end)
+                        .unwrap_or_default()
+                        .iter()
+                        .position(|byte| *byte != 0)
+                        .map(|pos| padding.range.start.saturating_add(pos as u64));
+                    padding.checked = true;
+                    if let Some(offset) = padding.first_nonzero {
+                        if state.padding_check == PaddingCheck::Strict {
+                            return Err(CarReaderError::NonZeroPadding(offset));
+                        }
+                        warn_event!(
+                            WIRE_V2,
+                            offset,
+                            length = padding.len(),
+                            "Non-zero pre-payload padding"
+                        );
+                    }
+                }
+                trace_event!(
+                    WIRE_V2,
+                    data_offset = header.data_offset,
+                    data_size = header.data_size,
+                    index_offset = header.index_offset,
+                    full_index = header.characteristics.has_full_index(),
+                    "Header parsed"
+                );
+                let mut index = IndexState::default();
+                index.receive_data(header.index_offset, &state.data, 0);
+                let mut v1_reader = v1::CarReader::with_min_read_hint(state.min_read_hint);
+                v1_reader.set_limits(state.limits);
+                v1_reader.set_cid_parsing(state.cid_parsing);
+                if let Some(end) = state.end {
+                    finish_payload(&header, &mut v1_reader, end);
+                }
                 if state.data.len() > header.data_offset as usize {
                     // Feed any available data to the CAR v1 reader
-                    let v1_data_end = (header.data_offset as usize + header.data_size as usize)
+                    let v1_data_end = (header.data_offset as usize)
+                        .saturating_add(header.data_size as usize)
                         .min(state.data.len());
-                    v1_reader
-                        .receive_data(&state.data[header.data_offset as usize..v1_data_end], 0);
+                    v1_reader.receive_data(
+                        state
+                            .data
+                            .get(header.data_offset as usize..v1_data_end)
+                            .unwrap_or_default(),
+                        0,
+                    );
                 }
 
                 // Try to read the CAR v1 header
@@ -118,9 +509,21 @@ impl CarReader {
                     v1::CarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidVersion(_) => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+                    v1::CarReaderError::HeaderTooLarge(length) => {
+                        CarReaderError::HeaderTooLarge(length)
+                    }
                     v1::CarReaderError::PreconditionNotMet => CarReaderError::PreconditionNotMet,
+                    v1::CarReaderError::Truncated { missing_bytes } => {
+                        CarReaderError::Truncated { missing_bytes }
+                    }
+                    v1::CarReaderError::EndOfSections => {
+                        CarReaderError::Truncated { missing_bytes: 1 }
+                    }
                     v1::CarReaderError::InsufficientData(offset, hint) => {
-                        CarReaderError::InsufficientData(header.data_offset as usize + offset, hint)
+                        CarReaderError::InsufficientData(
+                            (header.data_offset as usize).saturating_add(offset),
+                            hint,
+                        )
                     }
                     v1::CarReaderError::InvalidSectionFormat(e) => {
                         CarReaderError::InvalidSectionFormat(e)
@@ -128,12 +531,22 @@ impl CarReader {
                 }) {
                     Ok(_) => {
                         // Successfully read both headers -> Fully initialized
-                        self.0 = CarReaderState::HeaderV1(HeaderState { header, v1_reader });
+                        self.0 = CarReaderState::HeaderV1(HeaderState {
+                            header,
+                            padding,
+                            v1_reader,
+                            index,
+                        });
                         Ok(())
                     }
                     Err(e) => {
                         // Could not read CAR v1 header yet -> Keep as HeaderV2 state
-                        self.0 = CarReaderState::HeaderV2(HeaderState { header, v1_reader });
+                        self.0 = CarReaderState::HeaderV2(HeaderState {
+                            header,
+                            padding,
+                            v1_reader,
+                            index,
+                        });
                         Err(e)
                     }
                 }
@@ -144,10 +557,19 @@ impl CarReader {
                     v1::CarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidVersion(_) => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+                    v1::CarReaderError::HeaderTooLarge(length) => {
+                        CarReaderError::HeaderTooLarge(length)
+                    }
                     v1::CarReaderError::PreconditionNotMet => CarReaderError::PreconditionNotMet,
+                    v1::CarReaderError::Truncated { missing_bytes } => {
+                        CarReaderError::Truncated { missing_bytes }
+                    }
+                    v1::CarReaderError::EndOfSections => {
+                        CarReaderError::Truncated { missing_bytes: 1 }
+                    }
                     v1::CarReaderError::InsufficientData(offset, hint) => {
                         CarReaderError::InsufficientData(
-                            state.header.data_offset as usize + offset,
+                            (state.header.data_offset as usize).saturating_add(offset),
                             hint,
                         )
                     }
@@ -164,16 +586,125 @@ impl CarReader {
         }
     }
 
+    /// Read the index of the CAR v2 file, if it has one
+    ///
+    /// The index starts at [CarV2Header::index_offset](header::CarV2Header::index_offset) and spans until
+    /// the end of the file. Its bytes are requested through [CarReaderError::InsufficientData] until it can
+    /// be decoded, then the decoded index is kept by the reader.
+    ///
+    /// ## Returns
+    /// - `Ok(Some(index))` once the index is received and decoded.
+    /// - `Ok(None)` if the file has no index (`index_offset` is 0).
+    /// - `Err(CarReaderError::InsufficientData(offset, hint))` if more bytes of the index are needed.
+    /// - `Err(CarReaderError::InvalidIndex(_))` if the index is malformed or of an unknown type.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the CAR v2 header is not read yet.
+    pub fn read_index(&mut self) -> Result<Option<&Index>, CarReaderError> {
+        let state = match &mut self.0 {
+            CarReaderState::HeaderV2(state) | CarReaderState::HeaderV1(state) => state,
+            CarReaderState::NoHeader(_) => return Err(CarReaderError::PreconditionNotMet),
+        };
+        let index_offset = state.header.index_offset as usize;
+        if index_offset == 0 {
+            return Ok(None);
+        }
+        if state.index.index.is_none() {
+            match Index::decode(&state.index.data) {
+                Ok((index, _)) => {
+                    state.index.data = Vec::new();
+                    state.index.index = Some(index);
+                }
+                Err(IndexFormatError::InsufficientData) => {
+                    let received = state.index.data.len();
+                    return Err(CarReaderError::InsufficientData(
+                        index_offset.saturating_add(received),
+                        received
+                            .max(INDEX_READ_HINT)
+                            .max(state.v1_reader.min_read_hint()),
+                    ));
+                }
+                Err(e) => return Err(CarReaderError::InvalidIndex(e)),
+            }
+        }
+        Ok(state.index.index.as_ref())
+    }
+
+    /// Find and return the section with the given CID
+    ///
+    /// If the file has a full index, the index is read (see [CarReader::read_index]) and the reader jumps
+    /// directly to the section offset. Otherwise, the sections are searched sequentially from the current
+    /// position, see [v1::CarReader::find_section] for more details. Locations are absolute.
+    ///
+    /// ## Returns
+    /// - `Ok(section)` with the found section.
+    /// - `Err(CarReaderError::SectionNotFound)` if the CID is absent from the full index.
+    /// - `Err(CarReaderError::InsufficientData(offset, hint))` if more bytes (of the index or of the sections)
+    ///   are needed.
     pub fn find_section(&mut self, cid: &RawCid) -> Result<LocatableSection, CarReaderError> {
-        // TODO: Use the index if available to find the section location more efficiently instead of searching sequentially
-        match &mut self.0 {
-            CarReaderState::HeaderV1(state) => state
+        self.seek_indexed(|index| index.find(cid))?;
+        self.find_in_payload(|reader| reader.find_section(cid))
+    }
+
+    /// Find and return the first section whose CID has the given multihash digest
+    ///
+    /// See [v1::CarReader::find_section_by_digest] for more details, locations are absolute.
+    /// Like [CarReader::find_section], the full index is used if the file has one.
+    pub fn find_section_by_digest(
+        &mut self,
+        digest: &[u8],
+    ) -> Result<LocatableSection, CarReaderError> {
+        self.seek_indexed(|index| index.find_digest(None, digest))?;
+        self.find_in_payload(|reader| reader.find_section_by_digest(digest))
+    }
+
+    /// Position the inner CAR v1 reader on the section found in the full index, if the file has one
+    ///
+    /// Without a full index, the reader is left untouched (the search falls back to a sequential scan).
+    /// The reader is only moved if it is not already on the section, so that the bytes received after
+    /// a [CarReaderError::InsufficientData] are kept.
+    fn seek_indexed(
+        &mut self,
+        lookup: impl FnOnce(&Index) -> Option<u64>,
+    ) -> Result<(), CarReaderError> {
+        let CarReaderState::HeaderV1(state) = &self.0 else {
+            return Err(CarReaderError::PreconditionNotMet);
+        };
+        if !state.header.characteristics.has_full_index() {
+            return Ok(());
+        }
+        let Some(index) = self.read_index()? else {
+            return Ok(());
+        };
+        let offset = lookup(index).ok_or(CarReaderError::SectionNotFound)?;
+        let CarReaderState::HeaderV1(state) = &mut self.0 else {
+            return Err(CarReaderError::PreconditionNotMet);
+        };
+        if offset >= state.header.data_size {
+            return Err(CarReaderError::InvalidFormat);
+        }
+        if state.v1_reader.position() != offset as usize {
+            trace_event!(WIRE_V2, offset, "Section found in the index");
+            state
                 .v1_reader
-                .find_section(cid)
+                .seek_to(&SectionLocation { offset, length: 0 })
+                .map_err(|_| CarReaderError::InvalidFormat)?;
+        }
+        Ok(())
+    }
+
+    /// Run a search of the inner CAR v1 reader, converting its locations and errors to the CAR v2 file
+    fn find_in_payload(
+        &mut self,
+        find: impl FnOnce(&mut v1::CarReader) -> Result<LocatableSection, v1::CarReaderError>,
+    ) -> Result<LocatableSection, CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => find(&mut state.v1_reader)
                 .map(|locsec| LocatableSection {
                     section: locsec.section,
                     location: SectionLocation {
-                        offset: state.header.data_offset + locsec.location.offset,
+                        offset: state
+                            .header
+                            .data_offset
+                            .saturating_add(locsec.location.offset),
                         length: locsec.location.length,
                     },
                 })
@@ -181,15 +712,29 @@ impl CarReader {
                     v1::CarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidVersion(_) => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+                    v1::CarReaderError::HeaderTooLarge(length) => {
+                        CarReaderError::HeaderTooLarge(length)
+                    }
                     v1::CarReaderError::InvalidSectionFormat(e) => {
                         CarReaderError::InvalidSectionFormat(e)
                     }
                     v1::CarReaderError::PreconditionNotMet => CarReaderError::PreconditionNotMet,
+                    v1::CarReaderError::Truncated { missing_bytes } => {
+                        CarReaderError::Truncated { missing_bytes }
+                    }
+                    v1::CarReaderError::EndOfSections => {
+                        CarReaderError::Truncated { missing_bytes: 1 }
+                    }
                     v1::CarReaderError::InsufficientData(offset, hint) => {
-                        CarReaderError::InsufficientData(
-                            state.header.data_offset as usize + offset,
-                            hint,
-                        )
+                        // Like read_section, the search ends with the CAR v1 payload
+                        if offset < state.header.data_size as usize {
+                            CarReaderError::InsufficientData(
+                                (state.header.data_offset as usize).saturating_add(offset),
+                                hint,
+                            )
+                        } else {
+                            CarReaderError::EndOfSections
+                        }
                     }
                 }),
             _ => Err(CarReaderError::PreconditionNotMet),
@@ -205,7 +750,10 @@ impl CarReader {
                     .map(|locsec| LocatableSection {
                         section: locsec.section,
                         location: SectionLocation {
-                            offset: state.header.data_offset + locsec.location.offset,
+                            offset: state
+                                .header
+                                .data_offset
+                                .saturating_add(locsec.location.offset),
                             length: locsec.location.length,
                         },
                     })
@@ -213,17 +761,26 @@ impl CarReader {
                         v1::CarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
                         v1::CarReaderError::InvalidVersion(_) => CarReaderError::InvalidFormat,
                         v1::CarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+                        v1::CarReaderError::HeaderTooLarge(length) => {
+                            CarReaderError::HeaderTooLarge(length)
+                        }
                         v1::CarReaderError::InvalidSectionFormat(e) => {
                             CarReaderError::InvalidSectionFormat(e)
                         }
                         v1::CarReaderError::PreconditionNotMet => {
                             CarReaderError::PreconditionNotMet
                         }
+                        v1::CarReaderError::Truncated { missing_bytes } => {
+                            CarReaderError::Truncated { missing_bytes }
+                        }
+                        v1::CarReaderError::EndOfSections => {
+                            CarReaderError::Truncated { missing_bytes: 1 }
+                        }
                         v1::CarReaderError::InsufficientData(offset, hint) => {
                             // Check if the offset is within the CAR v1 data range
                             if offset < state.header.data_size as usize {
                                 CarReaderError::InsufficientData(
-                                    state.header.data_offset as usize + offset,
+                                    (state.header.data_offset as usize).saturating_add(offset),
                                     hint,
                                 )
                             } else {
@@ -236,6 +793,159 @@ impl CarReader {
         }
     }
 
+    /// Read the next section, borrowing its block data from the internal buffer
+    ///
+    /// See [v1::CarReader::read_section_ref], the location is absolute like in [CarReader::read_section].
+    pub fn read_section_ref(
+        &mut self,
+    ) -> Result<(SectionLocation, v1::SectionRef<'_>), CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let header = &state.header;
+                state
+                    .v1_reader
+                    .read_section_ref()
+                    .map(|(location, section)| (payload_location(header, location), section))
+                    .map_err(|e| payload_error(header, e))
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
+    /// Skip the next section, only reading its length and CID
+    ///
+    /// See [v1::CarReader::skip_section], the location is absolute like in [CarReader::read_section].
+    pub fn skip_section(&mut self) -> Result<(SectionLocation, RawCid), CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let header = &state.header;
+                state
+                    .v1_reader
+                    .skip_section()
+                    .map(|(location, cid)| (payload_location(header, location), cid))
+                    .map_err(|e| payload_error(header, e))
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
+    /// Start reading the next section in chunks, only reading its length and CID
+    ///
+    /// See [v1::CarReader::begin_section], the location is absolute like in [CarReader::read_section].
+    pub fn begin_section(&mut self) -> Result<(SectionLocation, RawCid, usize), CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let header = &state.header;
+                state
+                    .v1_reader
+                    .begin_section()
+                    .map(|(location, cid, block_length)| {
+                        (payload_location(header, location), cid, block_length)
+                    })
+                    .map_err(|e| payload_error(header, e))
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
+    /// Read the next chunk of the block of the section started with [CarReader::begin_section]
+    ///
+    /// See [v1::CarReader::read_block_chunk].
+    pub fn read_block_chunk(&mut self) -> Result<Option<&[u8]>, CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let header = &state.header;
+                state
+                    .v1_reader
+                    .read_block_chunk()
+                    .map_err(|e| payload_error(header, e))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Number of bytes of the block not read yet, while a section is read in chunks
+    pub fn block_remaining(&self) -> usize {
+        match &self.0 {
+            CarReaderState::HeaderV1(state) => state.v1_reader.block_remaining(),
+            _ => 0,
+        }
+    }
+
+    /// Skip the corrupt bytes at the current position, up to the next plausible section
+    ///
+    /// See [v1::CarReader::resync], the range is absolute. The sections must end within the CAR v1 payload: when
+    /// there is no plausible section until its end, the whole rest of the payload is skipped, and the next read
+    /// returns [CarReaderError::EndOfSections].
+    pub fn resync(&mut self) -> Result<Range<u64>, CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let header = &state.header;
+                state
+                    .v1_reader
+                    .resync_within(Some(header.data_size as usize))
+                    .map(|range| {
+                        header.data_offset.saturating_add(range.start)
+                            ..header.data_offset.saturating_add(range.end)
+                    })
+                    .map_err(|e| payload_error(header, e))
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
+    /// Read the next section, with its block data as a [Bytes](bytes::Bytes) slice of the received chunk
+    ///
+    /// See [v1::CarReader::read_section_bytes], the location is absolute like in [CarReader::read_section].
+    #[cfg(feature = "bytes")]
+    #[doc(cfg(feature = "bytes"))]
+    pub fn read_section_bytes(
+        &mut self,
+    ) -> Result<(SectionLocation, v1::SectionBytes), CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let header = &state.header;
+                state
+                    .v1_reader
+                    .read_section_bytes()
+                    .map(|(location, section)| (payload_location(header, location), section))
+                    .map_err(|e| payload_error(header, e))
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
+    /// Seek to the section at the given location
+    ///
+    /// The location is absolute (from the start of the CAR v2 file), as returned by [CarReader::read_section].
+    /// The next [CarReaderError::InsufficientData] requests exactly `location.offset`.
+    ///
+    /// ## Returns
+    /// - `Ok(())` if the reader is positioned on the location.
+    /// - `Err(CarReaderError::PreconditionNotMet)` if the headers are not read yet,
+    ///   or if the location is outside of the CAR v1 payload.
+    pub fn seek_to(&mut self, location: &SectionLocation) -> Result<(), CarReaderError> {
+        match &mut self.0 {
+            CarReaderState::HeaderV1(state) => {
+                let data_offset = state.header.data_offset;
+                if location.offset < data_offset
+                    || location.offset >= data_offset.saturating_add(state.header.data_size)
+                {
+                    return Err(CarReaderError::PreconditionNotMet);
+                }
+                let relative = SectionLocation {
+                    offset: location.offset.saturating_sub(data_offset),
+                    length: location.length,
+                };
+                state
+                    .v1_reader
+                    .seek_to(&relative)
+                    .map_err(|_| CarReaderError::PreconditionNotMet)
+            }
+            _ => Err(CarReaderError::PreconditionNotMet),
+        }
+    }
+
     pub fn seek_first_section(&mut self) -> Result<(), CarReaderError> {
         match &mut self.0 {
             CarReaderState::HeaderV1(state) => {
@@ -243,13 +953,22 @@ impl CarReader {
                     v1::CarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidVersion(_) => CarReaderError::InvalidFormat,
                     v1::CarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+                    v1::CarReaderError::HeaderTooLarge(length) => {
+                        CarReaderError::HeaderTooLarge(length)
+                    }
                     v1::CarReaderError::InvalidSectionFormat(e) => {
                         CarReaderError::InvalidSectionFormat(e)
                     }
                     v1::CarReaderError::PreconditionNotMet => CarReaderError::PreconditionNotMet,
+                    v1::CarReaderError::Truncated { missing_bytes } => {
+                        CarReaderError::Truncated { missing_bytes }
+                    }
+                    v1::CarReaderError::EndOfSections => {
+                        CarReaderError::Truncated { missing_bytes: 1 }
+                    }
                     v1::CarReaderError::InsufficientData(offset, hint) => {
                         CarReaderError::InsufficientData(
-                            state.header.data_offset as usize + offset,
+                            (state.header.data_offset as usize).saturating_add(offset),
                             hint,
                         )
                     }
@@ -260,6 +979,53 @@ impl CarReader {
     }
 }
 
+/// Absolute location of a section read by the inner CAR v1 reader
+fn payload_location(header: &header::CarV2Header, location: SectionLocation) -> SectionLocation {
+    SectionLocation {
+        offset: header.data_offset.saturating_add(location.offset),
+        length: location.length,
+    }
+}
+
+/// Declare the end of the file to the inner CAR v1 reader, if it cuts the CAR v1 payload
+///
+/// Otherwise, the end of the sections is the end of the payload, as declared by the CAR v2 header.
+fn finish_payload(header: &header::CarV2Header, v1_reader: &mut v1::CarReader, total_len: usize) {
+    let data_end = header.data_offset.saturating_add(header.data_size);
+    if (total_len as u64) < data_end {
+        v1_reader.finish(total_len.saturating_sub(header.data_offset as usize));
+    }
+}
+
+/// Map an error of the inner CAR v1 reader, while reading the sections
+pub(super) fn payload_error(header: &header::CarV2Header, e: v1::CarReaderError) -> CarReaderError {
+    match e {
+        v1::CarReaderError::InvalidFormat => CarReaderError::InvalidFormat,
+        v1::CarReaderError::InvalidVersion(_) => CarReaderError::InvalidFormat,
+        v1::CarReaderError::InvalidHeader(e) => CarReaderError::InvalidHeader(e),
+        v1::CarReaderError::HeaderTooLarge(length) => CarReaderError::HeaderTooLarge(length),
+        v1::CarReaderError::InvalidSectionFormat(e) => CarReaderError::InvalidSectionFormat(e),
+        v1::CarReaderError::PreconditionNotMet => CarReaderError::PreconditionNotMet,
+        v1::CarReaderError::Truncated { missing_bytes } => {
+            CarReaderError::Truncated { missing_bytes }
+        }
+        // The inner reader only knows the end of the file when the payload is truncated (see CarReader::finish),
+        // its end of sections is then the start of a missing section
+        v1::CarReaderError::EndOfSections => CarReaderError::Truncated { missing_bytes: 1 },
+        v1::CarReaderError::InsufficientData(offset, hint) => {
+            // The sections end with the CAR v1 payload
+            if offset < header.data_size as usize {
+                CarReaderError::InsufficientData(
+                    (header.data_offset as usize).saturating_add(offset),
+                    hint,
+                )
+            } else {
+                CarReaderError::EndOfSections
+            }
+        }
+    }
+}
+
 /// Errors related to CarReader operations
 #[derive(thiserror::Error, Debug)]
 pub enum CarReaderError {
@@ -268,6 +1034,11 @@ pub enum CarReaderError {
     InvalidFormat,
     #[error("Invalid header format")]
     InvalidHeader(ciborium::de::Error<std::io::Error>),
+    /// The CAR v1 header is larger than the limits, see [Limits::max_header_size]
+    ///
+    /// In [PaddingCheck::Strict] mode, also returned when the pre-payload padding is, as it is buffered to be checked.
+    #[error("Header too large: {0} bytes")]
+    HeaderTooLarge(u64),
     #[error("Invalid CAR version, expected 2")]
     InvalidVersion,
     #[error("Invalid section format")]
@@ -279,7 +1050,7 @@ pub enum CarReaderError {
     ///
     /// # Arguments
     /// * usize - Need to read from this offset
-    /// * usize - Hint length of data to read (if known, otherwise 0)
+    /// * usize - Hint length of data to read (if known, otherwise 0), never below the configured minimum read hint
     #[error("Insufficient data to proceed")]
     InsufficientData(usize, usize),
     /// No more sections available in the CAR file
@@ -288,4 +1059,20 @@ pub enum CarReaderError {
     /// For instance, when you reached the end of the inner CARv1 data in a CARv2 file and try to read another section, you will get this error.
     #[error("No more sections available in the CAR file")]
     EndOfSections,
+    /// The file ends in the middle of a header or of a section (see [CarReader::finish])
+    ///
+    /// `missing_bytes` is the number of bytes missing to complete it, or 1 if its length is not even known.
+    #[error("Truncated CAR file, {missing_bytes} bytes missing")]
+    Truncated { missing_bytes: usize },
+    /// The pre-payload padding contains a non-zero byte, at this absolute offset
+    ///
+    /// Only returned in [PaddingCheck::Strict] mode.
+    #[error("Non-zero pre-payload padding at offset {0}")]
+    NonZeroPadding(u64),
+    /// The CAR v2 index is malformed or of an unknown type
+    #[error("Invalid index: {0}")]
+    InvalidIndex(#[from] IndexFormatError),
+    /// The section is absent from the full index of the file, so it is not in the file
+    #[error("Section not found in the index")]
+    SectionNotFound,
 }
@@ -0,0 +1,344 @@
+use crate::wire::v1;
+use crate::wire::v2::{
+    CarReaderError, CarV2Header, Index, IndexType, extract::decode_head,
+    header::PRAGMA_AND_HEADER_SIZE, read::payload_error,
+};
+
+/// Regenerator of the index of an existing CAR v2 file
+///
+/// CAR v2 files received from third parties frequently lack an index, or carry a stale one. This struct scans the
+/// sections of the CAR v1 payload in a sans-io manner, recording their offsets, and builds a fresh index along with
+/// the patched CAR v2 header (see [Reindexed]). Only the headers of the sections are read: the caller can seek
+/// to the offsets requested by [Reindexer::scan], the block data being skipped.
+///
+/// The old index (if any) is never read, so a corrupt index does not prevent the reindexing.
+///
+/// ## Examples
+/// ```
+/// use navira_car::wire::v2::{CarReaderError, Index, IndexType, Reindexer};
+///
+/// let car_bytes = include_bytes!("../../res/carv2-basic.car");
+/// let mut reindexer = Reindexer::new(IndexType::MultihashIndexSorted);
+/// reindexer.finish(car_bytes.len());
+/// loop {
+///     match reindexer.scan() {
+///         Ok(()) => break,
+///         Err(CarReaderError::InsufficientData(offset, _)) => {
+///             reindexer.receive_data(&car_bytes[offset..], offset);
+///         }
+///         Err(e) => panic!("{e}"),
+///     }
+/// }
+/// let reindexed = reindexer.reindexed().unwrap();
+/// assert_eq!(reindexed.header.index_offset, 499);
+/// let (index, _) = Index::decode(&reindexed.index).unwrap();
+/// assert_eq!(index.len(), 5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Reindexer {
+    /// Type of the index to build
+    index_type: IndexType,
+    /// Bytes received from the start of the file, until the header is parsed
+    head: Vec<u8>,
+    /// Parsed header, once all its bytes are received
+    header: Option<CarV2Header>,
+    /// Reader of the CAR v1 payload, fed with the payload bytes only
+    ///
+    /// Its end is always declared (see [Reindexer::payload_end]), so that sections cut by the end of the payload
+    /// are told apart from the end of the sections.
+    v1_reader: v1::CarReader,
+    /// Total length of the file, once declared by [Reindexer::finish]
+    end: Option<usize>,
+    /// `(multihash code, digest, offset)` of the sections found so far, offsets being relative to the payload
+    entries: Vec<(u64, Vec<u8>, u64)>,
+    /// Have all the sections been scanned?
+    done: bool,
+}
+
+/// Result of a [Reindexer]: the fresh index, and the header locating it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reindexed {
+    /// The patched CAR v2 header, to be written after the pragma (at offset 11)
+    ///
+    /// The index is located right after the CAR v1 payload, and the file is marked as fully indexed. The other
+    /// fields and characteristics are kept.
+    pub header: CarV2Header,
+    /// The encoded index, to be written at `header.index_offset`
+    pub index: Vec<u8>,
+}
+
+impl Reindexed {
+    /// Length of the reindexed file, which ends with the index
+    ///
+    /// The bytes of the old file past this length (e.g. a longer stale index) must be truncated.
+    pub fn file_len(&self) -> u64 {
+        self.header
+            .index_offset
+            .saturating_add(self.index.len() as u64)
+    }
+}
+
+impl Reindexer {
+    /// Creates a new Reindexer, building an index of the given type
+    pub fn new(index_type: IndexType) -> Self {
+        Self {
+            index_type,
+            head: Vec::with_capacity(PRAGMA_AND_HEADER_SIZE as usize),
+            header: None,
+            v1_reader: v1::CarReader::new(),
+            end: None,
+            entries: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Get the CAR v2 header of the file being reindexed, once read
+    pub fn header(&self) -> Option<&CarV2Header> {
+        self.header.as_ref()
+    }
+
+    /// Number of sections indexed so far
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is no section indexed yet?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Declare the end of the file, with its total length
+    ///
+    /// See [CarReader::finish](crate::wire::v2::CarReader::finish): scanning a file whose payload is cut by its
+    /// end then returns [CarReaderError::Truncated] instead of requesting more data.
+    pub fn finish(&mut self, total_len: usize) {
+        self.end = Some(total_len);
+        if let Some(header) = &self.header {
+            let end = self.payload_end(header);
+            self.v1_reader.finish(end);
+        }
+    }
+
+    /// Receives more data to process
+    ///
+    /// Only the pragma, the header and the CAR v1 payload are used, the other bytes are ignored.
+    pub fn receive_data(&mut self, buf: &[u8], pos: usize) {
+        let Some(header) = &self.header else {
+            let end = self.head.len();
+            if pos <= end && pos.saturating_add(buf.len()) > end {
+                // Only append the bytes not buffered yet, the payload ones being fed once the header is parsed
+                self.head
+                    .extend_from_slice(buf.get(end.saturating_sub(pos)..).unwrap_or_default());
+            }
+            return;
+        };
+        let data_start = header.data_offset as usize;
+        let data_end = data_start.saturating_add(header.data_size as usize);
+        if pos.saturating_add(buf.len()) <= data_start || pos >= data_end {
+            return;
+        }
+        let skip = data_start.saturating_sub(pos);
+        let len = buf.len().min(data_end.saturating_sub(pos));
+        self.v1_reader.receive_data(
+            buf.get(skip..len).unwrap_or_default(),
+            pos.saturating_add(skip).saturating_sub(data_start),
+        );
+    }
+
+    /// Scan the sections of the payload, recording their offsets
+    ///
+    /// # Returns
+    /// * Ok(()) - All the sections have been scanned, the index can be built with [Reindexer::reindexed]
+    /// * Err(CarReaderError::InsufficientData(offset, hint)) - More bytes are needed from the given absolute offset,
+    ///   the scan must be called again once they are received
+    /// * Err(CarReaderError) - The file is not a valid CAR v2 file, or its payload is truncated or corrupt
+    pub fn scan(&mut self) -> Result<(), CarReaderError> {
+        if self.done {
+            return Ok(());
+        }
+        let header = match &self.header {
+            Some(header) => header.clone(),
+            None => self.read_header()?,
+        };
+        if !self.v1_reader.has_header() {
+            self.v1_reader.read_header().map_err(|e| match e {
+                // The CAR v1 header must fit in the payload
+                v1::CarReaderError::InsufficientData(offset, _)
+                    if offset >= header.data_size as usize =>
+                {
+                    CarReaderError::InvalidFormat
+                }
+                e => payload_error(&header, e),
+            })?;
+        }
+        loop {
+            let payload_end = self.payload_end(&header) as u64;
+            match self.v1_reader.skip_section() {
+                Ok((location, cid)) => {
+                    // The blocks are skipped, so the last one may overflow the payload unnoticed
+                    let end = location.offset.saturating_add(location.length);
+                    if end > header.data_size {
+                        return Err(CarReaderError::InvalidFormat);
+                    } else if end > payload_end {
+                        let missing_bytes = end.saturating_sub(payload_end) as usize;
+                        return Err(CarReaderError::Truncated { missing_bytes });
+                    }
+                    if let (Some(code), Some(digest)) = (cid.multihash_code(), cid.digest()) {
+                        self.entries.push((code, digest.to_vec(), location.offset));
+                    }
+                }
+                Err(v1::CarReaderError::EndOfSections) if payload_end == header.data_size => {
+                    self.done = true;
+                    return Ok(());
+                }
+                // A section cut by the end of the payload, rather than by the end of the file
+                Err(v1::CarReaderError::Truncated { .. }) if payload_end == header.data_size => {
+                    return Err(CarReaderError::InvalidFormat);
+                }
+                Err(e) => return Err(payload_error(&header, e)),
+            }
+        }
+    }
+
+    /// Build the fresh index, and the header locating it
+    ///
+    /// # Returns
+    /// * Ok(Reindexed) - The index and header to write
+    /// * Err(CarReaderError::PreconditionNotMet) - The sections have not all been scanned yet (see [Reindexer::scan])
+    pub fn reindexed(&self) -> Result<Reindexed, CarReaderError> {
+        let (Some(header), true) = (&self.header, self.done) else {
+            return Err(CarReaderError::PreconditionNotMet);
+        };
+        let index = Index::from_entries(
+            self.index_type,
+            self.entries
+                .iter()
+                .map(|(code, digest, offset)| (*code, digest.as_slice(), *offset)),
+        );
+        let mut header = header.clone();
+        header.characteristics.set_has_full_index(true);
+        header.index_offset = header.data_offset.saturating_add(header.data_size);
+        Ok(Reindexed {
+            header,
+            index: index.encode(),
+        })
+    }
+
+    /// Parse the received pragma and header, then feed the payload bytes already received to the CAR v1 reader
+    fn read_header(&mut self) -> Result<CarV2Header, CarReaderError> {
+        if self.head.len() < PRAGMA_AND_HEADER_SIZE as usize {
+            return Err(CarReaderError::InsufficientData(
+                self.head.len(),
+                (PRAGMA_AND_HEADER_SIZE as usize).saturating_sub(self.head.len()),
+            ));
+        }
+        let header = decode_head(&self.head)?;
+        let end = self.payload_end(&header);
+        self.v1_reader.finish(end);
+        self.header = Some(header.clone());
+        let head = std::mem::take(&mut self.head);
+        self.receive_data(&head, 0);
+        Ok(header)
+    }
+
+    /// End of the CAR v1 payload (relative to its start), or of the file if it cuts the payload
+    fn payload_end(&self, header: &CarV2Header) -> usize {
+        let end = self.end.map_or(usize::MAX, |end| {
+            end.saturating_sub(header.data_offset as usize)
+        });
+        end.min(header.data_size as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::{
+        CARV2_BASIC, CARV2_BASIC_DATA_OFFSET, CARV2_BASIC_DATA_SIZE, CARV2_BASIC_INDEX_OFFSET,
+        CARV2_BASIC_SECTIONS, carv2_basic_index,
+    };
+    use crate::wire::v2::CarReader;
+
+    /// Scan the whole file, feeding the requested bytes `chunk_size` at a time
+    fn reindex(car: &[u8], chunk_size: usize) -> Result<Reindexed, CarReaderError> {
+        let mut reindexer = Reindexer::new(IndexType::MultihashIndexSorted);
+        reindexer.finish(car.len());
+        loop {
+            match reindexer.scan() {
+                Ok(()) => break,
+                Err(CarReaderError::InsufficientData(offset, _)) => {
+                    let end = (offset + chunk_size).min(car.len());
+                    reindexer.receive_data(&car[offset..end], offset);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        reindexer.reindexed()
+    }
+
+    #[test]
+    fn test_reindexer() {
+        let data_end = (CARV2_BASIC_DATA_OFFSET + CARV2_BASIC_DATA_SIZE) as usize;
+        assert_eq!(data_end as u64, CARV2_BASIC_INDEX_OFFSET);
+        for chunk_size in [1, 7, 64, 4096] {
+            let reindexed = reindex(CARV2_BASIC, chunk_size).unwrap();
+            assert_eq!(reindexed.header.index_offset, CARV2_BASIC_INDEX_OFFSET);
+            assert!(reindexed.header.characteristics.has_full_index());
+            assert_eq!(reindexed.index, carv2_basic_index());
+            assert_eq!(
+                reindexed.file_len(),
+                CARV2_BASIC_INDEX_OFFSET + carv2_basic_index().len() as u64
+            );
+        }
+
+        // The index is missing: a new one is built from the payload alone
+        let mut car = CARV2_BASIC[..data_end].to_vec();
+        car[43..51].fill(0);
+        let reindexed = reindex(&car, 64).unwrap();
+        car[11..51].copy_from_slice(&<[u8; 40]>::from(&reindexed.header));
+        car.extend_from_slice(&reindexed.index);
+        let mut reader = CarReader::new();
+        reader.receive_data(&car, 0);
+        reader.read_header().unwrap();
+        assert_eq!(reader.read_index().unwrap().unwrap().len(), 5);
+        for section in CARV2_BASIC_SECTIONS {
+            let found = reader.find_section(&section.cid()).unwrap();
+            assert_eq!(found.location, section.location());
+        }
+
+        // The scan needs all the sections
+        let mut reindexer = Reindexer::new(IndexType::IndexSorted);
+        assert!(matches!(
+            reindexer.reindexed(),
+            Err(CarReaderError::PreconditionNotMet)
+        ));
+        reindexer.receive_data(&car[..200], 0);
+        assert!(matches!(
+            reindexer.scan(),
+            Err(CarReaderError::InsufficientData(200, _))
+        ));
+        assert_eq!(reindexer.len(), 1);
+    }
+
+    #[test]
+    fn test_reindexer_invalid() {
+        let data_end = (CARV2_BASIC_DATA_OFFSET + CARV2_BASIC_DATA_SIZE) as usize;
+        // Truncated payload
+        assert!(matches!(
+            reindex(&CARV2_BASIC[..data_end - 10], 64),
+            Err(CarReaderError::Truncated { .. })
+        ));
+        // Not a CAR v2 file
+        assert!(matches!(
+            reindex(crate::testdata::CARV1_BASIC, 64),
+            Err(CarReaderError::InvalidVersion)
+        ));
+        // The payload size cuts the last section
+        let mut car = CARV2_BASIC.to_vec();
+        car[35..43].copy_from_slice(&(CARV2_BASIC_DATA_SIZE - 10).to_le_bytes());
+        assert!(matches!(
+            reindex(&car, 64),
+            Err(CarReaderError::InvalidFormat)
+        ));
+    }
+}
@@ -1,10 +1,12 @@
-use std::io::Write;
-
 use crate::types::Sealed;
 use crate::wire::{
     cid::RawCid,
-    v1,
-    v2::{CAR_V2_PRAGMA, CarV2Header, Characteristics, Section, SectionLocation},
+    limits::Limits,
+    v1::{self, describe_dangling_links},
+    v2::{
+        CAR_V2_PRAGMA, CarV2Header, Characteristics, Index, IndexType, Section, SectionLocation,
+        WriteManifest, header::PRAGMA_AND_HEADER_SIZE,
+    },
 };
 
 /// CAR v2 writer
@@ -19,7 +21,8 @@ pub trait CarWriteV2State: Sealed {}
 #[derive(Debug, Clone)]
 pub struct SectionWritingState {
     data_start: u64,
-    inner_written_bytes: u64,
+    index_padding: u64,   // Minimal padding between the payload and the index
+    index_alignment: u64, // Alignment of the index offset (0 or 1 for none)
     inner: v1::CarWriter,
 }
 
@@ -29,13 +32,18 @@ pub struct IndexWritingState {
     data_start: u64,
     data_end: u64,
     index_start: u64,
-    index_offset: u64, // Current writting offset from index_start
+    padding_offset: u64, // Offset of the next padding byte to send, up to index_start
+    index_written: u64,  // Bytes of the index sent so far, from index_start
+    entries: Vec<(u64, Vec<u8>, u64)>, // Collected (multihash code, digest, offset) index entries
+    manifest: Option<Box<WriteManifest>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct FinalizedWritingState {
     header: CarV2Header,
     header_saved: bool,
+    padding_offset: u64, // Offset of the next padding byte to send, up to the data offset
+    manifest: Option<Box<WriteManifest>>,
 }
 
 impl Sealed for SectionWritingState {}
@@ -58,27 +66,170 @@ impl CarWriter<SectionWritingState> {
     pub fn with_buffer_size(roots: Vec<RawCid>, buffer_size: usize) -> Self {
         let inner = v1::CarWriter::with_buffer_size(roots, buffer_size);
         let state = SectionWritingState {
-            data_start: 51, // CARv2 pragma + header is 11 + 40 bytes long, so the data starts right after it
-            inner_written_bytes: 0,
+            data_start: PRAGMA_AND_HEADER_SIZE, // By default, the data starts right after the pragma and header
+            index_padding: 0,
+            index_alignment: 0,
             inner,
         };
         Self { state }
     }
 
+    /// Set the offset of the CAR v1 payload, leaving zero padding between the header and the payload
+    ///
+    /// The padding can e.g. reserve space for a future, larger header. Offsets below 51 (the size of the pragma and
+    /// header) are raised to it.
+    ///
+    /// It must be set before writing the first section, as the locations of the written sections are not updated.
+    pub fn with_data_offset(mut self, data_offset: u64) -> Self {
+        self.state.data_start = data_offset.max(PRAGMA_AND_HEADER_SIZE);
+        self
+    }
+
+    /// Align the CAR v1 payload on the given boundary, e.g. 4096 bytes for a page-aligned payload
+    ///
+    /// The payload starts at the first multiple of `alignment` after the header (alignments 0 and 1 leave no padding).
+    /// See [CarWriter::with_data_offset].
+    pub fn with_data_alignment(self, alignment: u64) -> Self {
+        let data_offset = PRAGMA_AND_HEADER_SIZE.next_multiple_of(alignment.max(1));
+        self.with_data_offset(data_offset)
+    }
+
+    /// Leave at least `padding` zero bytes between the CAR v1 payload and the index
+    ///
+    /// The padding is only written along with an index (see [CarWriter::finalize_sections]).
+    pub fn with_index_padding(mut self, padding: u64) -> Self {
+        self.state.index_padding = padding;
+        self
+    }
+
+    /// Align the index on the given boundary, padding the end of the CAR v1 payload with zeros
+    ///
+    /// Combined with [CarWriter::with_index_padding], the index starts at the first multiple of `alignment` after
+    /// the padding (alignments 0 and 1 leave no extra padding).
+    pub fn with_index_alignment(mut self, alignment: u64) -> Self {
+        self.state.index_alignment = alignment;
+        self
+    }
+
+    /// Get the offset of the CAR v1 payload in the CAR v2 file
+    ///
+    /// The offsets recorded in a CAR v2 index are relative to this offset.
+    pub fn data_offset(&self) -> u64 {
+        self.state.data_start
+    }
+
+    /// Get the size limits applied to the written sections
+    pub fn limits(&self) -> &Limits {
+        self.state.inner.limits()
+    }
+
+    /// Set the size limits applied to the written sections
+    ///
+    /// See [v1::CarWriter::set_limits] for more details.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.state.inner.set_limits(limits);
+    }
+
+    /// Is the manifest recording enabled?
+    pub fn record_manifest(&self) -> bool {
+        self.state.inner.record_manifest()
+    }
+
+    /// Enable or disable the manifest recording
+    ///
+    /// See [v1::CarWriter::set_record_manifest] for more details, the manifest is returned by
+    /// [CarWriter::finish_with_manifest] once the CAR v2 file is finalized.
+    pub fn set_record_manifest(&mut self, record_manifest: bool) {
+        self.state.inner.set_record_manifest(record_manifest);
+    }
+
+    /// Take the recorded manifest, with absolute section locations
+    fn take_manifest(&mut self) -> Option<Box<WriteManifest>> {
+        let data_start = self.state.data_start;
+        self.state.inner.take_manifest().map(|mut manifest| {
+            for (_, location) in &mut manifest.sections {
+                location.offset = location.offset.saturating_add(data_start);
+            }
+            manifest.data_offset = data_start;
+            Box::new(manifest)
+        })
+    }
+
     /// Write a section to the CAR stream.
     ///
     /// This method will serialize the section and append it to the current CAR stream.
     /// However, it does not actually write to the underlying sink until `send_data` is called.
+    ///
+    /// Sections exceeding the configured [Limits] are rejected with [CarWriterError::SectionTooLarge] or
+    /// [CarWriterError::BlockTooLarge].
     pub fn write_section(&mut self, section: &Section) -> Result<SectionLocation, CarWriterError> {
         self.state
             .inner
             .write_section(section)
             .map(|loc| SectionLocation {
-                offset: self.state.data_start + loc.offset,
+                offset: self.state.data_start.saturating_add(loc.offset),
+                length: loc.length,
+            })
+            .map_err(CarWriterError::from)
+    }
+
+    /// Write the exact bytes of a section (length prefix, CID and block data) to the CAR stream.
+    ///
+    /// See [v1::CarWriter::write_raw_section] for more details, the returned location is absolute.
+    pub fn write_raw_section(
+        &mut self,
+        bytes: &[u8],
+        cid: &RawCid,
+    ) -> Result<SectionLocation, CarWriterError> {
+        self.state
+            .inner
+            .write_raw_section(bytes, cid)
+            .map(|loc| SectionLocation {
+                offset: self.state.data_start.saturating_add(loc.offset),
                 length: loc.length,
             })
+            .map_err(CarWriterError::from)
+    }
+
+    /// Write a section to the CAR stream, at an offset satisfying the given placement constraint.
+    ///
+    /// Offsets (and alignments) are absolute, from the start of the CAR v2 file.
+    /// See [v1::CarWriter::write_section_placed] for more details on how sections are placed.
+    pub fn write_section_placed(
+        &mut self,
+        section: &Section,
+        placement: v1::Placement,
+    ) -> Result<v1::PlacedSection, CarWriterError> {
+        let data_start = self.state.data_start;
+        let position = data_start.saturating_add(self.state.inner.position());
+        let offset = v1::resolve_placement(placement, position).ok_or(
+            CarWriterError::UnsatisfiablePlacement {
+                placement,
+                position,
+            },
+        )?;
+        let to_absolute = |loc: SectionLocation| SectionLocation {
+            offset: data_start.saturating_add(loc.offset),
+            length: loc.length,
+        };
+        self.state
+            .inner
+            .write_section_placed(
+                section,
+                v1::Placement::At(offset.saturating_sub(data_start)),
+            )
+            .map(|placed| v1::PlacedSection {
+                location: to_absolute(placed.location),
+                padding: placed.padding.into_iter().map(to_absolute).collect(),
+            })
             .map_err(|err| match err {
-                v1::CarWriterError::BufferFull => CarWriterError::BufferFull,
+                v1::CarWriterError::UnsatisfiablePlacement { .. } => {
+                    CarWriterError::UnsatisfiablePlacement {
+                        placement,
+                        position,
+                    }
+                }
+                err => CarWriterError::from(err),
             })
     }
 
@@ -95,9 +246,11 @@ impl CarWriter<SectionWritingState> {
     ///
     /// A tuple (offset, length) indicating the range of bytes in the underlying sink that should be written.
     pub fn send_data(&mut self, buf: &mut [u8]) -> (usize, usize) {
+        let offset = self
+            .state
+            .data_start
+            .saturating_add(self.state.inner.sent_offset());
         let bytes_to_send = self.state.inner.send_data(buf);
-        let offset = self.state.data_start + self.state.inner_written_bytes;
-        self.state.inner_written_bytes += bytes_to_send as u64;
         (offset as usize, bytes_to_send)
     }
 
@@ -108,6 +261,99 @@ impl CarWriter<SectionWritingState> {
         self.state.inner.has_data_to_send()
     }
 
+    /// Start a section whose block is written in chunks, for blocks too large to be held in memory
+    ///
+    /// See [v1::CarWriter::begin_section] for more details, the returned location is absolute.
+    pub fn begin_section(
+        &mut self,
+        cid: &RawCid,
+        block_length: usize,
+    ) -> Result<SectionLocation, CarWriterError> {
+        self.state
+            .inner
+            .begin_section(cid, block_length)
+            .map(|loc| SectionLocation {
+                offset: self.state.data_start.saturating_add(loc.offset),
+                length: loc.length,
+            })
+            .map_err(CarWriterError::from)
+    }
+
+    /// Write the next chunk of the block of the section in progress
+    ///
+    /// See [v1::CarWriter::write_block_chunk] for more details.
+    pub fn write_block_chunk(&mut self, chunk: &[u8]) -> Result<usize, CarWriterError> {
+        Ok(self.state.inner.write_block_chunk(chunk)?)
+    }
+
+    /// Complete the section in progress, once its whole block is written
+    ///
+    /// See [v1::CarWriter::end_section] for more details, the returned location is absolute.
+    pub fn end_section(&mut self) -> Result<SectionLocation, CarWriterError> {
+        self.state
+            .inner
+            .end_section()
+            .map(|loc| SectionLocation {
+                offset: self.state.data_start.saturating_add(loc.offset),
+                length: loc.length,
+            })
+            .map_err(CarWriterError::from)
+    }
+
+    /// Is a section in progress, started with [CarWriter::begin_section] and not yet completed?
+    pub fn section_in_progress(&self) -> bool {
+        self.state.inner.section_in_progress()
+    }
+
+    /// Get the link validation mode
+    pub fn link_validation(&self) -> v1::LinkValidation {
+        self.state.inner.link_validation()
+    }
+
+    /// Set the link validation mode
+    ///
+    /// See [v1::CarWriter::set_link_validation] for more details.
+    pub fn set_link_validation(&mut self, link_validation: v1::LinkValidation) {
+        self.state.inner.set_link_validation(link_validation);
+    }
+
+    /// Links to blocks which have not been written (yet), in the order of their first reference
+    pub fn dangling_links(&self) -> Vec<v1::DanglingLink> {
+        self.state.inner.dangling_links()
+    }
+
+    /// Check the links of the written sections, to be called before [CarWriter::finalize_sections]
+    ///
+    /// See [v1::CarWriter::check_links] for more details.
+    pub fn check_links(&self) -> Result<Vec<v1::DanglingLink>, CarWriterError> {
+        Ok(self.state.inner.check_links()?)
+    }
+
+    /// Get the duplicate suppression mode
+    pub fn dedup(&self) -> v1::WriteDedup {
+        self.state.inner.dedup()
+    }
+
+    /// Set the duplicate suppression mode
+    ///
+    /// See [v1::CarWriter::set_dedup] for more details, the location of the first section is returned as an
+    /// absolute location too.
+    pub fn set_dedup(&mut self, dedup: v1::WriteDedup) {
+        self.state.inner.set_dedup(dedup);
+    }
+
+    /// Set the duplicate suppression mode, remembering the written sections in the given set
+    ///
+    /// See [v1::CarWriter::set_dedup_set], the remembered locations are relative to the CAR v1 payload.
+    pub fn set_dedup_set(&mut self, dedup: v1::WriteDedup, set: Box<dyn v1::DedupSet>) {
+        self.state.inner.set_dedup_set(dedup, set);
+    }
+
+    /// Number of writes suppressed as duplicates so far
+    pub fn skipped_duplicates(&self) -> u64 {
+        self.state.inner.skipped_duplicates()
+    }
+
     /// Finalize the sections writing and transition to index writing state.
     ///
     /// # Args
@@ -115,20 +361,30 @@ impl CarWriter<SectionWritingState> {
     ///
     /// # Returns
     /// * `Ok(CarWriter<IndexWritingState>)` - If the sections are successfully finalized and there is no pending data to be flushed.
-    /// * `Err(Self)` - If there is still data to be flushed, the caller should flush it first before finalizing.
-    pub fn finalize_sections(self) -> Result<CarWriter<IndexWritingState>, Self> {
-        if self.has_data_to_send() {
+    /// * `Err(Self)` - If there is still data to be flushed, the caller should flush it first before finalizing, or
+    ///   if a section is still in progress (see [CarWriter::begin_section]).
+    pub fn finalize_sections(mut self) -> Result<CarWriter<IndexWritingState>, Self> {
+        if self.has_data_to_send() || self.section_in_progress() {
             return Err(self);
         }
 
-        // TODO: Write the correct data size (in header) to file
+        let data_end = self
+            .state
+            .data_start
+            .saturating_add(self.state.inner.sent_offset());
+        let index_start = data_end
+            .saturating_add(self.state.index_padding)
+            .next_multiple_of(self.state.index_alignment.max(1));
         Ok(CarWriter {
             state: IndexWritingState {
                 data: Vec::new(),
                 data_start: self.state.data_start,
-                data_end: self.state.data_start + self.state.inner_written_bytes,
-                index_start: 0,
-                index_offset: 0,
+                data_end,
+                index_start,
+                padding_offset: data_end,
+                index_written: 0,
+                entries: Vec::new(),
+                manifest: self.take_manifest(),
             },
         })
     }
@@ -140,16 +396,17 @@ impl CarWriter<SectionWritingState> {
     ///
     /// # Returns
     /// * `Ok(CarWriter<FinalizedWritingState>)` - If the sections are successfully finalized and there is no pending data to be flushed.
-    /// * `Err(Self)` - If there is still data to be flushed, the caller should flush it first before finalizing.
-    pub fn finalize_all(self) -> Result<CarWriter<FinalizedWritingState>, Self> {
-        if self.has_data_to_send() {
+    /// * `Err(Self)` - If there is still data to be flushed, the caller should flush it first before finalizing, or
+    ///   if a section is still in progress (see [CarWriter::begin_section]).
+    pub fn finalize_all(mut self) -> Result<CarWriter<FinalizedWritingState>, Self> {
+        if self.has_data_to_send() || self.section_in_progress() {
             return Err(self);
         }
 
         let header = CarV2Header {
             characteristics: Characteristics(0),
             data_offset: self.state.data_start,
-            data_size: self.state.inner_written_bytes,
+            data_size: self.state.inner.sent_offset(),
             index_offset: 0,
         };
 
@@ -157,6 +414,8 @@ impl CarWriter<SectionWritingState> {
             state: FinalizedWritingState {
                 header,
                 header_saved: false,
+                padding_offset: PRAGMA_AND_HEADER_SIZE,
+                manifest: self.take_manifest(),
             },
         })
     }
@@ -172,6 +431,29 @@ impl CarWriteV2 for CarWriter<SectionWritingState> {
     }
 }
 
+impl IndexWritingState {
+    /// Offset of the index to record in the header, 0 if no index has been written
+    fn written_index_offset(&self) -> u64 {
+        if self.index_written > 0 {
+            self.index_start
+        } else {
+            0
+        }
+    }
+
+    /// Take the recorded manifest, with the location of the written index
+    fn manifest_with_index(&mut self) -> Option<Box<WriteManifest>> {
+        let index_location = (self.index_written > 0).then_some(SectionLocation {
+            offset: self.index_start,
+            length: self.index_written,
+        });
+        self.manifest.take().map(|mut manifest| {
+            manifest.index_location = index_location;
+            manifest
+        })
+    }
+}
+
 impl CarWriter<IndexWritingState> {
     /// Finalize the index writing and transition to finalized state.
     ///
@@ -181,22 +463,25 @@ impl CarWriter<IndexWritingState> {
     /// # Returns
     /// * `Ok(CarWriter<FinalizedWritingState>)` - If the index is successfully finalized and there is no pending data to be flushed.
     /// * `Err(Self)` - If there is still data to be flushed, the caller should flush it first before finalizing.
-    pub fn finalize_index(self) -> Result<CarWriter<FinalizedWritingState>, Self> {
-        if !self.state.data.is_empty() {
+    ///   Collected index entries must be written first as well (see [CarWriter::write_collected_index]).
+    pub fn finalize_index(mut self) -> Result<CarWriter<FinalizedWritingState>, Self> {
+        if !self.state.data.is_empty() || !self.state.entries.is_empty() {
             return Err(self);
         }
 
         let header = CarV2Header {
             characteristics: Characteristics(0),
             data_offset: self.state.data_start,
-            data_size: self.state.data_end - self.state.data_start,
-            index_offset: self.state.index_start,
+            data_size: self.state.data_end.saturating_sub(self.state.data_start),
+            index_offset: self.state.written_index_offset(),
         };
 
         Ok(CarWriter {
             state: FinalizedWritingState {
                 header,
                 header_saved: false,
+                padding_offset: PRAGMA_AND_HEADER_SIZE,
+                manifest: self.state.manifest_with_index(),
             },
         })
     }
@@ -209,8 +494,9 @@ impl CarWriter<IndexWritingState> {
     /// # Returns
     /// * `Ok(CarWriter<FinalizedWritingState>)` - If the index is successfully finalized and there is no pending data to be flushed.
     /// * `Err(Self)` - If there is still data to be flushed, the caller should flush it first before finalizing.
-    pub fn finalize_full_index(self) -> Result<CarWriter<FinalizedWritingState>, Self> {
-        if !self.state.data.is_empty() {
+    ///   Collected index entries must be written first as well (see [CarWriter::write_collected_index]).
+    pub fn finalize_full_index(mut self) -> Result<CarWriter<FinalizedWritingState>, Self> {
+        if !self.state.data.is_empty() || !self.state.entries.is_empty() {
             return Err(self);
         }
 
@@ -219,18 +505,80 @@ impl CarWriter<IndexWritingState> {
         let header = CarV2Header {
             characteristics: c,
             data_offset: self.state.data_start,
-            data_size: self.state.data_end - self.state.data_start,
-            index_offset: self.state.index_start,
+            data_size: self.state.data_end.saturating_sub(self.state.data_start),
+            index_offset: self.state.written_index_offset(),
         };
 
         Ok(CarWriter {
             state: FinalizedWritingState {
                 header,
                 header_saved: false,
+                padding_offset: PRAGMA_AND_HEADER_SIZE,
+                manifest: self.state.manifest_with_index(),
             },
         })
     }
 
+    /// Get the offset of the CAR v1 payload in the CAR v2 file
+    ///
+    /// The offsets recorded in a CAR v2 index are relative to this offset.
+    pub fn data_offset(&self) -> u64 {
+        self.state.data_start
+    }
+
+    /// Get the offset of the index in the CAR v2 file, after the padding of the payload (if any)
+    pub fn index_offset(&self) -> u64 {
+        self.state.index_start
+    }
+
+    /// Write an index to the CAR stream, after the CAR v1 payload and its padding (if any).
+    ///
+    /// The index offsets must be relative to the CAR v1 payload (see [CarWriter::data_offset]).
+    /// Like the sections, the index is only written to the underlying sink by `send_data`.
+    pub fn write_index(&mut self, index: &Index) {
+        self.state.data.extend(index.encode());
+    }
+
+    /// Collect an index entry for a written section, from its CID and its location
+    ///
+    /// The location is absolute, as returned by [CarWriter::write_section]. CIDs which are not
+    /// well-formed (no multihash to index) are ignored.
+    pub fn add_section_entry(&mut self, cid: &RawCid, location: &SectionLocation) {
+        let (Some(code), Some(digest), Some(offset)) = (
+            cid.multihash_code(),
+            cid.digest(),
+            location.offset.checked_sub(self.state.data_start),
+        ) else {
+            return;
+        };
+        self.add_index_entry(code, digest, offset);
+    }
+
+    /// Collect an index entry, from a raw multihash digest and its section offset
+    ///
+    /// The offset must be relative to the CAR v1 payload (see [CarWriter::data_offset]).
+    /// The multihash code is only recorded in MultihashIndexSorted indexes.
+    pub fn add_index_entry(&mut self, multihash_code: u64, digest: &[u8], offset: u64) {
+        self.state
+            .entries
+            .push((multihash_code, digest.to_vec(), offset));
+    }
+
+    /// Serialize the collected index entries as an index of the given type
+    ///
+    /// The entries are grouped and sorted as required by the specification (see [Index::from_entries]),
+    /// and the index is written like [CarWriter::write_index].
+    pub fn write_collected_index(&mut self, index_type: IndexType) {
+        let entries = std::mem::take(&mut self.state.entries);
+        let index = Index::from_entries(
+            index_type,
+            entries
+                .iter()
+                .map(|(code, digest, offset)| (*code, digest.as_slice(), *offset)),
+        );
+        self.write_index(&index);
+    }
+
     /// Flush the current data buffer and return the bytes to be written to the underlying sink.
     ///
     /// The caller should write these bytes to the underlying sink and then call `send_data` again
@@ -248,10 +596,29 @@ impl CarWriter<IndexWritingState> {
         if bytes_to_send == 0 {
             return (0, 0);
         }
-        buf[..bytes_to_send].copy_from_slice(&self.state.data[..bytes_to_send]);
+        // The padding is sent first, only once there is an index to write after it
+        if self.state.padding_offset < self.state.index_start {
+            let offset = self.state.padding_offset;
+            let length = (self.state.index_start.saturating_sub(offset) as usize).min(buf.len());
+            buf.get_mut(..length).unwrap_or_default().fill(0);
+            self.state.padding_offset = offset.saturating_add(length as u64);
+            return (offset as usize, length);
+        }
+        if let (Some(out), Some(data)) = (
+            buf.get_mut(..bytes_to_send),
+            self.state.data.get(..bytes_to_send),
+        ) {
+            out.copy_from_slice(data);
+        }
         self.state.data.drain(..bytes_to_send);
-        let offset = self.state.index_start + self.state.index_offset;
-        self.state.index_offset += bytes_to_send as u64;
+        let offset = self
+            .state
+            .index_start
+            .saturating_add(self.state.index_written);
+        self.state.index_written = self
+            .state
+            .index_written
+            .saturating_add(bytes_to_send as u64);
         (offset as usize, bytes_to_send)
     }
 
@@ -278,6 +645,24 @@ impl CarWriter<FinalizedWritingState> {
         &self.state.header
     }
 
+    /// Finish the CAR v2 file, and return the manifest of the written file
+    ///
+    /// The section locations are absolute, and the index location is set if an index was written.
+    ///
+    /// ## Returns
+    /// - `Ok(WriteManifest)` once the header has been sent.
+    /// - `Err(Self)` if the header is still to be sent (flush it first), or if the manifest recording
+    ///   was not enabled (see [CarWriter::set_record_manifest]).
+    pub fn finish_with_manifest(mut self) -> Result<WriteManifest, Self> {
+        if self.has_data_to_send() {
+            return Err(self);
+        }
+        match self.state.manifest.take() {
+            Some(manifest) => Ok(*manifest),
+            None => Err(self),
+        }
+    }
+
     /// Flush the current data buffer and return the bytes to be written to the underlying sink.
     ///
     /// The caller should write these bytes to the underlying sink and then call `send_data` again
@@ -289,22 +674,35 @@ impl CarWriter<FinalizedWritingState> {
     ///
     /// **Assumption**: The header is always 51 bytes and is written at the very beginning of the CARv2 file,
     /// so the offset is always 0. Therefore, it is necessary that **buf is at least 51 bytes long to accommodate the header**.
-    /// Otherwise, it will be truncated and the reader will fail to read the header correctly.
+    /// Otherwise, nothing is written and the header is still to be sent (see [CarWriter::has_data_to_send]).
+    ///
+    /// Once the header is sent, the zero padding up to the payload (if any, see
+    /// [CarWriter::with_data_offset](CarWriter::<SectionWritingState>::with_data_offset)) is sent as well.
     ///
     /// # Returns
     ///
     /// A tuple (offset, length) indicating the range of bytes in the underlying sink that should be written.
-    pub fn send_data(&mut self, mut buf: &mut [u8]) -> (usize, usize) {
-        debug_assert!(
-            buf.len() >= 51,
-            "Buffer size must be at least 51 bytes to accommodate the CARv2 header"
-        );
+    pub fn send_data(&mut self, buf: &mut [u8]) -> (usize, usize) {
         if self.state.header_saved {
-            return (0, 0);
+            let offset = self.state.padding_offset;
+            let length =
+                (self.state.header.data_offset.saturating_sub(offset) as usize).min(buf.len());
+            if length == 0 {
+                return (0, 0);
+            }
+            buf.get_mut(..length).unwrap_or_default().fill(0);
+            self.state.padding_offset = offset.saturating_add(length as u64);
+            return (offset as usize, length);
         }
+        let Some((pragma, header)) = buf
+            .get_mut(..51)
+            .map(|buf| buf.split_at_mut(CAR_V2_PRAGMA.len()))
+        else {
+            return (0, 0);
+        };
         let header_bytes: [u8; 40] = (&self.state.header).into();
-        buf.write(&CAR_V2_PRAGMA).unwrap();
-        buf.write(&header_bytes).unwrap();
+        pragma.copy_from_slice(CAR_V2_PRAGMA);
+        header.copy_from_slice(&header_bytes);
         self.state.header_saved = true;
         (0, 51)
     }
@@ -313,7 +711,7 @@ impl CarWriter<FinalizedWritingState> {
     ///
     /// This can be used by the caller to determine when to call `send_data` to flush the data buffer.
     pub fn has_data_to_send(&self) -> bool {
-        !self.state.header_saved
+        !self.state.header_saved || self.state.padding_offset < self.state.header.data_offset
     }
 }
 
@@ -337,6 +735,105 @@ pub enum CarWriterError {
     /// or increase the buffer size when creating the CarWriter.
     #[error("Buffer is full, cannot write section")]
     BufferFull,
+    /// The section exceeds the configured size limits
+    ///
+    /// Such a section would be rejected by any reader using the same limits, so it is never written.
+    #[error("Section too large: {length} bytes (max: {max} bytes)")]
+    SectionTooLarge {
+        /// Length of the section (CID and block data)
+        length: u64,
+        /// Maximum section size allowed by the limits
+        max: usize,
+    },
+    /// The block of the section exceeds the configured size limits
+    #[error("Block too large: {length} bytes (max: {max} bytes)")]
+    BlockTooLarge {
+        /// Length of the block data
+        length: usize,
+        /// Maximum block size allowed by the limits
+        max: usize,
+    },
+    /// The section is larger than the whole buffer
+    ///
+    /// See [v1::CarWriterError::SectionExceedsBuffer].
+    #[error("Section of {length} bytes exceeds the buffer capacity ({capacity} bytes)")]
+    SectionExceedsBuffer {
+        /// Length of the section (length prefix, CID and block data)
+        length: usize,
+        /// Capacity of the buffer
+        capacity: usize,
+    },
+    /// The placement constraint of a section cannot be satisfied
+    ///
+    /// See [v1::CarWriterError::UnsatisfiablePlacement].
+    #[error("Cannot place section ({placement:?}) from position {position}")]
+    UnsatisfiablePlacement {
+        /// The requested placement
+        placement: v1::Placement,
+        /// Position of the writer when the placement was requested
+        position: u64,
+    },
+    /// The bytes of a raw section are not exactly one section of the given CID
+    ///
+    /// See [v1::CarWriterError::InvalidRawSection].
+    #[error("Invalid raw section for CID {0}")]
+    InvalidRawSection(RawCid),
+    /// Some links point to blocks which have not been written
+    ///
+    /// See [v1::CarWriterError::DanglingLinks].
+    #[error("{}", describe_dangling_links(.0))]
+    DanglingLinks(Vec<v1::DanglingLink>),
+    /// A section is in progress, it must be completed first
+    ///
+    /// See [v1::CarWriterError::SectionInProgress].
+    #[error("A section is still in progress")]
+    SectionInProgress,
+    /// No section is in progress
+    ///
+    /// See [v1::CarWriterError::NoSectionInProgress].
+    #[error("No section in progress")]
+    NoSectionInProgress,
+    /// The block data written does not match the declared block length
+    ///
+    /// See [v1::CarWriterError::BlockLengthMismatch].
+    #[error("Block length mismatch: {written} bytes written, {declared} bytes declared")]
+    BlockLengthMismatch {
+        /// Block length declared when the section was started
+        declared: u64,
+        /// Block length written (or that would be written)
+        written: u64,
+    },
+}
+
+impl From<v1::CarWriterError> for CarWriterError {
+    fn from(err: v1::CarWriterError) -> Self {
+        match err {
+            v1::CarWriterError::BufferFull => CarWriterError::BufferFull,
+            v1::CarWriterError::SectionTooLarge { length, max } => {
+                CarWriterError::SectionTooLarge { length, max }
+            }
+            v1::CarWriterError::BlockTooLarge { length, max } => {
+                CarWriterError::BlockTooLarge { length, max }
+            }
+            v1::CarWriterError::SectionExceedsBuffer { length, capacity } => {
+                CarWriterError::SectionExceedsBuffer { length, capacity }
+            }
+            v1::CarWriterError::UnsatisfiablePlacement {
+                placement,
+                position,
+            } => CarWriterError::UnsatisfiablePlacement {
+                placement,
+                position,
+            },
+            v1::CarWriterError::InvalidRawSection(cid) => CarWriterError::InvalidRawSection(cid),
+            v1::CarWriterError::DanglingLinks(links) => CarWriterError::DanglingLinks(links),
+            v1::CarWriterError::SectionInProgress => CarWriterError::SectionInProgress,
+            v1::CarWriterError::NoSectionInProgress => CarWriterError::NoSectionInProgress,
+            v1::CarWriterError::BlockLengthMismatch { declared, written } => {
+                CarWriterError::BlockLengthMismatch { declared, written }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -344,6 +841,26 @@ mod tests {
     use super::*;
     use crate::wire::v1::Block;
 
+    #[test]
+    fn test_car_writer_section_placed() {
+        let cid = RawCid::from_hex(
+            "015512200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let mut writer = CarWriter::new(vec![cid.clone()]);
+        let section = Section::new(cid, Block::new(vec![1, 2, 3, 4]));
+        // Offsets are absolute, including the CARv2 header
+        let placed = writer
+            .write_section_placed(&section, v1::Placement::Aligned(4096))
+            .unwrap();
+        assert_eq!(placed.location.offset, 4096);
+        assert!(placed.padding[0].offset > writer.data_offset());
+        assert!(matches!(
+            writer.write_section_placed(&section, v1::Placement::At(40)),
+            Err(CarWriterError::UnsatisfiablePlacement { .. })
+        ));
+    }
+
     #[test]
     fn test_car_writer_no_index() {
         let root_cid = RawCid::from_hex(
@@ -390,6 +907,7 @@ mod tests {
                         section_to_write.push(section); // Put the section back to try writing it again after flushing
                         continue;
                     }
+                    Err(e) => panic!("Unexpected error: {:?}", e),
                 }
             }
         }
@@ -406,6 +924,261 @@ mod tests {
         assert_eq!(sink.len(), 233);
     }
 
+    #[test]
+    fn test_car_writer_finish_with_manifest() {
+        let mut reader = crate::CarReader::from_bytes(crate::testdata::CARV1_BASIC).unwrap();
+        let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|locsec| locsec.section)
+            .collect();
+        let mut writer = CarWriter::new(vec![sections[0].cid().clone()]);
+        writer.set_record_manifest(true);
+        let mut sink = Vec::new();
+        fn send(writer: &mut impl CarWriteV2, sink: &mut Vec<u8>) {
+            let mut buf = vec![0u8; 64 * 1024];
+            while writer.has_data_to_send() {
+                let (pos, len) = writer.send_data(&mut buf);
+                if pos + len > sink.len() {
+                    sink.resize(pos + len, 0);
+                }
+                sink[pos..pos + len].copy_from_slice(&buf[..len]);
+            }
+        }
+        let locations: Vec<_> = sections
+            .iter()
+            .map(|section| writer.write_section(section).unwrap())
+            .collect();
+        send(&mut writer, &mut sink);
+        let mut writer = writer.finalize_sections().unwrap();
+        // The manifest feeds the index, without scanning the written sections
+        let index = writer.state.manifest.as_ref().unwrap().to_index();
+        writer.write_index(&index);
+        send(&mut writer, &mut sink);
+        let mut writer = writer.finalize_full_index().unwrap();
+        send(&mut writer, &mut sink);
+        let header = writer.header().clone();
+        let manifest = writer.finish_with_manifest().unwrap();
+
+        assert_eq!(manifest.header.roots()[0].to_raw_cid(), sections[0].cid());
+        assert_eq!(manifest.data_offset, 51);
+        assert_eq!(
+            manifest.sections,
+            sections
+                .iter()
+                .map(|section| section.cid().clone())
+                .zip(locations)
+                .collect::<Vec<_>>()
+        );
+        let index_location = manifest.index_location.clone().unwrap();
+        assert_eq!(index_location.offset, header.index_offset);
+        assert_eq!(
+            index_location.offset + index_location.length,
+            sink.len() as u64
+        );
+
+        let mut reader = crate::wire::v2::CarReader::new();
+        reader.receive_data(&sink, 0);
+        reader.read_header().unwrap();
+        for (cid, location) in &manifest.sections {
+            let found = reader.find_section(cid).unwrap();
+            assert_eq!(&found.location, location);
+            assert_eq!(index.find(cid), Some(location.offset - 51));
+        }
+
+        // Without recording, there is no manifest
+        let mut writer = CarWriter::new(vec![]);
+        send(&mut writer, &mut Vec::new());
+        let mut writer = writer.finalize_all().unwrap();
+        send(&mut writer, &mut Vec::new());
+        assert!(writer.finish_with_manifest().is_err());
+    }
+
+    #[test]
+    fn test_car_writer_data_alignment() {
+        let mut reader = crate::CarReader::from_bytes(crate::testdata::CARV1_BASIC).unwrap();
+        let roots = reader.header().unwrap().0.roots().to_vec();
+        let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|locsec| locsec.section)
+            .collect();
+        fn send(writer: &mut impl CarWriteV2, sink: &mut Vec<u8>) {
+            // Small chunks, so that the padding is sent in several parts
+            let mut buf = vec![0u8; 1000];
+            while writer.has_data_to_send() {
+                let (pos, len) = writer.send_data(&mut buf);
+                if pos + len > sink.len() {
+                    sink.resize(pos + len, 0xff);
+                }
+                sink[pos..pos + len].copy_from_slice(&buf[..len]);
+            }
+        }
+        let roots: Vec<_> = roots.iter().map(|root| root.to_raw_cid().clone()).collect();
+        let writer = CarWriter::new(roots.clone());
+        assert_eq!(writer.with_data_offset(10).data_offset(), 51);
+        assert_eq!(
+            CarWriter::new(vec![]).with_data_alignment(0).data_offset(),
+            51
+        );
+        assert_eq!(
+            CarWriter::new(vec![]).with_data_alignment(64).data_offset(),
+            64
+        );
+
+        let mut writer = CarWriter::new(roots).with_data_alignment(4096);
+        let mut sink = Vec::new();
+        let locations: Vec<_> = sections
+            .iter()
+            .map(|section| writer.write_section(section).unwrap())
+            .collect();
+        assert_eq!(locations[0].offset, 4096 + 100);
+        send(&mut writer, &mut sink);
+        let mut writer = writer.finalize_sections().unwrap();
+        for (section, location) in sections.iter().zip(&locations) {
+            writer.add_section_entry(section.cid(), location);
+        }
+        writer.write_collected_index(IndexType::MultihashIndexSorted);
+        send(&mut writer, &mut sink);
+        let mut writer = writer.finalize_full_index().unwrap();
+        send(&mut writer, &mut sink);
+        assert_eq!(writer.header().data_offset, 4096);
+
+        // Zero padding, then the payload as is
+        assert!(sink[51..4096].iter().all(|&byte| byte == 0));
+        let payload_end = 4096 + crate::testdata::CARV1_BASIC.len();
+        assert_eq!(&sink[4096..payload_end], crate::testdata::CARV1_BASIC);
+        let mut reader = crate::wire::v2::CarReader::new();
+        reader.receive_data(&sink, 0);
+        reader.read_header().unwrap();
+        for (section, location) in sections.iter().zip(&locations) {
+            assert_eq!(
+                &reader.find_section(section.cid()).unwrap().location,
+                location
+            );
+        }
+    }
+
+    #[test]
+    fn test_car_writer_index_padding() {
+        let mut reader = crate::CarReader::from_bytes(crate::testdata::CARV1_BASIC).unwrap();
+        let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|locsec| locsec.section)
+            .collect();
+        fn send(writer: &mut impl CarWriteV2, sink: &mut Vec<u8>) {
+            let mut buf = vec![0u8; 64];
+            while writer.has_data_to_send() {
+                let (pos, len) = writer.send_data(&mut buf);
+                if pos + len > sink.len() {
+                    sink.resize(pos + len, 0xff);
+                }
+                sink[pos..pos + len].copy_from_slice(&buf[..len]);
+            }
+        }
+        for with_index in [true, false] {
+            let mut writer = CarWriter::new(vec![sections[0].cid().clone()])
+                .with_index_padding(10)
+                .with_index_alignment(64);
+            let mut sink = Vec::new();
+            let locations: Vec<_> = sections
+                .iter()
+                .map(|section| writer.write_section(section).unwrap())
+                .collect();
+            send(&mut writer, &mut sink);
+            let data_end = sink.len() as u64;
+            let mut writer = writer.finalize_sections().unwrap();
+            let index_offset = writer.index_offset();
+            assert_eq!(index_offset, (data_end + 10).next_multiple_of(64));
+            if !with_index {
+                // Neither padding nor index offset without an index
+                let mut writer = writer.finalize_index().unwrap();
+                send(&mut writer, &mut sink);
+                assert_eq!(writer.header().index_offset, 0);
+                assert_eq!(sink.len() as u64, data_end);
+                continue;
+            }
+            for (section, location) in sections.iter().zip(&locations) {
+                writer.add_section_entry(section.cid(), location);
+            }
+            writer.write_collected_index(IndexType::IndexSorted);
+            send(&mut writer, &mut sink);
+            let mut writer = writer.finalize_full_index().unwrap();
+            send(&mut writer, &mut sink);
+            let header = writer.header().clone();
+            assert_eq!(header.index_offset, index_offset);
+            assert_eq!(header.data_offset + header.data_size, data_end);
+            assert!(
+                sink[data_end as usize..index_offset as usize]
+                    .iter()
+                    .all(|&b| b == 0)
+            );
+
+            let mut reader = crate::wire::v2::CarReader::new();
+            reader.receive_data(&sink, 0);
+            reader.read_header().unwrap();
+            for (section, location) in sections.iter().zip(&locations) {
+                assert_eq!(
+                    &reader.find_section(section.cid()).unwrap().location,
+                    location
+                );
+            }
+        }
+    }
+
     // TODO: Tests writer and reader match, by writing a CAR file with the writer and then reading
     // it with the reader and checking that the header and sections are the same.
+
+    #[test]
+    fn test_car_writer_collected_index() {
+        let mut reader = crate::CarReader::from_bytes(crate::testdata::CARV1_BASIC).unwrap();
+        let sections: Vec<_> = std::iter::from_fn(|| reader.read_section().ok())
+            .map(|locsec| locsec.section)
+            .collect();
+        fn send(writer: &mut impl CarWriteV2, sink: &mut Vec<u8>) {
+            let mut buf = vec![0u8; 64 * 1024];
+            while writer.has_data_to_send() {
+                let (pos, len) = writer.send_data(&mut buf);
+                if pos + len > sink.len() {
+                    sink.resize(pos + len, 0);
+                }
+                sink[pos..pos + len].copy_from_slice(&buf[..len]);
+            }
+        }
+        for index_type in IndexType::ALL {
+            let mut writer = CarWriter::new(vec![sections[0].cid().clone()]);
+            let mut sink = Vec::new();
+            let locations: Vec<_> = sections
+                .iter()
+                .map(|section| writer.write_section(section).unwrap())
+                .collect();
+            send(&mut writer, &mut sink);
+            let mut writer = writer.finalize_sections().unwrap();
+            for (section, location) in sections.iter().zip(&locations) {
+                writer.add_section_entry(section.cid(), location);
+            }
+            // Collected entries must be written before finalizing
+            let mut writer = writer.finalize_full_index().unwrap_err();
+            writer.write_collected_index(index_type);
+            send(&mut writer, &mut sink);
+            let mut writer = writer.finalize_full_index().unwrap();
+            send(&mut writer, &mut sink);
+            let header = writer.header().clone();
+
+            let (index, size) = Index::decode(&sink[header.index_offset as usize..]).unwrap();
+            assert_eq!(index.index_type, index_type);
+            assert_eq!(size, sink.len() - header.index_offset as usize);
+            assert_eq!(index.len(), sections.len());
+            let offsets: Vec<_> = locations
+                .iter()
+                .map(|location| location.offset - header.data_offset)
+                .collect();
+            if index_type == IndexType::MultihashIndexSorted {
+                let cids: Vec<_> = sections.iter().map(|section| section.cid()).collect();
+                assert_eq!(
+                    index,
+                    Index::multihash_sorted(cids.into_iter().zip(offsets.iter().copied()))
+                );
+            }
+            for (section, offset) in sections.iter().zip(offsets) {
+                let digest = section.cid().digest().unwrap();
+                assert_eq!(index.find_digest(None, digest), Some(offset));
+            }
+        }
+    }
 }
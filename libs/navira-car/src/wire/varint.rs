@@ -58,6 +58,9 @@ pub struct SignedVarint(
 );
 
 impl UnsignedVarint {
+    /// Maximal length of an encoded unsigned varint, in bytes (enough for any u64)
+    pub const MAX_LENGTH: usize = 10;
+
     /// Encodes the UnsignedVarint into a vector of bytes using LEB128 encoding.
     pub fn encode(self) -> Vec<u8> {
         let mut value = self.0;
@@ -84,14 +87,15 @@ impl UnsignedVarint {
     /// - `None` if the input bytes do not represent a valid varint (e.g., incomplete varint or overflow).
     pub fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
         let mut result = 0u64;
-        let mut shift = 0;
-        for (i, &byte) in bytes.iter().enumerate() {
+        let mut shift = 0u32;
+        for (read, &byte) in (1..).zip(bytes) {
             let value = (byte & 0x7F) as u64;
             result |= value << shift;
             if (byte & 0x80) == 0 {
-                return Some((UnsignedVarint(result), i + 1));
+                return Some((UnsignedVarint(result), read));
             }
-            shift += 7;
+            // Below 64, checked right after
+            shift = shift.wrapping_add(7);
             if shift >= 64 {
                 return None; // Overflow
             }
@@ -144,19 +148,20 @@ impl SignedVarint {
     /// - `None` if the input bytes do not represent a valid varint (e.g., incomplete varint or overflow).
     pub fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
         let mut result = 0i64;
-        let mut shift = 0;
+        let mut shift = 0u32;
         let mut byte: u8;
-        for (i, &b) in bytes.iter().enumerate() {
+        for (read, &b) in (1..).zip(bytes) {
             byte = b;
             let value = (byte & 0x7F) as i64;
             result |= value << shift;
-            shift += 7;
+            // Below 64 before, checked right after
+            shift = shift.wrapping_add(7);
             if (byte & 0x80) == 0 {
                 // Sign bit of byte is second high order bit (0x40)
                 if (shift < 64) && ((byte & 0x40) != 0) {
                     result |= -1i64 << shift; // Sign extend
                 }
-                return Some((SignedVarint(result), i + 1));
+                return Some((SignedVarint(result), read));
             }
             if shift >= 64 {
                 return None; // Overflow
@@ -0,0 +1,278 @@
+//! Frames of the IPC protocol
+
+use std::io::{Read, Write};
+
+use crate::IpcError;
+
+/// Default maximum payload size of a frame accepted by the decoders
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+/// Size of the frame header (kind and length)
+const FRAME_HEADER_SIZE: usize = 5;
+
+/// Kind of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// A request, sent by the client
+    Request = 0x01,
+    /// A chunk of the response payload, sent by the server
+    Data = 0x02,
+    /// The final status of a response, sent by the server
+    Trailer = 0x03,
+}
+
+impl FrameKind {
+    /// Creates a FrameKind from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(FrameKind::Request),
+            0x02 => Some(FrameKind::Data),
+            0x03 => Some(FrameKind::Trailer),
+            _ => None,
+        }
+    }
+}
+
+/// A frame of the IPC protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Kind of the frame
+    pub kind: FrameKind,
+    /// Payload of the frame
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Create a new frame
+    pub fn new(kind: FrameKind, payload: Vec<u8>) -> Self {
+        Self { kind, payload }
+    }
+
+    /// Encode the header of a frame with the given payload length
+    ///
+    /// # Returns
+    /// * `Ok([u8; FRAME_HEADER_SIZE])` - The encoded header
+    /// * `Err(IpcError::PayloadTooLarge)` - The length does not fit in the header
+    fn encode_header(kind: FrameKind, length: usize) -> Result<[u8; FRAME_HEADER_SIZE], IpcError> {
+        let length = u32::try_from(length).map_err(|_| IpcError::PayloadTooLarge(length))?;
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        header[0] = kind as u8;
+        header[1..].copy_from_slice(&length.to_le_bytes());
+        Ok(header)
+    }
+
+    /// Append the encoded frame to the buffer
+    ///
+    /// # Returns
+    /// * `Ok(())` - The frame is appended
+    /// * `Err(IpcError::PayloadTooLarge)` - The payload is over [u32::MAX] bytes, nothing is appended
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), IpcError> {
+        out.extend_from_slice(&Self::encode_header(self.kind, self.payload.len())?);
+        out.extend_from_slice(&self.payload);
+        Ok(())
+    }
+
+    /// Write a frame to the writer, without copying its payload
+    ///
+    /// Payloads over [u32::MAX] bytes are rejected with an [InvalidInput](std::io::ErrorKind::InvalidInput) error.
+    pub(crate) fn write_parts<W: Write>(
+        writer: &mut W,
+        kind: FrameKind,
+        payload: &[u8],
+    ) -> std::io::Result<()> {
+        let header = Self::encode_header(kind, payload.len())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        writer.write_all(&header)?;
+        writer.write_all(payload)
+    }
+
+    /// Write the encoded frame to the writer
+    ///
+    /// See [Frame::encode] for the payloads which can not be encoded.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        Self::write_parts(writer, self.kind, &self.payload)
+    }
+}
+
+/// Sans-IO decoder of frames
+///
+/// Bytes are fed with [FrameDecoder::receive_data] as they arrive, and the complete frames are pulled
+/// with [FrameDecoder::next_frame].
+#[derive(Debug, Clone)]
+pub struct FrameDecoder {
+    /// Received bytes, not decoded yet
+    buf: Vec<u8>,
+    /// Maximum payload size of a frame
+    max_frame_size: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    /// Create a new decoder, accepting frames up to [DEFAULT_MAX_FRAME_SIZE]
+    pub fn new() -> Self {
+        Self::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Create a new decoder, accepting frames up to the given payload size
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_frame_size,
+        }
+    }
+
+    /// Feed received bytes to the decoder
+    pub fn receive_data(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Number of received bytes not decoded yet
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Decode the next frame, if it has been fully received
+    ///
+    /// # Returns
+    /// * `Ok(Some(Frame))` - The next frame
+    /// * `Ok(None)` - More data is needed
+    /// * `Err(IpcError)` - The frame kind is unknown, or the frame is too large
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, IpcError> {
+        if self.buf.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+        let kind =
+            FrameKind::from_u8(self.buf[0]).ok_or(IpcError::UnknownFrameKind(self.buf[0]))?;
+        let length = u32::from_le_bytes(self.buf[1..FRAME_HEADER_SIZE].try_into().unwrap());
+        if length as usize > self.max_frame_size {
+            return Err(IpcError::FrameTooLarge(length));
+        }
+        let end = FRAME_HEADER_SIZE + length as usize;
+        if self.buf.len() < end {
+            return Ok(None);
+        }
+        let payload = self.buf[FRAME_HEADER_SIZE..end].to_vec();
+        self.buf.drain(..end);
+        Ok(Some(Frame { kind, payload }))
+    }
+}
+
+/// Read the next frame from a blocking reader
+///
+/// # Returns
+/// * `Ok(Some(Frame))` - The next frame
+/// * `Ok(None)` - The stream ended cleanly, between two frames
+/// * `Err(IpcError::Truncated)` - The stream ended in the middle of a frame
+/// * `Err(IpcError)` - The frame is invalid, or an I/O error occurred
+pub fn read_frame<R: Read>(
+    reader: &mut R,
+    max_frame_size: usize,
+) -> Result<Option<Frame>, IpcError> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    let mut read = 0;
+    while read < FRAME_HEADER_SIZE {
+        match reader.read(&mut header[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(IpcError::Truncated),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let kind = FrameKind::from_u8(header[0]).ok_or(IpcError::UnknownFrameKind(header[0]))?;
+    let length = u32::from_le_bytes(header[1..].try_into().unwrap());
+    if length as usize > max_frame_size {
+        return Err(IpcError::FrameTooLarge(length));
+    }
+    let mut payload = vec![0u8; length as usize];
+    reader
+        .read_exact(&mut payload)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => IpcError::Truncated,
+            _ => e.into(),
+        })?;
+    Ok(Some(Frame { kind, payload }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_decoder() {
+        let mut wire = Vec::new();
+        Frame::new(FrameKind::Data, b"hello".to_vec())
+            .encode(&mut wire)
+            .unwrap();
+        Frame::new(FrameKind::Trailer, vec![0])
+            .encode(&mut wire)
+            .unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        for byte in &wire[..7] {
+            assert_eq!(decoder.next_frame().unwrap(), None);
+            decoder.receive_data(std::slice::from_ref(byte));
+        }
+        decoder.receive_data(&wire[7..]);
+        assert_eq!(
+            decoder.next_frame().unwrap(),
+            Some(Frame::new(FrameKind::Data, b"hello".to_vec()))
+        );
+        assert_eq!(
+            decoder.next_frame().unwrap().unwrap().kind,
+            FrameKind::Trailer
+        );
+        assert_eq!(decoder.buffered(), 0);
+
+        let mut decoder = FrameDecoder::with_max_frame_size(4);
+        decoder.receive_data(&wire);
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(IpcError::FrameTooLarge(5))
+        ));
+        let mut decoder = FrameDecoder::new();
+        decoder.receive_data(&[0x7f, 0, 0, 0, 0]);
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(IpcError::UnknownFrameKind(0x7f))
+        ));
+    }
+
+    #[test]
+    fn test_read_frame() {
+        let mut wire = Vec::new();
+        Frame::new(FrameKind::Request, vec![1, 2, 3])
+            .encode(&mut wire)
+            .unwrap();
+        let mut reader = wire.as_slice();
+        assert_eq!(
+            read_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).unwrap(),
+            Some(Frame::new(FrameKind::Request, vec![1, 2, 3]))
+        );
+        assert_eq!(
+            read_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).unwrap(),
+            None
+        );
+        assert!(matches!(
+            read_frame(&mut &wire[..6], DEFAULT_MAX_FRAME_SIZE),
+            Err(IpcError::Truncated)
+        ));
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_frame_payload_too_large() {
+        let length = u32::MAX as usize + 1;
+        assert!(matches!(
+            Frame::encode_header(FrameKind::Data, length),
+            Err(IpcError::PayloadTooLarge(l)) if l == length
+        ));
+        let header = Frame::encode_header(FrameKind::Data, u32::MAX as usize).unwrap();
+        assert_eq!(header[1..], u32::MAX.to_le_bytes());
+    }
+}
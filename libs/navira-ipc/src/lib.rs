@@ -0,0 +1,80 @@
+//! navira-ipc implements the framing of the navira-store Unix socket protocol, shared by the server
+//! and its clients.
+//!
+//! ## Protocol
+//!
+//! Both directions of the socket carry length-prefixed frames:
+//! ```text
+//! frame := kind (u8) | length (u32le) | payload (length bytes)
+//! ```
+//! The client sends one [Request] frame at a time. The server answers with a streamed response: any number of
+//! data frames holding consecutive chunks of the payload (a block, a CAR export, …), then a final [Trailer]
+//! frame holding the status of the response. Since the status comes last, the server starts streaming before
+//! knowing whether the whole payload can be produced, and never buffers more than a chunk. Clients must therefore
+//! only trust the payload once an [Status::Ok] trailer has been received.
+//!
+//! The framing is available as a sans-IO [FrameDecoder], and as blocking helpers over [std::io::Read] and
+//! [std::io::Write]: [ResponseWriter] on the server side, [ResponseReader] and [Client] on the client side.
+//!
+//! ## Examples
+//! ```
+//! use navira_ipc::{ResponseReader, ResponseWriter, Status, Trailer};
+//! use std::io::{Read, Write};
+//!
+//! // Server side: stream a large payload in 64 KiB frames
+//! let mut writer = ResponseWriter::with_chunk_size(Vec::new(), 64 * 1024);
+//! writer.write_all(&vec![0xab; 1_000_000]).unwrap();
+//! let wire = writer.finish(&Trailer::ok()).unwrap();
+//!
+//! // Client side: read it back, then check the trailer
+//! let mut reader = ResponseReader::new(wire.as_slice());
+//! let mut payload = Vec::new();
+//! reader.read_to_end(&mut payload).unwrap();
+//! assert_eq!(payload.len(), 1_000_000);
+//! assert_eq!(reader.finish().unwrap().status, Status::Ok);
+//! ```
+
+mod frame;
+mod message;
+mod stream;
+
+pub use frame::{DEFAULT_MAX_FRAME_SIZE, Frame, FrameDecoder, FrameKind, read_frame};
+pub use message::{Request, Status, Trailer};
+pub use stream::{Client, DEFAULT_CHUNK_SIZE, ResponseReader, ResponseWriter, read_request};
+
+/// Errors related to the IPC protocol
+#[derive(thiserror::Error, Debug)]
+pub enum IpcError {
+    /// I/O error on the underlying stream
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The frame kind is unknown
+    #[error("Unknown frame kind: {0:#x}")]
+    UnknownFrameKind(u8),
+    /// The frame exceeds the maximum frame size
+    #[error("Frame of {0} bytes exceeds the maximum frame size")]
+    FrameTooLarge(u32),
+    /// The frame payload is too large to be encoded (its length does not fit in 32 bits)
+    #[error("Frame payload of {0} bytes is too large to be encoded")]
+    PayloadTooLarge(usize),
+    /// The stream ended in the middle of a frame, or before the trailer of a response
+    #[error("Stream truncated")]
+    Truncated,
+    /// A frame of this kind is not expected at this point of the exchange
+    #[error("Unexpected {0:?} frame")]
+    UnexpectedFrame(FrameKind),
+    /// The request could not be decoded
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    /// The trailer could not be decoded
+    #[error("Invalid trailer: {0}")]
+    InvalidTrailer(String),
+    /// The server answered with a non-Ok status
+    #[error("Request failed with status {status:?}: {message}")]
+    Failed {
+        /// Status of the response
+        status: Status,
+        /// Message of the server
+        message: String,
+    },
+}
@@ -0,0 +1,185 @@
+//! Requests and response trailers of the IPC protocol
+
+use navira_car::wire::cid::RawCid;
+
+use crate::IpcError;
+
+/// Operation code of a block request
+const OP_GET_BLOCK: u8 = 0x01;
+/// Operation code of a CAR export request
+const OP_EXPORT_CAR: u8 = 0x02;
+/// Operation code of a store report request
+const OP_REPORT: u8 = 0x03;
+
+/// A request of the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Get the data of a block
+    GetBlock(RawCid),
+    /// Export every servable block as a CARv1 stream
+    ExportCar,
+    /// Get a plain text report of the store (indexed blocks, quarantined CAR files, …)
+    Report,
+}
+
+impl Request {
+    /// Encode the request as a frame payload
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Request::GetBlock(cid) => {
+                let mut payload = Vec::with_capacity(1 + cid.bytes().len());
+                payload.push(OP_GET_BLOCK);
+                payload.extend_from_slice(cid.bytes());
+                payload
+            }
+            Request::ExportCar => vec![OP_EXPORT_CAR],
+            Request::Report => vec![OP_REPORT],
+        }
+    }
+
+    /// Decode a request from a frame payload
+    pub fn decode(payload: &[u8]) -> Result<Self, IpcError> {
+        match payload.split_first() {
+            Some((&OP_GET_BLOCK, cid)) => {
+                let (cid, len) = RawCid::try_read_bytes(cid)
+                    .map_err(|e| IpcError::InvalidRequest(format!("invalid CID: {:?}", e)))?;
+                if len != payload.len() - 1 {
+                    return Err(IpcError::InvalidRequest(
+                        "trailing bytes after the CID".to_owned(),
+                    ));
+                }
+                Ok(Request::GetBlock(cid))
+            }
+            Some((&OP_EXPORT_CAR, [])) => Ok(Request::ExportCar),
+            Some((&OP_REPORT, [])) => Ok(Request::Report),
+            Some((&OP_EXPORT_CAR | &OP_REPORT, _)) => Err(IpcError::InvalidRequest(
+                "trailing bytes after the operation".to_owned(),
+            )),
+            Some((op, _)) => Err(IpcError::InvalidRequest(format!(
+                "unknown operation {:#x}",
+                op
+            ))),
+            None => Err(IpcError::InvalidRequest("empty request".to_owned())),
+        }
+    }
+}
+
+/// Status of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The payload is complete
+    Ok = 0x00,
+    /// The block is not available
+    NotFound = 0x01,
+    /// The block is not servable under the serving policy
+    Denied = 0x02,
+    /// The block (or its CAR file) is tombstoned
+    Tombstoned = 0x03,
+    /// The request could not be decoded
+    BadRequest = 0x04,
+    /// The server failed to produce the payload (which is incomplete)
+    Error = 0x05,
+}
+
+impl Status {
+    /// Creates a Status from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Status::Ok),
+            0x01 => Some(Status::NotFound),
+            0x02 => Some(Status::Denied),
+            0x03 => Some(Status::Tombstoned),
+            0x04 => Some(Status::BadRequest),
+            0x05 => Some(Status::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Final status of a response, sent after the payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    /// Status of the response
+    pub status: Status,
+    /// Human-readable details (empty on success)
+    pub message: String,
+}
+
+impl Trailer {
+    /// Create a trailer
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Create a successful trailer
+    pub fn ok() -> Self {
+        Self::new(Status::Ok, String::new())
+    }
+
+    /// Encode the trailer as a frame payload
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(1 + self.message.len());
+        payload.push(self.status as u8);
+        payload.extend_from_slice(self.message.as_bytes());
+        payload
+    }
+
+    /// Decode a trailer from a frame payload
+    pub fn decode(payload: &[u8]) -> Result<Self, IpcError> {
+        let (&status, message) = payload
+            .split_first()
+            .ok_or_else(|| IpcError::InvalidTrailer("empty trailer".to_owned()))?;
+        let status = Status::from_u8(status)
+            .ok_or_else(|| IpcError::InvalidTrailer(format!("unknown status {:#x}", status)))?;
+        let message = String::from_utf8(message.to_vec())
+            .map_err(|_| IpcError::InvalidTrailer("message is not UTF-8".to_owned()))?;
+        Ok(Self { status, message })
+    }
+
+    /// Convert the trailer into a result, failing on non-Ok statuses
+    pub fn into_result(self) -> Result<(), IpcError> {
+        match self.status {
+            Status::Ok => Ok(()),
+            status => Err(IpcError::Failed {
+                status,
+                message: self.message,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_roundtrip() {
+        let cid = RawCid::from_hex("0155000461616161").unwrap();
+        for request in [Request::GetBlock(cid), Request::ExportCar, Request::Report] {
+            assert_eq!(Request::decode(&request.encode()).unwrap(), request);
+        }
+        assert!(Request::decode(&[]).is_err());
+        assert!(Request::decode(&[0x09]).is_err());
+        assert!(Request::decode(&[OP_EXPORT_CAR, 0]).is_err());
+        assert!(Request::decode(&[OP_REPORT, 0]).is_err());
+        assert!(Request::decode(&[OP_GET_BLOCK, 0x01, 0x55]).is_err());
+    }
+
+    #[test]
+    fn test_trailer_roundtrip() {
+        let trailer = Trailer::new(Status::Tombstoned, "gone");
+        assert_eq!(Trailer::decode(&trailer.encode()).unwrap(), trailer);
+        assert!(matches!(
+            trailer.into_result(),
+            Err(IpcError::Failed {
+                status: Status::Tombstoned,
+                ..
+            })
+        ));
+        assert!(Trailer::decode(&[0x42]).is_err());
+        assert!(Trailer::ok().into_result().is_ok());
+    }
+}
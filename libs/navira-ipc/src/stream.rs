@@ -0,0 +1,359 @@
+//! Blocking helpers streaming requests and responses over [Read] and [Write]
+
+use std::io::{Read, Write};
+
+use navira_car::wire::cid::RawCid;
+
+use crate::{
+    DEFAULT_MAX_FRAME_SIZE, Frame, FrameKind, IpcError, Request, Trailer, frame::read_frame,
+};
+
+/// Default payload size of the data frames written by a [ResponseWriter]
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Read the next request from the client
+///
+/// # Returns
+/// * `Ok(Some(Request))` - The next request
+/// * `Ok(None)` - The client closed the stream
+/// * `Err(IpcError)` - The request is invalid, or an I/O error occurred
+pub fn read_request<R: Read>(reader: &mut R) -> Result<Option<Request>, IpcError> {
+    match read_frame(reader, DEFAULT_MAX_FRAME_SIZE)? {
+        Some(Frame {
+            kind: FrameKind::Request,
+            payload,
+        }) => Request::decode(&payload).map(Some),
+        Some(frame) => Err(IpcError::UnexpectedFrame(frame.kind)),
+        None => Ok(None),
+    }
+}
+
+/// Server side of a streamed response
+///
+/// The payload is written with [Write], and sent as data frames of at most the chunk size. The response is
+/// terminated with [ResponseWriter::finish], which sends the trailer. Dropping the writer without finishing
+/// it leaves the response incomplete: the client reports it as truncated.
+#[derive(Debug)]
+pub struct ResponseWriter<W: Write> {
+    /// Underlying stream
+    inner: W,
+    /// Payload bytes not sent yet
+    buf: Vec<u8>,
+    /// Maximum payload size of the data frames
+    chunk_size: usize,
+}
+
+impl<W: Write> ResponseWriter<W> {
+    /// Create a response writer, sending data frames of [DEFAULT_CHUNK_SIZE]
+    pub fn new(inner: W) -> Self {
+        Self::with_chunk_size(inner, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Create a response writer, sending data frames of the given payload size
+    ///
+    /// The chunk size must not exceed the maximum frame size of the client ([DEFAULT_MAX_FRAME_SIZE] by default).
+    pub fn with_chunk_size(inner: W, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        Self {
+            inner,
+            buf: Vec::with_capacity(chunk_size),
+            chunk_size,
+        }
+    }
+
+    /// Send the buffered payload bytes as a data frame
+    fn send_chunk(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            Frame::write_parts(&mut self.inner, FrameKind::Data, &self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    /// Send the rest of the payload and the trailer, terminating the response
+    ///
+    /// # Returns
+    /// * `Ok(W)` - The underlying stream, ready for the next response
+    /// * `Err(std::io::Error)` - The response could not be sent
+    pub fn finish(mut self, trailer: &Trailer) -> std::io::Result<W> {
+        self.send_chunk()?;
+        Frame::write_parts(&mut self.inner, FrameKind::Trailer, &trailer.encode())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ResponseWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.buf.is_empty() && data.len() >= self.chunk_size {
+            // Large writes skip the buffer
+            Frame::write_parts(&mut self.inner, FrameKind::Data, &data[..self.chunk_size])?;
+            return Ok(self.chunk_size);
+        }
+        let len = data.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..len]);
+        if self.buf.len() == self.chunk_size {
+            self.send_chunk()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_chunk()?;
+        self.inner.flush()
+    }
+}
+
+/// Client side of a streamed response
+///
+/// The payload is read with [Read], chunk by chunk, and the trailer is returned by [ResponseReader::finish].
+/// A read error of kind [InvalidData](std::io::ErrorKind::InvalidData) or [UnexpectedEof](std::io::ErrorKind::UnexpectedEof)
+/// is returned if the server sends an unexpected frame, or closes the stream before the trailer.
+#[derive(Debug)]
+pub struct ResponseReader<R: Read> {
+    /// Underlying stream
+    inner: R,
+    /// Current data frame
+    chunk: Vec<u8>,
+    /// Position of the next byte to read in the current data frame
+    pos: usize,
+    /// Trailer, once received
+    trailer: Option<Trailer>,
+    /// Maximum payload size of a frame
+    max_frame_size: usize,
+}
+
+impl<R: Read> ResponseReader<R> {
+    /// Create a response reader, accepting frames up to [DEFAULT_MAX_FRAME_SIZE]
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            chunk: Vec::new(),
+            pos: 0,
+            trailer: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Trailer of the response, once the whole payload has been read
+    pub fn trailer(&self) -> Option<&Trailer> {
+        self.trailer.as_ref()
+    }
+
+    /// Receive the next frame of the response
+    ///
+    /// # Returns
+    /// * `Ok(true)` - A data frame was received
+    /// * `Ok(false)` - The trailer was received
+    fn next_chunk(&mut self) -> Result<bool, IpcError> {
+        match read_frame(&mut self.inner, self.max_frame_size)? {
+            Some(Frame {
+                kind: FrameKind::Data,
+                payload,
+            }) => {
+                self.chunk = payload;
+                self.pos = 0;
+                Ok(true)
+            }
+            Some(Frame {
+                kind: FrameKind::Trailer,
+                payload,
+            }) => {
+                self.trailer = Some(Trailer::decode(&payload)?);
+                Ok(false)
+            }
+            Some(frame) => Err(IpcError::UnexpectedFrame(frame.kind)),
+            None => Err(IpcError::Truncated),
+        }
+    }
+
+    /// Skip the rest of the payload, and return the trailer
+    ///
+    /// # Returns
+    /// * `Ok(Trailer)` - The trailer of the response, whatever its status
+    /// * `Err(IpcError)` - The response is malformed or truncated
+    pub fn finish(mut self) -> Result<Trailer, IpcError> {
+        while self.trailer.is_none() {
+            self.next_chunk()?;
+        }
+        Ok(self.trailer.take().unwrap())
+    }
+
+    /// Return the underlying stream, once the trailer has been received
+    pub fn into_inner(self) -> Option<R> {
+        self.trailer.is_some().then_some(self.inner)
+    }
+}
+
+impl<R: Read> Read for ResponseReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.trailer.is_some() {
+                return Ok(0);
+            }
+            self.next_chunk().map_err(|e| match e {
+                IpcError::Io(e) => e,
+                IpcError::Truncated => std::io::Error::new(std::io::ErrorKind::UnexpectedEof, e),
+                e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            })?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Blocking client of the protocol, over a connected stream (e.g. a `UnixStream`)
+#[derive(Debug)]
+pub struct Client<S: Read + Write> {
+    /// Connected stream
+    stream: S,
+}
+
+impl<S: Read + Write> Client<S> {
+    /// Create a client over a connected stream
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// Send a request, and return its streamed response
+    ///
+    /// The response must be read up to its trailer (see [ResponseReader::finish]) before sending another request.
+    pub fn request(&mut self, request: &Request) -> Result<ResponseReader<&mut S>, IpcError> {
+        Frame::write_parts(&mut self.stream, FrameKind::Request, &request.encode())?;
+        self.stream.flush()?;
+        Ok(ResponseReader::new(&mut self.stream))
+    }
+
+    /// Get the data of a block
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The block data
+    /// * `Err(IpcError::Failed)` - The server answered with a non-Ok status
+    /// * `Err(IpcError)` - The exchange failed
+    pub fn get_block(&mut self, cid: &RawCid) -> Result<Vec<u8>, IpcError> {
+        let mut response = self.request(&Request::GetBlock(cid.clone()))?;
+        let mut data = Vec::new();
+        response.read_to_end(&mut data)?;
+        response.finish()?.into_result()?;
+        Ok(data)
+    }
+
+    /// Export every servable block as a CARv1 stream, written to the sink
+    ///
+    /// The sink receives the stream as it arrives: its content is only complete if `Ok` is returned.
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Number of bytes of the CAR stream
+    /// * `Err(IpcError)` - The server failed to complete the export, or the exchange failed
+    pub fn export_car<W: Write>(&mut self, sink: &mut W) -> Result<u64, IpcError> {
+        let mut response = self.request(&Request::ExportCar)?;
+        let copied = std::io::copy(&mut response, sink)?;
+        response.finish()?.into_result()?;
+        Ok(copied)
+    }
+
+    /// Get the plain text report of the store
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The report
+    /// * `Err(IpcError)` - The server failed to produce the report, or the exchange failed
+    pub fn report(&mut self) -> Result<String, IpcError> {
+        let mut response = self.request(&Request::Report)?;
+        let mut report = String::new();
+        response.read_to_string(&mut report)?;
+        response.finish()?.into_result()?;
+        Ok(report)
+    }
+
+    /// Return the underlying stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+
+    /// In-memory duplex stream: reads from a scripted input, records the output
+    struct Duplex {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_response_chunking() {
+        let mut writer = ResponseWriter::with_chunk_size(Vec::new(), 4);
+        writer.write_all(b"ab").unwrap();
+        writer.write_all(b"cdefghij").unwrap();
+        let wire = writer.finish(&Trailer::ok()).unwrap();
+        // Frames: "abcd", "efgh", "ij", trailer
+        assert_eq!(wire.len(), 3 * 5 + 10 + 5 + 1);
+
+        let mut reader = ResponseReader::new(wire.as_slice());
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload).unwrap();
+        assert_eq!(payload, b"abcdefghij");
+        assert_eq!(reader.trailer(), Some(&Trailer::ok()));
+        assert!(reader.into_inner().unwrap().is_empty());
+
+        // Truncated response: no trailer
+        let mut reader = ResponseReader::new(&wire[..wire.len() - 6]);
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_client() {
+        let mut server = Vec::new();
+        let mut writer = ResponseWriter::new(&mut server);
+        writer.write_all(b"aaaa").unwrap();
+        writer.finish(&Trailer::ok()).unwrap();
+        ResponseWriter::new(&mut server)
+            .finish(&Trailer::new(Status::NotFound, "unknown block"))
+            .unwrap();
+
+        let mut client = Client::new(Duplex {
+            input: std::io::Cursor::new(server),
+            output: Vec::new(),
+        });
+        let cid = RawCid::from_hex("0155000461616161").unwrap();
+        assert_eq!(client.get_block(&cid).unwrap(), b"aaaa");
+        assert!(matches!(
+            client.get_block(&cid),
+            Err(IpcError::Failed {
+                status: Status::NotFound,
+                ..
+            })
+        ));
+
+        // The server received both requests
+        let requests = client.into_inner().output;
+        let mut reader = requests.as_slice();
+        assert_eq!(
+            read_request(&mut reader).unwrap(),
+            Some(Request::GetBlock(cid.clone()))
+        );
+        assert!(read_request(&mut reader).unwrap().is_some());
+        assert_eq!(read_request(&mut reader).unwrap(), None);
+    }
+}